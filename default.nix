@@ -88,6 +88,7 @@ let commonBuildInputs = pkgs:
     pkgs.ocamlPackages.bisect_ppx
     pkgs.ocamlPackages.obelisk
     pkgs.ocamlPackages.uucp
+    pkgs.ocamlPackages.uunf
     pkgs.perl
     pkgs.removeReferencesTo
   ]; in
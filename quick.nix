@@ -90,6 +90,7 @@ let
     ocamlPackages.bisect_ppx
     obelisk
     ocamlPackages.uucp
+    ocamlPackages.uunf
     pkgs.perl
     pkgs.removeReferencesTo
   ];
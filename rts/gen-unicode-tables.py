@@ -0,0 +1,96 @@
+#!/usr/bin/env python3
+"""
+Generates the Unicode normalization tables of the RTS (motoko-rts/src/unicode/tables.rs) from
+Python's `unicodedata`, so the tables follow the Unicode version of the Python used:
+
+    python3 gen-unicode-tables.py > motoko-rts/src/unicode/tables.rs
+
+Hangul syllables are decomposed and composed algorithmically, and are not in the tables.
+"""
+
+import sys
+import unicodedata
+
+MAX_CHAR = 0x110000
+HANGUL_FIRST = 0xAC00
+HANGUL_LAST = 0xD7A3
+
+
+def is_hangul(c):
+    return HANGUL_FIRST <= c <= HANGUL_LAST
+
+
+def combining_class_runs():
+    runs = []
+    last = None
+    for c in range(MAX_CHAR):
+        ccc = unicodedata.combining(chr(c))
+        if ccc != last:
+            runs.append((c, ccc))
+            last = ccc
+    return runs
+
+
+def decompositions():
+    decomps = []
+    for c in range(MAX_CHAR):
+        if is_hangul(c):
+            continue
+        d = unicodedata.decomposition(chr(c))
+        if d == "" or d.startswith("<"):
+            continue
+        parts = [int(p, 16) for p in d.split()]
+        assert 1 <= len(parts) <= 2
+        decomps.append((c, parts[0], parts[1] if len(parts) == 2 else 0))
+    return decomps
+
+
+def compositions(decomps):
+    # A canonical decomposition into two characters is a primary composite unless excluded from
+    # composition, in which case NFC does not map it to itself
+    comps = [(d1, d2, c) for (c, d1, d2) in decomps if d2 != 0 and unicodedata.normalize("NFC", chr(c)) == chr(c)]
+    keys = set((d1, d2) for (d1, d2, _) in comps)
+    assert len(keys) == len(comps)
+    return sorted(comps)
+
+
+def pack(a, b, c):
+    return (a << 42) | (b << 21) | c
+
+
+def print_table(out, name, ty, values, per_line, fmt):
+    out.write("pub(crate) static %s: [%s; %d] = [\n" % (name, ty, len(values)))
+    for i in range(0, len(values), per_line):
+        out.write("    " + " ".join(fmt % v + "," for v in values[i : i + per_line]) + "\n")
+    out.write("];\n")
+
+
+def main():
+    out = sys.stdout
+    runs = combining_class_runs()
+    decomps = decompositions()
+    comps = compositions(decomps)
+
+    out.write("//! Unicode %s normalization tables, generated by `rts/gen-unicode-tables.py`. Do not edit.\n" % unicodedata.unidata_version)
+    out.write("\n")
+    out.write("/// Unicode version of the tables\n")
+    out.write('pub const UNICODE_VERSION: (u8, u8, u8) = (%s);\n' % ", ".join(unicodedata.unidata_version.split(".")))
+    out.write("\n")
+    out.write("/// Canonical combining classes: runs of characters with the same class, as\n")
+    out.write("/// `first_char << 8 | class`, sorted\n")
+    out.write("#[rustfmt::skip]\n")
+    print_table(out, "COMBINING_CLASSES", "u32", [(c << 8) | ccc for (c, ccc) in runs], 8, "0x%08x")
+    out.write("\n")
+    out.write("/// Canonical decompositions into one or two characters, as `char << 42 | first << 21 | second`,\n")
+    out.write("/// with `second` 0 for singleton decompositions, sorted\n")
+    out.write("#[rustfmt::skip]\n")
+    print_table(out, "DECOMPOSITIONS", "u64", [pack(c, d1, d2) for (c, d1, d2) in decomps], 4, "0x%016x")
+    out.write("\n")
+    out.write("/// Canonical compositions of primary composites, as `first << 42 | second << 21 | char`,\n")
+    out.write("/// sorted\n")
+    out.write("#[rustfmt::skip]\n")
+    print_table(out, "COMPOSITIONS", "u64", [pack(d1, d2, c) for (d1, d2, c) in comps], 4, "0x%016x")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,78 @@
+use crate::memory::TestMemory;
+
+use motoko_rts::alloc_profile::{
+    alloc_blob_at, alloc_site, alloc_words_at, reset_alloc_sites, AllocSite, MAX_ALLOC_SITES,
+};
+use motoko_rts::types::{size_of, Blob, Bytes, Words};
+
+pub unsafe fn test() {
+    println!("Testing allocation-site profiling ...");
+
+    test_per_site_totals();
+    test_site_overflow();
+}
+
+unsafe fn test_per_site_totals() {
+    println!("  Testing per-site totals");
+
+    reset_alloc_sites();
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    const RECORD_SITE: u32 = 3;
+    const BLOB_SITE: u32 = 7;
+
+    // Tiny records at one site, large blobs at another
+    for _ in 0..100 {
+        alloc_words_at(&mut mem, Words(3), RECORD_SITE);
+    }
+
+    for _ in 0..5 {
+        alloc_blob_at(&mut mem, Bytes(10_000), BLOB_SITE);
+    }
+
+    let blob_size = (size_of::<Blob>() + Bytes(10_000u32).to_words()).to_bytes();
+
+    assert_eq!(
+        alloc_site(RECORD_SITE),
+        AllocSite {
+            count: 100,
+            bytes: Bytes(100 * 12),
+            max_size: Bytes(12),
+        }
+    );
+
+    assert_eq!(
+        alloc_site(BLOB_SITE),
+        AllocSite {
+            count: 5,
+            bytes: Bytes(5 * u64::from(blob_size.as_u32())),
+            max_size: blob_size,
+        }
+    );
+
+    // Other sites did not allocate
+    for site in 0..MAX_ALLOC_SITES {
+        if site != RECORD_SITE && site != BLOB_SITE {
+            assert_eq!(alloc_site(site).count, 0);
+        }
+    }
+}
+
+unsafe fn test_site_overflow() {
+    println!("  Testing site ids out of range");
+
+    reset_alloc_sites();
+
+    let mut mem = TestMemory::new(Words(1024));
+
+    alloc_words_at(&mut mem, Words(2), MAX_ALLOC_SITES - 1);
+    alloc_words_at(&mut mem, Words(4), MAX_ALLOC_SITES);
+    alloc_words_at(&mut mem, Words(1), u32::MAX);
+
+    let last = alloc_site(MAX_ALLOC_SITES - 1);
+    assert_eq!(last.count, 3);
+    assert_eq!(last.bytes, Bytes(28));
+    assert_eq!(last.max_size, Bytes(16));
+    assert_eq!(alloc_site(u32::MAX), last);
+}
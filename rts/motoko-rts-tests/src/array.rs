@@ -0,0 +1,633 @@
+use crate::memory::TestMemory;
+use crate::trap::traps_with;
+
+use motoko_rts::array::{
+    array_binary_search_internal, array_dedup_internal, array_filter_internal, array_freeze,
+    array_get, array_is_frozen, array_map_internal, array_set, array_sort_internal,
+    array_tabulate_internal, TABULATE_SENTINEL,
+};
+use motoko_rts::gc::barrier::{BarrierMode, BARRIER_MODE, TEST_GENERATION_BOUNDS};
+use motoko_rts::gc::mark_compact::generational::REMEMBERED_SET;
+use motoko_rts::gc::mark_compact::{compacting_gc_internal, GcContext};
+use motoko_rts::memory::{alloc_array, alloc_blob, Memory};
+use motoko_rts::types::{Bytes, MutBox, Value, Words, TAG_FROZEN_ARRAY, TAG_MUTBOX};
+
+use std::cmp::Ordering;
+
+const ARRAY_INDEX_ERROR: &str = "Array index out of bounds";
+
+pub unsafe fn test() {
+    println!("Testing array element access ...");
+
+    let mut mem = TestMemory::new(Words(1024));
+
+    let len = 10;
+    let arr = alloc_array(&mut mem, len);
+    for i in 0..len {
+        array_set(arr, i, Value::from_scalar(i * 2));
+    }
+
+    println!("  Testing in-range indices");
+    for i in 0..len {
+        assert_eq!(array_get(arr, i).get_scalar(), i * 2);
+    }
+
+    println!("  Testing boundary indices");
+    array_set(arr, 0, Value::from_scalar(100));
+    array_set(arr, len - 1, Value::from_scalar(200));
+    assert_eq!(array_get(arr, 0).get_scalar(), 100);
+    assert_eq!(array_get(arr, len - 1).get_scalar(), 200);
+    assert!(traps_with(ARRAY_INDEX_ERROR, || {
+        array_get(arr, len);
+    }));
+    assert!(traps_with(ARRAY_INDEX_ERROR, || array_set(
+        arr,
+        len,
+        Value::from_scalar(0)
+    )));
+
+    let empty = alloc_array(&mut mem, 0);
+    assert!(traps_with(ARRAY_INDEX_ERROR, || {
+        array_get(empty, 0);
+    }));
+    assert!(traps_with(ARRAY_INDEX_ERROR, || array_set(
+        empty,
+        0,
+        Value::from_scalar(0)
+    )));
+
+    println!("  Testing out-of-range indices");
+    for idx in [len + 1, 1000, u32::MAX] {
+        assert!(traps_with(ARRAY_INDEX_ERROR, || {
+            array_get(arr, idx);
+        }));
+        assert!(traps_with(ARRAY_INDEX_ERROR, || array_set(
+            arr,
+            idx,
+            Value::from_scalar(0)
+        )));
+    }
+
+    // Failed accesses do not modify the array
+    assert_eq!(array_get(arr, 0).get_scalar(), 100);
+    for i in 1..len - 1 {
+        assert_eq!(array_get(arr, i).get_scalar(), i * 2);
+    }
+    assert_eq!(array_get(arr, len - 1).get_scalar(), 200);
+
+    test_array_sort();
+    test_array_sort_write_barrier();
+    test_array_map_filter();
+    test_array_map_filter_gc();
+    test_array_tabulate_gc();
+    test_array_binary_search();
+    test_array_dedup();
+    test_array_search_dedup_gc();
+    test_frozen_arrays();
+}
+
+unsafe fn test_array_sort() {
+    println!("  Testing array_sort");
+
+    let mut rand = oorandom::Rand32::new(0);
+
+    for len in [0, 1, 2, 3, 16, 17, 100, 1000, 10_000] {
+        let sorted: Vec<u32> = (0..len).collect();
+        let reversed: Vec<u32> = (0..len).rev().collect();
+        let random: Vec<u32> = (0..len).map(|_| rand.rand_range(0..1_000_000)).collect();
+        // Few distinct keys, makes quicksort fall back to heapsort
+        let duplicates: Vec<u32> = (0..len).map(|_| rand.rand_range(0..3)).collect();
+
+        for values in [sorted, reversed, random, duplicates] {
+            check_sort(&values);
+        }
+    }
+
+    // Comparison function that allocates
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+    let values: Vec<u32> = (0..1000).map(|_| rand.rand_range(0..1000)).collect();
+    let arr = array_of_scalars(&mut mem, &values);
+    let mut n_comparisons = 0;
+    array_sort_internal(&mut mem, arr, &mut |mem, a, b| {
+        alloc_blob(mem, Bytes(8));
+        n_comparisons += 1;
+        a.get_scalar().cmp(&b.get_scalar())
+    });
+    check_sorted(arr, &values);
+    assert!(n_comparisons > 0);
+}
+
+/// Sorting an old array moves pointers to the young generation to other fields, which are
+/// remembered, so that a minor GC does not miss them
+unsafe fn test_array_sort_write_barrier() {
+    println!("  Testing array_sort write barrier");
+
+    const LEN: u32 = 20;
+
+    let mut mem = TestMemory::new(Words(64 * 1024));
+    let heap_base = mem.heap_ptr() as u32;
+    let arr = alloc_array(&mut mem, LEN);
+    let young_base = mem.heap_ptr() as u32;
+
+    // Boxed values in reverse order, so sorting moves all of them
+    for i in 0..LEN {
+        let blob = alloc_blob(&mut mem, Bytes(4));
+        blob.as_blob_mut().payload_addr().write(i as u8);
+        arr.as_array().set(LEN - 1 - i, blob);
+    }
+
+    REMEMBERED_SET = None;
+    BARRIER_MODE = BarrierMode::RememberedSet;
+    TEST_GENERATION_BOUNDS = (heap_base, young_base);
+
+    array_sort_internal(&mut mem, arr, &mut |_, a, b| {
+        a.as_blob().get(0).cmp(&b.as_blob().get(0))
+    });
+    for i in 0..LEN {
+        assert_eq!(arr.as_array().get(i).as_blob().get(0), i as u8);
+        assert!(REMEMBERED_SET
+            .as_ref()
+            .unwrap()
+            .contains(arr.as_array().payload_addr().add(i as usize)));
+    }
+
+    REMEMBERED_SET = None;
+    BARRIER_MODE = BarrierMode::Disabled;
+    TEST_GENERATION_BOUNDS = (0, 0);
+}
+
+unsafe fn test_frozen_arrays() {
+    println!("  Testing frozen arrays");
+
+    const FROZEN_ERROR: &str = "Mutation of a frozen array";
+
+    let mut mem = TestMemory::new(Words(1024));
+    let values = [3, 1, 2];
+    let arr = array_of_scalars(&mut mem, &values);
+    assert!(!array_is_frozen(arr));
+
+    assert_eq!(array_freeze(arr).get_raw(), arr.get_raw());
+    assert_eq!(arr.tag(), TAG_FROZEN_ARRAY);
+    assert!(array_is_frozen(arr));
+
+    // Freezing again does nothing
+    array_freeze(arr);
+    assert!(array_is_frozen(arr));
+
+    // Reads succeed
+    assert_eq!(scalars(arr), values);
+    for (i, value) in values.iter().enumerate() {
+        assert_eq!(array_get(arr, i as u32).get_scalar(), *value);
+    }
+    assert!(traps_with(ARRAY_INDEX_ERROR, || {
+        array_get(arr, 3);
+    }));
+
+    // Writes trap, and leave the array unchanged
+    assert!(traps_with(FROZEN_ERROR, || array_set(
+        arr,
+        0,
+        Value::from_scalar(0)
+    )));
+    assert!(traps_with(FROZEN_ERROR, || array_sort_internal(
+        &mut mem,
+        arr,
+        &mut |_, a, b| a.get_scalar().cmp(&b.get_scalar())
+    )));
+    assert_eq!(scalars(arr), values);
+
+    // Mapping and filtering read the frozen array and create normal arrays
+    let mut arr = arr;
+    let mut result = Value::from_scalar(0);
+    array_map_internal(&mut mem, &mut arr, &mut result, &mut |_, x| x);
+    assert!(!array_is_frozen(result));
+    assert_eq!(scalars(result), values);
+}
+
+unsafe fn test_array_map_filter() {
+    println!("  Testing array_map and array_filter");
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    for len in [0, 1, 7, 8, 9, 100] {
+        let values: Vec<u32> = (0..len).collect();
+        let mut arr = array_of_scalars(&mut mem, &values);
+        let mut result = Value::from_scalar(0);
+
+        array_map_internal(&mut mem, &mut arr, &mut result, &mut |_, x| {
+            Value::from_scalar(x.get_scalar() * 3)
+        });
+        let expected: Vec<u32> = values.iter().map(|x| x * 3).collect();
+        assert_eq!(scalars(result), expected);
+
+        // Called once per element, in order
+        let mut seen = vec![];
+        array_filter_internal(&mut mem, &mut arr, &mut result, &mut |_, x| {
+            seen.push(x.get_scalar());
+            x.get_scalar() % 3 == 1
+        });
+        assert_eq!(seen, values);
+        let expected: Vec<u32> = values.iter().copied().filter(|x| x % 3 == 1).collect();
+        assert_eq!(scalars(result), expected);
+
+        array_filter_internal(&mut mem, &mut arr, &mut result, &mut |_, _| true);
+        assert_eq!(scalars(result), values);
+        array_filter_internal(&mut mem, &mut arr, &mut result, &mut |_, _| false);
+        assert_eq!(result.as_array().len(), 0);
+    }
+
+    // Callbacks that allocate
+    let values: Vec<u32> = (0..100).collect();
+    let mut arr = array_of_scalars(&mut mem, &values);
+    let mut result = Value::from_scalar(0);
+    array_map_internal(&mut mem, &mut arr, &mut result, &mut |mem, x| {
+        boxed(mem, x.get_scalar() + 1)
+    });
+    let mapped: Vec<u32> = (0..100)
+        .map(|i| unboxed(result.as_array().get(i)))
+        .collect();
+    let expected: Vec<u32> = values.iter().map(|x| x + 1).collect();
+    assert_eq!(mapped, expected);
+
+    array_filter_internal(&mut mem, &mut arr, &mut result, &mut |mem, x| {
+        alloc_blob(mem, Bytes(100));
+        x.get_scalar() % 2 == 0
+    });
+    let expected: Vec<u32> = values.iter().copied().filter(|x| x % 2 == 0).collect();
+    assert_eq!(scalars(result), expected);
+}
+
+/// Callbacks that run a compacting GC, which moves the input and result arrays
+unsafe fn test_array_map_filter_gc() {
+    println!("  Testing array_map and array_filter with GC in callbacks");
+
+    const LEN: u32 = 200;
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    // Locations of the input and result arrays are fields of mutable boxes in the static roots,
+    // below the heap base, so the GC updates them
+    let (roots, heap_base) = static_roots(&mut mem, 2);
+    let arr_loc = root_field(roots, 0);
+    let result_loc = root_field(roots, 1);
+
+    // Boxed elements, with garbage between them so that GC moves them
+    *arr_loc = alloc_array(&mut mem, LEN);
+    for i in 0..LEN {
+        alloc_blob(&mut mem, Bytes(64));
+        let elem = boxed(&mut mem, i);
+        (*arr_loc).as_array().set(i, elem);
+    }
+
+    let mut n_gcs = 0;
+    let mut gc = |mem: &mut TestMemory| {
+        alloc_blob(mem, Bytes(1024));
+        collect(mem, heap_base, roots);
+        n_gcs += 1;
+    };
+
+    array_map_internal(&mut mem, arr_loc, result_loc, &mut |mem, x| {
+        // `x` is not valid after the GC
+        let x = unboxed(x);
+        gc(mem);
+        boxed(mem, x * 2)
+    });
+    let mapped: Vec<u32> = (0..LEN)
+        .map(|i| unboxed((*result_loc).as_array().get(i)))
+        .collect();
+    let expected: Vec<u32> = (0..LEN).map(|i| i * 2).collect();
+    assert_eq!(mapped, expected);
+
+    array_filter_internal(&mut mem, arr_loc, result_loc, &mut |mem, x| {
+        let x = unboxed(x);
+        gc(mem);
+        x % 3 == 0
+    });
+    let filtered: Vec<u32> = (0..(*result_loc).as_array().len())
+        .map(|i| unboxed((*result_loc).as_array().get(i)))
+        .collect();
+    let expected: Vec<u32> = (0..LEN).filter(|i| i % 3 == 0).collect();
+    assert_eq!(filtered, expected);
+
+    assert_eq!(n_gcs, 2 * LEN);
+}
+
+/// A callback that runs a compacting GC at index `K`, which moves the result array and the elements
+/// written before
+unsafe fn test_array_tabulate_gc() {
+    println!("  Testing array_tabulate with GC in callbacks");
+
+    const LEN: u32 = 100;
+    const K: u32 = 40;
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    // Location of the result array is a field of a mutable box in the static roots, below the
+    // heap base, so the GC updates it
+    let (roots, heap_base) = static_roots(&mut mem, 1);
+    let result_loc = root_field(roots, 0);
+
+    // Garbage before the result array, so that GC moves it
+    alloc_blob(&mut mem, Bytes(256));
+
+    let mut n_calls = 0;
+    array_tabulate_internal(&mut mem, LEN, result_loc, &mut |mem, i| {
+        let i = i.get_scalar();
+        assert_eq!(i, n_calls);
+        n_calls += 1;
+
+        if i == K {
+            let result_before = *result_loc;
+            collect(mem, heap_base, roots);
+            assert!(*result_loc != result_before);
+        }
+
+        // Slots before `i` are written, the others hold the sentinel
+        let result = (*result_loc).as_array();
+        for j in 0..LEN {
+            if j < i {
+                assert_eq!(unboxed(result.get(j)), j * 3);
+            } else {
+                assert!(result.get(j) == TABULATE_SENTINEL);
+            }
+        }
+
+        // Garbage between the elements, so that GC moves them
+        alloc_blob(mem, Bytes(64));
+        boxed(mem, i * 3)
+    });
+
+    assert_eq!(n_calls, LEN);
+    let result: Vec<u32> = (0..LEN)
+        .map(|i| unboxed((*result_loc).as_array().get(i)))
+        .collect();
+    let expected: Vec<u32> = (0..LEN).map(|i| i * 3).collect();
+    assert_eq!(result, expected);
+
+    // Empty arrays don't call the callback
+    array_tabulate_internal(&mut mem, 0, result_loc, &mut |_, _| unreachable!());
+    assert_eq!((*result_loc).as_array().len(), 0);
+}
+
+unsafe fn test_array_binary_search() {
+    println!("  Testing array_binary_search");
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+    let cmp = &mut |_: &mut TestMemory, a: Value, b: Value| a.get_scalar().cmp(&b.get_scalar());
+
+    // Empty array: every key would be inserted at 0
+    let mut arr = array_of_scalars(&mut mem, &[]);
+    let mut key = Value::from_scalar(5);
+    assert_eq!(
+        array_binary_search_internal(&mut mem, &mut arr, &mut key, cmp),
+        !0
+    );
+
+    // Even numbers, odd keys are absent
+    for len in [1, 2, 7, 8, 100] {
+        let values: Vec<u32> = (0..len).map(|i| i * 2).collect();
+        let mut arr = array_of_scalars(&mut mem, &values);
+        for k in 0..2 * len + 1 {
+            let mut key = Value::from_scalar(k);
+            let found = array_binary_search_internal(&mut mem, &mut arr, &mut key, cmp);
+            let expected = match values.binary_search(&k) {
+                Ok(idx) => idx as i32,
+                Err(idx) => !(idx as i32),
+            };
+            assert_eq!(found, expected, "len {} key {}", len, k);
+            if k % 2 == 1 {
+                assert!(found < 0);
+            }
+        }
+    }
+
+    // With equal elements, one of them is found
+    let values = [1, 3, 3, 3, 3, 5];
+    let mut arr = array_of_scalars(&mut mem, &values);
+    let mut key = Value::from_scalar(3);
+    let found = array_binary_search_internal(&mut mem, &mut arr, &mut key, cmp);
+    assert_eq!(values[found as usize], 3);
+    let mut key = Value::from_scalar(4);
+    assert_eq!(
+        array_binary_search_internal(&mut mem, &mut arr, &mut key, cmp),
+        !5
+    );
+
+    // Comparisons are logarithmic
+    let values: Vec<u32> = (0..1000).collect();
+    let mut arr = array_of_scalars(&mut mem, &values);
+    let mut key = Value::from_scalar(1000);
+    let mut n_calls = 0;
+    let found = array_binary_search_internal(&mut mem, &mut arr, &mut key, &mut |_, a, b| {
+        n_calls += 1;
+        a.get_scalar().cmp(&b.get_scalar())
+    });
+    assert_eq!(found, !1000);
+    assert!(n_calls <= 10);
+}
+
+unsafe fn test_array_dedup() {
+    println!("  Testing array_dedup");
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    for (values, expected) in [
+        (vec![], vec![]),
+        (vec![1], vec![1]),
+        (vec![1, 1, 1, 1], vec![1]),
+        (vec![1, 2, 3], vec![1, 2, 3]),
+        (vec![1, 1, 2, 3, 3, 3, 4, 1, 1], vec![1, 2, 3, 4, 1]),
+    ] {
+        let mut arr = array_of_scalars(&mut mem, &values);
+        let mut result = Value::from_scalar(0);
+
+        // Called once per pair of adjacent elements, in order
+        let mut seen = vec![];
+        array_dedup_internal(&mut mem, &mut arr, &mut result, &mut |_, a, b| {
+            seen.push((a.get_scalar(), b.get_scalar()));
+            a.get_scalar() == b.get_scalar()
+        });
+        assert_eq!(scalars(result), expected);
+        let pairs: Vec<(u32, u32)> = values.windows(2).map(|w| (w[0], w[1])).collect();
+        assert_eq!(seen, pairs);
+    }
+
+    // Runs of elements equal by key, the first element of a run is kept
+    let values: Vec<u32> = (0..100).collect();
+    let mut arr = array_of_scalars(&mut mem, &values);
+    let mut result = Value::from_scalar(0);
+    array_dedup_internal(&mut mem, &mut arr, &mut result, &mut |_, a, b| {
+        a.get_scalar() / 10 == b.get_scalar() / 10
+    });
+    let expected: Vec<u32> = (0..10).map(|i| i * 10).collect();
+    assert_eq!(scalars(result), expected);
+}
+
+/// Callbacks that run a compacting GC, which moves the array, the key and the result
+unsafe fn test_array_search_dedup_gc() {
+    println!("  Testing array_binary_search and array_dedup with GC in callbacks");
+
+    const LEN: u32 = 200;
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    let (roots, heap_base) = static_roots(&mut mem, 3);
+    let arr_loc = root_field(roots, 0);
+    let key_loc = root_field(roots, 1);
+    let result_loc = root_field(roots, 2);
+
+    // Boxed elements `0, 0, 1, 1, ...`, with garbage between them so that GC moves them
+    *arr_loc = alloc_array(&mut mem, LEN);
+    for i in 0..LEN {
+        alloc_blob(&mut mem, Bytes(64));
+        let elem = boxed(&mut mem, i / 2);
+        (*arr_loc).as_array().set(i, elem);
+    }
+
+    let mut n_gcs = 0;
+    let mut gc = |mem: &mut TestMemory| {
+        alloc_blob(mem, Bytes(1024));
+        collect(mem, heap_base, roots);
+        n_gcs += 1;
+    };
+
+    for k in [0, 37, LEN / 2 - 1, LEN] {
+        alloc_blob(&mut mem, Bytes(64));
+        *key_loc = boxed(&mut mem, k);
+        let found = array_binary_search_internal(&mut mem, arr_loc, key_loc, &mut |mem, a, b| {
+            // `a` and `b` are not valid after the GC
+            let (a, b) = (unboxed(a), unboxed(b));
+            gc(mem);
+            a.cmp(&b)
+        });
+        if k < LEN / 2 {
+            assert_eq!(found / 2, k as i32);
+        } else {
+            assert_eq!(found, !(LEN as i32));
+        }
+    }
+
+    array_dedup_internal(&mut mem, arr_loc, result_loc, &mut |mem, a, b| {
+        let (a, b) = (unboxed(a), unboxed(b));
+        gc(mem);
+        a == b
+    });
+    let deduped: Vec<u32> = (0..(*result_loc).as_array().len())
+        .map(|i| unboxed((*result_loc).as_array().get(i)))
+        .collect();
+    let expected: Vec<u32> = (0..LEN / 2).collect();
+    assert_eq!(deduped, expected);
+
+    assert!(n_gcs > LEN);
+}
+
+/// Static roots array with `n` mutable boxes, followed by padding. Returns the array and the heap
+/// base after it.
+pub unsafe fn static_roots(mem: &mut TestMemory, n: u32) -> (Value, u32) {
+    let roots = alloc_array(mem, n);
+    for i in 0..n {
+        let mutbox = mem.alloc_words(motoko_rts::types::size_of::<MutBox>());
+        (*(mutbox.get_ptr() as *mut MutBox)).header.tag = TAG_MUTBOX;
+        (*(mutbox.get_ptr() as *mut MutBox)).field = Value::from_scalar(0);
+        roots.as_array().set(i, mutbox);
+    }
+
+    let misalignment = mem.heap_ptr() % 32;
+    if misalignment != 0 {
+        mem.alloc_words(Bytes((32 - misalignment) as u32).to_words());
+    }
+
+    (roots, mem.heap_ptr() as u32)
+}
+
+pub unsafe fn root_field(roots: Value, i: u32) -> *mut Value {
+    &mut (*(roots.as_array().get(i).get_ptr() as *mut MutBox)).field
+}
+
+/// Compacting GC of the heap starting at `heap_base`
+pub unsafe fn collect(mem: &mut TestMemory, heap_base: u32, roots: Value) {
+    // The heap pointer callbacks access the memory while the GC allocates in it
+    let mem_ptr: *mut TestMemory = mem;
+    let mut continuation_table = Value::from_scalar(0);
+    compacting_gc_internal(
+        mem,
+        GcContext {
+            heap_base,
+            get_hp: || (*mem_ptr).heap_ptr(),
+            set_hp: |hp| (*mem_ptr).set_heap_ptr(hp as usize),
+            static_roots: roots,
+            continuation_table_ptr_loc: &mut continuation_table,
+        },
+        // note_live_size
+        |_| {},
+        // note_reclaimed
+        |_| {},
+        // note_phase_time
+        |_, _| {},
+    );
+}
+
+unsafe fn boxed<M: Memory>(mem: &mut M, n: u32) -> Value {
+    let blob = alloc_blob(mem, Bytes(4));
+    for (i, byte) in n.to_le_bytes().iter().enumerate() {
+        blob.as_blob_mut().set(i as u32, *byte);
+    }
+    blob
+}
+
+unsafe fn unboxed(blob: Value) -> u32 {
+    let blob = blob.as_blob();
+    assert_eq!(blob.len(), Bytes(4));
+    u32::from_le_bytes([blob.get(0), blob.get(1), blob.get(2), blob.get(3)])
+}
+
+unsafe fn scalars(arr: Value) -> Vec<u32> {
+    let arr = arr.as_array();
+    (0..arr.len()).map(|i| arr.get(i).get_scalar()).collect()
+}
+
+/// Sort the values and compare with `slice::sort`
+unsafe fn check_sort(values: &[u32]) {
+    let mut mem = TestMemory::new(Words(2 * values.len() as u32 + 1024));
+    let arr = array_of_scalars(&mut mem, values);
+    array_sort_internal(&mut mem, arr, &mut |_, a, b| {
+        a.get_scalar().cmp(&b.get_scalar())
+    });
+    check_sorted(arr, values);
+
+    // Sort by key with descending order, check that elements are ordered by key and that the
+    // array is a permutation of the input
+    let arr = array_of_scalars(&mut mem, values);
+    let key_cmp =
+        |a: Value, b: Value| -> Ordering { (b.get_scalar() % 7).cmp(&(a.get_scalar() % 7)) };
+    array_sort_internal(&mut mem, arr, &mut |_, a, b| key_cmp(a, b));
+    let len = values.len() as u32;
+    for i in 1..len {
+        assert!(key_cmp(arr.as_array().get(i - 1), arr.as_array().get(i)) != Ordering::Greater);
+    }
+    let mut sorted_values: Vec<u32> = (0..len)
+        .map(|i| arr.as_array().get(i).get_scalar())
+        .collect();
+    sorted_values.sort();
+    let mut expected = values.to_vec();
+    expected.sort();
+    assert_eq!(sorted_values, expected);
+}
+
+unsafe fn check_sorted(arr: Value, values: &[u32]) {
+    let mut expected = values.to_vec();
+    expected.sort();
+    let arr = arr.as_array();
+    assert_eq!(arr.len(), expected.len() as u32);
+    for (i, value) in expected.iter().enumerate() {
+        assert_eq!(arr.get(i as u32).get_scalar(), *value);
+    }
+}
+
+unsafe fn array_of_scalars(mem: &mut TestMemory, values: &[u32]) -> Value {
+    let arr = alloc_array(mem, values.len() as u32);
+    for (i, value) in values.iter().enumerate() {
+        arr.as_array().set(i as u32, Value::from_scalar(*value));
+    }
+    arr
+}
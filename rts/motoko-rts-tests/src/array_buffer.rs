@@ -0,0 +1,167 @@
+use crate::memory::TestMemory;
+use crate::trap::traps_with;
+
+use motoko_rts::array_buffer::{
+    array_buffer_add, array_buffer_append, array_buffer_capacity, array_buffer_get,
+    array_buffer_len, array_buffer_new, array_buffer_to_array, INITIAL_ARRAY_BUFFER_CAPACITY,
+};
+use motoko_rts::gc::barrier::{BarrierMode, BARRIER_MODE, TEST_GENERATION_BOUNDS};
+use motoko_rts::gc::mark_compact::generational::REMEMBERED_SET;
+use motoko_rts::memory::alloc_array;
+use motoko_rts::types::{Value, Words};
+
+pub unsafe fn test() {
+    println!("Testing array buffers ...");
+
+    test_add();
+    test_append();
+    test_unused_slots();
+    test_bounds();
+    test_write_barrier();
+}
+
+/// Building a large array by repeated appends copies each element a bounded number of times
+unsafe fn test_add() {
+    println!("  Testing repeated appends");
+
+    const N: u32 = 100_000;
+
+    let mut mem = TestMemory::new(Words(2 * 1024 * 1024));
+    let buf = array_buffer_new(&mut mem);
+    assert_eq!(array_buffer_len(buf), 0);
+    assert_eq!(array_buffer_capacity(buf), INITIAL_ARRAY_BUFFER_CAPACITY);
+
+    let heap_start = mem.heap_ptr();
+    let mut capacity_changes = 0;
+    for i in 0..N {
+        let capacity = array_buffer_capacity(buf);
+        array_buffer_add(&mut mem, buf, Value::from_scalar(i));
+        if array_buffer_capacity(buf) != capacity {
+            capacity_changes += 1;
+        }
+        assert_eq!(array_buffer_len(buf), i + 1);
+    }
+
+    // Capacity doubles: 8 * 2^14 >= 100_000
+    assert_eq!(capacity_changes, 14);
+    assert_eq!(
+        array_buffer_capacity(buf),
+        INITIAL_ARRAY_BUFFER_CAPACITY << 14
+    );
+
+    // All storage arrays together are less than twice the final one, plus array headers
+    let allocated = Words(((mem.heap_ptr() - heap_start) / 4) as u32);
+    assert!(allocated.as_u32() < 2 * array_buffer_capacity(buf) + 14 * 2);
+
+    let arr = array_buffer_to_array(&mut mem, buf);
+    assert_eq!(arr.as_array().len(), N);
+    for i in 0..N {
+        assert_eq!(arr.as_array().get(i).get_scalar(), i);
+        assert_eq!(array_buffer_get(buf, i).get_scalar(), i);
+    }
+
+    // The array is a copy, appending to the buffer does not change it
+    array_buffer_add(&mut mem, buf, Value::from_scalar(N));
+    assert_eq!(arr.as_array().len(), N);
+    assert_eq!(array_buffer_len(buf), N + 1);
+}
+
+unsafe fn test_append() {
+    println!("  Testing appending arrays");
+
+    let mut mem = TestMemory::new(Words(64 * 1024));
+    let buf = array_buffer_new(&mut mem);
+
+    let mut expected = vec![];
+    for n in [0, 3, 20, 1, 100] {
+        let arr = alloc_array(&mut mem, n);
+        for i in 0..n {
+            let x = expected.len() as u32 + i;
+            arr.as_array().set(i, Value::from_scalar(x));
+        }
+        array_buffer_append(&mut mem, buf, arr);
+        expected.extend(expected.len() as u32..expected.len() as u32 + n);
+    }
+
+    let arr = array_buffer_to_array(&mut mem, buf);
+    let contents: Vec<u32> = (0..arr.as_array().len())
+        .map(|i| arr.as_array().get(i).get_scalar())
+        .collect();
+    assert_eq!(contents, expected);
+}
+
+/// Slots after the elements are scalars, so the GC does not trace them
+unsafe fn test_unused_slots() {
+    println!("  Testing unused slots");
+
+    let mut mem = TestMemory::new(Words(1024));
+    let buf = array_buffer_new(&mut mem);
+
+    for _ in 0..INITIAL_ARRAY_BUFFER_CAPACITY + 1 {
+        let elem = alloc_array(&mut mem, 0);
+        array_buffer_add(&mut mem, buf, elem);
+    }
+
+    // Buffer layout: `[len; storage]`
+    assert_eq!(buf.as_array().len(), 2);
+    let storage = buf.as_array().get(1).as_array();
+    assert_eq!(storage.len(), array_buffer_capacity(buf));
+    for i in 0..storage.len() {
+        assert_eq!(storage.get(i).is_ptr(), i < array_buffer_len(buf));
+    }
+}
+
+unsafe fn test_bounds() {
+    println!("  Testing bounds checks");
+
+    let mut mem = TestMemory::new(Words(1024));
+    let buf = array_buffer_new(&mut mem);
+    assert!(traps_with("Array buffer index out of bounds", || {
+        array_buffer_get(buf, 0);
+    }));
+
+    array_buffer_add(&mut mem, buf, Value::from_scalar(1));
+    assert_eq!(array_buffer_get(buf, 0).get_scalar(), 1);
+    for idx in [1, INITIAL_ARRAY_BUFFER_CAPACITY, u32::MAX] {
+        assert!(traps_with("Array buffer index out of bounds", || {
+            array_buffer_get(buf, idx);
+        }));
+    }
+}
+
+/// Elements added to an old buffer, and the storage array of an old buffer when it grows, are
+/// remembered, so that a minor GC does not miss them
+unsafe fn test_write_barrier() {
+    println!("  Testing write barrier");
+
+    let mut mem = TestMemory::new(Words(64 * 1024));
+    let heap_base = mem.heap_ptr() as u32;
+    let buf = array_buffer_new(&mut mem);
+    let young_base = mem.heap_ptr() as u32;
+
+    REMEMBERED_SET = None;
+    BARRIER_MODE = BarrierMode::RememberedSet;
+    TEST_GENERATION_BOUNDS = (heap_base, young_base);
+
+    // The element is stored in the old storage array
+    let elem = alloc_array(&mut mem, 0);
+    array_buffer_add(&mut mem, buf, elem);
+    let storage = buf.as_array().get(1).as_array();
+    assert!(REMEMBERED_SET
+        .as_ref()
+        .unwrap()
+        .contains(storage.payload_addr()));
+
+    // The new storage array is stored in the old buffer
+    for _ in 1..INITIAL_ARRAY_BUFFER_CAPACITY + 1 {
+        array_buffer_add(&mut mem, buf, Value::from_scalar(0));
+    }
+    assert!(REMEMBERED_SET
+        .as_ref()
+        .unwrap()
+        .contains(buf.as_array().payload_addr().add(1)));
+
+    REMEMBERED_SET = None;
+    BARRIER_MODE = BarrierMode::Disabled;
+    TEST_GENERATION_BOUNDS = (0, 0);
+}
@@ -1,9 +1,14 @@
 use crate::memory::TestMemory;
+use crate::trap::traps_with;
 
 use motoko_rts::bigint::{self, *};
 use motoko_rts::buf::Buf;
+use motoko_rts::cycles::Cycles128;
+use motoko_rts::text::text_of_str;
 use motoko_rts::types::{Bytes, Value, Words};
 
+use oorandom::Rand32;
+
 // mp functions below are implemented separately for tests as we can't modify mp_int source code to
 // pass a generic heap argument (then monomorphise it for IC).
 
@@ -62,10 +67,541 @@ pub unsafe fn test() {
         test_bigint_sleb128(bigint_neg(plus_one));
     }
 
+    test_cycles_nat();
+    test_text_to_num(&mut heap);
+    test_bit_ops();
+    test_nat_narrowing();
+
+    HEAP = std::ptr::null_mut();
+    drop(heap);
+
+    //
+    // Multiplication
+    //
+
+    // Large operands and intermediate results don't fit into the heap above
+    let mut heap = TestMemory::new(Words(16 * 1024 * 1024));
+    HEAP = &mut heap;
+
+    test_factorial_mul();
+    test_mul_against_schoolbook();
+
     HEAP = std::ptr::null_mut();
     drop(heap);
 }
 
+/// Conversions between 128-bit cycle amounts and `Nat`
+unsafe fn test_cycles_nat() {
+    let one = bigint_of_word32(1);
+    let two = bigint_of_word32(2);
+    let two_pow_64 = bigint_pow(two, bigint_of_word32(64));
+    let two_pow_128 = bigint_pow(two, bigint_of_word32(128));
+
+    // Carry into the high word
+    let carry = Cycles128::from_u64(u64::MAX) + Cycles128::from_u64(1);
+    assert!(bigint_eq(carry.to_nat(), two_pow_64));
+
+    let max = Cycles128::new(u64::MAX, u64::MAX);
+    assert!(bigint_eq(max.to_nat(), bigint_sub(two_pow_128, one)));
+    assert!(bigint_eq(
+        Cycles128::new(3, 5).to_nat(),
+        bigint_add(
+            bigint_mul(bigint_of_word32(3), two_pow_64),
+            bigint_of_word32(5)
+        )
+    ));
+
+    // Small amounts use the compact representation
+    assert_eq!(Cycles128::from_u64(42).to_nat().get_signed_scalar(), 42);
+
+    for cycles in [
+        Cycles128::ZERO,
+        Cycles128::from_u64(1),
+        Cycles128::from_u64((1 << 30) - 1),
+        Cycles128::from_u64(1 << 30),
+        Cycles128::from_u64(u64::MAX),
+        Cycles128::new(1, 0),
+        Cycles128::new(12345, 678),
+        max,
+    ] {
+        assert_eq!(Cycles128::of_nat(cycles.to_nat()), cycles);
+    }
+
+    assert!(traps_with("cycles out of bounds", || {
+        Cycles128::of_nat(two_pow_128);
+    }));
+    assert!(traps_with("cycles out of bounds", || {
+        Cycles128::of_nat(bigint_neg(two_pow_64));
+    }));
+}
+
+/// Parsing `Nat` and `Int` from text, see `text_to_nat`
+unsafe fn test_text_to_num(heap: &mut TestMemory) {
+    let parse_nat = |heap: &mut TestMemory, text: &str, radix: u32| {
+        let text = text_of_str(heap, text);
+        text_to_nat(heap, text, radix)
+    };
+    let parse_int = |heap: &mut TestMemory, text: &str, radix: u32| {
+        let text = text_of_str(heap, text);
+        text_to_int(heap, text, radix)
+    };
+
+    // Decimal, compact up to 2^30 - 1
+    assert_eq!(parse_nat(heap, "0", 10).get_signed_scalar(), 0);
+    assert_eq!(parse_nat(heap, "12345", 10).get_signed_scalar(), 12345);
+    assert_eq!(
+        parse_nat(heap, "1073741823", 10).get_signed_scalar(),
+        (1 << 30) - 1
+    );
+    assert!(bigint_eq(
+        parse_nat(heap, "1073741824", 10),
+        bigint_of_word32(1 << 30)
+    ));
+    for digits in [
+        "18446744073709551615",
+        "18446744073709551616",
+        "30414093201713378043612608166064768844377641568960512000000000000",
+    ] {
+        assert!(bigint_eq(
+            parse_nat(heap, digits, 10),
+            bigint_of_decimal(digits)
+        ));
+    }
+
+    // Other radices, digits in either case, no `0x` prefix
+    assert_eq!(parse_nat(heap, "ff", 16).get_signed_scalar(), 255);
+    assert_eq!(parse_nat(heap, "FF", 16).get_signed_scalar(), 255);
+    assert!(bigint_eq(
+        parse_nat(heap, "DEADbeef", 16),
+        bigint_of_word32(0xdeadbeef)
+    ));
+    assert!(bigint_eq(
+        parse_nat(heap, "100000000000000000000000000000000", 16),
+        bigint_pow(bigint_of_word32(2), bigint_of_word32(128))
+    ));
+    assert_eq!(parse_nat(heap, "1010", 2).get_signed_scalar(), 10);
+    assert_eq!(parse_nat(heap, "777", 8).get_signed_scalar(), 511);
+    assert_eq!(parse_nat(heap, "zz", 36).get_signed_scalar(), 1295);
+
+    // Separators
+    assert_eq!(
+        parse_nat(heap, "1_000_000", 10).get_signed_scalar(),
+        1_000_000
+    );
+    assert_eq!(parse_nat(heap, "ff_ff", 16).get_signed_scalar(), 0xffff);
+    assert!(bigint_eq(
+        parse_nat(heap, "18_446_744_073_709_551_616", 10),
+        bigint_of_decimal("18446744073709551616")
+    ));
+
+    // Invalid input
+    for (text, radix) in [
+        ("", 10),
+        ("_", 10),
+        ("_1", 10),
+        ("1_", 10),
+        ("1__0", 10),
+        ("12a", 10),
+        ("0x1f", 16),
+        ("g", 16),
+        ("2", 2),
+        (" 1", 10),
+        ("1.5", 10),
+        ("\u{e9}", 16),
+        ("1", 1),
+        ("1", 37),
+        ("18446744073709551616x", 10),
+    ] {
+        assert!(parse_nat(heap, text, radix) == INVALID_NUMBER);
+        assert!(parse_int(heap, text, radix) == INVALID_NUMBER);
+    }
+
+    // Signs, only in `Int`
+    assert!(parse_nat(heap, "-1", 10) == INVALID_NUMBER);
+    assert!(parse_nat(heap, "+1", 10) == INVALID_NUMBER);
+    assert_eq!(parse_int(heap, "-42", 10).get_signed_scalar(), -42);
+    assert_eq!(parse_int(heap, "+42", 10).get_signed_scalar(), 42);
+    assert_eq!(parse_int(heap, "-0", 10).get_signed_scalar(), 0);
+    assert_eq!(parse_int(heap, "-ff", 16).get_signed_scalar(), -255);
+    assert_eq!(
+        parse_int(heap, "-1073741824", 10).get_signed_scalar(),
+        -(1 << 30)
+    );
+    assert!(bigint_eq(
+        parse_int(heap, "-1073741825", 10),
+        bigint_neg(bigint_of_decimal("1073741825"))
+    ));
+    assert!(bigint_eq(
+        parse_int(heap, "-18_446_744_073_709_551_616", 10),
+        bigint_neg(bigint_of_decimal("18446744073709551616"))
+    ));
+    for text in ["-", "+", "--1", "+-1", "-_1", "1-"] {
+        assert!(parse_int(heap, text, 10) == INVALID_NUMBER);
+    }
+}
+
+/// Bit operations, against `i128` for small numbers and known results for large ones
+unsafe fn test_bit_ops() {
+    let mut values: Vec<i128> = vec![
+        0,
+        1,
+        2,
+        3,
+        5,
+        0xdead_beef,
+        (1 << 28) - 1,
+        1 << 28,
+        (1 << 30) - 1,
+        1 << 30,
+        (1 << 31) + 1,
+        u64::MAX as i128,
+        1 << 64,
+        (1 << 100) - 1,
+        1 << 100,
+        0x1234_5678_9abc_def0_1234_5678_9abc,
+    ];
+    values.extend(values.clone().iter().map(|n| -n));
+
+    for &a in &values {
+        for &b in &values {
+            check_int(nat_and(int(a), int(b)), a & b);
+            check_int(nat_or(int(a), int(b)), a | b);
+            check_int(nat_xor(int(a), int(b)), a ^ b);
+        }
+
+        for shift in [
+            0,
+            1,
+            2,
+            27,
+            28,
+            29,
+            31,
+            32,
+            60,
+            100,
+            127,
+            128,
+            1000,
+            u32::MAX,
+        ] {
+            let shr = if shift >= 128 { a >> 127 } else { a >> shift };
+            check_int(nat_shr(int(a), shift), shr);
+
+            // Only shifts with results in `i128`
+            let shl = if shift < 127 {
+                a.checked_mul(1 << shift)
+            } else {
+                None
+            };
+            if let Some(shl) = shl {
+                check_int(nat_shl(int(a), shift), shl);
+            }
+        }
+
+        // Bits that differ from the sign bit
+        let popcount = if a < 0 { !a } else { a }.count_ones();
+        assert_eq!(nat_popcount(int(a)), popcount, "popcount {}", a);
+    }
+
+    // Results fitting into the compact representation are compact
+    assert!(!nat_and(int(1 << 100), int(1 << 100)).is_scalar());
+    assert!(nat_and(int((1 << 100) + 5), int(7)).is_scalar());
+    assert!(nat_xor(int(1 << 100), int((1 << 100) + 1)).is_scalar());
+    assert!(nat_shr(int(1 << 100), 80).is_scalar());
+
+    // Large numbers
+    let two = bigint_of_word32(2);
+    let pow2 = |n: u32| bigint_pow(two, bigint_of_word32(n));
+    let one = bigint_of_word32(1);
+    let ones_1000 = bigint_sub(pow2(1000), one);
+    let minus_pow2_1000 = bigint_neg(pow2(1000));
+
+    check_eq(nat_and(pow2(1000), ones_1000), int(0));
+    check_eq(nat_or(pow2(1000), ones_1000), bigint_sub(pow2(1001), one));
+    check_eq(
+        nat_and(minus_pow2_1000, bigint_sub(pow2(1001), one)),
+        pow2(1000),
+    );
+    check_eq(nat_xor(ones_1000, int(-1)), minus_pow2_1000);
+    check_eq(nat_or(minus_pow2_1000, ones_1000), int(-1));
+    check_eq(nat_shl(one, 1000), pow2(1000));
+    check_eq(
+        nat_shl(int(-3), 1000),
+        bigint_mul(boxed(int(-3)), pow2(1000)),
+    );
+    check_eq(nat_shr(pow2(1000), 999), int(2));
+    check_eq(nat_shr(minus_pow2_1000, 1000), int(-1));
+    check_eq(nat_shr(bigint_sub(minus_pow2_1000, one), 1000), int(-2));
+    check_eq(nat_shr(ones_1000, 1000), int(0));
+    check_eq(nat_shl(int(0), u32::MAX), int(0));
+    assert_eq!(nat_popcount(ones_1000), 1000);
+    assert_eq!(nat_popcount(minus_pow2_1000), 1000);
+    assert_eq!(nat_popcount(pow2(1000)), 1);
+}
+
+/// Number in canonical form: compact when it fits into 31 bits, boxed otherwise
+unsafe fn int(n: i128) -> Value {
+    if (-(1 << 30)..(1 << 30)).contains(&n) {
+        return Value::from_signed_scalar(n as i32);
+    }
+    let chunks: Vec<u32> = (0..8)
+        .rev()
+        .map(|i| ((n.unsigned_abs() >> (16 * i)) & 0xFFFF) as u32)
+        .collect();
+    let magnitude = bigint_of_chunks(&chunks);
+    if n < 0 {
+        bigint_neg(magnitude)
+    } else {
+        magnitude
+    }
+}
+
+/// Boxed representation of a compact or boxed number, for `bigint_eq`
+unsafe fn boxed(n: Value) -> Value {
+    if n.is_scalar() {
+        let i = n.get_signed_scalar();
+        let magnitude = bigint_of_word32(i.unsigned_abs());
+        if i < 0 {
+            bigint_neg(magnitude)
+        } else {
+            magnitude
+        }
+    } else {
+        n
+    }
+}
+
+/// Check that the result is `expected`, in canonical form
+unsafe fn check_int(result: Value, expected: i128) {
+    check_eq(result, int(expected));
+}
+
+/// Checked and trapping conversions to `u32`, `u64` and `u128` at the boundaries of each width
+unsafe fn test_nat_narrowing() {
+    let one = bigint_of_word32(1);
+    let pow2 = |n: u32| bigint_pow(bigint_of_word32(2), bigint_of_word32(n));
+
+    // Compact and boxed small numbers
+    for (n, expected) in [
+        (int(0), 0),
+        (int(1), 1),
+        (int((1 << 30) - 1), (1 << 30) - 1),
+        (boxed(int(0)), 0),
+        (boxed(int(7)), 7),
+    ] {
+        assert_eq!(nat_to_u32_checked(n), Some(expected));
+        assert_eq!(nat_to_u64_checked(n), Some(u64::from(expected)));
+        assert_eq!(nat_to_u128_checked(n), Some(u128::from(expected)));
+        assert_eq!(nat_to_u32_trap(n), expected);
+        assert_eq!(nat_to_u64_trap(n), u64::from(expected));
+        assert_eq!(nat_to_u128_trap(n), u128::from(expected));
+    }
+
+    // `2^bits - 1` fits, `2^bits` does not
+    assert_eq!(
+        nat_to_u32_checked(bigint_sub(pow2(32), one)),
+        Some(u32::MAX)
+    );
+    assert_eq!(nat_to_u32_checked(pow2(32)), None);
+    assert_eq!(nat_to_u32_checked(pow2(31)), Some(1 << 31));
+    assert_eq!(nat_to_u32_checked(pow2(64)), None);
+
+    assert_eq!(
+        nat_to_u64_checked(bigint_sub(pow2(64), one)),
+        Some(u64::MAX)
+    );
+    assert_eq!(nat_to_u64_checked(pow2(64)), None);
+    assert_eq!(nat_to_u64_checked(pow2(32)), Some(1 << 32));
+    assert_eq!(nat_to_u64_checked(pow2(63)), Some(1 << 63));
+
+    assert_eq!(
+        nat_to_u128_checked(bigint_sub(pow2(128), one)),
+        Some(u128::MAX)
+    );
+    assert_eq!(nat_to_u128_checked(pow2(128)), None);
+    assert_eq!(nat_to_u128_checked(pow2(64)), Some(1 << 64));
+    assert_eq!(
+        nat_to_u128_checked(int(0x1234_5678_9abc_def0_1234_5678_9abc)),
+        Some(0x1234_5678_9abc_def0_1234_5678_9abc)
+    );
+    assert_eq!(nat_to_u128_checked(pow2(1000)), None);
+
+    // Negative numbers, compact and boxed
+    for n in [
+        int(-1),
+        boxed(int(-1)),
+        int(-(1 << 40)),
+        bigint_neg(pow2(128)),
+    ] {
+        assert_eq!(nat_to_u32_checked(n), None);
+        assert_eq!(nat_to_u64_checked(n), None);
+        assert_eq!(nat_to_u128_checked(n), None);
+        assert!(traps_with("nat_to_u32: negative number", || {
+            nat_to_u32_trap(n);
+        }));
+        assert!(traps_with("nat_to_u64: negative number", || {
+            nat_to_u64_trap(n);
+        }));
+        assert!(traps_with("nat_to_u128: negative number", || {
+            nat_to_u128_trap(n);
+        }));
+    }
+
+    assert_eq!(nat_to_u32_trap(bigint_sub(pow2(32), one)), u32::MAX);
+    assert!(traps_with(
+        "nat_to_u32: number does not fit in 32 bits",
+        || {
+            nat_to_u32_trap(pow2(32));
+        }
+    ));
+    assert_eq!(nat_to_u64_trap(bigint_sub(pow2(64), one)), u64::MAX);
+    assert!(traps_with(
+        "nat_to_u64: number does not fit in 64 bits",
+        || {
+            nat_to_u64_trap(pow2(64));
+        }
+    ));
+    assert_eq!(nat_to_u128_trap(bigint_sub(pow2(128), one)), u128::MAX);
+    assert!(traps_with(
+        "nat_to_u128: number does not fit in 128 bits",
+        || {
+            nat_to_u128_trap(pow2(128));
+        }
+    ));
+}
+
+/// Check that the result is `expected`, which is in canonical form
+unsafe fn check_eq(result: Value, expected: Value) {
+    if expected.is_scalar() {
+        assert!(result.is_scalar());
+        assert_eq!(result.get_signed_scalar(), expected.get_signed_scalar());
+    } else {
+        assert!(!result.is_scalar());
+        assert!(bigint_eq(result, expected));
+    }
+}
+
+/// Factorials computed by multiplying with small numbers (schoolbook multiplication) and with a
+/// product tree (Karatsuba multiplication for large operands, see `bigint_mul`) should agree
+unsafe fn test_factorial_mul() {
+    assert!(bigint_eq(
+        factorial(25),
+        bigint_of_decimal("15511210043330985984000000")
+    ));
+    assert!(bigint_eq(
+        factorial(50),
+        bigint_of_decimal("30414093201713378043612608166064768844377641568960512000000000000")
+    ));
+
+    for n in [25, 50, 100, 500, 1000] {
+        assert!(bigint_eq(factorial(n), product_tree(1, n)));
+    }
+
+    // 1000! has 8530 bits. Checks that the result has no leading zero digits, as the size is
+    // computed from the most significant digit.
+    assert_eq!(bigint_leb128_size(product_tree(1, 1000)), (8530 + 6) / 7);
+
+    // 6^5000 = 2^5000 * 3^5000
+    let exp = bigint_of_word32(5000);
+    assert!(bigint_eq(
+        bigint_pow(bigint_of_word32(6), exp),
+        bigint_mul(
+            bigint_pow(bigint_of_word32(2), exp),
+            bigint_pow(bigint_of_word32(3), exp)
+        )
+    ));
+}
+
+/// Compare products of random numbers, with sizes below and above the Karatsuba cutoff, with
+/// products computed by multiplying with one 16-bit chunk at a time (schoolbook multiplication)
+unsafe fn test_mul_against_schoolbook() {
+    let zero = bigint_of_word32(0);
+    let mut rng = Rand32::new(0);
+
+    for _ in 0..20 {
+        // Up to 300 chunks (4800 bits, 171 digits), Karatsuba cutoff is 80 digits
+        let a_chunks = random_chunks(&mut rng);
+        let b_chunks = random_chunks(&mut rng);
+
+        let a = bigint_of_chunks(&a_chunks);
+        let b = bigint_of_chunks(&b_chunks);
+        let expected = mul_by_chunks(a, &b_chunks);
+
+        assert!(bigint_eq(bigint_mul(a, b), expected));
+        assert!(bigint_eq(bigint_mul(b, a), expected));
+        assert!(bigint_eq(
+            bigint_mul(bigint_neg(a), b),
+            bigint_neg(expected)
+        ));
+        assert!(bigint_eq(
+            bigint_mul(bigint_neg(a), bigint_neg(b)),
+            expected
+        ));
+        assert_eq!(
+            bigint_leb128_size(bigint_mul(a, b)),
+            bigint_leb128_size(expected)
+        );
+
+        assert!(bigint_eq(bigint_mul(a, zero), zero));
+        assert_eq!(bigint_leb128_size(bigint_mul(a, zero)), 1);
+    }
+}
+
+unsafe fn factorial(n: u32) -> Value {
+    (1..=n).fold(bigint_of_word32(1), |acc, i| {
+        bigint_mul(acc, bigint_of_word32(i))
+    })
+}
+
+/// Product of `lo..=hi`, computed by multiplying the products of the two halves
+unsafe fn product_tree(lo: u32, hi: u32) -> Value {
+    if lo == hi {
+        bigint_of_word32(lo)
+    } else {
+        let mid = lo + (hi - lo) / 2;
+        bigint_mul(product_tree(lo, mid), product_tree(mid + 1, hi))
+    }
+}
+
+unsafe fn bigint_of_decimal(digits: &str) -> Value {
+    let ten = bigint_of_word32(10);
+    digits.chars().fold(bigint_of_word32(0), |acc, digit| {
+        bigint_add(
+            bigint_mul(acc, ten),
+            bigint_of_word32(digit.to_digit(10).unwrap()),
+        )
+    })
+}
+
+/// Random 16-bit chunks of a number, most significant first. The most significant chunk is not
+/// zero.
+fn random_chunks(rng: &mut Rand32) -> Vec<u32> {
+    let n_chunks = rng.rand_range(1..301);
+    let mut chunks: Vec<u32> = (0..n_chunks).map(|_| rng.rand_u32() & 0xFFFF).collect();
+    chunks[0] |= 1;
+    chunks
+}
+
+unsafe fn bigint_of_chunks(chunks: &[u32]) -> Value {
+    let base = bigint_of_word32(1 << 16);
+    chunks.iter().fold(bigint_of_word32(0), |acc, chunk| {
+        bigint_add(bigint_mul(acc, base), bigint_of_word32(*chunk))
+    })
+}
+
+/// `a` times the number with the given 16-bit chunks. Only multiplies with single digit numbers,
+/// which is done with schoolbook multiplication.
+unsafe fn mul_by_chunks(a: Value, chunks: &[u32]) -> Value {
+    let base = bigint_of_word32(1 << 16);
+    chunks.iter().fold(bigint_of_word32(0), |acc, chunk| {
+        bigint_add(
+            bigint_mul(acc, base),
+            bigint_mul(a, bigint_of_word32(*chunk)),
+        )
+    })
+}
+
 // Check leb128 encode/decode roundtrip
 unsafe fn test_bigint_leb128(n: Value) {
     let mut buf = [0u8; 100];
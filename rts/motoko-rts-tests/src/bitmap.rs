@@ -8,6 +8,9 @@ use motoko_rts::memory::Memory;
 use motoko_rts::types::{Bytes, Words};
 
 use std::collections::HashSet;
+use std::time::Instant;
+
+use oorandom::Rand32;
 
 use proptest::strategy::Strategy;
 use proptest::test_runner::{Config, TestCaseError, TestCaseResult, TestRunner};
@@ -44,6 +47,106 @@ pub unsafe fn test() {
             test_bit_iter(&mut mem, bits)
         })
         .unwrap();
+
+    println!("  Testing bit iteration against get_bit");
+    for (seed, density) in [(0, 0.01), (1, 0.5), (2, 1.0)] {
+        let mut mem = TestMemory::new(Words(SPARSE_HEAP_WORDS / 32 + 2));
+        test_bit_iter_reference(&mut mem, seed, density);
+    }
+
+    println!("  Benchmarking sparse bit iteration");
+    {
+        let mut mem = TestMemory::new(Words(SPARSE_HEAP_WORDS / 32 + 2));
+        bench_sparse_bit_iter(&mut mem);
+    }
+}
+
+/// Size of the heap in the sparse bitmap tests, in words
+const SPARSE_HEAP_WORDS: u32 = 1024 * 1024;
+
+/// Words before the heap in the sparse bitmap tests, to test bitmaps that don't start at address 0
+const SPARSE_HEAP_PREFIX_WORDS: u32 = 1024;
+
+/// Allocate a bitmap for `SPARSE_HEAP_WORDS`, and set bits randomly with the given density.
+/// Returns the set bits.
+unsafe fn alloc_random_bitmap<M: Memory>(mem: &mut M, seed: u64, density: f32) -> Vec<u32> {
+    alloc_bitmap(
+        mem,
+        Words(SPARSE_HEAP_WORDS).to_bytes(),
+        SPARSE_HEAP_PREFIX_WORDS,
+    );
+
+    let mut rng = Rand32::new(seed);
+    let bits: Vec<u32> = (SPARSE_HEAP_PREFIX_WORDS..SPARSE_HEAP_PREFIX_WORDS + SPARSE_HEAP_WORDS)
+        .filter(|_| rng.rand_float() < density)
+        .collect();
+
+    for bit in &bits {
+        set_bit(*bit);
+    }
+
+    bits
+}
+
+/// Bits set in the bitmap, found by checking every bit with `get_bit`
+unsafe fn naive_iter_bits() -> Vec<u32> {
+    (SPARSE_HEAP_PREFIX_WORDS..SPARSE_HEAP_PREFIX_WORDS + SPARSE_HEAP_WORDS)
+        .filter(|bit| get_bit(*bit))
+        .collect()
+}
+
+/// Bits set in the bitmap, found with `iter_bits`
+unsafe fn collect_bits() -> Vec<u32> {
+    let mut bits = vec![];
+    let mut iter = iter_bits();
+    let mut bit = iter.next();
+    while bit != BITMAP_ITER_END {
+        bits.push(bit);
+        bit = iter.next();
+    }
+
+    // Iterator keeps returning `BITMAP_ITER_END` at the end
+    assert_eq!(iter.next(), BITMAP_ITER_END);
+
+    bits
+}
+
+fn test_bit_iter_reference<M: Memory>(mem: &mut M, seed: u64, density: f32) {
+    unsafe {
+        let bits = alloc_random_bitmap(mem, seed, density);
+        let reference = naive_iter_bits();
+        assert_eq!(reference, bits);
+        assert_eq!(collect_bits(), reference);
+        free_bitmap();
+    }
+}
+
+/// Compare `iter_bits` with checking every bit, on a bitmap with 1% of the bits set
+fn bench_sparse_bit_iter<M: Memory>(mem: &mut M) {
+    unsafe {
+        let bits = alloc_random_bitmap(mem, 42, 0.01);
+
+        let start = Instant::now();
+        let iter_result = collect_bits();
+        let iter_time = start.elapsed();
+
+        let start = Instant::now();
+        let naive_result = naive_iter_bits();
+        let naive_time = start.elapsed();
+
+        assert_eq!(iter_result, bits);
+        assert_eq!(naive_result, bits);
+
+        println!(
+            "    {} of {} bits set: iter_bits {:?}, get_bit on every bit {:?}",
+            bits.len(),
+            SPARSE_HEAP_WORDS,
+            iter_time,
+            naive_time
+        );
+
+        free_bitmap();
+    }
 }
 
 /// Generates vectors of bit indices
@@ -0,0 +1,161 @@
+use crate::array::{collect, root_field, static_roots};
+use crate::idl_stream::{contents, make_blob};
+use crate::memory::TestMemory;
+
+use motoko_rts::blob_builder::{
+    blob_builder_capacity, blob_builder_finish, blob_builder_len, blob_builder_new,
+    blob_builder_push_byte, blob_builder_push_bytes, INITIAL_BLOB_BUILDER_CAPACITY,
+};
+use motoko_rts::gc::barrier::{BarrierMode, BARRIER_MODE, TEST_GENERATION_BOUNDS};
+use motoko_rts::gc::mark_compact::generational::REMEMBERED_SET;
+use motoko_rts::memory::alloc_blob;
+use motoko_rts::types::{Bytes, Words};
+
+pub unsafe fn test() {
+    println!("Testing blob builders ...");
+
+    test_push_byte();
+    test_push_bytes();
+    test_gc();
+    test_write_barrier();
+}
+
+/// Reference contents: byte `i` of a blob built from scratch
+fn byte(i: usize) -> u8 {
+    (i * 7 + i / 256) as u8
+}
+
+/// Building a large blob byte by byte copies each byte a bounded number of times
+unsafe fn test_push_byte() {
+    println!("  Testing pushing bytes");
+
+    const N: u32 = 100_000;
+
+    let mut mem = TestMemory::new(Words(256 * 1024));
+    let builder = blob_builder_new(&mut mem);
+    assert_eq!(blob_builder_len(builder), 0);
+    assert_eq!(
+        blob_builder_capacity(builder),
+        INITIAL_BLOB_BUILDER_CAPACITY
+    );
+
+    let heap_start = mem.heap_ptr();
+    let mut capacity_changes = 0;
+    for i in 0..N {
+        let capacity = blob_builder_capacity(builder);
+        blob_builder_push_byte(&mut mem, builder, byte(i as usize) as u32);
+        if blob_builder_capacity(builder) != capacity {
+            capacity_changes += 1;
+        }
+        assert_eq!(blob_builder_len(builder), i + 1);
+    }
+
+    // Capacity doubles: 32 * 2^12 >= 100_000
+    assert_eq!(capacity_changes, 12);
+    assert_eq!(
+        blob_builder_capacity(builder),
+        INITIAL_BLOB_BUILDER_CAPACITY << 12
+    );
+
+    // All storage blobs together are less than twice the final one, plus blob headers
+    let allocated = Bytes((mem.heap_ptr() - heap_start) as u32);
+    assert!(allocated.as_u32() < 2 * blob_builder_capacity(builder) + 12 * 8);
+
+    let expected: Vec<u8> = (0..N as usize).map(byte).collect();
+    let blob = blob_builder_finish(&mut mem, builder);
+    assert_eq!(contents(blob), expected);
+
+    // The blob is a copy, pushing to the builder does not change it
+    blob_builder_push_byte(&mut mem, builder, 0);
+    assert_eq!(contents(blob), expected);
+    assert_eq!(blob_builder_len(builder), N + 1);
+}
+
+unsafe fn test_push_bytes() {
+    println!("  Testing pushing blobs");
+
+    let mut mem = TestMemory::new(Words(64 * 1024));
+    let builder = blob_builder_new(&mut mem);
+
+    let mut expected = vec![];
+    for n in [0, 3, 100, 1, 0, 5000, 17] {
+        let bytes: Vec<u8> = (expected.len()..expected.len() + n).map(byte).collect();
+        let blob = make_blob(&mut mem, &bytes);
+        blob_builder_push_bytes(&mut mem, builder, blob);
+        expected.extend(bytes);
+        assert_eq!(blob_builder_len(builder), expected.len() as u32);
+    }
+
+    assert_eq!(contents(blob_builder_finish(&mut mem, builder)), expected);
+
+    // Empty builder
+    let builder = blob_builder_new(&mut mem);
+    assert_eq!(contents(blob_builder_finish(&mut mem, builder)), vec![]);
+}
+
+/// The builder and its storage survive collections between pushes
+unsafe fn test_gc() {
+    println!("  Testing GC between pushes");
+
+    const N: usize = 10_000;
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    // The builder is a field of a mutable box in the static roots, below the heap base, so the
+    // GC updates it
+    let (roots, heap_base) = static_roots(&mut mem, 1);
+    let builder_loc = root_field(roots, 0);
+    *builder_loc = blob_builder_new(&mut mem);
+
+    let mut expected = vec![];
+    for i in 0..N {
+        if i % 3 == 0 {
+            let bytes: Vec<u8> = (i..i + i % 50).map(byte).collect();
+            let blob = make_blob(&mut mem, &bytes);
+            blob_builder_push_bytes(&mut mem, *builder_loc, blob);
+            expected.extend(bytes);
+        } else {
+            blob_builder_push_byte(&mut mem, *builder_loc, byte(i) as u32);
+            expected.push(byte(i));
+        }
+
+        // Garbage between the pushes, so that GC moves the builder and its storage
+        alloc_blob(&mut mem, Bytes(64));
+
+        if i % 1000 == 999 {
+            collect(&mut mem, heap_base, roots);
+            assert_eq!(blob_builder_len(*builder_loc), expected.len() as u32);
+        }
+    }
+
+    assert_eq!(
+        contents(blob_builder_finish(&mut mem, *builder_loc)),
+        expected
+    );
+}
+
+/// The new storage blob of an old builder is remembered, so that a minor GC does not miss it
+unsafe fn test_write_barrier() {
+    println!("  Testing write barrier");
+
+    let mut mem = TestMemory::new(Words(64 * 1024));
+    let heap_base = mem.heap_ptr() as u32;
+    let builder = blob_builder_new(&mut mem);
+    let young_base = mem.heap_ptr() as u32;
+
+    REMEMBERED_SET = None;
+    BARRIER_MODE = BarrierMode::RememberedSet;
+    TEST_GENERATION_BOUNDS = (heap_base, young_base);
+
+    for i in 0..INITIAL_BLOB_BUILDER_CAPACITY + 1 {
+        blob_builder_push_byte(&mut mem, builder, i);
+    }
+    assert!(REMEMBERED_SET
+        .as_ref()
+        .unwrap()
+        .contains(builder.as_array().payload_addr().add(1)));
+
+    REMEMBERED_SET = None;
+    BARRIER_MODE = BarrierMode::Disabled;
+    TEST_GENERATION_BOUNDS = (0, 0);
+}
@@ -0,0 +1,151 @@
+use crate::memory::TestMemory;
+
+use motoko_rts::blob_encoding::{
+    base64_to_blob, blob_to_base64, blob_to_hex, hex_to_blob, DECODE_ERROR,
+};
+use motoko_rts::memory::Memory;
+use motoko_rts::text::{text_concat, text_of_ptr_size, text_of_str};
+use motoko_rts::types::{Bytes, Value, Words};
+
+pub unsafe fn test() {
+    println!("Testing blob encoding ...");
+
+    let mut heap = TestMemory::new(Words(1024 * 1024));
+
+    println!("  Testing hex");
+    test_hex(&mut heap);
+
+    println!("  Testing base64");
+    test_base64(&mut heap);
+
+    println!("  Testing round trips");
+    test_round_trips(&mut heap);
+
+    println!("  Testing invalid input");
+    test_invalid(&mut heap);
+}
+
+unsafe fn bytes<'a>(value: Value) -> &'a [u8] {
+    let blob = value.as_blob();
+    core::slice::from_raw_parts(blob.payload_const(), blob.len().as_usize())
+}
+
+unsafe fn blob<M: Memory>(mem: &mut M, bytes: &[u8]) -> Value {
+    text_of_ptr_size(mem, bytes.as_ptr(), Bytes(bytes.len() as u32))
+}
+
+unsafe fn test_hex<M: Memory>(mem: &mut M) {
+    let b = blob(mem, b"");
+    assert_eq!(bytes(blob_to_hex(mem, b)), b"");
+
+    let b = blob(mem, b"\x00\x01\x7f\x80\xab\xff");
+    assert_eq!(bytes(blob_to_hex(mem, b)), b"00017f80abff");
+
+    // Both cases are accepted
+    let t = text_of_str(mem, "00017F80aBfF");
+    assert_eq!(bytes(hex_to_blob(mem, t)), b"\x00\x01\x7f\x80\xab\xff");
+}
+
+unsafe fn test_base64<M: Memory>(mem: &mut M) {
+    // RFC 4648 test vectors
+    let vectors: &[(&[u8], &[u8], &[u8])] = &[
+        (b"", b"", b""),
+        (b"f", b"Zg==", b"Zg"),
+        (b"fo", b"Zm8=", b"Zm8"),
+        (b"foo", b"Zm9v", b"Zm9v"),
+        (b"foob", b"Zm9vYg==", b"Zm9vYg"),
+        (b"fooba", b"Zm9vYmE=", b"Zm9vYmE"),
+        (b"foobar", b"Zm9vYmFy", b"Zm9vYmFy"),
+        (b"\xfb\xff", b"+/8=", b"-_8"),
+        (b"\xfb\xef\xbe", b"++++", b"----"),
+        (b"\xff\xff\xff", b"////", b"____"),
+    ];
+
+    for (plain, standard, url_safe) in vectors.iter() {
+        let b = blob(mem, plain);
+        assert_eq!(bytes(blob_to_base64(mem, b, false)), *standard);
+        assert_eq!(bytes(blob_to_base64(mem, b, true)), *url_safe);
+
+        let t = blob(mem, standard);
+        assert_eq!(bytes(base64_to_blob(mem, t, false)), *plain);
+        let t = blob(mem, url_safe);
+        assert_eq!(bytes(base64_to_blob(mem, t, true)), *plain);
+    }
+
+    // Padding is optional in both alphabets
+    let t = text_of_str(mem, "Zm9vYg");
+    assert_eq!(bytes(base64_to_blob(mem, t, false)), b"foob");
+    let t = text_of_str(mem, "Zm9vYg==");
+    assert_eq!(bytes(base64_to_blob(mem, t, true)), b"foob");
+
+    // Text that is not a single blob
+    let t1 = text_of_str(mem, "Zm9vYmFyZm9v");
+    let t2 = text_of_str(mem, "YmFyZm9vYmFy");
+    let t = text_concat(mem, t1, t2);
+    assert_eq!(bytes(base64_to_blob(mem, t, false)), b"foobarfoobarfoobar");
+}
+
+unsafe fn test_round_trips<M: Memory>(mem: &mut M) {
+    let mut data = [0u8; 100];
+    let mut seed: u32 = 12345;
+    for byte in data.iter_mut() {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        *byte = (seed >> 16) as u8;
+    }
+
+    for len in 0..=data.len() {
+        let b = blob(mem, &data[..len]);
+
+        let hex = blob_to_hex(mem, b);
+        assert_eq!(bytes(hex).len(), 2 * len);
+        assert_eq!(bytes(hex_to_blob(mem, hex)), &data[..len]);
+
+        for url_safe in [false, true] {
+            let base64 = blob_to_base64(mem, b, url_safe);
+            assert_eq!(bytes(base64_to_blob(mem, base64, url_safe)), &data[..len]);
+        }
+    }
+}
+
+unsafe fn test_invalid<M: Memory>(mem: &mut M) {
+    for hex in ["0", "abc", "0g", "g0", "zz", " 00", "00 ", "0x00"] {
+        let t = text_of_str(mem, hex);
+        assert!(hex_to_blob(mem, t) == DECODE_ERROR, "{:?}", hex);
+    }
+
+    let invalid: &[(&str, bool)] = &[
+        // Lengths
+        ("Z", false),
+        ("Zm9vY", false),
+        ("Zm9vY===", false),
+        ("Zg===", false),
+        ("=", false),
+        ("====", false),
+        // Padding not completing the last group, or in the middle
+        ("Zg=", false),
+        ("Zm8==", false),
+        ("Zg==Zg==", false),
+        ("Z===", false),
+        // Non-zero unused bits
+        ("Zh==", false),
+        ("Zm9=", false),
+        ("Zh", true),
+        // Characters of the other alphabet
+        ("+/8=", true),
+        ("-_8=", false),
+        // Other characters
+        ("Zm9v\nYmFy", false),
+        ("Zm9v YmFy", false),
+        ("Zm9v.mFy", false),
+        ("Zm9vYmF\u{e9}", false),
+    ];
+
+    for (base64, url_safe) in invalid.iter() {
+        let t = text_of_str(mem, base64);
+        assert!(
+            base64_to_blob(mem, t, *url_safe) == DECODE_ERROR,
+            "{:?}",
+            base64
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! Tests for blob maps. Saving and loading use the mock stable memory of `stable_region`, an
+//! upgrade is simulated by loading the map into a new heap.
+
+use crate::memory::TestMemory;
+use crate::stable_region::reset_stable_memory;
+use crate::trap::traps_with;
+
+use motoko_rts::blob_map::{
+    blob_map_capacity, blob_map_get, blob_map_has, blob_map_len, blob_map_load, blob_map_new,
+    blob_map_put, blob_map_remove, blob_map_save, blob_map_stable_size, INITIAL_BLOB_MAP_SLOTS,
+};
+use motoko_rts::gc::barrier::{BarrierMode, BARRIER_MODE, TEST_GENERATION_BOUNDS};
+use motoko_rts::gc::mark_compact::generational::REMEMBERED_SET;
+use motoko_rts::memory::{alloc_blob, Memory};
+use motoko_rts::stable_region::{stable_region_grow, stable_region_write, STABLE_PAGE_SIZE};
+use motoko_rts::types::{Bytes, Value, Words};
+
+pub unsafe fn test() {
+    println!("Testing blob maps ...");
+
+    test_put_get_remove();
+    test_tombstones();
+    test_round_trip();
+    test_invalid_load();
+    test_write_barrier();
+
+    reset_stable_memory();
+}
+
+unsafe fn test_put_get_remove() {
+    println!("  Testing put, get and remove");
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+    let map = blob_map_new(&mut mem);
+    assert_eq!(blob_map_len(map), 0);
+    assert_eq!(blob_map_capacity(map), INITIAL_BLOB_MAP_SLOTS);
+
+    const N: u32 = 1000;
+    for i in 0..N {
+        let key = key_blob(&mut mem, i);
+        let value = value_blob(&mut mem, i);
+        blob_map_put(&mut mem, map, key, value);
+        assert_eq!(blob_map_len(map), i + 1);
+    }
+    assert!(blob_map_capacity(map).is_power_of_two());
+    assert!(blob_map_capacity(map) * 3 >= N * 4);
+
+    // Keys are compared by contents, not by address
+    for i in 0..N {
+        let key = key_blob(&mut mem, i);
+        assert!(blob_map_has(map, key));
+        assert_eq!(bytes_of_blob(blob_map_get(map, key)), value_bytes(i));
+    }
+    let missing = key_blob(&mut mem, N);
+    assert!(!blob_map_has(map, missing));
+    assert!(traps_with("Blob map key not found", || {
+        blob_map_get(map, missing);
+    }));
+
+    // Replacing a value does not add an entry
+    let key = key_blob(&mut mem, 7);
+    let value = blob_of_bytes(&mut mem, b"replaced");
+    blob_map_put(&mut mem, map, key, value);
+    assert_eq!(blob_map_len(map), N);
+    assert_eq!(bytes_of_blob(blob_map_get(map, key)), b"replaced");
+
+    // The empty blob is a key like any other
+    let empty = blob_of_bytes(&mut mem, b"");
+    blob_map_put(&mut mem, map, empty, empty);
+    assert_eq!(bytes_of_blob(blob_map_get(map, empty)), b"");
+    assert!(blob_map_remove(&mut mem, map, empty));
+
+    for i in (0..N).step_by(2) {
+        let key = key_blob(&mut mem, i);
+        assert!(blob_map_remove(&mut mem, map, key));
+        assert!(!blob_map_remove(&mut mem, map, key));
+    }
+    assert_eq!(blob_map_len(map), N / 2);
+    for i in 0..N {
+        let key = key_blob(&mut mem, i);
+        assert_eq!(blob_map_has(map, key), i % 2 == 1);
+    }
+}
+
+/// Removing and adding entries reuses slots, and does not grow the map
+unsafe fn test_tombstones() {
+    println!("  Testing tombstones");
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+    let map = blob_map_new(&mut mem);
+
+    for i in 0..10_000 {
+        let key = key_blob(&mut mem, i);
+        blob_map_put(&mut mem, map, key, key);
+        if i >= 10 {
+            let old_key = key_blob(&mut mem, i - 10);
+            assert!(blob_map_remove(&mut mem, map, old_key));
+        }
+        assert!(blob_map_len(map) <= 10);
+    }
+    assert!(blob_map_capacity(map) <= 32);
+
+    for i in 10_000 - 10..10_000 {
+        let key = key_blob(&mut mem, i);
+        assert_eq!(bytes_of_blob(blob_map_get(map, key)), key_bytes(i));
+    }
+}
+
+/// Save a large map with tombstones, load it in a new heap, and check that it has the same entries
+/// in the same slots
+unsafe fn test_round_trip() {
+    println!("  Testing round trip across an upgrade");
+
+    const N: u32 = 30_000;
+    const OFFSET: u64 = 1000;
+
+    let (saved_layout, saved_size) = {
+        let mut mem = TestMemory::new(Words(4 * 1024 * 1024));
+        let map = blob_map_new(&mut mem);
+        for i in 0..N {
+            let key = key_blob(&mut mem, i);
+            let value = value_blob(&mut mem, i);
+            blob_map_put(&mut mem, map, key, value);
+        }
+        for i in (0..N).step_by(7) {
+            let key = key_blob(&mut mem, i);
+            assert!(blob_map_remove(&mut mem, map, key));
+        }
+
+        let size = blob_map_stable_size(map);
+        let pages = (OFFSET + size).div_ceil(STABLE_PAGE_SIZE);
+        assert_eq!(stable_region_grow(pages), 0);
+
+        // The region is checked before writing
+        assert!(traps_with("StableMemory range out of bounds", || {
+            blob_map_save(map, pages * STABLE_PAGE_SIZE - size + 1);
+        }));
+
+        assert_eq!(blob_map_save(map, OFFSET), size);
+        (layout(map), size)
+    };
+
+    let mut mem = TestMemory::new(Words(4 * 1024 * 1024));
+    let map = blob_map_load(&mut mem, OFFSET);
+    assert_eq!(layout(map), saved_layout);
+    assert_eq!(blob_map_len(map), N - N.div_ceil(7));
+    assert_eq!(blob_map_stable_size(map), saved_size);
+
+    for i in 0..N {
+        let key = key_blob(&mut mem, i);
+        if i % 7 == 0 {
+            assert!(!blob_map_has(map, key));
+        } else {
+            assert_eq!(bytes_of_blob(blob_map_get(map, key)), value_bytes(i));
+        }
+    }
+
+    // The loaded map can be updated
+    for i in (0..N).step_by(7) {
+        let key = key_blob(&mut mem, i);
+        let value = value_blob(&mut mem, i);
+        blob_map_put(&mut mem, map, key, value);
+    }
+    assert_eq!(blob_map_len(map), N);
+    for i in 0..N {
+        let key = key_blob(&mut mem, i);
+        assert_eq!(bytes_of_blob(blob_map_get(map, key)), value_bytes(i));
+    }
+}
+
+unsafe fn test_invalid_load() {
+    println!("  Testing loading invalid maps");
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    // No saved map at the end of the region
+    let end = stable_region_grow(0) * STABLE_PAGE_SIZE;
+    assert!(traps_with("blob_map_load: no saved map at offset", || {
+        blob_map_load(&mut mem, end - 100);
+    }));
+    assert!(traps_with("StableMemory range out of bounds", || {
+        blob_map_load(&mut mem, end);
+    }));
+
+    let map = blob_map_new(&mut mem);
+    let key = key_blob(&mut mem, 0);
+    blob_map_put(&mut mem, map, key, key);
+    blob_map_save(map, 0);
+
+    let version = blob_of_bytes(&mut mem, &2u32.to_le_bytes());
+    stable_region_write(4, version);
+    assert!(traps_with("blob_map_load: unsupported version", || {
+        blob_map_load(&mut mem, 0);
+    }));
+
+    // Wrong number of entries
+    blob_map_save(map, 0);
+    let count = blob_of_bytes(&mut mem, &2u32.to_le_bytes());
+    stable_region_write(12, count);
+    assert!(traps_with("blob_map_load: invalid header", || {
+        blob_map_load(&mut mem, 0);
+    }));
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Slot {
+    Empty,
+    Tombstone,
+    Entry(Vec<u8>, Vec<u8>),
+}
+
+/// Contents of the slots of a map
+unsafe fn layout(map: Value) -> Vec<Slot> {
+    let slots = map.as_array().get(2).as_array();
+    (0..slots.len() / 2)
+        .map(|slot| {
+            let key = slots.get(2 * slot);
+            if key == Value::from_scalar(0) {
+                Slot::Empty
+            } else if key == Value::from_scalar(1) {
+                Slot::Tombstone
+            } else {
+                let value = slots.get(2 * slot + 1);
+                Slot::Entry(bytes_of_blob(key).to_vec(), bytes_of_blob(value).to_vec())
+            }
+        })
+        .collect()
+}
+
+fn key_bytes(i: u32) -> Vec<u8> {
+    format!("key {}", i).into_bytes()
+}
+
+/// Values have different lengths, from empty to 63 bytes
+fn value_bytes(i: u32) -> Vec<u8> {
+    (0..i % 64).map(|j| (i + j) as u8).collect()
+}
+
+/// Pointers stored in an old map are remembered, so that a minor GC does not miss them
+unsafe fn test_write_barrier() {
+    println!("  Testing write barrier");
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+    let heap_base = mem.heap_ptr() as u32;
+    let map = blob_map_new(&mut mem);
+    let young_base = mem.heap_ptr() as u32;
+
+    REMEMBERED_SET = None;
+    BARRIER_MODE = BarrierMode::RememberedSet;
+    TEST_GENERATION_BOUNDS = (heap_base, young_base);
+
+    let key = key_blob(&mut mem, 0);
+    let value = value_blob(&mut mem, 0);
+    blob_map_put(&mut mem, map, key, value);
+
+    // The key and the value are stored in the old slot array
+    let slots = map.as_array().get(2).as_array();
+    let slot = (0..slots.len() / 2)
+        .find(|slot| slots.get(2 * slot) == key)
+        .unwrap();
+    let remembered_set = REMEMBERED_SET.as_ref().unwrap();
+    assert_eq!(remembered_set.len(), 2);
+    assert!(remembered_set.contains(slots.payload_addr().add(2 * slot as usize)));
+    assert!(remembered_set.contains(slots.payload_addr().add(2 * slot as usize + 1)));
+
+    REMEMBERED_SET = None;
+    BARRIER_MODE = BarrierMode::Disabled;
+    TEST_GENERATION_BOUNDS = (0, 0);
+}
+
+unsafe fn key_blob<M: Memory>(mem: &mut M, i: u32) -> Value {
+    blob_of_bytes(mem, &key_bytes(i))
+}
+
+unsafe fn value_blob<M: Memory>(mem: &mut M, i: u32) -> Value {
+    blob_of_bytes(mem, &value_bytes(i))
+}
+
+unsafe fn blob_of_bytes<M: Memory>(mem: &mut M, bytes: &[u8]) -> Value {
+    let blob = alloc_blob(mem, Bytes(bytes.len() as u32));
+    let blob_ptr = blob.as_blob_mut();
+    for (i, byte) in bytes.iter().enumerate() {
+        blob_ptr.set(i as u32, *byte);
+    }
+    blob
+}
+
+unsafe fn bytes_of_blob(blob: Value) -> &'static [u8] {
+    let blob = blob.as_blob();
+    std::slice::from_raw_parts(blob.payload_const(), blob.len().as_usize())
+}
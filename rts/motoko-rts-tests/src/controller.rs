@@ -0,0 +1,84 @@
+//! Tests for controller checks, against a mock `is_controller_moc`. The mock implements the function
+//! generated by `moc` for the RTS.
+
+use crate::idl_stream::make_blob;
+use crate::memory::TestMemory;
+
+use motoko_rts::controller::{principal_is_controller, set_controllers};
+use motoko_rts::memory::alloc_array;
+use motoko_rts::types::{Value, Words};
+
+/// Whether the mock `ic0.is_controller` is available
+static mut IS_CONTROLLER_AVAILABLE: bool = true;
+
+/// Controllers known to the mock `ic0.is_controller`
+const IC_CONTROLLERS: &[&[u8]] = &[b"\x01\x02\x03", b"\xaa"];
+
+#[no_mangle]
+unsafe extern "C" fn is_controller_moc(src: *const u8, size: u32) -> i32 {
+    if !IS_CONTROLLER_AVAILABLE {
+        return -1;
+    }
+    let principal = std::slice::from_raw_parts(src, size as usize);
+    IC_CONTROLLERS.contains(&principal) as i32
+}
+
+pub unsafe fn test() {
+    println!("Testing controller checks ...");
+
+    let mut mem = TestMemory::new(Words(1024));
+
+    let controller = make_blob(&mut mem, b"\x01\x02\x03");
+    let prefix = make_blob(&mut mem, b"\x01\x02");
+    let registered = make_blob(&mut mem, b"\x04\x05");
+    let anonymous = make_blob(&mut mem, b"\x04");
+
+    println!("  Testing without registered controllers");
+    IS_CONTROLLER_AVAILABLE = false;
+    assert!(!principal_is_controller(controller));
+    assert!(!principal_is_controller(anonymous));
+
+    // Registered list only contains `registered`
+    let controllers = alloc_array(&mut mem, 1);
+    controllers
+        .as_array()
+        .set(0, make_blob(&mut mem, b"\x04\x05"));
+    set_controllers(controllers);
+
+    println!("  Testing ic0.is_controller");
+    IS_CONTROLLER_AVAILABLE = true;
+    assert!(principal_is_controller(controller));
+    assert!(principal_is_controller(make_blob(&mut mem, b"\xaa")));
+    assert!(!principal_is_controller(prefix));
+    assert!(!principal_is_controller(anonymous));
+    // The registered list is not used when `ic0.is_controller` is available
+    assert!(!principal_is_controller(registered));
+
+    println!("  Testing registered controllers");
+    IS_CONTROLLER_AVAILABLE = false;
+    assert!(principal_is_controller(registered));
+    assert!(!principal_is_controller(controller));
+    assert!(!principal_is_controller(anonymous));
+    assert!(!principal_is_controller(make_blob(
+        &mut mem,
+        b"\x04\x05\x06"
+    )));
+
+    // Replacing the list
+    let controllers = alloc_array(&mut mem, 2);
+    controllers.as_array().set(0, anonymous);
+    controllers.as_array().set(1, controller);
+    set_controllers(controllers);
+    assert!(!principal_is_controller(registered));
+    assert!(principal_is_controller(anonymous));
+    assert!(principal_is_controller(controller));
+
+    // Empty list
+    set_controllers(alloc_array(&mut mem, 0));
+    assert!(!principal_is_controller(controller));
+
+    IS_CONTROLLER_AVAILABLE = true;
+
+    // The list is a registered root, don't leave it pointing to the heap of this test
+    set_controllers(Value::from_scalar(0));
+}
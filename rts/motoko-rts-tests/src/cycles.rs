@@ -0,0 +1,104 @@
+//! Tests for 128-bit cycle amounts, against mock cycle functions. The mock implements the
+//! functions generated by `moc` for the RTS.
+
+use crate::trap::traps_with;
+
+use motoko_rts::cycles::{
+    call_cycles_add128, msg_cycles_accept128, msg_cycles_available128, Cycles128,
+};
+
+/// Cycles available in the current message
+static mut AVAILABLE: Cycles128 = Cycles128::ZERO;
+
+/// Cycles accepted by the canister
+static mut ACCEPTED: Cycles128 = Cycles128::ZERO;
+
+/// Cycles added to the call being constructed
+static mut ADDED: Cycles128 = Cycles128::ZERO;
+
+#[no_mangle]
+unsafe extern "C" fn msg_cycles_available128_moc(dst: *mut Cycles128) {
+    *dst = AVAILABLE;
+}
+
+#[no_mangle]
+unsafe extern "C" fn msg_cycles_accept128_moc(
+    max_amount_high: u64,
+    max_amount_low: u64,
+    dst: *mut Cycles128,
+) {
+    // The RTS never asks for more than is available
+    let amount = Cycles128::new(max_amount_high, max_amount_low);
+    assert!(amount <= AVAILABLE);
+    AVAILABLE = AVAILABLE - amount;
+    ACCEPTED = ACCEPTED + amount;
+    *dst = amount;
+}
+
+#[no_mangle]
+unsafe extern "C" fn call_cycles_add128_moc(amount_high: u64, amount_low: u64) {
+    ADDED = ADDED + Cycles128::new(amount_high, amount_low);
+}
+
+pub unsafe fn test() {
+    println!("Testing cycles ...");
+
+    println!("  Testing arithmetic");
+    let max_lo = Cycles128::from_u64(u64::MAX);
+    assert_eq!(max_lo + Cycles128::from_u64(1), Cycles128::new(1, 0));
+    assert_eq!(max_lo + max_lo, Cycles128::new(1, u64::MAX - 1));
+    assert_eq!(
+        Cycles128::new(1, u64::MAX) + Cycles128::new(2, 1),
+        Cycles128::new(4, 0)
+    );
+    assert_eq!(Cycles128::new(1, 0) - Cycles128::from_u64(1), max_lo);
+    assert_eq!(
+        Cycles128::new(5, 3) - Cycles128::new(2, 7),
+        Cycles128::new(2, u64::MAX - 3)
+    );
+    assert_eq!(
+        Cycles128::new(0, u64::MAX) - Cycles128::new(0, u64::MAX - 1),
+        Cycles128::new(0, 1)
+    );
+
+    assert!(traps_with("cycles underflow", || {
+        let _ = Cycles128::new(1, 0) - Cycles128::new(1, 1);
+    }));
+    assert!(traps_with("cycles overflow", || {
+        let _ = Cycles128::new(u64::MAX, u64::MAX) + Cycles128::from_u64(1);
+    }));
+
+    println!("  Testing comparison");
+    assert!(Cycles128::new(1, 0) > max_lo);
+    assert!(Cycles128::new(1, 2) < Cycles128::new(2, 1));
+    assert!(Cycles128::new(2, 1) < Cycles128::new(2, 2));
+    assert_eq!(
+        core::cmp::max(Cycles128::new(0, 7), Cycles128::new(3, 0)),
+        Cycles128::new(3, 0)
+    );
+
+    println!("  Testing accept");
+    AVAILABLE = Cycles128::new(2, 100);
+    assert_eq!(msg_cycles_available128(), Cycles128::new(2, 100));
+
+    // Accepting part of the available cycles
+    assert_eq!(
+        msg_cycles_accept128(Cycles128::new(1, 0)),
+        Cycles128::new(1, 0)
+    );
+    assert_eq!(msg_cycles_available128(), Cycles128::new(1, 100));
+
+    // Accepting more than is available saturates
+    assert_eq!(
+        msg_cycles_accept128(Cycles128::new(u64::MAX, 0)),
+        Cycles128::new(1, 100)
+    );
+    assert_eq!(msg_cycles_available128(), Cycles128::ZERO);
+    assert_eq!({ ACCEPTED }, Cycles128::new(2, 100));
+    assert_eq!(msg_cycles_accept128(max_lo), Cycles128::ZERO);
+
+    println!("  Testing add");
+    call_cycles_add128(max_lo);
+    call_cycles_add128(Cycles128::from_u64(2));
+    assert_eq!({ ADDED }, Cycles128::new(1, 1));
+}
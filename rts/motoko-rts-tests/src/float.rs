@@ -0,0 +1,170 @@
+use crate::memory::TestMemory;
+use crate::trap::traps_with;
+
+use motoko_rts::float::{
+    float_cos, float_exp, float_log, float_pow, float_sin, float_sqrt, float_to_text, text_to_float,
+};
+use motoko_rts::memory::Memory;
+use motoko_rts::text::{blob_of_text, text_concat, text_of_str};
+use motoko_rts::types::{Value, Words};
+
+pub unsafe fn test() {
+    println!("Testing floats ...");
+
+    test_text_conversions();
+    test_math();
+}
+
+unsafe fn test_text_conversions() {
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    println!("  Testing float_to_text");
+    for (a, str) in [
+        (0.0, "0.0"),
+        (-0.0, "-0.0"),
+        (1.0, "1.0"),
+        (-1.5, "-1.5"),
+        (0.1, "0.1"),
+        (0.1 + 0.2, "0.30000000000000004"),
+        (1.0 / 3.0, "0.3333333333333333"),
+        (123456789.0, "123456789.0"),
+        (1e100, "1e100"),
+        (1e-7, "1e-7"),
+        (f64::MAX, "1.7976931348623157e308"),
+        (f64::MIN_POSITIVE, "2.2250738585072014e-308"),
+        // Subnormals
+        (5e-324, "5e-324"),
+        (-2.225073858507201e-308, "-2.225073858507201e-308"),
+        (f64::INFINITY, "inf"),
+        (f64::NEG_INFINITY, "-inf"),
+        (f64::NAN, "nan"),
+    ] {
+        let text = float_to_text(&mut mem, a);
+        assert_eq!(text_to_string(&mut mem, text), str);
+    }
+
+    println!("  Testing text_to_float");
+    for (str, a) in [
+        ("0", 0.0),
+        ("-0.0", -0.0),
+        ("1e3", 1000.0),
+        ("2.5E-3", 0.0025),
+        ("inf", f64::INFINITY),
+        ("-inf", f64::NEG_INFINITY),
+        // Rounds to the nearest float
+        ("0.30000000000000001", 0.3),
+        ("1e-400", 0.0),
+    ] {
+        let text = text_of_str(&mut mem, str);
+        assert_eq!(text_to_float(&mut mem, text).to_bits(), f64::to_bits(a));
+    }
+
+    let text = text_of_str(&mut mem, "nan");
+    assert!(text_to_float(&mut mem, text).is_nan());
+
+    // Texts made of concat nodes
+    let text1 = text_of_str(&mut mem, "12345.");
+    let text2 = text_of_str(&mut mem, "678");
+    let text = text_concat(&mut mem, text1, text2);
+    assert_eq!(text_to_float(&mut mem, text), 12345.678);
+
+    for str in ["", "1.0.0", "0x10", "1e", "- 1"] {
+        let text = text_of_str(&mut mem, str);
+        assert!(traps_with("invalid float", || {
+            text_to_float(&mut mem, text);
+        }));
+    }
+
+    println!("  Testing round trips");
+    let mut rand = oorandom::Rand64::new(0);
+    for i in 0..100_000 {
+        // Random bit patterns cover all exponents, including subnormals. Also test random floats
+        // in [0, 1) that have more digits.
+        let a = if i % 2 == 0 {
+            f64::from_bits(rand.rand_u64())
+        } else {
+            rand.rand_float()
+        };
+
+        if a.is_nan() {
+            continue;
+        }
+
+        let text = float_to_text(&mut mem, a);
+        assert_eq!(text_to_float(&mut mem, text).to_bits(), a.to_bits());
+
+        // Free the texts, the memory is too small for all of them
+        if i % 1000 == 0 {
+            mem = TestMemory::new(Words(1024 * 1024));
+        }
+    }
+}
+
+unsafe fn test_math() {
+    println!("  Testing reference vectors");
+    // The reference vectors of `motoko_rts::float`, checked bit by bit
+    for (a, bits) in [
+        (float_sqrt(2.0), 0x3ff6a09e667f3bcd),
+        (float_sin(1.0), 0x3feaed548f090cee),
+        (float_cos(1.0), 0x3fe14a280fb5068c),
+        (float_sin(100.0), 0xbfe03425b78c4db8),
+        (float_cos(100.0), 0x3feb981dbf665fdf),
+        (float_exp(1.0), 0x4005bf0a8b145769),
+        (float_exp(-1.0), 0x3fd78b56362cef38),
+        (float_log(2.0), 0x3fe62e42fefa39ef),
+        (float_log(10.0), 0x40026bb1bbb55516),
+        (float_pow(2.0, 0.5), 0x3ff6a09e667f3bcd),
+        (float_pow(10.0, -2.0), 0x3f847ae147ae147b),
+        (float_pow(1.5, 2.5), 0x40060b9fd68a4554),
+    ] {
+        assert_eq!(a.to_bits(), bits, "{} is not {:#x}", a, bits);
+    }
+
+    println!("  Testing special values");
+    assert_eq!(float_sqrt(0.0), 0.0);
+    assert_eq!(float_sqrt(-0.0).to_bits(), (-0.0f64).to_bits());
+    assert_eq!(float_sqrt(f64::INFINITY), f64::INFINITY);
+    assert!(float_sqrt(-1.0).is_nan());
+    assert_eq!(float_sin(0.0), 0.0);
+    assert_eq!(float_cos(0.0), 1.0);
+    assert!(float_sin(f64::INFINITY).is_nan());
+    assert!(float_cos(f64::NEG_INFINITY).is_nan());
+    assert_eq!(float_exp(0.0), 1.0);
+    assert_eq!(float_exp(1000.0), f64::INFINITY);
+    assert_eq!(float_exp(-1000.0), 0.0);
+    assert_eq!(float_log(1.0), 0.0);
+    assert_eq!(float_log(0.0), f64::NEG_INFINITY);
+    assert!(float_log(-1.0).is_nan());
+    assert_eq!(float_pow(2.0, 10.0), 1024.0);
+    assert_eq!(float_pow(f64::NAN, 0.0), 1.0);
+    assert_eq!(float_pow(-2.0, 3.0), -8.0);
+    assert!(float_pow(-2.0, 0.5).is_nan());
+
+    println!("  Testing identities");
+    let mut rand = oorandom::Rand64::new(0);
+    for _ in 0..100_000 {
+        let a = rand.rand_float() * 1000.0 - 500.0;
+
+        // Exact squares, and correct rounding of square roots
+        let n = (a.abs() as u64) as f64;
+        assert_eq!(float_sqrt(n * n), n);
+        let r = float_sqrt(a.abs());
+        assert!((r * r - a.abs()).abs() <= 2.0 * f64::EPSILON * a.abs());
+
+        let s = float_sin(a);
+        let c = float_cos(a);
+        assert!((s * s + c * c - 1.0).abs() <= 4.0 * f64::EPSILON);
+
+        // Less than 1 ulp, each
+        let b = a / 100.0;
+        assert!((float_log(float_exp(b)) - b).abs() <= 4.0 * f64::EPSILON * b.abs().max(1.0));
+        assert!((float_pow(b, 2.0) - b * b).abs() <= f64::EPSILON * b * b);
+        assert!((float_pow(a.abs(), 0.5) - r).abs() <= f64::EPSILON * r);
+    }
+}
+
+unsafe fn text_to_string<M: Memory>(mem: &mut M, text: Value) -> String {
+    let blob = blob_of_text(mem, text).as_blob();
+    let bytes = std::slice::from_raw_parts(blob.payload_const(), blob.len().as_usize());
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
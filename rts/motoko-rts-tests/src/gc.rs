@@ -9,33 +9,2075 @@ mod heap;
 mod random;
 mod utils;
 
+use crate::array::{collect, root_field, static_roots};
+use crate::memory::TestMemory;
+use crate::trap::traps_with;
+
 use heap::MotokoHeap;
-use utils::{get_scalar_value, read_word, unskew_pointer, ObjectIdx, GC, GC_IMPLS, WORD_SIZE};
+use utils::{
+    get_scalar_value, make_pointer, read_word, unskew_pointer, ObjectIdx, GC, GC_IMPLS,
+    MAX_MARK_STACK_SIZE, WORD_SIZE,
+};
+
+use motoko_rts::finalizer::{
+    take_finalizer_queue, FINALIZABLES, FINALIZER_LIST_END, FINALIZER_QUEUE,
+};
+use motoko_rts::gc::barrier::{write_barrier_internal, BarrierMode, BARRIER_MODE};
+use motoko_rts::gc::copying::copying_gc_internal;
+use motoko_rts::gc::heap_dump::{heap_dump_internal, HEAP_DUMP_MAGIC, HEAP_DUMP_VERSION};
+use motoko_rts::gc::mark_compact::bitmap::get_bit;
+use motoko_rts::gc::mark_compact::estimate::estimate_reclaimable_internal;
+use motoko_rts::gc::mark_compact::generational::{
+    compacting_gc_minor_internal, enable_generational_gc, remember_field, GENERATIONAL,
+    REMEMBERED_SET,
+};
+use motoko_rts::gc::mark_compact::incremental::{
+    compacting_gc_incremental_internal, incremental_gc_write_barrier,
+    incremental_gc_write_barrier_needed, Phase, PHASE,
+};
+use motoko_rts::gc::mark_compact::mark_stack::{
+    DEFAULT_MAX_STACK_SIZE, INIT_STACK_SIZE, MAX_STACK_SIZE, STACK_PTR,
+};
+use motoko_rts::gc::mark_compact::recovery::{recover_interrupted_gc, GcProgress, GC_PROGRESS};
+use motoko_rts::gc::mark_compact::root_snapshot::root_snapshot_len;
+use motoko_rts::gc::mark_compact::{
+    compacting_gc_internal, heap_fragmentation, set_compaction_policy, thread, unthread,
+    CompactionPolicy, GcContext, GcPhase, LARGE_OBJECT_THRESHOLD, MAX_FRAGMENTATION_FOR_SWEEP,
+    MAX_HEAP_SIZE_FOR_SWEEP, SWEEP_FRAGMENTATION_THRESHOLD, SWEEP_HEAP_SIZE_LIMIT,
+};
+use motoko_rts::gc::size_histogram::{size_bucket, size_histogram_bucket, SIZE_HISTOGRAM_BUCKETS};
+use motoko_rts::gc::{set_gc_strategy, Strategy, GC_STRATEGY, INSTRUCTION_COUNTER};
+use motoko_rts::memory::{alloc_array, Memory};
+use motoko_rts::pin::{free_pin_table, pin, unpin};
+use motoko_rts::roots::{
+    drop_root_group, is_root, register_root, register_root_in_group, root_group_size,
+    unregister_root,
+};
+use motoko_rts::stream::alloc_stream;
+use motoko_rts::types::*;
+use motoko_rts::weak_ref::WEAK_REF_CLEARED;
+
+use std::cell::{Cell, RefCell};
+use std::convert::TryInto;
+use std::fmt::Write;
+use std::ptr::null_mut;
+
+use fxhash::{FxHashMap, FxHashSet};
+
+/// Work budget of incremental GC slices in tests. Small, to run many slices even on small heaps.
+const INCREMENTAL_GC_BUDGET: Words<u32> = Words(10);
+
+pub fn test() {
+    println!("Testing garbage collection ...");
+
+    // Test heaps are allocated with enough space for a mark stack of this size. Larger stacks
+    // are handled as mark stack overflows.
+    unsafe {
+        MAX_STACK_SIZE = Words(MAX_MARK_STACK_SIZE as u32);
+    }
+
+    // Tests check that mark-compact GC leaves only reachable objects in the heap, so always
+    // compact. Sweeping is tested separately.
+    unsafe {
+        SWEEP_FRAGMENTATION_THRESHOLD = 0.0;
+    }
+
+    println!("  Testing pre-defined heaps...");
+    for test_heap in test_heaps() {
+        test_gcs(&test_heap);
+    }
+
+    println!("  Testing random heaps...");
+    let max_seed = 100;
+    for seed in 0..max_seed {
+        print!("\r{}/{}", seed + 1, max_seed);
+        std::io::Write::flush(&mut std::io::stdout()).unwrap();
+        test_random_heap(seed, 180);
+    }
+    print!("\r");
+
+    println!("  Testing continuation table reachable from roots...");
+    for gc in &GC_IMPLS {
+        test_continuation_table_in_roots(*gc);
+    }
+
+    println!("  Testing mark stack overflow...");
+    test_mark_stack_overflow();
+
+    println!("  Testing GC after each allocation...");
+    for gc in &GC_IMPLS {
+        test_gc_after_each_allocation(*gc);
+    }
+
+    println!("  Testing mutation during incremental marking...");
+    test_incremental_mutation();
+
+    println!("  Testing root snapshot in incremental marking...");
+    test_root_snapshot();
+
+    println!("  Testing incremental GC without the write barrier...");
+    test_incremental_without_barrier();
+
+    println!("  Testing minor GC...");
+    test_minor_gc();
+
+    println!("  Testing write barrier modes...");
+    test_write_barrier();
+
+    println!("  Testing mutation through the write barrier during incremental marking...");
+    test_write_barrier_mutation();
+
+    println!("  Testing heap fragmentation...");
+    test_heap_fragmentation();
+
+    println!("  Testing GC strategy switch during incremental GC...");
+    test_gc_strategy_switch();
+
+    println!("  Comparing copying and compacting GC results...");
+    for test_heap in test_heaps() {
+        test_copying_compacting_same_heap(&test_heap);
+    }
+    for seed in 0..10 {
+        test_copying_compacting_same_heap(&random::generate(seed, 180));
+    }
+
+    println!("  Testing sweeping dense heaps...");
+    test_sweep();
+
+    println!("  Testing compaction policies...");
+    test_compaction_policy();
+
+    println!("  Testing threading and unthreading pointers...");
+    test_thread_unthread();
+
+    println!("  Testing large objects...");
+    test_large_object();
+
+    println!("  Testing weak references...");
+    for gc in &GC_IMPLS {
+        test_weak_ref_cleared(*gc);
+        test_weak_ref_kept(*gc);
+    }
+
+    println!("  Testing frozen arrays...");
+    for gc in &GC_IMPLS {
+        test_frozen_array(*gc);
+    }
+
+    println!("  Testing scalar fields...");
+    for gc in &GC_IMPLS {
+        test_scalar_fields(*gc);
+    }
+
+    println!("  Testing finalizers...");
+    for gc in &GC_IMPLS {
+        test_finalizers(*gc);
+    }
+
+    println!("  Testing registered roots...");
+    for gc in &GC_IMPLS {
+        test_registered_roots(*gc);
+    }
+
+    println!("  Testing root groups...");
+    for gc in &GC_IMPLS {
+        test_root_groups(*gc);
+    }
+
+    if cfg!(feature = "zero-on-free") {
+        println!("  Testing zeroing reclaimed space...");
+        for gc in &GC_IMPLS {
+            test_zero_on_free(*gc);
+        }
+    }
+
+    println!("  Testing pinned objects...");
+    for gc in [
+        GC::MarkCompact,
+        GC::IncrementalMarkCompact,
+        GC::Generational,
+    ] {
+        test_pinned_objects(gc);
+    }
+
+    println!("  Testing GC phase times...");
+    test_gc_phase_times();
+
+    println!("  Testing recovery from interrupted GC...");
+    test_interrupted_gc();
+
+    if cfg!(feature = "size-histogram") {
+        println!("  Testing live object size histogram...");
+        test_size_histogram();
+    }
+
+    println!("  Testing reclaimable size estimate...");
+    for test_heap in test_heaps() {
+        test_estimate_reclaimable(&test_heap);
+    }
+    for seed in 0..10 {
+        test_estimate_reclaimable(&random::generate(seed, 180));
+    }
+
+    println!("  Testing heap dump...");
+    for test_heap in test_heaps() {
+        test_heap_dump(&test_heap);
+    }
+    for seed in 0..10 {
+        test_heap_dump(&random::generate(seed, 180));
+    }
+    test_heap_dump_during_incremental_gc();
+
+    unsafe {
+        MAX_STACK_SIZE = DEFAULT_MAX_STACK_SIZE;
+        SWEEP_FRAGMENTATION_THRESHOLD = MAX_FRAGMENTATION_FOR_SWEEP;
+    }
+}
+
+/// When the heap is not fragmented, mark-compact GC should not move objects and replace dead
+/// objects with filler objects
+fn test_sweep() {
+    // A list of 20 objects, with one dead object in the middle. Last object points back to the
+    // first one.
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = (0..20)
+        .map(|idx| match idx {
+            9 => (idx, vec![11]),
+            10 => (idx, vec![]),
+            19 => (idx, vec![0]),
+            _ => (idx, vec![idx + 1]),
+        })
+        .collect();
+    let roots = vec![0];
+    let continuation_table = vec![19];
+
+    let heap = MotokoHeap::new(&refs, &roots, &continuation_table, GC::MarkCompact);
+
+    let object_offset = |idx: usize| {
+        let words: usize = refs[..idx].iter().map(|(_, fields)| 3 + fields.len()).sum();
+        heap.heap_base_offset() + words * WORD_SIZE
+    };
+
+    let heap_ptr_offset_before = heap.heap_ptr_offset();
+
+    unsafe {
+        SWEEP_FRAGMENTATION_THRESHOLD = MAX_FRAGMENTATION_FOR_SWEEP;
+    }
+
+    let stats = GC::MarkCompact.run(heap.clone());
+
+    unsafe {
+        SWEEP_FRAGMENTATION_THRESHOLD = 0.0;
+    }
+
+    // Last object in the heap (continuation table) is live, so heap size does not change
+    assert_eq!(heap.heap_ptr_offset(), heap_ptr_offset_before);
+    assert_eq!(stats.reclaimed, Bytes(0));
+    assert_eq!(
+        stats.live,
+        Bytes((heap_ptr_offset_before - heap.heap_base_offset() - 3 * WORD_SIZE) as u32)
+    );
+
+    {
+        let heap = heap.heap();
+
+        // Objects are not moved
+        for idx in (0..20).filter(|idx| *idx != 10) {
+            let offset = object_offset(idx as usize);
+            assert_eq!(read_word(&heap, offset), TAG_ARRAY);
+            assert_eq!(
+                get_scalar_value(read_word(&heap, offset + 2 * WORD_SIZE)),
+                idx
+            );
+        }
+
+        // Dead object is replaced with a filler
+        let offset = object_offset(10);
+        assert_eq!(read_word(&heap, offset), TAG_FREE_SPACE);
+        assert_eq!(read_word(&heap, offset + WORD_SIZE), 2);
+    }
+
+    // Pointers are restored
+    let heap_objects = read_heap_objects(&heap);
+    let expected_objects: FxHashMap<ObjectIdx, Vec<ObjectIdx>> =
+        refs.into_iter().filter(|(idx, _)| *idx != 10).collect();
+    assert_eq!(heap_objects.objects, expected_objects);
+    assert_eq!(heap_objects.continuation_table, continuation_table);
+}
+
+/// Compaction policies decide whether mark-compact GC moves live objects, on a fragmented heap and
+/// on a dense heap
+fn test_compaction_policy() {
+    // Every other object is live, heap fragmentation is 15 / 32 words
+    let fragmented: Vec<(ObjectIdx, Vec<ObjectIdx>)> = (0..10).map(|idx| (idx, vec![])).collect();
+    let fragmented_roots: Vec<ObjectIdx> = (0..10).step_by(2).collect();
+
+    // One dead object, heap fragmentation is 3 / 32 words
+    let dense = fragmented.clone();
+    let dense_roots: Vec<ObjectIdx> = (1..10).collect();
+
+    for (policy, moves_fragmented, moves_dense) in [
+        (CompactionPolicy::Never, false, false),
+        (CompactionPolicy::WhenFragmented, true, false),
+        (CompactionPolicy::Always, true, true),
+    ] {
+        unsafe {
+            set_compaction_policy(policy as u32);
+            SWEEP_FRAGMENTATION_THRESHOLD = MAX_FRAGMENTATION_FOR_SWEEP;
+        }
+
+        check_compaction(&fragmented, &fragmented_roots, moves_fragmented);
+        check_compaction(&dense, &dense_roots, moves_dense);
+    }
+
+    // `Never` compacts when the swept heap would be too large
+    unsafe {
+        set_compaction_policy(CompactionPolicy::Never as u32);
+        SWEEP_HEAP_SIZE_LIMIT = Bytes(3 * WORD_SIZE as u32);
+    }
+    check_compaction(&fragmented, &fragmented_roots, true);
+    check_compaction(&dense, &dense_roots, true);
+
+    unsafe {
+        SWEEP_HEAP_SIZE_LIMIT = MAX_HEAP_SIZE_FOR_SWEEP;
+        set_compaction_policy(CompactionPolicy::WhenFragmented as u32);
+        SWEEP_FRAGMENTATION_THRESHOLD = 0.0;
+    }
+}
+
+/// Run mark-compact GC on the heap, and check whether it moved the live objects. Objects are 3
+/// words each, and have no fields.
+fn check_compaction(refs: &[(ObjectIdx, Vec<ObjectIdx>)], roots: &[ObjectIdx], moves: bool) {
+    let heap = MotokoHeap::new(refs, roots, &[], GC::MarkCompact);
+    let heap_ptr_offset_before = heap.heap_ptr_offset();
+    let dead_bytes = (refs.len() - roots.len()) * 3 * WORD_SIZE;
+
+    let stats = GC::MarkCompact.run(heap.clone());
+
+    if moves {
+        assert_eq!(heap.heap_ptr_offset(), heap_ptr_offset_before - dead_bytes);
+        assert_eq!(stats.reclaimed, Bytes(dead_bytes as u32));
+    } else {
+        assert_eq!(heap.heap_ptr_offset(), heap_ptr_offset_before);
+        assert_eq!(stats.reclaimed, Bytes(0));
+
+        // Live objects stay in place, dead objects are replaced with fillers
+        let heap_bytes = heap.heap();
+        for idx in 0..refs.len() {
+            let offset = heap.heap_base_offset() + idx * 3 * WORD_SIZE;
+            if roots.contains(&(idx as ObjectIdx)) {
+                assert_eq!(read_word(&heap_bytes, offset), TAG_ARRAY);
+                assert_eq!(
+                    get_scalar_value(read_word(&heap_bytes, offset + 2 * WORD_SIZE)),
+                    idx as u32
+                );
+            } else {
+                assert_eq!(read_word(&heap_bytes, offset), TAG_FREE_SPACE);
+            }
+        }
+    }
+
+    let heap_objects = read_heap_objects(&heap);
+    let expected_objects: FxHashMap<ObjectIdx, Vec<ObjectIdx>> = refs
+        .iter()
+        .filter(|(idx, _)| roots.contains(idx))
+        .cloned()
+        .collect();
+    assert_eq!(heap_objects.objects, expected_objects);
+}
+
+/// Build a graph one object at a time and run GC after each allocation, as with the `force-gc`
+/// feature. The mark stack is tiny, and the last object points to all others, so marking
+/// overflows the stack in most of the GCs.
+fn test_gc_after_each_allocation(gc: GC) {
+    const N_NODES: u32 = 100;
+
+    unsafe {
+        MAX_STACK_SIZE = INIT_STACK_SIZE;
+    }
+
+    // Object 0 is the first node, pointed by the root. Object 1 is dead, to have space for the
+    // nodes allocated in the test.
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> =
+        vec![(0, vec![]), (1, vec![0; (N_NODES * N_NODES) as usize])];
+    let mut heap = MotokoHeap::new(&refs, &[0], &[], gc);
+
+    let root = unsafe {
+        Value::from_ptr(heap.static_root_array_address())
+            .as_array()
+            .get(0)
+            .as_obj() as *mut MutBox
+    };
+
+    for idx in 1..N_NODES {
+        unsafe {
+            // Node `idx` points to the nodes `0..idx`. Previous node points to the nodes
+            // `0..idx - 1`. Pointers are read from the root after the allocation, in case the
+            // allocation moves objects.
+            let node = alloc_array(&mut heap, 1 + idx);
+            let last_node = (*root).field.as_array();
+            let node_array = node.as_array();
+            node_array.set(0, Value::from_scalar(idx));
+            for i in 1..idx {
+                node_array.set(i, last_node.get(i));
+            }
+            node_array.set(idx, (*root).field);
+
+            (*root).field = node;
+        }
+
+        gc.run(heap.clone());
+    }
+
+    unsafe {
+        MAX_STACK_SIZE = Words(MAX_MARK_STACK_SIZE as u32);
+    }
+
+    let expected_objects: FxHashMap<ObjectIdx, Vec<ObjectIdx>> =
+        (0..N_NODES).map(|idx| (idx, (0..idx).collect())).collect();
+
+    let heap_objects = read_heap_objects(&heap);
+    assert_eq!(heap_objects.objects, expected_objects, "{:?}", gc);
+    assert!(heap_objects.continuation_table.is_empty());
+}
+
+/// Mark a large heap with a tiny mark stack, to exercise mark stack overflow handling in the
+/// mark-compact GC.
+/// The continuation table is marked both as a root and through a static root pointing to it. Both
+/// pointers should be updated after the table is moved.
+fn test_continuation_table_in_roots(gc: GC) {
+    // Root 0 is redirected to the continuation table, so object 0 becomes garbage and the table
+    // is moved
+    let refs = vec![(0, vec![]), (1, vec![])];
+    let roots = vec![0, 1];
+    let continuation_table = vec![1];
+
+    let heap = MotokoHeap::new(&refs, &roots, &continuation_table, gc);
+
+    let continuation_table_ptr_address = heap.continuation_table_ptr_address() as *mut Value;
+
+    unsafe {
+        let root_array = Value::from_ptr(heap.static_root_array_address()).as_array();
+        let root_field = &mut (*(root_array.get(0).as_obj() as *mut MutBox)).field as *mut Value;
+        let table_before = (*continuation_table_ptr_address).get_ptr();
+        *root_field = *continuation_table_ptr_address;
+
+        gc.run(heap.clone());
+
+        let table = *continuation_table_ptr_address;
+        assert!(table.get_ptr() < table_before);
+        assert!(*root_field == table);
+
+        // The table still points to object 1
+        let table_array = table.as_array();
+        assert_eq!(table_array.len(), 1);
+        let obj1 = table_array.get(0).as_array();
+        assert_eq!(get_scalar_value(obj1.get(0).get_raw()), 1);
+    }
+}
+
+fn test_mark_stack_overflow() {
+    const N_NODES: u32 = 100_000;
+
+    unsafe {
+        MAX_STACK_SIZE = INIT_STACK_SIZE;
+    }
+
+    // A linked list where each node also points back to the head. The head also points to every
+    // 100th node, so marking the head overflows the stack.
+    let mut heap: Vec<(ObjectIdx, Vec<ObjectIdx>)> = (0..N_NODES)
+        .map(|idx| {
+            let next = if idx + 1 == N_NODES { 0 } else { idx + 1 };
+            (idx, vec![next, 0])
+        })
+        .collect();
+
+    heap[0].1.extend((0..N_NODES).step_by(100));
+
+    // Unreachable objects
+    heap.extend((N_NODES..N_NODES + 100).map(|idx| (idx, vec![0])));
+
+    test_gcs(&TestHeap {
+        heap,
+        roots: vec![N_NODES / 2],
+        continuation_table: vec![N_NODES - 1],
+    });
+
+    unsafe {
+        MAX_STACK_SIZE = Words(MAX_MARK_STACK_SIZE as u32);
+    }
+}
+
+/// Mutate the heap between incremental GC slices, the way the mutator does: call the write barrier
+/// before overwriting pointers, and allocate new objects.
+fn test_incremental_mutation() {
+    // Object 1 is only reachable from object 0. The mutator moves it to a new object (3), after
+    // the root object 0 is marked but before it is scanned. Object 4 is garbage.
+    let refs = vec![(0, vec![1]), (1, vec![]), (2, vec![]), (4, vec![])];
+    let roots = vec![0, 2];
+
+    let mut heap = MotokoHeap::new(&refs, &roots, &[], GC::IncrementalMarkCompact);
+
+    let heap_base = heap.heap_base_address();
+    let obj_addr = |idx: usize| {
+        // Objects are laid out in index order, and each one is an array with `3 + n_refs` words
+        let offset: usize = refs[..idx].iter().map(|(_, fields)| 3 + fields.len()).sum();
+        heap_base + offset * WORD_SIZE
+    };
+
+    let live = Cell::new(Bytes(0));
+    let reclaimed = Cell::new(Bytes(0));
+
+    // Marks the roots and scans one object
+    assert!(!incremental_gc_slice(
+        &mut heap,
+        Words(1),
+        &live,
+        &reclaimed
+    ));
+
+    unsafe {
+        // Allocate object 3, pointing to object 1
+        let obj1 = Value::from_ptr(obj_addr(1));
+        let obj3 = heap.alloc_words(size_of::<Array>() + Words(2));
+        let obj3_array = obj3.get_ptr() as *mut Array;
+        (*obj3_array).header.tag = TAG_ARRAY;
+        (*obj3_array).len = 2;
+        obj3_array.set(0, Value::from_scalar(3));
+        obj3_array.set(1, obj1);
+
+        // Object 0 field 0: 1 -> 2
+        let obj0_array = obj_addr(0) as *mut Array;
+        let obj0_field = obj0_array.payload_addr().add(1);
+        assert!(incremental_gc_write_barrier_needed());
+        incremental_gc_write_barrier(obj0_field);
+        *obj0_field = Value::from_ptr(obj_addr(2));
+
+        // Root 1: 2 -> 3
+        let root_array = Value::from_ptr(heap.static_root_array_address()).as_array();
+        let root_field = &mut (*(root_array.get(1).as_obj() as *mut MutBox)).field as *mut Value;
+        incremental_gc_write_barrier(root_field);
+        *root_field = obj3;
+    }
+
+    while !incremental_gc_slice(&mut heap, Words(1), &live, &reclaimed) {}
+
+    let refs = vec![(0, vec![2]), (1, vec![]), (2, vec![]), (3, vec![1])];
+    let roots = vec![0, 3];
+
+    check_dynamic_heap(
+        true, // after gc
+        &refs,
+        &roots,
+        &[],
+        &**heap.heap(),
+        heap.heap_base_offset(),
+        heap.heap_ptr_offset(),
+        heap.continuation_table_ptr_offset(),
+    );
+
+    // Object 4 is reclaimed
+    assert_eq!(reclaimed.get(), Bytes(3 * WORD_SIZE as u32));
+    assert_eq!(
+        live.get(),
+        Bytes((heap.heap_ptr_offset() - heap.heap_base_offset()) as u32)
+    );
+}
+
+/// Overwrite a root during incremental marking without the write barrier. The object the root
+/// pointed to when marking started is in the root snapshot: it survives the cycle (and its fields
+/// are traced), and is reclaimed by the next cycle.
+fn test_root_snapshot() {
+    // Object 2 is only reachable from root 1, object 3 is garbage
+    let refs = vec![(0, vec![1]), (1, vec![]), (2, vec![1]), (3, vec![])];
+    let roots = vec![0, 2];
+
+    let mut heap = MotokoHeap::new(&refs, &roots, &[], GC::IncrementalMarkCompact);
+
+    let live = Cell::new(Bytes(0));
+    let reclaimed = Cell::new(Bytes(0));
+
+    // Takes the snapshot and marks root 0
+    assert!(!incremental_gc_slice(
+        &mut heap,
+        Words(1),
+        &live,
+        &reclaimed
+    ));
+
+    unsafe {
+        // Root 1 and the continuation table are not marked yet
+        assert_eq!(root_snapshot_len(), 2);
+
+        // Root 1: 2 -> 0, without the write barrier
+        let root_array = Value::from_ptr(heap.static_root_array_address()).as_array();
+        let root_0 = (*(root_array.get(0).as_obj() as *mut MutBox)).field;
+        (*(root_array.get(1).as_obj() as *mut MutBox)).field = root_0;
+    }
+
+    while !incremental_gc_slice(&mut heap, Words(1), &live, &reclaimed) {}
+
+    unsafe {
+        assert_eq!(root_snapshot_len(), 0);
+    }
+
+    // Only object 3 is reclaimed, object 2 was reachable when marking started
+    assert_eq!(reclaimed.get(), Bytes(3 * WORD_SIZE as u32));
+
+    // Object 2 is garbage in the next cycle
+    while !incremental_gc_slice(&mut heap, Words(1), &live, &reclaimed) {}
+    assert_eq!(reclaimed.get(), Bytes(4 * WORD_SIZE as u32));
+
+    let refs = vec![(0, vec![1]), (1, vec![])];
+    let roots = vec![0, 0];
+
+    check_dynamic_heap(
+        true, // after gc
+        &refs,
+        &roots,
+        &[],
+        &heap.heap(),
+        heap.heap_base_offset(),
+        heap.heap_ptr_offset(),
+        heap.continuation_table_ptr_offset(),
+    );
+}
+
+/// Without the write barrier the mutator cannot run in the middle of marking: a slice does the
+/// whole cycle, regardless of the budget
+fn test_incremental_without_barrier() {
+    let refs = vec![(0, vec![1]), (1, vec![2]), (2, vec![]), (3, vec![])];
+    let roots = vec![0];
+
+    let mut heap = MotokoHeap::new(&refs, &roots, &[], GC::IncrementalMarkCompact);
+
+    let live = Cell::new(Bytes(0));
+    let reclaimed = Cell::new(Bytes(0));
+
+    assert!(incremental_gc_slice_in_mode(
+        &mut heap,
+        BarrierMode::Disabled,
+        Words(1),
+        &live,
+        &reclaimed
+    ));
+
+    // Object 3 is reclaimed
+    assert_eq!(reclaimed.get(), Bytes(3 * WORD_SIZE as u32));
+
+    let refs = vec![(0, vec![1]), (1, vec![2]), (2, vec![])];
+
+    check_dynamic_heap(
+        true, // after gc
+        &refs,
+        &roots,
+        &[],
+        &**heap.heap(),
+        heap.heap_base_offset(),
+        heap.heap_ptr_offset(),
+        heap.continuation_table_ptr_offset(),
+    );
+}
+
+/// Mark-compact GC should not move large objects, but should update pointers to them and compact
+/// the objects after them
+fn test_large_object() {
+    // An array large enough to be a large object. Objects before and after it are dead, the
+    // object after the dead one is live and pointed by the large object.
+    let n_fields = LARGE_OBJECT_THRESHOLD.as_usize();
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = vec![
+        (0, vec![]),
+        (1, vec![3; n_fields]),
+        (2, vec![]),
+        (3, vec![1]),
+    ];
+    let roots = vec![1];
+    let continuation_table = vec![3];
+
+    let heap = MotokoHeap::new(&refs, &roots, &continuation_table, GC::MarkCompact);
+
+    let object_offset = |idx: usize| {
+        let words: usize = refs[..idx].iter().map(|(_, fields)| 3 + fields.len()).sum();
+        heap.heap_base_offset() + words * WORD_SIZE
+    };
+
+    GC::MarkCompact.run(heap.clone());
+
+    {
+        let heap = heap.heap();
+
+        // Space of the dead object before the large object is filled
+        assert_eq!(read_word(&heap, object_offset(0)), TAG_FREE_SPACE);
+
+        // Large object is not moved
+        let offset = object_offset(1);
+        assert_eq!(read_word(&heap, offset), TAG_ARRAY);
+        assert_eq!(
+            get_scalar_value(read_word(&heap, offset + 2 * WORD_SIZE)),
+            1
+        );
+
+        // Live small object after the large object is moved over the dead one
+        let offset = object_offset(2);
+        assert_eq!(read_word(&heap, offset), TAG_ARRAY);
+        assert_eq!(
+            get_scalar_value(read_word(&heap, offset + 2 * WORD_SIZE)),
+            3
+        );
+    }
+
+    // Pointers to and from the large object are updated
+    let heap_objects = read_heap_objects(&heap);
+    let expected_objects: FxHashMap<ObjectIdx, Vec<ObjectIdx>> =
+        refs.into_iter().filter(|(idx, _)| *idx % 2 == 1).collect();
+    assert_eq!(heap_objects.objects, expected_objects);
+    assert_eq!(heap_objects.continuation_table, continuation_table);
+}
+
+/// A weak reference to an object that is not otherwise reachable should be cleared
+fn test_weak_ref_cleared(gc: GC) {
+    // Object 0 is replaced with a weak reference to object 1
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = (0..3).map(|idx| (idx, vec![])).collect();
+    let roots = vec![0, 2];
+
+    let heap = MotokoHeap::new(&refs, &roots, &[], gc);
+    write_weak_ref(&heap, 0, 1);
+
+    gc.run(heap.clone());
+
+    // Weak reference is first in the heap, so it's not moved
+    let offset = heap.heap_base_offset();
+    let heap = heap.heap();
+    assert_eq!(read_word(&heap, offset), TAG_WEAK_REF);
+    assert_eq!(
+        read_word(&heap, offset + WORD_SIZE),
+        WEAK_REF_CLEARED.get_raw(),
+        "{:?}",
+        gc
+    );
+}
+
+/// A weak reference to a reachable object should be updated when the object is moved
+fn test_weak_ref_kept(gc: GC) {
+    // Objects 1 and 4 are replaced with weak references to object 3. Objects 0 and 2 are dead, so
+    // after GC the objects are 1, 3, 4, in this order.
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = (0..5).map(|idx| (idx, vec![])).collect();
+    let roots = vec![1, 3, 4];
+
+    let heap = MotokoHeap::new(&refs, &roots, &[], gc);
+    write_weak_ref(&heap, 1, 3);
+    write_weak_ref(&heap, 4, 3);
+
+    gc.run(heap.clone());
+
+    let object_offset = |idx: usize| heap.heap_base_offset() + idx * 3 * WORD_SIZE;
+    let target_address = heap.heap_base_address() + 3 * WORD_SIZE;
+
+    let heap = heap.heap();
+    assert_eq!(
+        get_scalar_value(read_word(&heap, object_offset(1) + 2 * WORD_SIZE)),
+        3
+    );
+
+    for weak_ref_idx in [0, 2] {
+        let offset = object_offset(weak_ref_idx);
+        assert_eq!(read_word(&heap, offset), TAG_WEAK_REF);
+        assert_eq!(
+            read_word(&heap, offset + WORD_SIZE),
+            make_pointer(target_address as u32),
+            "{:?}",
+            gc
+        );
+    }
+}
+
+/// Replace object `idx` in a heap of objects without fields with a weak reference to object
+/// `target_idx`. Objects without fields are 3 words, same as weak references.
+fn write_weak_ref(heap: &MotokoHeap, idx: usize, target_idx: usize) {
+    let object_address = |idx: usize| heap.heap_base_address() + idx * 3 * WORD_SIZE;
+
+    unsafe {
+        let weak_ref = object_address(idx) as *mut WeakRef;
+        (*weak_ref).header.tag = TAG_WEAK_REF;
+        (*weak_ref).target = Value::from_ptr(object_address(target_idx));
+        (*weak_ref).next = null_mut();
+    }
+}
+
+/// Frozen arrays should be traced and moved like other arrays, and stay frozen
+fn test_frozen_array(gc: GC) {
+    // Object 1 is frozen and points to object 3. Objects 0 and 2 are dead.
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = vec![
+        (0, vec![]),
+        (1, vec![3]),
+        (2, vec![]),
+        (3, vec![]),
+        (4, vec![]),
+    ];
+    let roots = vec![1, 4];
+
+    let heap = MotokoHeap::new(&refs, &roots, &[], gc);
+
+    // Objects without fields are 3 words
+    let frozen_address = heap.heap_base_address() + 3 * WORD_SIZE;
+    let field_address = heap.heap_base_address() + 10 * WORD_SIZE;
+    unsafe {
+        (*(frozen_address as *mut Obj)).tag = TAG_FROZEN_ARRAY;
+    }
+
+    gc.run(heap.clone());
+
+    // The frozen array is evacuated first by the copying GC, and is first in the heap after
+    // compaction
+    let frozen = Value::from_ptr(heap.heap_base_address());
+    unsafe {
+        assert_eq!(frozen.tag(), TAG_FROZEN_ARRAY, "{:?}", gc);
+        let frozen = frozen.as_array();
+        assert_eq!(frozen.len(), 2, "{:?}", gc);
+        assert_eq!(frozen.get(0).get_scalar(), 1, "{:?}", gc);
+        let field = frozen.get(1);
+        assert_ne!(field.get_ptr(), field_address, "{:?}", gc);
+        assert_eq!(field.tag(), TAG_ARRAY, "{:?}", gc);
+        assert_eq!(field.as_array().get(0).get_scalar(), 3, "{:?}", gc);
+    }
+}
+
+/// Scalars are never traced, even when their bits are the address of an object, and small
+/// `Nat`/`Int` values at the bounds of the scalar range are kept as is
+fn test_scalar_fields(gc: GC) {
+    // Object 1 points to object 3 three times, the pointers are replaced with scalars below, so
+    // object 3 is unreachable
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = vec![
+        (0, vec![]),
+        (1, vec![3, 3, 3]),
+        (2, vec![]),
+        (3, vec![]),
+        (4, vec![]),
+    ];
+    let roots = vec![1, 4];
+
+    let heap = MotokoHeap::new(&refs, &roots, &[], gc);
+
+    // Objects without fields are 3 words, object 1 is 6 words
+    let object1_address = heap.heap_base_address() + 3 * WORD_SIZE;
+    let object3_address = object1_address + 9 * WORD_SIZE;
+
+    let scalars = [
+        // Unskewed pointer to object 3: the low bit is clear, so it is a scalar
+        Value::from_raw(object3_address as u32),
+        Value::from_signed_scalar(-(1 << 30)),
+        Value::from_signed_scalar((1 << 30) - 1),
+    ];
+
+    unsafe {
+        let object1 = Value::from_ptr(object1_address).as_array();
+        for (i, scalar) in scalars.iter().enumerate() {
+            assert!(scalar.is_scalar());
+            assert_eq!(object1.get(i as u32 + 1).get_ptr(), object3_address);
+            object1.set(i as u32 + 1, *scalar);
+        }
+    }
+
+    gc.run(heap.clone());
+
+    // Collect the surviving objects with their fields
+    let mut objects: FxHashMap<u32, Vec<u32>> = Default::default();
+    let continuation_table = unsafe { *(heap.continuation_table_ptr_address() as *const Value) };
+    let mut address = heap.heap_base_address();
+    while address < heap.heap_ptr_address() {
+        let obj = Value::from_ptr(address);
+        unsafe {
+            assert_eq!(obj.tag(), TAG_ARRAY, "{:?}", gc);
+            let array = obj.as_array();
+            if obj != continuation_table {
+                let fields = (1..array.len()).map(|i| array.get(i).get_raw()).collect();
+                objects.insert(array.get(0).get_scalar(), fields);
+            }
+            address += (size_of::<Array>() + Words(array.len()))
+                .to_bytes()
+                .as_usize();
+        }
+    }
+
+    let mut live: Vec<u32> = objects.keys().copied().collect();
+    live.sort_unstable();
+    assert_eq!(live, vec![1, 4], "{:?}", gc);
+
+    let expected: Vec<u32> = scalars.iter().map(|scalar| scalar.get_raw()).collect();
+    assert_eq!(objects[&1], expected, "{:?}", gc);
+}
+
+/// Indices of unreachable finalizable objects should be added to the finalizer queue
+fn test_finalizers(gc: GC) {
+    // Objects 1, 3, 5 are replaced with finalizable objects with indices 10, 11, 12. Object 3 is
+    // reachable.
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = (0..6).map(|idx| (idx, vec![])).collect();
+    let roots = vec![0, 3];
+
+    let mut heap = MotokoHeap::new(&refs, &roots, &[], gc);
+    let heap_base = heap.heap_base_address();
+    let object_address = |idx: usize| heap_base + idx * 3 * WORD_SIZE;
+
+    unsafe {
+        let mut next = FINALIZER_LIST_END;
+        for (idx, index) in [(1, 10), (3, 11), (5, 12)] {
+            let finalizable = object_address(idx) as *mut Finalizable;
+            (*finalizable).header.tag = TAG_FINALIZABLE;
+            (*finalizable).next = next;
+            (*finalizable).index = index;
+            next = Value::from_ptr(finalizable as usize);
+        }
+        FINALIZABLES = next;
+    }
+
+    gc.run(heap.clone());
+
+    assert_eq!(take_queue(&mut heap), vec![10, 12], "{:?}", gc);
+
+    // Reachable object is still in the registry. Objects in the queue were kept alive until the
+    // queue is taken, so where the reachable object is moved depends on the GC.
+    unsafe {
+        assert_eq!(FINALIZABLES.tag(), TAG_FINALIZABLE, "{:?}", gc);
+        let finalizable = FINALIZABLES.get_ptr() as *mut Finalizable;
+        assert_eq!((*finalizable).index, 11);
+        assert!((*finalizable).next == FINALIZER_LIST_END);
+    }
+
+    // Queue is emptied when taken
+    gc.run(heap.clone());
+    assert_eq!(take_queue(&mut heap), vec![], "{:?}", gc);
+
+    unsafe {
+        FINALIZABLES = FINALIZER_LIST_END;
+        FINALIZER_QUEUE = FINALIZER_LIST_END;
+    }
+}
+
+/// Take the finalizer queue, return sorted finalizer indices
+fn take_queue(heap: &mut MotokoHeap) -> Vec<u32> {
+    unsafe {
+        let array = take_finalizer_queue(heap).as_array();
+        let mut indices: Vec<u32> = (0..array.len())
+            .map(|i| array.get(i).get_scalar())
+            .collect();
+        indices.sort_unstable();
+        indices
+    }
+}
+
+/// Objects pointed by registered roots should be kept alive, and the roots should be updated when
+/// the objects are moved
+fn test_registered_roots(gc: GC) {
+    // Object 2 is reachable only from the registered root, and keeps object 4 alive. Objects 1 and
+    // 3 are dead, so object 2 is moved by all GCs.
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = vec![
+        (0, vec![]),
+        (1, vec![]),
+        (2, vec![4]),
+        (3, vec![]),
+        (4, vec![]),
+    ];
+    let roots = vec![0];
+
+    let heap = MotokoHeap::new(&refs, &roots, &[], gc);
+
+    // Objects without fields are 3 words
+    let object_2_address = heap.heap_base_address() + 6 * WORD_SIZE;
+    let mut root = Value::from_ptr(object_2_address);
+
+    // Index of the object at the given address, and of the object its first field points to
+    let read_object = |address: usize| unsafe {
+        let idx = get_scalar_value(*((address + 2 * WORD_SIZE) as *const u32));
+        let pointee = unskew_pointer(*((address + 3 * WORD_SIZE) as *const u32)) as usize;
+        let pointee_idx = get_scalar_value(*((pointee + 2 * WORD_SIZE) as *const u32));
+        (idx, pointee_idx)
+    };
+
+    unsafe {
+        // Registering twice registers once, otherwise the location would be updated twice
+        register_root(&mut root);
+        register_root(&mut root);
+        assert!(is_root(&mut root));
+    }
+
+    gc.run(heap.clone());
+
+    assert_ne!(root.get_ptr(), object_2_address, "{:?}", gc);
+    assert_eq!(read_object(root.get_ptr()), (2, 4), "{:?}", gc);
+
+    let heap_ptr_offset = heap.heap_ptr_offset();
+
+    unsafe {
+        unregister_root(&mut root);
+        assert!(!is_root(&mut root));
+    }
+
+    // Objects 2 and 4 are collected once the root is unregistered
+    gc.run(heap.clone());
+    assert!(heap.heap_ptr_offset() < heap_ptr_offset, "{:?}", gc);
+}
+
+fn test_root_groups(gc: GC) {
+    // Object 2 is reachable only from the root of group 1, object 3 only from the root of group 2.
+    // Both point to object 1, shared by the groups.
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> =
+        vec![(0, vec![]), (1, vec![]), (2, vec![1]), (3, vec![1])];
+    let roots = vec![0];
+
+    let heap = MotokoHeap::new(&refs, &roots, &[], gc);
+
+    // Objects without fields are 3 words, objects with one field 4 words
+    let object_2_address = heap.heap_base_address() + 6 * WORD_SIZE;
+    let object_3_address = object_2_address + 4 * WORD_SIZE;
+    let mut root_1 = Value::from_ptr(object_2_address);
+    let mut root_2 = Value::from_ptr(object_3_address);
+
+    // Index of the object at the given address, and of the object its first field points to
+    let read_object = |address: usize| unsafe {
+        let idx = get_scalar_value(*((address + 2 * WORD_SIZE) as *const u32));
+        let pointee = unskew_pointer(*((address + 3 * WORD_SIZE) as *const u32)) as usize;
+        let pointee_idx = get_scalar_value(*((pointee + 2 * WORD_SIZE) as *const u32));
+        (idx, pointee_idx)
+    };
+
+    unsafe {
+        register_root_in_group(&mut root_1, 1);
+        register_root_in_group(&mut root_2, 2);
+        assert_eq!(root_group_size(1), 1);
+        assert!(traps_with("root registered in another group", || {
+            register_root_in_group(&mut root_1, 2)
+        }));
+    }
+
+    gc.run(heap.clone());
+    assert_eq!(read_object(root_1.get_ptr()), (2, 1), "{:?}", gc);
+    assert_eq!(read_object(root_2.get_ptr()), (3, 1), "{:?}", gc);
+    let heap_ptr_offset = heap.heap_ptr_offset();
+
+    // Object 2 is collected once group 1 is dropped. Object 1 is still reachable from group 2.
+    unsafe {
+        assert_eq!(drop_root_group(1), 1);
+        assert!(!is_root(&mut root_1));
+        assert!(is_root(&mut root_2));
+    }
+    gc.run(heap.clone());
+    assert_eq!(
+        heap.heap_ptr_offset(),
+        heap_ptr_offset - 4 * WORD_SIZE,
+        "{:?}",
+        gc
+    );
+    assert_eq!(read_object(root_2.get_ptr()), (3, 1), "{:?}", gc);
+
+    // Objects 1 and 3 are collected once group 2 is dropped
+    unsafe {
+        assert_eq!(drop_root_group(2), 1);
+        assert_eq!(drop_root_group(2), 0);
+        assert!(!is_root(&mut root_2));
+    }
+    gc.run(heap.clone());
+    assert_eq!(
+        heap.heap_ptr_offset(),
+        heap_ptr_offset - 11 * WORD_SIZE,
+        "{:?}",
+        gc
+    );
+}
+
+/// With the `zero-on-free` feature, data of dead objects should not remain in the heap after GC
+fn test_zero_on_free(gc: GC) {
+    const SENTINEL: u32 = 0xdead_bee0;
+
+    // Objects 1 and 3 are dead
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = vec![
+        (0, vec![2]),
+        (1, vec![0, 0, 0]),
+        (2, vec![4]),
+        (3, vec![0, 0]),
+        (4, vec![]),
+    ];
+    let roots = vec![0];
+
+    let heap = MotokoHeap::new(&refs, &roots, &[], gc);
+
+    let heap_ptr_offset_before = heap.heap_ptr_offset();
+
+    // Overwrite the fields of the dead objects, including the object index
+    let mut address = heap.heap_base_address();
+    for (idx, fields) in &refs {
+        let size = 3 + fields.len();
+        if *idx % 2 == 1 {
+            for i in 2..size {
+                unsafe { *((address + i * WORD_SIZE) as *mut u32) = SENTINEL };
+            }
+        }
+        address += size * WORD_SIZE;
+    }
+
+    gc.run(heap.clone());
+
+    let heap_ptr_offset_after = heap.heap_ptr_offset();
+    assert!(heap_ptr_offset_after < heap_ptr_offset_before, "{:?}", gc);
+
+    let heap = heap.heap();
+
+    // Reclaimed space is zeroed
+    for offset in (heap_ptr_offset_after..heap_ptr_offset_before).step_by(WORD_SIZE) {
+        assert_eq!(read_word(&heap, offset), 0, "{:?}", gc);
+    }
+
+    // No data of dead objects is left in the heap, e.g. in filler objects
+    for offset in (0..heap_ptr_offset_before).step_by(WORD_SIZE) {
+        assert_ne!(read_word(&heap, offset), SENTINEL, "{:?}", gc);
+    }
+}
+
+/// Mark-compact GCs should not move pinned objects, and should keep them and the objects reachable
+/// from them alive. Unpinned objects can be moved.
+fn test_pinned_objects(gc: GC) {
+    // Object 1 is reachable from a root, object 2 only from the pin table, object 3 from both.
+    // Dead objects before them make them move when not pinned.
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = vec![
+        (0, vec![]),
+        (1, vec![3]),
+        (2, vec![3]),
+        (3, vec![]),
+        (4, vec![]),
+    ];
+    let roots = vec![1];
+
+    // Incremental GC heap has the most space to allocate, for the pin table
+    let mut heap = MotokoHeap::new(&refs, &roots, &[], GC::IncrementalMarkCompact);
+
+    let heap_base = heap.heap_base_address();
+    let object_address = |idx: usize| {
+        let words: usize = refs[..idx].iter().map(|(_, fields)| 3 + fields.len()).sum();
+        heap_base + words * WORD_SIZE
+    };
+
+    // Index and pointees of the object at the given address
+    let read_object = |heap: &MotokoHeap, address: usize| -> (ObjectIdx, Vec<ObjectIdx>) {
+        let heap = heap.heap();
+        let offset = address - heap.as_ptr() as usize;
+        assert_eq!(read_word(&heap, offset), TAG_ARRAY);
+        let len = read_word(&heap, offset + WORD_SIZE) as usize;
+        let idx = get_scalar_value(read_word(&heap, offset + 2 * WORD_SIZE));
+        let pointees = (1..len)
+            .map(|i| {
+                let pointee = unskew_pointer(read_word(&heap, offset + (2 + i) * WORD_SIZE));
+                let pointee_offset = pointee as usize - heap.as_ptr() as usize;
+                get_scalar_value(read_word(&heap, pointee_offset + 2 * WORD_SIZE))
+            })
+            .collect();
+        (idx, pointees)
+    };
+
+    let pinned_addresses = [object_address(1), object_address(2)];
+
+    let handles: Vec<u32> = pinned_addresses
+        .iter()
+        .map(|address| unsafe { pin(&mut heap, Value::from_ptr(*address)) })
+        .collect();
+
+    gc.run(heap.clone());
+
+    // Pinned objects are not moved, and object 3 is kept alive by them
+    assert_eq!(
+        read_object(&heap, pinned_addresses[0]),
+        (1, vec![3]),
+        "{:?}",
+        gc
+    );
+    assert_eq!(
+        read_object(&heap, pinned_addresses[1]),
+        (2, vec![3]),
+        "{:?}",
+        gc
+    );
+
+    for handle in handles {
+        unsafe { unpin(handle) };
+    }
+
+    gc.run(heap.clone());
+
+    // Unpinned object 1 is moved over the dead object 0, object 2 is collected
+    assert_eq!(read_object(&heap, heap_base), (1, vec![3]), "{:?}", gc);
+
+    unsafe {
+        free_pin_table();
+    }
+}
+
+/// Mark-compact GC should note the instructions each phase took, once per phase
+fn test_gc_phase_times() {
+    static mut COUNTER: u64 = 0;
+
+    // Mock instruction counter, each phase takes 100 instructions
+    fn mock_instruction_counter() -> u64 {
+        unsafe {
+            COUNTER += 100;
+            COUNTER
+        }
+    }
+
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = vec![(0, vec![0]), (1, vec![])];
+    let mut heap = MotokoHeap::new(&refs, &[0], &[], GC::MarkCompact);
+
+    let heap_base = heap.heap_base_address() as u32;
+    let static_roots = Value::from_ptr(heap.static_root_array_address());
+    let continuation_table_ptr_address = heap.continuation_table_ptr_address() as *mut Value;
+
+    let heap_1 = heap.clone();
+    let heap_2 = heap.clone();
+
+    let phase_times: RefCell<Vec<(GcPhase, u64)>> = RefCell::new(vec![]);
+
+    unsafe {
+        let instruction_counter = INSTRUCTION_COUNTER;
+        INSTRUCTION_COUNTER = mock_instruction_counter;
+
+        compacting_gc_internal(
+            &mut heap,
+            GcContext {
+                heap_base,
+                get_hp: || heap_1.heap_ptr_address(),
+                set_hp: move |hp| heap_2.set_heap_ptr_address(hp as usize),
+                static_roots,
+                continuation_table_ptr_loc: continuation_table_ptr_address,
+            },
+            // note_live_size
+            |_| {},
+            // note_reclaimed
+            |_| {},
+            // note_phase_time
+            |phase, instructions| phase_times.borrow_mut().push((phase, instructions)),
+        );
+
+        INSTRUCTION_COUNTER = instruction_counter;
+    }
+
+    assert_eq!(
+        phase_times.into_inner(),
+        vec![
+            (GcPhase::Mark, 100),
+            (GcPhase::UpdateRefs, 100),
+            (GcPhase::FreeBitmap, 100)
+        ]
+    );
+}
+
+/// A mark-compact GC interrupted after marking leaves a threaded heap, which the next GC repairs
+fn test_interrupted_gc() {
+    // Backwards pointers, a self pointer, and pointers from roots and the continuation table are
+    // threaded in marking. Object 3 is dead.
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> =
+        vec![(0, vec![1, 2]), (1, vec![0]), (2, vec![2, 1]), (3, vec![0])];
+    let roots = vec![0, 2];
+    let continuation_table = vec![1];
+
+    let mut heap = MotokoHeap::new(&refs, &roots, &continuation_table, GC::MarkCompact);
+
+    let heap_base = heap.heap_base_address() as u32;
+    let static_roots = Value::from_ptr(heap.static_root_array_address());
+    let continuation_table_ptr_address = heap.continuation_table_ptr_address() as *mut Value;
+
+    let heap_1 = heap.clone();
+    let heap_2 = heap.clone();
+    let heap_3 = heap.clone();
+
+    // Interrupt the GC at the end of marking. The panic stands for a trap.
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+        compacting_gc_internal(
+            &mut heap,
+            GcContext {
+                heap_base,
+                get_hp: || heap_1.heap_ptr_address(),
+                set_hp: move |hp| heap_2.set_heap_ptr_address(hp as usize),
+                static_roots,
+                continuation_table_ptr_loc: continuation_table_ptr_address,
+            },
+            // note_live_size
+            |_| {},
+            // note_reclaimed
+            |_| {},
+            // note_phase_time
+            |phase, _| {
+                if phase == GcPhase::Mark {
+                    panic!("interrupted");
+                }
+            },
+        );
+    }));
+    std::panic::set_hook(hook);
+    assert!(result.is_err());
+
+    unsafe {
+        assert!(GC_PROGRESS == GcProgress::Marking);
+        assert!(recover_interrupted_gc(
+            |hp| heap_3.set_heap_ptr_address(hp as usize)
+        ));
+        assert!(GC_PROGRESS == GcProgress::Idle);
+        assert!(!recover_interrupted_gc(|_| panic!(
+            "heap is not repaired twice"
+        )));
+    }
+
+    // The graph is restored, with the dead object
+    check_dynamic_heap(
+        false, // before gc
+        &refs,
+        &roots,
+        &continuation_table,
+        &**heap.heap(),
+        heap.heap_base_offset(),
+        heap.heap_ptr_offset(),
+        heap.continuation_table_ptr_offset(),
+    );
+
+    // The next GC works as usual
+    GC::MarkCompact.run(heap.clone());
+    check_dynamic_heap(
+        true, // after gc
+        &refs,
+        &roots,
+        &continuation_table,
+        &**heap.heap(),
+        heap.heap_base_offset(),
+        heap.heap_ptr_offset(),
+        heap.continuation_table_ptr_offset(),
+    );
+
+    // A GC interrupted in compaction moved objects, the heap cannot be repaired
+    unsafe {
+        GC_PROGRESS = GcProgress::Compacting;
+        assert!(traps_with("heap cannot be repaired", || {
+            recover_interrupted_gc(|_| {});
+        }));
+        GC_PROGRESS = GcProgress::Idle;
+    }
+}
+
+/// Mark-compact GC should bin live objects by size, and not count dead objects
+fn test_size_histogram() {
+    // Small objects of 3 words (header, index, no fields) and large arrays of 1003 words. Half of
+    // the objects of each kind are dead.
+    let n_small = 20;
+    let n_large = 4;
+    let large_fields = 1000;
+
+    let mut refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = vec![];
+    for idx in 0..n_small {
+        refs.push((idx, vec![]));
+    }
+    for idx in n_small..n_small + n_large {
+        refs.push((idx, vec![0; large_fields]));
+    }
+
+    let roots: Vec<ObjectIdx> = refs
+        .iter()
+        .map(|(idx, _)| *idx)
+        .filter(|idx| idx % 2 == 0)
+        .collect();
+
+    let heap = MotokoHeap::new(&refs, &roots, &[], GC::MarkCompact);
+    GC::MarkCompact.run(heap);
+
+    let small_bucket = size_bucket(Words(3));
+    let large_bucket = size_bucket(Words(3 + large_fields as u32));
+    assert_eq!(small_bucket, 1);
+    assert_eq!(large_bucket, 9);
+
+    for bucket in 0..SIZE_HISTOGRAM_BUCKETS {
+        let expected = if bucket == small_bucket {
+            // Live small objects and the empty continuation table (2 words)
+            n_small / 2 + 1
+        } else if bucket == large_bucket {
+            n_large / 2
+        } else {
+            0
+        };
+        assert_eq!(unsafe { size_histogram_bucket(bucket) }, expected);
+    }
+
+    assert_eq!(unsafe { size_histogram_bucket(SIZE_HISTOGRAM_BUCKETS) }, 0);
+}
+
+/// Heap dump should list the objects reachable from the roots with their pointees, and leave the
+/// heap unchanged
+fn test_heap_dump(heap_descr: &TestHeap) {
+    let heap = MotokoHeap::new(
+        &heap_descr.heap,
+        &heap_descr.roots,
+        &heap_descr.continuation_table,
+        GC::MarkCompact,
+    );
+
+    let heap_ptr_offset = heap.heap_ptr_offset();
+    let heap_before = heap.heap()[..heap_ptr_offset].to_vec();
+
+    let dump = dump_heap(heap.clone());
+
+    let heap_ptr_address = heap.heap_ptr_address();
+    let heap_base_address = heap.heap_base_address();
+    let heap = heap.heap();
+    assert_eq!(heap_ptr_address, heap.as_ptr() as usize + heap_ptr_offset);
+    assert_eq!(&heap[..heap_ptr_offset], &heap_before[..]);
+
+    // Index of the object at the given address
+    let object_idx = |address: u32| {
+        let offset = address as usize - heap.as_ptr() as usize;
+        get_scalar_value(read_word(&heap, offset + 2 * WORD_SIZE)) // skip header + length
+    };
+
+    let mut words = dump.into_iter();
+    let mut next = || words.next().unwrap();
+
+    assert_eq!(next(), HEAP_DUMP_MAGIC);
+    assert_eq!(next(), HEAP_DUMP_VERSION);
+    assert_eq!(next(), heap_base_address as u32);
+
+    let n_roots = next();
+    let mut roots: Vec<ObjectIdx> = (0..n_roots).map(|_| object_idx(next())).collect();
+    let mut expected_roots = heap_descr.roots.clone();
+    roots.sort_unstable();
+    expected_roots.sort_unstable();
+    assert_eq!(roots, expected_roots);
+
+    let continuation_table_address = next();
+    assert_ne!(continuation_table_address, 0);
+
+    let mut objects: FxHashMap<ObjectIdx, Vec<ObjectIdx>> = Default::default();
+    let mut continuation_table = None;
+    let mut last_address = 0;
+    loop {
+        let address = next();
+        if address == 0 {
+            break;
+        }
+
+        // Objects are in address order
+        assert!(address > last_address);
+        last_address = address;
+
+        let tag = next();
+        let size = next();
+        let n_fields = next();
+        let fields: Vec<u32> = (0..n_fields).map(|_| next()).collect();
+
+        assert_eq!(tag, TAG_ARRAY);
+
+        if address == continuation_table_address {
+            assert_eq!(size, size_of::<Array>().as_u32() + n_fields);
+            continuation_table = Some(fields.into_iter().map(object_idx).collect::<Vec<_>>());
+        } else {
+            // First field is the object index, which is not a pointer
+            assert_eq!(size, size_of::<Array>().as_u32() + 1 + n_fields);
+            let pointees = fields.into_iter().map(object_idx).collect();
+            assert!(objects.insert(object_idx(address), pointees).is_none());
+        }
+    }
+
+    assert_eq!(
+        continuation_table.as_deref(),
+        Some(&heap_descr.continuation_table[..])
+    );
+
+    let heap_map: FxHashMap<ObjectIdx, &[ObjectIdx]> = heap_descr
+        .heap
+        .iter()
+        .map(|(idx, pointees)| (*idx, &pointees[..]))
+        .collect();
+
+    let reachable =
+        compute_reachable_objects(&heap_descr.roots, &heap_descr.continuation_table, &heap_map);
+
+    let expected_objects: FxHashMap<ObjectIdx, Vec<ObjectIdx>> = heap_descr
+        .heap
+        .iter()
+        .filter(|(idx, _)| reachable.contains(idx))
+        .cloned()
+        .collect();
+
+    assert_eq!(objects, expected_objects);
+}
 
-use motoko_rts::gc::copying::copying_gc_internal;
-use motoko_rts::gc::mark_compact::compacting_gc_internal;
-use motoko_rts::types::*;
+/// The dump uses the mark bitmap, so it traps while an incremental GC is marking
+fn test_heap_dump_during_incremental_gc() {
+    let heap_descr = &test_heaps()[0];
+    let heap = MotokoHeap::new(
+        &heap_descr.heap,
+        &heap_descr.roots,
+        &heap_descr.continuation_table,
+        GC::MarkCompact,
+    );
 
-use std::fmt::Write;
+    unsafe {
+        PHASE = Phase::Marking;
+        assert!(traps_with("heap_dump: incremental GC in progress", || {
+            dump_heap(heap);
+        }));
+        PHASE = Phase::Idle;
+    }
+}
 
-use fxhash::{FxHashMap, FxHashSet};
+/// Dump the heap to a stream with a small cache, return the dump as words
+fn dump_heap(mut heap: MotokoHeap) -> Vec<u32> {
+    static mut DUMP: Vec<u8> = Vec::new();
 
-pub fn test() {
-    println!("Testing garbage collection ...");
+    fn collect(_stream: *mut Stream, ptr: *const u8, n: Bytes<u32>) {
+        unsafe {
+            let bytes = std::slice::from_raw_parts(ptr, n.as_usize());
+            (*std::ptr::addr_of_mut!(DUMP)).extend_from_slice(bytes);
+        }
+    }
 
-    println!("  Testing pre-defined heaps...");
-    for test_heap in test_heaps() {
-        test_gcs(&test_heap);
+    let mut stream_mem = TestMemory::new(Words(100));
+
+    let heap_base = heap.heap_base_address() as u32;
+    let static_roots = Value::from_ptr(heap.static_root_array_address());
+    let continuation_table_ptr_address = heap.continuation_table_ptr_address() as *mut Value;
+
+    let heap_1 = heap.clone();
+    let heap_2 = heap.clone();
+
+    unsafe {
+        let stream = alloc_stream(&mut stream_mem, Bytes(64));
+        (*stream).outputter = collect;
+
+        let mut dump_size = None;
+        heap_dump_internal(
+            &mut heap,
+            GcContext {
+                heap_base,
+                get_hp: || heap_1.heap_ptr_address(),
+                set_hp: move |hp| heap_2.set_heap_ptr_address(hp as usize),
+                static_roots,
+                continuation_table_ptr_loc: continuation_table_ptr_address,
+            },
+            stream,
+            // check_size, called before writing
+            |size| {
+                assert!((*std::ptr::addr_of!(DUMP)).is_empty());
+                dump_size = Some(size.as_usize());
+            },
+        );
+
+        let dump = std::mem::take(&mut *std::ptr::addr_of_mut!(DUMP));
+        assert_eq!(Some(dump.len()), dump_size);
+        assert_eq!(dump.len() % 4, 0);
+        dump.chunks(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect()
     }
+}
 
-    println!("  Testing random heaps...");
-    let max_seed = 100;
-    for seed in 0..max_seed {
-        print!("\r{}/{}", seed + 1, max_seed);
-        std::io::Write::flush(&mut std::io::stdout()).unwrap();
-        test_random_heap(seed, 180);
+/// Threading fields pointing to an object should build a chain of field addresses in the object
+/// header, and unthreading should update the fields and restore the header
+fn test_thread_unthread() {
+    for tag in (TAG_OBJECT..=MAX_TAG).step_by(2) {
+        // Object header in word 0, fields pointing to the object in the rest
+        let mut words: Vec<u32> = vec![tag; 5];
+        let obj = words.as_mut_ptr() as *mut Obj;
+        let fields: Vec<*mut Value> = (1..words.len())
+            .map(|i| unsafe { words.as_mut_ptr().add(i) } as *mut Value)
+            .collect();
+
+        unsafe {
+            for field in &fields {
+                **field = Value::from_ptr(obj as usize);
+                thread(*field);
+                assert!(!is_tag((*obj).tag));
+            }
+
+            // Last threaded field is the head of the chain, first one holds the tag
+            assert_eq!((*obj).tag, *fields.last().unwrap() as u32);
+            assert_eq!((*fields[0]).get_raw(), tag);
+
+            let new_loc = 0x1000;
+            unthread(obj, new_loc);
+
+            assert_eq!((*obj).tag, tag);
+            for field in &fields {
+                assert_eq!((**field).get_ptr(), new_loc as usize);
+            }
+        }
+    }
+}
+
+/// Mark-compact GC should report fragmentation of a heap with live and dead objects interleaved
+fn test_heap_fragmentation() {
+    // Every other object is live. Objects are 3 words each.
+    let refs: Vec<(ObjectIdx, Vec<ObjectIdx>)> = (0..10).map(|idx| (idx, vec![])).collect();
+    let roots: Vec<ObjectIdx> = (0..10).step_by(2).collect();
+
+    let heap = MotokoHeap::new(&refs, &roots, &[], GC::MarkCompact);
+    GC::MarkCompact.run(heap);
+
+    // 5 dead objects, in a heap of 10 objects and the continuation table (2 words)
+    let dead_words = 5 * 3;
+    let heap_words = 10 * 3 + 2;
+
+    assert_eq!(
+        unsafe { heap_fragmentation() },
+        f64::from(dead_words) / f64::from(heap_words)
+    );
+
+    // Dead objects after the last live object are reclaimed without compaction, so they are not
+    // fragmentation
+    unsafe {
+        let mut mem = TestMemory::new(Words(1024));
+        let (roots, heap_base) = static_roots(&mut mem, 3);
+        *root_field(roots, 0) = alloc_array(&mut mem, 1);
+        alloc_array(&mut mem, 1);
+        *root_field(roots, 1) = alloc_array(&mut mem, 1);
+        *root_field(roots, 2) = alloc_array(&mut mem, 1);
+        for _ in 0..10 {
+            alloc_array(&mut mem, 1);
+        }
+
+        collect(&mut mem, heap_base, roots);
+
+        // One dead object in the 4 objects up to the last live object
+        assert_eq!(heap_fragmentation(), 1.0 / 4.0);
+    }
+}
+
+/// The estimate leaves the heap as it was, and is what a compacting GC reclaims
+fn test_estimate_reclaimable(heap_descr: &TestHeap) {
+    let mut heap = MotokoHeap::new(
+        &heap_descr.heap,
+        &heap_descr.roots,
+        &heap_descr.continuation_table,
+        GC::MarkCompact,
+    );
+
+    let heap_base = heap.heap_base_address() as u32;
+    let static_roots = Value::from_ptr(heap.static_root_array_address());
+    let continuation_table_ptr_address = heap.continuation_table_ptr_address() as *mut Value;
+
+    // Space after the heap pointer is only zeroed with the `zero-on-free` feature
+    let heap_ptr_offset = heap.heap_ptr_offset();
+    let heap_before = heap.heap()[..heap_ptr_offset].to_vec();
+    let heap_ptr_before = heap.heap_ptr_address();
+
+    let heap_1 = heap.clone();
+    let heap_2 = heap.clone();
+    let estimate = unsafe {
+        estimate_reclaimable_internal(
+            &mut heap,
+            heap_base,
+            // get_hp
+            || heap_1.heap_ptr_address(),
+            // set_hp
+            move |hp| heap_2.set_heap_ptr_address(hp as usize),
+            static_roots,
+            continuation_table_ptr_address,
+        )
+    };
+
+    assert_eq!(heap.heap_ptr_address(), heap_ptr_before);
+    assert!(heap.heap()[..heap_ptr_offset] == heap_before[..]);
+    if cfg!(feature = "zero-on-free") {
+        assert!(heap.heap()[heap_ptr_offset..].iter().all(|byte| *byte == 0));
+    }
+
+    let stats = GC::MarkCompact.run(heap);
+    assert_eq!(estimate, stats.reclaimed);
+}
+
+/// Changing GC strategy while an incremental GC is in progress should take effect after the
+/// incremental GC is done
+fn test_gc_strategy_switch() {
+    let mut heap = MotokoHeap::new(
+        &[(0, vec![1]), (1, vec![0]), (2, vec![])],
+        &[0],
+        &[],
+        GC::IncrementalMarkCompact,
+    );
+
+    let live = Cell::new(Bytes(0));
+    let reclaimed = Cell::new(Bytes(0));
+
+    unsafe {
+        assert_eq!(GC_STRATEGY, Strategy::MarkCompact);
+
+        assert!(!incremental_gc_slice(
+            &mut heap,
+            Words(1),
+            &live,
+            &reclaimed
+        ));
+
+        set_gc_strategy(Strategy::Copying as u32);
+        assert_eq!(GC_STRATEGY, Strategy::MarkCompact);
+
+        while !incremental_gc_slice(&mut heap, Words(1), &live, &reclaimed) {}
+        assert_eq!(GC_STRATEGY, Strategy::Copying);
+
+        // No GC in progress, takes effect immediately
+        set_gc_strategy(Strategy::MarkCompact as u32);
+        assert_eq!(GC_STRATEGY, Strategy::MarkCompact);
+
+        // Copying GC would invalidate the remembered set
+        BARRIER_MODE = BarrierMode::RememberedSet;
+        assert!(traps_with("does not support generational GC", || {
+            set_gc_strategy(Strategy::Copying as u32)
+        }));
+        BARRIER_MODE = BarrierMode::Disabled;
+        assert_eq!(GC_STRATEGY, Strategy::MarkCompact);
+    }
+}
+
+/// Minor GC with an old generation: old objects are not collected, and young objects only
+/// reachable from old objects are kept alive by the remembered set.
+fn test_minor_gc() {
+    // Objects 0 and 1 are old. Object 1 is garbage, but it's not collected in minor GC. Object 2
+    // is only reachable from the old object 0. Object 5 is young garbage.
+    let refs = vec![
+        (0, vec![2]),
+        (1, vec![]),
+        (2, vec![3]),
+        (3, vec![]),
+        (4, vec![]),
+        (5, vec![]),
+    ];
+    let roots = vec![0];
+    let continuation_table = vec![4];
+
+    let mut heap = MotokoHeap::new(&refs, &roots, &continuation_table, GC::Generational);
+
+    let heap_base = heap.heap_base_address() as u32;
+    // Object 0 has 4 words, object 1 has 3 words
+    let young_base = heap_base + 7 * WORD_SIZE as u32;
+
+    let live = Cell::new(Bytes(0));
+    let reclaimed = Cell::new(Bytes(0));
+
+    unsafe {
+        let obj0_field = (heap_base as *mut Array).payload_addr().add(1);
+
+        // Adding the same field twice should not thread it twice in GC
+        remember_field(&mut heap, heap_base, young_base, obj0_field);
+        remember_field(&mut heap, heap_base, young_base, obj0_field);
+
+        let heap_1 = heap.clone();
+        let heap_2 = heap.clone();
+
+        let hp_before = heap.heap_ptr_address() as u32;
+        let static_roots = Value::from_ptr(heap.static_root_array_address());
+        let continuation_table_ptr_address = heap.continuation_table_ptr_address() as *mut Value;
+
+        compacting_gc_minor_internal(
+            &mut heap,
+            GcContext {
+                heap_base,
+                get_hp: || heap_1.heap_ptr_address(),
+                set_hp: move |hp| heap_2.set_heap_ptr_address(hp as usize),
+                static_roots,
+                continuation_table_ptr_loc: continuation_table_ptr_address,
+            },
+            young_base,
+            // note_live_size
+            |live_size| live.set(live_size),
+            // note_reclaimed
+            |reclaimed_size| reclaimed.set(reclaimed_size),
+        );
+
+        let hp_after = heap.heap_ptr_address() as u32;
+        assert_eq!(reclaimed.get(), Bytes(hp_before - hp_after));
+    }
+
+    check_dynamic_heap(
+        false, // old garbage is not collected, don't check for unreachable objects
+        &refs,
+        &roots,
+        &continuation_table,
+        &**heap.heap(),
+        heap.heap_base_offset(),
+        heap.heap_ptr_offset(),
+        heap.continuation_table_ptr_offset(),
+    );
+
+    // Objects 0 to 4 and the continuation table are left in the heap
+    let live_words = 4 + 3 + 4 + 3 + 3 + 3;
+    assert_eq!(live.get(), Bytes(live_words * WORD_SIZE as u32));
+    assert_eq!(
+        heap.heap_ptr_offset() - heap.heap_base_offset(),
+        live_words as usize * WORD_SIZE
+    );
+}
+
+/// Check that `write_barrier_internal` stores the value, and marks the old value or remembers the
+/// field depending on the mode
+fn test_write_barrier() {
+    // Objects 0 and 1 are old, objects 2 and 3 are young
+    let refs = vec![(0, vec![1]), (1, vec![]), (2, vec![1]), (3, vec![])];
+    let roots = vec![0, 2, 3];
+
+    let obj_addr = |heap: &MotokoHeap, idx: usize| {
+        // Objects are laid out in index order, and each one is an array with `3 + n_refs` words
+        let offset: usize = refs[..idx].iter().map(|(_, fields)| 3 + fields.len()).sum();
+        (heap.heap_base_address() + offset * WORD_SIZE) as u32
+    };
+    let obj_field = |heap: &MotokoHeap, idx: usize| unsafe {
+        (obj_addr(heap, idx) as *mut Array).payload_addr().add(1)
+    };
+
+    unsafe {
+        REMEMBERED_SET = None;
+
+        // Disabled: only the store
+        BARRIER_MODE = BarrierMode::Disabled;
+        let mut heap = MotokoHeap::new(&refs, &roots, &[], GC::Generational);
+        let heap_base = heap.heap_base_address() as u32;
+        let young_base = obj_addr(&heap, 2);
+        let obj0_field = obj_field(&heap, 0);
+        let obj3 = Value::from_ptr(obj_addr(&heap, 3) as usize);
+        write_barrier_internal(&mut heap, heap_base, young_base, obj0_field, obj3);
+        assert!(*obj0_field == obj3);
+        assert!(REMEMBERED_SET.is_none());
+
+        // Remembered set: only old fields pointing to young objects are remembered
+        BARRIER_MODE = BarrierMode::RememberedSet;
+        let mut heap = MotokoHeap::new(&refs, &roots, &[], GC::Generational);
+        let heap_base = heap.heap_base_address() as u32;
+        let young_base = obj_addr(&heap, 2);
+        let obj0_field = obj_field(&heap, 0);
+        let obj2_field = obj_field(&heap, 2);
+        let obj1 = Value::from_ptr(obj_addr(&heap, 1) as usize);
+        let obj3 = Value::from_ptr(obj_addr(&heap, 3) as usize);
+
+        write_barrier_internal(&mut heap, heap_base, young_base, obj0_field, obj1);
+        assert!(REMEMBERED_SET.is_none());
+
+        write_barrier_internal(&mut heap, heap_base, young_base, obj2_field, obj3);
+        assert!(REMEMBERED_SET.is_none());
+
+        write_barrier_internal(&mut heap, heap_base, young_base, obj0_field, obj3);
+        assert!(*obj0_field == obj3);
+        let remembered_set = REMEMBERED_SET.as_ref().unwrap();
+        assert_eq!(remembered_set.len(), 1);
+        assert!(remembered_set.contains(obj0_field));
+
+        REMEMBERED_SET = None;
+
+        // Snapshot-at-the-beginning: the old value is marked during incremental marking
+        BARRIER_MODE = BarrierMode::SnapshotAtTheBeginning;
+        let mut heap = MotokoHeap::new(&refs, &roots, &[], GC::IncrementalMarkCompact);
+        let heap_base = heap.heap_base_address() as u32;
+        let obj0_field = obj_field(&heap, 0);
+        let obj1_addr = obj_addr(&heap, 1);
+        let obj3 = Value::from_ptr(obj_addr(&heap, 3) as usize);
+
+        let live = Cell::new(Bytes(0));
+        let reclaimed = Cell::new(Bytes(0));
+
+        // Marks the roots and scans one object, object 1 is not marked yet
+        assert!(!incremental_gc_slice(
+            &mut heap,
+            Words(1),
+            &live,
+            &reclaimed
+        ));
+        assert!(!get_bit(obj1_addr / WORD_SIZE as u32));
+
+        write_barrier_internal(&mut heap, heap_base, heap_base, obj0_field, obj3);
+        assert!(*obj0_field == obj3);
+        assert!(get_bit(obj1_addr / WORD_SIZE as u32));
+        assert!(REMEMBERED_SET.is_none());
+
+        while !incremental_gc_slice(&mut heap, Words(1), &live, &reclaimed) {}
+
+        // Object 1 is in the snapshot, so it's not reclaimed in this cycle
+        assert_eq!(reclaimed.get(), Bytes(0));
+
+        // Generational GC can only be enabled with the remembered set barrier
+        BARRIER_MODE = BarrierMode::Disabled;
+        assert!(traps_with("not in remembered set mode", || {
+            enable_generational_gc()
+        }));
+        assert!(!GENERATIONAL);
+
+        BARRIER_MODE = BarrierMode::RememberedSet;
+        enable_generational_gc();
+        assert!(GENERATIONAL);
+
+        GENERATIONAL = false;
+        BARRIER_MODE = BarrierMode::Disabled;
+    }
+}
+
+/// Move a reference during incremental marking with stores through `write_barrier_internal`, like
+/// the code generated with `moc --incremental-gc`. The object survives the GC cycle.
+fn test_write_barrier_mutation() {
+    // Object 1 is only reachable from object 0. The mutator stores it in object 2, then overwrites
+    // it in object 0, so it's only reachable from object 2 at the end of marking.
+    let refs = vec![(0, vec![1]), (1, vec![]), (2, vec![2])];
+    let roots = vec![0, 2];
+
+    let mut heap = MotokoHeap::new(&refs, &roots, &[], GC::IncrementalMarkCompact);
+
+    let heap_base = heap.heap_base_address();
+    let obj_addr = |idx: usize| {
+        // Objects are laid out in index order, and each one is an array with `3 + n_refs` words
+        let offset: usize = refs[..idx].iter().map(|(_, fields)| 3 + fields.len()).sum();
+        heap_base + offset * WORD_SIZE
+    };
+
+    let live = Cell::new(Bytes(0));
+    let reclaimed = Cell::new(Bytes(0));
+
+    unsafe {
+        BARRIER_MODE = BarrierMode::SnapshotAtTheBeginning;
+    }
+
+    // Marks the roots and scans one object
+    assert!(!incremental_gc_slice(
+        &mut heap,
+        Words(1),
+        &live,
+        &reclaimed
+    ));
+
+    unsafe {
+        let obj0_field = (obj_addr(0) as *mut Array).payload_addr().add(1);
+        let obj2_field = (obj_addr(2) as *mut Array).payload_addr().add(1);
+        let obj1 = Value::from_ptr(obj_addr(1));
+        let obj2 = Value::from_ptr(obj_addr(2));
+        let heap_base = heap_base as u32;
+
+        // Object 2 field 0: 2 -> 1
+        write_barrier_internal(&mut heap, heap_base, heap_base, obj2_field, obj1);
+        // Object 0 field 0: 1 -> 2. Object 1 is not marked yet, the barrier marks it and pushes it
+        // to the mark stack.
+        let stack_ptr = STACK_PTR;
+        write_barrier_internal(&mut heap, heap_base, heap_base, obj0_field, obj2);
+        assert_eq!(STACK_PTR, stack_ptr.add(2));
+        assert_eq!(*stack_ptr, obj_addr(1));
+    }
+
+    while !incremental_gc_slice(&mut heap, Words(1), &live, &reclaimed) {}
+
+    unsafe {
+        BARRIER_MODE = BarrierMode::Disabled;
+    }
+
+    let refs = vec![(0, vec![2]), (1, vec![]), (2, vec![1])];
+
+    check_dynamic_heap(
+        true, // after gc
+        &refs,
+        &roots,
+        &[],
+        &**heap.heap(),
+        heap.heap_base_offset(),
+        heap.heap_ptr_offset(),
+        heap.continuation_table_ptr_offset(),
+    );
+
+    assert_eq!(reclaimed.get(), Bytes(0));
+}
+
+/// Copying GC moves objects in the order they are reached from the roots, mark-compact GC keeps
+/// them in address order. Check that apart from the order, both leave the same objects in the
+/// heap.
+fn test_copying_compacting_same_heap(heap_descr: &TestHeap) {
+    let heaps: Vec<HeapObjects> = [GC::Copying, GC::MarkCompact]
+        .iter()
+        .map(|gc| {
+            let heap = MotokoHeap::new(
+                &heap_descr.heap,
+                &heap_descr.roots,
+                &heap_descr.continuation_table,
+                *gc,
+            );
+            gc.run(heap.clone());
+            read_heap_objects(&heap)
+        })
+        .collect();
+
+    assert_eq!(heaps[0], heaps[1]);
+}
+
+/// Objects in a heap, as a map from object indices to indices of the objects they point to, and
+/// the indices of the objects in the continuation table
+#[derive(Debug, PartialEq, Eq)]
+struct HeapObjects {
+    objects: FxHashMap<ObjectIdx, Vec<ObjectIdx>>,
+    continuation_table: Vec<ObjectIdx>,
+}
+
+fn read_heap_objects(heap: &MotokoHeap) -> HeapObjects {
+    let heap_base_offset = heap.heap_base_offset();
+    let heap_ptr_offset = heap.heap_ptr_offset();
+    let continuation_table_ptr_offset = heap.continuation_table_ptr_offset();
+    let heap = heap.heap();
+
+    // Index of the object at the given address
+    let object_idx = |address: u32| {
+        let offset = address as usize - heap.as_ptr() as usize;
+        get_scalar_value(read_word(&heap, offset + 2 * WORD_SIZE)) // skip header + length
+    };
+
+    let read_array = |offset: usize| -> Vec<u32> {
+        assert_eq!(read_word(&heap, offset), TAG_ARRAY);
+        let len = read_word(&heap, offset + WORD_SIZE) as usize;
+        (0..len)
+            .map(|i| read_word(&heap, offset + (2 + i) * WORD_SIZE))
+            .collect()
+    };
+
+    let continuation_table_addr = unskew_pointer(read_word(&heap, continuation_table_ptr_offset));
+    let continuation_table_offset = continuation_table_addr as usize - heap.as_ptr() as usize;
+
+    let continuation_table = read_array(continuation_table_offset)
+        .iter()
+        .map(|ptr| object_idx(unskew_pointer(*ptr)))
+        .collect();
+
+    let mut objects: FxHashMap<ObjectIdx, Vec<ObjectIdx>> = Default::default();
+
+    let mut offset = heap_base_offset;
+    while offset < heap_ptr_offset {
+        let object_offset = offset;
+
+        // Skip filler objects left by sweeping
+        match read_word(&heap, object_offset) {
+            TAG_ONE_WORD_FILLER => {
+                offset += WORD_SIZE;
+                continue;
+            }
+            TAG_FREE_SPACE => {
+                offset += (1 + read_word(&heap, object_offset + WORD_SIZE) as usize) * WORD_SIZE;
+                continue;
+            }
+            _ => {}
+        }
+
+        let fields = read_array(object_offset);
+        offset += (size_of::<Array>() + Words(fields.len() as u32))
+            .to_bytes()
+            .as_usize();
+
+        if object_offset == continuation_table_offset {
+            continue;
+        }
+
+        // First field is the object index
+        let pointees = fields[1..]
+            .iter()
+            .map(|ptr| object_idx(unskew_pointer(*ptr)))
+            .collect();
+
+        objects.insert(get_scalar_value(fields[0]), pointees);
+    }
+
+    HeapObjects {
+        objects,
+        continuation_table,
     }
-    print!("\r");
 }
 
 fn test_heaps() -> Vec<TestHeap> {
@@ -69,6 +2111,41 @@ fn test_heaps() -> Vec<TestHeap> {
             roots: vec![2],
             continuation_table: vec![],
         },
+        // Live objects interleaved with garbage, so that every live object moves in compaction.
+        // Each live object has both forward and backward pointers to other moving objects, which
+        // exercises unthreading at the new location (backwards pointers) and threading after the
+        // move (forward pointers) in `update_refs`.
+        TestHeap {
+            heap: vec![
+                (0, vec![]),
+                (1, vec![3, 5]),
+                (2, vec![]),
+                (3, vec![1, 5, 3]),
+                (4, vec![1]),
+                (5, vec![1, 3]),
+            ],
+            roots: vec![5],
+            continuation_table: vec![3],
+        },
+        // Diamond-shaped graphs: objects reachable via multiple paths should be marked (and their
+        // fields threaded) exactly once. Marking an object twice threads its fields twice, which
+        // corrupts the pointers checked after GC.
+        TestHeap {
+            heap: vec![
+                (0, vec![1, 2]),
+                (1, vec![3]),
+                (2, vec![3]),
+                (3, vec![4]),
+                (4, vec![]),
+            ],
+            roots: vec![0],
+            continuation_table: vec![],
+        },
+        TestHeap {
+            heap: vec![(0, vec![]), (1, vec![0]), (2, vec![0]), (3, vec![1, 2])],
+            roots: vec![3, 1],
+            continuation_table: vec![2],
+        },
     ]
 }
 
@@ -120,10 +2197,23 @@ fn test_gc(
     );
 
     for _ in 0..3 {
-        gc.run(heap.clone());
+        let heap_ptr_offset_before = heap.heap_ptr_offset();
+
+        let stats = gc.run(heap.clone());
 
         let heap_base_offset = heap.heap_base_offset();
         let heap_ptr_offset = heap.heap_ptr_offset();
+
+        check_stats(
+            stats,
+            refs,
+            roots,
+            continuation_table,
+            heap_base_offset,
+            heap_ptr_offset_before,
+            heap_ptr_offset,
+        );
+
         let continuation_table_ptr_offset = heap.continuation_table_ptr_offset();
         check_dynamic_heap(
             true, // after gc
@@ -283,6 +2373,51 @@ fn check_dynamic_heap(
     }
 }
 
+/// Check the live and reclaimed sizes reported by a GC:
+///
+/// - Live size should be the total size of reachable objects plus the continuation table, and
+///   should match the heap size after GC.
+///
+/// - Reclaimed size should be the difference between heap sizes before and after GC.
+///
+fn check_stats(
+    stats: GcStats,
+    objects: &[(ObjectIdx, Vec<ObjectIdx>)],
+    roots: &[ObjectIdx],
+    continuation_table: &[ObjectIdx],
+    heap_base_offset: usize,
+    heap_ptr_offset_before: usize,
+    heap_ptr_offset_after: usize,
+) {
+    let objects_map: FxHashMap<ObjectIdx, &[ObjectIdx]> = objects
+        .iter()
+        .map(|(obj, refs)| (*obj, refs.as_slice()))
+        .collect();
+
+    let reachable_objects = compute_reachable_objects(roots, continuation_table, &objects_map);
+
+    // Each object is an array with a header, length, index, and the fields
+    let reachable_words: usize = reachable_objects
+        .iter()
+        .map(|obj| 3 + objects_map.get(obj).unwrap().len())
+        .sum();
+
+    let continuation_table_words =
+        (size_of::<Array>() + Words(continuation_table.len() as u32)).as_usize();
+
+    let expected_live = Bytes(((reachable_words + continuation_table_words) * WORD_SIZE) as u32);
+
+    assert_eq!(stats.live, expected_live);
+    assert_eq!(
+        stats.live,
+        Bytes((heap_ptr_offset_after - heap_base_offset) as u32)
+    );
+    assert_eq!(
+        stats.reclaimed,
+        Bytes((heap_ptr_offset_before - heap_ptr_offset_after) as u32)
+    );
+}
+
 fn compute_reachable_objects(
     roots: &[ObjectIdx],
     continuation_table: &[ObjectIdx],
@@ -327,8 +2462,16 @@ fn check_continuation_table(mut offset: usize, continuation_table: &[ObjectIdx],
     }
 }
 
+/// Live and reclaimed sizes reported by a GC via the `note_live_size` and `note_reclaimed`
+/// callbacks
+#[derive(Debug, Clone, Copy)]
+struct GcStats {
+    live: Bytes<u32>,
+    reclaimed: Bytes<u32>,
+}
+
 impl GC {
-    fn run(&self, mut heap: MotokoHeap) {
+    fn run(&self, mut heap: MotokoHeap) -> GcStats {
         let heap_base = heap.heap_base_address() as u32;
         let static_roots = Value::from_ptr(heap.static_root_array_address());
         let continuation_table_ptr_address = heap.continuation_table_ptr_address() as *mut Value;
@@ -336,6 +2479,9 @@ impl GC {
         let heap_1 = heap.clone();
         let heap_2 = heap.clone();
 
+        let live = Cell::new(Bytes(0));
+        let reclaimed = Cell::new(Bytes(0));
+
         match self {
             GC::Copying => {
                 unsafe {
@@ -349,9 +2495,9 @@ impl GC {
                         static_roots,
                         continuation_table_ptr_address,
                         // note_live_size
-                        |_live_size| {},
+                        |live_size| live.set(live_size),
                         // note_reclaimed
-                        |_reclaimed| {},
+                        |reclaimed_size| reclaimed.set(reclaimed_size),
                     );
                 }
             }
@@ -360,20 +2506,113 @@ impl GC {
                 unsafe {
                     compacting_gc_internal(
                         &mut heap,
+                        GcContext {
+                            heap_base,
+                            get_hp: || heap_1.heap_ptr_address(),
+                            set_hp: move |hp| heap_2.set_heap_ptr_address(hp as usize),
+                            static_roots,
+                            continuation_table_ptr_loc: continuation_table_ptr_address,
+                        },
+                        // note_live_size
+                        |live_size| live.set(live_size),
+                        // note_reclaimed
+                        |reclaimed_size| reclaimed.set(reclaimed_size),
+                        // note_phase_time
+                        |_, _| {},
+                    );
+                }
+            }
+
+            GC::IncrementalMarkCompact => {
+                // Run small slices until the GC cycle is done
+                while !incremental_gc_slice(&mut heap, INCREMENTAL_GC_BUDGET, &live, &reclaimed) {}
+            }
+
+            GC::Generational => {
+                // Minor GC with the entire heap in the young generation
+                unsafe {
+                    compacting_gc_minor_internal(
+                        &mut heap,
+                        GcContext {
+                            heap_base,
+                            get_hp: || heap_1.heap_ptr_address(),
+                            set_hp: move |hp| heap_2.set_heap_ptr_address(hp as usize),
+                            static_roots,
+                            continuation_table_ptr_loc: continuation_table_ptr_address,
+                        },
                         heap_base,
-                        // get_hp
-                        || heap_1.heap_ptr_address(),
-                        // set_hp
-                        move |hp| heap_2.set_heap_ptr_address(hp as usize),
-                        static_roots,
-                        continuation_table_ptr_address,
                         // note_live_size
-                        |_live_size| {},
+                        |live_size| live.set(live_size),
                         // note_reclaimed
-                        |_reclaimed| {},
+                        |reclaimed_size| reclaimed.set(reclaimed_size),
                     );
                 }
             }
         }
+
+        GcStats {
+            live: live.get(),
+            reclaimed: reclaimed.get(),
+        }
+    }
+}
+
+/// Run a slice of the incremental mark-compact GC, with the mutator calling the write barrier
+/// between the slices. Returns whether the GC cycle is done.
+fn incremental_gc_slice(
+    heap: &mut MotokoHeap,
+    budget: Words<u32>,
+    live: &Cell<Bytes<u32>>,
+    reclaimed: &Cell<Bytes<u32>>,
+) -> bool {
+    incremental_gc_slice_in_mode(
+        heap,
+        BarrierMode::SnapshotAtTheBeginning,
+        budget,
+        live,
+        reclaimed,
+    )
+}
+
+/// Run a slice of the incremental mark-compact GC with the given write barrier mode. Returns
+/// whether the GC cycle is done.
+fn incremental_gc_slice_in_mode(
+    heap: &mut MotokoHeap,
+    barrier_mode: BarrierMode,
+    budget: Words<u32>,
+    live: &Cell<Bytes<u32>>,
+    reclaimed: &Cell<Bytes<u32>>,
+) -> bool {
+    let heap_base = heap.heap_base_address() as u32;
+    let static_roots = Value::from_ptr(heap.static_root_array_address());
+    let continuation_table_ptr_address = heap.continuation_table_ptr_address() as *mut Value;
+
+    let heap_1 = heap.clone();
+    let heap_2 = heap.clone();
+
+    unsafe {
+        let old_barrier_mode = BARRIER_MODE;
+        BARRIER_MODE = barrier_mode;
+
+        compacting_gc_incremental_internal(
+            heap,
+            GcContext {
+                heap_base,
+                get_hp: || heap_1.heap_ptr_address(),
+                set_hp: |hp| heap_2.set_heap_ptr_address(hp as usize),
+                static_roots,
+                continuation_table_ptr_loc: continuation_table_ptr_address,
+            },
+            Words(MAX_MARK_STACK_SIZE as u32),
+            budget,
+            // note_live_size
+            |live_size| live.set(live_size),
+            // note_reclaimed
+            |reclaimed_size| reclaimed.set(reclaimed_size),
+        );
+
+        BARRIER_MODE = old_barrier_mode;
+
+        PHASE == Phase::Idle
     }
 }
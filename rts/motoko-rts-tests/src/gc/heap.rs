@@ -1,9 +1,12 @@
 use super::utils::{
-    make_pointer, make_scalar, write_word, ObjectIdx, GC, MAX_MARK_STACK_SIZE, WORD_SIZE,
+    make_pointer, make_scalar, write_word, ObjectIdx, GC, MAX_INCREMENTAL_ALLOC_SIZE,
+    MAX_MARK_STACK_SIZE, WORD_SIZE,
 };
 
 use motoko_rts::gc::mark_compact::mark_stack::INIT_STACK_SIZE;
+use motoko_rts::gc::mark_compact::remembered_set::INIT_CAPACITY;
 use motoko_rts::memory::Memory;
+use motoko_rts::roots::MAX_ROOTS;
 use motoko_rts::types::*;
 
 use std::cell::{Ref, RefCell};
@@ -202,6 +205,7 @@ impl MotokoHeapInner {
             static_heap_size_bytes,
             dynamic_heap_size_bytes,
             map.len(),
+            roots.len(),
         );
 
         // The Worst-case unalignment w.r.t. 32-byte alignment is 28 (assuming
@@ -211,7 +215,9 @@ impl MotokoHeapInner {
         // MarkCompact assumes that the dynamic heap starts at a 32-byte multiple
         let realign = match gc {
             GC::Copying => 0,
-            GC::MarkCompact => (32 - (heap.as_ptr() as usize + static_heap_size_bytes) % 32) % 32,
+            GC::MarkCompact | GC::IncrementalMarkCompact | GC::Generational => {
+                (32 - (heap.as_ptr() as usize + static_heap_size_bytes) % 32) % 32
+            }
         };
         assert_eq!(realign % 4, 0);
 
@@ -273,6 +279,7 @@ fn heap_size_for_gc(
     static_heap_size_bytes: usize,
     dynamic_heap_size_bytes: usize,
     n_objects: usize,
+    n_roots: usize,
 ) -> usize {
     let total_heap_size_bytes = static_heap_size_bytes + dynamic_heap_size_bytes;
     match gc {
@@ -281,28 +288,62 @@ fn heap_size_for_gc(
             total_heap_size_bytes + to_space_bytes
         }
         GC::MarkCompact => {
-            let bitmap_size_bytes = {
-                let dynamic_heap_bytes = Bytes(dynamic_heap_size_bytes as u32);
-                // `...to_words().to_bytes()` below effectively rounds up heap size to word size
-                // then gets the bytes
-                let dynamic_heap_words = dynamic_heap_bytes.to_words();
-                let mark_bit_bytes = dynamic_heap_words.to_bytes();
-
-                // The bitmap implementation rounds up to 64-bits to be able to read as many
-                // bits as possible in one instruction and potentially skip 64 words in the
-                // heap with single 64-bit comparison
-                (((mark_bit_bytes.as_u32() + 7) / 8) * 8) + size_of::<Blob>().to_bytes().as_u32()
-            };
-            // In the worst case the entire heap will be pushed to the mark stack, but in tests
-            // we limit the size
-            let mark_stack_words = n_objects.clamp(INIT_STACK_SIZE.as_usize(), MAX_MARK_STACK_SIZE)
-                + size_of::<Blob>().as_usize();
-
-            total_heap_size_bytes + bitmap_size_bytes as usize + (mark_stack_words * WORD_SIZE)
+            total_heap_size_bytes
+                + bitmap_size_bytes(dynamic_heap_size_bytes)
+                + mark_stack_size_bytes(n_objects)
+        }
+        GC::Generational => {
+            // Space for a remembered set, allocated before the GC
+            let remembered_set_words = INIT_CAPACITY as usize + size_of::<Blob>().as_usize();
+
+            total_heap_size_bytes
+                + (remembered_set_words * WORD_SIZE)
+                + bitmap_size_bytes(dynamic_heap_size_bytes + remembered_set_words * WORD_SIZE)
+                + mark_stack_size_bytes(n_objects)
+        }
+        GC::IncrementalMarkCompact => {
+            // Mark stack has a fixed size in incremental marking, and objects allocated between
+            // the slices follow it
+            let mark_stack_words = MAX_MARK_STACK_SIZE + size_of::<Blob>().as_usize();
+
+            // Root snapshot: static roots, continuation table and registered roots
+            let root_snapshot_words = n_roots + 1 + MAX_ROOTS + size_of::<Blob>().as_usize();
+
+            total_heap_size_bytes
+                + bitmap_size_bytes(dynamic_heap_size_bytes)
+                + (root_snapshot_words * WORD_SIZE)
+                + (mark_stack_words * WORD_SIZE)
+                + (MAX_INCREMENTAL_ALLOC_SIZE * WORD_SIZE)
         }
     }
 }
 
+/// Size of the mark-compact GC mark stack for a heap with the given number of objects, including
+/// the blob header
+fn mark_stack_size_bytes(n_objects: usize) -> usize {
+    // In the worst case the entire heap will be pushed to the mark stack, but in tests we limit
+    // the size
+    let mark_stack_words = n_objects.clamp(INIT_STACK_SIZE.as_usize(), MAX_MARK_STACK_SIZE)
+        + size_of::<Blob>().as_usize();
+
+    mark_stack_words * WORD_SIZE
+}
+
+/// Size of the mark-compact GC bitmap for a dynamic heap of the given size, including the blob
+/// header
+fn bitmap_size_bytes(dynamic_heap_size_bytes: usize) -> usize {
+    let dynamic_heap_bytes = Bytes(dynamic_heap_size_bytes as u32);
+    // `...to_words().to_bytes()` below effectively rounds up heap size to word size
+    // then gets the bytes
+    let dynamic_heap_words = dynamic_heap_bytes.to_words();
+    let mark_bit_bytes = dynamic_heap_words.to_bytes();
+
+    // The bitmap implementation rounds up to 64-bits to be able to read as many
+    // bits as possible in one instruction and potentially skip 64 words in the
+    // heap with single 64-bit comparison
+    ((((mark_bit_bytes.as_u32() + 7) / 8) * 8) + size_of::<Blob>().to_bytes().as_u32()) as usize
+}
+
 /// Given a heap description (as a map from objects to objects), and the dynamic part of the heap
 /// (as an array), initialize the dynamic heap with objects.
 ///
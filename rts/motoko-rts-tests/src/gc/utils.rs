@@ -12,15 +12,25 @@ pub const WORD_SIZE: usize = motoko_rts::constants::WORD_SIZE as usize;
 // Max allowed size for the mark stack in mark-compact GC tests
 pub const MAX_MARK_STACK_SIZE: usize = 100;
 
+// Space for objects allocated between incremental mark-compact GC slices in tests, in words
+pub const MAX_INCREMENTAL_ALLOC_SIZE: usize = 100;
+
 /// Enum for the GC implementations. GC functions are generic so we can't put them into arrays or
 /// other data types, we use this type instead.
 #[derive(Debug, Clone, Copy)]
 pub enum GC {
     Copying,
     MarkCompact,
+    IncrementalMarkCompact,
+    Generational,
 }
 
-pub static GC_IMPLS: [GC; 2] = [GC::Copying, GC::MarkCompact];
+pub static GC_IMPLS: [GC; 4] = [
+    GC::Copying,
+    GC::MarkCompact,
+    GC::IncrementalMarkCompact,
+    GC::Generational,
+];
 
 /// Read a little-endian (Wasm) word from given offset
 pub fn read_word(heap: &[u8], offset: usize) -> u32 {
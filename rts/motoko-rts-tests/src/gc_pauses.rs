@@ -0,0 +1,86 @@
+use motoko_rts::gc::pauses::{PauseSamples, PAUSE_SAMPLES};
+
+pub unsafe fn test() {
+    println!("Testing GC pause percentiles ...");
+
+    test_percentiles();
+    test_window();
+    test_reset();
+}
+
+fn test_percentiles() {
+    println!("  Testing percentiles");
+
+    let mut pauses = PauseSamples::new();
+    assert!(pauses.is_empty());
+    assert_eq!(pauses.percentile(50.0), 0);
+
+    // Pauses 1000, 2000, ..., 100000, recorded out of order
+    for i in 0..100 {
+        pauses.record((i * 37 % 100 + 1) * 1000);
+    }
+    assert_eq!(pauses.len(), 100);
+
+    assert_eq!(pauses.percentile(50.0), 50_000);
+    assert_eq!(pauses.percentile(99.0), 99_000);
+    assert_eq!(pauses.percentile(99.5), 100_000);
+    assert_eq!(pauses.percentile(100.0), 100_000);
+    assert_eq!(pauses.percentile(1.0), 1000);
+    assert_eq!(pauses.percentile(0.0), 1000);
+
+    // Out of range arguments are clamped
+    assert_eq!(pauses.percentile(-5.0), 1000);
+    assert_eq!(pauses.percentile(250.0), 100_000);
+    assert_eq!(pauses.percentile(f64::NAN), 1000);
+
+    // Mostly short pauses with a few long ones: p50 is short, p99 is long
+    let mut pauses = PauseSamples::new();
+    for i in 0..200 {
+        pauses.record(if i % 50 == 0 { 5_000_000 } else { 10_000 + i });
+    }
+    assert!(pauses.percentile(50.0) < 11_000);
+    assert_eq!(pauses.percentile(99.0), 5_000_000);
+
+    // Single sample
+    let mut pauses = PauseSamples::new();
+    pauses.record(42);
+    assert_eq!(pauses.percentile(0.0), 42);
+    assert_eq!(pauses.percentile(50.0), 42);
+    assert_eq!(pauses.percentile(100.0), 42);
+}
+
+/// Only the last `PAUSE_SAMPLES` pauses are kept
+fn test_window() {
+    println!("  Testing sample window");
+
+    let mut pauses = PauseSamples::new();
+
+    // Long pauses, then enough short ones to replace all of them
+    for _ in 0..PAUSE_SAMPLES {
+        pauses.record(1_000_000);
+    }
+    assert_eq!(pauses.percentile(50.0), 1_000_000);
+
+    for i in 0..PAUSE_SAMPLES as u64 {
+        pauses.record(i + 1);
+    }
+    assert_eq!(pauses.len(), PAUSE_SAMPLES);
+    assert_eq!(pauses.percentile(100.0), PAUSE_SAMPLES as u64);
+    assert_eq!(pauses.percentile(50.0), PAUSE_SAMPLES as u64 / 2);
+}
+
+fn test_reset() {
+    println!("  Testing reset");
+
+    let mut pauses = PauseSamples::new();
+    for i in 0..10 {
+        pauses.record(i);
+    }
+    pauses.reset();
+    assert!(pauses.is_empty());
+    assert_eq!(pauses.percentile(99.0), 0);
+
+    pauses.record(7);
+    assert_eq!(pauses.len(), 1);
+    assert_eq!(pauses.percentile(50.0), 7);
+}
@@ -0,0 +1,63 @@
+use motoko_rts::gc::stats::GcStats;
+use motoko_rts::types::Bytes;
+
+pub unsafe fn test() {
+    println!("Testing GC stats ...");
+
+    test_monotonic_counters();
+    test_saturation();
+}
+
+fn test_monotonic_counters() {
+    println!("  Testing monotonic counters");
+
+    let mut stats = GcStats::new();
+
+    // Live size goes up and down, reclaimed size can be zero
+    let collections: [(u32, u64, u32); 6] = [
+        (1000, 500, 1500),
+        (2000, 0, 2000),
+        (800, 3000, 3800),
+        (800, 0, 800),
+        (5000, 100, 5100),
+        (10, 4990, 5000),
+    ];
+
+    let mut total_reclaimed = 0;
+    let mut max_heap = 0;
+
+    for (i, (live, reclaimed, heap_size)) in collections.iter().enumerate() {
+        let old_stats = stats;
+
+        stats.note_gc(Bytes(*live), Bytes(*reclaimed), Bytes(*heap_size), i as u64);
+
+        assert!(stats.collections > old_stats.collections);
+        assert!(stats.total_reclaimed >= old_stats.total_reclaimed);
+        assert!(stats.max_heap >= old_stats.max_heap);
+
+        total_reclaimed += reclaimed;
+        max_heap = max_heap.max(*heap_size);
+
+        assert_eq!(stats.collections, i as u64 + 1);
+        assert_eq!(stats.total_live, Bytes(*live));
+        assert_eq!(stats.total_reclaimed, Bytes(total_reclaimed));
+        assert_eq!(stats.last_pause, i as u64);
+        assert_eq!(stats.max_heap, Bytes(max_heap));
+    }
+}
+
+fn test_saturation() {
+    println!("  Testing saturation");
+
+    let mut stats = GcStats::new();
+    stats.collections = u64::MAX - 1;
+    stats.total_reclaimed = Bytes(u64::MAX - 10);
+
+    stats.note_gc(Bytes(0), Bytes(5), Bytes(0), 0);
+    assert_eq!(stats.collections, u64::MAX);
+    assert_eq!(stats.total_reclaimed, Bytes(u64::MAX - 5));
+
+    stats.note_gc(Bytes(0), Bytes(100), Bytes(0), 0);
+    assert_eq!(stats.collections, u64::MAX);
+    assert_eq!(stats.total_reclaimed, Bytes(u64::MAX));
+}
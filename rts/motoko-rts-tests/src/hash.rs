@@ -0,0 +1,81 @@
+use crate::memory::TestMemory;
+
+use motoko_rts::hash::{blob_hash, text_hash};
+use motoko_rts::memory::{alloc_blob, Memory};
+use motoko_rts::text::{text_concat, text_of_str};
+use motoko_rts::types::{Bytes, Value, Words, TAG_CONCAT};
+
+use std::collections::HashSet;
+
+pub unsafe fn test() {
+    println!("Testing blob and text hashing ...");
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    println!("  Testing known values");
+    for (str, hash) in [
+        ("", 0x811c9dc5),
+        ("a", 0xe40c292c),
+        ("foobar", 0xbf9cf968),
+        ("hello world", 0xd58b3fa7),
+        ("Motoko 🦀", 0xdf42f465),
+    ] {
+        let blob = blob_of_bytes(&mut mem, str.as_bytes());
+        assert_eq!(blob_hash(blob), hash);
+
+        let text = text_of_str(&mut mem, str);
+        assert_eq!(text_hash(text), hash);
+    }
+
+    println!("  Testing concatenations");
+    let str = "The quick brown fox jumps over the lazy dog";
+    let blob = blob_of_bytes(&mut mem, str.as_bytes());
+    for split in 0..=str.len() {
+        let text1 = text_of_str(&mut mem, &str[..split]);
+        let text2 = text_of_str(&mut mem, &str[split..]);
+        let text = text_concat(&mut mem, text1, text2);
+        assert_eq!(text_hash(text), blob_hash(blob));
+    }
+
+    let mut text = text_of_str(&mut mem, "");
+    for word in str.split_inclusive(' ') {
+        let word = text_of_str(&mut mem, word);
+        text = text_concat(&mut mem, text, word);
+    }
+    assert_eq!(text.tag(), TAG_CONCAT);
+    assert_eq!(text_hash(text), blob_hash(blob));
+
+    println!("  Testing collisions");
+    // All words of up to three lower case letters, and 100,000 numbered words. FNV-1a has no
+    // collisions in these.
+    let mut words: Vec<String> = vec![];
+    let letters: Vec<char> = ('a'..='z').collect();
+    for a in &letters {
+        words.push(a.to_string());
+        for b in &letters {
+            words.push(format!("{}{}", a, b));
+            for c in &letters {
+                words.push(format!("{}{}{}", a, b, c));
+            }
+        }
+    }
+    for i in 0..100_000 {
+        words.push(format!("word{}", i));
+    }
+
+    let mut hashes: HashSet<u32> = HashSet::new();
+    for word in &words {
+        let mut mem = TestMemory::new(Words(64));
+        let text = text_of_str(&mut mem, word);
+        assert!(hashes.insert(text_hash(text)), "collision: {}", word);
+    }
+}
+
+unsafe fn blob_of_bytes<M: Memory>(mem: &mut M, bytes: &[u8]) -> Value {
+    let blob = alloc_blob(mem, Bytes(bytes.len() as u32));
+    let blob_ptr = blob.as_blob_mut();
+    for (i, byte) in bytes.iter().enumerate() {
+        blob_ptr.set(i as u32, *byte);
+    }
+    blob
+}
@@ -0,0 +1,99 @@
+//! Tests for the heap high-water marks. Allocations are noted like `IcMemory` does, and the live
+//! size is noted by the GC.
+
+use crate::array::{root_field, static_roots};
+use crate::memory::TestMemory;
+
+use motoko_rts::gc::high_water_mark::HighWaterMark;
+use motoko_rts::gc::mark_compact::{compacting_gc_internal, GcContext};
+use motoko_rts::memory::{alloc_array, alloc_blob};
+use motoko_rts::types::{Bytes, Value, Words};
+
+pub unsafe fn test() {
+    println!("Testing heap high-water marks ...");
+
+    test_max();
+    test_spike();
+}
+
+fn test_max() {
+    println!("  Testing maximum");
+
+    let mut hwm = HighWaterMark::new();
+    assert_eq!(hwm.max_heap_size(1000), Bytes(0));
+    assert_eq!(hwm.max_live_size(), Bytes(0));
+
+    hwm.note_alloc(1500);
+    hwm.note_alloc(3000);
+    hwm.note_alloc(2000);
+    assert_eq!(hwm.max_heap_size(1000), Bytes(2000));
+
+    hwm.note_live_size(Bytes(800));
+    hwm.note_live_size(Bytes(300));
+    assert_eq!(hwm.max_live_size(), Bytes(800));
+}
+
+/// Allocate a spike that is live in one collection and dropped before the next, the high-water
+/// marks keep the peak
+unsafe fn test_spike() {
+    println!("  Testing allocation spike");
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+    let (roots, heap_base) = static_roots(&mut mem, 1);
+    let mut hwm = HighWaterMark::new();
+
+    let spike_len = 100;
+    let spike = alloc_array(&mut mem, spike_len);
+    for i in 0..spike_len {
+        let blob = alloc_blob(&mut mem, Bytes(1024));
+        spike.as_array().set(i, blob);
+    }
+    *root_field(roots, 0) = spike;
+    hwm.note_alloc(mem.heap_ptr());
+
+    let peak_heap = Bytes((mem.heap_ptr() - heap_base as usize) as u32);
+    assert_eq!(hwm.max_heap_size(heap_base as usize), peak_heap);
+
+    // The spike is live in the first collection
+    collect(&mut mem, heap_base, roots, &mut hwm);
+    let peak_live = hwm.max_live_size();
+    assert!(peak_live.as_u32() > spike_len * 1024);
+
+    // Dropped before the second
+    *root_field(roots, 0) = Value::from_scalar(0);
+    collect(&mut mem, heap_base, roots, &mut hwm);
+    let heap_after = Bytes((mem.heap_ptr() - heap_base as usize) as u32);
+    assert!(heap_after.as_u32() < 1024);
+
+    // Smaller allocations after the spike
+    for _ in 0..10 {
+        alloc_blob(&mut mem, Bytes(1024));
+        hwm.note_alloc(mem.heap_ptr());
+    }
+    collect(&mut mem, heap_base, roots, &mut hwm);
+
+    assert_eq!(hwm.max_heap_size(heap_base as usize), peak_heap);
+    assert_eq!(hwm.max_live_size(), peak_live);
+}
+
+unsafe fn collect(mem: &mut TestMemory, heap_base: u32, roots: Value, hwm: &mut HighWaterMark) {
+    let mem_ptr: *mut TestMemory = mem;
+    let hwm_ptr: *mut HighWaterMark = hwm;
+    let mut continuation_table = Value::from_scalar(0);
+    compacting_gc_internal(
+        mem,
+        GcContext {
+            heap_base,
+            get_hp: || (*mem_ptr).heap_ptr(),
+            set_hp: |hp| (*mem_ptr).set_heap_ptr(hp as usize),
+            static_roots: roots,
+            continuation_table_ptr_loc: &mut continuation_table,
+        },
+        // note_live_size
+        |live| (*hwm_ptr).note_live_size(live),
+        // note_reclaimed
+        |_| {},
+        // note_phase_time
+        |_, _| {},
+    );
+}
@@ -0,0 +1,205 @@
+use crate::idl_stream::{contents, leb128, make_blob};
+use crate::memory::TestMemory;
+use crate::trap::traps_with;
+
+use motoko_rts::idl_lazy::{
+    idl_lazy, idl_lazy_force, idl_lazy_is_null, idl_lazy_some, idl_lazy_variant_tag,
+    idl_lazy_variant_value,
+};
+use motoko_rts::types::Words;
+
+/// Type table of `opt opt opt record { a : text }`
+const NESTED_OPT_TYPES: [u8; 15] = [
+    0x44, 0x49, 0x44, 0x4c, // DIDL
+    0x04, // 4 types
+    0x6e, 0x01, // 0: opt 1
+    0x6e, 0x02, // 1: opt 2
+    0x6e, 0x03, // 2: opt 3
+    0x6c, 0x01, 0x61, 0x71, // 3: record { a (97) : text }
+];
+
+/// Type table of `variant { a : null; b : opt nat8 }`
+const VARIANT_TYPES: [u8; 13] = [
+    0x44, 0x49, 0x44, 0x4c, // DIDL
+    0x02, // 2 types
+    0x6b, 0x02, 0x61, 0x7f, 0x62, 0x01, // 0: variant { a (97) : null; b (98) : 1 }
+    0x6e, 0x7b, // 1: opt nat8
+];
+
+pub unsafe fn test() {
+    println!("Testing IDL lazy values ...");
+
+    test_nested_opts();
+    test_on_demand();
+    test_variants();
+    test_moved_message();
+    test_errors();
+}
+
+/// Message with a single argument of type `t` and the given value
+fn message(types: &[u8], t: u8, value: &[u8]) -> Vec<u8> {
+    let mut bytes = types.to_vec();
+    bytes.extend_from_slice(&[0x01, t]);
+    bytes.extend_from_slice(value);
+    bytes
+}
+
+/// Value of `opt opt opt record { a : text }` with `levels` non-null `opt`s
+fn nested_opt(levels: usize, text: &str) -> Vec<u8> {
+    let mut bytes = vec![0x01; levels];
+    if levels < 3 {
+        bytes.push(0x00);
+    } else {
+        leb128(&mut bytes, text.len() as u32);
+        bytes.extend_from_slice(text.as_bytes());
+    }
+    bytes
+}
+
+unsafe fn test_nested_opts() {
+    println!("  Testing nested opts");
+
+    let mut mem = TestMemory::new(Words(1024));
+
+    for levels in 0..3 {
+        let msg = make_blob(
+            &mut mem,
+            &message(&NESTED_OPT_TYPES, 0x00, &nested_opt(levels, "")),
+        );
+        let mut lazy = idl_lazy(&mut mem, msg);
+        for _ in 0..levels {
+            assert!(!idl_lazy_is_null(msg, lazy));
+            lazy = idl_lazy_some(&mut mem, msg, lazy);
+        }
+        assert!(idl_lazy_is_null(msg, lazy));
+    }
+
+    let bytes = message(&NESTED_OPT_TYPES, 0x00, &nested_opt(3, "motoko"));
+    let msg = make_blob(&mut mem, &bytes);
+    let mut lazy = idl_lazy(&mut mem, msg);
+
+    // Forcing the argument gives the original message
+    assert_eq!(contents(idl_lazy_force(&mut mem, msg, lazy)), bytes);
+
+    for _ in 0..3 {
+        lazy = idl_lazy_some(&mut mem, msg, lazy);
+    }
+    assert_eq!(
+        contents(idl_lazy_force(&mut mem, msg, lazy)),
+        message(&NESTED_OPT_TYPES, 0x03, &nested_opt(3, "motoko")[3..])
+    );
+}
+
+/// Payloads are only copied when forced
+unsafe fn test_on_demand() {
+    println!("  Testing materialization on demand");
+
+    let text = "x".repeat(10_000);
+    let bytes = message(&NESTED_OPT_TYPES, 0x00, &nested_opt(3, &text));
+
+    let mut mem = TestMemory::new(Words(8192));
+    let msg = make_blob(&mut mem, &bytes);
+    let mut lazy = idl_lazy(&mut mem, msg);
+
+    // Steps are done in a heap that is too small for the payload
+    let mut scratch = TestMemory::new(Words(64));
+    for _ in 0..3 {
+        assert!(!idl_lazy_is_null(msg, lazy));
+        lazy = idl_lazy_some(&mut scratch, msg, lazy);
+    }
+
+    assert!(traps_with("TestMemory::grow_memory", || {
+        idl_lazy_force(&mut scratch, msg, lazy);
+    }));
+
+    let record = contents(idl_lazy_force(&mut mem, msg, lazy));
+    assert_eq!(&record[record.len() - text.len()..], text.as_bytes());
+}
+
+unsafe fn test_variants() {
+    println!("  Testing variants");
+
+    let mut mem = TestMemory::new(Words(1024));
+
+    // #b(?5)
+    let msg = make_blob(
+        &mut mem,
+        &message(&VARIANT_TYPES, 0x00, &[0x01, 0x01, 0x05]),
+    );
+    let lazy = idl_lazy(&mut mem, msg);
+    assert_eq!(idl_lazy_variant_tag(msg, lazy), 98);
+    let payload = idl_lazy_variant_value(&mut mem, msg, lazy);
+    assert!(!idl_lazy_is_null(msg, payload));
+    let nat8 = idl_lazy_some(&mut mem, msg, payload);
+    assert_eq!(
+        contents(idl_lazy_force(&mut mem, msg, nat8)),
+        message(&VARIANT_TYPES, 0x7b, &[0x05])
+    );
+
+    // #a
+    let msg = make_blob(&mut mem, &message(&VARIANT_TYPES, 0x00, &[0x00]));
+    let lazy = idl_lazy(&mut mem, msg);
+    assert_eq!(idl_lazy_variant_tag(msg, lazy), 97);
+    let payload = idl_lazy_variant_value(&mut mem, msg, lazy);
+    assert_eq!(
+        contents(idl_lazy_force(&mut mem, msg, payload)),
+        message(&VARIANT_TYPES, 0x7f, &[])
+    );
+}
+
+/// GC may move the message between calls
+unsafe fn test_moved_message() {
+    println!("  Testing moved messages");
+
+    let mut mem = TestMemory::new(Words(1024));
+    let bytes = message(&NESTED_OPT_TYPES, 0x00, &nested_opt(3, "motoko"));
+    let msg = make_blob(&mut mem, &bytes);
+    let lazy = idl_lazy(&mut mem, msg);
+    let lazy = idl_lazy_some(&mut mem, msg, lazy);
+
+    let moved = make_blob(&mut mem, &bytes);
+    let lazy = idl_lazy_some(&mut mem, moved, lazy);
+    let lazy = idl_lazy_some(&mut mem, moved, lazy);
+    assert_eq!(
+        contents(idl_lazy_force(&mut mem, moved, lazy)),
+        message(&NESTED_OPT_TYPES, 0x03, &nested_opt(3, "motoko")[3..])
+    );
+}
+
+unsafe fn test_errors() {
+    println!("  Testing errors");
+
+    let mut mem = TestMemory::new(Words(1024));
+
+    let opt = make_blob(&mut mem, &message(&NESTED_OPT_TYPES, 0x00, &[0x00]));
+    let opt_lazy = idl_lazy(&mut mem, opt);
+    let variant = make_blob(&mut mem, &message(&VARIANT_TYPES, 0x00, &[0x00]));
+    let variant_lazy = idl_lazy(&mut mem, variant);
+
+    assert!(traps_with("lazy value: opt is null", || {
+        idl_lazy_some(&mut TestMemory::new(Words(64)), opt, opt_lazy);
+    }));
+    assert!(traps_with("lazy value: not a variant", || {
+        idl_lazy_variant_tag(opt, opt_lazy);
+    }));
+    assert!(traps_with("lazy value: not an opt", || {
+        idl_lazy_is_null(variant, variant_lazy);
+    }));
+
+    // Invalid values are rejected when the lazy value is created
+    let bytes = message(&NESTED_OPT_TYPES, 0x00, &[0x01, 0x01, 0x01, 0x05]);
+    let msg = make_blob(&mut mem, &bytes);
+    assert!(traps_with("advance out of buffer", || {
+        idl_lazy(&mut TestMemory::new(Words(64)), msg);
+    }));
+
+    let msg = make_blob(&mut mem, &message(&VARIANT_TYPES, 0x00, &[0x02]));
+    assert!(traps_with("skip_any: variant tag too large", || {
+        idl_lazy(&mut TestMemory::new(Words(64)), msg);
+    }));
+
+    let msg = make_blob(&mut mem, &message(&NESTED_OPT_TYPES, 0x00, &[0x00, 0x00]));
+    assert!(traps_with("lazy value: left-over bytes", || {
+        idl_lazy(&mut TestMemory::new(Words(64)), msg);
+    }));
+}
@@ -0,0 +1,286 @@
+use crate::memory::TestMemory;
+use crate::trap::traps_with;
+
+use motoko_rts::idl_stream::{idl_vec_stream, idl_vec_stream_next, idl_vec_stream_remaining};
+use motoko_rts::memory::{alloc_blob, Memory};
+use motoko_rts::types::{Bytes, Value, Words};
+
+/// Type table of `vec record { a : nat32; b : text }`
+const RECORD_TYPES: [u8; 10] = [
+    0x44, 0x49, 0x44, 0x4c, // DIDL
+    0x02, // 2 types
+    0x6d, 0x01, // 0: vec 1
+    0x6c, 0x02, 0x61, // 1: record { a (97) ...
+];
+
+pub unsafe fn test() {
+    println!("Testing IDL vec streams ...");
+
+    test_records();
+    test_recursive_type();
+    test_deep_values();
+    test_moved_message();
+    test_errors();
+    test_bounded_heap();
+}
+
+/// Header of `vec record { a : nat32; b : text }` with the list of argument types
+fn record_header(arg_types: &[u8]) -> Vec<u8> {
+    let mut bytes = RECORD_TYPES.to_vec();
+    bytes.extend_from_slice(&[0x79, 0x62, 0x71]); // ... : nat32; b (98) : text }
+    bytes.extend_from_slice(arg_types);
+    bytes
+}
+
+fn record(a: u32, b: &str) -> Vec<u8> {
+    let mut bytes = a.to_le_bytes().to_vec();
+    bytes.push(b.len() as u8);
+    bytes.extend_from_slice(b.as_bytes());
+    bytes
+}
+
+/// Message with a `vec record { a : nat32; b : text }` argument
+fn records_message(records: &[(u32, &str)]) -> Vec<u8> {
+    let mut bytes = record_header(&[0x01, 0x00]);
+    leb128(&mut bytes, records.len() as u32);
+    for (a, b) in records {
+        bytes.extend(record(*a, b));
+    }
+    bytes
+}
+
+unsafe fn test_records() {
+    println!("  Testing records");
+
+    let mut mem = TestMemory::new(Words(1024));
+    let msg = make_blob(
+        &mut mem,
+        &records_message(&[(1, "one"), (2, "two"), (1000, "")]),
+    );
+    let state = idl_vec_stream(&mut mem, msg);
+    assert_eq!(idl_vec_stream_remaining(state), 3);
+
+    // Element messages have a single `record { a : nat32; b : text }` argument
+    for (i, (a, b)) in [(1, "one"), (2, "two"), (1000, "")].iter().enumerate() {
+        let mut expected = record_header(&[0x01, 0x01]);
+        expected.extend(record(*a, b));
+        assert_eq!(
+            contents(idl_vec_stream_next(&mut mem, msg, state)),
+            expected
+        );
+        assert_eq!(idl_vec_stream_remaining(state), 2 - i as u32);
+    }
+
+    // Empty vec
+    let msg = make_blob(&mut mem, &records_message(&[]));
+    assert_eq!(idl_vec_stream_remaining(idl_vec_stream(&mut mem, msg)), 0);
+
+    // Primitive element type: `vec nat16`
+    let msg = make_blob(
+        &mut mem,
+        &[
+            0x44, 0x49, 0x44, 0x4c, 0x01, 0x6d, 0x7a, 0x01, 0x00, 0x02, 0x01, 0x00, 0x02, 0x00,
+        ],
+    );
+    let state = idl_vec_stream(&mut mem, msg);
+    for n in [1u8, 2] {
+        assert_eq!(
+            contents(idl_vec_stream_next(&mut mem, msg, state)),
+            vec![0x44, 0x49, 0x44, 0x4c, 0x01, 0x6d, 0x7a, 0x01, 0x7a, n, 0x00]
+        );
+    }
+}
+
+unsafe fn test_recursive_type() {
+    println!("  Testing recursive types");
+
+    // `vec L` where `L = opt record { 0 : nat8; 1 : L }`
+    let header = [
+        0x44, 0x49, 0x44, 0x4c, // DIDL
+        0x03, // 3 types
+        0x6d, 0x01, // 0: vec 1
+        0x6e, 0x02, // 1: opt 2
+        0x6c, 0x02, 0x00, 0x7b, 0x01, 0x01, // 2: record { 0 : nat8; 1 : 1 }
+    ];
+    let list_12 = [0x01, 0x01, 0x01, 0x02, 0x00]; // ?{ 1; ?{ 2; null } }
+    let list_empty = [0x00]; // null
+
+    let mut msg = header.to_vec();
+    msg.extend_from_slice(&[0x01, 0x00, 0x02]);
+    msg.extend_from_slice(&list_12);
+    msg.extend_from_slice(&list_empty);
+
+    let mut mem = TestMemory::new(Words(1024));
+    let msg = make_blob(&mut mem, &msg);
+    let state = idl_vec_stream(&mut mem, msg);
+
+    for list in [&list_12[..], &list_empty[..]] {
+        let mut expected = header.to_vec();
+        expected.extend_from_slice(&[0x01, 0x01]);
+        expected.extend_from_slice(list);
+        assert_eq!(
+            contents(idl_vec_stream_next(&mut mem, msg, state)),
+            expected
+        );
+    }
+}
+
+/// Skipping deeply nested values must not recurse on the Wasm stack
+unsafe fn test_deep_values() {
+    println!("  Testing deep values");
+
+    const DEPTH: usize = 100_000;
+
+    // `vec L` where `L = opt record { 0 : nat8; 1 : L }`: the nested list is the last field, so
+    // the work stack stays small
+    let right_header = [
+        0x44, 0x49, 0x44, 0x4c, 0x03, 0x6d, 0x01, 0x6e, 0x02, 0x6c, 0x02, 0x00, 0x7b, 0x01, 0x01,
+    ];
+    let mut right_list = [0x01, 0x07].repeat(DEPTH);
+    right_list.push(0x00);
+
+    // `vec L` where `L = opt record { 0 : L; 1 : nat8 }`: the work stack grows with the depth and
+    // moves to the heap
+    let left_header = [
+        0x44, 0x49, 0x44, 0x4c, 0x03, 0x6d, 0x01, 0x6e, 0x02, 0x6c, 0x02, 0x00, 0x01, 0x01, 0x7b,
+    ];
+    let mut left_list = vec![0x01; DEPTH];
+    left_list.push(0x00);
+    left_list.extend_from_slice(&[0x07; DEPTH]);
+
+    for (header, list) in [(right_header, right_list), (left_header, left_list)] {
+        let mut msg = header.to_vec();
+        msg.extend_from_slice(&[0x01, 0x00, 0x01]);
+        msg.extend_from_slice(&list);
+
+        let mut mem = TestMemory::new(Words(4 * 1024 * 1024));
+        let msg = make_blob(&mut mem, &msg);
+        let state = idl_vec_stream(&mut mem, msg);
+
+        let mut expected = header.to_vec();
+        expected.extend_from_slice(&[0x01, 0x01]);
+        expected.extend_from_slice(&list);
+        assert_eq!(
+            contents(idl_vec_stream_next(&mut mem, msg, state)),
+            expected
+        );
+        assert_eq!(idl_vec_stream_remaining(state), 0);
+    }
+}
+
+/// GC may move the message between calls
+unsafe fn test_moved_message() {
+    println!("  Testing moved messages");
+
+    let mut mem = TestMemory::new(Words(1024));
+    let bytes = records_message(&[(1, "one"), (2, "two")]);
+    let msg = make_blob(&mut mem, &bytes);
+    let state = idl_vec_stream(&mut mem, msg);
+    idl_vec_stream_next(&mut mem, msg, state);
+
+    let moved = make_blob(&mut mem, &bytes);
+    let mut expected = record_header(&[0x01, 0x01]);
+    expected.extend(record(2, "two"));
+    assert_eq!(
+        contents(idl_vec_stream_next(&mut mem, moved, state)),
+        expected
+    );
+}
+
+unsafe fn test_errors() {
+    println!("  Testing errors");
+
+    let mut mem = TestMemory::new(Words(1024));
+
+    // `nat8` argument
+    let msg = make_blob(&mut mem, &[0x44, 0x49, 0x44, 0x4c, 0x00, 0x01, 0x7b, 0x05]);
+    assert!(traps_with("vec stream: argument is not a vec", || {
+        idl_vec_stream(&mut TestMemory::new(Words(64)), msg);
+    }));
+
+    // Two arguments
+    let mut bytes = record_header(&[0x02, 0x00, 0x00]);
+    bytes.extend_from_slice(&[0x00, 0x00]);
+    let msg = make_blob(&mut mem, &bytes);
+    assert!(traps_with("vec stream: expected a single argument", || {
+        idl_vec_stream(&mut TestMemory::new(Words(64)), msg);
+    }));
+
+    // Reading past the last element
+    let msg = make_blob(&mut mem, &records_message(&[(1, "one")]));
+    let state = idl_vec_stream(&mut mem, msg);
+    idl_vec_stream_next(&mut mem, msg, state);
+    assert!(traps_with("vec stream: no more elements", || {
+        idl_vec_stream_next(&mut TestMemory::new(Words(64)), msg, state);
+    }));
+
+    // Bytes after the last element
+    let mut bytes = records_message(&[(1, "one")]);
+    bytes.push(0);
+    let msg = make_blob(&mut mem, &bytes);
+    let state = idl_vec_stream(&mut mem, msg);
+    assert!(traps_with("vec stream: left-over bytes", || {
+        idl_vec_stream_next(&mut TestMemory::new(Words(64)), msg, state);
+    }));
+
+    // Truncated element
+    let mut bytes = records_message(&[(1, "one")]);
+    bytes.pop();
+    let msg = make_blob(&mut mem, &bytes);
+    let state = idl_vec_stream(&mut mem, msg);
+    assert!(traps_with("advance out of buffer", || {
+        idl_vec_stream_next(&mut TestMemory::new(Words(64)), msg, state);
+    }));
+}
+
+/// Streaming a million records only allocates the element being returned
+unsafe fn test_bounded_heap() {
+    println!("  Testing bounded heap");
+
+    const N: u32 = 1_000_000;
+
+    let mut bytes = record_header(&[0x01, 0x00]);
+    leb128(&mut bytes, N);
+    for i in 0..N {
+        bytes.extend(record(i, "x"));
+    }
+
+    let mut mem = TestMemory::new(Bytes(bytes.len() as u32).to_words() + Words(1024));
+    let msg = make_blob(&mut mem, &bytes);
+    let state = idl_vec_stream(&mut mem, msg);
+
+    // Each element is returned from a fresh heap that is too small for more than one element
+    let elem_offset = record_header(&[0x01, 0x01]).len();
+    for i in 0..N {
+        let mut scratch = TestMemory::new(Words(12));
+        let elem = contents(idl_vec_stream_next(&mut scratch, msg, state));
+        assert_eq!(&elem[elem_offset..], &record(i, "x")[..]);
+    }
+    assert_eq!(idl_vec_stream_remaining(state), 0);
+}
+
+pub fn leb128(bytes: &mut Vec<u8>, mut n: u32) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            bytes.push(byte);
+            break;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+pub unsafe fn make_blob<M: Memory>(mem: &mut M, bytes: &[u8]) -> Value {
+    let blob = alloc_blob(mem, Bytes(bytes.len() as u32));
+    let blob_ptr = blob.as_blob_mut();
+    for (i, byte) in bytes.iter().enumerate() {
+        blob_ptr.set(i as u32, *byte);
+    }
+    blob
+}
+
+pub unsafe fn contents(blob: Value) -> Vec<u8> {
+    let blob = blob.as_blob();
+    std::slice::from_raw_parts(blob.payload_const(), blob.len().as_usize()).to_vec()
+}
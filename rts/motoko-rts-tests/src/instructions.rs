@@ -0,0 +1,38 @@
+use motoko_rts::gc::INSTRUCTION_COUNTER;
+use motoko_rts::instructions::{
+    instruction_budget, instruction_count, remaining_instructions, set_instruction_budget,
+    DEFAULT_INSTRUCTION_BUDGET,
+};
+
+static mut MOCK_COUNT: u64 = 0;
+
+fn mock_instruction_counter() -> u64 {
+    unsafe { MOCK_COUNT }
+}
+
+pub unsafe fn test() {
+    println!("Testing instruction budget ...");
+
+    // Without a counter the values are deterministic
+    assert_eq!(instruction_count(), 0);
+    assert_eq!(instruction_budget(), DEFAULT_INSTRUCTION_BUDGET);
+    assert_eq!(remaining_instructions(), DEFAULT_INSTRUCTION_BUDGET);
+
+    let instruction_counter = INSTRUCTION_COUNTER;
+    INSTRUCTION_COUNTER = mock_instruction_counter;
+
+    set_instruction_budget(1_000);
+
+    for (count, remaining) in [(0, 1_000), (400, 600), (999, 1), (1_000, 0), (5_000, 0)] {
+        MOCK_COUNT = count;
+        assert_eq!(instruction_count(), count);
+        assert_eq!(remaining_instructions(), remaining);
+    }
+
+    // Raising the budget makes instructions available again
+    set_instruction_budget(10_000);
+    assert_eq!(remaining_instructions(), 5_000);
+
+    set_instruction_budget(DEFAULT_INSTRUCTION_BUDGET);
+    INSTRUCTION_COUNTER = instruction_counter;
+}
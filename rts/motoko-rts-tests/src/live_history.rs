@@ -0,0 +1,134 @@
+//! Tests for the live size history. Messages are simulated by allocating, optionally leaking some
+//! of the allocations into a root, and collecting with the history recording the live size.
+
+use crate::array::{root_field, static_roots};
+use crate::memory::TestMemory;
+
+use motoko_rts::gc::live_history::{LiveHistory, LiveSample, LIVE_HISTORY_SIZE};
+use motoko_rts::gc::mark_compact::{compacting_gc_internal, GcContext};
+use motoko_rts::memory::{alloc_array, alloc_blob};
+use motoko_rts::types::{Bytes, Value, Words};
+
+pub unsafe fn test() {
+    println!("Testing live size history ...");
+
+    test_ring_buffer();
+    test_leak();
+    test_no_leak();
+}
+
+fn test_ring_buffer() {
+    println!("  Testing ring buffer");
+
+    let mut history = LiveHistory::new();
+    assert!(history.is_empty());
+
+    for message in 1..=3 {
+        history.record(message, Bytes(message as u32 * 10));
+    }
+    assert_eq!(history.len(), 3);
+    assert_eq!(
+        history.get(0),
+        LiveSample {
+            message: 1,
+            live: Bytes(10)
+        }
+    );
+    assert_eq!(history.get(2).message, 3);
+
+    // Oldest samples are replaced
+    let n = LIVE_HISTORY_SIZE as u64 + 5;
+    for message in 4..=n {
+        history.record(message, Bytes(message as u32 * 10));
+    }
+    assert_eq!(history.len(), LIVE_HISTORY_SIZE);
+    for i in 0..LIVE_HISTORY_SIZE {
+        let message = n - LIVE_HISTORY_SIZE as u64 + 1 + i as u64;
+        assert_eq!(history.get(i).message, message);
+        assert_eq!(history.get(i).live, Bytes(message as u32 * 10));
+    }
+}
+
+/// Run `n` messages, each allocating garbage and, when `leak` is set, adding an object to a list
+/// kept alive by a root. Returns the history of the collections at the end of the messages.
+unsafe fn run_messages(n: u64, leak: bool) -> LiveHistory {
+    let mut mem = TestMemory::new(Words(128 * 1024));
+    let (roots, heap_base) = static_roots(&mut mem, 1);
+    let mut history = LiveHistory::new();
+
+    for message in 1..=n {
+        alloc_blob(&mut mem, Bytes(256));
+        if leak {
+            let node = alloc_array(&mut mem, 2);
+            node.as_array().set(0, alloc_blob(&mut mem, Bytes(64)));
+            node.as_array().set(1, *root_field(roots, 0));
+            *root_field(roots, 0) = node;
+        }
+        alloc_blob(&mut mem, Bytes(256));
+
+        collect(&mut mem, heap_base, roots, &mut history, message);
+    }
+
+    history
+}
+
+/// Compacting GC of the heap, recording the live size as a sample of `message`
+unsafe fn collect(
+    mem: &mut TestMemory,
+    heap_base: u32,
+    roots: Value,
+    history: &mut LiveHistory,
+    message: u64,
+) {
+    let mem_ptr: *mut TestMemory = mem;
+    let history_ptr: *mut LiveHistory = history;
+    let mut continuation_table = Value::from_scalar(0);
+    compacting_gc_internal(
+        mem,
+        GcContext {
+            heap_base,
+            get_hp: || (*mem_ptr).heap_ptr(),
+            set_hp: |hp| (*mem_ptr).set_heap_ptr(hp as usize),
+            static_roots: roots,
+            continuation_table_ptr_loc: &mut continuation_table,
+        },
+        // note_live_size
+        |live| (*history_ptr).record(message, live),
+        // note_reclaimed
+        |_| {},
+        // note_phase_time
+        |_, _| {},
+    );
+}
+
+/// The baseline of a leaking canister grows by the leaked objects every message
+unsafe fn test_leak() {
+    println!("  Testing leaking messages");
+
+    let n = LIVE_HISTORY_SIZE as u64 + 10;
+    let history = run_messages(n, true);
+    assert_eq!(history.len(), LIVE_HISTORY_SIZE);
+
+    // Samples of the last messages, oldest first
+    let first = history.get(0);
+    assert_eq!(first.message, n - LIVE_HISTORY_SIZE as u64 + 1);
+    let leaked = history.get(1).live - first.live;
+    assert!(leaked.as_u32() >= 64);
+    for i in 1..LIVE_HISTORY_SIZE {
+        let (prev, sample) = (history.get(i - 1), history.get(i));
+        assert_eq!(sample.message, prev.message + 1);
+        assert_eq!(sample.live, prev.live + leaked);
+    }
+}
+
+/// The baseline of a canister that does not leak is flat
+unsafe fn test_no_leak() {
+    println!("  Testing messages without leaks");
+
+    let history = run_messages(10, false);
+    assert_eq!(history.len(), 10);
+    for i in 0..10 {
+        assert_eq!(history.get(i).message, i as u64 + 1);
+        assert_eq!(history.get(i).live, history.get(0).live);
+    }
+}
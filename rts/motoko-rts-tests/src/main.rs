@@ -1,17 +1,51 @@
 #![feature(map_first_last)]
 
+#[cfg(feature = "alloc-profiling")]
+mod alloc_profile;
+mod array;
+mod array_buffer;
 mod bigint;
 mod bitmap;
+mod blob_builder;
+mod blob_encoding;
+mod blob_map;
 mod continuation_table;
+mod controller;
 mod crc32;
+mod cycles;
+mod float;
 mod gc;
+mod gc_pauses;
+mod gc_stats;
+mod hash;
+mod high_water_mark;
+mod idl_lazy;
+mod idl_stream;
+mod instructions;
 mod leb128;
+mod live_history;
 mod mark_stack;
 mod memory;
+mod memory_pressure;
+mod on_trap;
+mod pointer_width;
 mod principal_id;
+mod remembered_set;
+mod reply_splice;
+mod rng;
+mod safepoint;
+mod scheduler;
+mod self_test;
+mod show_cycle;
+mod stabilization;
+mod stable_region;
+mod stable_sharing;
 mod stream;
 mod text;
+mod trap;
+mod unicode;
 mod utf8;
+mod visitor;
 
 use motoko_rts::types::Bytes;
 
@@ -22,17 +56,51 @@ fn main() {
     }
 
     unsafe {
+        #[cfg(feature = "alloc-profiling")]
+        alloc_profile::test();
+        array::test();
+        array_buffer::test();
         bigint::test();
         bitmap::test();
+        blob_builder::test();
+        blob_encoding::test();
+        blob_map::test();
         continuation_table::test();
+        controller::test();
         crc32::test();
+        cycles::test();
+        float::test();
         gc::test();
+        gc_pauses::test();
+        gc_stats::test();
+        hash::test();
+        high_water_mark::test();
+        idl_lazy::test();
+        idl_stream::test();
+        instructions::test();
         leb128::test();
+        live_history::test();
         mark_stack::test();
+        memory::test();
+        memory_pressure::test();
+        on_trap::test();
+        pointer_width::test();
         principal_id::test();
+        remembered_set::test();
+        reply_splice::test();
+        rng::test();
+        safepoint::test();
+        scheduler::test();
+        self_test::test();
+        show_cycle::test();
+        stabilization::test();
+        stable_region::test();
+        stable_sharing::test();
         stream::test();
         text::test();
+        unicode::test();
         utf8::test();
+        visitor::test();
     }
 }
 
@@ -2,7 +2,7 @@ use crate::memory::TestMemory;
 
 use motoko_rts::gc::mark_compact::mark_stack::{
     alloc_mark_stack, free_mark_stack, grow_stack, pop_mark_stack, push_mark_stack,
-    INIT_STACK_SIZE, STACK_BASE, STACK_PTR, STACK_TOP,
+    DEFAULT_MAX_STACK_SIZE, INIT_STACK_SIZE, MAX_STACK_SIZE, STACK_BASE, STACK_PTR, STACK_TOP,
 };
 use motoko_rts::memory::Memory;
 use motoko_rts::types::*;
@@ -14,6 +14,7 @@ pub unsafe fn test() {
 
     test_push_pop();
     test_grow_stack();
+    test_max_stack_size();
 }
 
 fn test_push_pop() {
@@ -94,13 +95,50 @@ unsafe fn test_grow_stack() {
     assert_eq!(STACK_BASE.add(current_size), STACK_TOP);
     assert_eq!(STACK_BASE, STACK_PTR);
 
-    grow_stack(&mut mem);
+    assert!(grow_stack(&mut mem));
     current_size *= 2;
     assert_eq!(STACK_BASE.add(current_size), STACK_TOP);
     assert_eq!(STACK_BASE, STACK_PTR);
 
-    grow_stack(&mut mem);
+    assert!(grow_stack(&mut mem));
     current_size *= 2;
     assert_eq!(STACK_BASE.add(current_size), STACK_TOP);
     assert_eq!(STACK_BASE, STACK_PTR);
+
+    free_mark_stack();
+}
+
+unsafe fn test_max_stack_size() {
+    println!("  Testing max stack size");
+
+    // Allow growing once, to 1.5 times the initial size
+    MAX_STACK_SIZE = INIT_STACK_SIZE + INIT_STACK_SIZE / 2;
+
+    let mut mem = TestMemory::new(size_of::<Blob>() + MAX_STACK_SIZE);
+
+    alloc_mark_stack(&mut mem);
+
+    // Each entry is two words
+    let n_entries = MAX_STACK_SIZE.as_u32() / 2;
+
+    for obj in 0..n_entries {
+        assert!(push_mark_stack(&mut mem, obj as usize, TAG_ARRAY));
+    }
+
+    assert_eq!(STACK_BASE.add(MAX_STACK_SIZE.as_usize()), STACK_TOP);
+
+    // Stack is full, push should fail without modifying the stack
+    assert!(!grow_stack(&mut mem));
+    assert!(!push_mark_stack(&mut mem, n_entries as usize, TAG_ARRAY));
+    assert_eq!(STACK_PTR, STACK_TOP);
+
+    for obj in (0..n_entries).rev() {
+        assert_eq!(pop_mark_stack(), Some((obj as usize, TAG_ARRAY)));
+    }
+
+    assert_eq!(pop_mark_stack(), None);
+
+    free_mark_stack();
+
+    MAX_STACK_SIZE = DEFAULT_MAX_STACK_SIZE;
 }
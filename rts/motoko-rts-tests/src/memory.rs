@@ -1,5 +1,8 @@
-use motoko_rts::memory::Memory;
-use motoko_rts::types::{Value, Words};
+use motoko_rts::constants::WASM_PAGE_SIZE;
+use motoko_rts::memory::{alloc_blob, wasm_pages_needed, Memory};
+use motoko_rts::types::{Bytes, Value, Words};
+
+use std::time::Instant;
 
 pub struct TestMemory {
     heap: Box<[u8]>,
@@ -14,6 +17,14 @@ impl TestMemory {
         TestMemory { heap, hp }
     }
 
+    pub fn heap_ptr(&self) -> usize {
+        self.hp
+    }
+
+    pub fn set_heap_ptr(&mut self, hp: usize) {
+        self.hp = hp;
+    }
+
     unsafe fn grow_memory(&mut self, ptr: usize) {
         let heap_end = self.heap.as_ptr() as usize + self.heap.len();
         if ptr > heap_end {
@@ -41,3 +52,81 @@ impl Memory for TestMemory {
         Value::from_ptr(old_hp)
     }
 }
+
+pub unsafe fn test() {
+    println!("Testing memory ...");
+
+    test_wasm_pages_needed();
+    test_alloc_across_pages();
+    bench_alloc();
+}
+
+fn test_wasm_pages_needed() {
+    println!("  Testing wasm_pages_needed");
+
+    let page_size = u64::from(WASM_PAGE_SIZE.as_u32());
+    assert_eq!(wasm_pages_needed(0), 0);
+    assert_eq!(wasm_pages_needed(1), 1);
+    assert_eq!(wasm_pages_needed(page_size - 1), 1);
+    assert_eq!(wasm_pages_needed(page_size), 1);
+    assert_eq!(wasm_pages_needed(page_size + 1), 2);
+    assert_eq!(wasm_pages_needed(u64::from(u32::MAX) + 1), 65536);
+}
+
+/// Allocate blobs of different sizes over a few Wasm pages, check that they are contiguous and
+/// keep their contents
+unsafe fn test_alloc_across_pages() {
+    println!("  Testing allocation across pages");
+
+    let page_words = WASM_PAGE_SIZE.to_words();
+    let mut mem = TestMemory::new(page_words * 4);
+    let heap_start = mem.hp;
+
+    let mut blobs = vec![];
+    let mut next = heap_start;
+    let mut size = 1;
+    while next < heap_start + 5 * WASM_PAGE_SIZE.as_usize() / 2 {
+        let blob = alloc_blob(&mut mem, Bytes(size));
+        assert_eq!(blob.get_ptr(), next);
+
+        let blob = blob.as_blob_mut();
+        for i in 0..size {
+            blob.set(i, (size + i) as u8);
+        }
+
+        next = mem.hp;
+        blobs.push((blob, size));
+        size = size * 3 % 4093 + 1;
+    }
+
+    assert!(wasm_pages_needed((mem.hp - heap_start) as u64) > 2);
+
+    for (blob, size) in blobs {
+        assert_eq!(blob.len(), Bytes(size));
+        for i in 0..size {
+            assert_eq!(blob.get(i), (size + i) as u8);
+        }
+    }
+}
+
+/// Allocate many small objects
+unsafe fn bench_alloc() {
+    println!("  Benchmarking allocation");
+
+    const N_OBJS: u32 = 1_000_000;
+    let obj_size = Words(3);
+    let mut mem = TestMemory::new(obj_size * N_OBJS);
+
+    let start = Instant::now();
+    for _ in 0..N_OBJS {
+        mem.alloc_words(obj_size);
+    }
+    let time = start.elapsed();
+
+    println!(
+        "    {} allocations of {} words: {:?}",
+        N_OBJS,
+        obj_size.as_u32(),
+        time
+    );
+}
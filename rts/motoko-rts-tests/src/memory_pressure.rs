@@ -0,0 +1,135 @@
+use crate::array::{collect, root_field, static_roots};
+use crate::memory::TestMemory;
+
+use motoko_rts::memory::{alloc_array, alloc_blob, Memory};
+use motoko_rts::memory_pressure::{
+    memory_pressure_handler_running, register_memory_pressure_handler, relieve_memory_pressure,
+    set_memory_pressure_high_water_mark, DEFAULT_HIGH_WATER_MARK, NO_HANDLER,
+};
+use motoko_rts::types::{Bytes, Value, Words};
+
+pub unsafe fn test() {
+    println!("Testing memory pressure handler ...");
+
+    let mut mem = TestMemory::new(Words(256 * 1024));
+
+    test_threshold(&mut mem);
+    test_reentrancy(&mut mem);
+    test_cache_collected();
+
+    register_memory_pressure_handler(NO_HANDLER);
+    set_memory_pressure_high_water_mark(DEFAULT_HIGH_WATER_MARK.as_u32());
+}
+
+/// Call the handler if the heap has `heap_size` bytes, returns whether it was called
+unsafe fn handler_called(heap_size: u32) -> bool {
+    let mut called = false;
+    let relieved = relieve_memory_pressure(Bytes(heap_size), |_| called = true);
+    assert_eq!(relieved, called);
+    called
+}
+
+unsafe fn test_threshold<M: Memory>(mem: &mut M) {
+    println!("  Testing high-water mark");
+
+    set_memory_pressure_high_water_mark(1000);
+
+    // Not called without handler
+    assert!(!handler_called(2000));
+
+    let handler = alloc_blob(mem, Bytes(0));
+    register_memory_pressure_handler(handler);
+
+    // Called with the registered handler when reaching the high-water mark
+    assert!(!handler_called(999));
+    let mut handler_arg = NO_HANDLER;
+    assert!(relieve_memory_pressure(Bytes(1000), |h| handler_arg = h));
+    assert!(handler_arg == handler);
+
+    // Not called again until the heap was below the high-water mark
+    assert!(!handler_called(1000));
+    assert!(!handler_called(5000));
+    assert!(!handler_called(999));
+    assert!(handler_called(1001));
+
+    // Removing the handler
+    register_memory_pressure_handler(NO_HANDLER);
+    assert!(!handler_called(0));
+    assert!(!handler_called(2000));
+}
+
+unsafe fn test_reentrancy<M: Memory>(mem: &mut M) {
+    println!("  Testing reentrancy");
+
+    set_memory_pressure_high_water_mark(1000);
+    let handler = alloc_blob(mem, Bytes(0));
+    register_memory_pressure_handler(handler);
+    assert!(!handler_called(0));
+
+    assert!(!memory_pressure_handler_running());
+    let mut nested_called = true;
+    assert!(relieve_memory_pressure(Bytes(2000), |_| {
+        assert!(memory_pressure_handler_running());
+        // Heap below and above the mark again, still not called
+        nested_called = handler_called(0) || handler_called(2000);
+    }));
+    assert!(!nested_called);
+    assert!(!memory_pressure_handler_running());
+
+    register_memory_pressure_handler(NO_HANDLER);
+}
+
+/// A cache dropped by the handler is reclaimed by the next GC, and the handler survives the GC
+unsafe fn test_cache_collected() {
+    println!("  Testing cache collected");
+
+    let mut mem = TestMemory::new(Words(256 * 1024));
+    let (roots, heap_base) = static_roots(&mut mem, 1);
+
+    let cache_size = 64;
+    let cache = alloc_array(&mut mem, cache_size);
+    for i in 0..cache_size {
+        let entry = alloc_blob(&mut mem, Bytes(1024));
+        cache.as_array().set(i, entry);
+    }
+    *root_field(roots, 0) = cache;
+
+    // The handler stands for a closure clearing the cache in root 0. Allocated after the cache, so
+    // it is moved when the cache is reclaimed.
+    let handler = alloc_array(&mut mem, 1);
+    handler.as_array().set(0, Value::from_scalar(42));
+    register_memory_pressure_handler(handler);
+
+    let heap_size = |mem: &TestMemory| Bytes(mem.heap_ptr() as u32 - heap_base);
+
+    set_memory_pressure_high_water_mark(cache_size * 1024);
+    assert!(!handler_called(0));
+
+    // Nothing is reclaimed while the cache is reachable
+    collect(&mut mem, heap_base, roots);
+    let full_size = heap_size(&mem);
+    assert!(full_size.as_u32() > cache_size * 1024);
+
+    let mut dropped = false;
+    let relieved = relieve_memory_pressure(heap_size(&mem), |h| {
+        assert!(h.as_array().get(0) == Value::from_scalar(42));
+        *root_field(roots, 0) = Value::from_scalar(0);
+        dropped = true;
+    });
+    assert!(relieved && dropped);
+
+    collect(&mut mem, heap_base, roots);
+    let relieved_size = heap_size(&mem);
+    assert!(full_size.as_u32() - relieved_size.as_u32() >= cache_size * 1024);
+
+    // The handler is a root, it survives the GC and is updated when moved
+    assert!(!handler_called(0));
+    set_memory_pressure_high_water_mark(0);
+    let mut handler_arg = NO_HANDLER;
+    assert!(relieve_memory_pressure(relieved_size, |h| handler_arg = h));
+    assert!(handler_arg != handler);
+    assert!(handler_arg.as_array().len() == 1);
+    assert!(handler_arg.as_array().get(0) == Value::from_scalar(42));
+
+    register_memory_pressure_handler(NO_HANDLER);
+}
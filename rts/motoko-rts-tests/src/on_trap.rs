@@ -0,0 +1,41 @@
+use crate::memory::TestMemory;
+
+use motoko_rts::on_trap::{on_trap_handlers, on_trap_next, on_trap_register, on_trap_set_handlers};
+use motoko_rts::types::{Value, Words};
+
+pub unsafe fn test() {
+    println!("Testing on-trap handlers ...");
+
+    let mut mem = TestMemory::new(Words(1024));
+
+    // No handlers registered
+    assert!(on_trap_next().is_scalar());
+
+    // Handlers are returned last registered first. Scalars stand in for closures.
+    for i in 1..=3 {
+        on_trap_register(&mut mem, Value::from_scalar(i));
+    }
+
+    // Handlers stored in a continuation table entry before an await
+    let stored = on_trap_handlers();
+
+    for i in (1..=3).rev() {
+        assert_eq!(on_trap_next().get_scalar(), i);
+    }
+    assert!(on_trap_next().is_scalar());
+
+    // Clearing at the start of a message, restoring in a callback
+    on_trap_register(&mut mem, Value::from_scalar(4));
+    on_trap_set_handlers(Value::from_scalar(0));
+    assert!(on_trap_next().is_scalar());
+
+    on_trap_set_handlers(stored);
+    on_trap_register(&mut mem, Value::from_scalar(5));
+    for i in [5, 3, 2, 1] {
+        assert_eq!(on_trap_next().get_scalar(), i);
+    }
+    assert!(on_trap_next().is_scalar());
+
+    // The handlers are a GC root, don't leave pointers to the test heap
+    on_trap_set_handlers(Value::from_scalar(0));
+}
@@ -0,0 +1,217 @@
+//! Tests for mark-compact GC threading with 32-bit and 64-bit words
+
+use crate::trap::traps_with;
+
+use motoko_rts::assert_level::AssertLevel;
+use motoko_rts::pointer_width::{
+    min_heap_base, thread, thread_checked, threaded_tag, unthread, unthread_checked, PointerWidth,
+    Width32, Width64,
+};
+use motoko_rts::types::*;
+
+/// Object tags and fields, fields are indices of the pointed objects
+type Graph = Vec<(Tag, Vec<usize>)>;
+
+/// Objects 2 and 5 are unreachable from the root
+const OBJECTS: &[(Tag, &[usize])] = &[
+    (TAG_ARRAY, &[1, 3]),
+    (TAG_MUTBOX, &[0]),
+    (TAG_SOME, &[2]),
+    (TAG_ARRAY, &[3, 1, 4]),
+    (TAG_MUTBOX, &[0]),
+    (TAG_SOME, &[4]),
+];
+
+const ROOTS: &[usize] = &[3];
+
+pub unsafe fn test() {
+    println!("Testing pointer widths ...");
+
+    println!("  Testing minimum heap base");
+    assert_eq!(min_heap_base::<Width32>(), 40);
+    assert_eq!(min_heap_base::<Width64>(), 40);
+
+    println!("  Testing compaction");
+    let expected_graph: Graph = vec![
+        (TAG_ARRAY, vec![1, 2]),
+        (TAG_MUTBOX, vec![0]),
+        (TAG_ARRAY, vec![2, 1, 3]),
+        (TAG_MUTBOX, vec![0]),
+    ];
+    let expected_roots = vec![2];
+
+    let (graph_32, roots_32) = compact::<Width32>();
+    assert_eq!(graph_32, expected_graph);
+    assert_eq!(roots_32, expected_roots);
+
+    let (graph_64, roots_64) = compact::<Width64>();
+    assert_eq!(graph_64, expected_graph);
+    assert_eq!(roots_64, expected_roots);
+
+    println!("  Testing assertion levels");
+    test_assert_levels();
+    test_corrupted_header::<Width32>();
+    test_corrupted_header::<Width64>();
+}
+
+fn test_assert_levels() {
+    use AssertLevel::*;
+    assert!(Off.checks(Off) && !Off.checks(Cheap) && !Off.checks(Full));
+    assert!(Cheap.checks(Off) && Cheap.checks(Cheap) && !Cheap.checks(Full));
+    assert!(Full.checks(Off) && Full.checks(Cheap) && Full.checks(Full));
+}
+
+/// Odd, so it looks like a tag, but not the tag of an object
+const CORRUPTED_TAG: usize = MAX_TAG as usize + 2;
+
+/// A corrupted object header is caught by threading and unthreading at the `Cheap` level, and
+/// copied as is at the `Off` level
+unsafe fn test_corrupted_header<W: PointerWidth>() {
+    // A field pointing to an object with one field
+    let mut slots = vec![Slot(0); 3];
+    let slots_addr = slots.as_mut_ptr() as usize;
+    let field = slots_addr;
+    let obj = slots_addr + W::WORD_SIZE;
+    assert!(obj >= min_heap_base::<W>());
+
+    let reset = |header: usize| {
+        W::store(field, skew(obj));
+        W::store(obj, header);
+    };
+
+    // Valid headers are threaded and unthreaded at all levels
+    for level in [AssertLevel::Off, AssertLevel::Cheap, AssertLevel::Full] {
+        reset(TAG_MUTBOX as usize);
+        thread_checked::<W>(field, level);
+        unthread_checked::<W>(obj, obj, level);
+        assert_eq!(W::load(obj), TAG_MUTBOX as usize);
+        assert_eq!(W::load(field), skew(obj));
+    }
+
+    // Corrupted before threading
+    reset(CORRUPTED_TAG);
+    assert!(traps_with("thread: invalid object tag", || {
+        thread_checked::<W>(field, AssertLevel::Cheap)
+    }));
+    thread_checked::<W>(field, AssertLevel::Off);
+    assert_eq!(W::load(field), CORRUPTED_TAG);
+
+    // Corrupted after threading, in the threaded field
+    reset(TAG_MUTBOX as usize);
+    thread_checked::<W>(field, AssertLevel::Cheap);
+    W::store(field, CORRUPTED_TAG);
+    assert!(traps_with("unthread: invalid object tag", || {
+        unthread_checked::<W>(obj, obj, AssertLevel::Cheap)
+    }));
+    unthread_checked::<W>(obj, obj, AssertLevel::Off);
+    assert_eq!(W::load(obj), CORRUPTED_TAG);
+    assert_eq!(W::load(field), skew(obj));
+}
+
+/// Large enough and aligned for words of both widths
+#[repr(C, align(8))]
+#[derive(Clone, Copy)]
+struct Slot(u64);
+
+/// Lay out `OBJECTS` in a heap of words of width `W`, and compact it with threading as done by
+/// the mark-compact GC. Returns the objects and roots after compaction.
+unsafe fn compact<W: PointerWidth>() -> (Graph, Vec<usize>) {
+    let sizes: Vec<usize> = OBJECTS.iter().map(|(_, fields)| 1 + fields.len()).collect();
+    let mut slots = vec![Slot(0); ROOTS.len() + sizes.iter().sum::<usize>()];
+    let slots_addr = slots.as_mut_ptr() as usize;
+    let word = |idx: usize| slots_addr + idx * W::WORD_SIZE;
+
+    // Roots first, objects after
+    let mut obj_addrs = vec![];
+    let mut next = word(ROOTS.len());
+    for size in &sizes {
+        obj_addrs.push(next);
+        next += size * W::WORD_SIZE;
+    }
+    let field = |addr: usize, idx: usize| addr + (idx + 1) * W::WORD_SIZE;
+
+    assert!(obj_addrs[0] >= min_heap_base::<W>());
+
+    for (obj, (tag, fields)) in OBJECTS.iter().enumerate() {
+        W::store(obj_addrs[obj], *tag as usize);
+        for (idx, pointed) in fields.iter().enumerate() {
+            W::store(field(obj_addrs[obj], idx), skew(obj_addrs[*pointed]));
+        }
+    }
+    for (idx, root) in ROOTS.iter().enumerate() {
+        W::store(word(idx), skew(obj_addrs[*root]));
+    }
+
+    // Mark: thread the roots, and the backward and self pointers of the live objects
+    let mut live = vec![false; OBJECTS.len()];
+    let mut stack: Vec<usize> = ROOTS.to_vec();
+    while let Some(obj) = stack.pop() {
+        if !live[obj] {
+            live[obj] = true;
+            stack.extend(OBJECTS[obj].1);
+        }
+    }
+    let live: Vec<usize> = (0..OBJECTS.len()).filter(|obj| live[*obj]).collect();
+
+    for idx in 0..ROOTS.len() {
+        thread::<W>(word(idx));
+    }
+    for obj in &live {
+        for (idx, pointed) in OBJECTS[*obj].1.iter().enumerate() {
+            if pointed <= obj {
+                thread::<W>(field(obj_addrs[*obj], idx));
+            }
+        }
+    }
+
+    // Compact: unthread the pointers to each live object, move it, and thread its forward
+    // pointers
+    let mut free = obj_addrs[0];
+    let mut new_addrs = vec![];
+    for obj in &live {
+        let addr = obj_addrs[*obj];
+        assert_eq!(threaded_tag::<W>(addr), OBJECTS[*obj].0);
+
+        unthread::<W>(addr, free);
+        for idx in 0..sizes[*obj] {
+            W::store(
+                free + idx * W::WORD_SIZE,
+                W::load(addr + idx * W::WORD_SIZE),
+            );
+        }
+
+        for idx in 0..OBJECTS[*obj].1.len() {
+            if unskew(W::load(field(free, idx))) > free {
+                thread::<W>(field(free, idx));
+            }
+        }
+
+        new_addrs.push(free);
+        free += sizes[*obj] * W::WORD_SIZE;
+    }
+
+    let new_idx = |value: usize| {
+        new_addrs
+            .iter()
+            .position(|addr| *addr == unskew(value))
+            .unwrap()
+    };
+
+    let graph = live
+        .iter()
+        .enumerate()
+        .map(|(new_obj, obj)| {
+            let addr = new_addrs[new_obj];
+            let fields = (0..OBJECTS[*obj].1.len())
+                .map(|idx| new_idx(W::load(field(addr, idx))))
+                .collect();
+            (W::load(addr) as Tag, fields)
+        })
+        .collect();
+
+    let roots = (0..ROOTS.len())
+        .map(|idx| new_idx(W::load(word(idx))))
+        .collect();
+
+    (graph, roots)
+}
@@ -1,8 +1,10 @@
 use crate::memory::TestMemory;
+use crate::trap::traps_with;
 
+use motoko_rts::memory::Memory;
 use motoko_rts::principal_id::{blob_of_principal, principal_of_blob};
 use motoko_rts::text::{text_compare, text_of_ptr_size, text_of_str};
-use motoko_rts::types::{Bytes, Words};
+use motoko_rts::types::{Bytes, Value, Words};
 
 pub unsafe fn test() {
     println!("Testing principal id encoding ...");
@@ -46,4 +48,69 @@ pub unsafe fn test() {
         ),
         0
     );
+
+    //
+    // Round trips
+    //
+
+    let principals: &[(&[u8], &str)] = &[
+        // Management canister
+        (b"", "aaaaa-aa"),
+        // Anonymous principal
+        (b"\x04", "2vxsx-fae"),
+        // Canister ids
+        (
+            b"\x00\x00\x00\x00\x00\x00\x00\x01\x01\x01",
+            "rrkah-fqaaa-aaaaa-aaaaq-cai",
+        ),
+        (
+            b"\x00\x00\x00\x00\x00\x00\x00\x02\x01\x01",
+            "ryjl3-tyaaa-aaaaa-aaaba-cai",
+        ),
+        // Maximum length
+        (
+            b"\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\x10\x11\x12\x13\x14\x15\x16\x17\x18\x19\x1a\x1b\x1c",
+            "2mhjn-ayaae-bagba-faydq-qcikb-mga2d-qpcai-reeyu-culbo-gazdi-nry",
+        ),
+    ];
+
+    for (bytes, str) in principals {
+        let blob = blob_of_bytes(&mut heap, bytes);
+        let text = principal_of_blob(&mut heap, blob);
+        assert_eq!(text_compare(text, text_of_str(&mut heap, str)), 0);
+
+        let text = text_of_str(&mut heap, str);
+        let decoded = blob_of_principal(&mut heap, text);
+        assert_eq!(text_compare(decoded, blob), 0);
+    }
+
+    //
+    // Invalid principals
+    //
+
+    for str in [
+        // Wrong checksum
+        "2vxsx-faf",
+        "ryjl3-tyaaa-aaaaa-aaaaq-cai",
+        // Wrong grouping
+        "2vxsxfae",
+        "2vxs-xfae",
+        "ryjl3-tyaaa-aaaaa-aaaba-ca-i",
+        // Not lowercase
+        "2VXSX-FAE",
+    ] {
+        let text = text_of_str(&mut heap, str);
+        assert!(traps_with("invalid principal", || {
+            blob_of_principal(&mut heap, text);
+        }));
+    }
+
+    let text = text_of_str(&mut heap, "aaaaa");
+    assert!(traps_with("principal too short", || {
+        blob_of_principal(&mut heap, text);
+    }));
+}
+
+unsafe fn blob_of_bytes<M: Memory>(mem: &mut M, bytes: &[u8]) -> Value {
+    text_of_ptr_size(mem, bytes.as_ptr(), Bytes(bytes.len() as u32))
 }
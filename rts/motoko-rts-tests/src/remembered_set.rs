@@ -0,0 +1,85 @@
+use crate::memory::TestMemory;
+
+use motoko_rts::gc::mark_compact::remembered_set::{RememberedSet, INIT_CAPACITY};
+use motoko_rts::types::{Value, Words};
+
+use std::collections::HashSet;
+
+use proptest::test_runner::{Config, TestCaseError, TestCaseResult, TestRunner};
+
+pub unsafe fn test() {
+    println!("Testing remembered set ...");
+
+    let mut proptest_runner = TestRunner::new(Config {
+        cases: 100,
+        failure_persistence: None,
+        ..Default::default()
+    });
+
+    // Up to 4x the initial capacity, to test growing the table a few times. Duplicates in the
+    // input test that addresses are not added twice.
+    proptest_runner
+        .run(
+            &proptest::collection::vec(1u32..INIT_CAPACITY * 2, 0..(INIT_CAPACITY * 4) as usize),
+            |words| {
+                let mut mem = TestMemory::new(Words(1024 * 1024));
+                test_insert_iter(&mut mem, words)
+            },
+        )
+        .unwrap();
+}
+
+fn test_insert_iter(mem: &mut TestMemory, words: Vec<u32>) -> TestCaseResult {
+    // Word-aligned field addresses, the set doesn't dereference them
+    let fields: Vec<*mut Value> = words.iter().map(|word| (word * 4) as *mut Value).collect();
+
+    let expected: HashSet<*mut Value> = fields.iter().copied().collect();
+
+    unsafe {
+        let mut set = RememberedSet::new(mem);
+
+        for field in &fields {
+            set.insert(mem, *field);
+        }
+
+        if set.len() as usize != expected.len() {
+            return Err(TestCaseError::Fail(
+                format!(
+                    "Unexpected set size: expected={}, found={}",
+                    expected.len(),
+                    set.len()
+                )
+                .into(),
+            ));
+        }
+
+        for field in &expected {
+            if !set.contains(*field) {
+                return Err(TestCaseError::Fail(
+                    format!("Field {:#x} not in set", *field as usize).into(),
+                ));
+            }
+        }
+
+        if set.contains((INIT_CAPACITY * 16) as *mut Value) {
+            return Err(TestCaseError::Fail(
+                "Set contains a field not inserted".into(),
+            ));
+        }
+
+        let iterated: Vec<*mut Value> = set.iter().collect();
+        let iterated_set: HashSet<*mut Value> = iterated.iter().copied().collect();
+
+        if iterated.len() != iterated_set.len() || iterated_set != expected {
+            return Err(TestCaseError::Fail(
+                format!(
+                    "Unexpected iteration result: expected={:?}, found={:?}",
+                    expected, iterated
+                )
+                .into(),
+            ));
+        }
+    }
+
+    Ok(())
+}
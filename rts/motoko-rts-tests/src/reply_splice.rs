@@ -0,0 +1,177 @@
+//! Tests for splicing blobs into replies, against a mock `msg_reply_data_append_moc`. The mock
+//! implements the function generated by `moc` for the RTS.
+
+use crate::idl_stream::{leb128, make_blob};
+use crate::memory::TestMemory;
+use crate::trap::traps_with;
+
+use motoko_rts::reply_splice::{
+    reply_splice_append, reply_splice_begin, reply_splice_blob_size, reply_splice_write_blob,
+    MAX_SPLICES, SPLICE_THRESHOLD,
+};
+use motoko_rts::types::{Value, Words};
+
+/// Chunks appended to the reply
+static mut REPLY: Vec<Vec<u8>> = Vec::new();
+
+#[no_mangle]
+unsafe extern "C" fn msg_reply_data_append_moc(src: *const u8, size: u32) {
+    let chunk = std::slice::from_raw_parts(src, size as usize).to_vec();
+    (*std::ptr::addr_of_mut!(REPLY)).push(chunk);
+}
+
+/// Header of a message with `n` blob arguments
+fn header(n: usize) -> Vec<u8> {
+    let mut bytes = b"DIDL\x01\x6d\x7b".to_vec();
+    leb128(&mut bytes, n as u32);
+    bytes.extend(std::iter::repeat_n(0x00, n));
+    bytes
+}
+
+/// Expected encoding of the blob arguments
+fn encoding(blobs: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = header(blobs.len());
+    for blob in blobs {
+        leb128(&mut bytes, blob.len() as u32);
+        bytes.extend_from_slice(blob);
+    }
+    bytes
+}
+
+pub unsafe fn test() {
+    println!("Testing reply splicing ...");
+
+    test_large_blob();
+    test_many_blobs();
+    test_not_splicing();
+    test_mismatch();
+}
+
+/// Serialize the blobs like the generated serializer: compute the buffer size, then write the
+/// buffer. Returns the buffer.
+unsafe fn serialize(blobs: &[Value]) -> Vec<u8> {
+    let mut size = header(blobs.len()).len();
+    for blob in blobs {
+        let mut len = vec![];
+        leb128(&mut len, blob.as_blob().len().as_u32());
+        size += len.len() + reply_splice_blob_size(*blob) as usize;
+    }
+
+    let mut buf = header(blobs.len());
+    buf.reserve_exact(size - buf.len());
+    for blob in blobs {
+        leb128(&mut buf, blob.as_blob().len().as_u32());
+        let written = reply_splice_write_blob(buf.as_mut_ptr().add(buf.len()), *blob);
+        buf.set_len(buf.len() + written as usize);
+    }
+
+    assert_eq!(buf.len(), size);
+    buf
+}
+
+/// Serialize and reply with the blobs. Returns the data buffer and the reply.
+unsafe fn reply(blobs: &[Value]) -> (Vec<u8>, Vec<Vec<u8>>) {
+    (*std::ptr::addr_of_mut!(REPLY)).clear();
+    let buf = serialize(blobs);
+    reply_splice_append(buf.as_ptr(), buf.len() as u32);
+    (buf, std::mem::take(&mut *std::ptr::addr_of_mut!(REPLY)))
+}
+
+/// Bytes of blob `idx` of `len` bytes
+fn blob_bytes(idx: usize, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 13 + idx) as u8).collect()
+}
+
+/// A megabyte blob is not copied to the data buffer, and is appended in one chunk
+unsafe fn test_large_blob() {
+    println!("  Testing large blobs");
+
+    let large = blob_bytes(0, 1024 * 1024);
+    let small = blob_bytes(1, 100);
+    let threshold = blob_bytes(2, SPLICE_THRESHOLD as usize);
+    let below = blob_bytes(3, SPLICE_THRESHOLD as usize - 1);
+    let contents = vec![
+        small.clone(),
+        large.clone(),
+        below.clone(),
+        threshold.clone(),
+    ];
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+    let blobs: Vec<Value> = contents.iter().map(|b| make_blob(&mut mem, b)).collect();
+
+    reply_splice_begin();
+    let (buf, chunks) = reply(&blobs);
+
+    // The buffer has the small blobs, but not the large ones
+    assert_eq!(
+        buf.len(),
+        encoding(&contents).len() - large.len() - threshold.len()
+    );
+    assert_eq!(chunks.concat(), encoding(&contents));
+
+    // Buffer up to the large blob, large blob, buffer up to the blob at the threshold, blob at
+    // the threshold. The buffer ends with the spliced blob, so there is no last chunk.
+    assert_eq!(chunks.len(), 4);
+    assert_eq!(chunks[1], large);
+    assert_eq!(chunks[3], threshold);
+
+    // Splicing ends with the reply
+    let (buf, chunks) = reply(&blobs);
+    assert_eq!(buf, encoding(&contents));
+    assert_eq!(chunks, vec![encoding(&contents)]);
+}
+
+/// Blobs after the first `MAX_SPLICES` large blobs are copied
+unsafe fn test_many_blobs() {
+    println!("  Testing many large blobs");
+
+    let contents: Vec<Vec<u8>> = (0..MAX_SPLICES + 3)
+        .map(|idx| blob_bytes(idx, SPLICE_THRESHOLD as usize))
+        .collect();
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+    let blobs: Vec<Value> = contents.iter().map(|b| make_blob(&mut mem, b)).collect();
+
+    reply_splice_begin();
+    let (buf, chunks) = reply(&blobs);
+    assert_eq!(
+        buf.len(),
+        encoding(&contents).len() - MAX_SPLICES * SPLICE_THRESHOLD as usize
+    );
+    assert_eq!(chunks.concat(), encoding(&contents));
+    assert_eq!(chunks.len(), 2 * MAX_SPLICES + 1);
+}
+
+/// Without `reply_splice_begin` the buffer is contiguous
+unsafe fn test_not_splicing() {
+    println!("  Testing contiguous buffers");
+
+    let contents = vec![blob_bytes(0, 2 * SPLICE_THRESHOLD as usize), vec![]];
+
+    let mut mem = TestMemory::new(Words(128 * 1024));
+    let blobs: Vec<Value> = contents.iter().map(|b| make_blob(&mut mem, b)).collect();
+
+    assert_eq!(serialize(&blobs), encoding(&contents));
+}
+
+unsafe fn test_mismatch() {
+    println!("  Testing mismatched passes");
+
+    let mut mem = TestMemory::new(Words(32 * 1024));
+    let blob = make_blob(&mut mem, &blob_bytes(0, SPLICE_THRESHOLD as usize));
+
+    // Sized but not written
+    reply_splice_begin();
+    reply_splice_blob_size(blob);
+    assert!(traps_with(
+        "reply_splice_append: blobs sized and written differ",
+        || {
+            reply_splice_append(std::ptr::null(), 0);
+        }
+    ));
+
+    // Reset, an empty reply ends splicing
+    reply_splice_begin();
+    reply(&[]);
+}
@@ -0,0 +1,111 @@
+use crate::memory::TestMemory;
+use crate::trap::traps_with;
+
+use motoko_rts::memory::alloc_blob;
+use motoko_rts::rng::{rng_next, rng_seed, rng_set_state, rng_state, Rng, RNG_STATE_SIZE};
+use motoko_rts::types::{Bytes, Value, Words};
+
+pub unsafe fn test() {
+    println!("Testing rng ...");
+
+    println!("  Testing reference outputs");
+    // State `[1, 2, 3, 4]`, outputs of the reference implementation
+    let mut seed = vec![];
+    for word in [1u64, 2, 3, 4] {
+        seed.extend_from_slice(&word.to_le_bytes());
+    }
+    let mut rng = Rng::from_seed(&seed);
+    assert_eq!(rng.next_u64(), 11520);
+    assert_eq!(rng.next_u64(), 0);
+    assert_eq!(rng.next_u64(), 1509978240);
+    assert_eq!(rng.next_u64(), 1215971899390074240);
+
+    println!("  Testing determinism");
+    let mut rng1 = Rng::from_seed(b"some seed");
+    let mut rng2 = Rng::from_seed(b"some seed");
+    for _ in 0..1000 {
+        assert_eq!(rng1.next_u64(), rng2.next_u64());
+    }
+    let mut rng3 = Rng::from_seed(b"other seed");
+    assert!((0..10).any(|_| rng1.next_u64() != rng3.next_u64()));
+
+    // The all-zero seed is not a fixed point
+    let mut rng = Rng::from_seed(&[]);
+    assert!((0..10).any(|_| rng.next_u64() != 0));
+
+    println!("  Testing distribution");
+    test_mean_variance(&mut Rng::from_seed(b"mean/variance"));
+
+    println!("  Testing ranges");
+    test_ranges(&mut Rng::from_seed(b"ranges"));
+
+    println!("  Testing state round-trip");
+    let mut mem = TestMemory::new(Words(1024));
+    assert!(traps_with("rng: not seeded", || {
+        rng_next();
+    }));
+    rng_seed(make_blob(&mut mem, b"round-trip"));
+    rng_next();
+    let state = rng_state(&mut mem);
+    assert_eq!(state.as_blob().len(), Bytes(RNG_STATE_SIZE));
+    let expected: Vec<u64> = (0..100).map(|_| rng_next()).collect();
+    rng_seed(make_blob(&mut mem, b"reseeded"));
+    rng_set_state(state);
+    let actual: Vec<u64> = (0..100).map(|_| rng_next()).collect();
+    assert_eq!(actual, expected);
+
+    assert!(traps_with("rng_set_state: invalid state", || {
+        rng_set_state(make_blob(&mut mem, b""));
+    }));
+    assert!(traps_with("rng_set_state: invalid state", || {
+        rng_set_state(make_blob(&mut mem, &[0; RNG_STATE_SIZE as usize]));
+    }));
+}
+
+/// Smoke test: the mean and variance of the outputs, scaled to `[0, 1)`, are close to those of
+/// the uniform distribution
+fn test_mean_variance(rng: &mut Rng) {
+    const N: usize = 100_000;
+    let samples: Vec<f64> = (0..N)
+        .map(|_| rng.next_u64() as f64 / u64::MAX as f64)
+        .collect();
+    let mean = samples.iter().sum::<f64>() / N as f64;
+    let variance = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / N as f64;
+    assert!((mean - 0.5).abs() < 0.01, "mean = {}", mean);
+    assert!(
+        (variance - 1.0 / 12.0).abs() < 0.01,
+        "variance = {}",
+        variance
+    );
+}
+
+unsafe fn test_ranges(rng: &mut Rng) {
+    for n in [1, 2, 3, 7, 10, 1000, u64::MAX / 2 + 2, u64::MAX] {
+        for _ in 0..1000 {
+            assert!(rng.next_range(n) < n);
+        }
+    }
+
+    // All values of a small range are hit about equally often
+    const N: u64 = 6;
+    let mut counts = [0u32; N as usize];
+    for _ in 0..60_000 {
+        counts[rng.next_range(N) as usize] += 1;
+    }
+    for count in counts {
+        assert!((9_000..11_000).contains(&count), "counts = {:?}", counts);
+    }
+
+    assert!(traps_with("rng_next_range: empty range", || {
+        rng.next_range(0);
+    }));
+}
+
+unsafe fn make_blob(mem: &mut TestMemory, bytes: &[u8]) -> Value {
+    let blob = alloc_blob(mem, Bytes(bytes.len() as u32));
+    let blob_ptr = blob.as_blob_mut();
+    for (i, byte) in bytes.iter().enumerate() {
+        blob_ptr.set(i as u32, *byte);
+    }
+    blob
+}
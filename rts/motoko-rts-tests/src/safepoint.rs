@@ -0,0 +1,139 @@
+//! Tests for safepoints, with a mock instruction counter
+
+use crate::array::{root_field, static_roots};
+use crate::memory::TestMemory;
+use crate::trap::traps_with;
+
+use motoko_rts::gc::barrier::{BarrierMode, BARRIER_MODE};
+use motoko_rts::gc::mark_compact::incremental::{compacting_gc_incremental_internal, Phase, PHASE};
+use motoko_rts::gc::mark_compact::GcContext;
+use motoko_rts::gc::INSTRUCTION_COUNTER;
+use motoko_rts::instructions::{set_instruction_budget, DEFAULT_INSTRUCTION_BUDGET};
+use motoko_rts::memory::alloc_array;
+use motoko_rts::safepoint::{
+    safepoint, safepoint_gc_slices, set_safepoint_action, SafepointAction, SAFEPOINT_ACTION,
+};
+use motoko_rts::types::{Value, Words};
+
+static mut MOCK_COUNT: u64 = 0;
+
+fn mock_instruction_counter() -> u64 {
+    unsafe { MOCK_COUNT }
+}
+
+pub unsafe fn test() {
+    println!("Testing safepoints ...");
+
+    let instruction_counter = INSTRUCTION_COUNTER;
+    INSTRUCTION_COUNTER = mock_instruction_counter;
+    set_instruction_budget(1_000);
+
+    test_budget_left();
+    test_collect();
+    test_trap();
+
+    set_instruction_budget(DEFAULT_INSTRUCTION_BUDGET);
+    INSTRUCTION_COUNTER = instruction_counter;
+}
+
+/// Call `safepoint` `n` times
+unsafe fn safepoints(mem: &mut TestMemory, n: u32) {
+    for _ in 0..n {
+        safepoint(mem);
+    }
+}
+
+unsafe fn test_budget_left() {
+    println!("  Testing safepoints with budget left");
+
+    let mut mem = TestMemory::new(Words(1024));
+    MOCK_COUNT = 999;
+    set_safepoint_action(SafepointAction::Trap as u32);
+    safepoints(&mut mem, 3);
+    set_safepoint_action(SafepointAction::Collect as u32);
+}
+
+/// Incremental GC slice of `budget` words. Returns whether the GC cycle is done.
+unsafe fn gc_slice(mem: &mut TestMemory, heap_base: u32, roots: Value, budget: u32) -> bool {
+    let mem_ptr: *mut TestMemory = mem;
+    let mut continuation_table = Value::from_scalar(0);
+    compacting_gc_incremental_internal(
+        mem,
+        GcContext {
+            heap_base,
+            get_hp: || (*mem_ptr).heap_ptr(),
+            set_hp: |hp| (*mem_ptr).set_heap_ptr(hp as usize),
+            static_roots: roots,
+            continuation_table_ptr_loc: &mut continuation_table,
+        },
+        Words(1024),
+        Words(budget),
+        // note_live_size
+        |_| {},
+        // note_reclaimed
+        |_| {},
+    );
+    PHASE == Phase::Idle
+}
+
+/// Once the budget is used up, the marking in progress is continued at a safepoint
+unsafe fn test_collect() {
+    println!("  Testing GC slices at safepoints");
+
+    const LIST_LEN: u32 = 1_000;
+
+    let mut mem = TestMemory::new(Words(64 * 1024));
+    let (roots, heap_base) = static_roots(&mut mem, 1);
+
+    // A list, marked one node at a time
+    for _ in 0..LIST_LEN {
+        let node = alloc_array(&mut mem, 1);
+        node.as_array().set(0, *root_field(roots, 0));
+        *root_field(roots, 0) = node;
+    }
+
+    // Marking is only left in progress with the write barrier
+    BARRIER_MODE = BarrierMode::SnapshotAtTheBeginning;
+
+    // Start marking
+    assert!(!gc_slice(&mut mem, heap_base, roots, 1));
+    assert!(PHASE == Phase::Marking);
+
+    MOCK_COUNT = 1_000;
+    let slices = safepoint_gc_slices();
+
+    // Without the write barrier, marking cannot continue in the middle of a message
+    BARRIER_MODE = BarrierMode::Disabled;
+    safepoint(&mut mem);
+    assert_eq!(safepoint_gc_slices(), slices);
+    BARRIER_MODE = BarrierMode::SnapshotAtTheBeginning;
+
+    safepoint(&mut mem);
+    assert_eq!(safepoint_gc_slices(), slices + 1);
+
+    // The safepoint marked the list and did not compact: the next slice only compacts
+    assert!(PHASE == Phase::Marking);
+    assert!(gc_slice(&mut mem, heap_base, roots, 1));
+
+    // No marking in progress, safepoints don't start a GC cycle
+    safepoints(&mut mem, 3);
+    assert!(PHASE == Phase::Idle);
+    assert_eq!(safepoint_gc_slices(), slices + 1);
+
+    BARRIER_MODE = BarrierMode::Disabled;
+}
+
+unsafe fn test_trap() {
+    println!("  Testing traps at safepoints");
+
+    let mut mem = TestMemory::new(Words(1024));
+    MOCK_COUNT = 2_000;
+    set_safepoint_action(SafepointAction::Trap as u32);
+    assert!(traps_with("instruction budget exhausted", || safepoint(
+        &mut mem
+    )));
+
+    assert!(traps_with("unknown action", || set_safepoint_action(2)));
+    assert!(SAFEPOINT_ACTION == SafepointAction::Trap);
+    set_safepoint_action(SafepointAction::Collect as u32);
+}
@@ -0,0 +1,187 @@
+use motoko_rts::gc::scheduler::GcScheduler;
+use motoko_rts::types::Bytes;
+
+const KIB: u32 = 1024;
+const MIB: u32 = 1024 * 1024;
+
+/// Large enough to not affect the tests
+const MAX_LIVE: Bytes<u64> = Bytes(3 * 1024 * 1024 * 1024);
+
+pub unsafe fn test() {
+    println!("Testing GC scheduler ...");
+
+    test_steady_allocation();
+    test_bursty_allocation();
+    test_live_size_trend();
+    test_heap_limit();
+    test_reservation();
+}
+
+/// Simulate messages allocating the given amounts, with a constant live size. Returns indices of
+/// messages after which GC is done.
+fn simulate(scheduler: &mut GcScheduler, live: u32, allocations: &[u32]) -> Vec<usize> {
+    simulate_max_heap(scheduler, live, allocations).0
+}
+
+/// Like `simulate`, also returns the largest heap size reached
+fn simulate_max_heap(
+    scheduler: &mut GcScheduler,
+    live: u32,
+    allocations: &[u32],
+) -> (Vec<usize>, u32) {
+    let mut gcs = vec![];
+    let mut max_heap = 0;
+
+    let mut heap_size = live;
+    let mut allocated = 0;
+
+    for (message, allocation) in allocations.iter().enumerate() {
+        heap_size += allocation;
+        allocated += allocation;
+        max_heap = max_heap.max(heap_size);
+
+        if scheduler.should_do_gc(Bytes(heap_size), Bytes(allocated), MAX_LIVE) {
+            gcs.push(message);
+            heap_size = live;
+            allocated = 0;
+            scheduler.note_gc(Bytes(live));
+        }
+    }
+
+    (gcs, max_heap)
+}
+
+fn test_scheduler() -> GcScheduler {
+    let mut scheduler = GcScheduler::new();
+    scheduler.growth_factor = 2.0;
+    scheduler.min_interval = Bytes(100 * KIB);
+    scheduler
+}
+
+fn test_steady_allocation() {
+    println!("  Testing steady allocation");
+
+    let mut scheduler = test_scheduler();
+    scheduler.note_gc(Bytes(MIB));
+
+    // Heap grows from 1 MiB to 2 MiB in 16 messages
+    let gcs = simulate(&mut scheduler, MIB, &[64 * KIB; 160]);
+
+    let expected: Vec<usize> = (15..160).step_by(16).collect();
+    assert_eq!(gcs, expected);
+}
+
+fn test_bursty_allocation() {
+    println!("  Testing bursty allocation");
+
+    let mut scheduler = test_scheduler();
+    scheduler.note_gc(Bytes(MIB));
+
+    // A message allocating 4 MiB, followed by 20 messages allocating 1 KiB each, repeated. Small
+    // allocations are below the minimum interval, so GC is done only after the bursts.
+    let mut allocations = vec![];
+    for _ in 0..10 {
+        allocations.push(4 * MIB);
+        allocations.extend([KIB; 20]);
+    }
+
+    let gcs = simulate(&mut scheduler, MIB, &allocations);
+
+    let expected: Vec<usize> = (0..allocations.len()).step_by(21).collect();
+    assert_eq!(gcs, expected);
+}
+
+fn test_live_size_trend() {
+    println!("  Testing live size trend");
+
+    let mut scheduler = test_scheduler();
+    scheduler.note_gc(Bytes(MIB));
+
+    // Without a trend the heap can grow to 2x the live size
+    assert!(!scheduler.should_do_gc(Bytes(2 * MIB - 1), Bytes(MIB), MAX_LIVE));
+    assert!(scheduler.should_do_gc(Bytes(2 * MIB), Bytes(MIB), MAX_LIVE));
+
+    // Live size grows by 1 MiB: projected live size is 2.5 MiB, so the heap can grow to 5 MiB
+    scheduler.note_gc(Bytes(2 * MIB));
+    assert!(!scheduler.should_do_gc(Bytes(4 * MIB), Bytes(2 * MIB), MAX_LIVE));
+    assert!(scheduler.should_do_gc(Bytes(5 * MIB), Bytes(3 * MIB), MAX_LIVE));
+
+    // Heap close to the maximum: GC even when below the minimum interval
+    assert!(scheduler.should_do_gc(Bytes(3 * 1024 * MIB), Bytes(KIB), MAX_LIVE));
+}
+
+fn test_heap_limit() {
+    println!("  Testing heap limit");
+
+    // Messages allocating 1 MiB of garbage each. With a large growth factor the heap grows beyond
+    // 8 MiB before GC.
+    let allocations = [MIB; 50];
+    let mut scheduler = test_scheduler();
+    scheduler.growth_factor = 100.0;
+    scheduler.note_gc(Bytes(MIB));
+    let (gcs, max_heap) = simulate_max_heap(&mut scheduler, MIB, &allocations);
+    assert!(gcs.is_empty());
+    assert!(max_heap > 8 * MIB);
+
+    // With an 8 MiB limit the garbage is collected before the heap reaches the limit: heap is
+    // collected when halfway between the heap size after the last GC and the limit
+    let mut scheduler = test_scheduler();
+    scheduler.growth_factor = 100.0;
+    scheduler.heap_limit = Some(Bytes(8 * MIB));
+    scheduler.note_gc(Bytes(MIB));
+    let (gcs, max_heap) = simulate_max_heap(&mut scheduler, MIB, &allocations);
+    let expected: Vec<usize> = (3..50).step_by(4).collect();
+    assert_eq!(gcs, expected);
+    assert_eq!(max_heap, 5 * MIB);
+
+    // The limit does not increase the maximum heap size
+    scheduler.heap_limit = Some(Bytes(u32::MAX));
+    assert!(scheduler.should_do_gc(Bytes(3 * 1024 * MIB), Bytes(KIB), MAX_LIVE));
+}
+
+fn test_reservation() {
+    println!("  Testing heap reservation");
+
+    // Warmup of a new canister: live size grows to 4 MiB in 64 KiB steps, nothing is garbage
+    let warmup = [64 * KIB; 64];
+
+    // Without a reservation the heap is collected repeatedly while growing
+    let mut scheduler = test_scheduler();
+    assert!(!simulate_growth(&mut scheduler, &warmup).is_empty());
+
+    // Within an 8 MiB reservation no GC is done
+    let mut scheduler = test_scheduler();
+    scheduler.reservation = Bytes(8 * MIB);
+    assert_eq!(simulate_growth(&mut scheduler, &warmup), vec![]);
+
+    // Beyond the reservation GC is scheduled as usual
+    scheduler.note_gc(Bytes(8 * MIB));
+    assert!(!scheduler.should_do_gc(Bytes(8 * MIB), Bytes(4 * MIB), MAX_LIVE));
+    assert!(scheduler.should_do_gc(Bytes(16 * MIB), Bytes(8 * MIB), MAX_LIVE));
+
+    // The reservation does not increase the maximum heap size
+    scheduler.reservation = Bytes(u32::MAX);
+    assert!(scheduler.should_do_gc(Bytes(3 * 1024 * MIB), Bytes(KIB), MAX_LIVE));
+}
+
+/// Simulate messages allocating the given amounts, all of it live. Returns indices of messages
+/// after which GC is done.
+fn simulate_growth(scheduler: &mut GcScheduler, allocations: &[u32]) -> Vec<usize> {
+    let mut gcs = vec![];
+
+    let mut heap_size = 0;
+    let mut allocated = 0;
+
+    for (message, allocation) in allocations.iter().enumerate() {
+        heap_size += allocation;
+        allocated += allocation;
+
+        if scheduler.should_do_gc(Bytes(heap_size), Bytes(allocated), MAX_LIVE) {
+            gcs.push(message);
+            allocated = 0;
+            scheduler.note_gc(Bytes(heap_size));
+        }
+    }
+
+    gcs
+}
@@ -0,0 +1,51 @@
+use crate::memory::TestMemory;
+
+use motoko_rts::memory::{alloc_blob, Memory};
+use motoko_rts::self_test::self_test_internal;
+use motoko_rts::types::{Bytes, Value, Words};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// `TestMemory` shared with the heap pointer callbacks of the self-test
+#[derive(Clone)]
+struct SharedMemory(Rc<RefCell<TestMemory>>);
+
+impl Memory for SharedMemory {
+    unsafe fn alloc_words(&mut self, n: Words<u32>) -> Value {
+        self.0.borrow_mut().alloc_words(n)
+    }
+}
+
+pub unsafe fn test() {
+    println!("Testing RTS self-test ...");
+
+    let checksum = run_self_test(Bytes(0));
+    assert_ne!(checksum, 0);
+
+    // The checksum does not depend on the objects below the test heap, or on the alignment
+    for size in [1, 4, 5, 1000] {
+        assert_eq!(run_self_test(Bytes(size)), checksum);
+    }
+}
+
+/// Run the self-test after a blob of `size` bytes, return the checksum
+unsafe fn run_self_test(size: Bytes<u32>) -> u32 {
+    let mut mem = SharedMemory(Rc::new(RefCell::new(TestMemory::new(Words(128 * 1024)))));
+    alloc_blob(&mut mem, size);
+
+    let hp = mem.0.borrow().heap_ptr();
+    let (mem_1, mem_2) = (mem.clone(), mem.clone());
+    let checksum = self_test_internal(
+        &mut mem,
+        // get_hp
+        || mem_1.0.borrow().heap_ptr(),
+        // set_hp
+        move |hp| mem_2.0.borrow_mut().set_heap_ptr(hp as usize),
+    );
+
+    // The test does not leave objects behind
+    assert_eq!(mem.0.borrow().heap_ptr(), hp);
+
+    checksum
+}
@@ -0,0 +1,41 @@
+use crate::memory::TestMemory;
+
+use motoko_rts::memory::alloc_array;
+use motoko_rts::show_cycle::{show_cycle_enter, show_cycle_leave};
+use motoko_rts::types::Words;
+
+pub unsafe fn test() {
+    println!("Testing debug_show cycle detection ...");
+
+    let mut mem = TestMemory::new(Words(1024));
+
+    // More objects than the initial stack size, to test growing the stack
+    let objs: Vec<_> = (0..20).map(|_| alloc_array(&mut mem, 1)).collect();
+
+    println!("  Testing revisiting objects");
+    for obj in &objs {
+        assert!(show_cycle_enter(&mut mem, *obj));
+    }
+    for obj in &objs {
+        assert!(!show_cycle_enter(&mut mem, *obj));
+    }
+
+    // Objects that are done can be shown again
+    show_cycle_leave();
+    assert!(show_cycle_enter(&mut mem, objs[19]));
+    assert!(!show_cycle_enter(&mut mem, objs[0]));
+
+    for _ in &objs {
+        show_cycle_leave();
+    }
+
+    println!("  Testing independent calls");
+    // A self-referential object: the second visit is a cycle
+    assert!(show_cycle_enter(&mut mem, objs[0]));
+    assert!(!show_cycle_enter(&mut mem, objs[0]));
+    show_cycle_leave();
+
+    // Next call starts with an empty stack
+    assert!(show_cycle_enter(&mut mem, objs[0]));
+    show_cycle_leave();
+}
@@ -0,0 +1,36 @@
+use crate::trap::traps_with;
+
+use motoko_rts::stabilization::{
+    check_stable_layout_version, stable_layout_version, STABLE_LAYOUT_VERSION,
+};
+
+pub unsafe fn test() {
+    println!("Testing stable layout version check ...");
+
+    assert_eq!(stable_layout_version(), STABLE_LAYOUT_VERSION);
+
+    // Versions written by this and older RTSes are accepted
+    for version in 1..=STABLE_LAYOUT_VERSION {
+        check_stable_layout_version(version);
+    }
+
+    // Newer versions, and the cleared word
+    let newer = STABLE_LAYOUT_VERSION + 1;
+    assert!(traps_with(
+        &format!(
+            "unsupported stable memory version {} (expected {} or lower)",
+            newer, STABLE_LAYOUT_VERSION
+        ),
+        || check_stable_layout_version(newer)
+    ));
+    assert!(traps_with(
+        &format!(
+            "unsupported stable memory version 0 (expected {} or lower)",
+            STABLE_LAYOUT_VERSION
+        ),
+        || check_stable_layout_version(0)
+    ));
+    assert!(traps_with("unsupported stable memory version", || {
+        check_stable_layout_version(u32::MAX)
+    }));
+}
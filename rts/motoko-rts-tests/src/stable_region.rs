@@ -0,0 +1,173 @@
+//! Tests for the stable memory region, against a mock stable memory. The mock implements the
+//! functions generated by `moc` for the RTS, and is also used by the tests of other RTS components
+//! that access stable memory.
+
+use crate::memory::TestMemory;
+use crate::trap::traps_with;
+
+use motoko_rts::memory::{alloc_blob, Memory};
+use motoko_rts::stable_region::{
+    stable_region_grow, stable_region_read, stable_region_size, stable_region_write,
+    STABLE_PAGE_SIZE,
+};
+use motoko_rts::types::{Bytes, Value, Words};
+
+use std::sync::Mutex;
+
+/// Physical stable memory
+static STABLE_MEM: Mutex<Vec<u8>> = Mutex::new(vec![]);
+
+/// Logical size of the stable memory (the region), in pages
+static mut STABLE_MEM_SIZE: u64 = 0;
+
+/// Maximum logical size, as `--max-stable-pages`
+pub const MAX_STABLE_PAGES: u64 = 64;
+
+/// Drop the contents of the mock stable memory. Tests using the mock before `test` should reset
+/// it when done.
+pub unsafe fn reset_stable_memory() {
+    STABLE_MEM.lock().unwrap().clear();
+    STABLE_MEM_SIZE = 0;
+}
+
+#[no_mangle]
+extern "C" fn stable_mem_size_moc() -> u64 {
+    unsafe { STABLE_MEM_SIZE }
+}
+
+#[no_mangle]
+extern "C" fn stable_mem_grow_moc(pages: u64) -> u64 {
+    unsafe {
+        let old_size = STABLE_MEM_SIZE;
+        let new_size = old_size + pages;
+        if new_size > MAX_STABLE_PAGES {
+            return u64::MAX;
+        }
+        let new_len = (new_size * STABLE_PAGE_SIZE) as usize;
+        let mut stable_mem = STABLE_MEM.lock().unwrap();
+        if stable_mem.len() < new_len {
+            stable_mem.resize(new_len, 0);
+        }
+        STABLE_MEM_SIZE = new_size;
+        old_size
+    }
+}
+
+#[no_mangle]
+extern "C" fn stable64_read_moc(dst: u64, offset: u64, size: u64) {
+    unsafe {
+        let stable_mem = STABLE_MEM.lock().unwrap();
+        let src = &stable_mem[offset as usize..(offset + size) as usize];
+        std::ptr::copy_nonoverlapping(src.as_ptr(), dst as usize as *mut u8, size as usize);
+    }
+}
+
+#[no_mangle]
+extern "C" fn stable64_write_moc(offset: u64, src: u64, size: u64) {
+    unsafe {
+        let mut stable_mem = STABLE_MEM.lock().unwrap();
+        let dst = &mut stable_mem[offset as usize..(offset + size) as usize];
+        std::ptr::copy_nonoverlapping(src as usize as *const u8, dst.as_mut_ptr(), size as usize);
+    }
+}
+
+pub unsafe fn test() {
+    println!("Testing stable memory region ...");
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    println!("  Testing grow");
+    assert_eq!(stable_region_size(), 0);
+
+    // Nothing can be accessed in an empty region
+    let empty = alloc_blob(&mut mem, Bytes(0));
+    stable_region_read(0, empty);
+    let byte = blob_of_bytes(&mut mem, &[1]);
+    assert!(traps_with("StableMemory range out of bounds", || {
+        stable_region_write(0, byte)
+    }));
+
+    assert_eq!(stable_region_grow(1), 0);
+    assert_eq!(stable_region_grow(0), 1);
+    assert_eq!(stable_region_size(), 1);
+    assert_eq!(stable_region_grow(MAX_STABLE_PAGES), u64::MAX);
+    assert_eq!(stable_region_size(), 1);
+
+    // Grown pages are zero
+    let page = alloc_blob(&mut mem, Bytes(STABLE_PAGE_SIZE as u32));
+    stable_region_read(0, page);
+    assert!(bytes_of_blob(page).iter().all(|byte| *byte == 0));
+
+    println!("  Testing reads and writes");
+    let data = blob_of_bytes(&mut mem, b"hello stable memory");
+    stable_region_write(100, data);
+    let read = alloc_blob(&mut mem, Bytes(19));
+    stable_region_read(100, read);
+    assert_eq!(bytes_of_blob(read), b"hello stable memory");
+
+    println!("  Testing boundaries");
+    let end = STABLE_PAGE_SIZE;
+    let last_bytes = blob_of_bytes(&mut mem, &[1, 2, 3, 4]);
+    stable_region_write(end - 4, last_bytes);
+    let read = alloc_blob(&mut mem, Bytes(4));
+    stable_region_read(end - 4, read);
+    assert_eq!(bytes_of_blob(read), &[1, 2, 3, 4]);
+    stable_region_read(end, empty);
+
+    for offset in [end - 3, end, end + 1, u64::MAX - 4] {
+        assert!(traps_with("StableMemory range out of bounds", || {
+            stable_region_read(offset, read)
+        }));
+        assert!(traps_with("StableMemory range out of bounds", || {
+            stable_region_write(offset, read)
+        }));
+    }
+
+    for offset in [u64::MAX - 2, u64::MAX] {
+        assert!(traps_with("StableMemory range overflow", || {
+            stable_region_read(offset, read)
+        }));
+    }
+
+    println!("  Testing overlap protection");
+    // Stable variables are serialized after the region, to physical pages not in the region
+    let serialized = (STABLE_PAGE_SIZE as usize)..(3 * STABLE_PAGE_SIZE) as usize;
+    {
+        let mut stable_mem = STABLE_MEM.lock().unwrap();
+        stable_mem.resize(serialized.end, 0);
+        stable_mem[serialized.clone()].fill(0xAA);
+    }
+
+    let page = blob_of_bytes(&mut mem, &vec![0x55; STABLE_PAGE_SIZE as usize]);
+    assert!(traps_with("StableMemory range out of bounds", || {
+        stable_region_write(1, page)
+    }));
+    assert!(traps_with("StableMemory range out of bounds", || {
+        stable_region_write(STABLE_PAGE_SIZE, byte)
+    }));
+    assert!(STABLE_MEM.lock().unwrap()[serialized]
+        .iter()
+        .all(|byte| *byte == 0xAA));
+
+    // Growing the region makes the pages accessible
+    assert_eq!(stable_region_grow(1), 1);
+    stable_region_write(1, page);
+    stable_region_write(STABLE_PAGE_SIZE + 1, byte);
+    let read = alloc_blob(&mut mem, Bytes(2));
+    stable_region_read(STABLE_PAGE_SIZE, read);
+    assert_eq!(bytes_of_blob(read), &[0x55, 1]);
+}
+
+unsafe fn blob_of_bytes<M: Memory>(mem: &mut M, bytes: &[u8]) -> Value {
+    let blob = alloc_blob(mem, Bytes(bytes.len() as u32));
+    let blob_ptr = blob.as_blob_mut();
+    for (i, byte) in bytes.iter().enumerate() {
+        blob_ptr.set(i as u32, *byte);
+    }
+    blob
+}
+
+unsafe fn bytes_of_blob(blob: Value) -> &'static [u8] {
+    let blob = blob.as_blob();
+    std::slice::from_raw_parts(blob.payload_const(), blob.len().as_usize())
+}
@@ -0,0 +1,46 @@
+use crate::memory::TestMemory;
+
+use motoko_rts::memory::alloc_array;
+use motoko_rts::stable_sharing::{stable_sharing_reset, stable_sharing_visit, FIRST_VISIT};
+use motoko_rts::types::{Value, Words};
+
+pub unsafe fn test() {
+    println!("Testing stable sharing table ...");
+
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    println!("  Testing visits");
+    let typ_a = 0x1000;
+    let typ_b = 0x2000;
+    let arr = alloc_array(&mut mem, 3);
+    assert_eq!(stable_sharing_visit(&mut mem, arr, typ_a, 10), FIRST_VISIT);
+    assert_eq!(stable_sharing_visit(&mut mem, arr, typ_a, 20), 10);
+
+    // The same array at another type is not shared
+    assert_eq!(stable_sharing_visit(&mut mem, arr, typ_b, 30), FIRST_VISIT);
+    assert_eq!(stable_sharing_visit(&mut mem, arr, typ_b, 40), 30);
+    assert_eq!(stable_sharing_visit(&mut mem, arr, typ_a, 50), 10);
+
+    println!("  Testing many arrays");
+    let arrays: Vec<Value> = (0..10_000).map(|_| alloc_array(&mut mem, 1)).collect();
+    for (i, arr) in arrays.iter().enumerate() {
+        assert_eq!(
+            stable_sharing_visit(&mut mem, *arr, typ_a, i as u32 * 8),
+            FIRST_VISIT
+        );
+    }
+    for (i, arr) in arrays.iter().enumerate() {
+        assert_eq!(stable_sharing_visit(&mut mem, *arr, typ_a, 0), i as u32 * 8);
+    }
+    assert_eq!(stable_sharing_visit(&mut mem, arr, typ_a, 0), 10);
+
+    println!("  Testing reset");
+    stable_sharing_reset();
+    assert_eq!(stable_sharing_visit(&mut mem, arr, typ_a, 60), FIRST_VISIT);
+    assert_eq!(
+        stable_sharing_visit(&mut mem, arrays[0], typ_a, 70),
+        FIRST_VISIT
+    );
+    assert_eq!(stable_sharing_visit(&mut mem, arr, typ_a, 0), 60);
+    stable_sharing_reset();
+}
@@ -1,14 +1,16 @@
 //! Text and text iterator tests
 
 use crate::memory::TestMemory;
+use crate::trap::traps_with;
 
-use motoko_rts::memory::Memory;
+use motoko_rts::memory::{alloc_array, alloc_blob, Memory};
 use motoko_rts::text::{
-    blob_of_text, decode_code_point, text_compare, text_concat, text_len, text_of_str,
-    text_singleton, text_size,
+    blob_compare, blob_eq, blob_of_text, blob_to_text_checked, decode_code_point, text_compare,
+    text_concat, text_contains, text_depth, text_index_of, text_join, text_len, text_of_str,
+    text_replace, text_singleton, text_size, text_split, UNKNOWN_N_CHARS,
 };
 use motoko_rts::text_iter::{text_iter, text_iter_done, text_iter_next};
-use motoko_rts::types::{Bytes, Value, Words, TAG_BLOB};
+use motoko_rts::types::{Bytes, Value, Words, TAG_BLOB, TAG_CONCAT};
 
 use std::convert::TryFrom;
 
@@ -76,8 +78,32 @@ pub unsafe fn test() {
     println!("  Testing concatenation");
     concat1(&mut mem);
 
+    println!("  Testing blob_to_text_checked");
+    test_blob_to_text_checked(&mut mem);
+
+    println!("  Testing blob_eq and blob_compare");
+    test_blob_compare(&mut mem);
+
     drop(mem);
 
+    println!("  Testing concatenation rebalancing");
+    test_concat_balanced();
+
+    println!("  Testing text_len of concatenations");
+    test_concat_len();
+
+    println!("  Testing split and join");
+    test_split_join();
+
+    println!("  Testing split sharing the rope");
+    test_split_sharing();
+
+    println!("  Testing join balancing");
+    test_join_balanced();
+
+    println!("  Testing contains, index_of and replace");
+    test_search();
+
     let mut proptest_runner = TestRunner::new(Config {
         cases: 1_000,
         failure_persistence: None,
@@ -93,6 +119,197 @@ pub unsafe fn test() {
             },
         )
         .unwrap();
+
+    proptest_runner
+        .run(
+            &(
+                proptest::string::string_regex("[ab,ö]{0,40}").unwrap(),
+                proptest::string::string_regex("[ab,ö]{0,3}").unwrap(),
+                1..10usize,
+            ),
+            |(str, delim, chunk)| {
+                let mut mem = TestMemory::new(Words(1024 * 1024));
+                split_join_prop(&mut mem, &str, &delim, chunk)
+            },
+        )
+        .unwrap();
+
+    proptest_runner
+        .run(
+            &(
+                proptest::string::string_regex("[abö]{0,40}").unwrap(),
+                proptest::string::string_regex("[abö]{0,3}").unwrap(),
+                1..10usize,
+            ),
+            |(str, needle, chunk)| {
+                let mut mem = TestMemory::new(Words(1024 * 1024));
+                search_prop(&mut mem, &str, &needle, chunk)
+            },
+        )
+        .unwrap();
+}
+
+unsafe fn test_blob_to_text_checked<M: Memory>(mem: &mut M) {
+    // Valid texts, including multi-byte characters at the boundaries of the encoding lengths
+    for str in [
+        "",
+        "abc",
+        "öabcdef",
+        "€ and 😀",
+        "\u{7f}\u{80}\u{7ff}\u{800}\u{ffff}\u{10000}\u{10ffff}",
+    ] {
+        let blob = blob_of_bytes(mem, str.as_bytes());
+        let text = blob_to_text_checked(blob);
+        assert!(text == blob);
+        assert_eq!(TextIter::from_text(mem, text).collect::<String>(), str);
+    }
+
+    let invalid: &[&[u8]] = &[
+        // Truncated sequences
+        b"\xc3",
+        b"\xe2\x82",
+        b"\xf0\x9f\x98",
+        b"a\xe2\x82b",
+        // Overlong encodings
+        b"\xc0\xaf",
+        b"\xc1\xbf",
+        b"\xe0\x80\xaf",
+        b"\xf0\x80\x80\xaf",
+        // UTF-16 surrogates
+        b"\xed\xa0\x80",
+        b"\xed\xbf\xbf",
+        // Above U+10FFFF
+        b"\xf4\x90\x80\x80",
+    ];
+
+    for bytes in invalid {
+        let blob = blob_of_bytes(mem, bytes);
+        assert!(traps_with("Blob is not UTF-8", || {
+            blob_to_text_checked(blob);
+        }));
+    }
+}
+
+unsafe fn test_blob_compare<M: Memory>(mem: &mut M) {
+    // Lengths around the word size, to cover both the word-wise and the byte-wise comparisons
+    let cases: &[(&[u8], &[u8])] = &[
+        // Equal
+        (b"", b""),
+        (b"a", b"a"),
+        (b"abcd", b"abcd"),
+        (b"abcdefghi", b"abcdefghi"),
+        // Prefixes
+        (b"", b"a"),
+        (b"abc", b"abcd"),
+        (b"abcd", b"abcde"),
+        (b"abcdefgh", b"abcdefghijklmnop"),
+        // Difference in the first word, decided by the first differing byte
+        (b"abcd", b"abdc"),
+        (b"\x01\xff\xff\xff", b"\x02\x00\x00\x00"),
+        (b"abce", b"abcd0000"),
+        // Difference after the first word
+        (b"abcdefgh", b"abcdefgi"),
+        (b"abcdefgz", b"abcdefghijk"),
+        (b"abcd\x80", b"abcd\x7f"),
+    ];
+
+    for (bytes1, bytes2) in cases {
+        let blob1 = blob_of_bytes(mem, bytes1);
+        let blob2 = blob_of_bytes(mem, bytes2);
+
+        let expected = bytes1.cmp(bytes2) as i32;
+        assert_eq!(blob_compare(blob1, blob2), expected);
+        assert_eq!(blob_compare(blob2, blob1), -expected);
+
+        assert_eq!(blob_eq(blob1, blob2), bytes1 == bytes2);
+        assert_eq!(blob_eq(blob2, blob1), bytes1 == bytes2);
+
+        assert_eq!(blob_compare(blob1, blob1), 0);
+        assert!(blob_eq(blob1, blob1));
+    }
+}
+
+unsafe fn blob_of_bytes<M: Memory>(mem: &mut M, bytes: &[u8]) -> Value {
+    let blob = alloc_blob(mem, Bytes(bytes.len() as u32));
+    let blob_ptr = blob.as_blob_mut();
+    for (i, byte) in bytes.iter().enumerate() {
+        blob_ptr.set(i as u32, *byte);
+    }
+    blob
+}
+
+unsafe fn test_concat_balanced() {
+    const N_CHARS: u32 = 50_000;
+
+    let chars: Vec<char> = (0..N_CHARS)
+        .map(|i| char::from_u32('a' as u32 + i % 26).unwrap())
+        .collect();
+    let expected: String = chars.iter().collect();
+
+    // Append characters one by one
+    let mut mem = TestMemory::new(Words(32 * 1024 * 1024));
+    let mut text = text_of_str(&mut mem, "");
+    for char in &chars {
+        let char_text = text_singleton(&mut mem, *char as u32);
+        text = text_concat(&mut mem, text, char_text);
+    }
+    check_balanced_text(&mut mem, text, &expected);
+    drop(mem);
+
+    // Prepend characters one by one
+    let mut mem = TestMemory::new(Words(32 * 1024 * 1024));
+    let mut text = text_of_str(&mut mem, "");
+    for char in chars.iter().rev() {
+        let char_text = text_singleton(&mut mem, *char as u32);
+        text = text_concat(&mut mem, char_text, text);
+    }
+    check_balanced_text(&mut mem, text, &expected);
+}
+
+unsafe fn test_concat_len() {
+    let mut mem = TestMemory::new(Words(32 * 1024 * 1024));
+
+    let strs = ["a", "ö", "€", "😀", "abcdefghij"];
+    let mut text = text_of_str(&mut mem, "");
+    let mut expected = String::new();
+    for i in 0..20_000 {
+        let str = strs[i % strs.len()];
+        let str_text = text_of_str(&mut mem, str);
+        text = text_concat(&mut mem, text, str_text);
+        expected.push_str(str);
+    }
+
+    // Characters are counted by `text_len`, not by the concatenation
+    assert_eq!(text.tag(), TAG_CONCAT);
+    assert_eq!((*text.as_concat()).n_chars, UNKNOWN_N_CHARS);
+    assert_eq!(text_len(text), expected.chars().count() as u32);
+    assert_eq!((*text.as_concat()).n_chars, expected.chars().count() as u32);
+
+    // Make the first character of the leftmost blob a continuation byte. `text_len` should still
+    // return the length stored in the concat node, without counting the characters in the blob.
+    let mut leaf = text;
+    while leaf.tag() == TAG_CONCAT {
+        leaf = leaf.as_concat().text1();
+    }
+    leaf.as_blob_mut().set(0, 0x80);
+
+    assert_eq!(text_len(text), expected.chars().count() as u32);
+}
+
+unsafe fn check_balanced_text<M: Memory>(mem: &mut M, text: Value, expected: &str) {
+    assert_eq!(text_size(text), Bytes(expected.len() as u32));
+
+    let blob = blob_of_text(mem, text);
+    assert_eq!(TextIter::from_text(mem, blob).collect::<String>(), expected);
+
+    // A balanced text has depth at most log_φ(size), which is about 1.44 * log_2(size)
+    let max_depth = 2 * (32 - (expected.len() as u32).leading_zeros());
+    assert!(
+        text_depth(text) <= max_depth,
+        "text depth {} larger than {}",
+        text_depth(text),
+        max_depth
+    );
 }
 
 unsafe fn concat1<M: Memory>(mem: &mut M) {
@@ -185,3 +402,252 @@ fn concat_prop<M: Memory>(mem: &mut M, strs: Vec<String>) -> TestCaseResult {
         Ok(())
     }
 }
+
+/// Text of the string, concatenated from parts of `chunk` characters, so that delimiters span
+/// leaves
+unsafe fn rope_of_str<M: Memory>(mem: &mut M, str: &str, chunk: usize) -> Value {
+    let chars: Vec<char> = str.chars().collect();
+    let mut text = text_of_str(mem, "");
+    for part in chars.chunks(chunk) {
+        let part = text_of_str(mem, &part.iter().collect::<String>());
+        text = text_concat(mem, text, part);
+    }
+    text
+}
+
+unsafe fn text_string<M: Memory>(mem: &mut M, text: Value) -> String {
+    TextIter::from_text(mem, text).collect()
+}
+
+/// Expected parts of a split
+fn split_str(str: &str, delim: &str) -> Vec<String> {
+    if delim.is_empty() {
+        str.chars().map(String::from).collect()
+    } else {
+        str.split(delim).map(String::from).collect()
+    }
+}
+
+unsafe fn split_strs<M: Memory>(mem: &mut M, text: Value, delim: &str) -> Vec<String> {
+    let delim = text_of_str(mem, delim);
+    let parts = text_split(mem, text, delim).as_array();
+    (0..parts.len())
+        .map(|i| text_string(mem, parts.get(i)))
+        .collect()
+}
+
+unsafe fn test_split_join() {
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    let cases = [
+        ("a,b,,c", ","),
+        (",a,b,", ","),
+        (",", ","),
+        ("", ","),
+        ("abc", ","),
+        ("abc", "abc"),
+        ("a::b:::c::", "::"),
+        ("aaaa", "aa"),
+        ("aaa", "aa"),
+        ("abcabcab", "abcab"),
+        ("ö€😀ö€€", "€"),
+        ("ö€😀", ""),
+        ("", ""),
+    ];
+
+    for (str, delim) in cases {
+        for chunk in [1, 2, 3, 100] {
+            let text = rope_of_str(&mut mem, str, chunk);
+            assert_eq!(
+                split_strs(&mut mem, text, delim),
+                split_str(str, delim),
+                "splitting {:?} on {:?}",
+                str,
+                delim
+            );
+
+            let delim_text = text_of_str(&mut mem, delim);
+            let parts = text_split(&mut mem, text, delim_text);
+            let joined = text_join(&mut mem, parts, delim_text);
+            assert_eq!(text_string(&mut mem, joined), str);
+        }
+    }
+
+    // Joining with an empty separator, and an empty array
+    let parts = alloc_array(&mut mem, 3);
+    for (i, str) in ["ab", "", "c"].iter().enumerate() {
+        parts.as_array().set(i as u32, text_of_str(&mut mem, str));
+    }
+    let empty = text_of_str(&mut mem, "");
+    let joined = text_join(&mut mem, parts, empty);
+    assert_eq!(text_string(&mut mem, joined), "abc");
+
+    let sep = text_of_str(&mut mem, ", ");
+    let no_parts = alloc_array(&mut mem, 0);
+    let joined = text_join(&mut mem, no_parts, sep);
+    assert_eq!(text_string(&mut mem, joined), "");
+}
+
+/// Parts that are leaves or subtexts of the text are not copied
+unsafe fn test_split_sharing() {
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    let leaf1 = text_of_str(&mut mem, "the first leaf of the text");
+    let leaf2 = text_of_str(&mut mem, "the second leaf of the text");
+    let leaf3 = text_of_str(&mut mem, "the third leaf of the text");
+    let leaves12 = text_concat(&mut mem, leaf1, leaf2);
+    let sep = text_of_str(&mut mem, ";");
+
+    let text = text_concat(&mut mem, leaves12, sep);
+    let text = text_concat(&mut mem, text, leaf3);
+    let parts = text_split(&mut mem, text, sep).as_array();
+    assert_eq!(parts.len(), 2);
+    assert!(parts.get(0) == leaves12);
+    assert!(parts.get(1) == leaf3);
+
+    // A part spanning leaves shares the leaves in the range
+    let delim = text_of_str(&mut mem, "first ");
+    let parts = text_split(&mut mem, leaves12, delim).as_array();
+    assert_eq!(parts.len(), 2);
+    let part = parts.get(1);
+    assert_eq!(part.tag(), TAG_CONCAT);
+    assert!(part.as_concat().text2() == leaf2);
+}
+
+unsafe fn test_join_balanced() {
+    const N_PARTS: u32 = 10_000;
+
+    let mut mem = TestMemory::new(Words(32 * 1024 * 1024));
+    let parts = alloc_array(&mut mem, N_PARTS);
+    let mut strs = vec![];
+    for i in 0..N_PARTS {
+        let str = format!("part {}", i);
+        parts.as_array().set(i, text_of_str(&mut mem, &str));
+        strs.push(str);
+    }
+
+    let sep = text_of_str(&mut mem, ", ");
+    let text = text_join(&mut mem, parts, sep);
+    check_balanced_text(&mut mem, text, &strs.join(", "));
+}
+
+/// Expected character index of the first occurrence of `needle`, or -1
+fn index_of_str(str: &str, needle: &str) -> i32 {
+    match str.find(needle) {
+        Some(pos) => str[..pos].chars().count() as i32,
+        None => -1,
+    }
+}
+
+unsafe fn replace_str<M: Memory>(mem: &mut M, text: Value, needle: &str, repl: &str) -> String {
+    let needle = text_of_str(mem, needle);
+    let repl = text_of_str(mem, repl);
+    let replaced = text_replace(mem, text, needle, repl);
+    text_string(mem, replaced)
+}
+
+unsafe fn test_search() {
+    let mut mem = TestMemory::new(Words(1024 * 1024));
+
+    // (text, needle, replacement)
+    let cases = [
+        // Found and not found
+        ("hello world", "world", "there"),
+        ("hello world", "word", "there"),
+        ("hello", "hello!", "x"),
+        // Multiple and overlapping occurrences
+        ("a,b,,c", ",", ";;"),
+        ("aaa", "aa", "b"),
+        ("aaaa", "aa", "b"),
+        ("abcabcab", "abcab", "x"),
+        ("aabaabaaab", "aab", ""),
+        // Failure function fallbacks
+        ("abababca", "ababca", "x"),
+        ("aaaab", "aaab", "x"),
+        // Non-ASCII
+        ("ö€😀ö€€", "€", "e"),
+        ("ö€😀ö€€", "😀ö", ""),
+        // Empty inputs
+        ("abc", "", "-"),
+        ("ö€", "", ""),
+        ("", "", "-"),
+        ("", "a", "-"),
+        ("abc", "b", ""),
+    ];
+
+    for (str, needle, repl) in cases {
+        for chunk in [1, 2, 3, 100] {
+            let text = rope_of_str(&mut mem, str, chunk);
+            let needle_text = text_of_str(&mut mem, needle);
+
+            assert_eq!(
+                text_contains(&mut mem, text, needle_text),
+                str.contains(needle),
+                "{:?} contains {:?}",
+                str,
+                needle
+            );
+            assert_eq!(
+                text_index_of(&mut mem, text, needle_text),
+                index_of_str(str, needle),
+                "index of {:?} in {:?}",
+                needle,
+                str
+            );
+            assert_eq!(
+                replace_str(&mut mem, text, needle, repl),
+                str.replace(needle, repl),
+                "replacing {:?} in {:?} with {:?}",
+                needle,
+                str,
+                repl
+            );
+        }
+    }
+
+    // The text is returned as is when the needle does not occur
+    let text = rope_of_str(&mut mem, "hello world", 3);
+    let needle = text_of_str(&mut mem, "xyz");
+    let repl = text_of_str(&mut mem, "abc");
+    assert!(text_replace(&mut mem, text, needle, repl) == text);
+}
+
+fn search_prop<M: Memory>(mem: &mut M, str: &str, needle: &str, chunk: usize) -> TestCaseResult {
+    unsafe {
+        let text = rope_of_str(mem, str, chunk);
+        let needle_text = text_of_str(mem, needle);
+
+        if text_contains(mem, text, needle_text) != str.contains(needle) {
+            return Err(TestCaseError::Fail("text_contains".into()));
+        }
+
+        if text_index_of(mem, text, needle_text) != index_of_str(str, needle) {
+            return Err(TestCaseError::Fail("text_index_of".into()));
+        }
+
+        if replace_str(mem, text, needle, "<>") != str.replace(needle, "<>") {
+            return Err(TestCaseError::Fail("text_replace".into()));
+        }
+
+        Ok(())
+    }
+}
+
+fn split_join_prop<M: Memory>(mem: &mut M, str: &str, delim: &str, chunk: usize) -> TestCaseResult {
+    unsafe {
+        let text = rope_of_str(mem, str, chunk);
+
+        if split_strs(mem, text, delim) != split_str(str, delim) {
+            return Err(TestCaseError::Fail("text_split".into()));
+        }
+
+        let delim = text_of_str(mem, delim);
+        let parts = text_split(mem, text, delim);
+        let joined = text_join(mem, parts, delim);
+        if text_compare(joined, text) != 0 {
+            return Err(TestCaseError::Fail("text_join of text_split".into()));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+//! Testing RTS traps
+
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::FromRawFd;
+
+/// Run `f` in a child process, return whether it trapped with a message containing `msg`.
+/// `rts_trap` panics in an `extern "C"` function, which aborts the process, so traps cannot be
+/// caught in the test process.
+pub unsafe fn traps_with<F: FnOnce()>(msg: &str, f: F) -> bool {
+    let mut fds = [0; 2];
+    assert_eq!(libc::pipe(fds.as_mut_ptr()), 0);
+    let [read_fd, write_fd] = fds;
+
+    let pid = libc::fork();
+    assert!(pid >= 0, "fork failed");
+
+    if pid == 0 {
+        // Send the panic message to the parent
+        libc::close(read_fd);
+        libc::dup2(write_fd, 2);
+        f();
+        libc::_exit(0);
+    }
+
+    libc::close(write_fd);
+    let mut output = vec![];
+    File::from_raw_fd(read_fd).read_to_end(&mut output).unwrap();
+
+    let mut status = 0;
+    assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+    let exited = libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0;
+
+    !exited && String::from_utf8_lossy(&output).contains(msg)
+}
@@ -0,0 +1,153 @@
+//! Case mapping and normalization tests
+
+use crate::memory::TestMemory;
+
+use motoko_rts::memory::Memory;
+use motoko_rts::text::{blob_of_text, text_concat, text_of_str};
+use motoko_rts::types::{Value, Words};
+use motoko_rts::unicode::{text_nfc, text_nfd, text_to_lower, text_to_upper};
+
+pub unsafe fn test() {
+    println!("Testing Unicode case mapping and normalization ...");
+
+    let mut mem = TestMemory::new(Words(16 * 1024 * 1024));
+
+    test_case_mapping(&mut mem);
+    test_turkish_i(&mut mem);
+    test_case_mapping_all_chars(&mut mem);
+    test_normalization(&mut mem);
+    test_hangul(&mut mem);
+    test_unchanged(&mut mem);
+}
+
+unsafe fn to_string<M: Memory>(mem: &mut M, text: Value) -> String {
+    let blob = blob_of_text(mem, text).as_blob();
+    let bytes = std::slice::from_raw_parts(blob.payload_const(), blob.len().as_usize());
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+unsafe fn apply<M: Memory>(mem: &mut M, f: unsafe fn(&mut M, Value) -> Value, s: &str) -> String {
+    let text = text_of_str(mem, s);
+    let result = f(mem, text);
+    to_string(mem, result)
+}
+
+unsafe fn test_case_mapping<M: Memory>(mem: &mut M) {
+    println!("  Testing case mapping");
+
+    assert_eq!(apply(mem, text_to_upper, "Motoko"), "MOTOKO");
+    assert_eq!(apply(mem, text_to_lower, "Motoko"), "motoko");
+
+    // Mappings to multiple characters
+    assert_eq!(apply(mem, text_to_upper, "straße"), "STRASSE");
+    assert_eq!(apply(mem, text_to_lower, "STRASSE"), "strasse");
+    assert_eq!(apply(mem, text_to_lower, "ß"), "ß");
+    assert_eq!(apply(mem, text_to_upper, "ﬁ"), "FI");
+
+    // Non-ASCII letters
+    assert_eq!(apply(mem, text_to_upper, "ça va, Ωμέγα"), "ÇA VA, ΩΜΈΓΑ");
+    assert_eq!(apply(mem, text_to_lower, "ÇA VA, ΩΜΈΓΑ"), "ça va, ωμέγα");
+
+    // Final sigma is not handled specially
+    assert_eq!(apply(mem, text_to_lower, "ΟΔΟΣ"), "οδοσ");
+
+    // Concatenations
+    let s1 = text_of_str(mem, "Groß");
+    let s2 = text_of_str(mem, "Stadt");
+    let text = text_concat(mem, s1, s2);
+    let upper = text_to_upper(mem, text);
+    assert_eq!(to_string(mem, upper), "GROSSSTADT");
+}
+
+/// Case mapping is locale independent, so Turkish and Azeri dotted and dotless i are mapped as in
+/// other languages
+unsafe fn test_turkish_i<M: Memory>(mem: &mut M) {
+    println!("  Testing Turkish i");
+
+    assert_eq!(apply(mem, text_to_lower, "I"), "i");
+    assert_eq!(apply(mem, text_to_upper, "i"), "I");
+    assert_eq!(apply(mem, text_to_upper, "ı"), "I");
+    assert_eq!(apply(mem, text_to_lower, "ı"), "ı");
+    assert_eq!(apply(mem, text_to_upper, "İ"), "İ");
+    assert_eq!(apply(mem, text_to_lower, "İ"), "i\u{307}");
+    assert_eq!(apply(mem, text_to_upper, "i\u{307}"), "I\u{307}");
+    assert_eq!(apply(mem, text_to_lower, "İSTANBUL"), "i\u{307}stanbul");
+}
+
+/// Case mapping of every character is the same as `char::to_lowercase` and `char::to_uppercase`
+unsafe fn test_case_mapping_all_chars<M: Memory>(mem: &mut M) {
+    println!("  Testing case mapping of all characters");
+
+    let all: String = (0..=0x10FFFF).filter_map(char::from_u32).collect();
+
+    let lower: String = all.chars().flat_map(char::to_lowercase).collect();
+    assert_eq!(apply(mem, text_to_lower, &all), lower);
+
+    let upper: String = all.chars().flat_map(char::to_uppercase).collect();
+    assert_eq!(apply(mem, text_to_upper, &all), upper);
+}
+
+unsafe fn test_normalization<M: Memory>(mem: &mut M) {
+    println!("  Testing normalization");
+
+    // Composed and decomposed accents
+    assert_eq!(apply(mem, text_nfc, "e\u{301}"), "é");
+    assert_eq!(apply(mem, text_nfd, "é"), "e\u{301}");
+    assert_eq!(apply(mem, text_nfc, "é"), "é");
+    assert_eq!(apply(mem, text_nfd, "e\u{301}"), "e\u{301}");
+    assert_eq!(apply(mem, text_nfc, "Cafe\u{301} au lait"), "Café au lait");
+
+    // Multiple decompositions
+    assert_eq!(apply(mem, text_nfd, "ệ"), "e\u{323}\u{302}");
+    assert_eq!(apply(mem, text_nfc, "e\u{323}\u{302}"), "ệ");
+    assert_eq!(apply(mem, text_nfc, "e\u{302}\u{323}"), "ệ");
+
+    // Canonical ordering of combining marks: below (220) before above (230)
+    assert_eq!(apply(mem, text_nfd, "a\u{301}\u{316}"), "a\u{316}\u{301}");
+    assert_eq!(apply(mem, text_nfc, "a\u{316}\u{301}"), "á\u{316}");
+
+    // A mark of the same class in between blocks composition
+    assert_eq!(apply(mem, text_nfc, "a\u{308}\u{301}"), "ä\u{301}");
+    assert_eq!(apply(mem, text_nfc, "a\u{301}\u{308}"), "á\u{308}");
+
+    // Singleton decompositions are never composed back
+    assert_eq!(apply(mem, text_nfd, "\u{212B}"), "A\u{30A}");
+    assert_eq!(apply(mem, text_nfc, "\u{212B}"), "\u{C5}");
+    assert_eq!(apply(mem, text_nfc, "\u{2126}"), "\u{3A9}");
+
+    // Composition exclusions
+    assert_eq!(apply(mem, text_nfc, "\u{958}"), "\u{915}\u{93C}");
+
+    // Compatibility decompositions are not applied
+    assert_eq!(apply(mem, text_nfd, "ﬁ²"), "ﬁ²");
+    assert_eq!(apply(mem, text_nfc, "ﬁ²"), "ﬁ²");
+
+    // Concatenations
+    let s1 = text_of_str(mem, "Cafe");
+    let s2 = text_of_str(mem, "\u{301}");
+    let text = text_concat(mem, s1, s2);
+    let nfc = text_nfc(mem, text);
+    assert_eq!(to_string(mem, nfc), "Café");
+}
+
+unsafe fn test_hangul<M: Memory>(mem: &mut M) {
+    println!("  Testing Hangul syllables");
+
+    assert_eq!(apply(mem, text_nfd, "한"), "\u{1112}\u{1161}\u{11AB}");
+    assert_eq!(apply(mem, text_nfc, "\u{1112}\u{1161}\u{11AB}"), "한");
+    assert_eq!(apply(mem, text_nfd, "가"), "\u{1100}\u{1161}");
+    assert_eq!(apply(mem, text_nfc, "\u{1100}\u{1161}"), "가");
+    assert_eq!(apply(mem, text_nfc, "가\u{11A8}"), "각");
+    assert_eq!(apply(mem, text_nfc, "한국어"), "한국어");
+}
+
+unsafe fn test_unchanged<M: Memory>(mem: &mut M) {
+    println!("  Testing unchanged texts");
+
+    let text = text_of_str(mem, "already lower case, ß");
+    assert!(text_to_lower(mem, text) == text);
+
+    let text = text_of_str(mem, "ASCII only");
+    assert!(text_nfc(mem, text) == text);
+    assert!(text_nfd(mem, text) == text);
+}
@@ -9,7 +9,7 @@ static TEST_STRS_VALID: [&[u8]; 2] = [
     b" \xe2\x96\x88 ",
 ];
 
-static TEST_STRS_INVALID: [&[u8]; 32] = [
+static TEST_STRS_INVALID: [&[u8]; 35] = [
     // from https://www.cl.cam.ac.uk/~mgk25/ucs/examples/UTF-8-test.txt
     //
     // 3.5  Impossible bytes
@@ -50,6 +50,10 @@ static TEST_STRS_INVALID: [&[u8]; 32] = [
     b"\xed\xae\x80\xed\xbf\xbf",
     b"\xed\xaf\xbf\xed\xb0\x80",
     b"\xed\xaf\xbf\xed\xbf\xbf",
+    // Truncated sequences
+    b"\xc3",
+    b"\xe2\x82",
+    b"\xf0\x9f\x98",
 ];
 
 pub unsafe fn test() {
@@ -0,0 +1,128 @@
+use crate::memory::TestMemory;
+
+use motoko_rts::constants::WORD_SIZE;
+use motoko_rts::memory::{alloc_array, alloc_blob, Memory};
+use motoko_rts::types::*;
+use motoko_rts::visitor::{for_each_field, visit_pointer_fields};
+
+pub unsafe fn test() {
+    println!("Testing object field iteration ...");
+
+    let mut mem = TestMemory::new(Words(1024));
+
+    // Pointer fields of the objects point to this blob, to compare with `visit_pointer_fields`
+    let target = alloc_blob(&mut mem, Bytes(0));
+
+    let object = alloc_obj(&mut mem, TAG_OBJECT, size_of::<Object>() + Words(2));
+    (*(object as *mut Object)).size = 2;
+    check_fields("object", object, target, &[false, false, true, true]);
+
+    let array = alloc_array(&mut mem, 3).as_obj();
+    check_fields("array", array, target, &[false, true, true, true]);
+
+    let frozen_array = alloc_array(&mut mem, 3).as_obj();
+    (*frozen_array).tag = TAG_FROZEN_ARRAY;
+    check_fields(
+        "frozen array",
+        frozen_array,
+        target,
+        &[false, true, true, true],
+    );
+
+    let closure = alloc_obj(&mut mem, TAG_CLOSURE, size_of::<Closure>() + Words(2));
+    (*(closure as *mut Closure)).size = 2;
+    check_fields("closure", closure, target, &[false, false, true, true]);
+
+    let mutbox = alloc_obj(&mut mem, TAG_MUTBOX, size_of::<MutBox>());
+    check_fields("mutbox", mutbox, target, &[true]);
+
+    let some = alloc_obj(&mut mem, TAG_SOME, size_of::<Some>());
+    check_fields("some", some, target, &[true]);
+
+    let variant = alloc_obj(&mut mem, TAG_VARIANT, size_of::<Variant>());
+    check_fields("variant", variant, target, &[false, true]);
+
+    let obj_ind = alloc_obj(&mut mem, TAG_OBJ_IND, size_of::<ObjInd>());
+    check_fields("obj_ind", obj_ind, target, &[true]);
+
+    let concat = alloc_obj(&mut mem, TAG_CONCAT, size_of::<Concat>());
+    check_fields("concat", concat, target, &[false, true, true, false, false]);
+
+    // Blob payloads are plain words, including the padding
+    let blob = alloc_blob(&mut mem, Bytes(5)).as_obj();
+    check_fields("blob", blob, target, &[false, false, false]);
+
+    let bits64 = alloc_obj(&mut mem, TAG_BITS64, size_of::<Bits64>());
+    check_fields("bits64", bits64, target, &[false, false]);
+
+    let bits32 = alloc_obj(&mut mem, TAG_BITS32, size_of::<Bits32>());
+    check_fields("bits32", bits32, target, &[false]);
+
+    // `mp_int` fields and digits are plain words
+    let bigint = alloc_obj(&mut mem, TAG_BIGINT, size_of::<BigInt>() + Words(2));
+    (*(bigint as *mut BigInt)).mp_int.alloc = 2;
+    let bigint_words =
+        (size_of::<BigInt>() - Words(1) + (bigint as *mut BigInt).len().to_words()).as_usize();
+    check_fields("bigint", bigint, target, &vec![false; bigint_words]);
+
+    // Weak fields are not pointer fields
+    let weak_ref = alloc_obj(&mut mem, TAG_WEAK_REF, size_of::<WeakRef>());
+    check_fields("weak_ref", weak_ref, target, &[false, false]);
+
+    let finalizable = alloc_obj(&mut mem, TAG_FINALIZABLE, size_of::<Finalizable>());
+    check_fields("finalizable", finalizable, target, &[false, false]);
+
+    let null = alloc_obj(&mut mem, TAG_NULL, size_of::<Null>());
+    check_fields("null", null, target, &[]);
+
+    let filler = alloc_obj(&mut mem, TAG_ONE_WORD_FILLER, size_of::<OneWordFiller>());
+    check_fields("one word filler", filler, target, &[]);
+
+    let free_space = alloc_obj(&mut mem, TAG_FREE_SPACE, Words(3));
+    (*(free_space as *mut FreeSpace)).words = Words(2);
+    check_fields("free space", free_space, target, &[false, false]);
+}
+
+/// Allocate an object of `size` words with the given tag, fields zeroed
+unsafe fn alloc_obj(mem: &mut TestMemory, tag: Tag, size: Words<u32>) -> *mut Obj {
+    let obj = mem.alloc_words(size).get_ptr() as *mut Obj;
+    (*obj).tag = tag;
+    for i in 1..size.as_usize() {
+        *(obj as *mut u32).add(i) = 0;
+    }
+    obj
+}
+
+/// Check the pointer flags of the fields of `obj`, and that the fields are the words after the
+/// header, in order. Then store `target` in the pointer fields, and check that they are the fields
+/// `visit_pointer_fields` visits.
+unsafe fn check_fields(name: &str, obj: *mut Obj, target: Value, expected: &[bool]) {
+    println!("  Testing {}", name);
+
+    let mut fields = vec![];
+    for_each_field(obj, |field, is_pointer| fields.push((field, is_pointer)));
+
+    let flags: Vec<bool> = fields.iter().map(|(_, is_pointer)| *is_pointer).collect();
+    assert_eq!(flags, expected);
+
+    for (i, (field, _)) in fields.iter().enumerate() {
+        assert_eq!(*field as usize, obj as usize + (i + 1) * WORD_SIZE as usize);
+    }
+
+    let pointer_fields: Vec<*mut Value> = fields
+        .iter()
+        .filter(|(_, is_pointer)| *is_pointer)
+        .map(|(field, _)| *field)
+        .collect();
+    if pointer_fields.is_empty() {
+        // `visit_pointer_fields` does not accept all of these objects
+        return;
+    }
+    for field in &pointer_fields {
+        **field = target;
+    }
+
+    let mut visited = vec![];
+    visit_pointer_fields(obj, obj.tag(), 0, |field| visited.push(field));
+    assert_eq!(visited, pointer_fields);
+}
@@ -0,0 +1,97 @@
+//! Allocation-site profiling, enabled with the `alloc-profiling` feature.
+//!
+//! Code compiled with `moc --alloc-profiling` allocates with `alloc_words_at` and
+//! `alloc_blob_at`, passing the id of the allocation site in the generated code. We accumulate
+//! the number of allocations and allocated bytes per site. The canister reads them with
+//! `alloc_site_count` and `alloc_site_bytes` for site ids below `alloc_sites`, e.g. in a query.
+//!
+//! Allocations done by the RTS itself (text concatenation, bignums, deserialization etc.) are not
+//! attributed to a site.
+//!
+//! Counters saturate instead of overflowing, like the GC statistics (`gc::stats`).
+
+use crate::memory::{alloc_blob, Memory};
+use crate::types::{size_of, Blob, Bytes, Value, Words};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Number of allocation sites. Allocations at sites with larger ids are accumulated in the last
+/// site.
+pub const MAX_ALLOC_SITES: u32 = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocSite {
+    /// Number of allocations at the site
+    pub count: u64,
+
+    /// Bytes allocated at the site
+    pub bytes: Bytes<u64>,
+
+    /// Size of the largest object allocated at the site
+    pub max_size: Bytes<u32>,
+}
+
+impl AllocSite {
+    const fn new() -> AllocSite {
+        AllocSite {
+            count: 0,
+            bytes: Bytes(0),
+            max_size: Bytes(0),
+        }
+    }
+}
+
+static mut ALLOC_SITES: [AllocSite; MAX_ALLOC_SITES as usize] =
+    [AllocSite::new(); MAX_ALLOC_SITES as usize];
+
+fn site_idx(site: u32) -> usize {
+    ::core::cmp::min(site, MAX_ALLOC_SITES - 1) as usize
+}
+
+/// Returns the profile of the allocation site
+pub unsafe fn alloc_site(site: u32) -> AllocSite {
+    ALLOC_SITES[site_idx(site)]
+}
+
+/// Reset the profiles of all allocation sites
+pub unsafe fn reset_alloc_sites() {
+    for site in ALLOC_SITES.iter_mut() {
+        *site = AllocSite::new();
+    }
+}
+
+unsafe fn note_alloc(site: u32, size: Bytes<u32>) {
+    let site = &mut ALLOC_SITES[site_idx(site)];
+    site.count = site.count.saturating_add(1);
+    site.bytes = Bytes(site.bytes.0.saturating_add(u64::from(size.as_u32())));
+    site.max_size = ::core::cmp::max(site.max_size, size);
+}
+
+/// `alloc_words`, attributing the allocation to `site`
+#[ic_mem_fn]
+pub unsafe fn alloc_words_at<M: Memory>(mem: &mut M, n: Words<u32>, site: u32) -> Value {
+    note_alloc(site, n.to_bytes());
+    mem.alloc_words(n)
+}
+
+/// `alloc_blob`, attributing the allocation to `site`
+#[ic_mem_fn]
+pub unsafe fn alloc_blob_at<M: Memory>(mem: &mut M, size: Bytes<u32>, site: u32) -> Value {
+    note_alloc(site, (size_of::<Blob>() + size.to_words()).to_bytes());
+    alloc_blob(mem, size)
+}
+
+#[no_mangle]
+unsafe extern "C" fn alloc_sites() -> u32 {
+    MAX_ALLOC_SITES
+}
+
+#[no_mangle]
+unsafe extern "C" fn alloc_site_count(site: u32) -> u64 {
+    alloc_site(site).count
+}
+
+#[no_mangle]
+unsafe extern "C" fn alloc_site_bytes(site: u32) -> Bytes<u64> {
+    alloc_site(site).bytes
+}
@@ -0,0 +1,431 @@
+//! Bounds-checked array element access, sorting, searching, mapping and filtering with closures,
+//! and freezing
+//!
+//! A frozen array (`TAG_FROZEN_ARRAY`) is an array that the mutation primitives (`array_set`,
+//! `array_sort`) reject with a trap, to catch mutations of arrays that are shared as immutable,
+//! e.g. captured by a continuation across an `await`. Otherwise frozen arrays are arrays: reads,
+//! the GCs and the generated code don't distinguish them.
+
+use crate::gc::barrier::permuted_array_barrier;
+use crate::memory::{alloc_array, alloc_blob, Memory};
+use crate::rts_trap_with;
+use crate::types::{Array, Bytes, Value, TAG_FROZEN_ARRAY};
+
+use core::cmp::Ordering;
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Returns element `idx` of the array. Traps when `idx` is out of bounds.
+#[no_mangle]
+pub unsafe extern "C" fn array_get(arr: Value, idx: u32) -> Value {
+    let arr = arr.as_array();
+    check_array_idx(arr, idx);
+    arr.get(idx)
+}
+
+/// Sets element `idx` of the array. Traps when `idx` is out of bounds.
+///
+/// Does not call a write barrier, see `gc::barrier`.
+#[no_mangle]
+pub unsafe extern "C" fn array_set(arr: Value, idx: u32, value: Value) {
+    check_not_frozen(arr);
+    let arr = arr.as_array();
+    check_array_idx(arr, idx);
+    arr.set(idx, value);
+}
+
+unsafe fn check_array_idx(arr: *mut Array, idx: u32) {
+    if idx >= arr.len() {
+        rts_trap_with("Array index out of bounds");
+    }
+}
+
+/// Freeze the array in place, and return it. Freezing a frozen array does nothing.
+#[no_mangle]
+pub unsafe extern "C" fn array_freeze(arr: Value) -> Value {
+    (*arr.as_array()).header.tag = TAG_FROZEN_ARRAY;
+    arr
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn array_is_frozen(arr: Value) -> bool {
+    arr.tag() == TAG_FROZEN_ARRAY
+}
+
+unsafe fn check_not_frozen(arr: Value) {
+    if array_is_frozen(arr) {
+        rts_trap_with("Mutation of a frozen array");
+    }
+}
+
+/// Arrays up to this length are sorted with insertion sort
+const INSERTION_SORT_MAX_LEN: u32 = 16;
+
+#[cfg(feature = "ic")]
+extern "C" {
+    // Generated by `moc`. Calls the comparison closure `cmp` and returns a negative number, zero, or
+    // a positive number when the result is `#less`, `#equal`, or `#greater`, respectively.
+    fn compare_moc(cmp: Value, a: Value, b: Value) -> i32;
+}
+
+#[cfg(feature = "ic")]
+extern "C" {
+    // Generated by `moc`. Calls the closure `f` with `x` and returns the result.
+    fn apply_moc(f: Value, x: Value) -> Value;
+
+    // Generated by `moc`. Calls the predicate closure `pred` with `x` and returns 1 when the result
+    // is `true`, 0 otherwise.
+    fn predicate_moc(pred: Value, x: Value) -> u32;
+
+    // Generated by `moc`. Calls the equality closure `eq` with `a` and `b` and returns 1 when the
+    // result is `true`, 0 otherwise.
+    fn equal_moc(eq: Value, a: Value, b: Value) -> u32;
+}
+
+/// Sort a mutable array in place with the comparison closure `cmp`. The sort is not stable.
+#[ic_mem_fn(ic_only)]
+unsafe fn array_sort<M: Memory>(mem: &mut M, arr: Value, cmp: Value) {
+    array_sort_internal(mem, arr, &mut |_, a, b| compare_moc(cmp, a, b).cmp(&0));
+}
+
+/// Sort an array in place with introsort: quicksort, falling back to heapsort when the recursion
+/// gets too deep, and to insertion sort for short ranges. The sort is not stable.
+///
+/// The comparison function can allocate. Elements are always read from and written to the array,
+/// addresses of the elements are not kept across calls to `cmp`. Elements are only moved within
+/// the array, which doesn't affect the snapshot of the incremental GC, but can move pointers to
+/// the young generation to fields that are not remembered, so the array goes through
+/// `permuted_array_barrier` after sorting.
+pub unsafe fn array_sort_internal<M: Memory, F: FnMut(&mut M, Value, Value) -> Ordering>(
+    mem: &mut M,
+    arr: Value,
+    cmp: &mut F,
+) {
+    check_not_frozen(arr);
+    let len = arr.as_array().len();
+    let depth_limit = 2 * (32 - len.leading_zeros());
+    introsort(arr, 0, len, depth_limit, &mut |a, b| cmp(mem, a, b));
+    permuted_array_barrier(mem, arr.as_array());
+}
+
+/// Sort the range `lo..hi` of the array
+unsafe fn introsort<F: FnMut(Value, Value) -> Ordering>(
+    arr: Value,
+    mut lo: u32,
+    mut hi: u32,
+    mut depth_limit: u32,
+    cmp: &mut F,
+) {
+    loop {
+        if hi - lo <= INSERTION_SORT_MAX_LEN {
+            insertion_sort(arr, lo, hi, cmp);
+            return;
+        }
+
+        if depth_limit == 0 {
+            heapsort(arr, lo, hi, cmp);
+            return;
+        }
+
+        depth_limit -= 1;
+
+        let pivot = partition(arr, lo, hi, cmp);
+
+        // Recurse into the smaller part, loop on the larger part, to keep the Rust stack shallow
+        if pivot - lo < hi - pivot {
+            introsort(arr, lo, pivot, depth_limit, cmp);
+            lo = pivot + 1;
+        } else {
+            introsort(arr, pivot + 1, hi, depth_limit, cmp);
+            hi = pivot;
+        }
+    }
+}
+
+/// Partition the range `lo..hi` around a median-of-three pivot. Returns the index of the pivot.
+/// Elements before the pivot are not greater than the pivot, elements after it are not less.
+unsafe fn partition<F: FnMut(Value, Value) -> Ordering>(
+    arr: Value,
+    lo: u32,
+    hi: u32,
+    cmp: &mut F,
+) -> u32 {
+    let mid = lo + (hi - lo) / 2;
+    let last = hi - 1;
+
+    // Sort `lo`, `mid`, `last`, then move the median to `last`
+    if cmp(get(arr, mid), get(arr, lo)) == Ordering::Less {
+        swap(arr, mid, lo);
+    }
+    if cmp(get(arr, last), get(arr, mid)) == Ordering::Less {
+        swap(arr, last, mid);
+        if cmp(get(arr, mid), get(arr, lo)) == Ordering::Less {
+            swap(arr, mid, lo);
+        }
+    }
+    swap(arr, mid, last);
+
+    let mut store = lo;
+    for i in lo..last {
+        if cmp(get(arr, i), get(arr, last)) == Ordering::Less {
+            swap(arr, i, store);
+            store += 1;
+        }
+    }
+    swap(arr, store, last);
+
+    store
+}
+
+unsafe fn insertion_sort<F: FnMut(Value, Value) -> Ordering>(
+    arr: Value,
+    lo: u32,
+    hi: u32,
+    cmp: &mut F,
+) {
+    for i in lo + 1..hi {
+        let mut j = i;
+        while j > lo && cmp(get(arr, j), get(arr, j - 1)) == Ordering::Less {
+            swap(arr, j, j - 1);
+            j -= 1;
+        }
+    }
+}
+
+unsafe fn heapsort<F: FnMut(Value, Value) -> Ordering>(arr: Value, lo: u32, hi: u32, cmp: &mut F) {
+    let len = hi - lo;
+
+    for i in (0..len / 2).rev() {
+        sift_down(arr, lo, i, len, cmp);
+    }
+
+    for end in (1..len).rev() {
+        swap(arr, lo, lo + end);
+        sift_down(arr, lo, 0, end, cmp);
+    }
+}
+
+/// Restore the max-heap property of the heap `arr[lo..lo + len]`, starting at heap index `root`
+unsafe fn sift_down<F: FnMut(Value, Value) -> Ordering>(
+    arr: Value,
+    lo: u32,
+    mut root: u32,
+    len: u32,
+    cmp: &mut F,
+) {
+    loop {
+        let mut child = 2 * root + 1;
+        if child >= len {
+            return;
+        }
+
+        if child + 1 < len && cmp(get(arr, lo + child), get(arr, lo + child + 1)) == Ordering::Less
+        {
+            child += 1;
+        }
+
+        if cmp(get(arr, lo + root), get(arr, lo + child)) != Ordering::Less {
+            return;
+        }
+
+        swap(arr, lo + root, lo + child);
+        root = child;
+    }
+}
+
+/// Array of length `len` with the results of the closure `f` applied to the indices `0..len`
+#[ic_mem_fn(ic_only)]
+unsafe fn array_tabulate<M: Memory>(mem: &mut M, len: u32, f: Value) -> Value {
+    let mut result = Value::from_scalar(0);
+    array_tabulate_internal(mem, len, &mut result, &mut |_, i| apply_moc(f, i));
+    result
+}
+
+/// Array with the results of the closure `f` applied to the elements of `arr`
+#[ic_mem_fn(ic_only)]
+unsafe fn array_map<M: Memory>(mem: &mut M, arr: Value, f: Value) -> Value {
+    let mut arr = arr;
+    let mut result = Value::from_scalar(0);
+    array_map_internal(mem, &mut arr, &mut result, &mut |_, x| apply_moc(f, x));
+    result
+}
+
+/// Array with the elements of `arr` that satisfy the predicate closure `pred`
+#[ic_mem_fn(ic_only)]
+unsafe fn array_filter<M: Memory>(mem: &mut M, arr: Value, pred: Value) -> Value {
+    let mut arr = arr;
+    let mut result = Value::from_scalar(0);
+    array_filter_internal(mem, &mut arr, &mut result, &mut |_, x| {
+        predicate_moc(pred, x) != 0
+    });
+    result
+}
+
+/// Index of `key` in the array `arr` sorted with the comparison closure `cmp`, or the bitwise
+/// complement of the index where `key` would be inserted to keep the array sorted when it's not in
+/// the array. With equal elements, any of them is found.
+#[ic_mem_fn(ic_only)]
+unsafe fn array_binary_search<M: Memory>(mem: &mut M, arr: Value, key: Value, cmp: Value) -> i32 {
+    let mut arr = arr;
+    let mut key = key;
+    array_binary_search_internal(mem, &mut arr, &mut key, &mut |_, a, b| {
+        compare_moc(cmp, a, b).cmp(&0)
+    })
+}
+
+/// Array of the elements of `arr`, with runs of adjacent elements equal by the closure `eq`
+/// collapsed into their first element
+#[ic_mem_fn(ic_only)]
+unsafe fn array_dedup<M: Memory>(mem: &mut M, arr: Value, eq: Value) -> Value {
+    let mut arr = arr;
+    let mut result = Value::from_scalar(0);
+    array_dedup_internal(mem, &mut arr, &mut result, &mut |_, a, b| {
+        equal_moc(eq, a, b) != 0
+    });
+    result
+}
+
+/// Value of the slots of an array allocated by `array_tabulate_internal` that are not written
+/// yet. A scalar, so the GCs don't follow it.
+pub const TABULATE_SENTINEL: Value = Value::from_scalar(0);
+
+/// Allocate an array of length `len` in `result`, and write slot `i` with the result of `f` on `i`
+/// as a `Nat`, in order. Array lengths are below 2^30, so the indices are always compact `Nat`s,
+/// i.e. scalars.
+///
+/// `f` can allocate, and can move objects as long as it updates `result`, so the array pointer is
+/// read from `result` after every call. Before the calls the slots are filled with
+/// `TABULATE_SENTINEL`, for the GC to never see uninitialized slots, and then every slot is written
+/// once with the result of `f`. The array needs no write barrier as it is new.
+pub unsafe fn array_tabulate_internal<M: Memory, F: FnMut(&mut M, Value) -> Value>(
+    mem: &mut M,
+    len: u32,
+    result: *mut Value,
+    f: &mut F,
+) {
+    *result = alloc_array(mem, len);
+    for i in 0..len {
+        (*result).as_array().set(i, TABULATE_SENTINEL);
+    }
+
+    for i in 0..len {
+        let x = f(mem, Value::from_scalar(i));
+        (*result).as_array().set(i, x);
+    }
+}
+
+/// Allocate an array of the length of the array at `arr` in `result`, and fill it with the results
+/// of `f` on the elements of `arr`, in order.
+///
+/// Like in `array_tabulate_internal`, `f` can allocate and move objects as long as it updates
+/// `arr` and `result`.
+pub unsafe fn array_map_internal<M: Memory, F: FnMut(&mut M, Value) -> Value>(
+    mem: &mut M,
+    arr: *mut Value,
+    result: *mut Value,
+    f: &mut F,
+) {
+    let len = (*arr).as_array().len();
+    array_tabulate_internal(mem, len, result, &mut |mem, i| {
+        f(mem, get(*arr, i.get_scalar()))
+    });
+}
+
+/// Allocate an array with the elements of the array at `arr` that satisfy `pred` in `result`, in
+/// order.
+///
+/// `pred` is called once per element, and the results are recorded in a bitmap, so the result
+/// array can be allocated with its exact length after the calls. The bitmap is kept in `result`
+/// until then. Like in `array_tabulate_internal`, `pred` can allocate and move objects.
+pub unsafe fn array_filter_internal<M: Memory, P: FnMut(&mut M, Value) -> bool>(
+    mem: &mut M,
+    arr: *mut Value,
+    result: *mut Value,
+    pred: &mut P,
+) {
+    let len = (*arr).as_array().len();
+    *result = alloc_blob(mem, Bytes((len + 7) / 8));
+    for i in 0..(len + 7) / 8 {
+        (*result).as_blob_mut().set(i, 0);
+    }
+
+    let mut n_selected = 0;
+    for i in 0..len {
+        if pred(mem, get(*arr, i)) {
+            let bitmap = (*result).as_blob_mut();
+            bitmap.set(i / 8, bitmap.get(i / 8) | (1 << (i % 8)));
+            n_selected += 1;
+        }
+    }
+
+    let bitmap = *result;
+    let selected = alloc_array(mem, n_selected);
+    let mut j = 0;
+    for i in 0..len {
+        if bitmap.as_blob().get(i / 8) & (1 << (i % 8)) != 0 {
+            selected.as_array().set(j, get(*arr, i));
+            j += 1;
+        }
+    }
+    *result = selected;
+}
+
+/// Binary search of the value at `key` in the array at `arr`, sorted by `cmp`. `cmp` is called
+/// with an element of the array and the key. Returns the index of an element equal to the key, or
+/// the bitwise complement (a negative number) of the index where the key would be inserted.
+///
+/// Like in `array_tabulate_internal`, `cmp` can allocate and move objects as long as it updates
+/// `arr` and `key`, which are read after every call. Array lengths are below 2^30, so indices and
+/// their complements fit in an `i32`.
+pub unsafe fn array_binary_search_internal<
+    M: Memory,
+    F: FnMut(&mut M, Value, Value) -> Ordering,
+>(
+    mem: &mut M,
+    arr: *mut Value,
+    key: *mut Value,
+    cmp: &mut F,
+) -> i32 {
+    // The key is in `lo..hi` if it's in the array
+    let mut lo = 0;
+    let mut hi = (*arr).as_array().len();
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        match cmp(mem, get(*arr, mid), *key) {
+            Ordering::Less => lo = mid + 1,
+            Ordering::Greater => hi = mid,
+            Ordering::Equal => return mid as i32,
+        }
+    }
+    !(lo as i32)
+}
+
+/// Allocate an array with the elements of the array at `arr` in `result`, without the elements
+/// that are equal by `eq` to the element before them. `eq` is called once for each pair of adjacent
+/// elements, in order, with the earlier element first.
+///
+/// Like in `array_filter_internal`, `eq` can allocate and move objects.
+pub unsafe fn array_dedup_internal<M: Memory, P: FnMut(&mut M, Value, Value) -> bool>(
+    mem: &mut M,
+    arr: *mut Value,
+    result: *mut Value,
+    eq: &mut P,
+) {
+    let mut idx = 0;
+    array_filter_internal(mem, arr, result, &mut |mem, x| {
+        let keep = idx == 0 || !eq(mem, get(*arr, idx - 1), x);
+        idx += 1;
+        keep
+    });
+}
+
+unsafe fn get(arr: Value, idx: u32) -> Value {
+    arr.as_array().get(idx)
+}
+
+unsafe fn swap(arr: Value, i: u32, j: u32) {
+    let arr = arr.as_array();
+    let tmp = arr.get(i);
+    arr.set(i, arr.get(j));
+    arr.set(j, tmp);
+}
@@ -0,0 +1,129 @@
+//! Growable arrays with amortized O(1) append, to build arrays element by element without copying
+//! the elements built so far on every append. The elements are copied to a flat array only when
+//! the buffer is frozen with `array_buffer_to_array`.
+//!
+//! A buffer is an array of two elements: the number of elements as a scalar, and the storage
+//! array. The storage array is larger than the number of elements, and doubles when it is full.
+//! The unused slots after the elements hold scalars (`Value::from_scalar(0)`), so the GCs visit the
+//! storage array as any other array, and don't trace the unused slots, nor keep alive elements that
+//! were in the slots before. Elements are only appended, so used slots never become unused.
+//!
+//! The buffer and its storage array may be in the old generation, or already marked by an
+//! incremental GC, so pointers are stored in them with the write barrier (see `gc::barrier`).
+
+use crate::gc::barrier::array_set_with_barrier;
+use crate::memory::{alloc_array, Memory};
+use crate::rts_trap_with;
+use crate::types::Value;
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Capacity of the storage array of a new buffer
+pub const INITIAL_ARRAY_BUFFER_CAPACITY: u32 = 8;
+
+const COUNT_IDX: u32 = 0;
+const STORAGE_IDX: u32 = 1;
+
+/// Allocate an empty buffer
+#[ic_mem_fn]
+pub unsafe fn array_buffer_new<M: Memory>(mem: &mut M) -> Value {
+    let storage = alloc_storage(mem, INITIAL_ARRAY_BUFFER_CAPACITY);
+    let buf = alloc_array(mem, 2);
+    buf.as_array().set(COUNT_IDX, Value::from_scalar(0));
+    buf.as_array().set(STORAGE_IDX, storage);
+    buf
+}
+
+/// Number of elements in the buffer
+#[no_mangle]
+pub unsafe extern "C" fn array_buffer_len(buf: Value) -> u32 {
+    buf.as_array().get(COUNT_IDX).get_scalar()
+}
+
+/// Number of elements the buffer can hold before its storage array is reallocated
+pub unsafe fn array_buffer_capacity(buf: Value) -> u32 {
+    storage(buf).as_array().len()
+}
+
+/// Returns element `idx` of the buffer. Traps when `idx` is out of bounds.
+#[no_mangle]
+pub unsafe extern "C" fn array_buffer_get(buf: Value, idx: u32) -> Value {
+    if idx >= array_buffer_len(buf) {
+        rts_trap_with("Array buffer index out of bounds");
+    }
+    storage(buf).as_array().get(idx)
+}
+
+/// Append an element to the buffer
+#[ic_mem_fn]
+pub unsafe fn array_buffer_add<M: Memory>(mem: &mut M, buf: Value, elem: Value) {
+    let len = array_buffer_len(buf);
+    reserve(mem, buf, len + 1);
+    array_set_with_barrier(mem, storage(buf).as_array(), len, elem);
+    set_len(buf, len + 1);
+}
+
+/// Append the elements of the array `arr` to the buffer
+#[ic_mem_fn]
+pub unsafe fn array_buffer_append<M: Memory>(mem: &mut M, buf: Value, arr: Value) {
+    let len = array_buffer_len(buf);
+    let n = arr.as_array().len();
+    reserve(mem, buf, len + n);
+    let storage = storage(buf).as_array();
+    for i in 0..n {
+        array_set_with_barrier(mem, storage, len + i, arr.as_array().get(i));
+    }
+    set_len(buf, len + n);
+}
+
+/// Array with the elements of the buffer. The buffer can still be appended to afterwards, the
+/// array is not affected.
+#[ic_mem_fn]
+pub unsafe fn array_buffer_to_array<M: Memory>(mem: &mut M, buf: Value) -> Value {
+    let len = array_buffer_len(buf);
+    let arr = alloc_array(mem, len);
+    let storage = storage(buf).as_array();
+    for i in 0..len {
+        arr.as_array().set(i, storage.get(i));
+    }
+    arr
+}
+
+unsafe fn storage(buf: Value) -> Value {
+    buf.as_array().get(STORAGE_IDX)
+}
+
+unsafe fn set_len(buf: Value, len: u32) {
+    buf.as_array().set(COUNT_IDX, Value::from_scalar(len));
+}
+
+/// Allocate a storage array of the given capacity with all slots unused
+unsafe fn alloc_storage<M: Memory>(mem: &mut M, capacity: u32) -> Value {
+    let storage = alloc_array(mem, capacity);
+    for i in 0..capacity {
+        storage.as_array().set(i, Value::from_scalar(0));
+    }
+    storage
+}
+
+/// Make sure the storage array of the buffer can hold `len` elements. Doubles the capacity, or
+/// more if needed, so that appending `n` elements one by one copies O(n) elements in total.
+unsafe fn reserve<M: Memory>(mem: &mut M, buf: Value, len: u32) {
+    let capacity = array_buffer_capacity(buf);
+    if len <= capacity {
+        return;
+    }
+
+    // Element counts are stored as scalars
+    if len >> 31 != 0 {
+        rts_trap_with("Array buffer too large");
+    }
+
+    let new_capacity = core::cmp::max(len, capacity.saturating_mul(2));
+    let new_storage = alloc_storage(mem, new_capacity);
+    let old_storage = storage(buf).as_array();
+    for i in 0..array_buffer_len(buf) {
+        new_storage.as_array().set(i, old_storage.get(i));
+    }
+    array_set_with_barrier(mem, buf.as_array(), STORAGE_IDX, new_storage);
+}
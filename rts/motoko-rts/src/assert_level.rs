@@ -0,0 +1,44 @@
+//! Assertion levels of the RTS.
+//!
+//! `debug_assert!` checks vanish in release builds. For checks that are cheap enough to keep in
+//! production, the RTS has assertion levels, selected at compile time with Cargo features:
+//!
+//! - `Off` (no feature, release builds): no checks beyond the ones that are always done.
+//!
+//! - `Cheap` (`rts-assert-cheap` feature, and debug builds): O(1) checks per operation.
+//!   Currently the tags of objects in threading and unthreading of mark-compact GC
+//!   (`pointer_width::thread`, `pointer_width::unthread`): the pointed object's header in
+//!   `thread`, unless already threaded, and the header at the end of the chain of threaded fields
+//!   in `unthread` must be a tag between `TAG_OBJECT` and `MAX_TAG`. A corrupted header traps when
+//!   it is threaded, instead of being copied into a field and read as a pointer later.
+//!
+//! - `Full` (`rts-assert-full` feature, implies `rts-assert-cheap`): the `Cheap` checks, and the
+//!   whole-heap verifier (`gc::verify`) after each mark-compact GC, like the `debug-heap`
+//!   feature. Linear in the heap size, like the GC itself.
+//!
+//! Checks of a level are also done at the higher levels. Functions with checks take the level as
+//! an argument, so that the checks can be tested at all levels. The RTS passes `ASSERT_LEVEL`,
+//! which is a constant, so the checks of disabled levels are compiled out.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AssertLevel {
+    Off = 0,
+    Cheap = 1,
+    Full = 2,
+}
+
+/// Assertion level of the RTS, selected with the `rts-assert-cheap` and `rts-assert-full` features
+pub const ASSERT_LEVEL: AssertLevel = if cfg!(feature = "rts-assert-full") {
+    AssertLevel::Full
+} else if cfg!(feature = "rts-assert-cheap") || cfg!(debug_assertions) {
+    AssertLevel::Cheap
+} else {
+    AssertLevel::Off
+};
+
+impl AssertLevel {
+    /// Whether the checks of `checks` are done at this level
+    pub const fn checks(self, checks: AssertLevel) -> bool {
+        checks as u8 <= self as u8
+    }
+}
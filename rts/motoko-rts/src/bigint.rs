@@ -3,6 +3,8 @@
 //! - libtommath memory management
 //! - libtommath wrappers
 //! - (s)leb128 encoding/decoding for bigints
+//! - parsing `Nat` and `Int` from text
+//! - bit operations on `Nat` and `Int`
 
 /*
 A libtommath arbitrary precision integer is a struct (`mp_int`) that contains a pointer to a data
@@ -34,8 +36,11 @@ This scheme makes the following assumptions:
 use crate::buf::{read_byte, Buf};
 use crate::mem_utils::memcpy_bytes;
 use crate::memory::Memory;
+use crate::text::blob_of_text;
 use crate::tommath_bindings::*;
-use crate::types::{size_of, BigInt, Bytes, Stream, Value, TAG_BIGINT};
+use crate::types::{size_of, skew, BigInt, Bytes, Stream, Value, TAG_BIGINT};
+
+use core::slice;
 
 use motoko_rts_macros::ic_mem_fn;
 
@@ -137,8 +142,7 @@ pub(crate) unsafe fn mp_get_u32(p: *const mp_int) -> u32 {
     mp_get_i32(p) as u32
 }
 
-#[cfg(feature = "ic")]
-unsafe fn mp_get_u64(p: *const mp_int) -> u64 {
+pub(crate) unsafe fn mp_get_u64(p: *const mp_int) -> u64 {
     mp_get_i64(p) as u64
 }
 
@@ -152,14 +156,14 @@ pub(crate) unsafe fn mp_iszero(p: *const mp_int) -> bool {
 }
 
 // Allocates a mp_int on the stack
-unsafe fn tmp_bigint() -> mp_int {
+pub(crate) unsafe fn tmp_bigint() -> mp_int {
     let mut i: mp_int = core::mem::zeroed();
     check(mp_init(&mut i));
     i
 }
 
 // Persists an mp_int from the stack on the heap
-unsafe fn persist_bigint(i: mp_int) -> Value {
+pub(crate) unsafe fn persist_bigint(i: mp_int) -> Value {
     if i.dp == core::ptr::null_mut() {
         panic!("persist_bigint: dp == NULL?");
     }
@@ -326,6 +330,10 @@ pub unsafe extern "C" fn bigint_sub(a: Value, b: Value) -> Value {
     persist_bigint(i)
 }
 
+/// `mp_mul` uses schoolbook multiplication for small operands, and switches to Karatsuba and then
+/// Toom-Cook multiplication when both operands are larger than `MP_MUL_KARATSUBA_CUTOFF` and
+/// `MP_MUL_TOOM_CUTOFF` digits. With `MP_FIXED_CUTOFFS` (see `Makefile`) these are libtommath's
+/// tuned defaults. The result is clamped (no leading zero digits).
 #[no_mangle]
 pub unsafe extern "C" fn bigint_mul(a: Value, b: Value) -> Value {
     let mut i = tmp_bigint();
@@ -629,3 +637,393 @@ pub unsafe extern "C" fn bigint_sleb128_decode_word64(
 
     bigint_of_int64(signed)
 }
+
+/// Returned by `text_to_nat` and `text_to_int` on invalid input. This is a null pointer, so it is
+/// neither a compact nor a boxed number.
+pub const INVALID_NUMBER: Value = Value::from_raw(skew(0) as u32);
+
+/// Parse a `Nat` in the given radix (2 to 36). Digits are `0-9` and letters (either case), and
+/// underscores can separate digits, as in `1_000_000`. Returns `INVALID_NUMBER` when the radix
+/// is out of range or the text is not a number.
+#[ic_mem_fn]
+pub unsafe fn text_to_nat<M: Memory>(mem: &mut M, text: Value, radix: u32) -> Value {
+    parse_digits(text_bytes(mem, text), radix, false)
+}
+
+/// Parse an `Int` in the given radix, like `text_to_nat`, with an optional `+` or `-` sign
+#[ic_mem_fn]
+pub unsafe fn text_to_int<M: Memory>(mem: &mut M, text: Value, radix: u32) -> Value {
+    let bytes = text_bytes(mem, text);
+    match bytes.split_first() {
+        Some((b'-', digits)) => parse_digits(digits, radix, true),
+        Some((b'+', digits)) => parse_digits(digits, radix, false),
+        _ => parse_digits(bytes, radix, false),
+    }
+}
+
+unsafe fn text_bytes<'a, M: Memory>(mem: &mut M, text: Value) -> &'a [u8] {
+    let blob = blob_of_text(mem, text).as_blob();
+    slice::from_raw_parts(blob.payload_const(), blob.len().as_usize())
+}
+
+unsafe fn parse_digits(digits: &[u8], radix: u32, negate: bool) -> Value {
+    if !(2..=36).contains(&radix) {
+        return INVALID_NUMBER;
+    }
+
+    // Parse into `small` until it overflows, then continue in a bignum
+    let mut small: u64 = 0;
+    let mut big: Option<(mp_int, mp_int, mp_int)> = None;
+
+    // Underscores are only allowed between digits
+    let mut after_digit = false;
+
+    for &byte in digits {
+        if byte == b'_' {
+            if !after_digit {
+                return INVALID_NUMBER;
+            }
+            after_digit = false;
+            continue;
+        }
+
+        let digit = match char::from(byte).to_digit(radix) {
+            Some(digit) => digit,
+            None => return INVALID_NUMBER,
+        };
+        after_digit = true;
+
+        if big.is_none() {
+            match small
+                .checked_mul(u64::from(radix))
+                .and_then(|n| n.checked_add(u64::from(digit)))
+            {
+                Some(n) => {
+                    small = n;
+                    continue;
+                }
+                None => {
+                    let mut i = tmp_bigint();
+                    mp_set_u64(&mut i, small);
+                    let mut radix_big = tmp_bigint();
+                    mp_set_u32(&mut radix_big, radix);
+                    big = Some((i, radix_big, tmp_bigint()));
+                }
+            }
+        }
+
+        let (i, radix_big, digit_big) = big.as_mut().unwrap();
+        mp_set_u32(digit_big, digit);
+        check(mp_mul(i, radix_big, i));
+        check(mp_add(i, digit_big, i));
+    }
+
+    // No digits, or a trailing underscore
+    if !after_digit {
+        return INVALID_NUMBER;
+    }
+
+    let mut i = match big {
+        Some((i, _, _)) => i,
+        None => {
+            // Compact representation, as in `bigint_leb128_decode_word64`
+            if small <= 1 << 30 {
+                let n = if negate {
+                    -(small as i32)
+                } else {
+                    small as i32
+                };
+                if n < 1 << 30 {
+                    return Value::from_signed_scalar(n);
+                }
+            }
+            let mut i = tmp_bigint();
+            mp_set_u64(&mut i, small);
+            i
+        }
+    };
+
+    if negate {
+        check(mp_neg(&i, &mut i));
+    }
+
+    persist_bigint(i)
+}
+
+/*
+Bit operations on `Nat` and `Int`
+---------------------------------
+
+libtommath stores numbers as sign and magnitude, in digits of `DIGIT_BIT` bits. The bitwise
+operations work on the two's complement of the numbers instead: a negative number `-n` has the
+infinite bit pattern of `!(n - 1)`, so `-1 & n == n` and `-1 ^ n == -n - 1`, as with fixed-width
+integers. The operations convert the digits of the operands to two's complement on the fly
+(`TwosComplementDigits`), combine them, and convert the result back to sign and magnitude.
+
+The arguments can be compact or boxed, and the results are in canonical form: compact when the
+number fits, as the compiler expects for `Nat` and `Int` values.
+*/
+
+/// Bits in an `mp_digit`, `MP_DIGIT_BIT` with `MP_32BIT` (see `TOMMATH_FLAGS` in `Makefile`)
+const DIGIT_BIT: u32 = 28;
+
+const DIGIT_MASK: mp_digit = (1 << DIGIT_BIT) - 1;
+
+/// `mp_int` of a compact or boxed number. The `mp_int` of a boxed number shares its digits, so it
+/// should not be modified.
+unsafe fn mp_int_of_value(n: Value) -> mp_int {
+    if n.is_scalar() {
+        let mut i = tmp_bigint();
+        mp_set_i32(&mut i, n.get_signed_scalar());
+        i
+    } else {
+        *n.as_bigint().mp_int_ptr()
+    }
+}
+
+/// Persist the number, in compact representation when it fits, as in `bigint_leb128_decode_word64`
+unsafe fn canonical_bigint(i: mp_int) -> Value {
+    if mp_count_bits(&i) <= 31 {
+        let n = mp_get_i64(&i);
+        if (-(1 << 30)..(1 << 30)).contains(&n) {
+            return Value::from_signed_scalar(n as i32);
+        }
+    }
+    persist_bigint(i)
+}
+
+/// Digit `digit` of `-n` in two's complement, where `digit` is a digit of `n` and `carry` is the
+/// carry from the less significant digits, initially 1
+fn negate_digit(digit: mp_digit, carry: &mut mp_digit) -> mp_digit {
+    let negated = (!digit & DIGIT_MASK) + *carry;
+    *carry = negated >> DIGIT_BIT;
+    negated & DIGIT_MASK
+}
+
+/// Digits of a number in two's complement, least significant first, sign-extended beyond the
+/// digits of the magnitude
+struct TwosComplementDigits {
+    digits: *const mp_digit,
+    used: usize,
+    negative: bool,
+    next: usize,
+    carry: mp_digit,
+}
+
+impl TwosComplementDigits {
+    unsafe fn new(i: *const mp_int) -> TwosComplementDigits {
+        TwosComplementDigits {
+            digits: (*i).dp,
+            used: (*i).used as usize,
+            negative: mp_isneg(i),
+            next: 0,
+            carry: 1,
+        }
+    }
+
+    unsafe fn next(&mut self) -> mp_digit {
+        let digit = if self.next < self.used {
+            *self.digits.add(self.next)
+        } else {
+            0
+        };
+        self.next += 1;
+        if self.negative {
+            negate_digit(digit, &mut self.carry)
+        } else {
+            digit
+        }
+    }
+}
+
+/// Apply a bitwise operation to the numbers in two's complement. `op` should map two zero digits
+/// to zero.
+unsafe fn bitwise<Op: Fn(mp_digit, mp_digit) -> mp_digit>(a: Value, b: Value, op: Op) -> Value {
+    let a = mp_int_of_value(a);
+    let b = mp_int_of_value(b);
+
+    // The sign of the result is the operation on the signs, extended to all digits
+    let sign_digit = |i: &mp_int| if mp_isneg(i) { DIGIT_MASK } else { 0 };
+    let negative = op(sign_digit(&a), sign_digit(&b)) & DIGIT_MASK != 0;
+
+    // One more digit than the operands for the sign, so the magnitude of a negative result fits
+    let n_digits = core::cmp::max(a.used, b.used) + 1;
+
+    let mut r: mp_int = core::mem::zeroed();
+    check(mp_init_size(&mut r, n_digits));
+
+    let mut a_digits = TwosComplementDigits::new(&a);
+    let mut b_digits = TwosComplementDigits::new(&b);
+    let mut carry = 1;
+    for i in 0..n_digits as usize {
+        let digit = op(a_digits.next(), b_digits.next()) & DIGIT_MASK;
+        *r.dp.add(i) = if negative {
+            negate_digit(digit, &mut carry)
+        } else {
+            digit
+        };
+    }
+
+    // Drop leading zero digits, like `mp_clamp`
+    let mut used = n_digits;
+    while used > 0 && *r.dp.add(used as usize - 1) == 0 {
+        used -= 1;
+    }
+    r.used = used;
+    // `MP_NEG` is 1, `MP_ZPOS` is 0. Zero is not negative.
+    r.sign = (negative && used != 0) as mp_sign;
+
+    canonical_bigint(r)
+}
+
+/// Bitwise and of `Nat` or `Int` numbers, in two's complement for negative numbers
+#[no_mangle]
+pub unsafe extern "C" fn nat_and(a: Value, b: Value) -> Value {
+    bitwise(a, b, |a, b| a & b)
+}
+
+/// Bitwise or of `Nat` or `Int` numbers, in two's complement for negative numbers
+#[no_mangle]
+pub unsafe extern "C" fn nat_or(a: Value, b: Value) -> Value {
+    bitwise(a, b, |a, b| a | b)
+}
+
+/// Bitwise exclusive or of `Nat` or `Int` numbers, in two's complement for negative numbers
+#[no_mangle]
+pub unsafe extern "C" fn nat_xor(a: Value, b: Value) -> Value {
+    bitwise(a, b, |a, b| a ^ b)
+}
+
+/// `a * 2^shift`. Traps when the result does not fit into memory.
+#[no_mangle]
+pub unsafe extern "C" fn nat_shl(a: Value, shift: u32) -> Value {
+    let a_int = mp_int_of_value(a);
+    if mp_iszero(&a_int) || shift == 0 {
+        return a;
+    }
+    // `mp_mul_2d` fails with `MP_VAL` for shifts above `i32::MAX`
+    let mut i = tmp_bigint();
+    check(mp_mul_2d(&a_int, shift as i32, &mut i));
+    canonical_bigint(i)
+}
+
+/// `a / 2^shift`, rounded towards negative infinity, as an arithmetic right shift in two's
+/// complement
+#[no_mangle]
+pub unsafe extern "C" fn nat_shr(a: Value, shift: u32) -> Value {
+    let a_int = mp_int_of_value(a);
+    let negative = mp_isneg(&a_int);
+
+    if shift >= mp_count_bits(&a_int) as u32 {
+        return Value::from_signed_scalar(if negative { -1 } else { 0 });
+    }
+
+    // `mp_div_2d` rounds towards zero
+    let mut i = tmp_bigint();
+    let mut rem = tmp_bigint();
+    check(mp_div_2d(&a_int, shift as i32, &mut i, &mut rem));
+    if negative && !mp_iszero(&rem) {
+        let mut one = tmp_bigint();
+        mp_set_u32(&mut one, 1);
+        let i_ptr: *mut mp_int = &mut i;
+        check(mp_sub(i_ptr, &one, i_ptr));
+    }
+    canonical_bigint(i)
+}
+
+/// Number of one bits of a `Nat`. For a negative `Int`, number of zero bits in two's complement
+/// (the bits that differ from the sign bit), which is the number of one bits of `-a - 1`.
+#[no_mangle]
+pub unsafe extern "C" fn nat_popcount(a: Value) -> u32 {
+    let a_int = mp_int_of_value(a);
+    let negative = mp_isneg(&a_int);
+
+    // Digits of a negative number beyond the magnitude are all ones
+    let mut digits = TwosComplementDigits::new(&a_int);
+    let mut count = 0;
+    for _ in 0..a_int.used {
+        let digit = digits.next();
+        let digit = if negative { !digit & DIGIT_MASK } else { digit };
+        count += digit.count_ones();
+    }
+    count
+}
+
+/// `n` as an unsigned number of at most `bits` bits, or `None` when `n` is negative or does not fit
+unsafe fn nat_to_bits_checked(n: Value, bits: u32) -> Option<u128> {
+    // Compact numbers have at most 30 bits
+    debug_assert!((32..=128).contains(&bits));
+    if n.is_scalar() {
+        let n = n.get_signed_scalar();
+        return if n < 0 { None } else { Some(n as u128) };
+    }
+
+    let mp_int = n.as_bigint().mp_int_ptr();
+    if mp_isneg(mp_int) || mp_count_bits(mp_int) as u32 > bits {
+        return None;
+    }
+
+    let lo = u128::from(mp_get_u64(mp_int));
+    if mp_count_bits(mp_int) <= 64 {
+        return Some(lo);
+    }
+
+    let mut hi = tmp_bigint();
+    check(mp_div_2d(mp_int, 64, &mut hi, core::ptr::null_mut()));
+    Some(u128::from(mp_get_u64(&hi)) << 64 | lo)
+}
+
+/// `n` as an unsigned number of at most `bits` bits, trapping with the `negative` or `too_large`
+/// message when it does not fit
+unsafe fn nat_to_bits_trap(n: Value, bits: u32, negative: &str, too_large: &str) -> u128 {
+    match nat_to_bits_checked(n, bits) {
+        Some(n) => n,
+        None if mp_isneg(&mp_int_of_value(n)) => crate::rts_trap_with(negative),
+        None => crate::rts_trap_with(too_large),
+    }
+}
+
+/// `Nat` to `u32`, `None` when the number is negative or at least `2^32`
+pub unsafe fn nat_to_u32_checked(n: Value) -> Option<u32> {
+    nat_to_bits_checked(n, 32).map(|n| n as u32)
+}
+
+/// `Nat` to `u64`, `None` when the number is negative or at least `2^64`
+pub unsafe fn nat_to_u64_checked(n: Value) -> Option<u64> {
+    nat_to_bits_checked(n, 64).map(|n| n as u64)
+}
+
+/// `Nat` to `u128`, `None` when the number is negative or at least `2^128`
+pub unsafe fn nat_to_u128_checked(n: Value) -> Option<u128> {
+    nat_to_bits_checked(n, 128)
+}
+
+/// `Nat` to `u32`, traps when the number is negative or at least `2^32`
+pub unsafe fn nat_to_u32_trap(n: Value) -> u32 {
+    nat_to_bits_trap(
+        n,
+        32,
+        "nat_to_u32: negative number",
+        "nat_to_u32: number does not fit in 32 bits",
+    ) as u32
+}
+
+/// `Nat` to `u64`, traps when the number is negative or at least `2^64`
+pub unsafe fn nat_to_u64_trap(n: Value) -> u64 {
+    nat_to_bits_trap(
+        n,
+        64,
+        "nat_to_u64: negative number",
+        "nat_to_u64: number does not fit in 64 bits",
+    ) as u64
+}
+
+/// `Nat` to `u128`, traps when the number is negative or at least `2^128`
+pub unsafe fn nat_to_u128_trap(n: Value) -> u128 {
+    nat_to_bits_trap(
+        n,
+        128,
+        "nat_to_u128: negative number",
+        "nat_to_u128: number does not fit in 128 bits",
+    )
+}
@@ -0,0 +1,117 @@
+//! Growable blobs with amortized O(1) append, to build blobs byte by byte without copying the bytes
+//! written so far on every append. The bytes are copied to a blob of the exact size only when the
+//! builder is finished with `blob_builder_finish`.
+//!
+//! A builder is an array of two elements: the number of bytes as a scalar, and the storage blob.
+//! The storage blob is larger than the number of bytes, and doubles when it is full. Blobs have no
+//! pointer fields, so the GCs copy or move the storage like any other blob, and the builder stays
+//! valid across collections between appends.
+//!
+//! The builder may be in the old generation, or already marked by an incremental GC, so a new
+//! storage blob is stored in it with the write barrier (see `gc::barrier`).
+
+use crate::gc::barrier::array_set_with_barrier;
+use crate::mem_utils::memcpy_bytes;
+use crate::memory::{alloc_array, alloc_blob, Memory};
+use crate::rts_trap_with;
+use crate::types::{Bytes, Value};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Capacity of the storage blob of a new builder, in bytes
+pub const INITIAL_BLOB_BUILDER_CAPACITY: u32 = 32;
+
+const COUNT_IDX: u32 = 0;
+const STORAGE_IDX: u32 = 1;
+
+/// Allocate an empty builder
+#[ic_mem_fn]
+pub unsafe fn blob_builder_new<M: Memory>(mem: &mut M) -> Value {
+    let storage = alloc_blob(mem, Bytes(INITIAL_BLOB_BUILDER_CAPACITY));
+    let builder = alloc_array(mem, 2);
+    builder.as_array().set(COUNT_IDX, Value::from_scalar(0));
+    builder.as_array().set(STORAGE_IDX, storage);
+    builder
+}
+
+/// Number of bytes in the builder
+#[no_mangle]
+pub unsafe extern "C" fn blob_builder_len(builder: Value) -> u32 {
+    builder.as_array().get(COUNT_IDX).get_scalar()
+}
+
+/// Number of bytes the builder can hold before its storage blob is reallocated
+pub unsafe fn blob_builder_capacity(builder: Value) -> u32 {
+    storage(builder).as_blob().len().as_u32()
+}
+
+/// Append a byte to the builder
+#[ic_mem_fn]
+pub unsafe fn blob_builder_push_byte<M: Memory>(mem: &mut M, builder: Value, byte: u32) {
+    let len = blob_builder_len(builder);
+    reserve(mem, builder, len + 1);
+    storage(builder).as_blob_mut().set(len, byte as u8);
+    set_len(builder, len + 1);
+}
+
+/// Append the bytes of the blob `blob` to the builder
+#[ic_mem_fn]
+pub unsafe fn blob_builder_push_bytes<M: Memory>(mem: &mut M, builder: Value, blob: Value) {
+    let len = blob_builder_len(builder);
+    let n = blob.as_blob().len();
+    if n.as_u32() > u32::MAX - len {
+        rts_trap_with("Blob builder too large");
+    }
+    reserve(mem, builder, len + n.as_u32());
+    memcpy_bytes(
+        storage(builder).as_blob_mut().payload_addr() as usize + len as usize,
+        blob.as_blob().payload_const() as usize,
+        n,
+    );
+    set_len(builder, len + n.as_u32());
+}
+
+/// Blob with the bytes of the builder. The builder can still be appended to afterwards, the blob
+/// is not affected.
+#[ic_mem_fn]
+pub unsafe fn blob_builder_finish<M: Memory>(mem: &mut M, builder: Value) -> Value {
+    let len = Bytes(blob_builder_len(builder));
+    let blob = alloc_blob(mem, len);
+    memcpy_bytes(
+        blob.as_blob_mut().payload_addr() as usize,
+        storage(builder).as_blob().payload_const() as usize,
+        len,
+    );
+    blob
+}
+
+unsafe fn storage(builder: Value) -> Value {
+    builder.as_array().get(STORAGE_IDX)
+}
+
+unsafe fn set_len(builder: Value, len: u32) {
+    builder.as_array().set(COUNT_IDX, Value::from_scalar(len));
+}
+
+/// Make sure the storage blob of the builder can hold `len` bytes. Doubles the capacity, or more if
+/// needed, so that appending `n` bytes one by one copies O(n) bytes in total.
+unsafe fn reserve<M: Memory>(mem: &mut M, builder: Value, len: u32) {
+    let capacity = blob_builder_capacity(builder);
+    if len <= capacity {
+        return;
+    }
+
+    // Byte counts are stored as scalars
+    if len >> 31 != 0 {
+        rts_trap_with("Blob builder too large");
+    }
+
+    let new_capacity = core::cmp::max(len, capacity.saturating_mul(2));
+    let new_storage = alloc_blob(mem, Bytes(new_capacity));
+    memcpy_bytes(
+        new_storage.as_blob_mut().payload_addr() as usize,
+        storage(builder).as_blob().payload_const() as usize,
+        Bytes(blob_builder_len(builder)),
+    );
+    array_set_with_barrier(mem, builder.as_array(), STORAGE_IDX, new_storage);
+}
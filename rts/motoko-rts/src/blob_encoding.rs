@@ -0,0 +1,189 @@
+//! Hex and base64 (RFC 4648) encoding of blobs
+//!
+//! Encoding returns the text of the encoding: hex digits are lower case, standard base64 is padded
+//! with `=`, URL-safe base64 (`-` and `_` instead of `+` and `/`) is not padded.
+//!
+//! Decoding takes a text and returns `DECODE_ERROR` when the text is not a valid encoding. Hex
+//! digits can be upper or lower case. Base64 padding is optional, but when present it must pad the
+//! text to a multiple of 4 characters. The unused bits of the last base64 character must be zero,
+//! so every blob has exactly one encoding (up to padding). Characters of the other base64
+//! alphabet, whitespace and line breaks are invalid.
+
+use crate::memory::{alloc_blob, Memory};
+use crate::text::{alloc_text_blob, blob_of_text};
+use crate::types::{Bytes, Value};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Returned by the decoding functions on malformed input. This is not a pointer, so it cannot be
+/// confused with a blob.
+pub const DECODE_ERROR: Value = Value::from_scalar(0);
+
+static HEX_CHARS: &[u8] = b"0123456789abcdef";
+
+static BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+static BASE64_URL_CHARS: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Encode a blob as hex digits
+#[ic_mem_fn]
+pub unsafe fn blob_to_hex<M: Memory>(mem: &mut M, blob: Value) -> Value {
+    let len = blob.as_blob().len().as_u32();
+    let r = alloc_text_blob(mem, Bytes(len * 2));
+
+    let src = blob.as_blob().payload_const();
+    let dest = r.as_blob_mut().payload_addr();
+    for i in 0..len as usize {
+        let byte = *src.add(i);
+        *dest.add(2 * i) = HEX_CHARS[(byte >> 4) as usize];
+        *dest.add(2 * i + 1) = HEX_CHARS[(byte & 0xF) as usize];
+    }
+
+    r
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decode hex digits, `DECODE_ERROR` if the length is odd or there are non-hex characters
+#[ic_mem_fn]
+pub unsafe fn hex_to_blob<M: Memory>(mem: &mut M, text: Value) -> Value {
+    let text = blob_of_text(mem, text);
+    let len = text.as_blob().len().as_u32();
+    if len % 2 != 0 {
+        return DECODE_ERROR;
+    }
+
+    let r = alloc_blob(mem, Bytes(len / 2));
+
+    let src = text.as_blob().payload_const();
+    let dest = r.as_blob_mut().payload_addr();
+    for i in 0..(len / 2) as usize {
+        match (hex_digit(*src.add(2 * i)), hex_digit(*src.add(2 * i + 1))) {
+            (Some(hi), Some(lo)) => *dest.add(i) = (hi << 4) | lo,
+            _ => return DECODE_ERROR,
+        }
+    }
+
+    r
+}
+
+/// Encode a blob as base64, with the URL-safe alphabet and without padding if `url_safe`
+#[ic_mem_fn]
+pub unsafe fn blob_to_base64<M: Memory>(mem: &mut M, blob: Value, url_safe: bool) -> Value {
+    let chars = if url_safe {
+        BASE64_URL_CHARS
+    } else {
+        BASE64_CHARS
+    };
+
+    let len = blob.as_blob().len().as_u32();
+    let encoded_len = if url_safe {
+        (len * 4).div_ceil(3)
+    } else {
+        len.div_ceil(3) * 4
+    };
+    let r = alloc_text_blob(mem, Bytes(encoded_len));
+
+    let src = blob.as_blob().payload_const();
+    let mut dest = r.as_blob_mut().payload_addr();
+    for chunk in 0..len.div_ceil(3) as usize {
+        let n = core::cmp::min(3, len as usize - 3 * chunk);
+
+        let mut group: u32 = 0;
+        for i in 0..3 {
+            group <<= 8;
+            if i < n {
+                group |= *src.add(3 * chunk + i) as u32;
+            }
+        }
+
+        // `n` bytes are `n + 1` characters
+        for i in 0..4 {
+            if i <= n {
+                *dest = chars[((group >> (18 - 6 * i)) & 0b11_1111) as usize];
+            } else if !url_safe {
+                *dest = b'=';
+            } else {
+                break;
+            }
+            dest = dest.add(1);
+        }
+    }
+
+    r
+}
+
+fn base64_digit(c: u8, url_safe: bool) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' if !url_safe => Some(62),
+        b'/' if !url_safe => Some(63),
+        b'-' if url_safe => Some(62),
+        b'_' if url_safe => Some(63),
+        _ => None,
+    }
+}
+
+/// Decode base64 of the standard or URL-safe alphabet, `DECODE_ERROR` on malformed input (see
+/// the module documentation)
+#[ic_mem_fn]
+pub unsafe fn base64_to_blob<M: Memory>(mem: &mut M, text: Value, url_safe: bool) -> Value {
+    let text = blob_of_text(mem, text);
+    let src = text.as_blob().payload_const();
+    let mut len = text.as_blob().len().as_u32() as usize;
+
+    // Strip the padding, which must complete the last group
+    if len % 4 == 0 && len > 0 && *src.add(len - 1) == b'=' {
+        len -= 1;
+        if *src.add(len - 1) == b'=' {
+            len -= 1;
+        }
+    }
+
+    // A group of 4 characters is 3 bytes, a last group of 2 or 3 characters is 1 or 2 bytes
+    let rem = len % 4;
+    if rem == 1 {
+        return DECODE_ERROR;
+    }
+    let decoded_len = len / 4 * 3 + rem.saturating_sub(1);
+
+    let r = alloc_blob(mem, Bytes(decoded_len as u32));
+    let mut dest = r.as_blob_mut().payload_addr();
+
+    for chunk in 0..len.div_ceil(4) {
+        let n = core::cmp::min(4, len - 4 * chunk);
+
+        let mut group: u32 = 0;
+        for i in 0..4 {
+            group <<= 6;
+            if i < n {
+                match base64_digit(*src.add(4 * chunk + i), url_safe) {
+                    Some(digit) => group |= digit as u32,
+                    None => return DECODE_ERROR,
+                }
+            }
+        }
+
+        // `n` characters are `n - 1` bytes, the remaining bits must be zero
+        if group & ((1 << (8 * (4 - n))) - 1) != 0 {
+            return DECODE_ERROR;
+        }
+
+        for i in 0..n - 1 {
+            *dest = (group >> (16 - 8 * i)) as u8;
+            dest = dest.add(1);
+        }
+    }
+
+    r
+}
@@ -0,0 +1,451 @@
+//! Hash maps from blobs to blobs that can be saved to stable memory and loaded back without
+//! rehashing.
+//!
+//! A map is an array of three elements: the number of entries and the number of used slots
+//! (entries and tombstones) as scalars, and the slot array. The slot array has two elements per
+//! slot, the key and the value, and a power of two number of slots. Keys are placed with linear
+//! probing from `blob_hash(key)`, which only depends on the bytes of the key and is stable across
+//! upgrades. Unused slots hold scalars: `EMPTY` keys end probe sequences, `TOMBSTONE` keys (of
+//! removed entries) don't. The slot array is reallocated and the entries rehashed when more than
+//! 3/4 of the slots are used.
+//!
+//! `blob_map_save` writes the map to a contiguous range of the stable memory region, slot by slot,
+//! including the empty slots and tombstones. `blob_map_load` puts every entry back in the slot it
+//! was saved from, so loading a map does not hash the keys, and the layout of the map is the same
+//! after an upgrade. The stable layout is:
+//!
+//! ```text
+//! ┌───────┬─────────┬───────┬─────────┬──────┬────────┬─────┐
+//! │ magic │ version │ slots │ entries │ used │ slot 0 │ ... │
+//! └───────┴─────────┴───────┴─────────┴──────┴────────┴─────┘
+//! ```
+//!
+//! with 32-bit little-endian header fields. A slot is a 32-bit tag, `STABLE_EMPTY`,
+//! `STABLE_TOMBSTONE`, or the length of the key, followed by the bytes of the key, the 32-bit
+//! length of the value and the bytes of the value for entries.
+//!
+//! The map and the slot array may be in the old generation, or already marked by an incremental
+//! GC, so pointers are stored with the write barrier (see `gc::barrier`).
+
+use crate::gc::barrier::array_set_with_barrier;
+use crate::hash::blob_hash;
+use crate::memory::{alloc_array, alloc_blob, Memory};
+use crate::rts_trap_with;
+use crate::stable_region::{check_range, stable_region_size, STABLE_PAGE_SIZE};
+use crate::text::blob_eq;
+use crate::types::{Bytes, Value};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Number of slots of a new map
+pub const INITIAL_BLOB_MAP_SLOTS: u32 = 8;
+
+/// First word of a saved map ("BMAP")
+pub const BLOB_MAP_MAGIC: u32 = u32::from_le_bytes(*b"BMAP");
+
+/// Version of the stable layout of maps
+pub const BLOB_MAP_VERSION: u32 = 1;
+
+const COUNT_IDX: u32 = 0;
+const USED_IDX: u32 = 1;
+const SLOTS_IDX: u32 = 2;
+
+/// Key of a slot that was never used
+const EMPTY: u32 = 0;
+/// Key of a slot of a removed entry
+const TOMBSTONE: u32 = 1;
+
+/// Maximum number of slots, the counts are stored as scalars
+const MAX_SLOTS: u32 = 1 << 29;
+
+/// Stable tags of empty slots and tombstones, keys are shorter
+const STABLE_EMPTY: u32 = u32::MAX;
+const STABLE_TOMBSTONE: u32 = u32::MAX - 1;
+
+/// Size of the header of a saved map
+const STABLE_HEADER_SIZE: u64 = 5 * 4;
+
+/// Allocate an empty map
+#[ic_mem_fn]
+pub unsafe fn blob_map_new<M: Memory>(mem: &mut M) -> Value {
+    let slots = alloc_slots(mem, INITIAL_BLOB_MAP_SLOTS);
+    alloc_map(mem, slots, 0, 0)
+}
+
+/// Number of entries in the map
+#[no_mangle]
+pub unsafe extern "C" fn blob_map_len(map: Value) -> u32 {
+    map.as_array().get(COUNT_IDX).get_scalar()
+}
+
+/// Number of slots of the map
+pub unsafe fn blob_map_capacity(map: Value) -> u32 {
+    slots(map).as_array().len() / 2
+}
+
+/// Does the map have an entry for `key`?
+#[no_mangle]
+pub unsafe extern "C" fn blob_map_has(map: Value, key: Value) -> bool {
+    find(map, key).is_ok()
+}
+
+/// Value of `key`. Traps when the map has no entry for `key`.
+#[no_mangle]
+pub unsafe extern "C" fn blob_map_get(map: Value, key: Value) -> Value {
+    match find(map, key) {
+        Ok(slot) => slots(map).as_array().get(2 * slot + 1),
+        Err(_) => rts_trap_with("Blob map key not found"),
+    }
+}
+
+/// Set the value of `key`, replacing the current value if there is one
+#[ic_mem_fn]
+pub unsafe fn blob_map_put<M: Memory>(mem: &mut M, map: Value, key: Value, value: Value) {
+    if let Ok(slot) = find(map, key) {
+        array_set_with_barrier(mem, slots(map).as_array(), 2 * slot + 1, value);
+        return;
+    }
+
+    reserve(mem, map);
+
+    // `reserve` may have rehashed the entries, so the slot is found after it
+    let slot = match find(map, key) {
+        Ok(_) => unreachable!(),
+        Err(slot) => slot,
+    };
+    let slots = slots(map).as_array();
+    if slots.get(2 * slot) != Value::from_scalar(TOMBSTONE) {
+        set_field(map, USED_IDX, get_field(map, USED_IDX) + 1);
+    }
+    array_set_with_barrier(mem, slots, 2 * slot, key);
+    array_set_with_barrier(mem, slots, 2 * slot + 1, value);
+    set_field(map, COUNT_IDX, blob_map_len(map) + 1);
+}
+
+/// Remove the entry of `key`. Returns whether the map had an entry for `key`.
+#[ic_mem_fn]
+pub unsafe fn blob_map_remove<M: Memory>(mem: &mut M, map: Value, key: Value) -> bool {
+    match find(map, key) {
+        Ok(slot) => {
+            let slots = slots(map).as_array();
+            array_set_with_barrier(mem, slots, 2 * slot, Value::from_scalar(TOMBSTONE));
+            array_set_with_barrier(mem, slots, 2 * slot + 1, Value::from_scalar(0));
+            set_field(map, COUNT_IDX, blob_map_len(map) - 1);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Number of bytes `blob_map_save` writes for the map
+#[no_mangle]
+pub unsafe extern "C" fn blob_map_stable_size(map: Value) -> u64 {
+    let slots = slots(map).as_array();
+    let mut size = STABLE_HEADER_SIZE;
+    for slot in 0..slots.len() / 2 {
+        size += 4;
+        let key = slots.get(2 * slot);
+        if !key.is_scalar() {
+            size += 4
+                + key.as_blob().len().as_u32() as u64
+                + slots.get(2 * slot + 1).as_blob().len().as_u32() as u64;
+        }
+    }
+    size
+}
+
+/// Write the map to the stable memory region at `offset`. Returns the number of bytes written.
+/// Traps when the region is too small, the region should be grown before saving.
+#[no_mangle]
+pub unsafe extern "C" fn blob_map_save(map: Value, offset: u64) -> u64 {
+    let size = blob_map_stable_size(map);
+    check_range(offset, size);
+
+    let mut writer = StableWriter::new(offset);
+    let slots = slots(map).as_array();
+    writer.write_u32(BLOB_MAP_MAGIC);
+    writer.write_u32(BLOB_MAP_VERSION);
+    writer.write_u32(slots.len() / 2);
+    writer.write_u32(blob_map_len(map));
+    writer.write_u32(get_field(map, USED_IDX));
+    for slot in 0..slots.len() / 2 {
+        let key = slots.get(2 * slot);
+        if key == Value::from_scalar(EMPTY) {
+            writer.write_u32(STABLE_EMPTY);
+        } else if key == Value::from_scalar(TOMBSTONE) {
+            writer.write_u32(STABLE_TOMBSTONE);
+        } else {
+            writer.write_blob(key);
+            writer.write_blob(slots.get(2 * slot + 1));
+        }
+    }
+    writer.flush();
+
+    debug_assert_eq!(writer.offset, offset + size);
+    size
+}
+
+/// Load a map saved with `blob_map_save` at `offset` of the stable memory region. Traps when there
+/// is no saved map at `offset`.
+#[ic_mem_fn]
+pub unsafe fn blob_map_load<M: Memory>(mem: &mut M, offset: u64) -> Value {
+    let mut reader = StableReader::new(offset);
+    if reader.read_u32() != BLOB_MAP_MAGIC {
+        rts_trap_with("blob_map_load: no saved map at offset");
+    }
+    if reader.read_u32() != BLOB_MAP_VERSION {
+        rts_trap_with("blob_map_load: unsupported version");
+    }
+
+    let capacity = reader.read_u32();
+    let count = reader.read_u32();
+    let used = reader.read_u32();
+    if !capacity.is_power_of_two() || capacity > MAX_SLOTS || count > used || used > capacity {
+        rts_trap_with("blob_map_load: invalid header");
+    }
+
+    let slots = alloc_slots(mem, capacity);
+    let (mut n_entries, mut n_used) = (0, 0);
+    for slot in 0..capacity {
+        match reader.read_u32() {
+            STABLE_EMPTY => {}
+            STABLE_TOMBSTONE => {
+                slots
+                    .as_array()
+                    .set(2 * slot, Value::from_scalar(TOMBSTONE));
+                n_used += 1;
+            }
+            key_len => {
+                let key = reader.read_blob(mem, key_len);
+                let value_len = reader.read_u32();
+                let value = reader.read_blob(mem, value_len);
+                array_set_with_barrier(mem, slots.as_array(), 2 * slot, key);
+                array_set_with_barrier(mem, slots.as_array(), 2 * slot + 1, value);
+                n_entries += 1;
+                n_used += 1;
+            }
+        }
+    }
+    if n_entries != count || n_used != used {
+        rts_trap_with("blob_map_load: invalid header");
+    }
+
+    alloc_map(mem, slots, count, used)
+}
+
+unsafe fn alloc_map<M: Memory>(mem: &mut M, slots: Value, count: u32, used: u32) -> Value {
+    let map = alloc_array(mem, 3);
+    map.as_array().set(COUNT_IDX, Value::from_scalar(count));
+    map.as_array().set(USED_IDX, Value::from_scalar(used));
+    array_set_with_barrier(mem, map.as_array(), SLOTS_IDX, slots);
+    map
+}
+
+/// Allocate a slot array with `capacity` empty slots
+unsafe fn alloc_slots<M: Memory>(mem: &mut M, capacity: u32) -> Value {
+    let slots = alloc_array(mem, 2 * capacity);
+    for i in 0..2 * capacity {
+        slots.as_array().set(i, Value::from_scalar(EMPTY));
+    }
+    slots
+}
+
+unsafe fn slots(map: Value) -> Value {
+    map.as_array().get(SLOTS_IDX)
+}
+
+unsafe fn get_field(map: Value, idx: u32) -> u32 {
+    map.as_array().get(idx).get_scalar()
+}
+
+unsafe fn set_field(map: Value, idx: u32, n: u32) {
+    map.as_array().set(idx, Value::from_scalar(n));
+}
+
+/// Slot of the entry of `key`, or, when there is no entry, the slot to insert it into: the first
+/// tombstone or the empty slot that ends the probe sequence
+unsafe fn find(map: Value, key: Value) -> Result<u32, u32> {
+    let slots = slots(map).as_array();
+    let mask = slots.len() / 2 - 1;
+    let mut slot = blob_hash(key) & mask;
+    let mut free = None;
+    loop {
+        let slot_key = slots.get(2 * slot);
+        if slot_key == Value::from_scalar(EMPTY) {
+            return Err(free.unwrap_or(slot));
+        } else if slot_key == Value::from_scalar(TOMBSTONE) {
+            free.get_or_insert(slot);
+        } else if blob_eq(slot_key, key) {
+            return Ok(slot);
+        }
+        slot = (slot + 1) & mask;
+    }
+}
+
+/// Make sure a slot can be used for a new entry without using more than 3/4 of the slots. Rehashes
+/// the entries to a slot array with twice the slots when at least half of the slots have entries,
+/// or to a slot array of the same size to drop the tombstones otherwise.
+unsafe fn reserve<M: Memory>(mem: &mut M, map: Value) {
+    let capacity = blob_map_capacity(map);
+    let used = get_field(map, USED_IDX);
+    if (used + 1) * 4 <= capacity * 3 {
+        return;
+    }
+
+    let count = blob_map_len(map);
+    let new_capacity = if (count + 1) * 2 > capacity {
+        capacity * 2
+    } else {
+        capacity
+    };
+    if new_capacity > MAX_SLOTS {
+        rts_trap_with("Blob map too large");
+    }
+
+    let old_slots = slots(map).as_array();
+    let new_slots = alloc_slots(mem, new_capacity);
+    array_set_with_barrier(mem, map.as_array(), SLOTS_IDX, new_slots);
+    for slot in 0..capacity {
+        let key = old_slots.get(2 * slot);
+        if !key.is_scalar() {
+            let new_slot = match find(map, key) {
+                Ok(_) => unreachable!(),
+                Err(new_slot) => new_slot,
+            };
+            let value = old_slots.get(2 * slot + 1);
+            array_set_with_barrier(mem, new_slots.as_array(), 2 * new_slot, key);
+            array_set_with_barrier(mem, new_slots.as_array(), 2 * new_slot + 1, value);
+        }
+    }
+    set_field(map, USED_IDX, count);
+}
+
+extern "C" {
+    // generated by `moc`
+    fn stable64_read_moc(dst: u64, offset: u64, size: u64);
+    fn stable64_write_moc(offset: u64, src: u64, size: u64);
+}
+
+/// Size of the staging buffers of `StableWriter` and `StableReader`, so that small fields are not
+/// written and read one by one
+const STAGING_SIZE: usize = 1024;
+
+/// Sequential writes to stable memory, staged in a buffer
+struct StableWriter {
+    /// Offset of the first staged byte
+    offset: u64,
+    buf: [u8; STAGING_SIZE],
+    len: usize,
+}
+
+impl StableWriter {
+    fn new(offset: u64) -> StableWriter {
+        StableWriter {
+            offset,
+            buf: [0; STAGING_SIZE],
+            len: 0,
+        }
+    }
+
+    unsafe fn write_u32(&mut self, n: u32) {
+        self.write_bytes(&n.to_le_bytes());
+    }
+
+    /// Write the length and the bytes of the blob
+    unsafe fn write_blob(&mut self, blob: Value) {
+        let blob = blob.as_blob();
+        let len = blob.len().as_usize();
+        self.write_u32(len as u32);
+        self.write_bytes(core::slice::from_raw_parts(blob.payload_const(), len));
+    }
+
+    unsafe fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.len + bytes.len() > STAGING_SIZE {
+            self.flush();
+        }
+        if bytes.len() > STAGING_SIZE {
+            // Large blobs are written directly
+            stable64_write_moc(self.offset, bytes.as_ptr() as u64, bytes.len() as u64);
+            self.offset += bytes.len() as u64;
+        } else {
+            self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+            self.len += bytes.len();
+        }
+    }
+
+    unsafe fn flush(&mut self) {
+        if self.len != 0 {
+            stable64_write_moc(self.offset, self.buf.as_ptr() as u64, self.len as u64);
+            self.offset += self.len as u64;
+            self.len = 0;
+        }
+    }
+}
+
+/// Sequential reads from stable memory, staged in a buffer. Reads are checked against the region.
+struct StableReader {
+    /// Offset of the byte after the staged bytes
+    offset: u64,
+    buf: [u8; STAGING_SIZE],
+    /// Staged bytes are `buf[pos..len]`
+    pos: usize,
+    len: usize,
+}
+
+impl StableReader {
+    fn new(offset: u64) -> StableReader {
+        StableReader {
+            offset,
+            buf: [0; STAGING_SIZE],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    unsafe fn read_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.read_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    unsafe fn read_blob<M: Memory>(&mut self, mem: &mut M, len: u32) -> Value {
+        let blob = alloc_blob(mem, Bytes(len));
+        let payload = blob.as_blob_mut().payload_addr();
+        self.read_bytes(core::slice::from_raw_parts_mut(payload, len as usize));
+        blob
+    }
+
+    unsafe fn read_bytes(&mut self, bytes: &mut [u8]) {
+        let mut done = 0;
+        while done < bytes.len() {
+            if self.pos == self.len {
+                // Read directly when the rest does not fit in the buffer
+                let rest = bytes.len() - done;
+                if rest > STAGING_SIZE {
+                    check_range(self.offset, rest as u64);
+                    stable64_read_moc(bytes[done..].as_mut_ptr() as u64, self.offset, rest as u64);
+                    self.offset += rest as u64;
+                    return;
+                }
+                self.fill();
+            }
+            let n = core::cmp::min(self.len - self.pos, bytes.len() - done);
+            bytes[done..done + n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            done += n;
+        }
+    }
+
+    /// Stage the next bytes, up to the end of the region
+    unsafe fn fill(&mut self) {
+        let region_end = stable_region_size().saturating_mul(STABLE_PAGE_SIZE);
+        let n = core::cmp::min(STAGING_SIZE as u64, region_end.saturating_sub(self.offset));
+        if n == 0 {
+            rts_trap_with("StableMemory range out of bounds");
+        }
+        stable64_read_moc(self.buf.as_mut_ptr() as u64, self.offset, n);
+        self.offset += n;
+        self.pos = 0;
+        self.len = n as usize;
+    }
+}
@@ -1,6 +1,7 @@
 use crate::types::{Bytes, Words};
 
-/// Wasm word size. RTS only works correctly on platforms with this word size.
+/// Wasm word size. RTS only works correctly on platforms with this word size. Word size of
+/// `pointer_width::Width`.
 pub const WORD_SIZE: u32 = 4;
 
 /// Wasm page size (64 KiB) in bytes
@@ -22,6 +22,7 @@
 //! the free list. Since all indices are relative to the payload begin, they stay valid. We never
 //! shrink the table.
 
+use crate::gc::mark_compact::incremental::incremental_gc_write_barrier;
 use crate::memory::{alloc_array, Memory};
 use crate::rts_trap_with;
 use crate::types::Value;
@@ -99,9 +100,10 @@ pub unsafe fn remember_continuation<M: Memory>(mem: &mut M, ptr: Value) -> u32 {
     idx
 }
 
-// Position of the future in explicit self-send ContinuationTable entries
+// Position of the future in explicit self-send ContinuationTable entries, after the reply and
+// reject closures and the `on_trap` handlers
 // Invariant: keep this synchronised with compiler.ml (see future_array_index)
-const FUTURE_ARRAY_INDEX: u32 = 2;
+const FUTURE_ARRAY_INDEX: u32 = 3;
 
 #[no_mangle]
 pub unsafe extern "C" fn peek_future_continuation(idx: u32) -> Value {
@@ -134,6 +136,8 @@ pub unsafe extern "C" fn recall_continuation(idx: u32) -> Value {
 
     let ptr = TABLE.as_array().get(idx);
 
+    // The slot is overwritten with a scalar, the continuation may need to be marked
+    incremental_gc_write_barrier(TABLE.as_array().payload_addr().add(idx as usize));
     TABLE.as_array().set(idx, Value::from_scalar(FREE_SLOT));
     FREE_SLOT = idx;
 
@@ -0,0 +1,58 @@
+//! Checking whether a principal is a controller of the canister, for authorization checks.
+//!
+//! When the replica supports it (the `--is-controller-api` flag of `moc`), the check is
+//! `ic0.is_controller`, which knows the current controllers. Otherwise the principal is compared
+//! against the controller list registered with `set_controllers`, which the canister has to keep
+//! up to date. The list is not preserved across upgrades: canisters relying on it register it
+//! again in `postupgrade`.
+
+use crate::gc::mark_compact::incremental::incremental_gc_write_barrier;
+use crate::roots::register_root;
+use crate::types::Value;
+
+use core::ptr::addr_of_mut;
+
+/// Registered controllers: an array of principal blobs, or a scalar when no list is registered
+static mut CONTROLLERS: Value = Value::from_scalar(0);
+
+extern "C" {
+    // generated by `moc`, forwarding to `ic0.is_controller`. Returns 1 for controllers, 0 for
+    // other principals, and -1 when `ic0.is_controller` is not available.
+    fn is_controller_moc(src: *const u8, size: u32) -> i32;
+}
+
+/// Register the array of controller principals used when `ic0.is_controller` is not available.
+/// Replaces the list registered before. A scalar removes the list.
+#[no_mangle]
+pub unsafe extern "C" fn set_controllers(controllers: Value) {
+    let location = addr_of_mut!(CONTROLLERS);
+    register_root(location);
+    incremental_gc_write_barrier(location);
+    *location = controllers;
+}
+
+/// Whether `principal` (a principal blob) is a controller of the canister
+#[no_mangle]
+pub unsafe extern "C" fn principal_is_controller(principal: Value) -> bool {
+    let principal = principal_bytes(principal);
+    match is_controller_moc(principal.as_ptr(), principal.len() as u32) {
+        -1 => registered_controller(principal),
+        result => result == 1,
+    }
+}
+
+/// Whether `principal` is in the controller list registered with `set_controllers`
+unsafe fn registered_controller(principal: &[u8]) -> bool {
+    let controllers = CONTROLLERS;
+    if !controllers.is_ptr() {
+        return false;
+    }
+
+    let controllers = controllers.as_array();
+    (0..controllers.len()).any(|i| principal_bytes(controllers.get(i)) == principal)
+}
+
+unsafe fn principal_bytes<'a>(principal: Value) -> &'a [u8] {
+    let blob = principal.as_blob();
+    core::slice::from_raw_parts(blob.payload_const(), blob.len().as_usize())
+}
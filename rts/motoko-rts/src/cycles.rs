@@ -0,0 +1,150 @@
+//! 128-bit cycle amounts.
+//!
+//! The IC passes cycle amounts as 128-bit numbers, which do not fit the `Nat64` arithmetic of the
+//! generated code. `Cycles128` implements the arithmetic needed on cycle amounts, and conversions
+//! to and from `Nat`. The `ic0` cycle functions are wrapped to take and return `Cycles128`.
+
+use crate::bigint::{check, nat_to_u128_checked, persist_bigint, tmp_bigint};
+use crate::rts_trap_with;
+use crate::tommath_bindings::{mp_add, mp_int, mp_mul_2d, mp_set_u64};
+use crate::types::Value;
+
+use core::cmp::Ordering;
+use core::ops::{Add, Sub};
+
+/// A 128-bit cycle amount. The layout is the little-endian 128-bit number written by the
+/// `ic0.msg_cycles_*128` functions.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cycles128 {
+    pub lo: u64,
+    pub hi: u64,
+}
+
+impl Cycles128 {
+    pub const ZERO: Cycles128 = Cycles128 { lo: 0, hi: 0 };
+
+    pub const fn new(hi: u64, lo: u64) -> Self {
+        Cycles128 { lo, hi }
+    }
+
+    pub const fn from_u64(n: u64) -> Self {
+        Cycles128 { lo: n, hi: 0 }
+    }
+
+    /// Convert to `Nat`
+    pub unsafe fn to_nat(self) -> Value {
+        // Compact representation, as in `bigint_leb128_decode_word64`
+        if self.hi == 0 && self.lo < 1 << 30 {
+            return Value::from_signed_scalar(self.lo as i32);
+        }
+
+        let mut i = tmp_bigint();
+        let i_ptr: *mut mp_int = &mut i;
+        mp_set_u64(i_ptr, self.hi);
+        check(mp_mul_2d(i_ptr, 64, i_ptr));
+        let mut lo = tmp_bigint();
+        mp_set_u64(&mut lo, self.lo);
+        check(mp_add(i_ptr, &lo, i_ptr));
+        persist_bigint(i)
+    }
+
+    /// Convert from `Nat`, trapping if the number does not fit in 128 bits
+    pub unsafe fn of_nat(n: Value) -> Cycles128 {
+        match nat_to_u128_checked(n) {
+            Some(n) => Cycles128::new((n >> 64) as u64, n as u64),
+            None => rts_trap_with("cycles out of bounds"),
+        }
+    }
+}
+
+impl Add for Cycles128 {
+    type Output = Self;
+
+    /// Add, trapping on overflow
+    fn add(self, rhs: Self) -> Self::Output {
+        let (lo, carry) = self.lo.overflowing_add(rhs.lo);
+        let hi = self
+            .hi
+            .checked_add(rhs.hi)
+            .and_then(|hi| hi.checked_add(carry as u64));
+        match hi {
+            Some(hi) => Cycles128 { lo, hi },
+            None => unsafe { rts_trap_with("cycles overflow") },
+        }
+    }
+}
+
+impl Sub for Cycles128 {
+    type Output = Self;
+
+    /// Subtract, trapping on underflow
+    fn sub(self, rhs: Self) -> Self::Output {
+        if self < rhs {
+            unsafe { rts_trap_with("cycles underflow") }
+        }
+        let (lo, borrow) = self.lo.overflowing_sub(rhs.lo);
+        Cycles128 {
+            lo,
+            hi: self.hi - rhs.hi - borrow as u64,
+        }
+    }
+}
+
+impl PartialOrd for Cycles128 {
+    fn partial_cmp(&self, other: &Cycles128) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Cycles128 {
+    fn cmp(&self, other: &Cycles128) -> Ordering {
+        (self.hi, self.lo).cmp(&(other.hi, other.lo))
+    }
+}
+
+extern "C" {
+    // generated by `moc`, forwarding to `ic0`
+    fn msg_cycles_available128_moc(dst: *mut Cycles128);
+    fn msg_cycles_accept128_moc(max_amount_high: u64, max_amount_low: u64, dst: *mut Cycles128);
+    fn call_cycles_add128_moc(amount_high: u64, amount_low: u64);
+}
+
+/// Cycles transferred with the current message and not yet accepted
+pub unsafe fn msg_cycles_available128() -> Cycles128 {
+    let mut available = Cycles128::ZERO;
+    msg_cycles_available128_moc(&mut available);
+    available
+}
+
+/// Accept up to `max` of the available cycles, returns the accepted amount. Never asks for more
+/// than is available, so the result is exactly the minimum of `max` and the available amount.
+pub unsafe fn msg_cycles_accept128(max: Cycles128) -> Cycles128 {
+    let amount = core::cmp::min(max, msg_cycles_available128());
+    let mut accepted = Cycles128::ZERO;
+    msg_cycles_accept128_moc(amount.hi, amount.lo, &mut accepted);
+    accepted
+}
+
+/// Add cycles to the call being constructed
+pub unsafe fn call_cycles_add128(amount: Cycles128) {
+    call_cycles_add128_moc(amount.hi, amount.lo);
+}
+
+/// `Nat` version of `msg_cycles_available128`
+#[no_mangle]
+pub unsafe extern "C" fn cycles_available() -> Value {
+    msg_cycles_available128().to_nat()
+}
+
+/// `Nat` version of `msg_cycles_accept128`
+#[no_mangle]
+pub unsafe extern "C" fn cycles_accept(max: Value) -> Value {
+    msg_cycles_accept128(Cycles128::of_nat(max)).to_nat()
+}
+
+/// `Nat` version of `call_cycles_add128`
+#[no_mangle]
+pub unsafe extern "C" fn cycles_add(amount: Value) {
+    call_cycles_add128(Cycles128::of_nat(amount))
+}
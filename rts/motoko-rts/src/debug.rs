@@ -159,9 +159,14 @@ pub(crate) unsafe fn print_boxed_object(buf: &mut WriteBuf, p: usize) {
             let obj_ind = obj as *const ObjInd;
             let _ = write!(buf, "<ObjInd field={:#x}>", (*obj_ind).field.get_raw());
         }
-        TAG_ARRAY => {
+        TAG_ARRAY | TAG_FROZEN_ARRAY => {
             let array = obj as *mut Array;
-            let _ = write!(buf, "<Array len={:#x}", (*array).len);
+            let name = if tag == TAG_FROZEN_ARRAY {
+                "FrozenArray"
+            } else {
+                "Array"
+            };
+            let _ = write!(buf, "<{} len={:#x}", name, (*array).len);
 
             for i in 0..::core::cmp::min(10, (*array).len) {
                 let _ = write!(buf, " {:#x}", array.get(i).get_raw());
@@ -218,10 +223,12 @@ pub(crate) unsafe fn print_boxed_object(buf: &mut WriteBuf, p: usize) {
             let concat = obj.as_concat();
             let _ = write!(
                 buf,
-                "<Concat n_bytes={:#x} obj1={:#x} obj2={:#x}>",
+                "<Concat n_bytes={:#x} obj1={:#x} obj2={:#x} depth={} n_chars={}>",
                 (*concat).n_bytes.as_u32(),
                 (*concat).text1.get_raw(),
-                (*concat).text2.get_raw()
+                (*concat).text2.get_raw(),
+                (*concat).depth,
+                (*concat).n_chars
             );
         }
         TAG_ONE_WORD_FILLER => {
@@ -231,6 +238,19 @@ pub(crate) unsafe fn print_boxed_object(buf: &mut WriteBuf, p: usize) {
             let free_space = obj as *const FreeSpace;
             let _ = write!(buf, "<Free space {} words>", (*free_space).words.as_u32());
         }
+        TAG_WEAK_REF => {
+            let weak_ref = obj as *const WeakRef;
+            let _ = write!(buf, "<WeakRef target={:#x}>", (*weak_ref).target.get_raw());
+        }
+        TAG_FINALIZABLE => {
+            let finalizable = obj as *const Finalizable;
+            let _ = write!(
+                buf,
+                "<Finalizable index={} next={:#x}>",
+                (*finalizable).index,
+                (*finalizable).next.get_raw()
+            );
+        }
         other => {
             let _ = write!(buf, "<??? {} ???>", other);
         }
@@ -0,0 +1,98 @@
+//! Finalizers. `register_finalizer` allocates a finalizable object (`Finalizable`) with a
+//! finalizer index. The generated code stores the finalizable object in the object that needs
+//! cleanup. When the finalizable object becomes unreachable, the GC appends its index to the
+//! finalizer queue, and the generated code runs the finalizers with the indices returned by
+//! `take_finalizer_queue`, after GC.
+//!
+//! Finalizers are identified by indices, they never get a pointer to the dead object, so running a
+//! finalizer cannot resurrect it.
+//!
+//! Finalizable objects are linked in a registry (`FINALIZABLES`). After marking, the GC moves dead
+//! objects in the registry to the queue (`FINALIZER_QUEUE`), and keeps the objects in the queue
+//! alive until the queue is taken. The links (`Finalizable::next`) don't keep objects alive, the
+//! GCs update them after moving objects, like weak references (see `visit_weak_fields`).
+
+use crate::memory::{alloc_array, Memory};
+use crate::types::{size_of, Finalizable, Value, TAG_FINALIZABLE};
+
+use core::ptr::addr_of_mut;
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// End of the registry and queue lists
+pub const FINALIZER_LIST_END: Value = Value::from_scalar(0);
+
+/// Finalizable objects, linked by their `next` fields
+pub static mut FINALIZABLES: Value = FINALIZER_LIST_END;
+
+/// Dead finalizable objects, linked by their `next` fields
+pub static mut FINALIZER_QUEUE: Value = FINALIZER_LIST_END;
+
+/// Allocate a finalizable object with the given finalizer index, and add it to the registry
+#[ic_mem_fn]
+pub unsafe fn register_finalizer<M: Memory>(mem: &mut M, index: u32) -> Value {
+    let ptr = mem.alloc_words(size_of::<Finalizable>());
+    let finalizable = ptr.get_ptr() as *mut Finalizable;
+    (*finalizable).header.tag = TAG_FINALIZABLE;
+    (*finalizable).next = FINALIZABLES;
+    (*finalizable).index = index;
+    FINALIZABLES = ptr;
+    ptr
+}
+
+/// Return indices of the finalizers to run, as an array of scalars, and empty the queue
+#[ic_mem_fn]
+pub unsafe fn take_finalizer_queue<M: Memory>(mem: &mut M) -> Value {
+    let mut len = 0;
+    let mut finalizable = FINALIZER_QUEUE;
+    while finalizable != FINALIZER_LIST_END {
+        len += 1;
+        finalizable = (*(finalizable.get_ptr() as *mut Finalizable)).next;
+    }
+
+    let indices = alloc_array(mem, len);
+    let indices_array = indices.as_array();
+
+    let mut finalizable = FINALIZER_QUEUE;
+    for i in 0..len {
+        let finalizable_ptr = finalizable.get_ptr() as *mut Finalizable;
+        indices_array.set(i, Value::from_scalar((*finalizable_ptr).index));
+        finalizable = (*finalizable_ptr).next;
+    }
+
+    FINALIZER_QUEUE = FINALIZER_LIST_END;
+
+    indices
+}
+
+/// Move dead objects in the registry to the queue. `is_dead` is called on objects in the registry.
+pub(crate) unsafe fn queue_dead_finalizables<IsDead: Fn(*mut Finalizable) -> bool>(
+    is_dead: IsDead,
+) {
+    let mut link = addr_of_mut!(FINALIZABLES);
+
+    while *link != FINALIZER_LIST_END {
+        let finalizable = (*link).get_ptr() as *mut Finalizable;
+        if is_dead(finalizable) {
+            *link = (*finalizable).next;
+            (*finalizable).next = FINALIZER_QUEUE;
+            FINALIZER_QUEUE = Value::from_ptr(finalizable as usize);
+        } else {
+            link = &mut (*finalizable).next;
+        }
+    }
+}
+
+/// Call the callback on the locations of the links in the registry and the queue, with whether
+/// the location is a list head (a static variable, so not in the heap). The callback can update
+/// the link, the `next` field of the linked object is read after the callback.
+pub(crate) unsafe fn visit_finalizer_links<F: FnMut(*mut Value, bool)>(mut f: F) {
+    for head in [addr_of_mut!(FINALIZABLES), addr_of_mut!(FINALIZER_QUEUE)] {
+        let mut link = head;
+        while *link != FINALIZER_LIST_END {
+            let finalizable = (*link).get_ptr() as *mut Finalizable;
+            f(link, link == head);
+            link = &mut (*finalizable).next;
+        }
+    }
+}
@@ -1,10 +1,17 @@
 use crate::memory::Memory;
-use crate::text::text_of_ptr_size;
+use crate::print::WriteBuf;
+use crate::rts_trap_with;
+use crate::text::{blob_of_text, text_of_ptr_size, text_of_str};
 use crate::types::{Bytes, Value};
 
+use core::fmt::Write;
+use core::intrinsics::sqrtf64;
+use core::{slice, str};
+
 use motoko_rts_macros::ic_mem_fn;
 
 // The meaning of the `mode` parameter is documented in motoko-base, function Float.format()
+#[cfg(feature = "ic")]
 #[ic_mem_fn]
 unsafe fn float_fmt<M: Memory>(mem: &mut M, a: f64, prec: u32, mode: u32) -> Value {
     // prec and mode are tagged small words (`Nat8`s), so we shift 24 bits. See
@@ -36,3 +43,117 @@ unsafe fn float_fmt<M: Memory>(mem: &mut M, a: f64, prec: u32, mode: u32) -> Val
 
     text_of_ptr_size(mem, buf.as_ptr(), Bytes(n_written as u32))
 }
+
+/// Shortest decimal representation of a float that parses back to the same float, e.g. `0.1`,
+/// `1.0`, `1e100`, `-0.0`, `5e-324`. NaN is `nan`, infinities are `inf` and `-inf`.
+#[ic_mem_fn]
+pub unsafe fn float_to_text<M: Memory>(mem: &mut M, a: f64) -> Value {
+    if a.is_nan() {
+        return text_of_str(mem, "nan");
+    }
+
+    if a.is_infinite() {
+        return text_of_str(mem, if a > 0.0 { "inf" } else { "-inf" });
+    }
+
+    // `Debug` of `f64` prints the shortest round-trip digits, using an exponent for very large and
+    // very small numbers (including subnormals), and keeps the sign of negative zero. The longest
+    // output is 24 bytes (e.g. `-2.2250738585072014e-308`).
+    let mut buf = [0u8; 32];
+    let mut fmt = WriteBuf::new(&mut buf);
+    let _ = write!(fmt, "{:?}", a);
+    let len = fmt.len();
+
+    text_of_ptr_size(mem, buf.as_ptr(), Bytes(len as u32))
+}
+
+/// Parse a float, e.g. the output of `float_to_text`. The result is the float closest to the
+/// decimal number. Traps when the text is not a float.
+#[ic_mem_fn]
+pub unsafe fn text_to_float<M: Memory>(mem: &mut M, text: Value) -> f64 {
+    let blob = blob_of_text(mem, text).as_blob();
+    let str = str::from_utf8_unchecked(slice::from_raw_parts(
+        blob.payload_const(),
+        blob.len().as_usize(),
+    ));
+
+    match str.parse::<f64>() {
+        Ok(a) => a,
+        Err(_) => rts_trap_with("text_to_float: invalid float"),
+    }
+}
+
+// Elementary functions
+//
+// The transcendental functions are the ones of musl (`rts/Makefile`, `MUSLFILES`), which is
+// compiled to Wasm and linked into the RTS. Wasm floating point arithmetic is IEEE 754 with
+// round-to-nearest and no extended precision, and musl does not depend on the host, so the results
+// are the same on all replicas (only the bits of NaN results are not specified by Wasm). The host
+// libm is never called. The RTS tests, which run natively, use the host libm instead.
+//
+// Precision, in units in the last place (ulp) of the exact result:
+//
+// - `float_sqrt`: correctly rounded (Wasm `f64.sqrt`)
+// - `float_exp`, `float_log`, `float_pow`: less than 0.52 ulp
+// - `float_sin`, `float_cos`: less than 1 ulp, with exact argument reduction for large arguments
+//
+// Reference vectors, shortest round-trip decimal and bits (see also `test/run/float-math.mo`,
+// which checks them in the interpreter and in the compiled code):
+//
+//   float_sqrt(2.0)       1.4142135623730951    0x3ff6a09e667f3bcd
+//   float_sin(1.0)        0.8414709848078965    0x3feaed548f090cee
+//   float_cos(1.0)        0.5403023058681398    0x3fe14a280fb5068c
+//   float_sin(100.0)     -0.5063656411097588    0xbfe03425b78c4db8
+//   float_cos(100.0)      0.8623188722876839    0x3feb981dbf665fdf
+//   float_exp(1.0)        2.718281828459045     0x4005bf0a8b145769
+//   float_exp(-1.0)       0.36787944117144233   0x3fd78b56362cef38
+//   float_log(2.0)        0.6931471805599453    0x3fe62e42fefa39ef
+//   float_log(10.0)       2.302585092994046     0x40026bb1bbb55516
+//   float_pow(2.0, 0.5)   1.4142135623730951    0x3ff6a09e667f3bcd
+//   float_pow(10.0, -2.0) 0.01                  0x3f847ae147ae147b
+//   float_pow(1.5, 2.5)   2.7556759606310752    0x40060b9fd68a4554
+
+extern "C" {
+    fn sin(a: f64) -> f64;
+    fn cos(a: f64) -> f64;
+    fn exp(a: f64) -> f64;
+    fn log(a: f64) -> f64;
+    fn pow(a: f64, b: f64) -> f64;
+}
+
+/// Square root, NaN for negative numbers
+#[no_mangle]
+pub unsafe extern "C" fn float_sqrt(a: f64) -> f64 {
+    sqrtf64(a)
+}
+
+/// Sine of `a` radians, NaN for infinities
+#[no_mangle]
+pub unsafe extern "C" fn float_sin(a: f64) -> f64 {
+    sin(a)
+}
+
+/// Cosine of `a` radians, NaN for infinities
+#[no_mangle]
+pub unsafe extern "C" fn float_cos(a: f64) -> f64 {
+    cos(a)
+}
+
+/// `e` to the power of `a`, infinity on overflow
+#[no_mangle]
+pub unsafe extern "C" fn float_exp(a: f64) -> f64 {
+    exp(a)
+}
+
+/// Natural logarithm, negative infinity for zero and NaN for negative numbers
+#[no_mangle]
+pub unsafe extern "C" fn float_log(a: f64) -> f64 {
+    log(a)
+}
+
+/// `a` to the power of `b`, with the special cases of C99 (e.g. `pow(a, 0.0) == 1.0` for all `a`,
+/// NaN for negative `a` and non-integer `b`)
+#[no_mangle]
+pub unsafe extern "C" fn float_pow(a: f64, b: f64) -> f64 {
+    pow(a, b)
+}
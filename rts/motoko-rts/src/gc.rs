@@ -1,20 +1,213 @@
+pub mod barrier;
 pub mod copying;
+pub mod heap_dump;
+pub mod high_water_mark;
+pub mod live_history;
 pub mod mark_compact;
+pub mod pauses;
+pub mod scheduler;
+pub mod size_histogram;
+pub mod stats;
+pub mod verify;
 
+use crate::rts_trap_with;
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// GC algorithms that `schedule_compacting_gc` can use
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Strategy {
+    MarkCompact = 0,
+    Copying = 1,
+}
+
+/// GC algorithm used by `schedule_compacting_gc`. Mark-compact by default, or copying when
+/// compiled with the `copying-gc` feature.
+pub static mut GC_STRATEGY: Strategy = if cfg!(feature = "copying-gc") {
+    Strategy::Copying
+} else {
+    Strategy::MarkCompact
+};
+
+/// Strategy set with `set_gc_strategy` while an incremental GC was in progress. Applied when the
+/// incremental GC is done.
+static mut PENDING_GC_STRATEGY: Option<Strategy> = None;
+
+/// Select the GC algorithm. Argument is a `Strategy` value.
+///
+/// When an incremental GC is in progress the change takes effect after the incremental GC is
+/// done, as the heap cannot be collected by another GC in the middle of incremental marking.
+#[no_mangle]
+pub unsafe extern "C" fn set_gc_strategy(strategy: u32) {
+    let strategy = match strategy {
+        0 => Strategy::MarkCompact,
+        1 => Strategy::Copying,
+        _ => rts_trap_with("set_gc_strategy: unknown GC strategy"),
+    };
+
+    if strategy == Strategy::Copying {
+        // Copying GC moves all objects, and does not know about the remembered set
+        if barrier::BARRIER_MODE == barrier::BarrierMode::RememberedSet {
+            rts_trap_with("set_gc_strategy: copying GC does not support generational GC");
+        }
+
+        // Copying GC moves all objects, and does not know about the pin table
+        if crate::pin::has_pinned_objects() {
+            rts_trap_with("set_gc_strategy: copying GC does not support pinned objects");
+        }
+        crate::pin::free_pin_table();
+    }
+
+    if mark_compact::incremental::PHASE == mark_compact::incremental::Phase::Idle {
+        GC_STRATEGY = strategy;
+    } else {
+        PENDING_GC_STRATEGY = Some(strategy);
+    }
+}
+
+/// Returns whether the copying GC is used, or will be used after the incremental GC in progress
+pub(crate) unsafe fn copying_gc_selected() -> bool {
+    GC_STRATEGY == Strategy::Copying || PENDING_GC_STRATEGY == Some(Strategy::Copying)
+}
+
+/// Apply the strategy change deferred by `set_gc_strategy`. Called when an incremental GC is done.
+pub(crate) unsafe fn apply_pending_gc_strategy() {
+    if let Some(strategy) = PENDING_GC_STRATEGY.take() {
+        GC_STRATEGY = strategy;
+    }
+}
+
+/// Number of instructions executed in the current message, to measure GC phases
 #[cfg(feature = "ic")]
-use crate::types::Bytes;
+pub(crate) unsafe fn instruction_counter() -> u64 {
+    extern "C" {
+        // generated by `moc`
+        fn performance_counter_moc(counter_type: u32) -> u64;
+    }
+
+    performance_counter_moc(0)
+}
+
+/// Instruction counter when not running on the IC. Does not count by default, tests can replace
+/// it with a mock.
+#[cfg(not(feature = "ic"))]
+pub static mut INSTRUCTION_COUNTER: fn() -> u64 = no_instruction_counter;
+
+#[cfg(not(feature = "ic"))]
+fn no_instruction_counter() -> u64 {
+    0
+}
+
+#[cfg(not(feature = "ic"))]
+pub(crate) unsafe fn instruction_counter() -> u64 {
+    INSTRUCTION_COUNTER()
+}
+
+#[cfg(feature = "ic")]
+use crate::{memory::Memory, types::Bytes};
+
+/// Whether `schedule_compacting_gc` and `schedule_copying_gc` collect on every call, enabled with
+/// the `force-gc` feature. Makes GC timing deterministic, to reproduce bugs that only show up when
+/// objects are moved at specific points.
+pub const FORCE_GC: bool = cfg!(feature = "force-gc");
 
 #[cfg(feature = "ic")]
 unsafe fn should_do_gc(max_live: Bytes<u64>) -> bool {
-    use crate::memory::ic::{HP, LAST_HP};
+    use crate::memory::ic::{get_heap_base, HP, LAST_HP};
+
+    FORCE_GC
+        || scheduler::SCHEDULER.should_do_gc(
+            Bytes(HP - get_heap_base()),
+            Bytes(HP - LAST_HP),
+            max_live,
+        )
+}
+
+/// Call the memory pressure handler when the heap has crossed the high-water mark, see
+/// `memory_pressure`. Returns whether the handler was called, the whole heap should then be
+/// collected.
+#[cfg(feature = "ic")]
+unsafe fn relieve_memory_pressure() -> bool {
+    use crate::memory::ic::{get_heap_base, HP};
+    use crate::types::Value;
+
+    extern "C" {
+        // generated by `moc`
+        fn call_moc(f: Value);
+    }
+
+    crate::memory_pressure::relieve_memory_pressure(Bytes(HP - get_heap_base()), |handler| {
+        call_moc(handler)
+    })
+}
+
+/// Set by `gc_request_collection`, cleared by `take_collection_request`
+static mut COLLECTION_REQUESTED: bool = false;
+
+/// Make the next `schedule_compacting_gc` or `schedule_copying_gc` call collect the whole heap
+/// with `gc_collect_now`, regardless of the scheduler.
+///
+/// The generated code keeps pointers to heap objects in Wasm locals, which are not GC roots, so
+/// Motoko code cannot collect in the middle of a message. It requests a collection instead, which
+/// is done at the end of the message.
+#[no_mangle]
+pub unsafe extern "C" fn gc_request_collection() {
+    COLLECTION_REQUESTED = true;
+}
+
+/// Returns whether a collection was requested with `gc_request_collection`, and clears the request
+#[cfg(feature = "ic")]
+pub(crate) unsafe fn take_collection_request() -> bool {
+    core::mem::replace(&mut *core::ptr::addr_of_mut!(COLLECTION_REQUESTED), false)
+}
+
+/// Collect the whole heap with the selected GC strategy, regardless of the scheduler. Returns the
+/// number of bytes reclaimed.
+///
+/// An incremental GC in progress is finished first, and then the whole heap is collected again, as
+/// the incremental GC does not reclaim objects that became garbage after its marking started.
+///
+/// Must only be called when no pointers to heap objects are held outside of the GC roots, i.e. not
+/// in the middle of a message, see `gc_request_collection`.
+#[ic_mem_fn(ic_only)]
+pub unsafe fn gc_collect_now<M: Memory>(mem: &mut M) -> Bytes<u64> {
+    use crate::memory::ic::RECLAIMED;
+
+    let reclaimed_before = RECLAIMED;
 
-    // A factor of last heap size. We allow at most this much allocation before doing GC.
-    const HEAP_GROWTH_FACTOR: f64 = 1.5;
+    if mark_compact::incremental::PHASE != mark_compact::incremental::Phase::Idle {
+        // Also applies a pending strategy change
+        mark_compact::compacting_gc(mem);
+    }
+
+    match GC_STRATEGY {
+        Strategy::MarkCompact => mark_compact::compacting_gc(mem),
+        Strategy::Copying => copying::copying_gc(mem),
+    }
+
+    RECLAIMED - reclaimed_before
+}
+
+/// Called after the scheduled GC at the end of a message. When the heap is beyond the limit set
+/// with `set_heap_limit`, collect the whole heap with `full_gc`, and trap if the heap is still
+/// beyond the limit. Trapping rolls back the message, including its allocations.
+#[cfg(feature = "ic")]
+unsafe fn enforce_heap_limit<FullGc: FnOnce()>(full_gc: FullGc) {
+    let limit = match scheduler::SCHEDULER.heap_limit {
+        Some(limit) => limit,
+        None => return,
+    };
 
-    let heap_limit = core::cmp::min(
-        (f64::from(LAST_HP) * HEAP_GROWTH_FACTOR) as u64,
-        (u64::from(LAST_HP) + max_live.0) / 2,
-    );
+    let heap_size = || {
+        use crate::memory::ic::{get_heap_base, HP};
+        Bytes(HP - get_heap_base())
+    };
 
-    u64::from(HP) >= heap_limit
+    if heap_size() > limit {
+        full_gc();
+        if heap_size() > limit {
+            rts_trap_with("heap limit exceeded");
+        }
+    }
 }
@@ -0,0 +1,133 @@
+//! Write barrier for pointer stores in the generated code, shared by the incremental and
+//! generational GCs.
+//!
+//! `write_barrier` does the store, and what else needs to be done depends on `BARRIER_MODE`:
+//!
+//! - `Disabled`: only the store. This is the default, the stop-the-world GCs don't need a barrier.
+//!
+//! - `SnapshotAtTheBeginning`: before the store, the object pointed by the old value is marked
+//!   when incremental marking is in progress (see `incremental_gc_write_barrier`).
+//!
+//! - `RememberedSet`: after the store, the field is added to the remembered set when it's in the
+//!   old generation and points to the young generation (see `generational::remember_field`).
+//!
+//! The generated code selects the mode with `set_write_barrier_mode` on initialization, before the
+//! first store, according to the GC mode (`moc --incremental-gc` or `--generational-gc`). In the
+//! other modes it calls `write_barrier` for pointer stores to MutBox fields, array elements and
+//! mutable object fields. Stores to newly allocated objects don't need the barrier.
+//!
+//! The RTS stores to existing objects with `array_set_with_barrier`, e.g. in `blob_map` and
+//! `array_buffer`, and calls `permuted_array_barrier` after permuting an array in place.
+
+use super::mark_compact::generational::remember_field;
+use super::mark_compact::incremental::incremental_gc_write_barrier;
+
+use crate::memory::Memory;
+use crate::rts_trap_with;
+use crate::types::{Array, Value};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// What `write_barrier` does in addition to the store
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BarrierMode {
+    Disabled = 0,
+    SnapshotAtTheBeginning = 1,
+    RememberedSet = 2,
+}
+
+/// Current write barrier mode
+pub static mut BARRIER_MODE: BarrierMode = BarrierMode::Disabled;
+
+/// Select the write barrier mode. Argument is a `BarrierMode` value.
+#[no_mangle]
+pub unsafe extern "C" fn set_write_barrier_mode(mode: u32) {
+    BARRIER_MODE = match mode {
+        0 => BarrierMode::Disabled,
+        1 => BarrierMode::SnapshotAtTheBeginning,
+        2 => BarrierMode::RememberedSet,
+        _ => rts_trap_with("set_write_barrier_mode: unknown write barrier mode"),
+    };
+}
+
+/// Store `new_value` in `field`, with the write barrier of the current mode
+#[ic_mem_fn(ic_only)]
+unsafe fn write_barrier<M: Memory>(mem: &mut M, field: *mut Value, new_value: Value) {
+    let (heap_base, young_base) = generation_bounds();
+    write_barrier_internal(mem, heap_base, young_base, field, new_value);
+}
+
+/// Store `new_value` in element `idx` of an array, with the write barrier of the current mode. For
+/// stores of the RTS to arrays that may be old, or already marked.
+pub unsafe fn array_set_with_barrier<M: Memory>(
+    mem: &mut M,
+    array: *mut Array,
+    idx: u32,
+    new_value: Value,
+) {
+    debug_assert!(idx < array.len());
+    let (heap_base, young_base) = generation_bounds();
+    let field = array.payload_addr().add(idx as usize);
+    write_barrier_internal(mem, heap_base, young_base, field, new_value);
+}
+
+/// Barrier for an array whose elements the RTS permuted in place, e.g. in `array_sort`. The values
+/// in the array are the same, so the snapshot of the incremental GC is not affected, but pointers
+/// to the young generation may have moved to fields that are not in the remembered set yet. In
+/// `RememberedSet` mode every field of the array is remembered.
+pub unsafe fn permuted_array_barrier<M: Memory>(mem: &mut M, array: *mut Array) {
+    if BARRIER_MODE != BarrierMode::RememberedSet {
+        return;
+    }
+    let (heap_base, young_base) = generation_bounds();
+    for idx in 0..array.len() {
+        remember_field(
+            mem,
+            heap_base,
+            young_base,
+            array.payload_addr().add(idx as usize),
+        );
+    }
+}
+
+/// Beginning of the dynamic heap and of the young generation, see `remember_field`
+#[cfg(feature = "ic")]
+unsafe fn generation_bounds() -> (u32, u32) {
+    use crate::memory::ic;
+    (ic::get_aligned_heap_base(), ic::LAST_HP)
+}
+
+/// Generation bounds of the test heap in the RTS tests, as `(heap_base, young_base)`, see
+/// `remember_field`. With the default no field is remembered.
+#[cfg(not(feature = "ic"))]
+pub static mut TEST_GENERATION_BOUNDS: (u32, u32) = (0, 0);
+
+#[cfg(not(feature = "ic"))]
+unsafe fn generation_bounds() -> (u32, u32) {
+    TEST_GENERATION_BOUNDS
+}
+
+/// Store `new_value` in `field`, with the write barrier of the current mode. `heap_base` and
+/// `young_base` are used in `RememberedSet` mode, see `remember_field`.
+pub unsafe fn write_barrier_internal<M: Memory>(
+    mem: &mut M,
+    heap_base: u32,
+    young_base: u32,
+    field: *mut Value,
+    new_value: Value,
+) {
+    match BARRIER_MODE {
+        BarrierMode::Disabled => {
+            *field = new_value;
+        }
+        BarrierMode::SnapshotAtTheBeginning => {
+            incremental_gc_write_barrier(field);
+            *field = new_value;
+        }
+        BarrierMode::RememberedSet => {
+            *field = new_value;
+            remember_field(mem, heap_base, young_base, field);
+        }
+    }
+}
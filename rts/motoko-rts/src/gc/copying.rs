@@ -1,26 +1,42 @@
 use crate::constants::WORD_SIZE;
-use crate::mem_utils::{memcpy_bytes, memcpy_words};
+use crate::finalizer::{FINALIZABLES, FINALIZER_QUEUE};
+use crate::mem_utils::{memcpy_bytes, memcpy_words, zero_free_space};
 use crate::memory::Memory;
+use crate::roots::visit_roots;
 use crate::types::*;
+use crate::visitor::pointer_to_dynamic_heap;
+use crate::weak_ref::{drain_weak_refs, register_weak_ref, WEAK_REF_CLEARED};
+
+use core::ptr::addr_of_mut;
 
 use motoko_rts_macros::ic_mem_fn;
 
 #[ic_mem_fn(ic_only)]
-unsafe fn schedule_copying_gc<M: Memory>(mem: &mut M) {
+pub(crate) unsafe fn schedule_copying_gc<M: Memory>(mem: &mut M) {
+    if crate::memory_pressure::memory_pressure_handler_running() {
+        // The heap is collected after the handler returns
+        return;
+    }
+
     // Half of the heap.
     // NB. This expression is evaluated in compile time to a constant.
     let max_live: Bytes<u64> =
         Bytes(u64::from((crate::constants::WASM_HEAP_SIZE / 2).as_u32()) * u64::from(WORD_SIZE));
 
-    if super::should_do_gc(max_live) {
+    let relieved = super::relieve_memory_pressure();
+    if super::take_collection_request() || relieved || super::should_do_gc(max_live) {
         copying_gc(mem);
     }
+
+    super::enforce_heap_limit(|| copying_gc(mem));
 }
 
 #[ic_mem_fn(ic_only)]
-unsafe fn copying_gc<M: Memory>(mem: &mut M) {
+pub(crate) unsafe fn copying_gc<M: Memory>(mem: &mut M) {
     use crate::memory::ic;
 
+    let gc_start = super::stats::GcStart::now();
+
     copying_gc_internal(
         mem,
         ic::get_heap_base(),
@@ -31,12 +47,16 @@ unsafe fn copying_gc<M: Memory>(mem: &mut M) {
         ic::get_static_roots(),
         crate::continuation_table::continuation_table_loc(),
         // note_live_size
-        |live_size| ic::MAX_LIVE = ::core::cmp::max(ic::MAX_LIVE, live_size),
+        |live_size| ic::note_live_size(live_size),
         // note_reclaimed
-        |reclaimed| ic::RECLAIMED += Bytes(u64::from(reclaimed.as_u32())),
+        |reclaimed| ic::note_reclaimed(reclaimed),
     );
 
     ic::LAST_HP = ic::HP;
+    gc_start.note_gc_end();
+
+    // Objects are moved, remembered fields are no longer valid
+    super::mark_compact::generational::note_major_gc();
 }
 
 pub unsafe fn copying_gc_internal<
@@ -73,6 +93,12 @@ pub unsafe fn copying_gc_internal<
         );
     }
 
+    visit_roots(|root| {
+        if pointer_to_dynamic_heap(root, begin_from_space) {
+            evac(mem, begin_from_space, begin_to_space, root as usize);
+        }
+    });
+
     // Scavenge to-space
     let mut p = begin_to_space;
     while p < get_hp() {
@@ -81,6 +107,10 @@ pub unsafe fn copying_gc_internal<
         p += size.to_bytes().as_usize();
     }
 
+    update_weak_refs(begin_from_space);
+
+    evac_finalizables(mem, begin_from_space, begin_to_space);
+
     let end_to_space = get_hp();
 
     // Note the stats
@@ -99,6 +129,7 @@ pub unsafe fn copying_gc_internal<
 
     // Reset the heap pointer
     let new_hp = begin_from_space + (end_to_space - begin_to_space);
+    zero_free_space(new_hp, end_to_space);
     set_hp(new_hp as u32);
 }
 
@@ -164,11 +195,78 @@ unsafe fn evac<M: Memory>(
 unsafe fn scav<M: Memory>(mem: &mut M, begin_from_space: usize, begin_to_space: usize, obj: usize) {
     let obj = obj as *mut Obj;
 
+    if obj.tag() == TAG_WEAK_REF {
+        register_weak_ref(obj as *mut WeakRef);
+    }
+
     crate::visitor::visit_pointer_fields(obj, obj.tag(), begin_from_space, |field_addr| {
         evac(mem, begin_from_space, begin_to_space, field_addr as usize);
     });
 }
 
+/// Update weak references (see `weak_ref` module) in to-space to point to the evacuated targets.
+/// Targets that were not evacuated are dead, references to those are cleared.
+unsafe fn update_weak_refs(begin_from_space: usize) {
+    drain_weak_refs(|weak_ref| {
+        let field_addr = &mut (*weak_ref).target;
+        if !pointer_to_dynamic_heap(field_addr, begin_from_space) {
+            return;
+        }
+
+        let target = (*field_addr).as_obj();
+        if target.tag() == TAG_FWD_PTR {
+            *field_addr = (*(target as *const FwdPtr)).fwd;
+        } else {
+            *field_addr = WEAK_REF_CLEARED;
+        }
+    });
+}
+
+/// Evacuate objects in the finalizer queue, and move dead objects in the finalizer registry to the
+/// queue (see `finalizer` module). Links in the lists don't keep objects alive, so this is done
+/// after scavenging. Objects in the lists don't need to be scavenged as they don't have other
+/// pointer fields.
+unsafe fn evac_finalizables<M: Memory>(
+    mem: &mut M,
+    begin_from_space: usize,
+    begin_to_space: usize,
+) {
+    // Location of an object's `next` field in to-space, given its final location
+    let next_field = |obj: Value| {
+        let obj_addr = obj.get_ptr() - begin_from_space + begin_to_space;
+        &mut (*(obj_addr as *mut Finalizable)).next as *mut Value
+    };
+
+    let mut link = addr_of_mut!(FINALIZER_QUEUE);
+    while pointer_to_dynamic_heap(link, begin_from_space) {
+        evac(mem, begin_from_space, begin_to_space, link as usize);
+        link = next_field(*link);
+    }
+
+    let mut link = addr_of_mut!(FINALIZABLES);
+    while pointer_to_dynamic_heap(link, begin_from_space) {
+        let obj = (*link).as_obj();
+        if obj.tag() == TAG_FWD_PTR {
+            // Live, `next` field of the from-space object is overwritten by the forwarding
+            // pointer, continue with the to-space object
+            *link = (*(obj as *const FwdPtr)).fwd;
+            link = next_field(*link);
+        } else {
+            let finalizable = obj as *mut Finalizable;
+            let next = (*finalizable).next;
+            (*finalizable).next = FINALIZER_QUEUE;
+            FINALIZER_QUEUE = *link;
+            evac(
+                mem,
+                begin_from_space,
+                begin_to_space,
+                addr_of_mut!(FINALIZER_QUEUE) as usize,
+            );
+            *link = next;
+        }
+    }
+}
+
 // We have a special evacuation routine for "static roots" array: we don't evacuate elements of
 // "static roots", we just scavenge them.
 unsafe fn evac_static_roots<M: Memory>(
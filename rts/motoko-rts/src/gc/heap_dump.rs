@@ -0,0 +1,304 @@
+//! Writes the live object graph to a stream (e.g. to stable memory, see `stream`), for offline
+//! debugging.
+//!
+//! Live objects are found by marking from the roots, as in mark-compact GC, but without threading
+//! pointers, so the heap is not modified. The dump is a sequence of little-endian 32-bit words:
+//!
+//! ```text
+//! HEAP_DUMP_MAGIC | HEAP_DUMP_VERSION | heap_base
+//! n_roots | root_1 | ... | root_n
+//! continuation_table
+//! object_1 | ... | object_n
+//! 0
+//! ```
+//!
+//! Roots are addresses of the dynamic heap objects pointed by static roots, followed by the ones
+//! pointed by registered roots (see `roots` module). `continuation_table`
+//! is the address of the continuation table, or 0 when it's not in the dynamic heap. Objects are
+//! dumped in address order, each object is:
+//!
+//! ```text
+//! address | tag | size (in words) | n_fields | field_1 | ... | field_n
+//! ```
+//!
+//! where fields are addresses of the objects pointed by the pointer fields of the object. Weak
+//! fields (see `visit_weak_fields`) are not dumped, as they may point to dead objects.
+//!
+//! The dump uses the mark bitmap, so it cannot be taken while an incremental GC is in progress.
+
+use super::mark_compact::bitmap::{
+    alloc_bitmap, free_bitmap, get_bit, iter_bits, set_bit, BITMAP_ITER_END,
+};
+use super::mark_compact::mark_stack::{
+    alloc_mark_stack, free_mark_stack, pop_mark_stack, push_mark_stack,
+};
+use super::mark_compact::GcContext;
+
+use crate::constants::WORD_SIZE;
+use crate::memory::Memory;
+use crate::roots::visit_roots;
+use crate::types::*;
+use crate::visitor::{pointer_to_dynamic_heap, visit_pointer_fields};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// First word of a heap dump ("MOHD")
+pub const HEAP_DUMP_MAGIC: u32 = 0x4448_4f4d;
+
+/// Version of the heap dump format
+pub const HEAP_DUMP_VERSION: u32 = 1;
+
+/// Size of the stream cache when dumping to stable memory
+#[cfg(feature = "ic")]
+const STABLE_DUMP_CACHE_SIZE: Bytes<u32> = Bytes(4096);
+
+/// Dump the heap to `stream`. The stream is flushed after the dump.
+#[ic_mem_fn(ic_only)]
+unsafe fn heap_dump<M: Memory>(mem: &mut M, stream: *mut Stream) {
+    use crate::memory::ic;
+
+    heap_dump_internal(
+        mem,
+        GcContext {
+            heap_base: ic::get_heap_base(),
+            get_hp: || ic::HP as usize,
+            set_hp: |hp| ic::HP = hp,
+            static_roots: ic::get_static_roots(),
+            continuation_table_ptr_loc: crate::continuation_table::continuation_table_loc(),
+        },
+        stream,
+        // check_size
+        |_| {},
+    );
+}
+
+/// Dump the heap to the user region of stable memory (see `stable_region`) at `offset`. Returns
+/// the size of the dump in bytes. Traps when the dump does not fit in the region.
+#[ic_mem_fn(ic_only)]
+unsafe fn heap_dump_to_stable<M: Memory>(mem: &mut M, offset: u64) -> u64 {
+    use crate::memory::ic;
+
+    let stream = crate::stream::alloc_stream(mem, STABLE_DUMP_CACHE_SIZE);
+    let mut dump_size = 0;
+
+    heap_dump_internal(
+        mem,
+        GcContext {
+            heap_base: ic::get_heap_base(),
+            get_hp: || ic::HP as usize,
+            set_hp: |hp| ic::HP = hp,
+            static_roots: ic::get_static_roots(),
+            continuation_table_ptr_loc: crate::continuation_table::continuation_table_loc(),
+        },
+        stream,
+        // check_size
+        |size| {
+            dump_size = size.as_u32() as u64;
+            crate::stable_region::check_range(offset, dump_size);
+            stream.setup_stable_dest(offset, offset + dump_size);
+        },
+    );
+
+    dump_size
+}
+
+/// Dump the heap to `stream`. `check_size` is called with the size of the dump before anything is
+/// written to the stream. Traps while an incremental GC is in progress.
+pub unsafe fn heap_dump_internal<
+    M: Memory,
+    GetHp: Fn() -> usize,
+    SetHp: Fn(u32),
+    CheckSize: FnOnce(Bytes<u32>),
+>(
+    mem: &mut M,
+    ctx: GcContext<GetHp, SetHp>,
+    stream: *mut Stream,
+    check_size: CheckSize,
+) {
+    use super::mark_compact::incremental;
+
+    let GcContext {
+        heap_base,
+        get_hp,
+        set_hp,
+        static_roots,
+        continuation_table_ptr_loc,
+    } = ctx;
+
+    if incremental::PHASE != incremental::Phase::Idle {
+        // The bitmap is in use
+        crate::rts_trap_with("heap_dump: incremental GC in progress");
+    }
+
+    let hp = get_hp() as u32;
+
+    // Bitmap needs to start at a 32-byte aligned address. Objects between the bitmap start and
+    // `heap_base` are static and never marked.
+    let bitmap_base = heap_base / 32 * 32;
+    alloc_bitmap(mem, Bytes(hp - bitmap_base), bitmap_base / WORD_SIZE);
+    alloc_mark_stack(mem);
+
+    mark(mem, heap_base, static_roots, continuation_table_ptr_loc);
+
+    let root_array = static_roots.as_array();
+    let root_fields = (0..root_array.len()).map(|i| {
+        let mutbox = root_array.get(i).as_obj() as *mut MutBox;
+        &mut (*mutbox).field as *mut Value
+    });
+
+    let mut n_roots = root_fields
+        .clone()
+        .filter(|field_addr| pointer_to_dynamic_heap(*field_addr, heap_base as usize))
+        .count();
+    visit_roots(|root| {
+        if pointer_to_dynamic_heap(root, heap_base as usize) {
+            n_roots += 1;
+        }
+    });
+
+    check_size(dump_size(heap_base, n_roots as u32));
+
+    write_word(stream, HEAP_DUMP_MAGIC);
+    write_word(stream, HEAP_DUMP_VERSION);
+    write_word(stream, heap_base);
+    write_word(stream, n_roots as u32);
+
+    for field_addr in root_fields {
+        if pointer_to_dynamic_heap(field_addr, heap_base as usize) {
+            write_word(stream, (*field_addr).get_ptr() as u32);
+        }
+    }
+
+    visit_roots(|root| {
+        if pointer_to_dynamic_heap(root, heap_base as usize) {
+            write_word(stream, (*root).get_ptr() as u32);
+        }
+    });
+
+    if pointer_to_dynamic_heap(continuation_table_ptr_loc, heap_base as usize) {
+        write_word(stream, (*continuation_table_ptr_loc).get_ptr() as u32);
+    } else {
+        write_word(stream, 0);
+    }
+
+    let mut bitmap_iter = iter_bits();
+    let mut bit = bitmap_iter.next();
+    while bit != BITMAP_ITER_END {
+        let obj = (bit * WORD_SIZE) as *mut Obj;
+        let tag = obj.tag();
+
+        write_word(stream, obj as u32);
+        write_word(stream, tag);
+        write_word(stream, object_size(obj as usize).as_u32());
+
+        let mut n_fields = 0;
+        visit_pointer_fields(obj, tag, heap_base as usize, |_| n_fields += 1);
+        write_word(stream, n_fields);
+
+        visit_pointer_fields(obj, tag, heap_base as usize, |field_addr| {
+            write_word(stream, (*field_addr).get_ptr() as u32);
+        });
+
+        bit = bitmap_iter.next();
+    }
+
+    write_word(stream, 0);
+    stream.shutdown();
+
+    free_mark_stack();
+    free_bitmap();
+    set_hp(hp);
+}
+
+/// Size of the dump of the marked objects, with `n_roots` roots
+unsafe fn dump_size(heap_base: u32, n_roots: u32) -> Bytes<u32> {
+    // Magic, version, heap base, number of roots, roots, continuation table, terminating 0
+    let mut words = 6 + n_roots;
+
+    let mut bitmap_iter = iter_bits();
+    let mut bit = bitmap_iter.next();
+    while bit != BITMAP_ITER_END {
+        let obj = (bit * WORD_SIZE) as *mut Obj;
+        // Address, tag, size, number of fields, fields
+        words += 4;
+        visit_pointer_fields(obj, obj.tag(), heap_base as usize, |_| words += 1);
+        bit = bitmap_iter.next();
+    }
+
+    Words(words).to_bytes()
+}
+
+/// Mark objects reachable from the roots. Bitmap and mark stack should be allocated.
+unsafe fn mark<M: Memory>(
+    mem: &mut M,
+    heap_base: u32,
+    static_roots: Value,
+    continuation_table_ptr_loc: *mut Value,
+) {
+    // Set when an object could not be pushed to the mark stack, see `mark_compact::mark_stack`
+    let mut overflow = false;
+
+    let root_array = static_roots.as_array();
+    for i in 0..root_array.len() {
+        let mutbox = root_array.get(i).as_obj() as *mut MutBox;
+        let field_addr = &mut (*mutbox).field;
+        if pointer_to_dynamic_heap(field_addr, heap_base as usize) {
+            mark_object(mem, *field_addr, &mut overflow);
+        }
+    }
+
+    if pointer_to_dynamic_heap(continuation_table_ptr_loc, heap_base as usize) {
+        mark_object(mem, *continuation_table_ptr_loc, &mut overflow);
+    }
+
+    visit_roots(|root| {
+        if pointer_to_dynamic_heap(root, heap_base as usize) {
+            mark_object(mem, *root, &mut overflow);
+        }
+    });
+
+    loop {
+        while let Some((obj, tag)) = pop_mark_stack() {
+            visit_pointer_fields(obj as *mut Obj, tag, heap_base as usize, |field_addr| {
+                mark_object(mem, *field_addr, &mut overflow);
+            });
+        }
+
+        if !overflow {
+            break;
+        }
+
+        // Scan marked objects again, to find the objects that could not be pushed
+        overflow = false;
+
+        let mut bitmap_iter = iter_bits();
+        let mut bit = bitmap_iter.next();
+        while bit != BITMAP_ITER_END {
+            let obj = (bit * WORD_SIZE) as *mut Obj;
+            visit_pointer_fields(obj, obj.tag(), heap_base as usize, |field_addr| {
+                mark_object(mem, *field_addr, &mut overflow);
+            });
+            bit = bitmap_iter.next();
+        }
+    }
+}
+
+unsafe fn mark_object<M: Memory>(mem: &mut M, obj: Value, overflow: &mut bool) {
+    let obj_tag = obj.tag();
+    let obj = obj.get_ptr() as u32;
+    let obj_idx = obj / WORD_SIZE;
+
+    if get_bit(obj_idx) {
+        return;
+    }
+
+    set_bit(obj_idx);
+    if !push_mark_stack(mem, obj as usize, obj_tag) {
+        *overflow = true;
+    }
+}
+
+unsafe fn write_word(stream: *mut Stream, word: u32) {
+    let bytes = word.to_le_bytes();
+    stream.cache_bytes(bytes.as_ptr(), Bytes(bytes.len() as u32));
+}
@@ -0,0 +1,59 @@
+//! High-water marks of the heap: the largest heap size reached, which is the heap size before a
+//! collection, and the largest live size after a collection.
+//!
+//! Peak, not current, heap usage is what determines whether a canister runs out of memory under
+//! load. The current heap size after a message can be small even when the message allocated
+//! close to the limit, and the live size after the last collection can be small even when an
+//! earlier collection retained much more.
+//!
+//! The heap end is updated on every allocation, so `note_alloc` is a `max`, compiled to a `select`
+//! without a branch. The heap size is computed from the heap end only when the high-water mark is
+//! read. The live size is updated after each collection, with `note_live_size`.
+
+use crate::types::Bytes;
+
+pub struct HighWaterMark {
+    /// Largest heap pointer after an allocation
+    max_heap_end: usize,
+    /// Largest live size after a collection
+    max_live: Bytes<u32>,
+}
+
+impl Default for HighWaterMark {
+    fn default() -> Self {
+        HighWaterMark::new()
+    }
+}
+
+impl HighWaterMark {
+    pub const fn new() -> HighWaterMark {
+        HighWaterMark {
+            max_heap_end: 0,
+            max_live: Bytes(0),
+        }
+    }
+
+    /// Update the heap high-water mark with the heap pointer after an allocation
+    #[inline]
+    pub fn note_alloc(&mut self, heap_end: usize) {
+        self.max_heap_end = ::core::cmp::max(self.max_heap_end, heap_end);
+    }
+
+    /// Update the live high-water mark with the live size after a collection
+    pub fn note_live_size(&mut self, live: Bytes<u32>) {
+        self.max_live = ::core::cmp::max(self.max_live, live);
+    }
+
+    /// Largest heap size reached, for the heap starting at `heap_base`
+    pub fn max_heap_size(&self, heap_base: usize) -> Bytes<u32> {
+        Bytes(self.max_heap_end.saturating_sub(heap_base) as u32)
+    }
+
+    /// Largest live size after a collection
+    pub fn max_live_size(&self) -> Bytes<u32> {
+        self.max_live
+    }
+}
+
+/// High-water marks of the heap of the RTS, updated by `IcMemory` and `note_live_size`
+pub static mut HIGH_WATER_MARK: HighWaterMark = HighWaterMark::new();
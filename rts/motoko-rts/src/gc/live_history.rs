@@ -0,0 +1,116 @@
+//! Live heap size after recent collections, to detect memory leaks.
+//!
+//! After each collection the live size is recorded, with the number of the message it was done
+//! in, in a ring buffer of the last `LIVE_HISTORY_SIZE` collections. A canister can read the
+//! samples in a query, and an operator can see whether the live size after collection (the
+//! baseline) grows message over message.
+//!
+//! Messages are counted by `note_message_end`, called by the generated code at the end of each
+//! message that can collect garbage, before the GC is scheduled. Collections done at the end of a
+//! message are recorded with the number of that message, starting with 1 for `canister_init`.
+
+use crate::types::Bytes;
+
+/// Number of samples kept
+pub const LIVE_HISTORY_SIZE: usize = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiveSample {
+    /// Number of the message the collection was done in
+    pub message: u64,
+    /// Live size after the collection
+    pub live: Bytes<u32>,
+}
+
+/// Ring buffer of the last `LIVE_HISTORY_SIZE` samples
+pub struct LiveHistory {
+    samples: [LiveSample; LIVE_HISTORY_SIZE],
+    /// Index of the next sample to write
+    next: usize,
+    /// Number of samples recorded, up to `LIVE_HISTORY_SIZE`
+    len: usize,
+}
+
+impl Default for LiveHistory {
+    fn default() -> Self {
+        LiveHistory::new()
+    }
+}
+
+impl LiveHistory {
+    pub const fn new() -> LiveHistory {
+        LiveHistory {
+            samples: [LiveSample {
+                message: 0,
+                live: Bytes(0),
+            }; LIVE_HISTORY_SIZE],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Record a sample, replacing the oldest one when the buffer is full
+    pub fn record(&mut self, message: u64, live: Bytes<u32>) {
+        self.samples[self.next] = LiveSample { message, live };
+        self.next = (self.next + 1) % LIVE_HISTORY_SIZE;
+        self.len = ::core::cmp::min(self.len + 1, LIVE_HISTORY_SIZE);
+    }
+
+    /// Number of samples
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Sample `idx`, the oldest sample first
+    pub fn get(&self, idx: usize) -> LiveSample {
+        assert!(idx < self.len);
+        let oldest = (self.next + LIVE_HISTORY_SIZE - self.len) % LIVE_HISTORY_SIZE;
+        self.samples[(oldest + idx) % LIVE_HISTORY_SIZE]
+    }
+}
+
+/// Samples of the GCs in the RTS
+pub static mut LIVE_HISTORY: LiveHistory = LiveHistory::new();
+
+/// Number of messages ended so far
+static mut MESSAGE_COUNT: u64 = 0;
+
+/// Count the end of a message
+#[no_mangle]
+pub unsafe extern "C" fn note_message_end() {
+    MESSAGE_COUNT += 1;
+}
+
+/// Record the live size after a collection in the current message. Called by `note_live_size`.
+#[cfg(feature = "ic")]
+pub(crate) unsafe fn record_live_size(live: Bytes<u32>) {
+    (*::core::ptr::addr_of_mut!(LIVE_HISTORY)).record(MESSAGE_COUNT, live);
+}
+
+/// Number of samples in `LIVE_HISTORY`
+#[no_mangle]
+pub unsafe extern "C" fn live_history_len() -> u32 {
+    (*::core::ptr::addr_of!(LIVE_HISTORY)).len() as u32
+}
+
+/// Message number of sample `idx` of `LIVE_HISTORY`, the oldest sample first
+#[no_mangle]
+pub unsafe extern "C" fn live_history_message(idx: u32) -> u64 {
+    (*::core::ptr::addr_of!(LIVE_HISTORY))
+        .get(idx as usize)
+        .message
+}
+
+/// Live size of sample `idx` of `LIVE_HISTORY`, the oldest sample first
+#[no_mangle]
+pub unsafe extern "C" fn live_history_live(idx: u32) -> u32 {
+    (*::core::ptr::addr_of!(LIVE_HISTORY))
+        .get(idx as usize)
+        .live
+        .as_u32()
+}
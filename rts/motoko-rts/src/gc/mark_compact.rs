@@ -3,21 +3,168 @@
 //! threaded compaction algorithm described in The Garbage Collection Handbook section 3.3.
 
 pub mod bitmap;
+pub mod estimate;
+pub mod generational;
+pub mod incremental;
 pub mod mark_stack;
+pub mod recovery;
+pub mod remembered_set;
+pub mod root_snapshot;
 
 use bitmap::{alloc_bitmap, free_bitmap, get_bit, iter_bits, set_bit, BITMAP_ITER_END};
 use mark_stack::{alloc_mark_stack, free_mark_stack, pop_mark_stack, push_mark_stack};
+use recovery::{note_gc_progress, note_gc_start, GcProgress};
 
 use crate::constants::WORD_SIZE;
-use crate::mem_utils::memcpy_words;
+use crate::finalizer::{queue_dead_finalizables, visit_finalizer_links};
+use crate::gc::instruction_counter;
+use crate::gc::size_histogram::{note_live_object, reset_size_histogram};
+use crate::mem_utils::{memcpy_words, zero_free_space};
 use crate::memory::Memory;
+use crate::pin::{is_pinned, visit_pinned_objects};
+use crate::pointer_width::{self, Width};
+use crate::roots::visit_roots;
+use crate::rts_trap_with;
 use crate::types::*;
-use crate::visitor::{pointer_to_dynamic_heap, visit_pointer_fields};
+use crate::visitor::{pointer_to_dynamic_heap, visit_pointer_fields, visit_weak_fields};
+use crate::weak_ref::{drain_weak_refs, register_weak_ref, WEAK_REF_CLEARED};
 
 use motoko_rts_macros::ic_mem_fn;
 
+/// Set when an object is marked, but could not be pushed to the mark stack because the stack was
+/// full. See `mark_stack` for how we handle this.
+static mut MARK_STACK_OVERFLOW: bool = false;
+
+/// Objects of this size or larger are not moved in compaction. Space before a large object that
+/// can't be filled by the objects moved to it is filled with a filler object. When a large object
+/// dies its space is reused by the objects moved after it.
+pub const LARGE_OBJECT_THRESHOLD: Words<u32> = Words(16 * 1024);
+
+/// Default value of `SWEEP_FRAGMENTATION_THRESHOLD`
+pub const MAX_FRAGMENTATION_FOR_SWEEP: f64 = 0.1;
+
+/// With the `WhenFragmented` compaction policy, when heap fragmentation (see `heap_fragmentation`)
+/// after marking is below this value, the heap is not compacted. Instead dead objects between live
+/// objects are replaced with filler objects, which is cheaper than compaction as live objects are
+/// not moved.
+pub static mut SWEEP_FRAGMENTATION_THRESHOLD: f64 = MAX_FRAGMENTATION_FOR_SWEEP;
+
+/// Set `SWEEP_FRAGMENTATION_THRESHOLD`. 0 disables sweeping.
+#[no_mangle]
+pub unsafe extern "C" fn set_sweep_fragmentation_threshold(threshold: f64) {
+    SWEEP_FRAGMENTATION_THRESHOLD = threshold;
+}
+
+/// Whether mark-compact GC compacts the heap after marking, or replaces dead objects with filler
+/// objects without moving live objects ("sweeping"). Sweeping makes GC pauses shorter, compacting
+/// makes the heap smaller.
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompactionPolicy {
+    /// Sweep as long as the swept heap is smaller than `SWEEP_HEAP_SIZE_LIMIT`, compact otherwise.
+    /// The allocator does not reuse the filler objects of sweeping, so without compaction the heap
+    /// would only grow. Live objects are moved only when the heap approaches its maximum size.
+    Never = 0,
+    /// Compact when heap fragmentation is at least `SWEEP_FRAGMENTATION_THRESHOLD`, sweep otherwise
+    WhenFragmented = 1,
+    /// Always compact
+    Always = 2,
+}
+
+/// Current compaction policy
+pub static mut COMPACTION_POLICY: CompactionPolicy = CompactionPolicy::WhenFragmented;
+
+/// Select the compaction policy. Argument is a `CompactionPolicy` value.
+///
+/// Only applies to full mark-compact GCs: incremental and minor GCs always compact.
+#[no_mangle]
+pub unsafe extern "C" fn set_compaction_policy(policy: u32) {
+    COMPACTION_POLICY = match policy {
+        0 => CompactionPolicy::Never,
+        1 => CompactionPolicy::WhenFragmented,
+        2 => CompactionPolicy::Always,
+        _ => rts_trap_with("set_compaction_policy: unknown compaction policy"),
+    };
+}
+
+/// Default value of `SWEEP_HEAP_SIZE_LIMIT`: 3 GiB, below the heap size at which GC is scheduled
+/// regardless of heap growth (see `schedule_compacting_gc`)
+pub const MAX_HEAP_SIZE_FOR_SWEEP: Bytes<u32> = Bytes(3 * 1024 * 1024 * 1024);
+
+/// With the `Never` compaction policy, when the heap from the heap base to the end of the last live
+/// object is at least this large, the heap is compacted instead of swept
+pub static mut SWEEP_HEAP_SIZE_LIMIT: Bytes<u32> = MAX_HEAP_SIZE_FOR_SWEEP;
+
+/// Whether to compact the heap with the given fragmentation, and size up to the end of the last
+/// live object, according to `COMPACTION_POLICY`
+unsafe fn should_compact(fragmentation: f64, heap_size: Bytes<u32>) -> bool {
+    match COMPACTION_POLICY {
+        CompactionPolicy::Never => heap_size >= SWEEP_HEAP_SIZE_LIMIT,
+        CompactionPolicy::WhenFragmented => fragmentation >= SWEEP_FRAGMENTATION_THRESHOLD,
+        CompactionPolicy::Always => true,
+    }
+}
+
+/// Heap fragmentation measured in the last mark-compact GC, before compaction. See
+/// `heap_fragmentation`.
+static mut HEAP_FRAGMENTATION: f64 = 0.0;
+
+/// Fragmentation of the heap in the last mark-compact GC, measured after marking: ratio of the
+/// space between live objects to the space from the heap base to the end of the last live object,
+/// in range `[0, 1]`. Dead objects after the last live object are not counted, as they are
+/// reclaimed without compaction. Useful to decide whether a compacting GC is worthwhile.
+#[no_mangle]
+pub unsafe extern "C" fn heap_fragmentation() -> f64 {
+    HEAP_FRAGMENTATION
+}
+
+/// Phases of mark-compact GC measured with `compacting_gc_internal`'s `note_phase_time` callback
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GcPhase {
+    /// Marking live objects
+    Mark = 0,
+    /// Updating references and moving objects, or sweeping
+    UpdateRefs = 1,
+    /// Freeing the mark stack and bitmap
+    FreeBitmap = 2,
+}
+
+/// Number of instructions each phase (indexed by `GcPhase`) took in the last mark-compact GC
+static mut LAST_GC_PHASE_TIMES: [u64; 3] = [0; 3];
+
+/// Number of instructions the given phase (a `GcPhase` value) took in the last mark-compact GC.
+/// Returns 0 for unknown phases.
+#[no_mangle]
+pub unsafe extern "C" fn last_gc_phase_time(phase: u32) -> u64 {
+    LAST_GC_PHASE_TIMES
+        .get(phase as usize)
+        .copied()
+        .unwrap_or(0)
+}
+
+/// The heap that a mark-compact GC collects: where the dynamic heap starts, how to read and update
+/// the heap pointer, and the roots
+pub struct GcContext<GetHp: Fn() -> usize, SetHp: Fn(u32)> {
+    pub heap_base: u32,
+    pub get_hp: GetHp,
+    pub set_hp: SetHp,
+    pub static_roots: Value,
+    pub continuation_table_ptr_loc: *mut Value,
+}
+
 #[ic_mem_fn(ic_only)]
 unsafe fn schedule_compacting_gc<M: Memory>(mem: &mut M) {
+    if crate::memory_pressure::memory_pressure_handler_running() {
+        // The heap is collected after the handler returns
+        return;
+    }
+
+    if super::GC_STRATEGY == super::Strategy::Copying {
+        super::copying::schedule_copying_gc(mem);
+        return;
+    }
+
     // 512 MiB slack for mark stack + allocation area for the next message
     let slack: u64 = 512 * 1024 * 1024;
     let heap_size_bytes: u64 =
@@ -27,31 +174,62 @@ unsafe fn schedule_compacting_gc<M: Memory>(mem: &mut M) {
     // NB. `max_live` is evaluated in compile time to a constant
     let max_live: Bytes<u64> = Bytes(heap_size_bytes - slack - max_bitmap_size_bytes);
 
-    if super::should_do_gc(max_live) {
-        compacting_gc(mem);
+    let relieved = super::relieve_memory_pressure();
+    if super::take_collection_request() || relieved {
+        super::gc_collect_now(mem);
+    } else if super::barrier::BARRIER_MODE == super::barrier::BarrierMode::SnapshotAtTheBeginning {
+        // Incremental GC: continue the cycle in progress, or start a new one
+        if incremental::PHASE != incremental::Phase::Idle || super::should_do_gc(max_live) {
+            incremental::compacting_gc_incremental(mem, incremental::SLICE_BUDGET.as_u32());
+        }
+    } else if super::should_do_gc(max_live) {
+        // `compacting_gc` finishes incremental GC in progress, minor GC cannot be done in the
+        // middle of incremental marking
+        if incremental::PHASE != incremental::Phase::Idle
+            || generational::should_do_major_gc(max_live)
+        {
+            compacting_gc(mem);
+        } else {
+            generational::compacting_gc_minor(mem);
+        }
     }
+
+    super::enforce_heap_limit(|| compacting_gc(mem));
 }
 
 #[ic_mem_fn(ic_only)]
-unsafe fn compacting_gc<M: Memory>(mem: &mut M) {
+pub(crate) unsafe fn compacting_gc<M: Memory>(mem: &mut M) {
     use crate::memory::ic;
 
+    if incremental::PHASE != incremental::Phase::Idle {
+        // Finish the incremental GC in progress instead of starting a new one
+        incremental::compacting_gc_incremental(mem, u32::MAX);
+        return;
+    }
+
+    let gc_start = super::stats::GcStart::now();
+
     compacting_gc_internal(
         mem,
-        ic::get_aligned_heap_base(),
-        // get_hp
-        || ic::HP as usize,
-        // set_hp
-        |hp| ic::HP = hp,
-        ic::get_static_roots(),
-        crate::continuation_table::continuation_table_loc(),
+        GcContext {
+            heap_base: ic::get_aligned_heap_base(),
+            get_hp: || ic::HP as usize,
+            set_hp: |hp| ic::HP = hp,
+            static_roots: ic::get_static_roots(),
+            continuation_table_ptr_loc: crate::continuation_table::continuation_table_loc(),
+        },
         // note_live_size
-        |live_size| ic::MAX_LIVE = ::core::cmp::max(ic::MAX_LIVE, live_size),
+        |live_size| ic::note_live_size(live_size),
         // note_reclaimed
-        |reclaimed| ic::RECLAIMED += Bytes(u64::from(reclaimed.as_u32())),
+        |reclaimed| ic::note_reclaimed(reclaimed),
+        // note_phase_time
+        |phase, instructions| LAST_GC_PHASE_TIMES[phase as usize] = instructions,
     );
 
     ic::LAST_HP = ic::HP;
+    gc_start.note_gc_end();
+
+    generational::note_major_gc();
 }
 
 pub unsafe fn compacting_gc_internal<
@@ -60,64 +238,244 @@ pub unsafe fn compacting_gc_internal<
     SetHp: Fn(u32),
     NoteLiveSize: Fn(Bytes<u32>),
     NoteReclaimed: Fn(Bytes<u32>),
+    NotePhaseTime: Fn(GcPhase, u64),
 >(
     mem: &mut M,
-    heap_base: u32,
-    get_hp: GetHp,
-    set_hp: SetHp,
-    static_roots: Value,
-    continuation_table_ptr_loc: *mut Value,
+    ctx: GcContext<GetHp, SetHp>,
     note_live_size: NoteLiveSize,
     note_reclaimed: NoteReclaimed,
+    note_phase_time: NotePhaseTime,
 ) {
+    let GcContext {
+        heap_base,
+        get_hp,
+        set_hp,
+        static_roots,
+        continuation_table_ptr_loc,
+    } = ctx;
+
     let old_hp = get_hp() as u32;
 
     assert_eq!(heap_base % 32, 0);
+    check_heap_base(heap_base);
 
-    mark_compact(
+    let live = mark_compact(
         mem,
-        set_hp,
+        &set_hp,
         heap_base,
         old_hp,
         static_roots,
         continuation_table_ptr_loc,
+        &note_phase_time,
     );
 
+    #[cfg(any(feature = "debug-heap", feature = "rts-assert-full"))]
+    {
+        let hp = get_hp() as u32;
+        crate::gc::verify::verify_heap(
+            mem,
+            heap_base,
+            hp,
+            static_roots,
+            continuation_table_ptr_loc,
+        );
+        // Drop the bitmap allocated by `verify_heap`
+        zero_free_space(hp as usize, get_hp());
+        set_hp(hp);
+    }
+
     let reclaimed = old_hp - (get_hp() as u32);
     note_reclaimed(Bytes(reclaimed));
 
-    let live = get_hp() as u32 - heap_base;
-    note_live_size(Bytes(live));
+    note_live_size(live);
 }
 
-unsafe fn mark_compact<M: Memory, SetHp: Fn(u32)>(
+/// Returns size of the live objects. When the heap is swept instead of compacted (see
+/// `COMPACTION_POLICY`) this is less than the heap size after GC.
+unsafe fn mark_compact<M: Memory, SetHp: Fn(u32), NotePhaseTime: Fn(GcPhase, u64)>(
     mem: &mut M,
     set_hp: SetHp,
     heap_base: u32,
     heap_end: u32,
     static_roots: Value,
     continuation_table_ptr_loc: *mut Value,
-) {
+    note_phase_time: NotePhaseTime,
+) -> Bytes<u32> {
     let mem_size = Bytes(heap_end - heap_base);
 
+    let phase_start = instruction_counter();
+
+    note_gc_start(heap_base, heap_end);
+
     alloc_bitmap(mem, mem_size, heap_base / WORD_SIZE);
     alloc_mark_stack(mem);
 
+    mark(mem, heap_base, static_roots, continuation_table_ptr_loc);
+
+    let phase_start = note_phase_end(&note_phase_time, GcPhase::Mark, phase_start);
+
+    reset_size_histogram();
+    let mut live_end = heap_base;
+    let live = marked_size(|obj, size| {
+        note_live_object(size);
+        live_end = obj as u32 + size.to_bytes().as_u32();
+    });
+
+    HEAP_FRAGMENTATION = fragmentation(heap_base, live_end, live);
+
+    let free = if should_compact(HEAP_FRAGMENTATION, Bytes(live_end - heap_base)) {
+        note_gc_progress(GcProgress::Compacting);
+        update_refs(heap_base, heap_end)
+    } else {
+        sweep(heap_base)
+    };
+
+    zero_free_space(free as usize, heap_end as usize);
+
+    let phase_start = note_phase_end(&note_phase_time, GcPhase::UpdateRefs, phase_start);
+
+    set_hp(free);
+
+    free_mark_stack();
+    free_bitmap();
+
+    note_gc_progress(GcProgress::Idle);
+
+    note_phase_end(&note_phase_time, GcPhase::FreeBitmap, phase_start);
+
+    live
+}
+
+/// Note the instructions executed since `phase_start` as the time of `phase`. Returns the start of
+/// the next phase.
+unsafe fn note_phase_end<NotePhaseTime: Fn(GcPhase, u64)>(
+    note_phase_time: NotePhaseTime,
+    phase: GcPhase,
+    phase_start: u64,
+) -> u64 {
+    let phase_end = instruction_counter();
+    note_phase_time(phase, phase_end - phase_start);
+    phase_end
+}
+
+/// Mark live objects, and thread backwards pointers and pointers from roots. Bitmap and mark
+/// stack should be allocated.
+unsafe fn mark<M: Memory>(
+    mem: &mut M,
+    heap_base: u32,
+    static_roots: Value,
+    continuation_table_ptr_loc: *mut Value,
+) {
     mark_static_roots(mem, static_roots, heap_base);
 
     if (*continuation_table_ptr_loc).is_ptr() {
         mark_object(mem, *continuation_table_ptr_loc);
         // Similar to `mark_root_mutbox_fields`, `continuation_table_ptr_loc` is in static heap so
-        // it will be readable when we unthread the continuation table
+        // it will be readable when we unthread the continuation table.
+        //
+        // The location needs to be threaded even when the table was already marked through a
+        // static root: it's a different location than the root field, and it's threaded only
+        // here, so it would not be updated otherwise. `mark_object` already skips marked objects.
         thread(continuation_table_ptr_loc);
     }
 
+    // Registered roots are outside of the dynamic heap, like `continuation_table_ptr_loc`
+    visit_roots(|root| {
+        if pointer_to_dynamic_heap(root, heap_base as usize) {
+            mark_object(mem, *root);
+            thread(root);
+        }
+    });
+
+    mark_pinned_objects(mem, heap_base);
+
     mark_stack(mem, heap_base);
 
-    update_refs(set_hp, heap_base);
+    clear_weak_refs(heap_base);
 
-    free_mark_stack();
-    free_bitmap();
+    mark_finalizables(heap_base);
+}
+
+/// Mark pinned objects and the pin table (see `pin` module). These are not moved, so pointers to
+/// them don't need updating and are not threaded.
+pub(crate) unsafe fn mark_pinned_objects<M: Memory>(mem: &mut M, heap_base: u32) {
+    visit_pinned_objects(|obj| {
+        if obj >= heap_base {
+            mark_object(mem, Value::from_ptr(obj as usize));
+        }
+    });
+}
+
+/// Clear weak references to unmarked objects (see `weak_ref` module), and thread the other weak
+/// references that point backwards, like `mark_fields` does for other fields. Forward pointers are
+/// threaded in `update_refs`. Should be called after marking.
+unsafe fn clear_weak_refs(heap_base: u32) {
+    drain_weak_refs(|weak_ref| {
+        let obj = weak_ref as *mut Obj;
+        visit_weak_fields(obj, TAG_WEAK_REF, heap_base as usize, |field_addr| {
+            let target = (*field_addr).get_ptr();
+            if !get_bit(target as u32 / WORD_SIZE) {
+                *field_addr = WEAK_REF_CLEARED;
+            } else if target <= obj as usize {
+                thread(field_addr);
+            }
+        });
+    });
+}
+
+/// Move dead finalizable objects to the finalizer queue (see `finalizer` module), and mark the
+/// objects in the queue. Then thread the links in the registry and the queue: links from list heads
+/// and from objects that are not moved (below `heap_base`), and backwards links. Forward links are
+/// threaded in `update_refs`. Should be called after marking.
+unsafe fn mark_finalizables(heap_base: u32) {
+    queue_dead_finalizables(|finalizable| {
+        let obj = finalizable as u32;
+        obj >= heap_base && !get_bit(obj / WORD_SIZE)
+    });
+
+    visit_finalizer_links(|link, is_head| {
+        let obj = (*link).get_ptr() as u32;
+        if obj < heap_base {
+            return;
+        }
+
+        // Objects in the registry are already marked. Objects in the queue don't have pointer
+        // fields, so they don't need to be pushed to the mark stack.
+        set_bit(obj / WORD_SIZE);
+
+        if is_head || (link as u32) < heap_base || obj <= link as u32 {
+            thread(link);
+        }
+    });
+}
+
+/// Total size of marked objects. `note_size` is called with the address and the size of each
+/// marked object, in address order. Should be called after marking and before compaction.
+unsafe fn marked_size<NoteSize: FnMut(usize, Words<u32>)>(mut note_size: NoteSize) -> Bytes<u32> {
+    let mut live_words = Words(0);
+
+    let mut bitmap_iter = iter_bits();
+    let mut bit = bitmap_iter.next();
+    while bit != BITMAP_ITER_END {
+        let obj = (bit * WORD_SIZE) as *mut Obj;
+        let size = object_size_with_tag(obj, threaded_tag(obj));
+        note_size(obj as usize, size);
+        live_words += size;
+        bit = bitmap_iter.next();
+    }
+
+    live_words.to_bytes()
+}
+
+/// Fragmentation of a heap with `live` bytes of live objects, the last one ending at `live_end`.
+/// See `heap_fragmentation`.
+fn fragmentation(heap_base: u32, live_end: u32, live: Bytes<u32>) -> f64 {
+    let live_span = live_end - heap_base;
+    if live_span == 0 {
+        0.0
+    } else {
+        f64::from(live_span - live.as_u32()) / f64::from(live_span)
+    }
 }
 
 unsafe fn mark_static_roots<M: Memory>(mem: &mut M, static_roots: Value, heap_base: u32) {
@@ -148,16 +506,69 @@ unsafe fn mark_object<M: Memory>(mem: &mut M, obj: Value) {
     }
 
     set_bit(obj_idx);
-    push_mark_stack(mem, obj as usize, obj_tag);
+    if !push_mark_stack(mem, obj as usize, obj_tag) {
+        MARK_STACK_OVERFLOW = true;
+    }
 }
 
+/// Mark objects in the mark stack, until the stack is empty.
+///
+/// The mark stack has a maximum size (`mark_stack::MAX_STACK_SIZE`). When an object cannot be
+/// pushed because the stack is full it is still marked in the bitmap, but its fields are not
+/// visited, and `MARK_STACK_OVERFLOW` is set. Once the stack is empty we recover from the overflow
+/// by scanning fields of all marked objects again (`rescan_marked_objects`). Rescanning may
+/// overflow the stack again, so we repeat until no overflows happen. This way the GC uses bounded
+/// space for marking, in exchange for extra heap scans when the stack overflows.
 unsafe fn mark_stack<M: Memory>(mem: &mut M, heap_base: u32) {
-    while let Some((obj, tag)) = pop_mark_stack() {
-        mark_fields(mem, obj as *mut Obj, tag, heap_base);
+    loop {
+        while let Some((obj, tag)) = pop_mark_stack() {
+            mark_fields(mem, obj as *mut Obj, tag, heap_base);
+        }
+
+        if !MARK_STACK_OVERFLOW {
+            break;
+        }
+
+        MARK_STACK_OVERFLOW = false;
+        rescan_marked_objects(mem, heap_base);
     }
 }
 
+/// Visit fields of all marked objects, to find objects that were marked but could not be pushed
+/// to the mark stack.
+///
+/// Visiting fields of an object that was already visited is harmless: backwards pointers of the
+/// object are already threaded, and `visit_pointer_fields` skips threaded fields as a threaded
+/// field holds either a header (tag), which does not point to the dynamic heap, or a field
+/// address, which is not a skewed pointer. Forward pointers are not threaded during marking, and
+/// marking the objects they point to again is a no-op.
+unsafe fn rescan_marked_objects<M: Memory>(mem: &mut M, heap_base: u32) {
+    let mut bitmap_iter = iter_bits();
+    let mut bit = bitmap_iter.next();
+    while bit != BITMAP_ITER_END {
+        let obj = (bit * WORD_SIZE) as *mut Obj;
+        mark_fields(mem, obj, threaded_tag(obj), heap_base);
+
+        // Mark objects pushed while visiting the fields right away, to keep the stack small
+        while let Some((obj, tag)) = pop_mark_stack() {
+            mark_fields(mem, obj as *mut Obj, tag, heap_base);
+        }
+
+        bit = bitmap_iter.next();
+    }
+}
+
+/// Get tag of an object that may have been threaded. Follows the chain of threaded fields to the
+/// object header.
+unsafe fn threaded_tag(obj: *mut Obj) -> Tag {
+    pointer_width::threaded_tag::<Width>(obj as usize)
+}
+
 unsafe fn mark_fields<M: Memory>(mem: &mut M, obj: *mut Obj, obj_tag: Tag, heap_base: u32) {
+    if obj_tag == TAG_WEAK_REF {
+        register_weak_ref(obj as *mut WeakRef);
+    }
+
     visit_pointer_fields(obj, obj_tag, heap_base as usize, |field_addr| {
         let field_value = *field_addr;
         mark_object(mem, field_value);
@@ -185,71 +596,97 @@ unsafe fn mark_root_mutbox_fields<M: Memory>(mem: &mut M, mutbox: *mut MutBox, h
 /// - Mark step threads all backwards pointers and pointers from roots, so unthread to update those
 ///   pointers to the objects new location.
 ///
-/// - Move the object
+/// - Move the object, unless it's a large object (see `LARGE_OBJECT_THRESHOLD`) or pinned (see
+///   `pin` module)
 ///
 /// - Thread forward pointers of the object
 ///
-unsafe fn update_refs<SetHp: Fn(u32)>(set_hp: SetHp, heap_base: u32) {
+/// Returns the new end of the heap.
+unsafe fn update_refs(heap_base: u32, heap_end: u32) -> u32 {
     let mut free = heap_base;
 
     let mut bitmap_iter = iter_bits();
     let mut bit = bitmap_iter.next();
     while bit != BITMAP_ITER_END {
         let p = (bit * WORD_SIZE) as *mut Obj;
-        let p_new = free;
+        let p_size_words = object_size_with_tag(p, threaded_tag(p));
+
+        let p_new = if p_size_words >= LARGE_OBJECT_THRESHOLD || is_pinned(p as u32) {
+            write_filler(free as usize, Bytes(p as u32 - free).to_words());
+            p as u32
+        } else {
+            free
+        };
 
         // Update backwards references to the object's new location and restore object header
         unthread(p, p_new);
 
         // Move the object
-        let p_size_words = object_size(p as usize);
         if p_new as usize != p as usize {
             memcpy_words(p_new as usize, p as usize, p_size_words);
         }
 
-        free += p_size_words.to_bytes().as_u32();
+        free = p_new + p_size_words.to_bytes().as_u32();
 
         // Thread forward pointers of the object
-        thread_fwd_pointers(p_new as *mut Obj, heap_base);
+        thread_fwd_pointers(p_new as *mut Obj, heap_base, heap_end);
 
         bit = bitmap_iter.next();
     }
 
-    set_hp(free);
+    free
+}
+
+/// Alternative to `update_refs` that does not move objects: unthread pointers to marked objects,
+/// and fill space between them with filler objects. Space after the last marked object is
+/// reclaimed.
+///
+/// Returns the new end of the heap.
+unsafe fn sweep(heap_base: u32) -> u32 {
+    // End of the last marked object
+    let mut free = heap_base;
+
+    let mut bitmap_iter = iter_bits();
+    let mut bit = bitmap_iter.next();
+    while bit != BITMAP_ITER_END {
+        let p = (bit * WORD_SIZE) as *mut Obj;
+
+        write_filler(free as usize, Bytes(p as u32 - free).to_words());
+
+        // Update backwards references and restore object header. Forward pointers are not
+        // threaded in marking.
+        unthread(p, p as u32);
+
+        free = p as u32 + object_size(p as usize).to_bytes().as_u32();
+
+        bit = bitmap_iter.next();
+    }
+
+    free
 }
 
-/// Thread forwards pointers in object
-unsafe fn thread_fwd_pointers(obj: *mut Obj, heap_base: u32) {
-    visit_pointer_fields(obj, obj.tag(), heap_base as usize, |field_addr| {
-        if (*field_addr).get_ptr() > obj as usize {
+/// Thread forwards pointers in object. Pointers to `heap_end` and beyond are not threaded, these
+/// point to objects allocated during incremental marking (see `incremental` module).
+unsafe fn thread_fwd_pointers(obj: *mut Obj, heap_base: u32, heap_end: u32) {
+    let thread_fwd_pointer = |field_addr: *mut Value| {
+        let field_value = (*field_addr).get_ptr();
+        if field_value > obj as usize && field_value < heap_end as usize {
             thread(field_addr)
         }
-    });
+    };
+
+    let tag = obj.tag();
+    visit_pointer_fields(obj, tag, heap_base as usize, thread_fwd_pointer);
+    visit_weak_fields(obj, tag, heap_base as usize, thread_fwd_pointer);
 }
 
-/// Thread a pointer field
-unsafe fn thread(field: *mut Value) {
-    // Store pointed object's header in the field, field address in the pointed object's header
-    let pointed = (*field).as_obj();
-    let pointed_header = pointed.tag();
-    *field = Value::from_raw(pointed_header);
-    (*pointed).tag = field as u32;
+/// Thread a pointer field. The pointed object's header becomes the start of a chain of field
+/// addresses, ending with the original header (see `is_tag`).
+pub unsafe fn thread(field: *mut Value) {
+    pointer_width::thread::<Width>(field as usize);
 }
 
 /// Unthread all references at given header, replacing with `new_loc`. Restores object header.
-unsafe fn unthread(obj: *mut Obj, new_loc: u32) {
-    let mut header = (*obj).tag;
-
-    // All objects and fields are word-aligned, and tags have the lowest bit set, so use the lowest
-    // bit to distinguish a header (tag) from a field address.
-    while header & 0b1 == 0 {
-        let tmp = (*(header as *mut Obj)).tag;
-        (*(header as *mut Value)) = Value::from_ptr(new_loc as usize);
-        header = tmp;
-    }
-
-    // At the end of the chain is the original header for the object
-    debug_assert!(header >= TAG_OBJECT && header <= TAG_NULL);
-
-    (*obj).tag = header;
+pub unsafe fn unthread(obj: *mut Obj, new_loc: u32) {
+    pointer_width::unthread::<Width>(obj as usize, new_loc as usize);
 }
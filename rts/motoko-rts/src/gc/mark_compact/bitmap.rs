@@ -55,6 +55,14 @@ Now let's mark the address 0x80548 in the DH. Its absolute word number is 0x2015
 The `(0x20152 / 8, 0x20152 % 8)`-rule gives a bit position 2 with byte offset 0x402A,
 thus we mark bit 2 in byte 0xAC004 + 0x402A = 0xB002E, which is in the BM.
 
+## Bitmap lifetime
+
+The bitmap is bump-allocated at the end of the heap in every GC, and is dropped by moving the
+heap pointer back after compaction (`free_bitmap` only resets the pointers). There is no
+allocator to return it to, so allocating a bitmap is just a heap pointer increment and zeroing
+the bitmap, which a cached bitmap would need as well. A bitmap cannot be kept across GCs: the
+mutator allocates over it after the GC.
+
  */
 
 /// Current bitmap
@@ -0,0 +1,144 @@
+//! Dry run of the mark-compact GC: estimates how much a GC would reclaim, without the cost of
+//! compaction.
+//!
+//! Marking in the stop-the-world GC threads pointers, which only compaction undoes. The dry run
+//! instead marks like incremental marking, without threading, so the heap is left as it was. Once
+//! marking is done the size of the marked objects is taken from the bitmap, and the bitmap and
+//! the mark stack are freed.
+//!
+//! Objects kept alive by the GC without being reachable are marked the same way the GC does:
+//! pinned objects, and finalizable objects, which the GC keeps in the finalizer queue when dead.
+//! Weak references don't keep their targets alive.
+
+use super::bitmap::{alloc_bitmap, free_bitmap, iter_bits, set_bit, BITMAP_ITER_END};
+use super::mark_stack::{alloc_mark_stack, free_mark_stack, pop_mark_stack};
+use super::{mark_object, mark_pinned_objects, marked_size, MARK_STACK_OVERFLOW};
+
+use crate::constants::WORD_SIZE;
+use crate::finalizer::visit_finalizer_links;
+use crate::mem_utils::zero_free_space;
+use crate::memory::Memory;
+use crate::roots::visit_roots;
+use crate::types::*;
+use crate::visitor::{pointer_to_dynamic_heap, visit_pointer_fields};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Size of the dead objects in the heap, which a mark-compact GC would reclaim. The GC reclaims
+/// less when the heap is swept instead of compacted (see `COMPACTION_POLICY`), or when large or
+/// pinned objects are not moved down over dead objects.
+#[ic_mem_fn(ic_only)]
+unsafe fn gc_estimate_reclaimable<M: Memory>(mem: &mut M) -> Bytes<u32> {
+    use super::incremental;
+    use crate::memory::ic;
+
+    if incremental::PHASE != incremental::Phase::Idle {
+        // The bitmap is in use
+        crate::rts_trap_with("gc_estimate_reclaimable: incremental GC in progress");
+    }
+
+    estimate_reclaimable_internal(
+        mem,
+        ic::get_aligned_heap_base(),
+        // get_hp
+        || ic::HP as usize,
+        // set_hp
+        |hp| ic::HP = hp,
+        ic::get_static_roots(),
+        crate::continuation_table::continuation_table_loc(),
+    )
+}
+
+/// Mark the heap without threading and return the size of the unmarked objects. The heap pointer
+/// is restored before returning. With the `zero-on-free` feature the space used by the bitmap and
+/// the mark stack is zeroed.
+pub unsafe fn estimate_reclaimable_internal<M: Memory, GetHp: Fn() -> usize, SetHp: Fn(u32)>(
+    mem: &mut M,
+    heap_base: u32,
+    get_hp: GetHp,
+    set_hp: SetHp,
+    static_roots: Value,
+    continuation_table_ptr_loc: *mut Value,
+) -> Bytes<u32> {
+    let heap_end = get_hp() as u32;
+
+    assert_eq!(heap_base % 32, 0);
+    check_heap_base(heap_base);
+
+    alloc_bitmap(mem, Bytes(heap_end - heap_base), heap_base / WORD_SIZE);
+    alloc_mark_stack(mem);
+
+    let root_array = static_roots.as_array();
+    for i in 0..root_array.len() {
+        let mutbox = root_array.get(i).as_obj() as *mut MutBox;
+        let field_addr = &mut (*mutbox).field;
+        if pointer_to_dynamic_heap(field_addr, heap_base as usize) {
+            mark_object(mem, *field_addr);
+        }
+    }
+
+    if (*continuation_table_ptr_loc).is_ptr() {
+        mark_object(mem, *continuation_table_ptr_loc);
+    }
+
+    visit_roots(|root| {
+        if pointer_to_dynamic_heap(root, heap_base as usize) {
+            mark_object(mem, *root);
+        }
+    });
+
+    mark_pinned_objects(mem, heap_base);
+
+    mark_reachable(mem, heap_base);
+
+    visit_finalizer_links(|link, _| {
+        let obj = (*link).get_ptr() as u32;
+        if obj >= heap_base {
+            // Finalizable objects don't have pointer fields, no need to scan them
+            set_bit(obj / WORD_SIZE);
+        }
+    });
+
+    let live = marked_size(|_, _| {});
+
+    free_mark_stack();
+    free_bitmap();
+    zero_free_space(heap_end as usize, get_hp());
+    set_hp(heap_end);
+
+    Bytes(heap_end - heap_base) - live
+}
+
+/// Mark objects reachable from the objects in the mark stack. Like in incremental marking, mark
+/// stack overflows are handled by scanning all marked objects again, which is harmless as fields
+/// are not threaded.
+unsafe fn mark_reachable<M: Memory>(mem: &mut M, heap_base: u32) {
+    let scan = |mem: &mut M, obj: *mut Obj, tag: Tag| {
+        visit_pointer_fields(obj, tag, heap_base as usize, |field_addr| {
+            mark_object(mem, *field_addr);
+        });
+    };
+
+    loop {
+        while let Some((obj, tag)) = pop_mark_stack() {
+            scan(mem, obj as *mut Obj, tag);
+        }
+
+        if !MARK_STACK_OVERFLOW {
+            return;
+        }
+
+        MARK_STACK_OVERFLOW = false;
+
+        let mut bitmap_iter = iter_bits();
+        let mut bit = bitmap_iter.next();
+        while bit != BITMAP_ITER_END {
+            let obj = (bit * WORD_SIZE) as *mut Obj;
+            scan(mem, obj, obj.tag());
+            while let Some((obj, tag)) = pop_mark_stack() {
+                scan(mem, obj as *mut Obj, tag);
+            }
+            bit = bitmap_iter.next();
+        }
+    }
+}
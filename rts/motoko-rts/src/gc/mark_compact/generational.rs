@@ -0,0 +1,260 @@
+//! Generational collection for the mark-compact GC.
+//!
+//! The dynamic heap is split into two generations:
+//!
+//! ```text
+//!   heap_base                 young_base                 hp
+//!   |---- old generation ----|---- young generation ----|
+//! ```
+//!
+//! The young generation is everything allocated since the last GC, so on the IC `young_base` is
+//! `LAST_HP`. A minor GC (`compacting_gc_minor`) only marks and compacts the young generation.
+//! Objects that survive a minor GC are promoted to the old generation, by moving `young_base` to
+//! the end of the compacted heap. Old garbage is only collected by a major GC (`compacting_gc`).
+//!
+//! Roots of a minor GC are the static roots, the continuation table, and the old fields that point
+//! to young objects. The latter are recorded in a remembered set (`REMEMBERED_SET`) by the write
+//! barrier (`gc::barrier` in remembered set mode, or `generational_write_barrier`), which the
+//! mutator needs to call after writing a pointer to an object field when generational GC is
+//! enabled, as the code generated with `moc --generational-gc` does. The continuation table is
+//! scanned in every minor GC instead, so RTS functions updating it don't need to call the barrier.
+//!
+//! Old objects pointed by the roots are not marked, so old garbage may keep young objects alive
+//! until the next major GC.
+
+use super::bitmap::{alloc_bitmap, free_bitmap};
+use super::mark_stack::{alloc_mark_stack, free_mark_stack};
+use super::remembered_set::RememberedSet;
+use super::{
+    clear_weak_refs, mark_finalizables, mark_object, mark_pinned_objects, mark_stack,
+    mark_static_roots, thread, update_refs, GcContext,
+};
+
+use crate::constants::WORD_SIZE;
+use crate::gc::barrier::{BarrierMode, BARRIER_MODE};
+use crate::mem_utils::zero_free_space;
+use crate::memory::Memory;
+use crate::roots::visit_roots;
+use crate::rts_trap_with;
+use crate::types::*;
+use crate::visitor::pointer_to_dynamic_heap;
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Whether the generated code calls the write barrier. When `false` minor GCs are not done, and
+/// the write barrier does nothing.
+pub static mut GENERATIONAL: bool = false;
+
+/// Old fields that may point to young objects. Allocated on first use, and reset after every GC.
+pub static mut REMEMBERED_SET: Option<RememberedSet> = None;
+
+/// Value of `ALLOCATED` after the last major GC
+#[cfg(feature = "ic")]
+static mut ALLOCATED_AT_LAST_MAJOR_GC: Bytes<u64> = Bytes(0);
+
+/// Do a major GC when allocation since the last major GC exceeds this amount
+#[cfg(feature = "ic")]
+const MAJOR_GC_ALLOCATION_THRESHOLD: Bytes<u64> = Bytes(512 * 1024 * 1024);
+
+/// Enable generational GC. Called on initialization by the code generated with
+/// `moc --generational-gc`, after selecting the remembered set mode of the write barrier (see
+/// `gc::barrier`). Minor GCs miss old-to-young pointers without the barrier, so traps in the other
+/// modes.
+#[no_mangle]
+pub unsafe extern "C" fn enable_generational_gc() {
+    if BARRIER_MODE != BarrierMode::RememberedSet {
+        rts_trap_with("enable_generational_gc: write barrier is not in remembered set mode");
+    }
+    GENERATIONAL = true;
+}
+
+#[ic_mem_fn(ic_only)]
+unsafe fn generational_write_barrier<M: Memory>(mem: &mut M, location: *mut Value) {
+    use crate::memory::ic;
+
+    if GENERATIONAL {
+        remember_field(mem, ic::get_aligned_heap_base(), ic::LAST_HP, location);
+    }
+}
+
+/// Record `location` in the remembered set if it's in the old generation and points to the young
+/// generation. Should be called after writing to the field.
+pub unsafe fn remember_field<M: Memory>(
+    mem: &mut M,
+    heap_base: u32,
+    young_base: u32,
+    location: *mut Value,
+) {
+    let location_addr = location as u32;
+
+    if location_addr < heap_base || location_addr >= young_base {
+        // Static field (a root), or young field (scanned in minor GC)
+        return;
+    }
+
+    if !pointer_to_dynamic_heap(location, young_base as usize) {
+        return;
+    }
+
+    match &mut REMEMBERED_SET {
+        Some(remembered_set) => remembered_set.insert(mem, location),
+        None => {
+            let mut remembered_set = RememberedSet::new(mem);
+            remembered_set.insert(mem, location);
+            REMEMBERED_SET = Some(remembered_set);
+        }
+    }
+}
+
+/// Decide whether the next GC should be a major GC
+#[cfg(feature = "ic")]
+pub(crate) unsafe fn should_do_major_gc(max_live: Bytes<u64>) -> bool {
+    use crate::memory::ic;
+
+    if !GENERATIONAL {
+        return true;
+    }
+
+    // Minor GCs do not reclaim old garbage, do a major GC when too much was allocated since the
+    // last one, or the old generation is getting too large
+    let allocated = ic::ALLOCATED.0 - ALLOCATED_AT_LAST_MAJOR_GC.0;
+    let old_generation_size = u64::from(ic::LAST_HP - ic::get_aligned_heap_base());
+
+    allocated >= MAJOR_GC_ALLOCATION_THRESHOLD.0 || old_generation_size >= max_live.0 / 2
+}
+
+/// Reset generational GC state after a major GC
+#[cfg(feature = "ic")]
+pub(crate) unsafe fn note_major_gc() {
+    REMEMBERED_SET = None;
+    ALLOCATED_AT_LAST_MAJOR_GC = crate::memory::ic::ALLOCATED;
+}
+
+#[ic_mem_fn(ic_only)]
+pub(crate) unsafe fn compacting_gc_minor<M: Memory>(mem: &mut M) {
+    use crate::memory::ic;
+
+    let gc_start = crate::gc::stats::GcStart::now();
+
+    compacting_gc_minor_internal(
+        mem,
+        GcContext {
+            heap_base: ic::get_aligned_heap_base(),
+            get_hp: || ic::HP as usize,
+            set_hp: |hp| ic::HP = hp,
+            static_roots: ic::get_static_roots(),
+            continuation_table_ptr_loc: crate::continuation_table::continuation_table_loc(),
+        },
+        ic::LAST_HP,
+        // note_live_size
+        |live_size| ic::note_live_size(live_size),
+        // note_reclaimed
+        |reclaimed| ic::note_reclaimed(reclaimed),
+    );
+
+    // Promote survivors
+    ic::LAST_HP = ic::HP;
+    gc_start.note_gc_end();
+}
+
+/// Collect the young generation, which starts at `young_base`. Uses and resets `REMEMBERED_SET`.
+///
+/// Live size is reported as the heap size after GC, as the size of live data in the old
+/// generation is not known.
+pub unsafe fn compacting_gc_minor_internal<
+    M: Memory,
+    GetHp: Fn() -> usize,
+    SetHp: Fn(u32),
+    NoteLiveSize: Fn(Bytes<u32>),
+    NoteReclaimed: Fn(Bytes<u32>),
+>(
+    mem: &mut M,
+    ctx: GcContext<GetHp, SetHp>,
+    young_base: u32,
+    note_live_size: NoteLiveSize,
+    note_reclaimed: NoteReclaimed,
+) {
+    let GcContext {
+        heap_base,
+        get_hp,
+        set_hp,
+        static_roots,
+        continuation_table_ptr_loc,
+    } = ctx;
+
+    let old_hp = get_hp() as u32;
+
+    assert_eq!(heap_base % 32, 0);
+    check_heap_base(heap_base);
+    debug_assert!(young_base >= heap_base && young_base <= old_hp);
+
+    // Remembered set lives in the young generation, it's collected in this GC
+    let remembered_set = REMEMBERED_SET.take();
+
+    // Bitmap needs to start at a 32-byte aligned address. Objects between the bitmap start and
+    // `young_base` are old and never marked.
+    let bitmap_base = young_base / 32 * 32;
+    alloc_bitmap(mem, Bytes(old_hp - bitmap_base), bitmap_base / WORD_SIZE);
+    alloc_mark_stack(mem);
+
+    mark_static_roots(mem, static_roots, young_base);
+
+    let continuation_table = *continuation_table_ptr_loc;
+    if continuation_table.is_ptr() {
+        if continuation_table.get_ptr() >= young_base as usize {
+            mark_object(mem, continuation_table);
+            thread(continuation_table_ptr_loc);
+        } else if continuation_table.get_ptr() >= heap_base as usize {
+            mark_old_fields(mem, continuation_table.as_array(), young_base);
+        }
+    }
+
+    // Objects in the old generation are not collected or moved, so roots pointing to them need
+    // no marking or updating
+    visit_roots(|root| {
+        if pointer_to_dynamic_heap(root, young_base as usize) {
+            mark_object(mem, *root);
+            thread(root);
+        }
+    });
+
+    if let Some(remembered_set) = &remembered_set {
+        for field_addr in remembered_set.iter() {
+            mark_old_field(mem, field_addr, young_base);
+        }
+    }
+
+    mark_pinned_objects(mem, young_base);
+
+    mark_stack(mem, young_base);
+
+    clear_weak_refs(young_base);
+
+    mark_finalizables(young_base);
+
+    let free = update_refs(young_base, old_hp);
+    zero_free_space(free as usize, old_hp as usize);
+    set_hp(free);
+
+    free_mark_stack();
+    free_bitmap();
+
+    note_reclaimed(Bytes(old_hp - free));
+    note_live_size(Bytes(free - heap_base));
+}
+
+/// Mark young objects pointed by elements of an old array
+unsafe fn mark_old_fields<M: Memory>(mem: &mut M, array: *mut Array, young_base: u32) {
+    for i in 0..array.len() {
+        mark_old_field(mem, array.payload_addr().add(i as usize), young_base);
+    }
+}
+
+/// Mark the object pointed by an old field, if it's young. The field is threaded, which is OK as
+/// old objects are not moved in minor GC.
+unsafe fn mark_old_field<M: Memory>(mem: &mut M, field_addr: *mut Value, young_base: u32) {
+    if pointer_to_dynamic_heap(field_addr, young_base as usize) {
+        mark_object(mem, *field_addr);
+        thread(field_addr);
+    }
+}
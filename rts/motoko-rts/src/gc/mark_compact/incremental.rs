@@ -0,0 +1,521 @@
+//! Incremental marking for the mark-compact GC.
+//!
+//! Marking is done in slices with a bounded amount of work (`compacting_gc_incremental`), and the
+//! mutator runs between the slices. Compaction is not incremental yet: once marking is done the
+//! last slice compacts the heap in one go. The state machine (`Phase`) already has a compaction
+//! phase, to be able to make compaction incremental later.
+//!
+//! ## Snapshot-at-the-beginning marking
+//!
+//! A GC cycle starts by recording the roots (`root_snapshot`), then marks objects reachable at that
+//! point (the snapshot) in slices. For this to be correct the mutator needs to call the write
+//! barrier (`incremental_gc_write_barrier`) before overwriting a pointer in a heap object while
+//! marking (see `incremental_gc_write_barrier_needed`). Otherwise an object in the snapshot could
+//! become reachable only from already scanned objects and never get marked. Roots can be
+//! overwritten without the barrier, the objects they pointed to are marked from the root snapshot.
+//!
+//! The generated code calls the barrier when compiled with `moc --incremental-gc`, which selects
+//! the snapshot-at-the-beginning mode of `gc::barrier`. Only in this mode a cycle spans several
+//! slices, and `schedule_compacting_gc` does a slice (`SLICE_BUDGET`) at the end of every message
+//! while a cycle is in progress.
+//!
+//! Objects allocated while marking are considered live and are not scanned. Fields of these
+//! objects point to objects in the snapshot, or to other new objects.
+//!
+//! Unlike the stop-the-world GC, marking does not thread backwards pointers, as the mutator needs
+//! to be able to read fields between the slices. Pointers are threaded before compaction.
+//!
+//! ## Heap layout while marking
+//!
+//! ```text
+//!   heap_base          MARK_END                                         NEW_START         hp
+//!   |---- marked heap ----|---- bitmap + root snapshot + mark stack ----|-- new objects --|
+//! ```
+//!
+//! The bitmap only covers the marked heap. The mark stack cannot grow as the mutator allocates
+//! after it, so it has a fixed size, and overflows are handled by rescanning marked objects (see
+//! `mark_compact::mark_stack`). The rescan is also done in slices, using a cursor into the bitmap
+//! (`RESCAN_ITER`).
+//!
+//! ## Compaction
+//!
+//! After marking, the marked heap is compacted with `update_refs`. New objects are then moved (as
+//! one block) to the end of the compacted heap, and pointers to them are adjusted.
+
+use super::bitmap::{
+    alloc_bitmap, free_bitmap, get_bit, iter_bits, set_bit, BitmapIter, BITMAP_ITER_END,
+};
+use super::mark_stack::{
+    alloc_fixed_mark_stack, free_mark_stack, pop_mark_stack, try_push_mark_stack, STACK_TOP,
+};
+use super::root_snapshot::{free_root_snapshot, pop_root_snapshot, snapshot_roots};
+use super::{mark_object, thread, threaded_tag, update_refs, GcContext, MARK_STACK_OVERFLOW};
+
+use crate::constants::WORD_SIZE;
+use crate::finalizer::{queue_dead_finalizables, visit_finalizer_links};
+use crate::gc::barrier::{BarrierMode, BARRIER_MODE};
+use crate::mem_utils::zero_free_space;
+use crate::memory::Memory;
+use crate::pin::visit_pinned_objects;
+use crate::roots::visit_roots;
+use crate::types::*;
+use crate::visitor::{visit_pointer_fields, visit_weak_fields};
+use crate::weak_ref::{drain_weak_refs, register_weak_ref, WEAK_REF_CLEARED};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Size of the mark stack in incremental marking (256 KiB)
+pub const MARK_STACK_SIZE: Words<u32> = Words(64 * 1024);
+
+/// Number of words marked in a GC slice at the end of a message (4 MiB)
+pub const SLICE_BUDGET: Words<u32> = Words(1024 * 1024);
+
+/// Phase of the incremental GC
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    /// No GC in progress
+    Idle = 0,
+    /// Marking in progress. The mutator needs to call the write barrier in this phase.
+    Marking = 1,
+    /// Compaction in progress. Compaction is currently done in one step, so the mutator never
+    /// observes this phase.
+    Compacting = 2,
+}
+
+/// Current phase
+pub static mut PHASE: Phase = Phase::Idle;
+
+/// Beginning of the dynamic heap in the current GC cycle
+static mut HEAP_BASE: u32 = 0;
+
+/// End of the heap being marked. Objects at and after this address are allocated during marking.
+static mut MARK_END: u32 = 0;
+
+/// Where the objects allocated during marking start, after the bitmap and the mark stack
+static mut NEW_START: u32 = 0;
+
+/// Cursor into the bitmap when rescanning marked objects after a mark stack overflow
+static mut RESCAN_ITER: Option<BitmapIter> = None;
+
+#[ic_mem_fn(ic_only)]
+pub(crate) unsafe fn compacting_gc_incremental<M: Memory>(mem: &mut M, budget_words: u32) {
+    use crate::memory::ic;
+
+    let gc_start = crate::gc::stats::GcStart::now();
+
+    compacting_gc_incremental_internal(
+        mem,
+        GcContext {
+            heap_base: ic::get_aligned_heap_base(),
+            get_hp: || ic::HP as usize,
+            set_hp: |hp| ic::HP = hp,
+            static_roots: ic::get_static_roots(),
+            continuation_table_ptr_loc: crate::continuation_table::continuation_table_loc(),
+        },
+        MARK_STACK_SIZE,
+        Words(budget_words),
+        // note_live_size
+        |live_size| ic::note_live_size(live_size),
+        // note_reclaimed
+        |reclaimed| ic::note_reclaimed(reclaimed),
+    );
+
+    if PHASE == Phase::Idle {
+        ic::LAST_HP = ic::HP;
+        gc_start.note_gc_end();
+        super::generational::note_major_gc();
+    }
+}
+
+/// Do a slice of incremental GC work. Starts a new GC cycle when there isn't one in progress.
+///
+/// `budget` is the number of words to scan in marking. At least one object is scanned in a slice.
+/// When marking is done, the heap is compacted in the same slice, regardless of the budget.
+///
+/// Marking is only left in progress for the mutator when the write barrier is in
+/// snapshot-at-the-beginning mode (see `gc::barrier`), otherwise the mutator could hide objects
+/// from the marker. In the other modes the whole cycle is done in one slice.
+pub unsafe fn compacting_gc_incremental_internal<
+    M: Memory,
+    GetHp: Fn() -> usize,
+    SetHp: Fn(u32),
+    NoteLiveSize: Fn(Bytes<u32>),
+    NoteReclaimed: Fn(Bytes<u32>),
+>(
+    mem: &mut M,
+    ctx: GcContext<GetHp, SetHp>,
+    mark_stack_size: Words<u32>,
+    budget: Words<u32>,
+    note_live_size: NoteLiveSize,
+    note_reclaimed: NoteReclaimed,
+) {
+    let static_roots = ctx.static_roots;
+    let continuation_table_ptr_loc = ctx.continuation_table_ptr_loc;
+
+    if PHASE == Phase::Idle {
+        start_marking(
+            mem,
+            ctx.heap_base,
+            (ctx.get_hp)() as u32,
+            static_roots,
+            continuation_table_ptr_loc,
+            mark_stack_size,
+        );
+    }
+
+    debug_assert_eq!(PHASE, Phase::Marking);
+
+    let budget = if BARRIER_MODE == BarrierMode::SnapshotAtTheBeginning {
+        budget
+    } else {
+        Words(u32::MAX)
+    };
+
+    if !mark_slice(mem, budget) {
+        return;
+    }
+
+    PHASE = Phase::Compacting;
+
+    let old_hp = (ctx.get_hp)() as u32;
+    let new_hp = compact(old_hp, static_roots, continuation_table_ptr_loc);
+    (ctx.set_hp)(new_hp);
+
+    // Bitmap and mark stack are not part of the heap before GC. When new objects are pinned the
+    // heap may grow, as they are not moved over the bitmap and mark stack.
+    let bitmap_and_stack_size = NEW_START - MARK_END;
+    note_reclaimed(Bytes(
+        (old_hp - bitmap_and_stack_size).saturating_sub(new_hp),
+    ));
+    note_live_size(Bytes(new_hp - HEAP_BASE));
+
+    free_mark_stack();
+    free_root_snapshot();
+    free_bitmap();
+
+    PHASE = Phase::Idle;
+
+    crate::gc::apply_pending_gc_strategy();
+}
+
+/// Do a slice of marking in the middle of a message, at a safepoint (see `crate::safepoint`).
+/// Returns whether marking is done.
+///
+/// Unlike `compacting_gc_incremental_internal`, never starts a cycle or compacts. The mutator holds
+/// pointers in Wasm locals, which are not roots, so objects cannot be moved in the middle of a
+/// message. Marking is safe: objects pointed by the locals are in the snapshot, or are new. The
+/// heap is compacted by the next slice at the end of a message.
+pub unsafe fn mark_slice_at_safepoint<M: Memory>(mem: &mut M, budget: Words<u32>) -> bool {
+    debug_assert!(PHASE == Phase::Marking);
+    mark_slice(mem, budget)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn incremental_gc_phase() -> u32 {
+    PHASE as u32
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn incremental_gc_write_barrier_needed() -> bool {
+    PHASE == Phase::Marking
+}
+
+/// Write barrier for incremental marking: should be called before overwriting the pointer at
+/// `location` when `incremental_gc_write_barrier_needed` returns `true`. Marks the object pointed
+/// by the old value, and pushes it to the mark stack for its fields to be scanned in the next
+/// slice.
+///
+/// The mark stack of incremental marking has a fixed size, so the push doesn't need `Memory`. When
+/// the stack is full the object is handled as a mark stack overflow, and its fields are scanned
+/// when rescanning marked objects.
+#[no_mangle]
+pub unsafe extern "C" fn incremental_gc_write_barrier(location: *mut Value) {
+    if PHASE != Phase::Marking {
+        return;
+    }
+
+    let value = *location;
+
+    if !value.is_ptr() {
+        return;
+    }
+
+    let obj = value.get_ptr() as u32;
+
+    if obj < HEAP_BASE || obj >= MARK_END {
+        // Static object or new object, not marked
+        return;
+    }
+
+    let obj_idx = obj / WORD_SIZE;
+
+    if !get_bit(obj_idx) {
+        set_bit(obj_idx);
+        if !try_push_mark_stack(obj as usize, value.tag()) {
+            MARK_STACK_OVERFLOW = true;
+        }
+    }
+}
+
+unsafe fn start_marking<M: Memory>(
+    mem: &mut M,
+    heap_base: u32,
+    heap_end: u32,
+    static_roots: Value,
+    continuation_table_ptr_loc: *mut Value,
+    mark_stack_size: Words<u32>,
+) {
+    debug_assert_eq!(heap_base % 32, 0);
+    check_heap_base(heap_base);
+
+    HEAP_BASE = heap_base;
+    MARK_END = heap_end;
+
+    alloc_bitmap(mem, Bytes(heap_end - heap_base), heap_base / WORD_SIZE);
+    // Roots are marked in the slices
+    snapshot_roots(mem, heap_base, static_roots, continuation_table_ptr_loc);
+    alloc_fixed_mark_stack(mem, mark_stack_size);
+
+    // Mark stack is allocated last, objects allocated during marking will follow it
+    NEW_START = STACK_TOP as u32;
+
+    // Objects pinned while marking are reachable from the snapshot, or are new objects
+    visit_pinned_objects(|obj| {
+        if obj >= heap_base {
+            mark_object(mem, Value::from_ptr(obj as usize));
+        }
+    });
+
+    PHASE = Phase::Marking;
+}
+
+/// Scan marked objects, until the budget is exhausted or marking is done. Returns whether marking
+/// is done.
+unsafe fn mark_slice<M: Memory>(mem: &mut M, budget: Words<u32>) -> bool {
+    let mut work = Words(0);
+
+    loop {
+        if let Some((obj, tag)) = pop_mark_stack() {
+            scan_object(mem, obj as *mut Obj, tag);
+            work += object_size(obj);
+        } else if let Some(root) = pop_root_snapshot() {
+            mark_object(mem, root);
+            work += Words(1);
+        } else if let Some(iter) = &mut RESCAN_ITER {
+            let bit = iter.next();
+            if bit == BITMAP_ITER_END {
+                RESCAN_ITER = None;
+            } else {
+                let obj = (bit * WORD_SIZE) as *mut Obj;
+                scan_object(mem, obj, obj.tag());
+                work += object_size(obj as usize);
+            }
+        } else if MARK_STACK_OVERFLOW {
+            // Rescan marked objects. Scanning an object again is harmless as marking is
+            // idempotent, and fields are not threaded in incremental marking.
+            MARK_STACK_OVERFLOW = false;
+            RESCAN_ITER = Some(iter_bits());
+        } else {
+            return true;
+        }
+
+        if work >= budget {
+            return false;
+        }
+    }
+}
+
+/// Mark objects pointed by fields of an object, skipping objects allocated during marking
+unsafe fn scan_object<M: Memory>(mem: &mut M, obj: *mut Obj, obj_tag: Tag) {
+    if obj_tag == TAG_WEAK_REF {
+        register_weak_ref(obj as *mut WeakRef);
+    }
+
+    visit_pointer_fields(obj, obj_tag, HEAP_BASE as usize, |field_addr| {
+        let field_value = *field_addr;
+        if field_value.get_ptr() < MARK_END as usize {
+            mark_object(mem, field_value);
+        }
+    });
+}
+
+/// Compact the heap after marking. Returns the new heap pointer.
+unsafe fn compact(hp: u32, static_roots: Value, continuation_table_ptr_loc: *mut Value) -> u32 {
+    clear_weak_refs();
+
+    mark_finalizables();
+
+    thread_pointers(hp, static_roots, continuation_table_ptr_loc);
+
+    let free = update_refs(HEAP_BASE, MARK_END);
+
+    // New objects cannot be moved when some of them are pinned. Fill the space between the
+    // compacted heap and the new objects instead.
+    let mut new_objects_pinned = false;
+    visit_pinned_objects(|obj| new_objects_pinned |= obj >= NEW_START);
+    if new_objects_pinned {
+        write_filler(free as usize, Bytes(NEW_START - free).to_words());
+        return hp;
+    }
+
+    // Move new objects to the end of the compacted heap
+    let new_size = hp - NEW_START;
+    let delta = NEW_START - free;
+
+    adjust_new_pointers(free, hp, delta, static_roots, continuation_table_ptr_loc);
+
+    // Destination is below the source, so copying words in increasing address order is safe even
+    // when the regions overlap
+    for i in 0..new_size / WORD_SIZE {
+        *((free + i * WORD_SIZE) as *mut u32) = *((NEW_START + i * WORD_SIZE) as *const u32);
+    }
+
+    zero_free_space((free + new_size) as usize, hp as usize);
+
+    free + new_size
+}
+
+/// Clear weak references to unmarked objects in the marked heap. Weak references allocated while
+/// marking are not in the registry, their targets are marked when allocating (see
+/// `weak_ref::alloc_weak_ref`).
+unsafe fn clear_weak_refs() {
+    drain_weak_refs(|weak_ref| {
+        let field_addr = &mut (*weak_ref).target;
+        if points_to_marked_heap(field_addr) && !get_bit(field_addr.get_ptr() as u32 / WORD_SIZE) {
+            *field_addr = WEAK_REF_CLEARED;
+        }
+    });
+}
+
+/// Move dead finalizable objects in the marked heap to the finalizer queue, and mark the objects in
+/// the queue (see `finalizer` module). Links are threaded in `thread_pointers`.
+unsafe fn mark_finalizables() {
+    queue_dead_finalizables(|finalizable| {
+        let obj = finalizable as u32;
+        obj >= HEAP_BASE && obj < MARK_END && !get_bit(obj / WORD_SIZE)
+    });
+
+    visit_finalizer_links(|link, _is_head| {
+        if points_to_marked_heap(link) {
+            set_bit((*link).get_ptr() as u32 / WORD_SIZE);
+        }
+    });
+}
+
+/// Returns whether the pointer points to the marked heap
+unsafe fn points_to_marked_heap(field_addr: *mut Value) -> bool {
+    let field_value = *field_addr;
+    field_value.is_ptr()
+        && field_value.get_ptr() >= HEAP_BASE as usize
+        && field_value.get_ptr() < MARK_END as usize
+}
+
+/// Thread pointers to the marked heap, to be able to run `update_refs` on the marked heap. This
+/// establishes the invariant that `update_refs` expects, which is established by marking in the
+/// stop-the-world GC: roots and backwards pointers are threaded.
+///
+/// All pointers from new objects to the marked heap are backwards pointers.
+unsafe fn thread_pointers(hp: u32, static_roots: Value, continuation_table_ptr_loc: *mut Value) {
+    let root_array = static_roots.as_array();
+    for i in 0..root_array.len() {
+        let mutbox = root_array.get(i).as_obj() as *mut MutBox;
+        let field_addr = &mut (*mutbox).field;
+        if points_to_marked_heap(field_addr) {
+            thread(field_addr);
+        }
+    }
+
+    if points_to_marked_heap(continuation_table_ptr_loc) {
+        thread(continuation_table_ptr_loc);
+    }
+
+    visit_roots(|root| {
+        if points_to_marked_heap(root) {
+            thread(root);
+        }
+    });
+
+    visit_finalizer_links(|link, is_head| {
+        if is_head && points_to_marked_heap(link) {
+            thread(link);
+        }
+    });
+
+    let mut bitmap_iter = iter_bits();
+    let mut bit = bitmap_iter.next();
+    while bit != BITMAP_ITER_END {
+        let obj = (bit * WORD_SIZE) as *mut Obj;
+        // Header of the object may be threaded by a root or by a self pointer
+        let tag = threaded_tag(obj);
+        let thread_backwards_pointer = |field_addr: *mut Value| {
+            if points_to_marked_heap(field_addr) && (*field_addr).get_ptr() <= obj as usize {
+                thread(field_addr);
+            }
+        };
+        visit_pointer_fields(obj, tag, HEAP_BASE as usize, thread_backwards_pointer);
+        visit_weak_fields(obj, tag, HEAP_BASE as usize, thread_backwards_pointer);
+        bit = bitmap_iter.next();
+    }
+
+    let mut obj = NEW_START;
+    while obj < hp {
+        let obj_ptr = obj as *mut Obj;
+        let tag = obj_ptr.tag();
+        let thread_pointer = |field_addr: *mut Value| {
+            if points_to_marked_heap(field_addr) {
+                thread(field_addr);
+            }
+        };
+        visit_pointer_fields(obj_ptr, tag, HEAP_BASE as usize, thread_pointer);
+        visit_weak_fields(obj_ptr, tag, HEAP_BASE as usize, thread_pointer);
+        obj += object_size(obj as usize).to_bytes().as_u32();
+    }
+}
+
+/// Subtract `delta` from pointers to new objects, in roots, the compacted heap (ending at
+/// `compacted_end`), and the new objects
+unsafe fn adjust_new_pointers(
+    compacted_end: u32,
+    hp: u32,
+    delta: u32,
+    static_roots: Value,
+    continuation_table_ptr_loc: *mut Value,
+) {
+    let adjust = |field_addr: *mut Value| {
+        let field_value = *field_addr;
+        if field_value.is_ptr()
+            && field_value.get_ptr() >= NEW_START as usize
+            && field_value.get_ptr() < hp as usize
+        {
+            *field_addr = Value::from_ptr(field_value.get_ptr() - delta as usize);
+        }
+    };
+
+    let root_array = static_roots.as_array();
+    for i in 0..root_array.len() {
+        let mutbox = root_array.get(i).as_obj() as *mut MutBox;
+        adjust(&mut (*mutbox).field);
+    }
+
+    adjust(continuation_table_ptr_loc);
+
+    visit_roots(adjust);
+
+    visit_finalizer_links(|link, is_head| {
+        if is_head {
+            adjust(link);
+        }
+    });
+
+    for (start, end) in [(HEAP_BASE, compacted_end), (NEW_START, hp)] {
+        let mut obj = start;
+        while obj < end {
+            let obj_ptr = obj as *mut Obj;
+            let tag = obj_ptr.tag();
+            visit_pointer_fields(obj_ptr, tag, HEAP_BASE as usize, adjust);
+            visit_weak_fields(obj_ptr, tag, HEAP_BASE as usize, adjust);
+            obj += object_size(obj as usize).to_bytes().as_u32();
+        }
+    }
+}
@@ -1,5 +1,18 @@
 //! A stack for marking heap objects (for GC). There should be no allocation after the stack
 //! otherwise things will break as we push. This invariant is checked in debug builds.
+//!
+//! The stack grows up to `MAX_STACK_SIZE`. When the stack is full and cannot grow further,
+//! `push_mark_stack` fails and the caller needs to handle the overflow. See
+//! `mark_compact::mark_stack` for how the mark-compact GC handles overflows.
+//!
+//! A stack allocated with `alloc_fixed_mark_stack` never grows. This is used when the mutator can
+//! allocate after the stack, as in incremental marking.
+//!
+//! Like the bitmap, the stack is bump-allocated at the end of the heap in every GC and dropped by
+//! moving the heap pointer back after compaction (`free_mark_stack` only resets the pointers).
+//! There is no allocator to return it to, and it cannot be kept across GCs as the mutator
+//! allocates over it. An incremental GC keeps its stack, and the objects allocated after it, until
+//! the end of the cycle, so marking can be suspended with the stack intact.
 
 use crate::memory::{alloc_blob, Memory};
 use crate::types::{Blob, Tag, Words};
@@ -9,6 +22,13 @@ use core::ptr::null_mut;
 /// Initial stack size
 pub const INIT_STACK_SIZE: Words<u32> = Words(64);
 
+/// Default value of `MAX_STACK_SIZE` (64 MiB)
+pub const DEFAULT_MAX_STACK_SIZE: Words<u32> = Words(16 * 1024 * 1024);
+
+/// Maximum stack size. The stack is not grown beyond this size, or beyond `INIT_STACK_SIZE` if
+/// this is smaller than `INIT_STACK_SIZE`.
+pub static mut MAX_STACK_SIZE: Words<u32> = DEFAULT_MAX_STACK_SIZE;
+
 /// Pointer to the `blob` object for the mark stack. Used to get the capacity of the stack.
 static mut STACK_BLOB_PTR: *mut Blob = null_mut();
 
@@ -21,14 +41,30 @@ pub static mut STACK_TOP: *mut usize = null_mut();
 /// Next free slot in the mark stack
 pub static mut STACK_PTR: *mut usize = null_mut();
 
+/// Whether the stack can grow. `false` when the stack is allocated with `alloc_fixed_mark_stack`.
+static mut STACK_GROWABLE: bool = true;
+
 pub unsafe fn alloc_mark_stack<M: Memory>(mem: &mut M) {
+    alloc_stack(mem, INIT_STACK_SIZE);
+    STACK_GROWABLE = true;
+}
+
+/// Allocate a stack of the given size that never grows
+pub unsafe fn alloc_fixed_mark_stack<M: Memory>(mem: &mut M, size: Words<u32>) {
+    // Stack entries are two words
+    debug_assert_eq!(size.as_u32() % 2, 0);
+    alloc_stack(mem, size);
+    STACK_GROWABLE = false;
+}
+
+unsafe fn alloc_stack<M: Memory>(mem: &mut M, size: Words<u32>) {
     debug_assert!(STACK_BLOB_PTR.is_null());
 
     // Allocating an actual object here to not break dump_heap
-    STACK_BLOB_PTR = alloc_blob(mem, INIT_STACK_SIZE.to_bytes()).get_ptr() as *mut Blob;
+    STACK_BLOB_PTR = alloc_blob(mem, size.to_bytes()).get_ptr() as *mut Blob;
     STACK_BASE = STACK_BLOB_PTR.payload_addr() as *mut usize;
     STACK_PTR = STACK_BASE;
-    STACK_TOP = STACK_BASE.add(INIT_STACK_SIZE.as_usize());
+    STACK_TOP = STACK_BASE.add(size.as_usize());
 }
 
 pub unsafe fn free_mark_stack() {
@@ -36,28 +72,65 @@ pub unsafe fn free_mark_stack() {
     STACK_BASE = null_mut();
     STACK_PTR = null_mut();
     STACK_TOP = null_mut();
+    STACK_GROWABLE = true;
 }
 
-/// Doubles the stack size
-pub unsafe fn grow_stack<M: Memory>(mem: &mut M) {
+/// Doubles the stack size, without exceeding `MAX_STACK_SIZE`. Returns `false` if the stack is
+/// already at its maximum size, or cannot grow.
+pub unsafe fn grow_stack<M: Memory>(mem: &mut M) -> bool {
+    if !STACK_GROWABLE {
+        return false;
+    }
+
     let stack_cap: Words<u32> = STACK_BLOB_PTR.len().to_words();
-    let p = mem.alloc_words(stack_cap).get_ptr() as *mut usize;
+
+    // Stack entries are two words, keep the capacity a multiple of 2
+    let max_cap = Words(MAX_STACK_SIZE.as_u32() & !0b1);
+    let new_cap: Words<u32> = core::cmp::min(stack_cap * 2, max_cap);
+
+    if new_cap <= stack_cap {
+        return false;
+    }
+
+    let p = mem.alloc_words(new_cap - stack_cap).get_ptr() as *mut usize;
 
     // Make sure nothing was allocated after the stack
     debug_assert_eq!(STACK_TOP, p);
 
-    let new_cap: Words<u32> = stack_cap * 2;
     (*STACK_BLOB_PTR).len = new_cap.to_bytes();
     STACK_TOP = STACK_BASE.add(new_cap.as_usize());
+
+    true
 }
 
-pub unsafe fn push_mark_stack<M: Memory>(mem: &mut M, obj: usize, obj_tag: Tag) {
+/// Push an object to the stack. Returns `false` if the stack is full and cannot grow further, in
+/// which case the object is not pushed.
+pub unsafe fn push_mark_stack<M: Memory>(mem: &mut M, obj: usize, obj_tag: Tag) -> bool {
     // We add 2 words in a push, and `STACK_PTR` and `STACK_TOP` are both multiples of 2, so we can
     // do simple equality check here
+    if STACK_PTR == STACK_TOP && !grow_stack(mem) {
+        return false;
+    }
+
+    push_unchecked(obj, obj_tag);
+
+    true
+}
+
+/// Push an object to the stack without growing it. Returns `false` if the stack is full, in which
+/// case the object is not pushed. For pushing without a `Memory`, e.g. to a stack allocated with
+/// `alloc_fixed_mark_stack`.
+pub unsafe fn try_push_mark_stack(obj: usize, obj_tag: Tag) -> bool {
     if STACK_PTR == STACK_TOP {
-        grow_stack(mem);
+        return false;
     }
 
+    push_unchecked(obj, obj_tag);
+
+    true
+}
+
+unsafe fn push_unchecked(obj: usize, obj_tag: Tag) {
     *STACK_PTR = obj;
     *(STACK_PTR.add(1)) = obj_tag as usize;
     STACK_PTR = STACK_PTR.add(2);
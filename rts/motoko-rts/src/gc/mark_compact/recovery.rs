@@ -0,0 +1,86 @@
+//! Repairing the heap after a mark-compact GC was interrupted by a trap.
+//!
+//! Marking threads pointers (see `thread`): the headers of live objects are replaced with chains
+//! of field addresses, and the fields with the headers. A heap in this state cannot be used by the
+//! mutator or parsed by another GC. On the IC a trap rolls back the whole message, including the
+//! heap, so a trapped GC is never observed there. Embedders that keep the memory after a trap (and
+//! the RTS tests) can however see a partially-threaded heap, so `mark_compact` records its
+//! progress in `GC_PROGRESS`, and the RTS initialization (`memory::ic::init`) repairs the heap
+//! before the mutator runs.
+//!
+//! Marking only threads backwards pointers and pointers from roots, and sweeping unthreads objects
+//! in place, so until compaction starts every threaded chain ends with a header in its object and
+//! unthreading each object to its own address restores the graph. Compaction threads forward
+//! pointers and moves objects, a heap interrupted there cannot be repaired.
+
+use super::bitmap::free_bitmap;
+use super::mark_stack::free_mark_stack;
+use super::{unthread, MARK_STACK_OVERFLOW};
+
+use crate::pointer_width::is_tag_word;
+use crate::rts_trap_with;
+use crate::types::{object_size, Obj};
+use crate::weak_ref::drain_weak_refs;
+
+/// Progress of the current mark-compact GC
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GcProgress {
+    /// No mark-compact GC in progress
+    Idle = 0,
+    /// Marking or sweeping: the heap can be repaired
+    Marking = 1,
+    /// Updating references and moving objects: the heap cannot be repaired
+    Compacting = 2,
+}
+
+pub static mut GC_PROGRESS: GcProgress = GcProgress::Idle;
+
+/// Dynamic heap of the GC in progress
+static mut GC_HEAP_BASE: u32 = 0;
+static mut GC_HEAP_END: u32 = 0;
+
+/// Record the start of a mark-compact GC of the heap from `heap_base` to `heap_end`
+pub(super) unsafe fn note_gc_start(heap_base: u32, heap_end: u32) {
+    GC_HEAP_BASE = heap_base;
+    GC_HEAP_END = heap_end;
+    GC_PROGRESS = GcProgress::Marking;
+}
+
+pub(super) unsafe fn note_gc_progress(progress: GcProgress) {
+    GC_PROGRESS = progress;
+}
+
+/// Repair the heap if a mark-compact GC was interrupted: unthread all objects, drop the bitmap and
+/// mark stack (allocated after the heap end) and reset the GC state. Returns whether the heap was
+/// repaired. Traps if the GC was interrupted while compacting.
+pub unsafe fn recover_interrupted_gc<SetHp: Fn(u32)>(set_hp: SetHp) -> bool {
+    match GC_PROGRESS {
+        GcProgress::Idle => return false,
+        GcProgress::Compacting => {
+            rts_trap_with("GC interrupted while compacting, heap cannot be repaired")
+        }
+        GcProgress::Marking => {}
+    }
+
+    let mut p = GC_HEAP_BASE;
+    while p < GC_HEAP_END {
+        let obj = p as *mut Obj;
+        if !is_tag_word(*(p as *const u32) as usize) {
+            unthread(obj, p);
+        }
+        p += object_size(p as usize).to_bytes().as_u32();
+    }
+
+    // Weak references registered in marking, cleared ones stay cleared as their targets were not
+    // marked
+    drain_weak_refs(|_| {});
+
+    free_mark_stack();
+    free_bitmap();
+    MARK_STACK_OVERFLOW = false;
+    set_hp(GC_HEAP_END);
+
+    GC_PROGRESS = GcProgress::Idle;
+    true
+}
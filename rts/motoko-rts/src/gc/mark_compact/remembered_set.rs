@@ -0,0 +1,167 @@
+//! A set of field addresses, used to record old-to-young pointers in generational GC (see
+//! `generational` module).
+//!
+//! Implemented as an open-addressing hash table with linear probing, in a blob in the dynamic
+//! heap. When the table is half full it is copied to a blob twice as large. Old tables are not
+//! referenced by anything and are collected by the next GC, same as the current table, as the set
+//! is only needed until the next GC.
+//!
+//! Field addresses are word-aligned and never 0, so 0 is used for empty entries.
+
+use crate::constants::WORD_SIZE;
+use crate::memory::{alloc_blob, Memory};
+use crate::types::{Blob, Bytes, Value};
+
+/// Initial capacity of the table, in entries. Needs to be a power of two.
+pub const INIT_CAPACITY: u32 = 1024;
+
+/// Marks empty entries in the table
+const EMPTY: u32 = 0;
+
+pub struct RememberedSet {
+    /// The hash table. Entries are field addresses, or `EMPTY`.
+    table: *mut Blob,
+    /// Number of addresses in the set
+    count: u32,
+}
+
+impl RememberedSet {
+    pub unsafe fn new<M: Memory>(mem: &mut M) -> RememberedSet {
+        RememberedSet {
+            table: alloc_table(mem, INIT_CAPACITY),
+            count: 0,
+        }
+    }
+
+    /// Add a field address to the set. Does nothing if the address is already in the set.
+    pub unsafe fn insert<M: Memory>(&mut self, mem: &mut M, field: *mut Value) {
+        debug_assert_ne!(field as u32, EMPTY);
+        debug_assert_eq!(field as u32 % WORD_SIZE, 0);
+
+        if insert_entry(self.table, field as u32) {
+            self.count += 1;
+
+            if self.count * 2 >= capacity(self.table) {
+                self.grow(mem);
+            }
+        }
+    }
+
+    pub unsafe fn contains(&self, field: *mut Value) -> bool {
+        let table = entries(self.table);
+        let mask = capacity(self.table) - 1;
+        let mut idx = hash(field as u32) & mask;
+
+        loop {
+            let entry = *table.add(idx as usize);
+            if entry == field as u32 {
+                return true;
+            }
+            if entry == EMPTY {
+                return false;
+            }
+            idx = (idx + 1) & mask;
+        }
+    }
+
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Iterate the field addresses in the set, in no particular order
+    pub unsafe fn iter(&self) -> RememberedSetIter {
+        RememberedSetIter {
+            table: entries(self.table),
+            capacity: capacity(self.table),
+            idx: 0,
+        }
+    }
+
+    unsafe fn grow<M: Memory>(&mut self, mem: &mut M) {
+        let old_table = self.table;
+        let new_table = alloc_table(mem, capacity(old_table) * 2);
+
+        let old_entries = entries(old_table);
+        for i in 0..capacity(old_table) {
+            let entry = *old_entries.add(i as usize);
+            if entry != EMPTY {
+                insert_entry(new_table, entry);
+            }
+        }
+
+        self.table = new_table;
+    }
+}
+
+pub struct RememberedSetIter {
+    table: *const u32,
+    capacity: u32,
+    idx: u32,
+}
+
+impl Iterator for RememberedSetIter {
+    type Item = *mut Value;
+
+    fn next(&mut self) -> Option<*mut Value> {
+        while self.idx < self.capacity {
+            let entry = unsafe { *self.table.add(self.idx as usize) };
+            self.idx += 1;
+            if entry != EMPTY {
+                return Some(entry as *mut Value);
+            }
+        }
+
+        None
+    }
+}
+
+unsafe fn alloc_table<M: Memory>(mem: &mut M, capacity: u32) -> *mut Blob {
+    debug_assert!(capacity.is_power_of_two());
+
+    let table = alloc_blob(mem, Bytes(capacity * WORD_SIZE)).get_ptr() as *mut Blob;
+
+    let table_entries = entries(table);
+    for i in 0..capacity {
+        *table_entries.add(i as usize) = EMPTY;
+    }
+
+    table
+}
+
+unsafe fn entries(table: *mut Blob) -> *mut u32 {
+    table.payload_addr() as *mut u32
+}
+
+unsafe fn capacity(table: *mut Blob) -> u32 {
+    table.len().as_u32() / WORD_SIZE
+}
+
+/// Insert an entry to the table. Returns `false` if the entry is already in the table. The table
+/// should have at least one empty entry.
+unsafe fn insert_entry(table: *mut Blob, entry: u32) -> bool {
+    let table_entries = entries(table);
+    let mask = capacity(table) - 1;
+    let mut idx = hash(entry) & mask;
+
+    loop {
+        let current = *table_entries.add(idx as usize);
+        if current == entry {
+            return false;
+        }
+        if current == EMPTY {
+            *table_entries.add(idx as usize) = entry;
+            return true;
+        }
+        idx = (idx + 1) & mask;
+    }
+}
+
+/// Fibonacci hashing of field addresses. Addresses are word-aligned, so the lowest two bits are
+/// dropped first.
+fn hash(field: u32) -> u32 {
+    (field / WORD_SIZE).wrapping_mul(2654435769) >> 8
+}
@@ -0,0 +1,100 @@
+//! Snapshot of the roots at the beginning of an incremental GC cycle.
+//!
+//! Snapshot-at-the-beginning marking (see `incremental`) marks the objects reachable when the
+//! cycle starts. Objects reachable from heap objects are protected by the write barrier, but roots
+//! are overwritten without the barrier (e.g. static roots by the generated code, registered roots
+//! by the RTS). `snapshot_roots` copies the pointers to the heap held by the static roots, the
+//! continuation table pointer and the registered roots (`crate::roots`) when the cycle starts, and
+//! the marking slices mark the objects in the snapshot (`pop_root_snapshot`). An object a root
+//! pointed to at the start is marked even when the root is overwritten before the object is
+//! marked, and roots never race with the marker.
+//!
+//! There are no stack references to record: the Wasm stack and locals of the mutator are not
+//! roots, objects held there are in the snapshot or allocated during marking.
+//!
+//! Like the bitmap and the mark stack, the snapshot is a blob allocated after the heap being marked,
+//! and is dropped after compaction with `free_root_snapshot`.
+
+use crate::memory::{alloc_blob, Memory};
+use crate::roots::visit_roots;
+use crate::types::{Bytes, MutBox, Value};
+use crate::visitor::pointer_to_dynamic_heap;
+
+use core::ptr::null_mut;
+
+/// Pointers in the snapshot, in the payload of the snapshot blob
+static mut SNAPSHOT: *mut Value = null_mut();
+
+/// Number of pointers in the snapshot
+static mut SNAPSHOT_LEN: u32 = 0;
+
+/// Index of the next pointer to mark
+static mut SNAPSHOT_NEXT: u32 = 0;
+
+/// Record the pointers to the dynamic heap (starting at `heap_base`) in the roots
+pub unsafe fn snapshot_roots<M: Memory>(
+    mem: &mut M,
+    heap_base: u32,
+    static_roots: Value,
+    continuation_table_ptr_loc: *mut Value,
+) {
+    debug_assert!(SNAPSHOT.is_null());
+
+    let mut len = 0;
+    visit_root_locations(static_roots, continuation_table_ptr_loc, |loc| {
+        if pointer_to_dynamic_heap(loc, heap_base as usize) {
+            len += 1;
+        }
+    });
+
+    let blob = alloc_blob(mem, Bytes(len * 4)).as_blob_mut();
+    SNAPSHOT = blob.payload_addr() as *mut Value;
+    SNAPSHOT_LEN = len;
+    SNAPSHOT_NEXT = 0;
+
+    let mut idx = 0;
+    visit_root_locations(static_roots, continuation_table_ptr_loc, |loc| {
+        if pointer_to_dynamic_heap(loc, heap_base as usize) {
+            *SNAPSHOT.add(idx) = *loc;
+            idx += 1;
+        }
+    });
+}
+
+/// Next pointer of the snapshot to mark, `None` when all pointers were popped
+pub unsafe fn pop_root_snapshot() -> Option<Value> {
+    if SNAPSHOT_NEXT == SNAPSHOT_LEN {
+        return None;
+    }
+
+    let value = *SNAPSHOT.add(SNAPSHOT_NEXT as usize);
+    SNAPSHOT_NEXT += 1;
+    Some(value)
+}
+
+/// Number of pointers of the snapshot not popped yet
+pub unsafe fn root_snapshot_len() -> u32 {
+    SNAPSHOT_LEN - SNAPSHOT_NEXT
+}
+
+pub unsafe fn free_root_snapshot() {
+    SNAPSHOT = null_mut();
+    SNAPSHOT_LEN = 0;
+    SNAPSHOT_NEXT = 0;
+}
+
+unsafe fn visit_root_locations<F: FnMut(*mut Value)>(
+    static_roots: Value,
+    continuation_table_ptr_loc: *mut Value,
+    mut f: F,
+) {
+    let root_array = static_roots.as_array();
+    for i in 0..root_array.len() {
+        let mutbox = root_array.get(i).as_obj() as *mut MutBox;
+        f(&mut (*mutbox).field);
+    }
+
+    f(continuation_table_ptr_loc);
+
+    visit_roots(f);
+}
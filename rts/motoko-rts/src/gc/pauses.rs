@@ -0,0 +1,103 @@
+//! Pause durations of recent collections, for percentile statistics.
+//!
+//! `GcStats::last_pause` only has the last pause, which says little about the pauses a canister
+//! sees over time. The instructions of each collection (the pause measured by `GcStart`, an
+//! incremental GC slice for the incremental GC) are recorded in a ring buffer of the last
+//! `PAUSE_SAMPLES` collections, and `gc_pause_percentile` computes percentiles (e.g. p50, p99) of
+//! these. `reset_gc_pauses` drops the samples, e.g. after changing the GC configuration.
+
+/// Number of pauses kept
+pub const PAUSE_SAMPLES: usize = 256;
+
+/// Ring buffer of the last `PAUSE_SAMPLES` pauses
+pub struct PauseSamples {
+    samples: [u64; PAUSE_SAMPLES],
+    /// Index of the next sample to write
+    next: usize,
+    /// Number of samples recorded, up to `PAUSE_SAMPLES`
+    len: usize,
+}
+
+impl Default for PauseSamples {
+    fn default() -> Self {
+        PauseSamples::new()
+    }
+}
+
+impl PauseSamples {
+    pub const fn new() -> PauseSamples {
+        PauseSamples {
+            samples: [0; PAUSE_SAMPLES],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Record a pause, replacing the oldest one when the buffer is full
+    pub fn record(&mut self, pause: u64) {
+        self.samples[self.next] = pause;
+        self.next = (self.next + 1) % PAUSE_SAMPLES;
+        self.len = ::core::cmp::min(self.len + 1, PAUSE_SAMPLES);
+    }
+
+    /// Number of samples
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn reset(&mut self) {
+        self.next = 0;
+        self.len = 0;
+    }
+
+    /// Percentile `p` (in range `[0, 100]`, clamped) of the samples, with the nearest-rank method:
+    /// the smallest sample such that at least `p` percent of the samples are less than or equal
+    /// to it. Returns 0 when there are no samples.
+    pub fn percentile(&self, p: f64) -> u64 {
+        if self.len == 0 {
+            return 0;
+        }
+
+        // Order does not matter, the samples are the first `len` entries until the buffer is full
+        let mut sorted = self.samples;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable();
+
+        // Rank `ceil(p / 100 * len)`, in range `[1, len]`
+        let p = if p.is_nan() { 0.0 } else { p.clamp(0.0, 100.0) };
+        let exact_rank = p / 100.0 * self.len as f64;
+        let mut rank = exact_rank as usize;
+        if (rank as f64) < exact_rank {
+            rank += 1;
+        }
+        let rank = rank.clamp(1, self.len);
+
+        sorted[rank - 1]
+    }
+}
+
+/// Pauses of the GCs in the RTS
+pub static mut GC_PAUSES: PauseSamples = PauseSamples::new();
+
+/// Record a pause of a GC in the RTS. Called by `GcStart::note_gc_end`.
+#[cfg(feature = "ic")]
+pub(crate) unsafe fn record_pause(pause: u64) {
+    (*::core::ptr::addr_of_mut!(GC_PAUSES)).record(pause);
+}
+
+/// Percentile `p` (in range `[0, 100]`) of the pauses of the last `PAUSE_SAMPLES` collections, in
+/// instructions. 0 when there was no collection since the last reset.
+#[no_mangle]
+pub unsafe extern "C" fn gc_pause_percentile(p: f64) -> u64 {
+    (*::core::ptr::addr_of!(GC_PAUSES)).percentile(p)
+}
+
+/// Drop the recorded pauses
+#[no_mangle]
+pub unsafe extern "C" fn reset_gc_pauses() {
+    (*::core::ptr::addr_of_mut!(GC_PAUSES)).reset();
+}
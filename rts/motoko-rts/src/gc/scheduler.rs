@@ -0,0 +1,151 @@
+//! Decides when to do GC, based on allocation since the last GC and the trend of live sizes in
+//! recent GCs.
+//!
+//! The heap is allowed to grow to `growth_factor` times the projected live size of the next GC.
+//! Projected live size is the live size of the last GC plus the smoothed growth of live size per
+//! GC, so a program with a growing live set is not collected too often. In addition, at least
+//! `min_interval` bytes need to be allocated between two GCs, unless the heap is close to the
+//! maximum heap size.
+//!
+//! A soft limit of the heap size can be set with `set_heap_limit`, below the maximum heap size. GC
+//! is then scheduled to keep the heap below the limit, the same way as when the heap gets close to
+//! the maximum heap size. When the heap is still beyond the limit at the end of a message, a full
+//! GC is done, and the message traps if that does not bring the heap below the limit (see
+//! `enforce_heap_limit`). GC cannot be done in the middle of a message, as the generated code keeps
+//! pointers to heap objects in Wasm locals, which are not GC roots.
+//!
+//! A canister with a known working set can reserve heap space with `reserve_heap`, e.g. in `init`.
+//! The Wasm memory for the reservation is allocated upfront, and no GC is scheduled while the heap
+//! is within the reservation, so the heap is not collected repeatedly while it warms up.
+
+use crate::rts_trap_with;
+use crate::types::Bytes;
+
+/// Default value of `GcScheduler::growth_factor`
+pub const DEFAULT_GROWTH_FACTOR: f64 = 1.5;
+
+/// Default value of `GcScheduler::min_interval` (1 MiB)
+pub const DEFAULT_MIN_INTERVAL: Bytes<u32> = Bytes(1024 * 1024);
+
+/// Weight of the last change in live size in the live size trend
+const TREND_SMOOTHING: f64 = 0.5;
+
+pub struct GcScheduler {
+    /// Heap can grow to this factor of the projected live size before GC
+    pub growth_factor: f64,
+
+    /// Minimum allocation between two GCs
+    pub min_interval: Bytes<u32>,
+
+    /// Live size after the last GC
+    last_live: Bytes<u32>,
+
+    /// Exponential moving average of the change in live size per GC
+    live_trend: f64,
+
+    /// Number of GCs so far
+    n_gcs: u32,
+
+    /// Soft limit of the dynamic heap size, set with `set_heap_limit`
+    pub heap_limit: Option<Bytes<u32>>,
+
+    /// Heap size reserved with `reserve_heap`. No GC is done while the heap is smaller.
+    pub reservation: Bytes<u32>,
+}
+
+/// Scheduler used by the GCs in the RTS
+pub static mut SCHEDULER: GcScheduler = GcScheduler::new();
+
+impl Default for GcScheduler {
+    fn default() -> Self {
+        GcScheduler::new()
+    }
+}
+
+impl GcScheduler {
+    pub const fn new() -> GcScheduler {
+        GcScheduler {
+            growth_factor: DEFAULT_GROWTH_FACTOR,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            last_live: Bytes(0),
+            live_trend: 0.0,
+            n_gcs: 0,
+            heap_limit: None,
+            reservation: Bytes(0),
+        }
+    }
+
+    /// Update the live size trend after a GC
+    pub fn note_gc(&mut self, live: Bytes<u32>) {
+        if self.n_gcs != 0 {
+            let change = f64::from(live.as_u32()) - f64::from(self.last_live.as_u32());
+            self.live_trend = TREND_SMOOTHING * change + (1.0 - TREND_SMOOTHING) * self.live_trend;
+        }
+
+        self.last_live = live;
+        self.n_gcs += 1;
+    }
+
+    /// Whether to do GC now. `heap_size` is the current size of the dynamic heap, `allocated` is
+    /// the allocation since the last GC, `max_live` is the maximum heap size the GC can handle. The
+    /// heap limit, when set, is used as the maximum heap size when smaller. Growth within the
+    /// reservation does not trigger GC, unless the heap is close to the maximum heap size.
+    pub fn should_do_gc(
+        &self,
+        heap_size: Bytes<u32>,
+        allocated: Bytes<u32>,
+        max_live: Bytes<u64>,
+    ) -> bool {
+        let heap_size = u64::from(heap_size.as_u32());
+
+        let max_live = match self.heap_limit {
+            Some(limit) => Bytes(max_live.0.min(u64::from(limit.as_u32()))),
+            None => max_live,
+        };
+
+        // Leave space for the GC when getting close to the maximum heap size, regardless of the
+        // minimum interval
+        let last_heap_size = heap_size - u64::from(allocated.as_u32());
+        let hard_limit = (last_heap_size + max_live.0) / 2;
+
+        if heap_size >= hard_limit {
+            return true;
+        }
+
+        if heap_size <= u64::from(self.reservation.as_u32()) {
+            return false;
+        }
+
+        if allocated < self.min_interval {
+            return false;
+        }
+
+        let projected_live = f64::from(self.last_live.as_u32()) + self.live_trend.max(0.0);
+        let soft_limit = projected_live * self.growth_factor;
+
+        heap_size as f64 >= soft_limit
+    }
+}
+
+/// Set the factor of the projected live size that the heap can grow to before GC. Should be at
+/// least 1.
+#[no_mangle]
+pub unsafe extern "C" fn set_gc_growth_factor(growth_factor: f64) {
+    if growth_factor.is_nan() || growth_factor < 1.0 {
+        rts_trap_with("set_gc_growth_factor: growth factor should be at least 1");
+    }
+
+    SCHEDULER.growth_factor = growth_factor;
+}
+
+/// Set the minimum allocation between two GCs, in bytes
+#[no_mangle]
+pub unsafe extern "C" fn set_gc_min_interval(min_interval: u32) {
+    SCHEDULER.min_interval = Bytes(min_interval);
+}
+
+/// Set a soft limit of the dynamic heap size, in bytes. 0 removes the limit.
+#[no_mangle]
+pub unsafe extern "C" fn set_heap_limit(limit: u32) {
+    SCHEDULER.heap_limit = if limit == 0 { None } else { Some(Bytes(limit)) };
+}
@@ -0,0 +1,45 @@
+//! Histogram of the sizes of live objects, enabled with the `size-histogram` feature.
+//!
+//! The mark-compact GC bins the objects it marks by size when it sums up the live size after
+//! marking (`mark_compact::marked_size`), so no separate heap traversal is needed. Bucket `i`
+//! counts the objects of `2^i` to `2^(i+1) - 1` words. The canister reads the histogram of the
+//! last mark-compact GC with `size_histogram_bucket`, e.g. in a query.
+//!
+//! Without the feature objects are not binned and all buckets are 0. The other GCs, and the
+//! incremental and generational modes of the mark-compact GC, don't update the histogram.
+
+use crate::types::Words;
+
+/// Number of buckets. Object sizes in words fit in 32 bits, so the last bucket is never exceeded.
+pub const SIZE_HISTOGRAM_BUCKETS: u32 = 32;
+
+static mut SIZE_HISTOGRAM: [u32; SIZE_HISTOGRAM_BUCKETS as usize] =
+    [0; SIZE_HISTOGRAM_BUCKETS as usize];
+
+/// Bucket of objects of the given size
+pub fn size_bucket(size: Words<u32>) -> u32 {
+    debug_assert_ne!(size.as_u32(), 0);
+    31 - size.as_u32().leading_zeros()
+}
+
+/// Clear the histogram. Called by the GC before binning the live objects.
+pub(crate) unsafe fn reset_size_histogram() {
+    if cfg!(feature = "size-histogram") {
+        SIZE_HISTOGRAM = [0; SIZE_HISTOGRAM_BUCKETS as usize];
+    }
+}
+
+/// Count a live object of the given size
+pub(crate) unsafe fn note_live_object(size: Words<u32>) {
+    if cfg!(feature = "size-histogram") {
+        let bucket = &mut SIZE_HISTOGRAM[size_bucket(size) as usize];
+        *bucket += 1;
+    }
+}
+
+/// Number of live objects in the given bucket in the last mark-compact GC. Returns 0 for buckets
+/// out of range.
+#[no_mangle]
+pub unsafe extern "C" fn size_histogram_bucket(bucket: u32) -> u32 {
+    SIZE_HISTOGRAM.get(bucket as usize).copied().unwrap_or(0)
+}
@@ -0,0 +1,107 @@
+//! GC statistics, accumulated over all collections. The canister reads them with `gc_stats`.
+//!
+//! Counters saturate instead of overflowing, so a long-running canister gets a large but valid
+//! value rather than a wrapped one.
+
+use crate::types::Bytes;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GcStats {
+    /// Number of collections so far. An incremental GC cycle counts as one collection.
+    pub collections: u64,
+
+    /// Live size after the last collection
+    pub total_live: Bytes<u32>,
+
+    /// Bytes reclaimed by all collections
+    pub total_reclaimed: Bytes<u64>,
+
+    /// Instructions executed by the last collection. For the incremental GC this is the last
+    /// slice of the cycle.
+    pub last_pause: u64,
+
+    /// Largest heap size seen at the start of a collection
+    pub max_heap: Bytes<u32>,
+}
+
+/// Statistics of the GCs in the RTS
+pub static mut GC_STATS: GcStats = GcStats::new();
+
+impl Default for GcStats {
+    fn default() -> Self {
+        GcStats::new()
+    }
+}
+
+impl GcStats {
+    pub const fn new() -> GcStats {
+        GcStats {
+            collections: 0,
+            total_live: Bytes(0),
+            total_reclaimed: Bytes(0),
+            last_pause: 0,
+            max_heap: Bytes(0),
+        }
+    }
+
+    /// Update the statistics after a collection. `heap_size` is the heap size at the start of the
+    /// collection.
+    pub fn note_gc(
+        &mut self,
+        live: Bytes<u32>,
+        reclaimed: Bytes<u64>,
+        heap_size: Bytes<u32>,
+        pause: u64,
+    ) {
+        self.collections = self.collections.saturating_add(1);
+        self.total_live = live;
+        self.total_reclaimed = Bytes(self.total_reclaimed.0.saturating_add(reclaimed.0));
+        self.last_pause = pause;
+        self.max_heap = ::core::cmp::max(self.max_heap, heap_size);
+    }
+}
+
+/// Returns a pointer to the GC statistics. The statistics are updated in place after each
+/// collection.
+#[no_mangle]
+pub unsafe extern "C" fn gc_stats() -> *const GcStats {
+    ::core::ptr::addr_of!(GC_STATS)
+}
+
+/// State of the heap and the instruction counter at the start of a collection, to update
+/// `GC_STATS` when the collection is done
+#[cfg(feature = "ic")]
+pub(crate) struct GcStart {
+    instructions: u64,
+    reclaimed: Bytes<u64>,
+    heap_size: Bytes<u32>,
+}
+
+#[cfg(feature = "ic")]
+impl GcStart {
+    pub(crate) unsafe fn now() -> GcStart {
+        use crate::memory::ic;
+
+        GcStart {
+            instructions: super::instruction_counter(),
+            reclaimed: ic::RECLAIMED,
+            heap_size: Bytes(ic::HP - ic::get_heap_base()),
+        }
+    }
+
+    /// Record a collection that started at `self`. Reads live size and reclaimed bytes from the
+    /// values noted by the GC. The pause is also recorded in `pauses`.
+    pub(crate) unsafe fn note_gc_end(self) {
+        use crate::memory::ic;
+
+        let pause = super::instruction_counter().saturating_sub(self.instructions);
+        GC_STATS.note_gc(
+            ic::LIVE_SIZE,
+            Bytes(ic::RECLAIMED.0 - self.reclaimed.0),
+            self.heap_size,
+            pause,
+        );
+        super::pauses::record_pause(pause);
+    }
+}
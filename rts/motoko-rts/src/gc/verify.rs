@@ -0,0 +1,122 @@
+//! Heap integrity checks, to catch heap corruption right after GC instead of when the mutator
+//! reads a bad pointer. Run after every mark-compact GC with the `debug-heap` feature or at the
+//! `Full` assertion level (see `assert_level`), and by the self-test (see `self_test`).
+
+use super::mark_compact::bitmap::{alloc_bitmap, free_bitmap, get_bit, set_bit};
+
+use crate::constants::WORD_SIZE;
+use crate::finalizer::visit_finalizer_links;
+use crate::memory::Memory;
+use crate::pin::visit_pinned_objects;
+use crate::roots::visit_roots;
+use crate::rts_trap_with;
+use crate::types::*;
+use crate::visitor::{pointer_to_dynamic_heap, visit_pointer_fields, visit_weak_fields};
+
+/// Check that the dynamic heap (`heap_base` to `heap_end`) consists of objects with valid tags,
+/// and all pointers in the heap and in the roots point to static objects or to starts of objects
+/// in the dynamic heap. Traps on the first inconsistency.
+///
+/// Allocates a bitmap after `heap_end`, the caller needs to reset the heap pointer after the
+/// check. `heap_base` should be 32-byte aligned (see `bitmap`).
+pub unsafe fn verify_heap<M: Memory>(
+    mem: &mut M,
+    heap_base: u32,
+    heap_end: u32,
+    static_roots: Value,
+    continuation_table_ptr_loc: *mut Value,
+) {
+    // Record object starts
+    alloc_bitmap(mem, Bytes(heap_end - heap_base), heap_base / WORD_SIZE);
+
+    let mut obj = heap_base;
+    while obj < heap_end {
+        let tag = (*(obj as *mut Obj)).tag;
+        if !is_valid_tag(tag) {
+            println!(
+                200,
+                "verify_heap: object at {:#x} has invalid tag {}", obj, tag
+            );
+            rts_trap_with("verify_heap: invalid object tag");
+        }
+
+        set_bit(obj / WORD_SIZE);
+        obj += object_size(obj as usize).to_bytes().as_u32();
+    }
+
+    if obj != heap_end {
+        println!(
+            200,
+            "verify_heap: last object ends at {:#x}, after heap end {:#x}", obj, heap_end
+        );
+        rts_trap_with("verify_heap: object crosses heap end");
+    }
+
+    // Check pointers
+    let mut obj = heap_base;
+    while obj < heap_end {
+        let tag = (*(obj as *mut Obj)).tag;
+        let verify_field = |field_addr| verify_pointer(field_addr, heap_end);
+        visit_pointer_fields(obj as *mut Obj, tag, heap_base as usize, verify_field);
+        visit_weak_fields(obj as *mut Obj, tag, heap_base as usize, verify_field);
+        obj += object_size(obj as usize).to_bytes().as_u32();
+    }
+
+    let root_array = static_roots.as_array();
+    for i in 0..root_array.len() {
+        let mutbox = root_array.get(i).as_obj() as *mut MutBox;
+        let field_addr = &mut (*mutbox).field;
+        if pointer_to_dynamic_heap(field_addr, heap_base as usize) {
+            verify_pointer(field_addr, heap_end);
+        }
+    }
+
+    if pointer_to_dynamic_heap(continuation_table_ptr_loc, heap_base as usize) {
+        verify_pointer(continuation_table_ptr_loc, heap_end);
+    }
+
+    visit_roots(|root| {
+        if pointer_to_dynamic_heap(root, heap_base as usize) {
+            verify_pointer(root, heap_end);
+        }
+    });
+
+    visit_finalizer_links(|link, is_head| {
+        if is_head && pointer_to_dynamic_heap(link, heap_base as usize) {
+            verify_pointer(link, heap_end);
+        }
+    });
+
+    visit_pinned_objects(|obj| {
+        if obj >= heap_base && (obj >= heap_end || !get_bit(obj / WORD_SIZE)) {
+            println!(
+                200,
+                "verify_heap: pinned object {:#x} is not an object", obj
+            );
+            rts_trap_with("verify_heap: pinned object moved");
+        }
+    });
+
+    free_bitmap();
+}
+
+/// Check that a pointer to the dynamic heap points to an object start. Object starts should be
+/// recorded in the bitmap.
+unsafe fn verify_pointer(field_addr: *mut Value, heap_end: u32) {
+    let pointee = (*field_addr).get_ptr() as u32;
+
+    if pointee >= heap_end || !get_bit(pointee / WORD_SIZE) {
+        println!(
+            200,
+            "verify_heap: field at {:#x} points to {:#x}, which is not an object",
+            field_addr as usize,
+            pointee
+        );
+        rts_trap_with("verify_heap: dangling pointer");
+    }
+}
+
+fn is_valid_tag(tag: Tag) -> bool {
+    // Forwarding pointers are only valid during copying GC
+    is_tag(tag) && tag >= TAG_OBJECT && tag <= MAX_TAG && tag != TAG_FWD_PTR
+}
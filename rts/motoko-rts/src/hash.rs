@@ -0,0 +1,56 @@
+//! Hashing of blobs and texts, with 32-bit FNV-1a (http://www.isthe.com/chongo/tech/comp/fnv/).
+//!
+//! The hash of a text is the hash of its UTF-8 encoding, so a text and a blob with the same bytes
+//! have the same hash. Hashes only depend on the bytes, not on the representation of the text, and
+//! the algorithm is fixed, so hashes are stable across canister upgrades and can be stored in
+//! stable variables.
+//!
+//! FNV-1a is not a cryptographic hash, and an attacker can easily find collisions.
+
+use crate::types::{Value, TAG_BLOB};
+
+use core::slice;
+
+const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+
+const FNV_PRIME: u32 = 0x0100_0193;
+
+fn fnv1a(mut hash: u32, bytes: &[u8]) -> u32 {
+    for byte in bytes {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+unsafe fn fnv1a_blob(hash: u32, blob: Value) -> u32 {
+    let blob = blob.as_blob();
+    fnv1a(
+        hash,
+        slice::from_raw_parts(blob.payload_const(), blob.len().as_usize()),
+    )
+}
+
+/// Hash of the bytes of a blob
+#[no_mangle]
+pub unsafe extern "C" fn blob_hash(blob: Value) -> u32 {
+    fnv1a_blob(FNV_OFFSET_BASIS, blob)
+}
+
+/// Hash of the UTF-8 encoding of a text. Hashes the blobs of concatenations in order, without
+/// copying the text to a single blob.
+#[no_mangle]
+pub unsafe extern "C" fn text_hash(text: Value) -> u32 {
+    text_hash_from(FNV_OFFSET_BASIS, text)
+}
+
+// Recursion depth is bounded by the text depth, which is kept small by `text_concat`
+unsafe fn text_hash_from(hash: u32, text: Value) -> u32 {
+    if text.tag() == TAG_BLOB {
+        fnv1a_blob(hash, text)
+    } else {
+        let concat = text.as_concat();
+        let hash = text_hash_from(hash, concat.text1());
+        text_hash_from(hash, concat.text2())
+    }
+}
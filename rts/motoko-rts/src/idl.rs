@@ -4,7 +4,7 @@ use crate::buf::{read_byte, read_word, skip_leb128, Buf};
 use crate::idl_trap_with;
 use crate::leb128::{leb128_decode, sleb128_decode};
 use crate::memory::{alloc_blob, Memory};
-use crate::types::Words;
+use crate::types::{Bytes, Words};
 use crate::utf8::utf8_validate;
 
 use core::cmp::min;
@@ -33,10 +33,10 @@ const IDL_PRIM_text: i32 = -15;
 const IDL_PRIM_reserved: i32 = -16;
 const IDL_PRIM_empty: i32 = -17;
 
-const IDL_CON_opt: i32 = -18;
-const IDL_CON_vec: i32 = -19;
+pub(crate) const IDL_CON_opt: i32 = -18;
+pub(crate) const IDL_CON_vec: i32 = -19;
 const IDL_CON_record: i32 = -20;
-const IDL_CON_variant: i32 = -21;
+pub(crate) const IDL_CON_variant: i32 = -21;
 const IDL_CON_func: i32 = -22;
 const IDL_CON_service: i32 = -23;
 
@@ -93,7 +93,7 @@ unsafe fn alloc<M: Memory>(mem: &mut M, size: Words<u32>) -> *mut u8 {
 /// * returns a pointer to the beginning of the list of main types
 ///   (again via pointer argument, for lack of multi-value returns in C ABI)
 #[ic_mem_fn]
-unsafe fn parse_idl_header<M: Memory>(
+pub(crate) unsafe fn parse_idl_header<M: Memory>(
     mem: &mut M,
     extended: bool,
     buf: *mut Buf,
@@ -270,32 +270,183 @@ unsafe fn skip_text(buf: *mut Buf) {
     utf8_validate(p as *const _, len);
 }
 
-unsafe fn skip_any_vec(buf: *mut Buf, typtbl: *mut *mut u8, t: i32, count: u32) {
-    if count == 0 {
-        return;
+/// Pending work of `skip_any`
+#[derive(Clone, Copy)]
+enum Skip {
+    /// Skip a value of type `t`, nested in `depth` records
+    Value { t: i32, depth: i32 },
+    /// Skip the remaining `count` fields of a record of type `t`. `fields` points to the next
+    /// field in the type table.
+    Fields {
+        t: i32,
+        depth: i32,
+        fields: *mut u8,
+        count: u32,
+    },
+    /// Skip the remaining `count` elements of type `t` of a vector, unless skipping the first
+    /// element, which started at `start`, made no progress
+    FirstElem { t: i32, count: u32, start: *mut u8 },
+    /// Skip the remaining `count` elements of type `t` of a vector
+    Elems { t: i32, count: u32 },
+}
+
+/// Number of work items `skip_any` keeps on the native stack. Deeper values spill to the heap.
+const SKIP_STACK_INLINE_LEN: usize = 32;
+
+/// Work stack of `skip_any`. Starts in a small inline array, and moves to a blob that doubles in
+/// size when the array is full, so the depth of the skipped value is limited by the heap rather
+/// than by the Wasm stack.
+struct SkipStack {
+    inline: [Skip; SKIP_STACK_INLINE_LEN],
+    /// Heap storage, or null while the inline array is used
+    heap: *mut Skip,
+    cap: usize,
+    len: usize,
+}
+
+impl SkipStack {
+    fn new() -> SkipStack {
+        SkipStack {
+            inline: [Skip::Elems { t: 0, count: 0 }; SKIP_STACK_INLINE_LEN],
+            heap: core::ptr::null_mut(),
+            cap: SKIP_STACK_INLINE_LEN,
+            len: 0,
+        }
+    }
+
+    fn slots(&mut self) -> *mut Skip {
+        if self.heap.is_null() {
+            self.inline.as_mut_ptr()
+        } else {
+            self.heap
+        }
     }
-    let ptr_before = (*buf).ptr;
-    skip_any(buf, typtbl, t, 0);
-    let ptr_after = (*buf).ptr;
-    if ptr_after == ptr_before {
-        // this looks like a vec null bomb, or equivalent, where skip_any
-        // makes no progress. No point in calling it over and over again.
-        // (This is easier to detect this way than by analyzing the type table,
-        // where we’d have to chase single-field-records.)
-        return;
+
+    // Blob payloads are not necessarily aligned for `Skip`, hence the unaligned reads and writes
+
+    unsafe fn push<M: Memory>(&mut self, mem: &mut M, item: Skip) {
+        if self.len == self.cap {
+            self.grow(mem);
+        }
+        self.slots().add(self.len).write_unaligned(item);
+        self.len += 1;
+    }
+
+    unsafe fn pop(&mut self) -> Option<Skip> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.slots().add(self.len).read_unaligned())
     }
-    for _ in 1..count {
-        skip_any(buf, typtbl, t, 0);
+
+    unsafe fn grow<M: Memory>(&mut self, mem: &mut M) {
+        let new_cap = self.cap * 2;
+        let item_size = core::mem::size_of::<Skip>();
+        let blob = alloc_blob(mem, Bytes((new_cap * item_size) as u32));
+        let new_slots = blob.as_blob_mut().payload_addr() as *mut Skip;
+        core::ptr::copy_nonoverlapping(
+            self.slots() as *const u8,
+            new_slots as *mut u8,
+            self.len * item_size,
+        );
+        self.heap = new_slots;
+        self.cap = new_cap;
     }
 }
 
 // Assumes buf is the encoding of type t, and fast-forwards past that
 // Assumes all type references in the typtbl are already checked
 //
-// This is currently implemented recursively, but we could
-// do this in a loop (by maintaing a stack of the t arguments)
-#[no_mangle]
-unsafe extern "C" fn skip_any(buf: *mut Buf, typtbl: *mut *mut u8, t: i32, depth: i32) {
+// The values nested in t are skipped with an explicit work stack rather than recursively, so
+// that deeply nested values (e.g. long lists) don't overflow the Wasm stack
+#[ic_mem_fn]
+pub unsafe fn skip_any<M: Memory>(
+    mem: &mut M,
+    buf: *mut Buf,
+    typtbl: *mut *mut u8,
+    t: i32,
+    depth: i32,
+) {
+    let mut stack = SkipStack::new();
+    stack.push(mem, Skip::Value { t, depth });
+
+    while let Some(item) = stack.pop() {
+        match item {
+            Skip::Value { t, depth } => skip_value(mem, &mut stack, buf, typtbl, t, depth),
+            Skip::Fields {
+                t,
+                depth,
+                fields,
+                count,
+            } => {
+                let mut tb = Buf {
+                    ptr: fields,
+                    end: (*buf).end,
+                };
+                skip_leb128(&mut tb);
+                let it = sleb128_decode(&mut tb);
+                // This is just a quick check; we should be keeping
+                // track of all enclosing records to detect larger loops
+                if it == t {
+                    idl_trap_with("skip_any: recursive record");
+                }
+                if count > 1 {
+                    stack.push(
+                        mem,
+                        Skip::Fields {
+                            t,
+                            depth,
+                            fields: tb.ptr,
+                            count: count - 1,
+                        },
+                    );
+                }
+                stack.push(
+                    mem,
+                    Skip::Value {
+                        t: it,
+                        depth: depth + 1,
+                    },
+                );
+            }
+            Skip::FirstElem { t, count, start } => {
+                if (*buf).ptr == start {
+                    // this looks like a vec null bomb, or equivalent, where skip_any
+                    // makes no progress. No point in calling it over and over again.
+                    // (This is easier to detect this way than by analyzing the type table,
+                    // where we’d have to chase single-field-records.)
+                    continue;
+                }
+                if count > 0 {
+                    stack.push(mem, Skip::Elems { t, count });
+                }
+            }
+            Skip::Elems { t, count } => {
+                if count > 1 {
+                    stack.push(
+                        mem,
+                        Skip::Elems {
+                            t,
+                            count: count - 1,
+                        },
+                    );
+                }
+                stack.push(mem, Skip::Value { t, depth: 0 });
+            }
+        }
+    }
+}
+
+// Skips a value of type t, pushing the values nested in it to the work stack
+unsafe fn skip_value<M: Memory>(
+    mem: &mut M,
+    stack: &mut SkipStack,
+    buf: *mut Buf,
+    typtbl: *mut *mut u8,
+    t: i32,
+    depth: i32,
+) {
     if depth > 100 {
         idl_trap_with("skip_any: too deeply nested record");
     }
@@ -346,24 +497,36 @@ unsafe extern "C" fn skip_any(buf: *mut Buf, typtbl: *mut *mut u8, t: i32, depth
             IDL_CON_opt => {
                 let it = sleb128_decode(&mut tb);
                 if read_byte_tag(buf) != 0 {
-                    skip_any(buf, typtbl, it, 0);
+                    stack.push(mem, Skip::Value { t: it, depth: 0 });
                 }
             }
             IDL_CON_vec => {
                 let it = sleb128_decode(&mut tb);
                 let count = leb128_decode(buf);
-                skip_any_vec(buf, typtbl, it, count);
+                if count > 0 {
+                    stack.push(
+                        mem,
+                        Skip::FirstElem {
+                            t: it,
+                            count: count - 1,
+                            start: (*buf).ptr,
+                        },
+                    );
+                    stack.push(mem, Skip::Value { t: it, depth: 0 });
+                }
             }
             IDL_CON_record => {
-                for _ in 0..leb128_decode(&mut tb) {
-                    skip_leb128(&mut tb);
-                    let it = sleb128_decode(&mut tb);
-                    // This is just a quick check; we should be keeping
-                    // track of all enclosing records to detect larger loops
-                    if it == t {
-                        idl_trap_with("skip_any: recursive record");
-                    }
-                    skip_any(buf, typtbl, it, depth + 1);
+                let count = leb128_decode(&mut tb);
+                if count > 0 {
+                    stack.push(
+                        mem,
+                        Skip::Fields {
+                            t,
+                            depth,
+                            fields: tb.ptr,
+                            count,
+                        },
+                    );
                 }
             }
             IDL_CON_variant => {
@@ -378,7 +541,7 @@ unsafe extern "C" fn skip_any(buf: *mut Buf, typtbl: *mut *mut u8, t: i32, depth
                 }
                 skip_leb128(&mut tb);
                 let it = sleb128_decode(&mut tb);
-                skip_any(buf, typtbl, it, 0);
+                stack.push(mem, Skip::Value { t: it, depth: 0 });
             }
             IDL_CON_func => {
                 if read_byte_tag(buf) == 0 {
@@ -406,7 +569,7 @@ unsafe extern "C" fn skip_any(buf: *mut Buf, typtbl: *mut *mut u8, t: i32, depth
                 if tag == 0 {
                     buf.advance(8);
                     // this is the contents (not a reference)
-                    skip_any(buf, typtbl, it, 0);
+                    stack.push(mem, Skip::Value { t: it, depth: 0 });
                 } else {
                     buf.advance(4);
                 }
@@ -451,8 +614,9 @@ If the tag does not exist:
          or at the value past the record
   n:     the number of fields left, including the field pointed to by tb
 */
-#[no_mangle]
-unsafe extern "C" fn find_field(
+#[ic_mem_fn(ic_only)]
+unsafe fn find_field<M: Memory>(
+    mem: &mut M,
     tb: *mut Buf,
     buf: *mut Buf,
     typtbl: *mut *mut u8,
@@ -464,7 +628,7 @@ unsafe extern "C" fn find_field(
         let this_tag = leb128_decode(tb);
         if this_tag < tag {
             let it = sleb128_decode(tb);
-            skip_any(buf, typtbl, it, 0);
+            skip_any(mem, buf, typtbl, it, 0);
             *n -= 1;
         } else if tag == this_tag {
             *n -= 1;
@@ -479,12 +643,18 @@ unsafe extern "C" fn find_field(
     0
 }
 
-#[no_mangle]
-unsafe extern "C" fn skip_fields(tb: *mut Buf, buf: *mut Buf, typtbl: *mut *mut u8, n: *mut u8) {
+#[ic_mem_fn(ic_only)]
+unsafe fn skip_fields<M: Memory>(
+    mem: &mut M,
+    tb: *mut Buf,
+    buf: *mut Buf,
+    typtbl: *mut *mut u8,
+    n: *mut u8,
+) {
     while *n > 0 {
         skip_leb128(tb);
         let it = sleb128_decode(tb);
-        skip_any(buf, typtbl, it, 0);
+        skip_any(mem, buf, typtbl, it, 0);
         *n -= 1;
     }
 }
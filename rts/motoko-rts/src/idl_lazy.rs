@@ -0,0 +1,220 @@
+//! Lazy decoding of `opt` and `variant` values in Candid messages with a single argument.
+//!
+//! Decoding a value of a deeply optional type like `opt opt opt record { ... }` allocates a box
+//! for every `?` level, and the whole payload, even when the program only checks whether the value
+//! is null. A lazy value is instead an unparsed cursor: the position of a value in the message and
+//! its type, computed by `parse_idl_header`. `opt` and `variant` values can be inspected (null or
+//! not, the variant case) and their payloads stepped into without allocating the payloads, and a
+//! value is only materialized when forced, as a Candid message of its own with a single argument of
+//! the value's type (like the elements of a vec stream, see `idl_stream`), to be decoded by the
+//! generated code for the type.
+//!
+//! The state of a lazy value is kept in a blob, laid out like the vec stream state, with type
+//! table entries pointing into the message. The GC does not visit blob contents, so the state does
+//! not keep the message alive, and is not updated when the GC moves the message: the message is
+//! passed to every call, and must be kept alive by the caller (the `candidLazy` object in the
+//! prelude holds it). The type table entries are rebased when the message has moved since the last
+//! call. Stepping into a payload allocates a new state, the message is never copied until forced.
+//!
+//! The argument is checked with `skip_any` when the lazy value is created, so invalid messages are
+//! rejected upfront, as with eager decoding.
+
+use crate::buf::{read_byte, Buf};
+use crate::idl::{skip_any, IDL_CON_opt, IDL_CON_variant};
+use crate::idl_stream::{message_buf, parse_single_arg_header, rebase_typtbl, value_message};
+use crate::idl_trap_with;
+use crate::leb128::{leb128_decode, sleb128_decode};
+use crate::memory::{alloc_blob, Memory};
+use crate::types::{size_of, Bytes, Value};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Lazy value state, in the payload of a blob, followed by `typtbl_size` type table entries
+#[repr(C)]
+struct LazyValue {
+    /// Payload address of the message when the type table entries were last updated
+    base: *mut u8,
+    /// Offset of the end of the type table in the message
+    types_end: u32,
+    /// Type of the value
+    t: i32,
+    /// Offset of the value in the message
+    cursor: u32,
+    typtbl_size: u32,
+}
+
+impl LazyValue {
+    unsafe fn typtbl(self: *mut Self) -> *mut *mut u8 {
+        self.add(1) as *mut *mut u8
+    }
+}
+
+unsafe fn alloc_lazy_value<M: Memory>(mem: &mut M, typtbl_size: u32) -> Value {
+    alloc_blob(
+        mem,
+        size_of::<LazyValue>().to_bytes()
+            + Bytes(typtbl_size * core::mem::size_of::<usize>() as u32),
+    )
+}
+
+/// State of a lazy value, with the type table entries updated for the current location of the
+/// message
+unsafe fn lazy_value(msg: Value, state: Value) -> *mut LazyValue {
+    let lazy = state.as_blob_mut().payload_addr() as *mut LazyValue;
+    let base = msg.as_blob_mut().payload_addr();
+    if (*lazy).base != base {
+        rebase_typtbl(lazy.typtbl(), (*lazy).typtbl_size, (*lazy).base, base);
+        (*lazy).base = base;
+    }
+    lazy
+}
+
+/// Buffer at the value
+unsafe fn value_buf(msg: Value, lazy: *mut LazyValue) -> Buf {
+    let mut buf = message_buf(msg);
+    buf.ptr = buf.ptr.add((*lazy).cursor as usize);
+    buf
+}
+
+/// Buffer at the type table entry of the value, after the type constructor. Traps when the value
+/// is not of the type constructor `con`.
+unsafe fn type_buf(lazy: *mut LazyValue, buf_end: *mut u8, con: i32, error: &str) -> Buf {
+    let t = (*lazy).t;
+    if t < 0 {
+        idl_trap_with(error);
+    }
+    let mut tb = Buf {
+        ptr: *lazy.typtbl().add(t as usize),
+        end: buf_end,
+    };
+    if sleb128_decode(&mut tb) != con {
+        idl_trap_with(error);
+    }
+    tb
+}
+
+/// Lazy value of type `t` at `value`, in the message of the lazy value `state`
+unsafe fn step_into<M: Memory>(
+    mem: &mut M,
+    msg: Value,
+    state: Value,
+    t: i32,
+    value: *mut u8,
+) -> Value {
+    let lazy = lazy_value(msg, state);
+    let typtbl_size = (*lazy).typtbl_size;
+    let new_state = alloc_lazy_value(mem, typtbl_size);
+    let new_lazy = new_state.as_blob_mut().payload_addr() as *mut LazyValue;
+    (*new_lazy).base = (*lazy).base;
+    (*new_lazy).types_end = (*lazy).types_end;
+    (*new_lazy).t = t;
+    (*new_lazy).cursor = value.offset_from((*lazy).base) as u32;
+    (*new_lazy).typtbl_size = typtbl_size;
+    for i in 0..typtbl_size as usize {
+        *new_lazy.typtbl().add(i) = *lazy.typtbl().add(i);
+    }
+
+    new_state
+}
+
+/// Lazy value of the argument of a message
+#[ic_mem_fn]
+pub unsafe fn idl_lazy<M: Memory>(mem: &mut M, msg: Value) -> Value {
+    let mut buf = message_buf(msg);
+    let base = buf.ptr;
+
+    let mut typtbl: *mut *mut u8 = core::ptr::null_mut();
+    let mut typtbl_size: u32 = 0;
+    let (types_end, t) = match parse_single_arg_header(mem, &mut buf, &mut typtbl, &mut typtbl_size)
+    {
+        Some(arg) => arg,
+        None => idl_trap_with("lazy value: expected a single argument"),
+    };
+    let cursor = buf.ptr.offset_from(base) as u32;
+
+    skip_any(mem, &mut buf, typtbl, t, 0);
+    if buf.ptr != buf.end {
+        idl_trap_with("lazy value: left-over bytes");
+    }
+
+    let state = alloc_lazy_value(mem, typtbl_size);
+    let lazy = state.as_blob_mut().payload_addr() as *mut LazyValue;
+    (*lazy).base = base;
+    (*lazy).types_end = types_end;
+    (*lazy).t = t;
+    (*lazy).cursor = cursor;
+    (*lazy).typtbl_size = typtbl_size;
+    for i in 0..typtbl_size as usize {
+        *lazy.typtbl().add(i) = *typtbl.add(i);
+    }
+
+    state
+}
+
+/// Whether an `opt` value is null
+#[no_mangle]
+pub unsafe extern "C" fn idl_lazy_is_null(msg: Value, state: Value) -> bool {
+    let lazy = lazy_value(msg, state);
+    let mut buf = value_buf(msg, lazy);
+    type_buf(lazy, buf.end, IDL_CON_opt, "lazy value: not an opt");
+    read_byte(&mut buf) == 0
+}
+
+/// Lazy value of the payload of a non-null `opt` value
+#[ic_mem_fn]
+pub unsafe fn idl_lazy_some<M: Memory>(mem: &mut M, msg: Value, state: Value) -> Value {
+    let lazy = lazy_value(msg, state);
+    let mut buf = value_buf(msg, lazy);
+    let mut tb = type_buf(lazy, buf.end, IDL_CON_opt, "lazy value: not an opt");
+    if read_byte(&mut buf) == 0 {
+        idl_trap_with("lazy value: opt is null");
+    }
+    let t = sleb128_decode(&mut tb);
+    step_into(mem, msg, state, t, buf.ptr)
+}
+
+/// Field id (hash of the label) of the case of a `variant` value
+#[no_mangle]
+pub unsafe extern "C" fn idl_lazy_variant_tag(msg: Value, state: Value) -> u32 {
+    let (id, _, _) = variant_case(msg, state);
+    id
+}
+
+/// Lazy value of the payload of a `variant` value
+#[ic_mem_fn]
+pub unsafe fn idl_lazy_variant_value<M: Memory>(mem: &mut M, msg: Value, state: Value) -> Value {
+    let (_, t, value) = variant_case(msg, state);
+    step_into(mem, msg, state, t, value)
+}
+
+/// Field id and type of the case of a `variant` value, and the address of its payload
+unsafe fn variant_case(msg: Value, state: Value) -> (u32, i32, *mut u8) {
+    let lazy = lazy_value(msg, state);
+    let mut buf = value_buf(msg, lazy);
+    let mut tb = type_buf(lazy, buf.end, IDL_CON_variant, "lazy value: not a variant");
+
+    // Checked by `skip_any` when the lazy value was created
+    let n = leb128_decode(&mut tb);
+    let idx = leb128_decode(&mut buf);
+    debug_assert!(idx < n);
+
+    for _ in 0..idx {
+        leb128_decode(&mut tb);
+        sleb128_decode(&mut tb);
+    }
+    let id = leb128_decode(&mut tb);
+    let t = sleb128_decode(&mut tb);
+
+    (id, t, buf.ptr)
+}
+
+/// Materialize the value, as a message with a single argument of the value's type
+#[ic_mem_fn]
+pub unsafe fn idl_lazy_force<M: Memory>(mem: &mut M, msg: Value, state: Value) -> Value {
+    let lazy = lazy_value(msg, state);
+    let mut buf = value_buf(msg, lazy);
+    let value = buf.ptr;
+    skip_any(mem, &mut buf, lazy.typtbl(), (*lazy).t, 0);
+    let len = buf.ptr.offset_from(value) as u32;
+    value_message(mem, (*lazy).base, (*lazy).types_end, (*lazy).t, value, len)
+}
@@ -0,0 +1,232 @@
+//! Streaming decoding of Candid messages with a single `vec` argument.
+//!
+//! Decoding a large `vec` allocates the whole array before the program can look at it. A vec
+//! stream instead returns the elements one at a time, each as a Candid message of its own (the
+//! type table of the original message, followed by a single argument of the element type), to be
+//! decoded by the generated code for the element type. Per element, only the element message is
+//! allocated, so a program can fold over a vec without holding all of its elements.
+//!
+//! The stream state is kept in a blob: the position in the message, the number of remaining
+//! elements, and the type table computed by `parse_idl_header`, which `skip_any` uses to find the
+//! end of an element. The type table entries point into the message. The state does not point to
+//! the message, as the GC does not visit blob contents, so the message is passed to every call, and
+//! the entries are rebased when the GC has moved the message since the last call.
+//!
+//! Elements can refer to any type in the type table, including recursive types, as the element
+//! messages repeat the whole type table. Standard Candid has no references between values, and
+//! `parse_idl_header` rejects the extended (stable variable) format with aliases.
+
+use crate::buf::Buf;
+use crate::idl::{parse_idl_header, skip_any, IDL_CON_vec};
+use crate::idl_trap_with;
+use crate::leb128::{leb128_decode, sleb128_decode, sleb128_encode};
+use crate::mem_utils::memcpy_bytes;
+use crate::memory::{alloc_blob, Memory};
+use crate::types::{size_of, Bytes, Value};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Stream state, in the payload of a blob, followed by `typtbl_size` type table entries
+#[repr(C)]
+struct VecStream {
+    /// Payload address of the message when the type table entries were last updated
+    base: *mut u8,
+    /// Offset of the end of the type table in the message
+    types_end: u32,
+    /// Type of the elements
+    elem_type: i32,
+    /// Offset of the next element in the message
+    cursor: u32,
+    /// Number of elements not returned yet
+    remaining: u32,
+    typtbl_size: u32,
+}
+
+impl VecStream {
+    unsafe fn typtbl(self: *mut Self) -> *mut *mut u8 {
+        self.add(1) as *mut *mut u8
+    }
+}
+
+pub(crate) unsafe fn message_buf(msg: Value) -> Buf {
+    let blob = msg.as_blob_mut();
+    let ptr = blob.payload_addr();
+    Buf {
+        ptr,
+        end: ptr.add(blob.len().as_usize()),
+    }
+}
+
+/// Parse the header of a message, leaving `buf` at the argument values. Returns the offset of the
+/// end of the type table in the message and the type of the argument, or `None` when the message
+/// does not have a single argument.
+pub(crate) unsafe fn parse_single_arg_header<M: Memory>(
+    mem: &mut M,
+    buf: &mut Buf,
+    typtbl: &mut *mut *mut u8,
+    typtbl_size: &mut u32,
+) -> Option<(u32, i32)> {
+    let base = buf.ptr;
+    let mut main_types: *mut u8 = core::ptr::null_mut();
+    parse_idl_header(mem, false, buf, typtbl, typtbl_size, &mut main_types);
+
+    let mut main_types_buf = Buf {
+        ptr: main_types,
+        end: buf.end,
+    };
+    if leb128_decode(&mut main_types_buf) != 1 {
+        return None;
+    }
+    let t = sleb128_decode(&mut main_types_buf);
+
+    Some((main_types.offset_from(base) as u32, t))
+}
+
+/// Update type table entries pointing into a message at `old_base` to point into the same message
+/// at `new_base`, after the GC moved the message
+pub(crate) unsafe fn rebase_typtbl(
+    typtbl: *mut *mut u8,
+    typtbl_size: u32,
+    old_base: *mut u8,
+    new_base: *mut u8,
+) {
+    for i in 0..typtbl_size as usize {
+        let entry = typtbl.add(i);
+        *entry = new_base.offset((*entry).offset_from(old_base));
+    }
+}
+
+/// Allocate a message with a single argument of type `t`: the type table of the message at `base`
+/// (`types_end` bytes), followed by `1` and `t` as the list of argument types, and the `len` bytes
+/// of the value at `value`
+pub(crate) unsafe fn value_message<M: Memory>(
+    mem: &mut M,
+    base: *mut u8,
+    types_end: u32,
+    t: i32,
+    value: *mut u8,
+    len: u32,
+) -> Value {
+    let mut arg_types = [0u8; 6];
+    arg_types[0] = 1;
+    sleb128_encode(t, arg_types.as_mut_ptr().add(1));
+    let arg_types_len = 1
+        + arg_types[1..]
+            .iter()
+            .take_while(|b| **b & 0x80 != 0)
+            .count()
+        + 1;
+
+    let msg = alloc_blob(mem, Bytes(types_end + arg_types_len as u32 + len));
+    let dst = msg.as_blob_mut().payload_addr() as usize;
+    memcpy_bytes(dst, base as usize, Bytes(types_end));
+    memcpy_bytes(
+        dst + types_end as usize,
+        arg_types.as_ptr() as usize,
+        Bytes(arg_types_len as u32),
+    );
+    memcpy_bytes(
+        dst + types_end as usize + arg_types_len,
+        value as usize,
+        Bytes(len),
+    );
+
+    msg
+}
+
+/// Start streaming the elements of the `vec` argument of a message. Returns the stream state.
+#[ic_mem_fn]
+pub unsafe fn idl_vec_stream<M: Memory>(mem: &mut M, msg: Value) -> Value {
+    let mut buf = message_buf(msg);
+    let base = buf.ptr;
+
+    let mut typtbl: *mut *mut u8 = core::ptr::null_mut();
+    let mut typtbl_size: u32 = 0;
+    let (types_end, t) = match parse_single_arg_header(mem, &mut buf, &mut typtbl, &mut typtbl_size)
+    {
+        Some(arg) => arg,
+        None => idl_trap_with("vec stream: expected a single argument"),
+    };
+    if t < 0 {
+        idl_trap_with("vec stream: argument is not a vec");
+    }
+    let mut tb = Buf {
+        ptr: *typtbl.add(t as usize),
+        end: buf.end,
+    };
+    if sleb128_decode(&mut tb) != IDL_CON_vec {
+        idl_trap_with("vec stream: argument is not a vec");
+    }
+    let elem_type = sleb128_decode(&mut tb);
+
+    let count = leb128_decode(&mut buf);
+
+    let state = alloc_blob(
+        mem,
+        size_of::<VecStream>().to_bytes()
+            + Bytes(typtbl_size * core::mem::size_of::<usize>() as u32),
+    );
+    let stream = state.as_blob_mut().payload_addr() as *mut VecStream;
+    (*stream).base = base;
+    (*stream).types_end = types_end;
+    (*stream).elem_type = elem_type;
+    (*stream).cursor = buf.ptr.offset_from(base) as u32;
+    (*stream).remaining = count;
+    (*stream).typtbl_size = typtbl_size;
+    for i in 0..typtbl_size as usize {
+        *stream.typtbl().add(i) = *typtbl.add(i);
+    }
+
+    check_end(stream, &buf);
+
+    state
+}
+
+/// Number of elements not returned yet
+#[no_mangle]
+pub unsafe extern "C" fn idl_vec_stream_remaining(state: Value) -> u32 {
+    (*(state.as_blob_mut().payload_addr() as *mut VecStream)).remaining
+}
+
+/// Return the next element, as a message with a single argument of the element type
+#[ic_mem_fn]
+pub unsafe fn idl_vec_stream_next<M: Memory>(mem: &mut M, msg: Value, state: Value) -> Value {
+    let stream = state.as_blob_mut().payload_addr() as *mut VecStream;
+    if (*stream).remaining == 0 {
+        idl_trap_with("vec stream: no more elements");
+    }
+
+    let mut buf = message_buf(msg);
+    let base = buf.ptr;
+    if (*stream).base != base {
+        rebase_typtbl(stream.typtbl(), (*stream).typtbl_size, (*stream).base, base);
+        (*stream).base = base;
+    }
+
+    let elem_start = base.add((*stream).cursor as usize);
+    buf.ptr = elem_start;
+    skip_any(mem, &mut buf, stream.typtbl(), (*stream).elem_type, 0);
+    let elem_len = buf.ptr.offset_from(elem_start) as u32;
+
+    let elem = value_message(
+        mem,
+        base,
+        (*stream).types_end,
+        (*stream).elem_type,
+        elem_start,
+        elem_len,
+    );
+
+    (*stream).cursor += elem_len;
+    (*stream).remaining -= 1;
+    check_end(stream, &buf);
+
+    elem
+}
+
+/// Once all elements are returned, the message must be fully consumed
+unsafe fn check_end(stream: *mut VecStream, buf: &Buf) {
+    if (*stream).remaining == 0 && buf.ptr != buf.end {
+        idl_trap_with("vec stream: left-over bytes");
+    }
+}
@@ -0,0 +1,41 @@
+//! Instruction budgeting for long-running loops.
+//!
+//! The IC traps a message that executes more instructions than the per-message limit. Code doing
+//! a lot of work in one message checks `remaining_instructions` before each chunk of work, and
+//! yields (e.g. awaits a self-call) to continue in a new message once the soft budget is used up.
+//! The budget is set with `set_instruction_budget`, below the hard limit to leave room for the
+//! work done between the checks. It is not preserved across upgrades.
+//!
+//! The counter is `ic0.performance_counter(0)`, the number of instructions executed in the current
+//! message. Outside of the IC it is the GC's counter (`gc::INSTRUCTION_COUNTER`), which is 0
+//! unless a test replaces it, so the values are deterministic.
+
+use crate::gc::instruction_counter;
+
+/// Default soft budget, a quarter of the limit for update messages (20 billion instructions)
+pub const DEFAULT_INSTRUCTION_BUDGET: u64 = 5_000_000_000;
+
+static mut INSTRUCTION_BUDGET: u64 = DEFAULT_INSTRUCTION_BUDGET;
+
+/// Number of instructions executed in the current message
+#[no_mangle]
+pub unsafe extern "C" fn instruction_count() -> u64 {
+    instruction_counter()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn instruction_budget() -> u64 {
+    INSTRUCTION_BUDGET
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn set_instruction_budget(budget: u64) {
+    INSTRUCTION_BUDGET = budget;
+}
+
+/// Number of instructions left in the soft budget in the current message, 0 when the budget is
+/// used up
+#[no_mangle]
+pub unsafe extern "C" fn remaining_instructions() -> u64 {
+    INSTRUCTION_BUDGET.saturating_sub(instruction_counter())
+}
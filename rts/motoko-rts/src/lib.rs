@@ -9,30 +9,58 @@ mod print;
 #[cfg(debug_assertions)]
 pub mod debug;
 
+#[cfg(feature = "alloc-profiling")]
+pub mod alloc_profile;
+pub mod array;
+pub mod array_buffer;
+pub mod assert_level;
 pub mod bigint;
+pub mod blob_builder;
+pub mod blob_encoding;
 #[cfg(feature = "ic")]
 mod blob_iter;
+pub mod blob_map;
 pub mod buf;
 mod char;
 pub mod constants;
 pub mod continuation_table;
-#[cfg(feature = "ic")]
-mod float;
+pub mod controller;
+pub mod cycles;
+pub mod finalizer;
+pub mod float;
 pub mod gc;
-#[cfg(feature = "ic")]
+pub mod hash;
 mod idl;
+pub mod idl_lazy;
+pub mod idl_stream;
+pub mod instructions;
 pub mod leb128;
 mod mem_utils;
 pub mod memory;
+pub mod memory_pressure;
+pub mod on_trap;
+pub mod pin;
+pub mod pointer_width;
 pub mod principal_id;
+pub mod reply_splice;
+pub mod rng;
+pub mod roots;
+pub mod safepoint;
+pub mod self_test;
+pub mod show_cycle;
+pub mod stabilization;
+pub mod stable_region;
+pub mod stable_sharing;
 mod static_checks;
 pub mod stream;
 pub mod text;
 pub mod text_iter;
 mod tommath_bindings;
 pub mod types;
+pub mod unicode;
 pub mod utf8;
-mod visitor;
+pub mod visitor;
+pub mod weak_ref;
 
 use types::Bytes;
 
@@ -91,6 +119,21 @@ pub(crate) unsafe fn rts_trap_with(msg: &str) -> ! {
     trap_with_prefix("RTS error: ", msg)
 }
 
+/// Trap with the bytes of a blob or text as the message, e.g. Candid-encoded error
+/// details. Unlike `rts_trap_with`, the message is not prefixed, not cut off and not necessarily
+/// UTF-8. Texts can be concatenations of pieces that are not contiguous, so they are copied to a
+/// blob first.
+#[ic_mem_fn]
+pub unsafe fn trap_with_blob<M: memory::Memory>(mem: &mut M, blob: types::Value) -> ! {
+    let blob = if blob.tag() == types::TAG_CONCAT {
+        text::blob_of_text(mem, blob)
+    } else {
+        blob
+    };
+    let blob = blob.as_blob();
+    rts_trap(blob.payload_const(), blob.len())
+}
+
 #[cfg(feature = "ic")]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
@@ -11,3 +11,13 @@ pub(crate) unsafe fn memcpy_bytes(to: usize, from: usize, n: Bytes<u32>) {
 pub(crate) unsafe fn memzero(to: usize, n: Words<u32>) {
     libc::memset(to as *mut _, 0, n.to_bytes().as_usize());
 }
+
+/// Zero heap space that no longer holds objects (reclaimed by GC, or filler objects), when the
+/// `zero-on-free` feature is enabled, so that data of dead objects cannot be read from the heap
+/// later
+pub(crate) unsafe fn zero_free_space(from: usize, to: usize) {
+    if cfg!(feature = "zero-on-free") {
+        debug_assert!(from <= to);
+        libc::memset(from as *mut _, 0, to - from);
+    }
+}
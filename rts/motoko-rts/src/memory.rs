@@ -1,7 +1,7 @@
 #[cfg(feature = "ic")]
 pub mod ic;
 
-use crate::constants::WASM_HEAP_SIZE;
+use crate::constants::{WASM_HEAP_SIZE, WASM_PAGE_SIZE};
 use crate::rts_trap_with;
 use crate::types::*;
 
@@ -29,6 +29,12 @@ pub trait Memory {
     unsafe fn alloc_words(&mut self, n: Words<u32>) -> Value;
 }
 
+/// Number of Wasm pages needed to allocate the memory up to, but excluding, the given address
+pub fn wasm_pages_needed(ptr: u64) -> usize {
+    let page_size = u64::from(WASM_PAGE_SIZE.as_u32());
+    ((ptr + page_size - 1) / page_size) as usize
+}
+
 /// Helper for allocating blobs
 #[ic_mem_fn]
 pub unsafe fn alloc_blob<M: Memory>(mem: &mut M, size: Bytes<u32>) -> Value {
@@ -1,14 +1,16 @@
 // This module is only enabled when compiling the RTS for IC or WASI.
 
-use super::Memory;
+use super::{wasm_pages_needed, Memory};
 use crate::constants::WASM_PAGE_SIZE;
+use crate::gc::high_water_mark::HIGH_WATER_MARK;
 use crate::rts_trap_with;
 use crate::types::*;
 
 use core::arch::wasm32;
+use core::ptr::{addr_of, addr_of_mut};
 
-/// Maximum live data retained in a GC.
-pub(crate) static mut MAX_LIVE: Bytes<u32> = Bytes(0);
+/// Live data retained in the last GC.
+pub(crate) static mut LIVE_SIZE: Bytes<u32> = Bytes(0);
 
 /// Amount of garbage collected so far.
 pub(crate) static mut RECLAIMED: Bytes<u64> = Bytes(0);
@@ -40,12 +42,29 @@ unsafe extern "C" fn init(align: bool) {
     } else {
         get_heap_base()
     };
+    crate::types::check_heap_base(HP);
     LAST_HP = HP;
+
+    // Repair the heap if a GC was interrupted, before the mutator runs
+    if crate::gc::mark_compact::recovery::recover_interrupted_gc(|hp| HP = hp) {
+        LAST_HP = HP;
+    }
 }
 
 #[no_mangle]
 unsafe extern "C" fn get_max_live_size() -> Bytes<u32> {
-    MAX_LIVE
+    (*addr_of!(HIGH_WATER_MARK)).max_live_size()
+}
+
+/// Largest heap size reached, the heap size before a collection is done
+#[no_mangle]
+unsafe extern "C" fn get_max_heap_size() -> Bytes<u32> {
+    (*addr_of!(HIGH_WATER_MARK)).max_heap_size(get_aligned_heap_base() as usize)
+}
+
+#[no_mangle]
+unsafe extern "C" fn get_live_size() -> Bytes<u32> {
+    LIVE_SIZE
 }
 
 #[no_mangle]
@@ -63,6 +82,29 @@ unsafe extern "C" fn get_heap_size() -> Bytes<u32> {
     Bytes(HP - get_aligned_heap_base())
 }
 
+/// Reserve `bytes` of dynamic heap: allocate the Wasm pages for a heap of that size now, and don't
+/// schedule GC while the heap is within the reservation (see `gc::scheduler`). Meant to be called
+/// in `init` by canisters with a known working set size. 0 removes the reservation; pages already
+/// allocated are kept.
+#[no_mangle]
+unsafe extern "C" fn reserve_heap(bytes: u32) {
+    grow_memory(u64::from(get_heap_base()) + u64::from(bytes));
+    crate::gc::scheduler::SCHEDULER.reservation = Bytes(bytes);
+}
+
+/// `note_live_size` callback of the GCs
+pub(crate) unsafe fn note_live_size(live_size: Bytes<u32>) {
+    LIVE_SIZE = live_size;
+    (*addr_of_mut!(HIGH_WATER_MARK)).note_live_size(live_size);
+    crate::gc::scheduler::SCHEDULER.note_gc(live_size);
+    crate::gc::live_history::record_live_size(live_size);
+}
+
+/// `note_reclaimed` callback of the GCs
+pub(crate) unsafe fn note_reclaimed(reclaimed: Bytes<u32>) {
+    RECLAIMED += Bytes(u64::from(reclaimed.as_u32()));
+}
+
 /// Provides a `Memory` implementation, to be used in functions compiled for IC or WASI. The
 /// `Memory` implementation allocates in Wasm heap with Wasm `memory.grow` instruction.
 pub struct IcMemory;
@@ -79,22 +121,26 @@ impl Memory for IcMemory {
         let old_hp = u64::from(HP);
         let new_hp = old_hp + delta;
 
-        // Grow memory if needed
-        grow_memory(new_hp);
+        // Fast path: the new object fits in the allocated Wasm pages, which is the case for most
+        // allocations. Otherwise grow the memory in the cold path.
+        if new_hp > (wasm32::memory_size(0) as u64) * u64::from(WASM_PAGE_SIZE.as_u32()) {
+            grow_memory(new_hp);
+        }
 
         debug_assert!(new_hp <= u64::from(core::u32::MAX));
         HP = new_hp as u32;
+        (*addr_of_mut!(HIGH_WATER_MARK)).note_alloc(HP as usize);
 
         Value::from_ptr(old_hp as usize)
     }
 }
 
 /// Page allocation. Ensures that the memory up to, but excluding, the given pointer is allocated.
+#[cold]
 #[inline(never)]
 unsafe fn grow_memory(ptr: u64) {
     debug_assert!(ptr <= 2 * u64::from(core::u32::MAX));
-    let page_size = u64::from(WASM_PAGE_SIZE.as_u32());
-    let total_pages_needed = ((ptr + page_size - 1) / page_size) as usize;
+    let total_pages_needed = wasm_pages_needed(ptr);
     let current_pages = wasm32::memory_size(0);
     if total_pages_needed > current_pages {
         if wasm32::memory_grow(0, total_pages_needed - current_pages) == core::usize::MAX {
@@ -0,0 +1,81 @@
+//! Memory pressure handler, registered with `register_memory_pressure_handler`.
+//!
+//! Programs with soft caches can register a handler closure that drops cache entries. When the
+//! dynamic heap has crossed the high-water mark (`set_memory_pressure_high_water_mark`) since the
+//! last time it was below, the GC schedulers call the handler before scheduling a GC, and then
+//! collect the whole heap, so the entries dropped by the handler are reclaimed right away. The
+//! handler is called once per crossing: while the heap stays above the high-water mark it is not
+//! called again.
+//!
+//! The handler runs at the end of a message, in the GC scheduler, so it must not collect garbage
+//! itself: scheduler calls while the handler runs return without collecting, and a collection
+//! requested with `gc_request_collection` is the one done after the handler returns.
+//!
+//! The handler is called with `call_moc` (generated by `moc`), which calls a closure without
+//! arguments.
+
+use crate::gc::mark_compact::incremental::incremental_gc_write_barrier;
+use crate::roots::register_root;
+use crate::types::{Bytes, Value};
+
+use core::ptr::addr_of_mut;
+
+/// No handler registered
+pub const NO_HANDLER: Value = Value::from_scalar(0);
+
+/// Default value of the high-water mark (2 GiB)
+pub const DEFAULT_HIGH_WATER_MARK: Bytes<u32> = Bytes(2 * 1024 * 1024 * 1024);
+
+static mut HANDLER: Value = NO_HANDLER;
+
+static mut HIGH_WATER_MARK: Bytes<u32> = DEFAULT_HIGH_WATER_MARK;
+
+/// Whether the heap was below the high-water mark since the handler was last called
+static mut ARMED: bool = true;
+
+static mut HANDLER_RUNNING: bool = false;
+
+/// Register the memory pressure handler closure, replacing the previous one. `NO_HANDLER` removes
+/// the handler.
+#[no_mangle]
+pub unsafe extern "C" fn register_memory_pressure_handler(handler: Value) {
+    let location = addr_of_mut!(HANDLER);
+    register_root(location);
+    incremental_gc_write_barrier(location);
+    *location = handler;
+}
+
+/// Set the dynamic heap size, in bytes, at which the memory pressure handler is called
+#[no_mangle]
+pub unsafe extern "C" fn set_memory_pressure_high_water_mark(bytes: u32) {
+    HIGH_WATER_MARK = Bytes(bytes);
+}
+
+/// Whether the memory pressure handler is running
+pub unsafe fn memory_pressure_handler_running() -> bool {
+    HANDLER_RUNNING
+}
+
+/// Called by the GC schedulers with the dynamic heap size. When the heap has crossed the
+/// high-water mark, calls `call` with the handler and returns `true`. The heap should then be
+/// collected.
+pub unsafe fn relieve_memory_pressure<Call: FnOnce(Value)>(
+    heap_size: Bytes<u32>,
+    call: Call,
+) -> bool {
+    if heap_size < HIGH_WATER_MARK {
+        ARMED = true;
+        return false;
+    }
+
+    if !ARMED || HANDLER == NO_HANDLER || HANDLER_RUNNING {
+        return false;
+    }
+
+    ARMED = false;
+    HANDLER_RUNNING = true;
+    call(HANDLER);
+    HANDLER_RUNNING = false;
+
+    true
+}
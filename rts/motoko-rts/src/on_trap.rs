@@ -0,0 +1,71 @@
+//! Handlers registered with `Prim.onTrap`, run when an update method traps after an `await`.
+//!
+//! When a message traps, the IC rolls back all state changes of the message, so state changed
+//! before the trap in the same message needs no cleanup. But an update method that awaits runs as
+//! several messages, and the state changes of the messages before the trapping one are committed.
+//! The handlers restore the invariants broken by those changes.
+//!
+//! The handlers of the running message are kept in `HANDLERS`, as a list of two-element arrays
+//! `[closure, next]`, with scalar 0 as the end of the list. The generated code
+//!
+//!  - clears the list at the start and at the end of every update message,
+//!  - stores the list in the continuation table entry of every call (next to the reply and reject
+//!    closures, see `continuation_table`), and restores it from the entry in the reply and reject
+//!    callbacks, so handlers registered before an `await` stay registered after it,
+//!  - in the cleanup callback (`ic0.call_on_cleanup`), which the IC runs when the reply or reject
+//!    callback traps, restores the list from the entry and calls the handlers with
+//!    `on_trap_next`, the last registered first.
+//!
+//! The state changes of the cleanup callback are committed, unless it traps itself. The handlers
+//! registered in the trapping message are rolled back with the rest of its state changes.
+
+use crate::gc::mark_compact::incremental::incremental_gc_write_barrier;
+use crate::memory::{alloc_array, Memory};
+use crate::roots::register_root;
+use crate::types::Value;
+
+use core::ptr::addr_of_mut;
+
+use motoko_rts_macros::ic_mem_fn;
+
+static mut HANDLERS: Value = Value::from_scalar(0);
+
+unsafe fn set_handlers(handlers: Value) {
+    let location = addr_of_mut!(HANDLERS);
+    register_root(location);
+    incremental_gc_write_barrier(location);
+    *location = handlers;
+}
+
+/// Register a handler closure for the running message
+#[ic_mem_fn]
+pub unsafe fn on_trap_register<M: Memory>(mem: &mut M, closure: Value) {
+    let cell = alloc_array(mem, 2);
+    cell.as_array().set(0, closure);
+    cell.as_array().set(1, HANDLERS);
+    set_handlers(cell);
+}
+
+/// Handlers of the running message, to be stored in a continuation table entry
+#[no_mangle]
+pub unsafe extern "C" fn on_trap_handlers() -> Value {
+    HANDLERS
+}
+
+/// Replace the handlers of the running message. Scalar 0 removes all handlers.
+#[no_mangle]
+pub unsafe extern "C" fn on_trap_set_handlers(handlers: Value) {
+    set_handlers(handlers);
+}
+
+/// Remove the last registered handler and return it, or scalar 0 when there are no handlers
+#[no_mangle]
+pub unsafe extern "C" fn on_trap_next() -> Value {
+    let handlers = HANDLERS;
+    if handlers.is_scalar() {
+        return handlers;
+    }
+
+    set_handlers(handlers.as_array().get(1));
+    handlers.as_array().get(0)
+}
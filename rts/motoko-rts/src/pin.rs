@@ -0,0 +1,137 @@
+//! Pinned objects. `pin` adds an object to the pin table and returns a handle, `unpin` removes it.
+//! The mark-compact GC (including the generational and incremental modes) does not move pinned
+//! objects, so their addresses can be passed to system calls and stay valid across GCs. Pinned
+//! objects are live until unpinned, and their fields are traced as usual.
+//!
+//! The pin table is a blob of pinned object addresses (0 for free slots), indexed by handles. The
+//! table itself is never moved either, so the GC can read it while compacting.
+//!
+//! The copying GC moves all objects, so objects cannot be pinned when the copying GC is used.
+
+use crate::memory::{alloc_blob, Memory};
+use crate::rts_trap_with;
+use crate::types::{Bytes, Value};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Value of `PIN_TABLE` before the first pin
+const NO_PIN_TABLE: Value = Value::from_scalar(0);
+
+/// Number of slots in the first pin table. Table size is doubled when it's full.
+const INITIAL_PIN_TABLE_LEN: u32 = 8;
+
+/// Pin table, a blob of `u32` object addresses
+static mut PIN_TABLE: Value = NO_PIN_TABLE;
+
+/// Number of pinned objects
+static mut N_PINNED: u32 = 0;
+
+/// Pin an object, returns a handle to unpin it. An object can be pinned multiple times, it's
+/// unpinned when all handles are unpinned.
+#[ic_mem_fn]
+pub unsafe fn pin<M: Memory>(mem: &mut M, obj: Value) -> u32 {
+    debug_assert!(obj.is_ptr());
+
+    if crate::gc::copying_gc_selected() {
+        rts_trap_with("pin: copying GC does not support pinned objects");
+    }
+
+    let len = pin_table_len();
+    let handle = (0..len)
+        .find(|handle| *pin_table_slot(*handle) == 0)
+        .unwrap_or_else(|| {
+            grow_pin_table(mem);
+            len
+        });
+
+    *pin_table_slot(handle) = obj.get_ptr() as u32;
+    N_PINNED += 1;
+
+    handle
+}
+
+/// Unpin an object pinned with `pin`
+#[no_mangle]
+pub unsafe extern "C" fn unpin(handle: u32) {
+    if handle >= pin_table_len() || *pin_table_slot(handle) == 0 {
+        rts_trap_with("unpin: invalid handle");
+    }
+
+    *pin_table_slot(handle) = 0;
+    N_PINNED -= 1;
+}
+
+/// Returns whether there are pinned objects
+pub unsafe fn has_pinned_objects() -> bool {
+    N_PINNED != 0
+}
+
+/// Drop the pin table. Should only be called when there are no pinned objects, before switching
+/// to a GC that does not know about the table.
+pub unsafe fn free_pin_table() {
+    debug_assert!(!has_pinned_objects());
+    PIN_TABLE = NO_PIN_TABLE;
+}
+
+/// Returns whether the object at the given address is pinned, or is the pin table
+pub(crate) unsafe fn is_pinned(obj: u32) -> bool {
+    if PIN_TABLE == NO_PIN_TABLE {
+        return false;
+    }
+
+    obj == PIN_TABLE.get_ptr() as u32
+        || (N_PINNED != 0 && (0..pin_table_len()).any(|handle| *pin_table_slot(handle) == obj))
+}
+
+/// Call the callback on the addresses of the pin table and the pinned objects. An object pinned
+/// multiple times is visited multiple times.
+pub(crate) unsafe fn visit_pinned_objects<F: FnMut(u32)>(mut f: F) {
+    if PIN_TABLE == NO_PIN_TABLE {
+        return;
+    }
+
+    f(PIN_TABLE.get_ptr() as u32);
+
+    for handle in 0..pin_table_len() {
+        let obj = *pin_table_slot(handle);
+        if obj != 0 {
+            f(obj);
+        }
+    }
+}
+
+unsafe fn pin_table_len() -> u32 {
+    if PIN_TABLE == NO_PIN_TABLE {
+        0
+    } else {
+        PIN_TABLE.as_blob().len().as_u32() / 4
+    }
+}
+
+unsafe fn pin_table_slot(handle: u32) -> *mut u32 {
+    (PIN_TABLE.as_blob_mut().payload_addr() as *mut u32).add(handle as usize)
+}
+
+/// Allocate a pin table with twice the slots and copy the slots of the current table to it. The
+/// old table is collected by the next GC.
+unsafe fn grow_pin_table<M: Memory>(mem: &mut M) {
+    let old_len = pin_table_len();
+    let new_len = if old_len == 0 {
+        INITIAL_PIN_TABLE_LEN
+    } else {
+        old_len * 2
+    };
+
+    let new_table = alloc_blob(mem, Bytes(new_len * 4));
+    let new_slots = new_table.as_blob_mut().payload_addr() as *mut u32;
+
+    for handle in 0..new_len {
+        *new_slots.add(handle as usize) = if handle < old_len {
+            *pin_table_slot(handle)
+        } else {
+            0
+        };
+    }
+
+    PIN_TABLE = new_table;
+}
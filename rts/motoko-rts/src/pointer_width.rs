@@ -0,0 +1,134 @@
+//! Width of heap words and pointers.
+//!
+//! The RTS targets 32-bit Wasm memory, where heap words, object headers and (skewed) pointers are
+//! 32 bits, see `Width`. With 64-bit Wasm memory pointers would need to be widened to 64 bits,
+//! doubling the size of reference-dense objects. To be able to support both, the encoding of
+//! pointers in heap words, and the parts of the mark-compact GC that depend on it (threading,
+//! unthreading, and telling tags from field addresses), are written against `PointerWidth`.
+//!
+//! Addresses are `usize` with both widths, and must fit in a word of the width.
+
+use crate::assert_level::{AssertLevel, ASSERT_LEVEL};
+use crate::rts_trap_with;
+use crate::types::{is_tag, skew, unskew, Tag, MAX_TAG, TAG_OBJECT};
+
+/// Width of heap words and pointers
+pub trait PointerWidth {
+    /// Size of a word, in bytes
+    const WORD_SIZE: usize;
+
+    /// Read the word at the address
+    unsafe fn load(addr: usize) -> usize;
+
+    /// Write the word at the address. The value must fit in a word.
+    unsafe fn store(addr: usize, value: usize);
+}
+
+/// 32-bit words, used by the RTS
+pub struct Width32;
+
+/// 64-bit words
+pub struct Width64;
+
+impl PointerWidth for Width32 {
+    const WORD_SIZE: usize = 4;
+
+    unsafe fn load(addr: usize) -> usize {
+        *(addr as *const u32) as usize
+    }
+
+    unsafe fn store(addr: usize, value: usize) {
+        debug_assert!(value <= u32::MAX as usize);
+        *(addr as *mut u32) = value as u32;
+    }
+}
+
+impl PointerWidth for Width64 {
+    const WORD_SIZE: usize = 8;
+
+    unsafe fn load(addr: usize) -> usize {
+        *(addr as *const u64) as usize
+    }
+
+    unsafe fn store(addr: usize, value: usize) {
+        *(addr as *mut u64) = value as u64;
+    }
+}
+
+/// Pointer width of the RTS. `constants::WORD_SIZE` is the word size of this width.
+pub type Width = Width32;
+
+/// Whether a heap word holds a tag rather than a field address, see `types::is_tag`. Only the
+/// lowest bit is checked, so this is the same for all widths.
+pub fn is_tag_word(word: usize) -> bool {
+    is_tag(word as u32)
+}
+
+/// Whether a heap word holds the tag of an object, see `assert_level`
+fn is_object_tag(word: usize) -> bool {
+    is_tag_word(word) && word >= TAG_OBJECT as usize && word <= MAX_TAG as usize
+}
+
+/// Smallest heap base for the width. Threaded fields hold tags, which must not look like skewed
+/// pointers to the dynamic heap (see `types::is_tag`), and objects in the heap are word aligned.
+pub fn min_heap_base<W: PointerWidth>() -> usize {
+    let after_tags = unskew(MAX_TAG as usize) + 1;
+    (after_tags + W::WORD_SIZE - 1) / W::WORD_SIZE * W::WORD_SIZE
+}
+
+/// Thread the pointer field at address `field`: store the pointed object's header in the field,
+/// and the field address in the pointed object's header.
+pub unsafe fn thread<W: PointerWidth>(field: usize) {
+    thread_checked::<W>(field, ASSERT_LEVEL);
+}
+
+/// `thread`, with the checks of the assertion level `level` (see `assert_level`)
+pub unsafe fn thread_checked<W: PointerWidth>(field: usize, level: AssertLevel) {
+    debug_assert_eq!(field % W::WORD_SIZE, 0);
+    let pointed = unskew(W::load(field));
+    let header = W::load(pointed);
+
+    // The header is a tag or the address of a field threaded before
+    if level.checks(AssertLevel::Cheap) && is_tag_word(header) && !is_object_tag(header) {
+        rts_trap_with("thread: invalid object tag");
+    }
+
+    W::store(field, header);
+    W::store(pointed, field);
+}
+
+/// Unthread all fields pointing to the object at address `obj`, making them point to `new_loc`,
+/// and restore the object header
+pub unsafe fn unthread<W: PointerWidth>(obj: usize, new_loc: usize) {
+    unthread_checked::<W>(obj, new_loc, ASSERT_LEVEL);
+}
+
+/// `unthread`, with the checks of the assertion level `level` (see `assert_level`)
+pub unsafe fn unthread_checked<W: PointerWidth>(obj: usize, new_loc: usize, level: AssertLevel) {
+    let mut header = W::load(obj);
+
+    while !is_tag_word(header) {
+        let next = W::load(header);
+        W::store(header, skew(new_loc));
+        header = next;
+    }
+
+    // At the end of the chain is the original header for the object
+    if level.checks(AssertLevel::Cheap) && !is_object_tag(header) {
+        rts_trap_with("unthread: invalid object tag");
+    }
+
+    W::store(obj, header);
+}
+
+/// Tag of the object at address `obj`, which may have been threaded. Follows the chain of threaded
+/// fields to the object header.
+pub unsafe fn threaded_tag<W: PointerWidth>(obj: usize) -> Tag {
+    let mut header = W::load(obj);
+
+    while !is_tag_word(header) {
+        header = W::load(header);
+    }
+
+    header as Tag
+}
@@ -255,7 +255,8 @@ unsafe fn base32_to_principal<M: Memory>(mem: &mut M, b: Value) -> Value {
     r
 }
 
-// Decode an textual principal representation into a blob
+// Decode an textual principal representation into a blob. Traps when the text is not the canonical
+// encoding of the blob, e.g. because of a wrong checksum, wrong grouping, or upper case letters.
 #[ic_mem_fn]
 pub unsafe fn blob_of_principal<M: Memory>(mem: &mut M, t: Value) -> Value {
     let b0 = blob_of_text(mem, t);
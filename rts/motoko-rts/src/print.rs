@@ -74,6 +74,11 @@ impl<'a> WriteBuf<'a> {
         }
     }
 
+    /// Number of bytes written to the buffer
+    pub(crate) fn len(&self) -> usize {
+        self.offset
+    }
+
     pub(crate) fn reset(&mut self) {
         self.offset = 0;
     }
@@ -0,0 +1,127 @@
+//! Replying with large blobs without copying them to the Candid data buffer.
+//!
+//! A reply is normally serialized to a data buffer in full, and the buffer is passed to
+//! `ic0.msg_reply_data_append`, so the bytes of every blob in the reply are copied twice: to the
+//! buffer, and by the IC. The IC allows appending the reply data in chunks, so large blobs are
+//! instead "spliced" into the reply: the serializer writes the length of the blob to the buffer but
+//! not its payload, and records the blob and the position in the buffer. `reply_splice_append` then
+//! appends the buffer up to the position, the payload of the blob directly from the heap, and
+//! continues with the rest of the buffer. The data buffer only holds the small parts of the reply.
+//!
+//! Splicing is enabled with `reply_splice_begin` right before serializing a reply, and disabled
+//! when the reply is appended, so other serializations (`to_candid`, call arguments, stable
+//! variables) still produce contiguous buffers. The GC does not run in the middle of a message, so
+//! the recorded blobs don't move between serialization and reply.
+//!
+//! The serializer computes the buffer size before writing, so both passes have to make the same
+//! decisions: the k-th blob of at least `SPLICE_THRESHOLD` bytes is spliced if `k < MAX_SPLICES`,
+//! larger blobs after that are copied as usual.
+
+use crate::mem_utils::memcpy_bytes;
+use crate::rts_trap_with;
+use crate::types::Value;
+
+/// Blobs with at least this many bytes are spliced
+pub const SPLICE_THRESHOLD: u32 = 64 * 1024;
+
+/// Maximum number of blobs spliced into a reply
+pub const MAX_SPLICES: usize = 32;
+
+#[derive(Clone, Copy)]
+struct Splice {
+    /// Address in the data buffer where the payload of the blob goes
+    position: *const u8,
+    blob: Value,
+}
+
+static mut SPLICING: bool = false;
+
+/// Number of blobs to splice, counted when computing the buffer size
+static mut N_SIZED: usize = 0;
+
+/// Spliced blobs, recorded when writing the buffer
+static mut SPLICES: [Splice; MAX_SPLICES] = [Splice {
+    position: core::ptr::null(),
+    blob: Value::from_scalar(0),
+}; MAX_SPLICES];
+
+static mut N_SPLICES: usize = 0;
+
+extern "C" {
+    // generated by `moc`, forwarding to `ic0.msg_reply_data_append`
+    fn msg_reply_data_append_moc(src: *const u8, size: u32);
+}
+
+/// Enable splicing for the next serialization
+#[no_mangle]
+pub unsafe extern "C" fn reply_splice_begin() {
+    SPLICING = true;
+    N_SIZED = 0;
+    N_SPLICES = 0;
+}
+
+/// Whether a blob of `len` bytes is spliced, when `count` blobs were spliced before it
+unsafe fn is_spliced(len: u32, count: usize) -> bool {
+    SPLICING && len >= SPLICE_THRESHOLD && count < MAX_SPLICES
+}
+
+/// Number of payload bytes of the blob in the data buffer: 0 when the blob is spliced
+#[no_mangle]
+pub unsafe extern "C" fn reply_splice_blob_size(blob: Value) -> u32 {
+    let len = blob.as_blob().len().as_u32();
+    if is_spliced(len, N_SIZED) {
+        N_SIZED += 1;
+        0
+    } else {
+        len
+    }
+}
+
+/// Write the payload of the blob to the data buffer at `buf`, or record it to be spliced there.
+/// Returns the number of bytes written.
+#[no_mangle]
+pub unsafe extern "C" fn reply_splice_write_blob(buf: *mut u8, blob: Value) -> u32 {
+    let len = blob.as_blob().len();
+    if is_spliced(len.as_u32(), N_SPLICES) {
+        SPLICES[N_SPLICES] = Splice {
+            position: buf,
+            blob,
+        };
+        N_SPLICES += 1;
+        0
+    } else {
+        memcpy_bytes(buf as usize, blob.as_blob().payload_const() as usize, len);
+        len.as_u32()
+    }
+}
+
+/// Append the data buffer of a reply, with the spliced blobs, to the reply data. Disables
+/// splicing.
+#[no_mangle]
+pub unsafe extern "C" fn reply_splice_append(data: *const u8, size: u32) {
+    if N_SPLICES != N_SIZED {
+        rts_trap_with("reply_splice_append: blobs sized and written differ");
+    }
+
+    let end = data.add(size as usize);
+    let mut start = data;
+    for splice in SPLICES[..N_SPLICES].iter() {
+        append(start, splice.position);
+        let blob = splice.blob.as_blob();
+        msg_reply_data_append_moc(blob.payload_const(), blob.len().as_u32());
+        start = splice.position;
+    }
+    append(start, end);
+
+    SPLICING = false;
+    N_SIZED = 0;
+    N_SPLICES = 0;
+}
+
+/// Append the bytes from `start` to `end`, if any
+unsafe fn append(start: *const u8, end: *const u8) {
+    let len = end.offset_from(start) as u32;
+    if len != 0 {
+        msg_reply_data_append_moc(start, len);
+    }
+}
@@ -0,0 +1,147 @@
+//! Deterministic pseudo-random numbers, with xoshiro256** (https://prng.di.unimi.it/).
+//!
+//! The generator is seeded once, typically with the 32 bytes returned by `ic0.raw_rand`, and then
+//! produces numbers without further calls to the management canister. The sequence only depends on
+//! the seed, so replicas agree on it. xoshiro256** is not a cryptographic generator: its state can
+//! be recovered from a few outputs.
+//!
+//! The state is not part of the heap, so it does not survive upgrades on its own. Programs keep the
+//! state returned by `rng_state` in a stable variable in `preupgrade`, and restore it with
+//! `rng_set_state` in `postupgrade`.
+
+use crate::memory::{alloc_blob, Memory};
+use crate::rts_trap_with;
+use crate::types::{Bytes, Value};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Size of the state, in bytes
+pub const RNG_STATE_SIZE: u32 = 32;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    s: [u64; 4],
+}
+
+impl Rng {
+    /// Seed the generator with the first 32 bytes of `seed`, read as four little-endian words.
+    /// Shorter seeds are padded with zeros. The all-zero state is a fixed point of xoshiro, so it
+    /// is replaced by the splitmix64 expansion of 0.
+    pub fn from_seed(seed: &[u8]) -> Rng {
+        let mut s = [0u64; 4];
+        for (i, byte) in seed.iter().take(RNG_STATE_SIZE as usize).enumerate() {
+            s[i / 8] |= (*byte as u64) << (8 * (i % 8));
+        }
+
+        if s == [0; 4] {
+            let mut x = 0;
+            for word in s.iter_mut() {
+                *word = splitmix64(&mut x);
+            }
+        }
+
+        Rng { s }
+    }
+
+    /// The state, as accepted by `from_seed`
+    pub fn state(&self) -> [u8; RNG_STATE_SIZE as usize] {
+        let mut bytes = [0u8; RNG_STATE_SIZE as usize];
+        for (i, word) in self.s.iter().enumerate() {
+            bytes[i * 8..(i + 1) * 8].copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.s[1] << 17;
+
+        self.s[2] ^= self.s[0];
+        self.s[3] ^= self.s[1];
+        self.s[1] ^= self.s[2];
+        self.s[0] ^= self.s[3];
+
+        self.s[2] ^= t;
+        self.s[3] = self.s[3].rotate_left(45);
+
+        result
+    }
+
+    /// A number uniformly distributed in `[0, n)`. Rejects the numbers below `2^64 mod n`, so that
+    /// all remainders are equally likely.
+    pub fn next_range(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            unsafe { rts_trap_with("rng_next_range: empty range") }
+        }
+
+        let threshold = n.wrapping_neg() % n;
+        loop {
+            let x = self.next_u64();
+            if x >= threshold {
+                return x % n;
+            }
+        }
+    }
+}
+
+fn splitmix64(x: &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The generator of the program, `None` until seeded
+static mut RNG: Option<Rng> = None;
+
+unsafe fn rng() -> &'static mut Rng {
+    match RNG.as_mut() {
+        Some(rng) => rng,
+        None => rts_trap_with("rng: not seeded"),
+    }
+}
+
+unsafe fn blob_bytes<'a>(blob: Value) -> &'a [u8] {
+    let blob = blob.as_blob();
+    core::slice::from_raw_parts(blob.payload_const(), blob.len().as_usize())
+}
+
+/// Seed, or reseed, the generator with the bytes of a blob
+#[no_mangle]
+pub unsafe extern "C" fn rng_seed(seed: Value) {
+    RNG = Some(Rng::from_seed(blob_bytes(seed)));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rng_next() -> u64 {
+    rng().next_u64()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rng_next_range(n: u64) -> u64 {
+    rng().next_range(n)
+}
+
+/// The state of the generator, as a blob to be restored with `rng_set_state`
+#[ic_mem_fn]
+pub unsafe fn rng_state<M: Memory>(mem: &mut M) -> Value {
+    let state = rng().state();
+    let blob = alloc_blob(mem, Bytes(RNG_STATE_SIZE));
+    let blob_ptr = blob.as_blob_mut();
+    for (i, byte) in state.iter().enumerate() {
+        blob_ptr.set(i as u32, *byte);
+    }
+    blob
+}
+
+/// Restore a state returned by `rng_state`. Unlike `rng_seed`, traps on blobs that are not states,
+/// to catch restoring from an uninitialized stable variable.
+#[no_mangle]
+pub unsafe extern "C" fn rng_set_state(state: Value) {
+    let bytes = blob_bytes(state);
+    if bytes.len() != RNG_STATE_SIZE as usize || bytes.iter().all(|byte| *byte == 0) {
+        rts_trap_with("rng_set_state: invalid state");
+    }
+    RNG = Some(Rng::from_seed(bytes));
+}
@@ -0,0 +1,120 @@
+//! GC roots registered at run time. RTS components that keep heap objects in their own globals
+//! register the globals with `register_root` to keep the objects alive, instead of adding fields
+//! to the static root array of the generated code.
+//!
+//! A root is a location outside of the dynamic heap (e.g. a `static mut`) holding a `Value`. All
+//! GCs mark the objects the roots point to, and update the roots when the objects are moved. Roots
+//! holding scalars or pointers to the static heap are skipped.
+//!
+//! ## Root groups
+//!
+//! Roots can be registered in a group (`register_root_in_group`), e.g. one group per actor class
+//! instance, and all roots of a group are unregistered at once with `drop_root_group` when the
+//! instance is dropped. The objects only reachable from the roots of the group are then reclaimed
+//! by the next GC.
+//!
+//! Groups don't own objects: the GCs only see the roots, not their groups. An object reachable
+//! from several groups is kept alive until the roots of all of these groups are dropped (or
+//! unregistered), like an object reachable from several roots.
+//!
+//! Roots registered with `register_root` are in `NO_ROOT_GROUP`, which cannot be dropped.
+
+use crate::rts_trap_with;
+use crate::types::Value;
+
+/// Maximum number of registered roots. Roots are for RTS components, so a small fixed table is
+/// enough, and we don't need to allocate to register a root.
+pub const MAX_ROOTS: usize = 32;
+
+/// Group of the roots registered with `register_root`
+pub const NO_ROOT_GROUP: u32 = 0;
+
+static mut ROOTS: [*mut Value; MAX_ROOTS] = [core::ptr::null_mut(); MAX_ROOTS];
+
+/// `ROOT_GROUPS[i]` is the group of `ROOTS[i]`
+static mut ROOT_GROUPS: [u32; MAX_ROOTS] = [NO_ROOT_GROUP; MAX_ROOTS];
+
+static mut N_ROOTS: usize = 0;
+
+/// Register a root location. Registering a location that is already registered does nothing.
+pub unsafe fn register_root(loc: *mut Value) {
+    register_root_in_group(loc, NO_ROOT_GROUP);
+}
+
+/// Register a root location in a group. Registering a location that is already registered in the
+/// same group does nothing, registering it in another group traps.
+pub unsafe fn register_root_in_group(loc: *mut Value, group: u32) {
+    if let Some(idx) = root_index(loc) {
+        if ROOT_GROUPS[idx] != group {
+            rts_trap_with("register_root: root registered in another group");
+        }
+        return;
+    }
+
+    if N_ROOTS == MAX_ROOTS {
+        rts_trap_with("register_root: too many roots");
+    }
+
+    ROOTS[N_ROOTS] = loc;
+    ROOT_GROUPS[N_ROOTS] = group;
+    N_ROOTS += 1;
+}
+
+/// Unregister a root location. Unregistering a location that is not registered does nothing.
+pub unsafe fn unregister_root(loc: *mut Value) {
+    if let Some(idx) = root_index(loc) {
+        remove_root(idx);
+    }
+}
+
+/// Unregister all roots of the group. Returns the number of roots unregistered.
+pub unsafe fn drop_root_group(group: u32) -> usize {
+    if group == NO_ROOT_GROUP {
+        rts_trap_with("drop_root_group: cannot drop roots without a group");
+    }
+
+    let n_roots = N_ROOTS;
+    let mut idx = 0;
+    while idx < N_ROOTS {
+        if ROOT_GROUPS[idx] == group {
+            // Moves the last root to `idx`
+            remove_root(idx);
+        } else {
+            idx += 1;
+        }
+    }
+
+    n_roots - N_ROOTS
+}
+
+/// Number of roots registered in the group
+pub unsafe fn root_group_size(group: u32) -> usize {
+    (0..N_ROOTS)
+        .filter(|idx| ROOT_GROUPS[*idx] == group)
+        .count()
+}
+
+/// Is the location registered as a root?
+pub unsafe fn is_root(loc: *mut Value) -> bool {
+    root_index(loc).is_some()
+}
+
+/// Visit registered root locations, each once
+pub unsafe fn visit_roots<F: FnMut(*mut Value)>(mut f: F) {
+    for root in ROOTS[..N_ROOTS].iter() {
+        f(*root);
+    }
+}
+
+unsafe fn root_index(loc: *mut Value) -> Option<usize> {
+    (0..N_ROOTS).find(|idx| ROOTS[*idx] == loc)
+}
+
+/// Remove the root at `idx`, moving the last root to `idx`
+unsafe fn remove_root(idx: usize) {
+    N_ROOTS -= 1;
+    ROOTS[idx] = ROOTS[N_ROOTS];
+    ROOT_GROUPS[idx] = ROOT_GROUPS[N_ROOTS];
+    ROOTS[N_ROOTS] = core::ptr::null_mut();
+    ROOT_GROUPS[N_ROOTS] = NO_ROOT_GROUP;
+}
@@ -0,0 +1,82 @@
+//! Safepoints in long-running loops.
+//!
+//! The code generated by `moc` calls `safepoint` at loop back-edges, so that a loop that runs out
+//! of instructions stops at a known point, between two iterations, rather than at the hard
+//! instruction limit of the IC, in the middle of whatever it was doing.
+//!
+//! The generated code keeps a countdown in a global, and only calls `safepoint` every 1024
+//! back-edges (see `Safepoint` in `compile.ml`), so most back-edges don't leave the generated code.
+//! A safepoint compares the instruction counter with the soft budget of `crate::instructions`.
+//! Once the budget is used up a safepoint does what `set_safepoint_action` selected:
+//!
+//! - `Collect` (default): do a slice of the incremental GC marking in progress, if any, so that
+//!   the slice at the end of the message has less to do. Safepoints never start a GC cycle or
+//!   compact the heap, see `mark_slice_at_safepoint`. Marking in the middle of a message is only
+//!   correct when the generated code calls the write barrier, so without a write barrier in
+//!   snapshot-at-the-beginning mode (see `gc::barrier`) this action does nothing.
+//!
+//! - `Trap`: trap, rolling back the message. Code doing resumable work in chunks uses this to fail
+//!   cleanly and continue in a new message with a smaller chunk.
+
+use crate::gc::barrier::{BarrierMode, BARRIER_MODE};
+use crate::gc::mark_compact::incremental::{mark_slice_at_safepoint, Phase, PHASE};
+use crate::instructions::remaining_instructions;
+use crate::memory::Memory;
+use crate::rts_trap_with;
+use crate::types::Words;
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Number of words marked in a GC slice at a safepoint (256 KiB)
+pub const SAFEPOINT_SLICE_BUDGET: Words<u32> = Words(64 * 1024);
+
+/// What a safepoint does once the instruction budget is used up
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SafepointAction {
+    /// Do a slice of the incremental marking in progress
+    Collect = 0,
+    /// Trap with a message saying that the work should be resumed in a new message
+    Trap = 1,
+}
+
+pub static mut SAFEPOINT_ACTION: SafepointAction = SafepointAction::Collect;
+
+/// Number of GC slices done at safepoints
+static mut SAFEPOINT_GC_SLICES: u32 = 0;
+
+/// Select the action of safepoints, see `SafepointAction`. Traps on unknown actions.
+#[no_mangle]
+pub unsafe extern "C" fn set_safepoint_action(action: u32) {
+    SAFEPOINT_ACTION = match action {
+        0 => SafepointAction::Collect,
+        1 => SafepointAction::Trap,
+        _ => rts_trap_with("set_safepoint_action: unknown action"),
+    };
+}
+
+/// Number of GC slices done at safepoints
+#[no_mangle]
+pub unsafe extern "C" fn safepoint_gc_slices() -> u32 {
+    SAFEPOINT_GC_SLICES
+}
+
+/// Called at loop back-edges by the generated code, when its countdown reaches zero
+#[ic_mem_fn]
+pub unsafe fn safepoint<M: Memory>(mem: &mut M) {
+    if remaining_instructions() != 0 {
+        return;
+    }
+
+    match SAFEPOINT_ACTION {
+        SafepointAction::Collect => {
+            if PHASE == Phase::Marking && BARRIER_MODE == BarrierMode::SnapshotAtTheBeginning {
+                mark_slice_at_safepoint(mem, SAFEPOINT_SLICE_BUDGET);
+                SAFEPOINT_GC_SLICES += 1;
+            }
+        }
+        SafepointAction::Trap => rts_trap_with(
+            "safepoint: instruction budget exhausted, resume the work in a new message",
+        ),
+    }
+}
@@ -0,0 +1,334 @@
+//! RTS self-test, for canister authors to check a new RTS after an upgrade.
+//!
+//! `rts_self_test` builds a fixed object graph (an array of records, a cycle of mutable boxes, a
+//! blob large enough to be a large object in mark-compact GC, and an array sharing parts of the
+//! others) after the current heap, and collects it with mark-compact GC. After each GC, the heap is
+//! checked with `verify_heap` and the graph is checked against its contents before the GC. Then
+//! the cycle is dropped and the graph collected again, which should reclaim the cycle.
+//!
+//! The test graph is collected as a heap of its own: its roots are allocated before the test heap,
+//! so the GC treats them as static roots, and objects of the program are below the test heap, so
+//! the GC does not visit them. The heap pointer is reset after the test, so the test does not
+//! leave objects behind.
+//!
+//! On a discrepancy the test traps with the number of the failed check (see `Check`). Otherwise
+//! it returns a checksum of the contents of the graph, which does not depend on addresses.
+
+use crate::constants::WORD_SIZE;
+use crate::gc::mark_compact::{compacting_gc_internal, GcContext};
+use crate::gc::verify::verify_heap;
+use crate::memory::{alloc_array, alloc_blob, Memory};
+use crate::print::WriteBuf;
+use crate::rts_trap_with;
+use crate::types::*;
+
+use core::cell::Cell;
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Root of the array of records
+const ROOT_RECORDS: u32 = 0;
+/// Root of the cycle of mutable boxes
+const ROOT_CYCLE: u32 = 1;
+/// Root of the large blob
+const ROOT_BLOB: u32 = 2;
+/// Root of the array sharing objects of the other roots
+const ROOT_SHARED: u32 = 3;
+
+const N_ROOTS: u32 = 4;
+
+const N_RECORDS: u32 = 100;
+
+const CYCLE_LEN: u32 = 16;
+
+/// Larger than `LARGE_OBJECT_THRESHOLD`, so the blob is not moved
+const BLOB_SIZE: Bytes<u32> = Bytes(80 * 1024);
+
+/// Checks of the self-test, numbered in the trap message
+#[derive(Clone, Copy)]
+enum Check {
+    Records = 1,
+    Cycle = 2,
+    Blob = 3,
+    Shared = 4,
+    /// Contents are the same before and after GC
+    Checksum = 5,
+    /// Dropping the cycle reclaims it
+    Reclaimed = 6,
+}
+
+unsafe fn check(cond: bool, check: Check) {
+    if !cond {
+        use core::fmt::Write;
+        let mut buf = [0u8; 64];
+        let mut fmt = WriteBuf::new(&mut buf);
+        let _ = write!(&mut fmt, "rts_self_test: check {} failed", check as u32);
+        let len = fmt.len();
+        rts_trap_with(core::str::from_utf8_unchecked(&buf[..len]));
+    }
+}
+
+#[ic_mem_fn(ic_only)]
+unsafe fn rts_self_test<M: Memory>(mem: &mut M) -> u32 {
+    use crate::gc::mark_compact::incremental;
+    use crate::memory::ic;
+
+    // The test GC cannot run in the middle of incremental marking
+    if incremental::PHASE != incremental::Phase::Idle {
+        incremental::compacting_gc_incremental(mem, u32::MAX);
+    }
+
+    self_test_internal(mem, || ic::HP as usize, |hp| ic::HP = hp)
+}
+
+/// Run the self-test after the heap pointer, return the checksum of the graph. Resets the heap
+/// pointer before returning.
+pub unsafe fn self_test_internal<M: Memory, GetHp: Fn() -> usize, SetHp: Fn(u32)>(
+    mem: &mut M,
+    get_hp: GetHp,
+    set_hp: SetHp,
+) -> u32 {
+    let start = get_hp() as u32;
+
+    let roots = alloc_array(mem, N_ROOTS);
+    for i in 0..N_ROOTS {
+        roots
+            .as_array()
+            .set(i, alloc_mutbox(mem, Value::from_scalar(0)));
+    }
+    let mut continuation_table = Value::from_scalar(0);
+
+    // Mark-compact GC needs a 32-byte aligned heap base
+    let misalignment = get_hp() as u32 % 32;
+    if misalignment != 0 {
+        mem.alloc_words(Bytes(32 - misalignment).to_words());
+    }
+    let heap_base = get_hp() as u32;
+
+    build_graph(mem, roots);
+
+    let checksum = check_graph(roots, true);
+    let live_with_cycle = collect(
+        mem,
+        heap_base,
+        &get_hp,
+        &set_hp,
+        roots,
+        &mut continuation_table,
+    );
+    check(check_graph(roots, true) == checksum, Check::Checksum);
+
+    // Drop the cycle
+    set_root(roots, ROOT_CYCLE, Value::from_scalar(0));
+    let shared = get_root(roots, ROOT_SHARED).as_array();
+    shared.set(1, Value::from_scalar(0));
+
+    let checksum = check_graph(roots, false);
+    let live = collect(
+        mem,
+        heap_base,
+        &get_hp,
+        &set_hp,
+        roots,
+        &mut continuation_table,
+    );
+    check(check_graph(roots, false) == checksum, Check::Checksum);
+    check(
+        live + (size_of::<MutBox>() * CYCLE_LEN).to_bytes() <= live_with_cycle,
+        Check::Reclaimed,
+    );
+
+    set_hp(start);
+
+    checksum
+}
+
+unsafe fn collect<M: Memory, GetHp: Fn() -> usize, SetHp: Fn(u32)>(
+    mem: &mut M,
+    heap_base: u32,
+    get_hp: &GetHp,
+    set_hp: &SetHp,
+    roots: Value,
+    continuation_table: *mut Value,
+) -> Bytes<u32> {
+    let live = Cell::new(Bytes(0));
+
+    compacting_gc_internal(
+        mem,
+        GcContext {
+            heap_base,
+            get_hp,
+            set_hp,
+            static_roots: roots,
+            continuation_table_ptr_loc: continuation_table,
+        },
+        // note_live_size
+        |live_size| live.set(live_size),
+        // note_reclaimed
+        |_| {},
+        // note_phase_time
+        |_, _| {},
+    );
+
+    let hp = get_hp() as u32;
+    verify_heap(mem, heap_base, hp, roots, continuation_table);
+    // Drop the bitmap allocated by `verify_heap`
+    set_hp(hp);
+
+    live.get()
+}
+
+unsafe fn build_graph<M: Memory>(mem: &mut M, roots: Value) {
+    // Records `{ i; blob of i }`, with garbage between them so that GC moves them
+    let records = alloc_array(mem, N_RECORDS);
+    set_root(roots, ROOT_RECORDS, records);
+    for i in 0..N_RECORDS {
+        alloc_array(mem, 3);
+        let blob = alloc_blob(mem, Bytes(WORD_SIZE));
+        for (j, byte) in i.to_le_bytes().iter().enumerate() {
+            blob.as_blob_mut().set(j as u32, *byte);
+        }
+        let record = alloc_record(mem, &[Value::from_scalar(i), blob]);
+        records.as_array().set(i, record);
+    }
+
+    // Cycle of mutable boxes
+    let first = alloc_mutbox(mem, Value::from_scalar(0));
+    let mut last = first;
+    for _ in 1..CYCLE_LEN {
+        alloc_array(mem, 1);
+        let mutbox = alloc_mutbox(mem, Value::from_scalar(0));
+        (*as_mutbox(last)).field = mutbox;
+        last = mutbox;
+    }
+    (*as_mutbox(last)).field = first;
+    set_root(roots, ROOT_CYCLE, first);
+
+    let blob = alloc_blob(mem, BLOB_SIZE);
+    for i in 0..BLOB_SIZE.as_u32() {
+        blob.as_blob_mut().set(i, blob_byte(i));
+    }
+    set_root(roots, ROOT_BLOB, blob);
+
+    // Record 50, the middle of the cycle, the blob, and the array of records
+    let shared = alloc_array(mem, 4);
+    let shared_array = shared.as_array();
+    shared_array.set(0, records.as_array().get(50));
+    let mut mutbox = first;
+    for _ in 0..CYCLE_LEN / 2 {
+        mutbox = (*as_mutbox(mutbox)).field;
+    }
+    shared_array.set(1, mutbox);
+    shared_array.set(2, blob);
+    shared_array.set(3, records);
+    set_root(roots, ROOT_SHARED, shared);
+}
+
+/// Check the graph built by `build_graph`, return a checksum of its contents
+unsafe fn check_graph(roots: Value, with_cycle: bool) -> u32 {
+    let mut checksum: u32 = 0;
+    let mut add = |n: u32| checksum = checksum.wrapping_mul(31).wrapping_add(n);
+
+    let records = get_root(roots, ROOT_RECORDS);
+    check(records.tag() == TAG_ARRAY, Check::Records);
+    check(records.as_array().len() == N_RECORDS, Check::Records);
+    for i in 0..N_RECORDS {
+        let record = records.as_array().get(i);
+        check(record.tag() == TAG_OBJECT, Check::Records);
+        let record = record.as_obj() as *mut Object;
+        check(record.size() == 2, Check::Records);
+        let field0 = *record.payload_addr();
+        let field1 = *record.payload_addr().add(1);
+        check(
+            field0.is_scalar() && field0.get_scalar() == i,
+            Check::Records,
+        );
+        check(field1.tag() == TAG_BLOB, Check::Records);
+        let blob = field1.as_blob();
+        check(blob.len() == Bytes(WORD_SIZE), Check::Records);
+        let mut bytes = [0u8; 4];
+        for (j, byte) in bytes.iter_mut().enumerate() {
+            *byte = blob.get(j as u32);
+        }
+        check(u32::from_le_bytes(bytes) == i, Check::Records);
+        add(field0.get_scalar());
+        add(u32::from_le_bytes(bytes));
+    }
+
+    let cycle = get_root(roots, ROOT_CYCLE);
+    let shared = get_root(roots, ROOT_SHARED);
+    check(shared.tag() == TAG_ARRAY, Check::Shared);
+    let shared = shared.as_array();
+    check(shared.len() == 4, Check::Shared);
+
+    if with_cycle {
+        let mut mutbox = cycle;
+        for i in 0..CYCLE_LEN {
+            check(mutbox.tag() == TAG_MUTBOX, Check::Cycle);
+            if i == CYCLE_LEN / 2 {
+                check(shared.get(1).get_raw() == mutbox.get_raw(), Check::Shared);
+            }
+            mutbox = (*as_mutbox(mutbox)).field;
+            add(i);
+        }
+        check(mutbox.get_raw() == cycle.get_raw(), Check::Cycle);
+    } else {
+        check(cycle.is_scalar(), Check::Cycle);
+        check(shared.get(1).is_scalar(), Check::Shared);
+    }
+
+    let blob = get_root(roots, ROOT_BLOB);
+    check(blob.tag() == TAG_BLOB, Check::Blob);
+    check(blob.as_blob().len() == BLOB_SIZE, Check::Blob);
+    for i in 0..BLOB_SIZE.as_u32() {
+        let byte = blob.as_blob().get(i);
+        check(byte == blob_byte(i), Check::Blob);
+        add(byte as u32);
+    }
+
+    check(
+        shared.get(0).get_raw() == records.as_array().get(50).get_raw(),
+        Check::Shared,
+    );
+    check(shared.get(2).get_raw() == blob.get_raw(), Check::Shared);
+    check(shared.get(3).get_raw() == records.get_raw(), Check::Shared);
+
+    checksum
+}
+
+fn blob_byte(i: u32) -> u8 {
+    (i.wrapping_mul(31).wrapping_add(7) % 256) as u8
+}
+
+unsafe fn as_mutbox(value: Value) -> *mut MutBox {
+    value.as_obj() as *mut MutBox
+}
+
+unsafe fn get_root(roots: Value, root: u32) -> Value {
+    (*as_mutbox(roots.as_array().get(root))).field
+}
+
+unsafe fn set_root(roots: Value, root: u32, value: Value) {
+    (*as_mutbox(roots.as_array().get(root))).field = value;
+}
+
+unsafe fn alloc_mutbox<M: Memory>(mem: &mut M, field: Value) -> Value {
+    let ptr = mem.alloc_words(size_of::<MutBox>());
+    // NB. Cannot use `as_mutbox` here as we didn't write the header yet
+    let mutbox = ptr.get_ptr() as *mut MutBox;
+    (*mutbox).header.tag = TAG_MUTBOX;
+    (*mutbox).field = field;
+    ptr
+}
+
+unsafe fn alloc_record<M: Memory>(mem: &mut M, fields: &[Value]) -> Value {
+    let ptr = mem.alloc_words(size_of::<Object>() + Words(fields.len() as u32));
+    let object = ptr.get_ptr() as *mut Object;
+    (*object).header.tag = TAG_OBJECT;
+    (*object).size = fields.len() as u32;
+    (*object).hash_ptr = 0;
+    for (i, field) in fields.iter().enumerate() {
+        *object.payload_addr().add(i) = *field;
+    }
+    ptr
+}
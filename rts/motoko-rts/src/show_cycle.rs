@@ -0,0 +1,97 @@
+//! Cycle detection for `debug_show`. Generated show functions call `show_cycle_enter` before
+//! showing an object that can be part of a cycle (a mutable array or an object with mutable
+//! fields), and `show_cycle_leave` after. `show_cycle_enter` returns `false` when the object is
+//! already being shown, in which case the show function prints a cycle marker instead of recursing.
+//!
+//! The objects being shown are kept in a stack (`SHOW_PATH`), a blob of `u32` object addresses.
+//! Objects shared by different parts of a value, but not part of a cycle, are shown in full. The
+//! stack is dropped when the outermost object is done, so every `debug_show` call starts with an
+//! empty stack.
+//!
+//! The GC does not know about the stack. This is fine as GC only runs between messages, and
+//! `debug_show` does not yield.
+
+use crate::memory::{alloc_blob, Memory};
+use crate::rts_trap_with;
+use crate::types::{Bytes, Value};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Value of `SHOW_PATH` when no object is being shown
+const NO_SHOW_PATH: Value = Value::from_scalar(0);
+
+/// Number of slots in the first stack. Stack size is doubled when it's full.
+const INITIAL_SHOW_PATH_LEN: u32 = 8;
+
+/// Addresses of the objects being shown, outermost first
+static mut SHOW_PATH: Value = NO_SHOW_PATH;
+
+/// Number of objects in `SHOW_PATH`
+static mut SHOW_DEPTH: u32 = 0;
+
+/// Push `obj` to the stack of objects being shown and return `true`, or return `false` when `obj`
+/// is already being shown. `show_cycle_leave` should be called after showing the object when this
+/// returns `true`.
+#[ic_mem_fn]
+pub unsafe fn show_cycle_enter<M: Memory>(mem: &mut M, obj: Value) -> bool {
+    let obj = obj.get_ptr() as u32;
+
+    if (0..SHOW_DEPTH).any(|i| *show_path_slot(i) == obj) {
+        return false;
+    }
+
+    if SHOW_DEPTH == show_path_len() {
+        grow_show_path(mem);
+    }
+
+    *show_path_slot(SHOW_DEPTH) = obj;
+    SHOW_DEPTH += 1;
+
+    true
+}
+
+/// Pop the object pushed by the last `show_cycle_enter` that returned `true`
+#[no_mangle]
+pub unsafe extern "C" fn show_cycle_leave() {
+    if SHOW_DEPTH == 0 {
+        rts_trap_with("show_cycle_leave: no object is being shown");
+    }
+
+    SHOW_DEPTH -= 1;
+
+    if SHOW_DEPTH == 0 {
+        // Outermost object done, let the GC collect the stack
+        SHOW_PATH = NO_SHOW_PATH;
+    }
+}
+
+unsafe fn show_path_len() -> u32 {
+    if SHOW_PATH == NO_SHOW_PATH {
+        0
+    } else {
+        SHOW_PATH.as_blob().len().as_u32() / 4
+    }
+}
+
+unsafe fn show_path_slot(idx: u32) -> *mut u32 {
+    (SHOW_PATH.as_blob_mut().payload_addr() as *mut u32).add(idx as usize)
+}
+
+/// Allocate a stack with twice the slots and copy the current stack to it
+unsafe fn grow_show_path<M: Memory>(mem: &mut M) {
+    let old_len = show_path_len();
+    let new_len = if old_len == 0 {
+        INITIAL_SHOW_PATH_LEN
+    } else {
+        old_len * 2
+    };
+
+    let new_path = alloc_blob(mem, Bytes(new_len * 4));
+    let new_slots = new_path.as_blob_mut().payload_addr() as *mut u32;
+
+    for i in 0..SHOW_DEPTH {
+        *new_slots.add(i as usize) = *show_path_slot(i);
+    }
+
+    SHOW_PATH = new_path;
+}
@@ -0,0 +1,47 @@
+//! Version of the layout of stable variables in stable memory.
+//!
+//! On upgrade, the generated code serializes the stable variables to stable memory and writes
+//! `STABLE_LAYOUT_VERSION` to the last word of the last stable memory page. After the upgrade, the
+//! version is read back and checked with `check_stable_layout_version` before deserializing, so
+//! data written in a newer layout is rejected with a trap instead of being misread.
+//!
+//! The version should be incremented on every incompatible change to the layout. Older versions
+//! are accepted, the generated code is responsible for reading them.
+//!
+//! Versions:
+//!
+//! 1. Stable variables serialized with an extension of Candid that preserves aliasing of mutable
+//!    values, see Note [mutable stable values] in `codegen/compile.ml`.
+//! 2. Immutable arrays are shared too, see Note [shared immutable stable values].
+
+use crate::print::WriteBuf;
+use crate::rts_trap_with;
+
+use core::fmt::Write;
+
+/// Current version of the stable variable layout. Starts from 1 to avoid accidentally accepting a
+/// cleared word.
+pub const STABLE_LAYOUT_VERSION: u32 = 2;
+
+/// Returns the version of the stable variable layout written by this RTS
+#[no_mangle]
+pub extern "C" fn stable_layout_version() -> u32 {
+    STABLE_LAYOUT_VERSION
+}
+
+/// Check the version of the stable variable layout read from stable memory. Traps when the version
+/// is newer than `STABLE_LAYOUT_VERSION`, or is 0.
+#[no_mangle]
+pub unsafe extern "C" fn check_stable_layout_version(version: u32) {
+    if version == 0 || version > STABLE_LAYOUT_VERSION {
+        let mut buf = [0u8; 100];
+        let mut fmt = WriteBuf::new(&mut buf);
+        let _ = write!(
+            fmt,
+            "unsupported stable memory version {} (expected {} or lower)",
+            version, STABLE_LAYOUT_VERSION
+        );
+        let len = fmt.len();
+        rts_trap_with(core::str::from_utf8_unchecked(&buf[..len]));
+    }
+}
@@ -0,0 +1,64 @@
+//! Bounds-checked access to the user region of stable memory (`ExperimentalStableMemory`).
+//!
+//! The region is the first `stable_region_size()` pages of stable memory. Its size is the logical
+//! stable memory size maintained by the generated code, which is not the same as the physical size
+//! returned by `ic0.stable64_size`. On upgrade, stable variables are serialized after the region,
+//! so accesses checked against the region size never overlap the serialized stable variables.
+//!
+//! Reads and writes trap when any byte of the accessed range is out of the region.
+
+use crate::rts_trap_with;
+use crate::types::Value;
+
+/// Size of a stable memory page in bytes
+pub const STABLE_PAGE_SIZE: u64 = 64 * 1024;
+
+extern "C" {
+    // generated by `moc`
+    fn stable_mem_size_moc() -> u64;
+    fn stable_mem_grow_moc(pages: u64) -> u64;
+    fn stable64_read_moc(dst: u64, offset: u64, size: u64);
+    fn stable64_write_moc(offset: u64, src: u64, size: u64);
+}
+
+/// Size of the region in pages
+#[no_mangle]
+pub unsafe extern "C" fn stable_region_size() -> u64 {
+    stable_mem_size_moc()
+}
+
+/// Grow the region by `pages` pages. Returns the previous size in pages, or `u64::MAX` when the
+/// region cannot be grown (e.g. because of `--max-stable-pages`). Grown pages are zero.
+#[no_mangle]
+pub unsafe extern "C" fn stable_region_grow(pages: u64) -> u64 {
+    stable_mem_grow_moc(pages)
+}
+
+/// Fill the blob with the bytes of the region starting at `offset`
+#[no_mangle]
+pub unsafe extern "C" fn stable_region_read(offset: u64, blob: Value) {
+    let blob = blob.as_blob_mut();
+    let len = blob.len().as_u32() as u64;
+    check_range(offset, len);
+    stable64_read_moc(blob.payload_addr() as u64, offset, len);
+}
+
+/// Write the bytes of the blob to the region, starting at `offset`
+#[no_mangle]
+pub unsafe extern "C" fn stable_region_write(offset: u64, blob: Value) {
+    let blob = blob.as_blob();
+    let len = blob.len().as_u32() as u64;
+    check_range(offset, len);
+    stable64_write_moc(offset, blob.payload_const() as u64, len);
+}
+
+pub(crate) unsafe fn check_range(offset: u64, len: u64) {
+    let end = match offset.checked_add(len) {
+        Some(end) => end,
+        None => rts_trap_with("StableMemory range overflow"),
+    };
+
+    if end > stable_mem_size_moc().saturating_mul(STABLE_PAGE_SIZE) {
+        rts_trap_with("StableMemory range out of bounds");
+    }
+}
@@ -0,0 +1,119 @@
+//! Immutable arrays written so far by the serializer of stable variables, to write arrays
+//! reachable from several places only once. See Note [shared immutable stable values] in
+//! `codegen/compile.ml`.
+//!
+//! Mutable values are marked as written by overwriting their headers, which does not work for
+//! immutable arrays: an immutable array can be serialized at different types (e.g. `[Nat]` and
+//! `[Int]`, with different encodings), and is only shared between occurrences at the same type.
+//! This table maps an array and a type (the address of the static type hash blob) to the offset of
+//! the array in the serialized data.
+//!
+//! The table is allocated in the dynamic heap but is not a root: serialization does not collect
+//! garbage, and the generated code drops the table with `stable_sharing_reset` when done.
+
+use crate::memory::{alloc_blob, Memory};
+use crate::types::{Bytes, Value};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Returned by `stable_sharing_visit` on the first visit of an array at a type
+pub const FIRST_VISIT: u32 = u32::MAX;
+
+/// Number of slots of a new table
+const INITIAL_SLOTS: u32 = 64;
+
+/// Words per slot: array, type, offset. The array is 0 in unused slots.
+const SLOT_WORDS: u32 = 3;
+
+/// The table, a blob of slots, or a scalar when there is no table
+static mut TABLE: Value = Value::from_scalar(0);
+
+/// Number of used slots
+static mut COUNT: u32 = 0;
+
+/// Drop the table
+#[no_mangle]
+pub unsafe extern "C" fn stable_sharing_reset() {
+    TABLE = Value::from_scalar(0);
+    COUNT = 0;
+}
+
+/// Visit the array `obj` at type `typ`. Returns the offset recorded on the first visit of `obj` at
+/// `typ`, or `FIRST_VISIT` on the first visit, recording `offset`.
+#[ic_mem_fn]
+pub unsafe fn stable_sharing_visit<M: Memory>(
+    mem: &mut M,
+    obj: Value,
+    typ: u32,
+    offset: u32,
+) -> u32 {
+    let mut table = TABLE;
+    if table.is_scalar() {
+        table = alloc_table(mem, INITIAL_SLOTS);
+        TABLE = table;
+    }
+
+    let slot = find(table, obj.get_raw(), typ);
+    let slot_ptr = slot_ptr(table, slot);
+    if *slot_ptr != 0 {
+        return *slot_ptr.add(2);
+    }
+
+    *slot_ptr = obj.get_raw();
+    *slot_ptr.add(1) = typ;
+    *slot_ptr.add(2) = offset;
+    COUNT += 1;
+
+    // Keep the load factor at most 1/2
+    if COUNT * 2 > n_slots(table) {
+        grow(mem);
+    }
+
+    FIRST_VISIT
+}
+
+unsafe fn alloc_table<M: Memory>(mem: &mut M, n_slots: u32) -> Value {
+    let table = alloc_blob(mem, Bytes(n_slots * SLOT_WORDS * 4));
+    let blob = table.as_blob_mut();
+    core::ptr::write_bytes(blob.payload_addr(), 0, blob.len().as_usize());
+    table
+}
+
+unsafe fn n_slots(table: Value) -> u32 {
+    table.as_blob().len().as_u32() / (SLOT_WORDS * 4)
+}
+
+unsafe fn slot_ptr(table: Value, slot: u32) -> *mut u32 {
+    (table.as_blob_mut().payload_addr() as *mut u32).add((slot * SLOT_WORDS) as usize)
+}
+
+/// Slot of the array `obj` at type `typ`, or the unused slot to record it in
+unsafe fn find(table: Value, obj: u32, typ: u32) -> u32 {
+    let mask = n_slots(table) - 1;
+    let mut slot = hash(obj, typ) & mask;
+    loop {
+        let slot_ptr = slot_ptr(table, slot);
+        if *slot_ptr == 0 || (*slot_ptr == obj && *slot_ptr.add(1) == typ) {
+            return slot;
+        }
+        slot = (slot + 1) & mask;
+    }
+}
+
+fn hash(obj: u32, typ: u32) -> u32 {
+    // Objects are word-aligned
+    ((obj >> 2) ^ typ.rotate_left(16)).wrapping_mul(0x9e37_79b9) >> 7
+}
+
+unsafe fn grow<M: Memory>(mem: &mut M) {
+    let old_table = TABLE;
+    let new_table = alloc_table(mem, n_slots(old_table) * 2);
+    for slot in 0..n_slots(old_table) {
+        let old_slot = slot_ptr(old_table, slot);
+        if *old_slot != 0 {
+            let new_slot = slot_ptr(new_table, find(new_table, *old_slot, *old_slot.add(1)));
+            core::ptr::copy_nonoverlapping(old_slot, new_slot, SLOT_WORDS as usize);
+        }
+    }
+    TABLE = new_table;
+}
@@ -15,6 +15,11 @@ const WORD_SIZE: usize = crate::constants::WORD_SIZE as usize;
 const _: () = assert!(size_of::<usize>() == size_of::<u32>());
 const _: () = assert!(size_of::<usize>() == WORD_SIZE);
 
+// Check that the pointer width of the RTS has the platform word size
+const _: () = assert!(
+    <crate::pointer_width::Width as crate::pointer_width::PointerWidth>::WORD_SIZE == WORD_SIZE
+);
+
 // Check that sizes of structs are as expected by the compiler
 // (Expectations are all over the place, e.g. `header_size` definitions in `compile.ml`, calls to `static_closure`, etc.)
 const _: () = assert!(size_of::<Obj>() == 1 * WORD_SIZE);
@@ -25,10 +30,12 @@ const _: () = assert!(size_of::<BigInt>() == 5 * WORD_SIZE);
 const _: () = assert!(size_of::<MutBox>() == 2 * WORD_SIZE);
 const _: () = assert!(size_of::<Some>() == 2 * WORD_SIZE);
 const _: () = assert!(size_of::<Variant>() == 3 * WORD_SIZE);
-const _: () = assert!(size_of::<Concat>() == 4 * WORD_SIZE);
+const _: () = assert!(size_of::<Concat>() == 6 * WORD_SIZE);
 const _: () = assert!(size_of::<Null>() == 1 * WORD_SIZE);
 const _: () = assert!(size_of::<Bits32>() == 2 * WORD_SIZE);
 const _: () = assert!(size_of::<Bits64>() == 3 * WORD_SIZE);
+const _: () = assert!(size_of::<WeakRef>() == 3 * WORD_SIZE);
+const _: () = assert!(size_of::<Finalizable>() == 3 * WORD_SIZE);
 
 // These aren't used generated by the compiler
 const _: () = assert!(size_of::<OneWordFiller>() == 1 * WORD_SIZE);
@@ -49,6 +56,8 @@ const _: () = assert!(align_of::<Concat>() == WORD_SIZE);
 const _: () = assert!(align_of::<Null>() == WORD_SIZE);
 const _: () = assert!(align_of::<Bits32>() == WORD_SIZE);
 const _: () = assert!(align_of::<Bits64>() == WORD_SIZE);
+const _: () = assert!(align_of::<WeakRef>() == WORD_SIZE);
+const _: () = assert!(align_of::<Finalizable>() == WORD_SIZE);
 const _: () = assert!(align_of::<OneWordFiller>() == WORD_SIZE);
 const _: () = assert!(align_of::<FreeSpace>() == WORD_SIZE);
 const _: () = assert!(align_of::<FwdPtr>() == WORD_SIZE);
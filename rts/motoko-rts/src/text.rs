@@ -14,21 +14,35 @@
 //!
 //! From here on, there are stretch goals like:
 //!  - restructure recursive code to not use unbounded Rust stack
-//!  - maybe rebalancing
+//!
+//! Concatenation rebalances the result when it gets too deep for its size, see `text_concat`.
+//! This keeps the depth logarithmic in the size, so the recursive code above does not need deep
+//! Rust stacks even for texts built by many small concatenations.
+//!
+//! `text_split` and `text_join` work on the rope: the parts of a split share the subtexts of the
+//! text, and a join concatenates the parts without copying them.
 
 // Layout of a concat node:
 //
-//      ┌──────────────┬─────────┬───────┬───────┐
-//      │ tag (concat) │ n_bytes │ text1 │ text2 │
-//      └──────────────┴─────────┴───────┴───────┘
+//      ┌──────────────┬─────────┬───────┬───────┬───────┬─────────┐
+//      │ tag (concat) │ n_bytes │ text1 │ text2 │ depth │ n_chars │
+//      └──────────────┴─────────┴───────┴───────┴───────┴─────────┘
 //
 // Note that `CONCAT_LEN` and `BLOB_LEN` are identical, so no need to check the tag to know the
 // size of the text.
+//
+// `depth` is the length of the longest path from the node to a blob. Blobs have depth 0.
+//
+// `n_chars` is the number of characters in the text, or `UNKNOWN_N_CHARS` until the first
+// `text_len` of the node. Counting is left to `text_len` so that concatenation is constant time,
+// and after the first `text_len` it does not need to traverse the concat node again.
 
+use crate::constants::WORD_SIZE;
 use crate::mem_utils::memcpy_bytes;
-use crate::memory::{alloc_blob, Memory};
+use crate::memory::{alloc_array, alloc_blob, Memory};
 use crate::rts_trap_with;
-use crate::types::{size_of, Blob, Bytes, Concat, Stream, Value, TAG_BLOB, TAG_CONCAT};
+use crate::types::{size_of, Array, Blob, Bytes, Concat, Stream, Value, TAG_BLOB, TAG_CONCAT};
+use crate::utf8::utf8_valid;
 
 use core::cmp::{min, Ordering};
 use core::{slice, str};
@@ -41,7 +55,34 @@ const MAX_STR_SIZE: Bytes<u32> = Bytes((1 << 30) - 1);
 // Make this MAX_STR_SIZE to disable the use of ropes completely, e.g. for debugging
 const MIN_CONCAT_SIZE: Bytes<u32> = Bytes(9);
 
-unsafe fn alloc_text_blob<M: Memory>(mem: &mut M, size: Bytes<u32>) -> Value {
+/// `n_chars` of a concat node whose characters are not counted yet. Texts are smaller than
+/// `MAX_STR_SIZE`, so this is never the number of characters of a text.
+pub const UNKNOWN_N_CHARS: u32 = u32::MAX;
+
+/// Number of entries in `MIN_BALANCED_LEN`
+const MAX_BALANCED_DEPTH: usize = 46;
+
+/// `MIN_BALANCED_LEN[d]` is the smallest size of a balanced text of depth `d`, which is the
+/// Fibonacci number `F(d + 2)`. As `MAX_STR_SIZE < F(45)`, balanced texts have depth at most 42.
+const MIN_BALANCED_LEN: [u32; MAX_BALANCED_DEPTH] = min_balanced_len();
+
+const fn min_balanced_len() -> [u32; MAX_BALANCED_DEPTH] {
+    let mut fib = [0; MAX_BALANCED_DEPTH];
+    fib[0] = 1;
+    fib[1] = 2;
+    let mut i = 2;
+    while i < MAX_BALANCED_DEPTH {
+        fib[i] = fib[i - 1] + fib[i - 2];
+        i += 1;
+    }
+    fib
+}
+
+// A concatenation is rebalanced when it's deeper than a balanced text of the same size by more than
+// this. Some slack avoids rebalancing on every concatenation when appending to a text.
+const REBALANCE_SLACK: u32 = 2;
+
+pub(crate) unsafe fn alloc_text_blob<M: Memory>(mem: &mut M, size: Bytes<u32>) -> Value {
     if size > MAX_STR_SIZE {
         rts_trap_with("alloc_text_blob: Text too large");
     }
@@ -60,8 +101,23 @@ pub unsafe fn text_of_str<M: Memory>(mem: &mut M, s: &str) -> Value {
     text_of_ptr_size(mem, s.as_ptr(), Bytes(s.len() as u32))
 }
 
+/// Concatenate two texts. The result is rebalanced when it's too deep for its size.
 #[ic_mem_fn]
 pub unsafe fn text_concat<M: Memory>(mem: &mut M, s1: Value, s2: Value) -> Value {
+    let r = concat(mem, s1, s2);
+
+    if r.tag() == TAG_CONCAT {
+        let depth = text_depth(r);
+        if depth > REBALANCE_SLACK && !is_balanced(text_size(r), depth - REBALANCE_SLACK) {
+            return rebalance(mem, r);
+        }
+    }
+
+    r
+}
+
+/// Concatenate two texts, without rebalancing
+unsafe fn concat<M: Memory>(mem: &mut M, s1: Value, s2: Value) -> Value {
     let blob1_len = text_size(s1);
     let blob2_len = text_size(s2);
 
@@ -110,9 +166,107 @@ pub unsafe fn text_concat<M: Memory>(mem: &mut M, s1: Value, s2: Value) -> Value
     (*r_concat).n_bytes = new_len;
     (*r_concat).text1 = s1;
     (*r_concat).text2 = s2;
+    (*r_concat).depth = 1 + core::cmp::max(text_depth(s1), text_depth(s2));
+    (*r_concat).n_chars = UNKNOWN_N_CHARS;
     r
 }
 
+/// Depth of a text: 0 for blobs, length of the longest path to a blob for concatenations
+pub unsafe fn text_depth(s: Value) -> u32 {
+    if s.tag() == TAG_CONCAT {
+        (*s.as_concat()).depth
+    } else {
+        0
+    }
+}
+
+/// Whether a text of the given size and depth is balanced
+fn is_balanced(len: Bytes<u32>, depth: u32) -> bool {
+    (depth as usize) < MAX_BALANCED_DEPTH && MIN_BALANCED_LEN[depth as usize] <= len.as_u32()
+}
+
+/// Rebalance a text, using the algorithm in "Ropes: an Alternative to Strings" (Boehm, Atkinson,
+/// Plass). The balanced subtexts are merged bottom-up into a forest, where slot `i` holds a text
+/// with size in `MIN_BALANCED_LEN[i]..MIN_BALANCED_LEN[i + 1]`. The forest is then concatenated,
+/// smallest text first.
+///
+/// Slots for larger texts hold texts that come earlier in the result, so the order of the texts is
+/// preserved.
+unsafe fn rebalance<M: Memory>(mem: &mut M, s: Value) -> Value {
+    let mut forest: [Option<Value>; MAX_BALANCED_DEPTH] = [None; MAX_BALANCED_DEPTH];
+
+    add_to_forest(mem, &mut forest, s);
+
+    let mut r: Option<Value> = None;
+    for slot in forest.iter() {
+        if let Some(t) = *slot {
+            r = Some(concat_opt(mem, t, r));
+        }
+    }
+
+    r.unwrap()
+}
+
+/// Add the balanced subtexts of a text to the forest, in order. Recursion depth is bounded by the
+/// text depth, which is kept small by `text_concat`.
+unsafe fn add_to_forest<M: Memory>(
+    mem: &mut M,
+    forest: &mut [Option<Value>; MAX_BALANCED_DEPTH],
+    s: Value,
+) {
+    if s.tag() == TAG_CONCAT && !is_balanced(text_size(s), text_depth(s)) {
+        let concat = s.as_concat();
+        add_to_forest(mem, forest, concat.text1());
+        add_to_forest(mem, forest, concat.text2());
+    } else {
+        add_balanced_to_forest(mem, forest, s);
+    }
+}
+
+unsafe fn add_balanced_to_forest<M: Memory>(
+    mem: &mut M,
+    forest: &mut [Option<Value>; MAX_BALANCED_DEPTH],
+    s: Value,
+) {
+    let len = text_size(s).as_u32();
+
+    // Concatenate the texts in the slots for smaller texts, these come before `s`
+    let mut prefix: Option<Value> = None;
+    let mut i = 0;
+    while len >= MIN_BALANCED_LEN[i + 1] {
+        if let Some(t) = forest[i].take() {
+            prefix = Some(concat_opt(mem, t, prefix));
+        }
+        i += 1;
+    }
+
+    let mut r = match prefix {
+        None => s,
+        Some(prefix) => concat(mem, prefix, s),
+    };
+
+    // Merge with the texts in the slots for larger texts, until the result fits in a slot
+    loop {
+        if let Some(t) = forest[i].take() {
+            r = concat(mem, t, r);
+        }
+
+        if i == MAX_BALANCED_DEPTH - 2 || text_size(r).as_u32() < MIN_BALANCED_LEN[i + 1] {
+            forest[i] = Some(r);
+            return;
+        }
+
+        i += 1;
+    }
+}
+
+unsafe fn concat_opt<M: Memory>(mem: &mut M, s1: Value, s2: Option<Value>) -> Value {
+    match s2 {
+        None => s1,
+        Some(s2) => concat(mem, s1, s2),
+    }
+}
+
 // Leaving breadcrumbs in the destination buffer for which concat node/blob to continue
 // serializing
 #[repr(packed)]
@@ -194,6 +348,27 @@ pub unsafe fn blob_of_text<M: Memory>(mem: &mut M, s: Value) -> Value {
     }
 }
 
+/// Convert a blob to text. Traps when the blob is not valid UTF-8, which includes overlong
+/// encodings, truncated sequences, and UTF-16 surrogates. A valid blob is returned as is, as
+/// blobs are texts.
+///
+/// `Text.of_blob` in the code generator (`decodeUtf8`) returns `null` instead of trapping.
+#[no_mangle]
+pub unsafe extern "C" fn blob_to_text_checked(blob: Value) -> Value {
+    let blob_ptr = blob.as_blob();
+    let len = blob_ptr.len();
+
+    if len > MAX_STR_SIZE {
+        rts_trap_with("blob_to_text_checked: Text too large");
+    }
+
+    if !utf8_valid(blob_ptr.payload_const() as *const _, len.as_u32()) {
+        rts_trap_with("blob_to_text_checked: Blob is not UTF-8");
+    }
+
+    blob
+}
+
 /// Size of the text, in bytes
 #[no_mangle]
 pub unsafe extern "C" fn text_size(s: Value) -> Bytes<u32> {
@@ -330,29 +505,71 @@ pub unsafe extern "C" fn text_compare(s1: Value, s2: Value) -> i32 {
     }
 }
 
-pub(crate) unsafe fn blob_compare(s1: Value, s2: Value) -> i32 {
-    let n1 = text_size(s1);
-    let n2 = text_size(s2);
-    let n = min(n1, n2);
+/// Compares `n` bytes at the given addresses, a word at a time
+unsafe fn compare_bytes(p1: *const u8, p2: *const u8, n: usize) -> Ordering {
+    const WORD_BYTES: usize = WORD_SIZE as usize;
 
-    let payload1 = s1.as_blob().payload_const();
-    let payload2 = s2.as_blob().payload_const();
-    let cmp = libc::memcmp(payload1 as *const _, payload2 as *const _, n.as_usize());
+    let mut i = 0;
 
-    if cmp == 0 {
-        if n1 < n2 {
-            -1
-        } else if n1 > n2 {
-            1
-        } else {
-            0
+    // Words are compared big-endian so that the first differing byte decides
+    while i + WORD_BYTES <= n {
+        let w1 = u32::from_be_bytes((p1.add(i) as *const [u8; WORD_BYTES]).read_unaligned());
+        let w2 = u32::from_be_bytes((p2.add(i) as *const [u8; WORD_BYTES]).read_unaligned());
+        if w1 != w2 {
+            return w1.cmp(&w2);
         }
-    } else {
-        cmp
+        i += WORD_BYTES;
+    }
+
+    while i < n {
+        let cmp = (*p1.add(i)).cmp(&*p2.add(i));
+        if cmp != Ordering::Equal {
+            return cmp;
+        }
+        i += 1;
     }
+
+    Ordering::Equal
 }
 
-/// Length in characters
+/// Lexicographic comparison of two blobs. Returns -1, 0, or 1.
+#[no_mangle]
+pub unsafe extern "C" fn blob_compare(b1: Value, b2: Value) -> i32 {
+    let blob1 = b1.as_blob();
+    let blob2 = b2.as_blob();
+    let n1 = blob1.len();
+    let n2 = blob2.len();
+
+    let cmp = match compare_bytes(
+        blob1.payload_const(),
+        blob2.payload_const(),
+        min(n1, n2).as_usize(),
+    ) {
+        Ordering::Equal => n1.cmp(&n2),
+        cmp => cmp,
+    };
+
+    match cmp {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }
+}
+
+/// Blob equality. Blobs of different lengths are not compared byte-wise.
+#[no_mangle]
+pub unsafe extern "C" fn blob_eq(b1: Value, b2: Value) -> bool {
+    let blob1 = b1.as_blob();
+    let blob2 = b2.as_blob();
+    let n = blob1.len();
+
+    n == blob2.len()
+        && compare_bytes(blob1.payload_const(), blob2.payload_const(), n.as_usize())
+            == Ordering::Equal
+}
+
+/// Length in characters. Concat nodes store their length on the first call, later calls are
+/// constant time.
 #[no_mangle]
 pub unsafe extern "C" fn text_len(text: Value) -> u32 {
     if text.tag() == TAG_BLOB {
@@ -367,8 +584,11 @@ pub unsafe extern "C" fn text_len(text: Value) -> u32 {
         .chars()
         .count() as u32
     } else {
-        let concat = text.as_concat();
-        text_len(concat.text1()) + text_len(concat.text2())
+        let concat = text.as_concat() as *mut Concat;
+        if (*concat).n_chars == UNKNOWN_N_CHARS {
+            (*concat).n_chars = text_len(concat.text1()) + text_len(concat.text2());
+        }
+        (*concat).n_chars
     }
 }
 
@@ -414,3 +634,294 @@ pub unsafe fn text_singleton<M: Memory>(mem: &mut M, char: u32) -> Value {
 
     blob_ptr
 }
+
+/// Text of the `len` bytes of `s` from `offset`. Subtexts of `s` in the range are shared, only the
+/// leaves at the ends of the range are copied. Recursion depth is bounded by the text depth.
+unsafe fn text_substring<M: Memory>(
+    mem: &mut M,
+    s: Value,
+    offset: Bytes<u32>,
+    len: Bytes<u32>,
+) -> Value {
+    if offset == Bytes(0) && len == text_size(s) {
+        return s;
+    }
+
+    if s.tag() == TAG_CONCAT {
+        let s_concat = s.as_concat();
+        let left = s_concat.text1();
+        let left_size = text_size(left);
+
+        if offset + len <= left_size {
+            text_substring(mem, left, offset, len)
+        } else if offset >= left_size {
+            text_substring(mem, s_concat.text2(), offset - left_size, len)
+        } else {
+            let s1 = text_substring(mem, left, offset, left_size - offset);
+            let s2 = text_substring(mem, s_concat.text2(), Bytes(0), offset + len - left_size);
+            concat(mem, s1, s2)
+        }
+    } else {
+        let payload = s.as_blob().payload_const().add(offset.as_usize());
+        text_of_ptr_size(mem, payload, len)
+    }
+}
+
+/// Call `f` with the offset and the blob of the leaves of the text, in order. Recursion depth is
+/// bounded by the text depth.
+unsafe fn for_each_leaf<F: FnMut(Bytes<u32>, *const Blob)>(
+    s: Value,
+    offset: Bytes<u32>,
+    f: &mut F,
+) {
+    if s.tag() == TAG_CONCAT {
+        let s_concat = s.as_concat();
+        let left = s_concat.text1();
+        for_each_leaf(left, offset, f);
+        for_each_leaf(s_concat.text2(), offset + text_size(left), f);
+    } else {
+        f(offset, s.as_blob());
+    }
+}
+
+/// Failure function of the Knuth-Morris-Pratt search for `needle`, a blob: element `i` is the
+/// length of the longest proper prefix of `needle[..=i]` that is also a suffix of it. Allocated as
+/// a blob of `u32`s, which is garbage after the search.
+unsafe fn kmp_table<M: Memory>(mem: &mut M, needle: Value) -> *const u32 {
+    let needle = needle.as_blob();
+    let len = needle.len().as_u32();
+    let table = alloc_blob(mem, Bytes(len * WORD_SIZE))
+        .as_blob_mut()
+        .payload_addr() as *mut u32;
+
+    let mut k = 0;
+    for i in 0..len {
+        let c = needle.get(i);
+        while k > 0 && needle.get(k) != c {
+            k = *table.add(k as usize - 1);
+        }
+        if i > 0 && needle.get(k) == c {
+            k += 1;
+        }
+        *table.add(i as usize) = k;
+    }
+
+    table
+}
+
+/// Call `f` with the offset of the matches of `needle`, a non-empty blob, in the text, in order.
+/// `table` is the failure function of `needle` (see `kmp_table`). `f` returns whether to continue
+/// the search.
+///
+/// Matches don't overlap: the search continues after the end of a match, so `aa` matches `aaa`
+/// once, at offset 0. The leaves of the text are searched in order with the Knuth-Morris-Pratt
+/// algorithm, so the search is linear in the size of the text and does not copy the text, even
+/// when a match spans leaves.
+unsafe fn for_each_match<F: FnMut(Bytes<u32>) -> bool>(
+    text: Value,
+    needle: Value,
+    table: *const u32,
+    f: &mut F,
+) {
+    let needle = needle.as_blob();
+    let needle_len = needle.len().as_u32();
+
+    // Number of bytes of `needle` matched before the current byte
+    let mut k = 0;
+    let mut done = false;
+    for_each_leaf(text, Bytes(0), &mut |offset, blob| {
+        let mut i = 0;
+        while !done && i < blob.len().as_u32() {
+            let c = blob.get(i);
+            while k > 0 && needle.get(k) != c {
+                k = *table.add(k as usize - 1);
+            }
+            if needle.get(k) == c {
+                k += 1;
+            }
+            if k == needle_len {
+                done = !f(offset + Bytes(i + 1) - Bytes(needle_len));
+                k = 0;
+            }
+            i += 1;
+        }
+    });
+}
+
+/// Call `f` with the offset and the size of the parts of the text separated by `delim`, a blob, in
+/// order. `table` is the failure function of `delim` (see `kmp_table`). Delimiters are matched from
+/// the start of the text and don't overlap (see `for_each_match`). An empty delimiter separates
+/// the characters.
+///
+/// Matches are always at character boundaries, as UTF-8 encoded characters cannot start in the
+/// middle of another character.
+unsafe fn for_each_part<F: FnMut(Bytes<u32>, Bytes<u32>)>(
+    text: Value,
+    delim: Value,
+    table: *const u32,
+    f: &mut F,
+) {
+    let delim_len = delim.as_blob().len();
+
+    if delim_len == Bytes(0) {
+        // Characters don't span leaves, as leaves are texts
+        for_each_leaf(text, Bytes(0), &mut |offset, blob| {
+            let mut i = 0;
+            while i < blob.len().as_u32() {
+                let mut size = 0;
+                decode_code_point(blob.payload_const().add(i as usize), &mut size);
+                f(offset + Bytes(i), Bytes(size));
+                i += size;
+            }
+        });
+        return;
+    }
+
+    // Start of the current part
+    let mut start = Bytes(0);
+    for_each_match(text, delim, table, &mut |pos| {
+        f(start, pos - start);
+        start = pos + delim_len;
+        true
+    });
+    f(start, text_size(text) - start);
+}
+
+/// Split the text on the delimiter. Returns an array of the parts, with an empty text for each
+/// delimiter at the start or at the end of the text, and between adjacent delimiters. An empty
+/// delimiter splits the text into its characters, and an empty text into no parts.
+///
+/// The parts share the leaves of the text when they can, see `text_substring`.
+#[ic_mem_fn]
+pub unsafe fn text_split<M: Memory>(mem: &mut M, text: Value, delim: Value) -> Value {
+    let delim = blob_of_text(mem, delim);
+    let table = kmp_table(mem, delim);
+
+    let mut n_parts = 0;
+    for_each_part(text, delim, table, &mut |_, _| n_parts += 1);
+
+    let parts = alloc_array(mem, n_parts);
+    let mut i = 0;
+    for_each_part(text, delim, table, &mut |offset, len| {
+        let part = text_substring(mem, text, offset, len);
+        parts.as_array().set(i, part);
+        i += 1;
+    });
+
+    parts
+}
+
+/// Concatenate the texts in the array, separated by `sep`. The texts and the separator are shared,
+/// and concatenated in a balanced tree.
+#[ic_mem_fn]
+pub unsafe fn text_join<M: Memory>(mem: &mut M, parts: Value, sep: Value) -> Value {
+    let parts = parts.as_array();
+    if parts.len() == 0 {
+        return alloc_text_blob(mem, Bytes(0));
+    }
+
+    // Element `2 * i` is part `i`, odd elements are separators
+    join_elements(mem, parts, sep, 0, 2 * parts.len() - 1)
+}
+
+/// Concatenate the elements `from..to` of the joined texts, see `text_join`. The range is not
+/// empty.
+unsafe fn join_elements<M: Memory>(
+    mem: &mut M,
+    parts: *mut Array,
+    sep: Value,
+    from: u32,
+    to: u32,
+) -> Value {
+    if to - from == 1 {
+        return if from % 2 == 0 {
+            parts.get(from / 2)
+        } else {
+            sep
+        };
+    }
+
+    let mid = from + (to - from) / 2;
+    let left = join_elements(mem, parts, sep, from, mid);
+    let right = join_elements(mem, parts, sep, mid, to);
+    concat(mem, left, right)
+}
+
+/// Offset of the first match of `needle`, a blob, in the text. An empty needle matches at 0.
+unsafe fn find<M: Memory>(mem: &mut M, text: Value, needle: Value) -> Option<Bytes<u32>> {
+    if needle.as_blob().len() == Bytes(0) {
+        return Some(Bytes(0));
+    }
+
+    let table = kmp_table(mem, needle);
+    let mut found = None;
+    for_each_match(text, needle, table, &mut |pos| {
+        found = Some(pos);
+        false
+    });
+    found
+}
+
+/// Whether `needle` occurs in the text. The empty text occurs in every text.
+#[ic_mem_fn]
+pub unsafe fn text_contains<M: Memory>(mem: &mut M, text: Value, needle: Value) -> bool {
+    let needle = blob_of_text(mem, needle);
+    find(mem, text, needle).is_some()
+}
+
+/// Index, in characters, of the first occurrence of `needle` in the text, or -1 if it does not
+/// occur. The empty text occurs at index 0.
+#[ic_mem_fn]
+pub unsafe fn text_index_of<M: Memory>(mem: &mut M, text: Value, needle: Value) -> i32 {
+    let needle = blob_of_text(mem, needle);
+    let pos = match find(mem, text, needle) {
+        Some(pos) => pos,
+        None => return -1,
+    };
+
+    // Count the characters before the match: the bytes that are not UTF-8 continuation bytes
+    let mut n_chars = 0;
+    for_each_leaf(text, Bytes(0), &mut |offset, blob| {
+        let end = min(
+            blob.len().as_u32(),
+            pos.as_u32().saturating_sub(offset.as_u32()),
+        );
+        for i in 0..end {
+            if blob.get(i) & 0b1100_0000 != 0b1000_0000 {
+                n_chars += 1;
+            }
+        }
+    });
+    n_chars
+}
+
+/// Replace the occurrences of `needle` in the text with `repl`. Occurrences are replaced from the
+/// start of the text and don't overlap, so replacing `aa` in `aaa` with `b` gives `ba`. An empty
+/// needle occurs before every character and at the end, so `repl` is inserted between the
+/// characters and at both ends, and replaces the empty text.
+///
+/// The text is returned as is when `needle` does not occur. Otherwise the result shares the parts
+/// of the text between the occurrences (see `text_split`) and `repl`.
+#[ic_mem_fn]
+pub unsafe fn text_replace<M: Memory>(
+    mem: &mut M,
+    text: Value,
+    needle: Value,
+    repl: Value,
+) -> Value {
+    if text_size(needle) == Bytes(0) {
+        if text_size(text) == Bytes(0) {
+            return repl;
+        }
+        let chars = text_split(mem, text, needle);
+        let inner = text_join(mem, chars, repl);
+        let result = text_concat(mem, repl, inner);
+        return text_concat(mem, result, repl);
+    }
+
+    let parts = text_split(mem, text, needle);
+    if parts.as_array().len() == 1 {
+        return text;
+    }
+    text_join(mem, parts, repl)
+}
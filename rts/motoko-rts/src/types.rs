@@ -154,6 +154,22 @@ impl From<Words<u32>> for Bytes<u32> {
 }
 
 /// A value in a heap slot
+///
+/// A slot holds either a skewed pointer or a scalar, told apart by the low bit:
+///
+///  - Pointers are skewed (see `skew`): a pointer to an object at the word-aligned address `a` is
+///    stored as `a - 1`, so the low bit is set. (`Value` was called `SkewedPtr` before it also
+///    covered scalars.)
+///  - Scalars are stored shifted left by one (`from_scalar`, `from_signed_scalar`), so the low bit
+///    is clear.
+///
+/// The generated code stores `Nat` and `Int` values in the range -2^30 to 2^30 - 1 as scalars,
+/// and only allocates a bignum (`TAG_BIGINT`) when the result of an operation is out of that
+/// range (see `BitTagged` and `MakeCompact` in `compile.ml`). Signed scalars are untagged with an
+/// arithmetic shift, see `get_signed_scalar`.
+///
+/// GCs and visitors only follow fields with the low bit set (`is_ptr`), so scalars are never
+/// traced, even when the scalar bits happen to be the address of an object.
 #[repr(transparent)]
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Value(u32);
@@ -269,9 +285,9 @@ impl Value {
     }
 
     /// Get the pointer as `Array`. In debug mode panics if the value is not a pointer or the
-    /// pointed object is not an `Array`.
+    /// pointed object is not an `Array` (frozen or not).
     pub unsafe fn as_array(self) -> *mut Array {
-        debug_assert_eq!(self.tag(), TAG_ARRAY);
+        debug_assert!(self.tag() == TAG_ARRAY || self.tag() == TAG_FROZEN_ARRAY);
         self.get_ptr() as *mut Array
     }
 
@@ -294,6 +310,13 @@ impl Value {
         self.get_ptr() as *mut Blob
     }
 
+    /// Get the pointer as `WeakRef`. In debug mode panics if the value is not a pointer or the
+    /// pointed object is not a `WeakRef`.
+    pub unsafe fn as_weak_ref(self) -> *mut WeakRef {
+        debug_assert_eq!(self.tag(), TAG_WEAK_REF);
+        self.get_ptr() as *mut WeakRef
+    }
+
     /// Get the pointer as `Stream`, which is a glorified `Blob`.
     /// In debug mode panics if the value is not a pointer or the
     /// pointed object is not a `Blob`.
@@ -351,6 +374,34 @@ pub const TAG_CONCAT: Tag = 25;
 pub const TAG_NULL: Tag = 27;
 pub const TAG_ONE_WORD_FILLER: Tag = 29;
 pub const TAG_FREE_SPACE: Tag = 31;
+pub const TAG_WEAK_REF: Tag = 33;
+pub const TAG_FINALIZABLE: Tag = 35;
+pub const TAG_FROZEN_ARRAY: Tag = 37;
+
+/// Largest tag. When adding a new tag update this.
+pub const MAX_TAG: Tag = TAG_FROZEN_ARRAY;
+
+/// Whether an object header holds a tag, rather than a field address. Mark-compact GC "threads"
+/// pointer fields by storing the field address in the header of the pointed object, and the old
+/// header in the field (see `gc::mark_compact::thread`). Field addresses are word aligned and tags
+/// are odd, so the lowest bit is enough to tell them apart.
+///
+/// Threaded fields temporarily hold tags as values. For the GC to not follow these as pointers
+/// the dynamic heap needs to start after `unskew(MAX_TAG)`, see `check_heap_base` and
+/// `pointer_width::min_heap_base`.
+#[inline]
+pub fn is_tag(header: u32) -> bool {
+    header & 0b1 != 0
+}
+
+/// Check the invariant explained in `is_tag`. Should be called when the heap base is configured
+/// and in GC entry points.
+#[inline]
+pub fn check_heap_base(heap_base: u32) {
+    debug_assert!(
+        heap_base as usize >= crate::pointer_width::min_heap_base::<crate::pointer_width::Width>()
+    );
+}
 
 // Common parts of any object. Other object pointers can be coerced into a pointer to this.
 #[repr(C)] // See the note at the beginning of this module
@@ -490,16 +541,10 @@ impl Blob {
 
         let slop = current_len_words - new_len_words;
 
-        if slop == Words(1) {
-            let filler = (self.payload_addr() as *mut u32).add(new_len_words.as_usize())
-                as *mut OneWordFiller;
-            (*filler).header.tag = TAG_ONE_WORD_FILLER;
-        } else if slop != Words(0) {
-            let filler =
-                (self.payload_addr() as *mut u32).add(new_len_words.as_usize()) as *mut FreeSpace;
-            (*filler).header.tag = TAG_FREE_SPACE;
-            (*filler).words = slop - Words(1);
-        }
+        write_filler(
+            (self.payload_addr() as *mut u32).add(new_len_words.as_usize()) as usize,
+            slop,
+        );
 
         (*self).len = new_len;
     }
@@ -567,6 +612,25 @@ pub struct MutBox {
     pub field: Value,
 }
 
+/// A weak reference, see `weak_ref` module
+#[repr(C)] // See the note at the beginning of this module
+pub struct WeakRef {
+    pub header: Obj,
+    pub target: Value,
+    /// Next weak reference in the GC registry. Null when not in the registry.
+    pub next: *mut WeakRef,
+}
+
+/// An object with a finalizer, see `finalizer` module
+#[repr(C)] // See the note at the beginning of this module
+pub struct Finalizable {
+    pub header: Obj,
+    /// Next object in the finalizer registry or queue. Does not keep the object alive.
+    pub next: Value,
+    /// Finalizer index, as passed to `register_finalizer`
+    pub index: u32,
+}
+
 #[repr(C)] // See the note at the beginning of this module
 pub struct Some {
     pub header: Obj,
@@ -586,6 +650,8 @@ pub struct Concat {
     pub n_bytes: Bytes<u32>,
     pub text1: Value,
     pub text2: Value,
+    pub depth: u32,
+    pub n_chars: u32,
 }
 
 impl Concat {
@@ -637,6 +703,20 @@ pub struct FreeSpace {
     pub words: Words<u32>,
 }
 
+/// Fill the given number of words at `addr` with a filler object, to keep the heap walkable
+pub(crate) unsafe fn write_filler(addr: usize, words: Words<u32>) {
+    crate::mem_utils::zero_free_space(addr, addr + words.to_bytes().as_usize());
+
+    if words == Words(1) {
+        let filler = addr as *mut OneWordFiller;
+        (*filler).header.tag = TAG_ONE_WORD_FILLER;
+    } else if words != Words(0) {
+        let filler = addr as *mut FreeSpace;
+        (*filler).header.tag = TAG_FREE_SPACE;
+        (*filler).words = words - Words(1);
+    }
+}
+
 impl FreeSpace {
     /// Size of the free space (includes object header)
     pub unsafe fn size(self: *mut Self) -> Words<u32> {
@@ -647,7 +727,13 @@ impl FreeSpace {
 /// Returns object size in words
 pub(crate) unsafe fn object_size(obj: usize) -> Words<u32> {
     let obj = obj as *mut Obj;
-    match obj.tag() {
+    object_size_with_tag(obj, obj.tag())
+}
+
+/// Same as `object_size`, but with the object tag given. Used when the object header is
+/// overwritten, as in threaded compaction.
+pub(crate) unsafe fn object_size_with_tag(obj: *mut Obj, tag: Tag) -> Words<u32> {
+    match tag {
         TAG_OBJECT => {
             let object = obj as *mut Object;
             let size = object.size();
@@ -656,7 +742,7 @@ pub(crate) unsafe fn object_size(obj: usize) -> Words<u32> {
 
         TAG_OBJ_IND => size_of::<ObjInd>(),
 
-        TAG_ARRAY => {
+        TAG_ARRAY | TAG_FROZEN_ARRAY => {
             let array = obj as *mut Array;
             let size = array.len();
             size_of::<Array>() + Words(size)
@@ -703,6 +789,10 @@ pub(crate) unsafe fn object_size(obj: usize) -> Words<u32> {
             free_space.size()
         }
 
+        TAG_WEAK_REF => size_of::<WeakRef>(),
+
+        TAG_FINALIZABLE => size_of::<Finalizable>(),
+
         _ => {
             rts_trap_with("object_size: invalid object tag");
         }
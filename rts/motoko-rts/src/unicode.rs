@@ -0,0 +1,276 @@
+//! Unicode case mapping and normalization of texts.
+//!
+//! `text_to_lower` and `text_to_upper` use the full case mappings of `core` (Unicode version
+//! `core::char::UNICODE_VERSION`), so a character can map to several characters, e.g. `ß` is
+//! upper-cased to `SS`. The mappings are locale and context independent: `I` is lower-cased to `i`
+//! and `İ` (U+0130) to `i̇` (`i` followed by U+0307), as outside of Turkish and Azeri, and `Σ` is
+//! always lower-cased to `σ`, also at the end of a word.
+//!
+//! `text_nfd` and `text_nfc` normalize to the canonical decomposition (NFD) and to the canonical
+//! composition (NFC) forms. The canonical combining classes, decompositions and compositions are in
+//! the generated `tables` (see `tables::UNICODE_VERSION`), except for Hangul syllables, which are
+//! decomposed and composed algorithmically.
+//!
+//! Texts are flattened with `blob_of_text`, and all functions return a new text, or the argument
+//! when it is unchanged.
+
+mod tables;
+
+pub use tables::UNICODE_VERSION;
+
+use crate::memory::{alloc_blob, Memory};
+use crate::rts_trap_with;
+use crate::text::{alloc_text_blob, blob_of_text};
+use crate::types::{Bytes, Value};
+
+use core::{slice, str};
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Lower-case the text
+#[ic_mem_fn]
+pub unsafe fn text_to_lower<M: Memory>(mem: &mut M, text: Value) -> Value {
+    map_chars(mem, text, |c, f| c.to_lowercase().for_each(f))
+}
+
+/// Upper-case the text
+#[ic_mem_fn]
+pub unsafe fn text_to_upper<M: Memory>(mem: &mut M, text: Value) -> Value {
+    map_chars(mem, text, |c, f| c.to_uppercase().for_each(f))
+}
+
+/// Normalize the text to NFD
+#[ic_mem_fn]
+pub unsafe fn text_nfd<M: Memory>(mem: &mut M, text: Value) -> Value {
+    normalize(mem, text, false)
+}
+
+/// Normalize the text to NFC
+#[ic_mem_fn]
+pub unsafe fn text_nfc<M: Memory>(mem: &mut M, text: Value) -> Value {
+    normalize(mem, text, true)
+}
+
+/// Contents of the text, flattened if needed
+unsafe fn text_str<'a, M: Memory>(mem: &mut M, text: Value) -> &'a str {
+    let blob = blob_of_text(mem, text).as_blob();
+    str::from_utf8_unchecked(slice::from_raw_parts(
+        blob.payload_const(),
+        blob.len().as_usize(),
+    ))
+}
+
+/// Map every character of the text to the characters passed by `map` to its callback
+unsafe fn map_chars<M: Memory, F: Fn(char, &mut dyn FnMut(char))>(
+    mem: &mut M,
+    text: Value,
+    map: F,
+) -> Value {
+    let str = text_str(mem, text);
+
+    // First pass: size of the result
+    let mut size: u32 = 0;
+    let mut changed = false;
+    for c in str.chars() {
+        let mut n = 0;
+        map(c, &mut |d| {
+            size += d.len_utf8() as u32;
+            changed |= n != 0 || d != c;
+            n += 1;
+        });
+        changed |= n != 1;
+    }
+
+    if !changed {
+        return text;
+    }
+
+    // Second pass: write the result
+    let blob = alloc_text_blob(mem, Bytes(size));
+    let mut dst = blob.as_blob_mut().payload_addr();
+    for c in str.chars() {
+        map(c, &mut |d| dst = write_char(dst, d));
+    }
+
+    blob
+}
+
+unsafe fn write_char(dst: *mut u8, c: char) -> *mut u8 {
+    let len = c.encode_utf8(slice::from_raw_parts_mut(dst, 4)).len();
+    dst.add(len)
+}
+
+unsafe fn normalize<M: Memory>(mem: &mut M, text: Value, compose: bool) -> Value {
+    let str = text_str(mem, text);
+
+    // Characters below U+00C0 don't decompose and are starters
+    if str.chars().all(|c| c < '\u{C0}') {
+        return text;
+    }
+
+    // Decompose into a scratch buffer of characters
+    let mut n: u32 = 0;
+    for c in str.chars() {
+        decompose(c as u32, &mut |_| n += 1);
+    }
+
+    if n > u32::MAX / 4 {
+        rts_trap_with("normalize: Text too large");
+    }
+
+    let buf_blob = alloc_blob(mem, Bytes(n * 4));
+    let buf = slice::from_raw_parts_mut(
+        buf_blob.as_blob_mut().payload_addr() as *mut u32,
+        n as usize,
+    );
+
+    let mut i = 0;
+    for c in str.chars() {
+        decompose(c as u32, &mut |d| {
+            buf[i] = d;
+            i += 1;
+        });
+    }
+
+    reorder(buf);
+
+    let len = if compose {
+        compose_chars(buf)
+    } else {
+        buf.len()
+    };
+    let chars = &buf[..len];
+
+    // Encode the result
+    let size: u32 = chars
+        .iter()
+        .map(|c| char::from_u32_unchecked(*c).len_utf8() as u32)
+        .sum();
+    let blob = alloc_text_blob(mem, Bytes(size));
+    let mut dst = blob.as_blob_mut().payload_addr();
+    for c in chars {
+        dst = write_char(dst, char::from_u32_unchecked(*c));
+    }
+
+    blob
+}
+
+// Hangul syllable decomposition constants, see section 3.12 of the Unicode standard
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+/// Canonical combining class of the character
+fn combining_class(c: u32) -> u8 {
+    let table = &tables::COMBINING_CLASSES;
+    // Index of the last run starting at or before `c`. The first run starts at 0.
+    let idx = table.partition_point(|run| run >> 8 <= c) - 1;
+    table[idx] as u8
+}
+
+/// Pass the full canonical decomposition of the character to `f`
+fn decompose(c: u32, f: &mut dyn FnMut(u32)) {
+    if c.wrapping_sub(S_BASE) < S_COUNT {
+        let s = c - S_BASE;
+        f(L_BASE + s / N_COUNT);
+        f(V_BASE + (s % N_COUNT) / T_COUNT);
+        if s % T_COUNT != 0 {
+            f(T_BASE + s % T_COUNT);
+        }
+        return;
+    }
+
+    let table = &tables::DECOMPOSITIONS;
+    match table.binary_search_by_key(&u64::from(c), |entry| entry >> 42) {
+        Ok(idx) => {
+            let first = ((table[idx] >> 21) & 0x1F_FFFF) as u32;
+            let second = (table[idx] & 0x1F_FFFF) as u32;
+            decompose(first, f);
+            if second != 0 {
+                decompose(second, f);
+            }
+        }
+        Err(_) => f(c),
+    }
+}
+
+/// Primary composite of the two characters
+fn compose_pair(first: u32, second: u32) -> Option<u32> {
+    if first.wrapping_sub(L_BASE) < L_COUNT && second.wrapping_sub(V_BASE) < V_COUNT {
+        let l = first - L_BASE;
+        let v = second - V_BASE;
+        return Some(S_BASE + (l * V_COUNT + v) * T_COUNT);
+    }
+
+    let s = first.wrapping_sub(S_BASE);
+    if s < S_COUNT && s % T_COUNT == 0 && second.wrapping_sub(T_BASE + 1) < T_COUNT - 1 {
+        return Some(first + (second - T_BASE));
+    }
+
+    let table = &tables::COMPOSITIONS;
+    let key = (u64::from(first) << 21) | u64::from(second);
+    table
+        .binary_search_by_key(&key, |entry| entry >> 21)
+        .ok()
+        .map(|idx| (table[idx] & 0x1F_FFFF) as u32)
+}
+
+/// Canonical ordering: sort runs of non-starters by combining class, keeping the order of
+/// characters of the same class
+fn reorder(chars: &mut [u32]) {
+    for i in 1..chars.len() {
+        let c = chars[i];
+        let class = combining_class(c);
+        if class == 0 {
+            continue;
+        }
+        let mut j = i;
+        while j > 0 && combining_class(chars[j - 1]) > class {
+            chars[j] = chars[j - 1];
+            j -= 1;
+        }
+        chars[j] = c;
+    }
+}
+
+/// Canonical composition of decomposed and reordered characters, in place. Returns the number of
+/// characters after composition.
+fn compose_chars(chars: &mut [u32]) -> usize {
+    // Index of the last starter in the result
+    let mut starter: Option<usize> = None;
+    // Combining class of the last character in the result
+    let mut last_class: u8 = 0;
+    let mut len = 0;
+
+    for i in 0..chars.len() {
+        let c = chars[i];
+        let class = combining_class(c);
+
+        if let Some(starter) = starter {
+            // A character between the starter and `c` blocks `c` when it is a starter, or has the
+            // same or higher combining class
+            let blocked = len != starter + 1 && (last_class == 0 || last_class >= class);
+            if !blocked {
+                if let Some(composite) = compose_pair(chars[starter], c) {
+                    chars[starter] = composite;
+                    continue;
+                }
+            }
+        }
+
+        if class == 0 {
+            starter = Some(len);
+        }
+        last_class = class;
+        chars[len] = c;
+        len += 1;
+    }
+
+    len
+}
@@ -23,7 +23,7 @@ pub unsafe fn visit_pointer_fields<F>(
             }
         }
 
-        TAG_ARRAY => {
+        TAG_ARRAY | TAG_FROZEN_ARRAY => {
             let array = obj as *mut Array;
             let array_payload = array.payload_addr();
             for i in 0..array.len() {
@@ -93,6 +93,10 @@ pub unsafe fn visit_pointer_fields<F>(
             // These don't have pointers, skip
         }
 
+        TAG_WEAK_REF | TAG_FINALIZABLE => {
+            // Fields of these don't keep objects alive, see `visit_weak_fields`
+        }
+
         TAG_NULL => {
             rts_trap_with("encountered NULL object tag in visit_pointer_fields");
         }
@@ -103,6 +107,79 @@ pub unsafe fn visit_pointer_fields<F>(
     }
 }
 
+/// Passes fields that don't keep the pointed objects alive to the callback, if they point to
+/// dynamic heap: target of a weak reference, and the link of a finalizable object (see `weak_ref`
+/// and `finalizer` modules). For updating pointers after moving objects, as `visit_pointer_fields`
+/// skips these fields.
+pub unsafe fn visit_weak_fields<F>(obj: *mut Obj, tag: Tag, heap_base: usize, visit_ptr_field: F)
+where
+    F: FnOnce(*mut Value),
+{
+    let field_addr = match tag {
+        TAG_WEAK_REF => &mut (*(obj as *mut WeakRef)).target,
+        TAG_FINALIZABLE => &mut (*(obj as *mut Finalizable)).next,
+        _ => return,
+    };
+
+    if pointer_to_dynamic_heap(field_addr, heap_base) {
+        visit_ptr_field(field_addr);
+    }
+}
+
+/// Passes every field of an object (every word after the header) to the callback, with whether
+/// the field is a pointer field. Pointer fields are the fields that `visit_pointer_fields` visits
+/// when they point to the dynamic heap, they can also hold scalars. Other fields are passed as
+/// plain words: lengths, hashes and function ids, payloads of blobs and big numbers, and weak
+/// fields (see `visit_weak_fields`).
+///
+/// For tools walking the heap. The object should be a valid object, not a forwarding pointer.
+pub unsafe fn for_each_field<F>(obj: *mut Obj, mut visit_field: F)
+where
+    F: FnMut(*mut Value, bool),
+{
+    let single = |field: *mut Value| (field, field.add(1));
+
+    // Pointer fields are consecutive in all objects
+    let (pointers_start, pointers_end) = match obj.tag() {
+        TAG_OBJECT => {
+            let obj = obj as *mut Object;
+            let payload = obj.payload_addr();
+            (payload, payload.add(obj.size() as usize))
+        }
+        TAG_ARRAY | TAG_FROZEN_ARRAY => {
+            let array = obj as *mut Array;
+            let payload = array.payload_addr();
+            (payload, payload.add(array.len() as usize))
+        }
+        TAG_CLOSURE => {
+            let closure = obj as *mut Closure;
+            let payload = closure.payload_addr();
+            (payload, payload.add(closure.size() as usize))
+        }
+        TAG_MUTBOX => single(&mut (*(obj as *mut MutBox)).field),
+        TAG_SOME => single(&mut (*(obj as *mut Some)).field),
+        TAG_VARIANT => single(&mut (*(obj as *mut Variant)).field),
+        TAG_OBJ_IND => single(&mut (*(obj as *mut ObjInd)).field),
+        TAG_CONCAT => {
+            let text1: *mut Value = &mut (*(obj as *mut Concat)).text1;
+            (text1, text1.add(2))
+        }
+        _ => (core::ptr::null_mut(), core::ptr::null_mut()),
+    };
+
+    // Traps on invalid tags
+    let size = object_size(obj as usize);
+
+    let fields_start = (obj as *mut Value).add(size_of::<Obj>().as_usize());
+    let fields_end = (obj as *mut Value).add(size.as_usize());
+
+    let mut field = fields_start;
+    while field < fields_end {
+        visit_field(field, field >= pointers_start && field < pointers_end);
+        field = field.add(1);
+    }
+}
+
 pub unsafe fn pointer_to_dynamic_heap(field_addr: *mut Value, heap_base: usize) -> bool {
     // NB. pattern matching on `field_addr.get()` generates inefficient code
     let field_value = (*field_addr).get_raw();
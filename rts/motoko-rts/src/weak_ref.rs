@@ -0,0 +1,87 @@
+//! Weak references. A weak reference (`WeakRef`) points to an object without keeping it alive.
+//! When the object is collected, the GC clears the reference, and `weak_ref_get` returns
+//! `WEAK_REF_CLEARED` instead of the object.
+//!
+//! Weak references are immutable, so the target is always allocated before the weak reference.
+//! This means the generational GC does not need to remember weak references: a young target is
+//! only pointed by young weak references.
+//!
+//! GCs skip the target field when tracing (see `visit_pointer_fields`). Weak references found
+//! while tracing are added to a registry (`register_weak_ref`), and after tracing the GC walks the
+//! registry (`drain_weak_refs`) to clear the references to dead objects, and update the ones to
+//! live objects.
+//!
+//! The registry is a linked list of weak references, using the `next` fields of the registered
+//! weak references, so it does not need allocation. Weak references are only registered during
+//! GC, and `next` is null outside of GC.
+
+use crate::memory::Memory;
+use crate::types::{size_of, Value, WeakRef, TAG_WEAK_REF};
+
+use core::ptr::null_mut;
+
+use motoko_rts_macros::ic_mem_fn;
+
+/// Target of a weak reference after the target is collected
+pub const WEAK_REF_CLEARED: Value = Value::from_scalar(0);
+
+/// Head of the registry. Last weak reference in the registry points to itself.
+static mut WEAK_REFS: *mut WeakRef = null_mut();
+
+/// Allocate a weak reference to `target`, which should be a pointer
+#[ic_mem_fn]
+pub unsafe fn alloc_weak_ref<M: Memory>(mem: &mut M, target: Value) -> Value {
+    debug_assert!(target.is_ptr());
+
+    let ptr = mem.alloc_words(size_of::<WeakRef>());
+    // NB. Cannot use `as_weak_ref` here as we didn't write the header yet
+    let weak_ref = ptr.get_ptr() as *mut WeakRef;
+    (*weak_ref).header.tag = TAG_WEAK_REF;
+    (*weak_ref).target = target;
+    (*weak_ref).next = null_mut();
+
+    // Incremental marking does not scan new objects, keep the target alive in the current GC cycle
+    crate::gc::mark_compact::incremental::incremental_gc_write_barrier(&mut (*weak_ref).target);
+
+    ptr
+}
+
+/// Get the target of a weak reference, or `WEAK_REF_CLEARED` if the target is collected
+#[no_mangle]
+pub unsafe extern "C" fn weak_ref_get(weak_ref: Value) -> Value {
+    let weak_ref = weak_ref.as_weak_ref();
+
+    // The target may be reachable only from the weak reference. When incremental marking is in
+    // progress the mutator may store it in an already scanned object, so mark it.
+    crate::gc::mark_compact::incremental::incremental_gc_write_barrier(&mut (*weak_ref).target);
+
+    (*weak_ref).target
+}
+
+/// Add a weak reference to the registry, if it's not already in the registry
+pub(crate) unsafe fn register_weak_ref(weak_ref: *mut WeakRef) {
+    if !(*weak_ref).next.is_null() {
+        return;
+    }
+
+    (*weak_ref).next = if WEAK_REFS.is_null() {
+        weak_ref
+    } else {
+        WEAK_REFS
+    };
+
+    WEAK_REFS = weak_ref;
+}
+
+/// Call the callback on the weak references in the registry, and empty the registry
+pub(crate) unsafe fn drain_weak_refs<F: FnMut(*mut WeakRef)>(mut f: F) {
+    let mut weak_ref = WEAK_REFS;
+    WEAK_REFS = null_mut();
+
+    while !weak_ref.is_null() {
+        let next = (*weak_ref).next;
+        (*weak_ref).next = null_mut();
+        f(weak_ref);
+        weak_ref = if next == weak_ref { null_mut() } else { next };
+    }
+}
@@ -286,6 +286,8 @@ module E = struct
       (* Sanity check: Nothing should bump end_of_static_memory once it has been read *)
     static_roots : int32 list ref;
       (* GC roots in static memory. (Everything that may be mutable.) *)
+    alloc_sites : int32 ref;
+      (* Number of allocation sites, with --alloc-profiling *)
 
     (* Metadata *)
     args : (bool * string) option ref;
@@ -327,6 +329,7 @@ module E = struct
     static_memory = ref [];
     static_memory_frozen = ref false;
     static_roots = ref [];
+    alloc_sites = ref 0l;
     (* Metadata *)
     args = ref None;
     service = ref None;
@@ -564,6 +567,12 @@ module E = struct
   let get_static_memory env =
     !(env.static_memory)
 
+  (* Allocation site ids for rts/motoko-rts/src/alloc_profile.rs, in code generation order *)
+  let next_alloc_site (env : t) =
+    let site = !(env.alloc_sites) in
+    env.alloc_sites := Int32.add site 1l;
+    site
+
   let mem_size env =
     Int32.(add (div (get_end_of_static_memory env) page_size) 1l)
 
@@ -574,6 +583,8 @@ module E = struct
     | Mo_config.Flags.Copying -> "copying"
     in
     let gc_fn = if !Flags.force_gc then gc_fn else "schedule_" ^ gc_fn in
+    (* Samples of rts/motoko-rts/src/gc/live_history.rs are numbered by message *)
+    call_import env "rts" "note_message_end" ^^
     call_import env "rts" (gc_fn ^ "_gc")
 end
 
@@ -825,6 +836,15 @@ module RTS = struct
     E.add_func_import env "rts" "memcpy" [I32Type; I32Type; I32Type] [I32Type]; (* standard libc memcpy *)
     E.add_func_import env "rts" "memcmp" [I32Type; I32Type; I32Type] [I32Type];
     E.add_func_import env "rts" "version" [] [I32Type];
+    E.add_func_import env "rts" "stable_layout_version" [] [I32Type];
+    E.add_func_import env "rts" "check_stable_layout_version" [I32Type] [];
+    E.add_func_import env "rts" "stable_sharing_visit" [I32Type; I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "stable_sharing_reset" [] [];
+    E.add_func_import env "rts" "stable_region_read" [I64Type; I32Type] [];
+    E.add_func_import env "rts" "stable_region_write" [I64Type; I32Type] [];
+    E.add_func_import env "rts" "cycles_available" [] [I32Type];
+    E.add_func_import env "rts" "cycles_accept" [I32Type] [I32Type];
+    E.add_func_import env "rts" "cycles_add" [I32Type] [];
     E.add_func_import env "rts" "parse_idl_header" [I32Type; I32Type; I32Type; I32Type; I32Type] [];
     E.add_func_import env "rts" "leb128_decode" [I32Type] [I32Type];
     E.add_func_import env "rts" "sleb128_decode" [I32Type] [I32Type];
@@ -860,6 +880,14 @@ module RTS = struct
     E.add_func_import env "rts" "bigint_leb128_encode" [I32Type; I32Type] [];
     E.add_func_import env "rts" "bigint_leb128_stream_encode" [I32Type; I32Type] [];
     E.add_func_import env "rts" "bigint_leb128_decode" [I32Type] [I32Type];
+    E.add_func_import env "rts" "text_to_nat" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "text_to_int" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "nat_and" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "nat_or" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "nat_xor" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "nat_shl" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "nat_shr" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "nat_popcount" [I32Type] [I32Type];
     E.add_func_import env "rts" "bigint_leb128_decode_word64" [I64Type; I64Type; I32Type] [I32Type];
     E.add_func_import env "rts" "bigint_sleb128_size" [I32Type] [I32Type];
     E.add_func_import env "rts" "bigint_sleb128_encode" [I32Type; I32Type] [];
@@ -874,17 +902,42 @@ module RTS = struct
     E.add_func_import env "rts" "skip_any" [I32Type; I32Type; I32Type; I32Type] [];
     E.add_func_import env "rts" "find_field" [I32Type; I32Type; I32Type; I32Type; I32Type] [I32Type];
     E.add_func_import env "rts" "skip_fields" [I32Type; I32Type; I32Type; I32Type] [];
+    E.add_func_import env "rts" "idl_vec_stream" [I32Type] [I32Type];
+    E.add_func_import env "rts" "idl_vec_stream_remaining" [I32Type] [I32Type];
+    E.add_func_import env "rts" "idl_vec_stream_next" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "idl_lazy" [I32Type] [I32Type];
+    E.add_func_import env "rts" "idl_lazy_is_null" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "idl_lazy_some" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "idl_lazy_variant_tag" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "idl_lazy_variant_value" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "idl_lazy_force" [I32Type; I32Type] [I32Type];
     E.add_func_import env "rts" "remember_continuation" [I32Type] [I32Type];
     E.add_func_import env "rts" "recall_continuation" [I32Type] [I32Type];
     E.add_func_import env "rts" "peek_future_continuation" [I32Type] [I32Type];
+    E.add_func_import env "rts" "on_trap_register" [I32Type] [];
+    E.add_func_import env "rts" "on_trap_handlers" [] [I32Type];
+    E.add_func_import env "rts" "on_trap_set_handlers" [I32Type] [];
+    E.add_func_import env "rts" "on_trap_next" [] [I32Type];
     E.add_func_import env "rts" "continuation_count" [] [I32Type];
     E.add_func_import env "rts" "continuation_table_size" [] [I32Type];
     E.add_func_import env "rts" "blob_of_text" [I32Type] [I32Type];
+    E.add_func_import env "rts" "trap_with_blob" [I32Type] [];
+    E.add_func_import env "rts" "blob_compare" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "blob_eq" [I32Type; I32Type] [I32Type];
     E.add_func_import env "rts" "text_compare" [I32Type; I32Type] [I32Type];
     E.add_func_import env "rts" "text_concat" [I32Type; I32Type] [I32Type];
     E.add_func_import env "rts" "text_iter_done" [I32Type] [I32Type];
     E.add_func_import env "rts" "text_iter" [I32Type] [I32Type];
+    E.add_func_import env "rts" "text_to_lower" [I32Type] [I32Type];
+    E.add_func_import env "rts" "text_to_upper" [I32Type] [I32Type];
+    E.add_func_import env "rts" "text_nfc" [I32Type] [I32Type];
+    E.add_func_import env "rts" "text_nfd" [I32Type] [I32Type];
     E.add_func_import env "rts" "text_iter_next" [I32Type] [I32Type];
+    E.add_func_import env "rts" "text_split" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "text_join" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "text_contains" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "text_index_of" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "text_replace" [I32Type; I32Type; I32Type] [I32Type];
     E.add_func_import env "rts" "text_len" [I32Type] [I32Type];
     E.add_func_import env "rts" "text_of_ptr_size" [I32Type; I32Type] [I32Type];
     E.add_func_import env "rts" "text_singleton" [I32Type] [I32Type];
@@ -893,19 +946,32 @@ module RTS = struct
     E.add_func_import env "rts" "blob_of_principal" [I32Type] [I32Type];
     E.add_func_import env "rts" "principal_of_blob" [I32Type] [I32Type];
     E.add_func_import env "rts" "compute_crc32" [I32Type] [I32Type];
+    E.add_func_import env "rts" "blob_to_hex" [I32Type] [I32Type];
+    E.add_func_import env "rts" "hex_to_blob" [I32Type] [I32Type];
+    E.add_func_import env "rts" "blob_to_base64" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "base64_to_blob" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "blob_hash" [I32Type] [I32Type];
+    E.add_func_import env "rts" "text_hash" [I32Type] [I32Type];
+    E.add_func_import env "rts" "rng_seed" [I32Type] [];
+    E.add_func_import env "rts" "rng_next" [] [I64Type];
+    E.add_func_import env "rts" "rng_next_range" [I64Type] [I64Type];
+    E.add_func_import env "rts" "rng_state" [] [I32Type];
+    E.add_func_import env "rts" "rng_set_state" [I32Type] [];
+    E.add_func_import env "rts" "show_cycle_enter" [I32Type] [I32Type];
+    E.add_func_import env "rts" "show_cycle_leave" [] [];
     E.add_func_import env "rts" "blob_iter_done" [I32Type] [I32Type];
     E.add_func_import env "rts" "blob_iter" [I32Type] [I32Type];
     E.add_func_import env "rts" "blob_iter_next" [I32Type] [I32Type];
-    E.add_func_import env "rts" "pow" [F64Type; F64Type] [F64Type]; (* musl *)
-    E.add_func_import env "rts" "sin" [F64Type] [F64Type]; (* musl *)
-    E.add_func_import env "rts" "cos" [F64Type] [F64Type]; (* musl *)
+    E.add_func_import env "rts" "float_pow" [F64Type; F64Type] [F64Type];
+    E.add_func_import env "rts" "float_sin" [F64Type] [F64Type];
+    E.add_func_import env "rts" "float_cos" [F64Type] [F64Type];
     E.add_func_import env "rts" "tan" [F64Type] [F64Type]; (* musl *)
     E.add_func_import env "rts" "asin" [F64Type] [F64Type]; (* musl *)
     E.add_func_import env "rts" "acos" [F64Type] [F64Type]; (* musl *)
     E.add_func_import env "rts" "atan" [F64Type] [F64Type]; (* musl *)
     E.add_func_import env "rts" "atan2" [F64Type; F64Type] [F64Type]; (* musl *)
-    E.add_func_import env "rts" "exp" [F64Type] [F64Type]; (* musl *)
-    E.add_func_import env "rts" "log" [F64Type] [F64Type]; (* musl *)
+    E.add_func_import env "rts" "float_exp" [F64Type] [F64Type];
+    E.add_func_import env "rts" "float_log" [F64Type] [F64Type];
     E.add_func_import env "rts" "fmod" [F64Type; F64Type] [F64Type]; (* remainder, musl *)
     E.add_func_import env "rts" "float_fmt" [F64Type; I32Type; I32Type] [I32Type];
     E.add_func_import env "rts" "char_to_upper" [I32Type] [I32Type];
@@ -915,6 +981,7 @@ module RTS = struct
     E.add_func_import env "rts" "char_is_uppercase" [I32Type] [I32Type];
     E.add_func_import env "rts" "char_is_alphabetic" [I32Type] [I32Type];
     E.add_func_import env "rts" "get_max_live_size" [] [I32Type];
+    E.add_func_import env "rts" "get_max_heap_size" [] [I32Type];
     E.add_func_import env "rts" "get_reclaimed" [] [I64Type];
     E.add_func_import env "rts" "copying_gc" [] [];
     E.add_func_import env "rts" "compacting_gc" [] [];
@@ -923,9 +990,67 @@ module RTS = struct
     E.add_func_import env "rts" "alloc_words" [I32Type] [I32Type];
     E.add_func_import env "rts" "get_total_allocations" [] [I64Type];
     E.add_func_import env "rts" "get_heap_size" [] [I32Type];
+    E.add_func_import env "rts" "reserve_heap" [I32Type] [];
+    E.add_func_import env "rts" "register_memory_pressure_handler" [I32Type] [];
+    E.add_func_import env "rts" "set_memory_pressure_high_water_mark" [I32Type] [];
+    E.add_func_import env "rts" "gc_request_collection" [] [];
+    E.add_func_import env "rts" "note_message_end" [] [];
+    E.add_func_import env "rts" "live_history_len" [] [I32Type];
+    E.add_func_import env "rts" "live_history_message" [I32Type] [I64Type];
+    E.add_func_import env "rts" "live_history_live" [I32Type] [I32Type];
+    E.add_func_import env "rts" "gc_pause_percentile" [F64Type] [I64Type];
+    E.add_func_import env "rts" "reset_gc_pauses" [] [];
+    E.add_func_import env "rts" "principal_is_controller" [I32Type] [I32Type];
+    E.add_func_import env "rts" "set_controllers" [I32Type] [];
+    E.add_func_import env "rts" "reply_splice_begin" [] [];
+    E.add_func_import env "rts" "reply_splice_blob_size" [I32Type] [I32Type];
+    E.add_func_import env "rts" "reply_splice_write_blob" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "reply_splice_append" [I32Type; I32Type] [];
+    E.add_func_import env "rts" "rts_self_test" [] [I32Type];
+    E.add_func_import env "rts" "gc_estimate_reclaimable" [] [I32Type];
+    E.add_func_import env "rts" "heap_dump_to_stable" [I64Type] [I64Type];
+    E.add_func_import env "rts" "size_histogram_bucket" [I32Type] [I32Type];
+    E.add_func_import env "rts" "instruction_count" [] [I64Type];
+    E.add_func_import env "rts" "instruction_budget" [] [I64Type];
+    E.add_func_import env "rts" "set_instruction_budget" [I64Type] [];
+    E.add_func_import env "rts" "safepoint" [] [];
+    E.add_func_import env "rts" "set_safepoint_action" [I32Type] [];
+    E.add_func_import env "rts" "safepoint_gc_slices" [] [I32Type];
+    E.add_func_import env "rts" "remaining_instructions" [] [I64Type];
     E.add_func_import env "rts" "init" [I32Type] [];
+    E.add_func_import env "rts" "set_write_barrier_mode" [I32Type] [];
+    E.add_func_import env "rts" "write_barrier" [I32Type; I32Type] [];
+    E.add_func_import env "rts" "enable_generational_gc" [] [];
     E.add_func_import env "rts" "alloc_blob" [I32Type] [I32Type];
     E.add_func_import env "rts" "alloc_array" [I32Type] [I32Type];
+    E.add_func_import env "rts" "array_sort" [I32Type; I32Type] [];
+    E.add_func_import env "rts" "array_tabulate" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "array_map" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "array_filter" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "array_binary_search" [I32Type; I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "array_dedup" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "array_freeze" [I32Type] [I32Type];
+    E.add_func_import env "rts" "array_is_frozen" [I32Type] [I32Type];
+    E.add_func_import env "rts" "array_buffer_new" [] [I32Type];
+    E.add_func_import env "rts" "array_buffer_len" [I32Type] [I32Type];
+    E.add_func_import env "rts" "array_buffer_get" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "array_buffer_add" [I32Type; I32Type] [];
+    E.add_func_import env "rts" "array_buffer_append" [I32Type; I32Type] [];
+    E.add_func_import env "rts" "array_buffer_to_array" [I32Type] [I32Type];
+    E.add_func_import env "rts" "blob_builder_new" [] [I32Type];
+    E.add_func_import env "rts" "blob_builder_len" [I32Type] [I32Type];
+    E.add_func_import env "rts" "blob_builder_push_byte" [I32Type; I32Type] [];
+    E.add_func_import env "rts" "blob_builder_push_bytes" [I32Type; I32Type] [];
+    E.add_func_import env "rts" "blob_builder_finish" [I32Type] [I32Type];
+    E.add_func_import env "rts" "blob_map_new" [] [I32Type];
+    E.add_func_import env "rts" "blob_map_len" [I32Type] [I32Type];
+    E.add_func_import env "rts" "blob_map_has" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "blob_map_get" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "blob_map_put" [I32Type; I32Type; I32Type] [];
+    E.add_func_import env "rts" "blob_map_remove" [I32Type; I32Type] [I32Type];
+    E.add_func_import env "rts" "blob_map_stable_size" [I32Type] [I64Type];
+    E.add_func_import env "rts" "blob_map_save" [I32Type; I64Type] [I64Type];
+    E.add_func_import env "rts" "blob_map_load" [I64Type] [I32Type];
     E.add_func_import env "rts" "alloc_stream" [I32Type] [I32Type];
     E.add_func_import env "rts" "stream_write" [I32Type; I32Type; I32Type] [];
     E.add_func_import env "rts" "stream_write_byte" [I32Type; I32Type] [];
@@ -934,6 +1059,13 @@ module RTS = struct
     E.add_func_import env "rts" "stream_shutdown" [I32Type] [];
     E.add_func_import env "rts" "stream_reserve" [I32Type; I32Type] [I32Type];
     E.add_func_import env "rts" "stream_stable_dest" [I32Type; I64Type; I64Type] [];
+    if !Flags.alloc_profiling then begin
+      E.add_func_import env "rts" "alloc_words_at" [I32Type; I32Type] [I32Type];
+      E.add_func_import env "rts" "alloc_blob_at" [I32Type; I32Type] [I32Type];
+      E.add_func_import env "rts" "alloc_sites" [] [I32Type];
+      E.add_func_import env "rts" "alloc_site_count" [I32Type] [I64Type];
+      E.add_func_import env "rts" "alloc_site_bytes" [I32Type] [I64Type]
+    end;
     ()
 
 end (* RTS *)
@@ -963,8 +1095,15 @@ module Heap = struct
   let get_max_live_size env =
     E.call_import env "rts" "get_max_live_size"
 
+  let get_max_heap_size env =
+    E.call_import env "rts" "get_max_heap_size"
+
   let dyn_alloc_words env =
-    E.call_import env "rts" "alloc_words"
+    if !Flags.alloc_profiling then
+      compile_unboxed_const (E.next_alloc_site env) ^^
+      E.call_import env "rts" "alloc_words_at"
+    else
+      E.call_import env "rts" "alloc_words"
 
   (* Static allocation (always words)
      (uses dynamic allocation for smaller and more readable code) *)
@@ -988,6 +1127,34 @@ module Heap = struct
     let offset = Int32.(add (mul word_size i) ptr_unskew) in
     G.i (Store {ty = I32Type; align = 2; offset; sz = None})
 
+  (* Write barrier of rts/motoko-rts/src/gc/barrier.rs, a BarrierMode value.
+     Only the incremental and generational modes of the compacting GC need one. *)
+  let write_barrier_mode () =
+    match !Flags.gc_strategy, !Flags.gc_mode with
+    | Mo_config.Flags.MarkCompact, Mo_config.Flags.Incremental -> 1l
+    | Mo_config.Flags.MarkCompact, Mo_config.Flags.Generational -> 2l
+    | _ -> 0l
+
+  (* Pointer stores to fields of mutable objects (MutBox fields, array elements), which go
+     through the write barrier when the GC needs one. Expects the (skewed) address of the field
+     and the new value on the stack, like store_ptr. *)
+  let store_ptr_with_barrier env : G.t =
+    if write_barrier_mode () = 0l then store_ptr else
+    let (set_new_val, get_new_val) = new_local env "new_val" in
+    set_new_val ^^
+    compile_add_const ptr_unskew ^^
+    get_new_val ^^
+    E.call_import env "rts" "write_barrier"
+
+  (* As above, expecting the object and the new value on the stack, like store_field *)
+  let store_field_with_barrier env (i : int32) : G.t =
+    if write_barrier_mode () = 0l then store_field i else
+    let (set_new_val, get_new_val) = new_local env "new_val" in
+    set_new_val ^^
+    compile_add_const (Int32.mul word_size i) ^^
+    get_new_val ^^
+    store_ptr_with_barrier env
+
   (* Although we occasionally want to treat two consecutive
      32 bit fields as one 64 bit number *)
 
@@ -1104,6 +1271,18 @@ module ContinuationTable = struct
   let size env : G.t = E.call_import env "rts" "continuation_table_size"
 end (* ContinuationTable *)
 
+module OnTrap = struct
+  (* See rts/motoko-rts/src/on_trap.rs *)
+  let register env : G.t = E.call_import env "rts" "on_trap_register"
+  let handlers env : G.t = E.call_import env "rts" "on_trap_handlers"
+  let set_handlers env : G.t = E.call_import env "rts" "on_trap_set_handlers"
+  let next env : G.t = E.call_import env "rts" "on_trap_next"
+  let clear env : G.t = compile_unboxed_zero ^^ set_handlers env
+
+  (* Position of the handlers in ContinuationTable entries *)
+  let array_index = 2l
+end (* OnTrap *)
+
 module Bool = struct
   (* Boolean literals are either 0 or 1,
      at StackRep UnboxedWord32
@@ -1266,6 +1445,9 @@ module Tagged = struct
     | CoercionFailure (* Used in the Candid decoder. Static singleton! *)
     | OneWordFiller (* Only used by the RTS *)
     | FreeSpace (* Only used by the RTS *)
+    | WeakRef (* Only allocated by the RTS *)
+    | Finalizable (* Only allocated by the RTS *)
+    | FrozenArray (* An Array after `Array_freeze`, only set by the RTS *)
 
   (* Tags needs to have the lowest bit set, to allow distinguishing object
      headers from heap locations (object or field addresses).
@@ -1289,6 +1471,9 @@ module Tagged = struct
     | Null -> 27l
     | OneWordFiller -> 29l
     | FreeSpace -> 31l
+    | WeakRef -> 33l
+    | Finalizable -> 35l
+    | FrozenArray -> 37l
     (* Next two tags won't be seen by the GC, so no need to set the lowest bit
        for `CoercionFailure` and `StableSeen` *)
     | CoercionFailure -> 0xfffffffel
@@ -1617,7 +1802,7 @@ module Word64 = struct
   let compile_unsigned_sub env =
     Func.share_code2 env "nat_sub" (("n1", I64Type), ("n2", I64Type)) [I64Type] (fun env get_n1 get_n2 ->
       get_n1 ^^ get_n2 ^^ G.i (Compare (Wasm.Values.I64 I64Op.LtU)) ^^
-      E.then_trap_with env "Natural subtraction underflow" ^^
+      E.then_trap_with env (Operator.binop_overflow Operator.SubOp Type.nat) ^^
       get_n1 ^^ get_n2 ^^ G.i (Binary (Wasm.Values.I64 I64Op.Sub))
     )
 
@@ -2747,7 +2932,7 @@ module BigNumLibtommath : BigNumType = struct
     Func.share_code1 env "assert_nonneg" ("n", I32Type) [I32Type] (fun env get_n ->
       get_n ^^
       E.call_import env "rts" "bigint_isneg" ^^
-      E.then_trap_with env "Natural subtraction underflow" ^^
+      E.then_trap_with env (Operator.binop_overflow Operator.SubOp Type.nat) ^^
       get_n
     )
 
@@ -3035,7 +3220,12 @@ module Blob = struct
     compile_unboxed_const (Int32.add ptr_unskew (E.add_static env StaticBytes.[Bytes s])) ^^
     compile_unboxed_const (Int32.of_int (String.length s))
 
-  let alloc env = E.call_import env "rts" "alloc_blob"
+  let alloc env =
+    if !Flags.alloc_profiling then
+      compile_unboxed_const (E.next_alloc_site env) ^^
+      E.call_import env "rts" "alloc_blob_at"
+    else
+      E.call_import env "rts" "alloc_blob"
 
   let unskewed_payload_offset = Int32.(add ptr_unskew (mul Heap.word_size header_size))
   let payload_ptr_unskewed = compile_add_const unskewed_payload_offset
@@ -3071,7 +3261,7 @@ module Blob = struct
     get_blob
 
   (* Lexicographic blob comparison. Expects two blobs on the stack *)
-  let rec compare env op =
+  let compare env op =
     let open Operator in
     let name = match op with
         | LtOp -> "Blob.compare_lt"
@@ -3082,74 +3272,18 @@ module Blob = struct
         | NeqOp -> assert false in
     Func.share_code2 env name (("x", I32Type), ("y", I32Type)) [I32Type] (fun env get_x get_y ->
       match op with
-        (* Some operators can be reduced to the negation of other operators *)
-        | LtOp ->  get_x ^^ get_y ^^ compare env GeOp ^^ Bool.neg
-        | GtOp ->  get_x ^^ get_y ^^ compare env LeOp ^^ Bool.neg
-        | NeqOp -> assert false
+        (* Equality short-circuits on the length *)
+        | EqOp -> get_x ^^ get_y ^^ E.call_import env "rts" "blob_eq"
         | _ ->
-      begin
-        let (set_len1, get_len1) = new_local env "len1" in
-        let (set_len2, get_len2) = new_local env "len2" in
-        let (set_len, get_len) = new_local env "len" in
-        let (set_a, get_a) = new_local env "a" in
-        let (set_b, get_b) = new_local env "b" in
-
-        get_x ^^ len env ^^ set_len1 ^^
-        get_y ^^ len env ^^ set_len2 ^^
-
-        (* Find mininum length *)
-        begin if op = EqOp then
-          (* Early exit for equality *)
-          get_len1 ^^ get_len2 ^^ G.i (Compare (Wasm.Values.I32 I32Op.Eq)) ^^
-          G.if0 G.nop (Bool.lit false ^^ G.i Return) ^^
-
-          get_len1 ^^ set_len
-        else
-          get_len1 ^^ get_len2 ^^ G.i (Compare (Wasm.Values.I32 I32Op.LeU)) ^^
-          G.if0
-            (get_len1 ^^ set_len)
-            (get_len2 ^^ set_len)
-        end ^^
-
-        (* We could do word-wise comparisons if we know that the trailing bytes
-           are zeroed *)
-        get_len ^^
-        from_0_to_n env (fun get_i ->
-          get_x ^^
-          payload_ptr_unskewed ^^
-          get_i ^^
-          G.i (Binary (Wasm.Values.I32 I32Op.Add)) ^^
-          G.i (Load {ty = I32Type; align = 0; offset = 0l; sz = Some Wasm.Types.(Pack8, ZX)}) ^^
-          set_a ^^
-
-
-          get_y ^^
-          payload_ptr_unskewed ^^
-          get_i ^^
-          G.i (Binary (Wasm.Values.I32 I32Op.Add)) ^^
-          G.i (Load {ty = I32Type; align = 0; offset = 0l; sz = Some Wasm.Types.(Pack8, ZX)}) ^^
-          set_b ^^
-
-          get_a ^^ get_b ^^ G.i (Compare (Wasm.Values.I32 I32Op.Eq)) ^^
-          G.if0 G.nop (
-            (* first non-equal elements *)
-            begin match op with
-            | LeOp -> get_a ^^ get_b ^^ G.i (Compare (Wasm.Values.I32 I32Op.LeU))
-            | GeOp -> get_a ^^ get_b ^^ G.i (Compare (Wasm.Values.I32 I32Op.GeU))
-            | EqOp -> Bool.lit false
-            | _ -> assert false
-            end ^^
-            G.i Return
-          )
-        ) ^^
-        (* Common prefix is same *)
-        match op with
-        | LeOp -> get_len1 ^^ get_len2 ^^ G.i (Compare (Wasm.Values.I32 I32Op.LeU))
-        | GeOp -> get_len1 ^^ get_len2 ^^ G.i (Compare (Wasm.Values.I32 I32Op.GeU))
-        | EqOp -> Bool.lit true
-        | _ -> assert false
-      end
-  )
+      get_x ^^ get_y ^^ E.call_import env "rts" "blob_compare" ^^
+      compile_unboxed_const 0l ^^
+      match op with
+        | LtOp -> G.i (Compare (Wasm.Values.I32 I32Op.LtS))
+        | LeOp -> G.i (Compare (Wasm.Values.I32 I32Op.LeS))
+        | GtOp -> G.i (Compare (Wasm.Values.I32 I32Op.GtS))
+        | GeOp -> G.i (Compare (Wasm.Values.I32 I32Op.GeS))
+        | EqOp | NeqOp -> assert false
+    )
 
   let iter env =
     E.call_import env "rts" "blob_iter"
@@ -3194,9 +3328,9 @@ module Text = struct
 
   (* The layout of a concatenation node is
 
-     ┌─────┬─────────┬───────┬───────┐
-     │ tag │ n_bytes │ text1 │ text2 │
-     └─────┴─────────┴───────┴───────┘
+     ┌─────┬─────────┬───────┬───────┬───────┬─────────┐
+     │ tag │ n_bytes │ text1 │ text2 │ depth │ n_chars │
+     └─────┴─────────┴───────┴───────┴───────┴─────────┘
 
     This is internal to rts/text.c, with the exception of GC-related code.
   *)
@@ -3345,35 +3479,12 @@ module Arr = struct
     get_r
 
   let tabulate env =
-    let (set_len, get_len) = new_local env "len" in
+    (* Allocates the array once and writes every slot once, see `array_tabulate` *)
     let (set_f, get_f) = new_local env "f" in
-    let (set_r, get_r) = new_local env "r" in
     set_f ^^
     BigNum.to_word32 env ^^
-    set_len ^^
-
-    (* Allocate *)
-    get_len ^^
-    alloc env ^^
-    set_r ^^
-
-    (* Write fields *)
-    get_len ^^
-    from_0_to_n env (fun get_i ->
-      (* Where to store *)
-      get_r ^^ get_i ^^ idx env ^^
-      (* The closure *)
-      get_f ^^
-      (* The arg *)
-      get_i ^^
-      BigNum.from_word32 env ^^
-      (* The closure again *)
-      get_f ^^
-      (* Call *)
-      Closure.call_closure env 1 1 ^^
-      store_ptr
-    ) ^^
-    get_r
+    get_f ^^
+    E.call_import env "rts" "array_tabulate"
 
   let ofBlob env =
     Func.share_code1 env "Arr.ofBlob" ("blob", I32Type) [I32Type] (fun env get_blob ->
@@ -3576,6 +3687,9 @@ module IC = struct
       E.add_func_import env "ic0" "msg_arg_data_size" [] [I32Type];
       E.add_func_import env "ic0" "msg_caller_copy" (i32s 3) [];
       E.add_func_import env "ic0" "msg_caller_size" [] [I32Type];
+      E.add_func_import env "ic0" "msg_method_name_copy" (i32s 3) [];
+      E.add_func_import env "ic0" "msg_method_name_size" [] [I32Type];
+      E.add_func_import env "ic0" "accept_message" [] [];
       E.add_func_import env "ic0" "msg_cycles_available128" [I32Type] [];
       E.add_func_import env "ic0" "msg_cycles_refunded128" [I32Type] [];
       E.add_func_import env "ic0" "msg_cycles_accept128" [I64Type; I64Type; I32Type] [];
@@ -3595,6 +3709,10 @@ module IC = struct
       E.add_func_import env "ic0" "stable64_size" [] [I64Type];
       E.add_func_import env "ic0" "stable64_grow" [I64Type] [I64Type];
       E.add_func_import env "ic0" "time" [] [I64Type];
+      E.add_func_import env "ic0" "global_timer_set" [I64Type] [I64Type];
+      E.add_func_import env "ic0" "performance_counter" [I32Type] [I64Type];
+      if !Flags.is_controller_api then
+        E.add_func_import env "ic0" "is_controller" (i32s 2) [I32Type];
       ()
 
   let system_imports env =
@@ -3740,6 +3858,32 @@ module IC = struct
       edesc = nr (FuncExport (nr fi))
     })
 
+  let export_timer env =
+    assert (E.mode env = Flags.ICMode || E.mode env = Flags.RefMode);
+    let fi = E.add_fun env "canister_global_timer"
+      (Func.of_body env [] [] (fun env ->
+        G.i (Call (nr (E.built_in env "timer_exp"))) ^^
+        E.collect_garbage env))
+    in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "canister_global_timer";
+      edesc = nr (FuncExport (nr fi))
+    })
+
+  let export_inspect env =
+    assert (E.mode env = Flags.ICMode || E.mode env = Flags.RefMode);
+    let fi = E.add_fun env "canister_inspect_message"
+      (Func.of_body env [] [] (fun env ->
+        G.i (Call (nr (E.built_in env "inspect_exp"))) ^^
+        G.if0
+          (system_call env "accept_message")
+          (E.trap_with env "canister_inspect_message explicitly refused message")))
+    in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "canister_inspect_message";
+      edesc = nr (FuncExport (nr fi))
+    })
+
   let export_wasi_start env =
     assert (E.mode env = Flags.WASIMode);
     let fi = E.add_fun env "_start" (Func.of_body env [] [] (fun env1 ->
@@ -3807,6 +3951,14 @@ module IC = struct
     | _ ->
       E.trap_with env "cannot get system time when running locally"
 
+  let set_global_timer env =
+    match E.mode env with
+    | Flags.ICMode | Flags.RefMode ->
+      system_call env "global_timer_set"
+    | _ ->
+      G.i Drop ^^
+      E.trap_with env "cannot set global timer when running locally"
+
   let caller env =
     SR.Vanilla,
     match E.mode env with
@@ -3818,6 +3970,18 @@ module IC = struct
     | _ ->
       E.trap_with env (Printf.sprintf "cannot get caller when running locally")
 
+  (* Method names are valid UTF-8, so the blob is a valid text *)
+  let method_name env =
+    SR.Vanilla,
+    match E.mode env with
+    | Flags.ICMode | Flags.RefMode ->
+      Blob.of_size_copy env
+        (fun env -> system_call env "msg_method_name_size")
+        (fun env -> system_call env "msg_method_name_copy")
+        (fun env -> compile_unboxed_const 0l)
+    | _ ->
+      E.trap_with env (Printf.sprintf "cannot get method name when running locally")
+
   let reject env arg_instrs =
     match E.mode env with
     | Flags.ICMode | Flags.RefMode ->
@@ -3862,9 +4026,10 @@ module IC = struct
   let reply_with_data env =
     Func.share_code2 env "reply_with_data" (("start", I32Type), ("size", I32Type)) [] (
       fun env get_data_start get_data_size ->
+        (* Appends the data in chunks when blobs were spliced, see `reply_splice.rs` *)
         get_data_start ^^
         get_data_size ^^
-        system_call env "msg_reply_data_append" ^^
+        E.call_import env "rts" "reply_splice_append" ^^
         system_call env "msg_reply"
    )
 
@@ -3994,25 +4159,6 @@ module Cycles = struct
       BigNum.compile_mul env ^^ (* TODO: use shift left instead *)
       BigNum.compile_add env)
 
-  (* takes a bignum from the stack, traps if ≥2^128, and leaves two 64bit words on the stack *)
-  (* only used twice, so ok to not use share_code1; that would require I64Type support in FakeMultiVal *)
-  let to_two_word64 env =
-    let (set_val, get_val) = new_local env "cycles" in
-    set_val ^^
-    get_val ^^
-    compile_unboxed_const (BigNum.vanilla_lit env (Big_int.power_int_positive_int 2 128)) ^^
-    BigNum.compile_relop env Lt ^^
-    E.else_trap_with env "cycles out of bounds" ^^
-
-    get_val ^^
-    (* shift right 64 bits *)
-    compile_unboxed_const (BigNum.vanilla_lit env (Big_int.power_int_positive_int 2 64)) ^^
-    BigNum.compile_unsigned_div env ^^ (* TODO: use shift right instead *)
-    BigNum.truncate_to_word64 env ^^
-
-    get_val ^^
-    BigNum.truncate_to_word64 env
-
   let balance env =
     Func.share_code0 env "cycle_balance" [I32Type] (fun env ->
       Stack.with_words env "dst" 4l (fun get_dst ->
@@ -4023,34 +4169,13 @@ module Cycles = struct
       )
     )
 
-  let add env =
-    Func.share_code1 env "cycle_add" ("cycles", I32Type) [] (fun env get_x ->
-      get_x ^^
-      to_two_word64 env ^^
-      IC.cycles_add env
-    )
+  (* 128-bit arithmetic and conversions are done by the RTS, see
+     rts/motoko-rts/src/cycles.rs *)
+  let add env = E.call_import env "rts" "cycles_add"
 
-  let accept env =
-    Func.share_code1 env "cycle_accept" ("cycles", I32Type) [I32Type] (fun env get_x ->
-      Stack.with_words env "dst" 4l (fun get_dst ->
-        get_x ^^
-        to_two_word64 env ^^
-        get_dst ^^
-        IC.cycles_accept env ^^
-        get_dst ^^
-        from_word128_ptr env
-      )
-    )
+  let accept env = E.call_import env "rts" "cycles_accept"
 
-  let available env =
-    Func.share_code0 env "cycle_available" [I32Type] (fun env ->
-      Stack.with_words env "dst" 4l (fun get_dst ->
-        get_dst ^^
-        IC.cycles_available env ^^
-        get_dst ^^
-        from_word128_ptr env
-      )
-    )
+  let available env = E.call_import env "rts" "cycles_available"
 
   let refunded env =
     Func.share_code0 env "cycle_refunded" [I32Type] (fun env ->
@@ -4066,9 +4191,6 @@ end (* Cycles *)
 
 module StableMem = struct
 
-  (* start from 1 to avoid accidental reads of 0 *)
-  let version = Int32.of_int 1
-
   let register_globals env =
     (* size (in pages) *)
     E.add_global64 env "__stablemem_size" Mutable 0L
@@ -4339,14 +4461,10 @@ module StableMem = struct
         (("offset", I64Type), ("len", I32Type)) [I32Type]
         (fun env get_offset get_len ->
           let (set_blob, get_blob) = new_local env "blob" in
-          get_offset ^^
-          get_len ^^
-          guard_range env ^^
           get_len ^^ Blob.alloc env ^^ set_blob ^^
-          get_blob ^^ Blob.payload_ptr_unskewed ^^ G.i (Convert (Wasm.Values.I64 I64Op.ExtendUI32)) ^^
           get_offset ^^
-          get_len ^^ G.i (Convert (Wasm.Values.I64 I64Op.ExtendUI32)) ^^
-          IC.system_call env "stable64_read" ^^
+          get_blob ^^
+          E.call_import env "rts" "stable_region_read" ^^
           get_blob)
     | _ -> assert false
 
@@ -4356,15 +4474,9 @@ module StableMem = struct
       Func.share_code2 env "__stablemem_store_blob"
         (("offset", I64Type), ("blob", I32Type)) []
         (fun env get_offset get_blob ->
-         let (set_len, get_len) = new_local env "len" in
-          get_blob ^^ Blob.len env ^^ set_len ^^
-          get_offset ^^
-          get_len ^^
-          guard_range env ^^
           get_offset ^^
-          get_blob ^^ Blob.payload_ptr_unskewed ^^ G.i (Convert (Wasm.Values.I64 I64Op.ExtendUI32)) ^^
-          get_len ^^ G.i (Convert (Wasm.Values.I64 I64Op.ExtendUI32)) ^^
-          IC.system_call env "stable64_write")
+          get_blob ^^
+          E.call_import env "rts" "stable_region_write")
     | _ -> assert false
 
 end (* StableMemory *)
@@ -4405,6 +4517,210 @@ module RTS_Exports = struct
     E.add_export env (nr {
       name = Wasm.Utf8.decode "stable64_write_moc";
       edesc = nr (FuncExport (nr stable64_write_moc_fi))
+    });
+
+    let stable64_read_moc_fi =
+      if E.mode env = Flags.WASIMode then
+        E.add_fun env "stable64_read_moc" (
+            Func.of_body env ["to", I64Type; "from", I64Type; "len", I64Type] []
+              (fun env ->
+                E.trap_with env "stable64_read_moc is not supposed to be called in WASI"
+              )
+          )
+      else E.reuse_import env "ic0" "stable64_read" in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "stable64_read_moc";
+      edesc = nr (FuncExport (nr stable64_read_moc_fi))
+    });
+
+    (* Logical size of the stable memory, see rts/motoko-rts/src/stable_region.rs *)
+    let stable_mem_size_moc_fi =
+      E.add_fun env "stable_mem_size_moc" (
+        Func.of_body env [] [I64Type] (fun env ->
+          if E.mode env = Flags.WASIMode
+          then compile_const_64 0L
+          else StableMem.get_mem_size env
+        )
+      ) in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "stable_mem_size_moc";
+      edesc = nr (FuncExport (nr stable_mem_size_moc_fi))
+    });
+
+    let stable_mem_grow_moc_fi =
+      E.add_fun env "stable_mem_grow_moc" (
+        Func.of_body env ["pages", I64Type] [I64Type] (fun env ->
+          if E.mode env = Flags.WASIMode
+          then E.trap_with env "stable_mem_grow_moc is not supposed to be called in WASI"
+          else G.i (LocalGet (nr 0l)) ^^ StableMem.logical_grow env
+        )
+      ) in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "stable_mem_grow_moc";
+      edesc = nr (FuncExport (nr stable_mem_grow_moc_fi))
+    });
+
+    let performance_counter_moc_fi =
+      if E.mode env = Flags.WASIMode then
+        E.add_fun env "performance_counter_moc" (
+            Func.of_body env ["counter_type", I32Type] [I64Type]
+              (fun env -> compile_const_64 0L)
+          )
+      else E.reuse_import env "ic0" "performance_counter" in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "performance_counter_moc";
+      edesc = nr (FuncExport (nr performance_counter_moc_fi))
+    });
+
+    let msg_reply_data_append_moc_fi =
+      if E.mode env = Flags.WASIMode then
+        E.add_fun env "msg_reply_data_append_moc" (
+            Func.of_body env ["src", I32Type; "size", I32Type] []
+              (fun env -> E.trap_with env "msg_reply_data_append_moc is not supposed to be called in WASI")
+          )
+      else E.reuse_import env "ic0" "msg_reply_data_append" in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "msg_reply_data_append_moc";
+      edesc = nr (FuncExport (nr msg_reply_data_append_moc_fi))
+    });
+
+    (* -1 tells the RTS that `ic0.is_controller` is not available, see `controller.rs` *)
+    let is_controller_moc_fi =
+      if E.mode env = Flags.WASIMode || not !Flags.is_controller_api then
+        E.add_fun env "is_controller_moc" (
+            Func.of_body env ["src", I32Type; "size", I32Type] [I32Type]
+              (fun env -> compile_unboxed_const (-1l))
+          )
+      else E.reuse_import env "ic0" "is_controller" in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "is_controller_moc";
+      edesc = nr (FuncExport (nr is_controller_moc_fi))
+    });
+
+    (* Calls the comparison closure of `array_sort` and `array_binary_search`, and turns the resulting
+       `{#less; #equal; #greater}` into -1, 0, 1 *)
+    let compare_moc_fi = E.add_fun env "compare_moc" (
+      Func.of_body env ["cmp", I32Type; "a", I32Type; "b", I32Type] [I32Type] (fun env ->
+        let get_cmp = G.i (LocalGet (nr 0l)) in
+        let get_a = G.i (LocalGet (nr 1l)) in
+        let get_b = G.i (LocalGet (nr 2l)) in
+        let (set_r, get_r) = new_local env "r" in
+        get_cmp ^^ get_a ^^ get_b ^^ get_cmp ^^
+        Closure.call_closure env 2 1 ^^
+        set_r ^^
+        get_r ^^ Variant.test_is env "less" ^^
+        G.if1 I32Type
+          (compile_unboxed_const (-1l))
+          (get_r ^^ Variant.test_is env "equal" ^^
+           G.if1 I32Type
+             (compile_unboxed_const 0l)
+             (compile_unboxed_const 1l))
+      )
+    ) in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "compare_moc";
+      edesc = nr (FuncExport (nr compare_moc_fi))
+    });
+
+    (* Calls the closure of `array_tabulate` and `array_map` *)
+    let apply_moc_fi = E.add_fun env "apply_moc" (
+      Func.of_body env ["f", I32Type; "x", I32Type] [I32Type] (fun env ->
+        let get_f = G.i (LocalGet (nr 0l)) in
+        let get_x = G.i (LocalGet (nr 1l)) in
+        get_f ^^ get_x ^^ get_f ^^
+        Closure.call_closure env 1 1
+      )
+    ) in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "apply_moc";
+      edesc = nr (FuncExport (nr apply_moc_fi))
+    });
+
+    (* Calls the memory pressure handler, see rts/motoko-rts/src/memory_pressure.rs *)
+    let call_moc_fi = E.add_fun env "call_moc" (
+      Func.of_body env ["f", I32Type] [] (fun env ->
+        let get_f = G.i (LocalGet (nr 0l)) in
+        get_f ^^ get_f ^^
+        Closure.call_closure env 0 0
+      )
+    ) in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "call_moc";
+      edesc = nr (FuncExport (nr call_moc_fi))
+    });
+
+    (* Calls the predicate closure of `array_filter`, and turns the resulting
+       `Bool` into 0 or 1 *)
+    let predicate_moc_fi = E.add_fun env "predicate_moc" (
+      Func.of_body env ["pred", I32Type; "x", I32Type] [I32Type] (fun env ->
+        let get_pred = G.i (LocalGet (nr 0l)) in
+        let get_x = G.i (LocalGet (nr 1l)) in
+        get_pred ^^ get_x ^^ get_pred ^^
+        Closure.call_closure env 1 1 ^^
+        compile_eq_const (Bool.vanilla_lit true)
+      )
+    ) in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "predicate_moc";
+      edesc = nr (FuncExport (nr predicate_moc_fi))
+    });
+
+    (* Calls the equality closure of `array_dedup`, and turns the resulting
+       `Bool` into 0 or 1 *)
+    let equal_moc_fi = E.add_fun env "equal_moc" (
+      Func.of_body env ["eq", I32Type; "a", I32Type; "b", I32Type] [I32Type] (fun env ->
+        let get_eq = G.i (LocalGet (nr 0l)) in
+        let get_a = G.i (LocalGet (nr 1l)) in
+        let get_b = G.i (LocalGet (nr 2l)) in
+        get_eq ^^ get_a ^^ get_b ^^ get_eq ^^
+        Closure.call_closure env 2 1 ^^
+        compile_eq_const (Bool.vanilla_lit true)
+      )
+    ) in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "equal_moc";
+      edesc = nr (FuncExport (nr equal_moc_fi))
+    });
+
+    (* Cycle functions, see rts/motoko-rts/src/cycles.rs.
+       These trap when not running on the IC *)
+    let msg_cycles_available128_moc_fi =
+      E.add_fun env "msg_cycles_available128_moc" (
+        Func.of_body env ["dst", I32Type] [] (fun env ->
+          G.i (LocalGet (nr 0l)) ^^
+          IC.cycles_available env
+        )
+      ) in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "msg_cycles_available128_moc";
+      edesc = nr (FuncExport (nr msg_cycles_available128_moc_fi))
+    });
+
+    let msg_cycles_accept128_moc_fi =
+      E.add_fun env "msg_cycles_accept128_moc" (
+        Func.of_body env ["max_high", I64Type; "max_low", I64Type; "dst", I32Type] [] (fun env ->
+          G.i (LocalGet (nr 0l)) ^^
+          G.i (LocalGet (nr 1l)) ^^
+          G.i (LocalGet (nr 2l)) ^^
+          IC.cycles_accept env
+        )
+      ) in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "msg_cycles_accept128_moc";
+      edesc = nr (FuncExport (nr msg_cycles_accept128_moc_fi))
+    });
+
+    let call_cycles_add128_moc_fi =
+      E.add_fun env "call_cycles_add128_moc" (
+        Func.of_body env ["high", I64Type; "low", I64Type] [] (fun env ->
+          G.i (LocalGet (nr 0l)) ^^
+          G.i (LocalGet (nr 1l)) ^^
+          IC.cycles_add env
+        )
+      ) in
+    E.add_export env (nr {
+      name = Wasm.Utf8.decode "call_cycles_add128_moc";
+      edesc = nr (FuncExport (nr call_cycles_add128_moc_fi))
     })
 
 end (* RTS_Exports *)
@@ -4464,6 +4780,10 @@ module type Stream = sig
      address to it. The address is invalidated by a GC, and as such must
      be written to in the next few instructions. *)
   val reserve : E.t -> G.t -> int32 -> G.t
+
+  (* Whether immutable arrays reachable from several places are written once,
+     see Note [shared immutable stable values] *)
+  val share_immutable_arrays : bool
 end
 
 
@@ -4491,6 +4811,8 @@ module BumpStream : Stream = struct
 
   let name_for fn_name ts = "@" ^ fn_name ^ "<" ^ Typ_hash.typ_seq_hash ts ^ ">"
 
+  let share_immutable_arrays = false
+
   let advance_data_buf get_data_buf =
     get_data_buf ^^ G.i (Binary (Wasm.Values.I32 I32Op.Add)) ^^ G.setter_for get_data_buf
 
@@ -4517,15 +4839,13 @@ module BumpStream : Stream = struct
     G.i (Store {ty = I32Type; align = 0; offset = 0l; sz = Some Wasm.Types.Pack8}) ^^
     compile_unboxed_const 1l ^^ advance_data_buf get_data_buf
 
+  (* Large blobs in replies are not copied, see `reply_splice.rs` *)
   let write_blob env get_data_buf get_x =
-    let set_len, get_len = new_local env "len" in
-    get_x ^^ Blob.len env ^^ set_len ^^
-    write_word_leb env get_data_buf get_len ^^
+    write_word_leb env get_data_buf (get_x ^^ Blob.len env) ^^
     get_data_buf ^^
-    get_x ^^ Blob.payload_ptr_unskewed ^^
-    get_len ^^
-    Heap.memcpy env ^^
-    get_len ^^ advance_data_buf get_data_buf
+    get_x ^^
+    E.call_import env "rts" "reply_splice_write_blob" ^^
+    advance_data_buf get_data_buf
 
   let write_text env get_data_buf get_x =
     let set_len, get_len = new_local env "len" in
@@ -4591,7 +4911,8 @@ module MakeSerialization (Strm : Stream) = struct
      it is easier to start like this.
   *)
 
-  module TM = Map.Make (struct type t = Type.typ let compare = compare end)
+  (* Type table entries, see `type_desc` *)
+  module TM = Map.Make (struct type t = bool * Type.typ let compare = compare end)
   let to_idl_prim = let open Type in function
     | Prim Null | Tup [] -> Some 1l
     | Prim Bool -> Some 2l
@@ -4623,9 +4944,22 @@ module MakeSerialization (Strm : Stream) = struct
   let idl_alias     = 1l (* see Note [mutable stable values] *)
 
 
+  (* Is the type an immutable array written as `alias vec`,
+     see Note [shared immutable stable values] *)
+  let is_shared_array t =
+    Strm.share_immutable_arrays &&
+    match Type.normalize t with
+    | Type.Array t -> not (Type.is_mut t)
+    | _ -> false
+
   let type_desc env ts : string =
     let open Type in
 
+    (* Type table entries are types, and (with the flag set) the `vec` targets
+       of the `alias` entries of shared immutable arrays. Mutable arrays alias
+       the `vec` entry of the immutable array type. *)
+    let vec_entry t = (is_shared_array t, t) in
+
     (* Type traversal *)
     (* We do a first traversal to find out the indices of non-primitive types *)
     let (typs, idx) =
@@ -4633,15 +4967,18 @@ module MakeSerialization (Strm : Stream) = struct
       let idx = ref TM.empty in
       let rec go t =
         let t = Type.normalize t in
-        if to_idl_prim t <> None then () else
-        if TM.mem t !idx then () else begin
-          idx := TM.add t (Lib.List32.length !typs) !idx;
-          typs := !typs @ [ t ];
+        if to_idl_prim t <> None then () else add (false, t)
+      and add ((is_vec, t) as entry) =
+        if TM.mem entry !idx then () else begin
+          idx := TM.add entry (Lib.List32.length !typs) !idx;
+          typs := !typs @ [ entry ];
           match t with
+          | Array t when is_vec -> go t
           | Tup ts -> List.iter go ts
           | Obj (_, fs) ->
             List.iter (fun f -> go f.typ) fs
-          | Array (Mut t) -> go (Array t)
+          | Array (Mut t) -> add (vec_entry (Array t))
+          | Array t when is_shared_array (Array t) -> add (vec_entry (Array t))
           | Array t -> go t
           | Opt t -> go t
           | Variant vs -> List.iter (fun f -> go f.typ) vs
@@ -4690,17 +5027,22 @@ module MakeSerialization (Strm : Stream) = struct
 
     (* Actual binary data *)
 
+    let add_entry_idx entry =
+      add_sleb128 (TM.find entry idx) in
+
     let add_idx t =
       let t = Type.normalize t in
       match to_idl_prim t with
       | Some i -> add_sleb128 (Int32.neg i)
-      | None -> add_sleb128 (TM.find (normalize t) idx) in
+      | None -> add_entry_idx (false, t) in
 
-    let rec add_typ t =
+    let rec add_typ (is_vec, t) =
       match t with
+      | Array t when is_vec ->
+        add_sleb128 idl_vec; add_idx t
       | Non -> assert false
       | Prim Blob ->
-        add_typ Type.(Array (Prim Nat8))
+        add_sleb128 idl_vec; add_idx (Prim Nat8)
       | Prim _ -> assert false
       | Tup ts ->
         add_sleb128 idl_record;
@@ -4717,7 +5059,9 @@ module MakeSerialization (Strm : Stream) = struct
           add_idx f.typ
         ) (sort_by_hash fs)
       | Array (Mut t) ->
-        add_sleb128 idl_alias; add_idx (Array t)
+        add_sleb128 idl_alias; add_entry_idx (vec_entry (Array t))
+      | Array t when is_shared_array (Array t) ->
+        add_sleb128 idl_alias; add_entry_idx (vec_entry (Array t))
       | Array t ->
         add_sleb128 idl_vec; add_idx t
       | Opt t ->
@@ -4768,7 +5112,7 @@ module MakeSerialization (Strm : Stream) = struct
   let rec buffer_size env t =
     let open Type in
     let t = Type.normalize t in
-    let name = "@buffer_size<" ^ typ_hash t ^ ">" in
+    let name = Strm.name_for "buffer_size" [t] in
     Func.share_code1 env name ("x", I32Type) [I32Type; I32Type]
     (fun env get_x ->
 
@@ -4828,6 +5172,32 @@ module MakeSerialization (Strm : Stream) = struct
         end
       in
 
+      let size_shared size_thing =
+        (* see Note [shared immutable stable values] *)
+        get_x ^^ Blob.lit env (typ_hash t) ^^ compile_unboxed_const 0l ^^
+        E.call_import env "rts" "stable_sharing_visit" ^^
+        compile_eq_const (-1l) ^^
+        G.if0 begin
+          (* Not yet seen *)
+          (* One byte marker, two words scratch space *)
+          inc_data_size (compile_unboxed_const 9l) ^^
+          size_thing ()
+        end begin
+          (* Seen before *)
+          (* One byte marker, one word offset *)
+          inc_data_size (compile_unboxed_const 5l)
+        end
+      in
+
+      let size_vec t =
+        size_word env (get_x ^^ Heap.load_field Arr.len_field) ^^
+        get_x ^^ Heap.load_field Arr.len_field ^^
+        from_0_to_n env (fun get_i ->
+          get_x ^^ get_i ^^ Arr.idx env ^^ load_ptr ^^
+          size env t
+        )
+      in
+
       (* Now the actual type-dependent code *)
       begin match t with
       | Prim Nat -> inc_data_size (get_x ^^ BigNum.compile_data_size_unsigned env)
@@ -4851,19 +5221,15 @@ module MakeSerialization (Strm : Stream) = struct
           size env f.typ
           ) (sort_by_hash fs)
       | Array (Mut t) ->
-        size_alias (fun () -> get_x ^^ size env (Array t))
+        size_alias (fun () -> size_vec t)
+      | Array t when is_shared_array (Array t) ->
+        size_shared (fun () -> size_vec t)
       | Array t ->
-        size_word env (get_x ^^ Heap.load_field Arr.len_field) ^^
-        get_x ^^ Heap.load_field Arr.len_field ^^
-        from_0_to_n env (fun get_i ->
-          get_x ^^ get_i ^^ Arr.idx env ^^ load_ptr ^^
-          size env t
-        )
+        size_vec t
       | Prim Blob ->
-        let (set_len, get_len) = new_local env "len" in
-        get_x ^^ Blob.len env ^^ set_len ^^
-        size_word env get_len ^^
-        inc_data_size get_len
+        size_word env (get_x ^^ Blob.len env) ^^
+        (* 0 for blobs spliced into replies *)
+        inc_data_size (get_x ^^ E.call_import env "rts" "reply_splice_blob_size")
       | Prim Text ->
         let (set_len, get_len) = new_local env "len" in
         get_x ^^ Text.size env ^^ set_len ^^
@@ -4970,6 +5336,42 @@ module MakeSerialization (Strm : Stream) = struct
         end
       in
 
+      let write_shared write_thing =
+        (* see Note [shared immutable stable values] *)
+        let (set_offset, get_offset) = new_local env "offset" in
+        (* Offset after the marker byte, recorded if this is the first occurrence *)
+        get_x ^^ Blob.lit env (typ_hash t) ^^
+        Strm.absolute_offset env get_data_buf ^^ compile_add_const 1l ^^
+        E.call_import env "rts" "stable_sharing_visit" ^^ set_offset ^^
+        get_offset ^^ compile_eq_const (-1l) ^^
+        G.if0
+        begin
+          (* This is the real data *)
+          write_byte env get_data_buf (compile_unboxed_const 0l) ^^
+          (* Leave space in the output buffer for the decoder's bookkeeping *)
+          write_word_32 env get_data_buf (compile_unboxed_const 0l) ^^
+          write_word_32 env get_data_buf (compile_unboxed_const 0l) ^^
+          write_thing ()
+        end
+        begin
+          (* This is a reference *)
+          write_byte env get_data_buf (compile_unboxed_const 1l) ^^
+          (* Calculate relative offset *)
+          get_offset ^^ Strm.absolute_offset env get_data_buf ^^ G.i (Binary (Wasm.Values.I32 I32Op.Sub)) ^^
+          set_offset ^^
+          write_word_32 env get_data_buf get_offset
+        end
+      in
+
+      let write_vec t =
+        write_word_leb env get_data_buf (get_x ^^ Heap.load_field Arr.len_field) ^^
+        get_x ^^ Heap.load_field Arr.len_field ^^
+        from_0_to_n env (fun get_i ->
+          get_x ^^ get_i ^^ Arr.idx env ^^ load_ptr ^^
+          write env t
+        )
+      in
+
       (* Now the actual serialization *)
 
       begin match t with
@@ -5010,14 +5412,11 @@ module MakeSerialization (Strm : Stream) = struct
           write env f.typ
         ) (sort_by_hash fs)
       | Array (Mut t) ->
-        write_alias (fun () -> get_x ^^ write env (Array t))
+        write_alias (fun () -> write_vec t)
+      | Array t when is_shared_array (Array t) ->
+        write_shared (fun () -> write_vec t)
       | Array t ->
-        write_word_leb env get_data_buf (get_x ^^ Heap.load_field Arr.len_field) ^^
-        get_x ^^ Heap.load_field Arr.len_field ^^
-        from_0_to_n env (fun get_i ->
-          get_x ^^ get_i ^^ Arr.idx env ^^ load_ptr ^^
-          write env t
-        )
+        write_vec t
       | Prim Null -> G.nop
       | Any -> G.nop
       | Opt t ->
@@ -5383,7 +5782,14 @@ module MakeSerialization (Strm : Stream) = struct
           (* Decoded before. Check type hash *)
           ReadBuf.read_word32 env get_data_buf ^^ Blob.lit env (typ_hash t) ^^
           G.i (Compare (Wasm.Values.I32 I32Op.Eq)) ^^
-          E.else_trap_with env ("Stable memory error: Aliased at wrong type, expected: " ^ typ_hash t)
+          match t with
+          | Array t' when not (is_mut t') ->
+            (* Shared immutable arrays can be read at different types (e.g. `[Nat]`
+               and `[Int]`), read a copy at this type, see
+               Note [shared immutable stable values] *)
+            G.if0 G.nop (read_thing get_arg_typ (fun get_thing -> get_thing ^^ set_result))
+          | _ ->
+            E.else_trap_with env ("Stable memory error: Aliased at wrong type, expected: " ^ typ_hash t)
         end ^^
 
         (* If this was a reference, reset read buffer *)
@@ -5467,6 +5873,7 @@ module MakeSerialization (Strm : Stream) = struct
         (* Any vanilla value works here *)
         Opt.null_lit env
       | Prim Blob ->
+        (* `vec nat8` is copied into the blob, no per-element values *)
         with_blob_typ env (read_blob ())
       | Prim Principal ->
         with_prim_typ t
@@ -5554,20 +5961,29 @@ module MakeSerialization (Strm : Stream) = struct
           )
         )
       | Array t ->
-        let (set_len, get_len) = new_local env "len" in
-        let (set_x, get_x) = new_local env "x" in
-        let (set_val, get_val) = new_local env "val" in
-        let (set_arg_typ, get_arg_typ) = new_local env "arg_typ" in
-        with_composite_typ idl_vec (ReadBuf.read_sleb128 env) ^^ set_arg_typ ^^
-        ReadBuf.read_leb128 env get_data_buf ^^ set_len ^^
-        get_len ^^ Arr.alloc env ^^ set_x ^^
-        get_len ^^ from_0_to_n env (fun get_i ->
-          get_x ^^ get_i ^^ Arr.idx env ^^
-          get_arg_typ ^^ go env t ^^ set_val ^^
-          remember_failure get_val ^^
-          get_val ^^ store_ptr
-        ) ^^
-        get_x
+        let read_vec get_array_typ on_alloc =
+          let (set_len, get_len) = new_local env "len" in
+          let (set_x, get_x) = new_local env "x" in
+          let (set_val, get_val) = new_local env "val" in
+          let (set_arg_typ, get_arg_typ) = new_local env "arg_typ" in
+          with_composite_arg_typ get_array_typ idl_vec (ReadBuf.read_sleb128 env) ^^ set_arg_typ ^^
+          ReadBuf.read_leb128 env get_data_buf ^^ set_len ^^
+          get_len ^^ Arr.alloc env ^^ set_x ^^
+          on_alloc get_x ^^
+          get_len ^^ from_0_to_n env (fun get_i ->
+            get_x ^^ get_i ^^ Arr.idx env ^^
+            get_arg_typ ^^ go env t ^^ set_val ^^
+            remember_failure get_val ^^
+            get_val ^^ store_ptr
+          ) ^^
+          get_x
+        in
+        (* Shared immutable arrays, see Note [shared immutable stable values] *)
+        check_composite_typ get_idltyp idl_alias ^^
+        G.if1 I32Type
+          (read_alias env (Array t) (fun get_array_typ on_alloc ->
+            read_vec get_array_typ on_alloc ^^ G.i Drop))
+          (read_vec get_idltyp (fun _ -> G.nop))
       | Opt t ->
         check_prim_typ (Prim Null) ^^
         G.if1 I32Type (Opt.null_lit env)
@@ -5679,6 +6095,13 @@ module MakeSerialization (Strm : Stream) = struct
       when_failed (compile_unboxed_const (coercion_error_value env) ^^ G.i Return)
     )
 
+  (* Drops the table of written shared immutable arrays, see
+     Note [shared immutable stable values] *)
+  let reset_sharing env =
+    if Strm.share_immutable_arrays
+    then E.call_import env "rts" "stable_sharing_reset"
+    else G.nop
+
   let serialize env ts : G.t =
     let name = Strm.name_for "serialize" ts in
     (* returns data/length pointers (will be GC’ed next time!) *)
@@ -5690,10 +6113,12 @@ module MakeSerialization (Strm : Stream) = struct
       let tydesc_len = Int32.of_int (String.length tydesc) in
 
       (* Get object sizes *)
+      reset_sharing env ^^
       get_x ^^
       buffer_size env (Type.seq ts) ^^
       set_refs_size ^^
       set_data_size ^^
+      reset_sharing env ^^
       (* check for overflow *)
       get_data_size ^^
       compile_add_const tydesc_len ^^
@@ -5713,6 +6138,7 @@ module MakeSerialization (Strm : Stream) = struct
       get_data_start ^^
       get_refs_start ^^
       serialize_go env (Type.seq ts) ^^
+      reset_sharing env ^^
 
       (* Sanity check: Did we fill exactly the buffer *)
       get_refs_start ^^ get_refs_size ^^ compile_mul_const Heap.word_size ^^ G.i (Binary (Wasm.Values.I32 I32Op.Add)) ^^
@@ -5911,6 +6337,38 @@ To detect and preserve aliasing, these steps are taken:
 
 *)
 
+(*
+Note [shared immutable stable values]
+~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~
+
+Immutable arrays are not aliased by Candid, so an array reachable from several
+stable variables (or several times from one) would be written several times,
+and read back as several copies. When serializing stable variables
+(`Strm.share_immutable_arrays`), immutable arrays are therefore written with
+the `alias` encoding of Note [mutable stable values] too:
+
+  e([t]) = alias vec e(t)
+
+The heap tag cannot be used to mark immutable arrays as seen: an immutable
+array can be serialized at different types (e.g. `[Nat]` and `[Int]`), with
+different encodings, so occurrences are only shared at the same type. Instead,
+the RTS keeps a table from array and type (the pointer to the static type hash
+blob) to the offset of the first occurrence (`stable_sharing_visit`, see
+rts/motoko-rts/src/stable_sharing.rs). The table is reset before `buffer_size`,
+before `serialize_go` and afterwards, so that both traversals see the same first
+occurrences.
+
+When deserializing, an immutable array is read as `alias vec` if the type table
+says so, and as `vec` otherwise (data written before stable layout version 2).
+Unlike mutable values, a shared immutable array can be read at two different
+types (after an upgrade that changes the types of the stable variables), in
+which case the second occurrence is read as a copy instead of trapping.
+
+Immutable values other than arrays (records, blobs, text) are still copied.
+Cycles in stable data go through mutable values, which are aliased.
+
+*)
+
 end (* MakeSerialization *)
 
 module Serialization = MakeSerialization(BumpStream)
@@ -5938,6 +6396,8 @@ module BlobStream : Stream = struct
 
   let name_for fn_name ts = "@Bl_" ^ fn_name ^ "<" ^ Typ_hash.typ_seq_hash ts ^ ">"
 
+  let share_immutable_arrays = false
+
   let absolute_offset _env get_token =
     let filled_field = Int32.add Blob.len_field 8l in (* see invariant in `stream.rs` *)
     get_token ^^ Heap.load_field_unskewed filled_field
@@ -6010,6 +6470,9 @@ module Stabilization = struct
 
     let name_for fn_name ts = "@Sm_" ^ fn_name ^ "<" ^ Typ_hash.typ_seq_hash ts ^ ">"
 
+    (* see Note [shared immutable stable values] *)
+    let share_immutable_arrays = true
+
     let create env get_data_size set_token get_token header =
       create env (compile_unboxed_const 0x8000l) set_token get_token header ^^
         (* TODO: push header directly? *)
@@ -6152,11 +6615,10 @@ module Stabilization = struct
         StableMem.read_and_clear_word32 env ^^
         StableMem.write_word32 env ^^
 
-        (* save version at M + (pagesize - 4) *)
+        (* save version at M + (pagesize - 4), see rts/motoko-rts/src/stabilization.rs *)
         get_M ^^
-          compile_add64_const (Int64.sub page_size64 4L) ^^
-        (* TODO bump version? *)
-        compile_unboxed_const StableMem.version ^^
+        compile_add64_const (Int64.sub page_size64 4L) ^^
+        E.call_import env "rts" "stable_layout_version" ^^
         StableMem.write_word32 env
 
       end
@@ -6211,11 +6673,7 @@ module Stabilization = struct
 
               (* check version *)
               get_version ^^
-              compile_unboxed_const StableMem.version ^^
-              G.i (Compare (Wasm.Values.I32 I32Op.GtU)) ^^
-              E.then_trap_with env (Printf.sprintf
-                "higher stable memory version (expected %s)"
-                (Int32.to_string StableMem.version)) ^^
+              E.call_import env "rts" "check_stable_layout_version" ^^
 
               (* restore StableMem bytes [0..4) *)
               compile_const_64 0L ^^
@@ -6613,14 +7071,14 @@ module Var = struct
       set_new_val ^^
       G.i (LocalGet (nr i)) ^^
       get_new_val ^^
-      Heap.store_field MutBox.field
+      Heap.store_field_with_barrier env MutBox.field
     | Some (HeapStatic ptr) ->
       SR.Vanilla,
       let (set_new_val, get_new_val) = new_local env "new_val" in
       set_new_val ^^
       compile_unboxed_const ptr ^^
       get_new_val ^^
-      Heap.store_field MutBox.field
+      Heap.store_field_with_barrier env MutBox.field
     | Some (Const _) -> fatal "set_val: %s is const" var
     | Some (PublicMethod _) -> fatal "set_val: %s is PublicMethod" var
     | None   -> fatal "set_val: %s missing" var
@@ -6686,6 +7144,26 @@ module Var = struct
 
 end (* Var *)
 
+(* Safepoints at loop back-edges, see rts/motoko-rts/src/safepoint.rs. Most back-edges only
+   decrement a countdown in a global, the RTS is called every `interval` back-edges. *)
+module Safepoint = struct
+  let interval = 1024l
+
+  let countdown env = E.get_global32_lazy env "__safepoint_countdown" Mutable interval
+
+  let back_edge env =
+    if not !Flags.safepoints then G.nop else
+    G.i (GlobalGet (nr (countdown env))) ^^
+    compile_sub_const 1l ^^
+    G.i (GlobalSet (nr (countdown env))) ^^
+    G.i (GlobalGet (nr (countdown env))) ^^
+    G.if0 G.nop begin
+      compile_unboxed_const interval ^^
+      G.i (GlobalSet (nr (countdown env))) ^^
+      E.call_import env "rts" "safepoint"
+    end
+end
+
 (* Calling well-known prelude functions *)
 (* FIXME: calling into the prelude will not work if we ever need to compile a program
    that requires top-level cps conversion;
@@ -6737,19 +7215,30 @@ module FuncDec = struct
 
   let message_start env sort = match sort with
       | Type.Shared Type.Write ->
-        Lifecycle.trans env Lifecycle.InUpdate
+        Lifecycle.trans env Lifecycle.InUpdate ^^
+        OnTrap.clear env
       | Type.Shared Type.Query ->
         Lifecycle.trans env Lifecycle.InQuery
       | _ -> assert false
 
   let message_cleanup env sort = match sort with
       | Type.Shared Type.Write ->
+        OnTrap.clear env ^^
         E.collect_garbage env ^^
         Lifecycle.trans env Lifecycle.Idle
       | Type.Shared Type.Query ->
         Lifecycle.trans env Lifecycle.PostQuery
       | _ -> assert false
 
+  (* Looks up (and frees) the continuation of a callback, and restores the on-trap handlers
+     stored with it, so that handlers registered before an await stay registered after it *)
+  let recall_continuation env =
+    let (set_entry, get_entry) = new_local env "entry" in
+    ContinuationTable.recall env ^^
+    set_entry ^^
+    get_entry ^^ Arr.load_field OnTrap.array_index ^^ OnTrap.set_handlers env ^^
+    get_entry
+
   let compile_const_message outer_env outer_ae sort control args mk_body ret_tys at : E.func_with_names =
     let ae0 = VarEnv.mk_fun_ae outer_ae in
     Func.of_body outer_env [] [] (fun env -> G.with_region at (
@@ -6909,7 +7398,7 @@ module FuncDec = struct
         (* Look up continuation *)
         let (set_closure, get_closure) = new_local env "closure" in
         G.i (LocalGet (nr 0l)) ^^
-        ContinuationTable.recall env ^^
+        recall_continuation env ^^
         Arr.load_field 0l ^^ (* get the reply closure *)
         set_closure ^^
         get_closure ^^
@@ -6929,7 +7418,7 @@ module FuncDec = struct
         (* Look up continuation *)
         let (set_closure, get_closure) = new_local env "closure" in
         G.i (LocalGet (nr 0l)) ^^
-        ContinuationTable.recall env ^^
+        recall_continuation env ^^
         Arr.load_field 1l ^^ (* get the reject closure *)
         set_closure ^^
         get_closure ^^
@@ -6945,10 +7434,14 @@ module FuncDec = struct
       );
 
     (* result is a function that accepts a list of closure getters, from which
-       the first and second must be the reply and reject continuations. *)
+       the first and second must be the reply and reject continuations.
+       The on-trap handlers are stored after them, at OnTrap.array_index. *)
     fun closure_getters ->
       let (set_cb_index, get_cb_index) = new_local env "cb_index" in
-      Arr.lit env closure_getters ^^
+      let entry = match closure_getters with
+        | get_k :: get_r :: extras -> get_k :: get_r :: OnTrap.handlers env :: extras
+        | _ -> assert false in
+      Arr.lit env entry ^^
       ContinuationTable.remember env ^^
       set_cb_index ^^
 
@@ -6970,12 +7463,21 @@ module FuncDec = struct
        get in the way of safe instantaneous upgrades *)
     compile_unboxed_const (-1l)
 
+  (* Called by the IC when the reply or reject callback traps. Frees the continuation, and runs
+     the on-trap handlers registered before the await, last registered first *)
   let cleanup_callback env =
     let name = "@cleanup_callback" in
     Func.define_built_in env name ["env", I32Type] [] (fun env ->
+        let (set_handler, get_handler) = new_local env "handler" in
+        message_start env (Type.Shared Type.Write) ^^
         G.i (LocalGet (nr 0l)) ^^
-        ContinuationTable.recall env ^^
-        G.i Drop);
+        recall_continuation env ^^
+        G.i Drop ^^
+        compile_while env
+          (OnTrap.next env ^^ set_handler ^^
+           get_handler ^^ compile_unboxed_zero ^^ G.i (Compare (Wasm.Values.I32 I32Op.Ne)))
+          (get_handler ^^ get_handler ^^ Closure.call_closure env 0 0) ^^
+        message_cleanup env (Type.Shared Type.Write));
     compile_unboxed_const (E.add_fun_ptr env (E.built_in env name))
 
   let ic_call_threaded env purpose get_meth_pair push_continuations add_data add_cycles =
@@ -7028,7 +7530,7 @@ module FuncDec = struct
       env
       "self call"
       get_meth_pair
-      (* Storing the tuple away, future_array_index = 2, keep in sync with rts/continuation_table.rs *)
+      (* Storing the tuple away, future_array_index = 3, keep in sync with rts/continuation_table.rs *)
       (closures_to_reply_reject_callbacks env ts [get_k; get_r; get_future])
       (fun get_cb_index ->
         get_cb_index ^^
@@ -7379,9 +7881,9 @@ let compile_lit_as env sr_out lit =
   code ^^ StackRep.adjust env sr_in sr_out
 
 
-(* helper, traps with message *)
-let then_arithmetic_overflow env =
-  E.then_trap_with env "arithmetic overflow"
+(* helper, traps with message naming the type and the operation, e.g. "Nat8 addition overflow" *)
+let then_arithmetic_overflow env ty op =
+  E.then_trap_with env (Operator.binop_overflow op Type.(Prim ty))
 
 (* The first returned StackRep is for the arguments (expected), the second for the results (produced) *)
 let compile_unop env t op =
@@ -7394,20 +7896,20 @@ let compile_unop env t op =
     BigNum.compile_neg env
   | NegOp, Type.(Prim Int64) ->
       SR.UnboxedWord64, SR.UnboxedWord64,
-      Func.share_code1 env "neg_trap" ("n", I64Type) [I64Type] (fun env get_n ->
+      Func.share_code1 env (prim_fun_name Type.Int64 "neg_trap") ("n", I64Type) [I64Type] (fun env get_n ->
         get_n ^^
         compile_eq64_const 0x8000000000000000L ^^
-        then_arithmetic_overflow env ^^
+        E.then_trap_with env (unop_overflow NegOp t) ^^
         compile_const_64 0L ^^
         get_n ^^
         G.i (Binary (Wasm.Values.I64 I64Op.Sub))
       )
-  | NegOp, Type.(Prim (Int8 | Int16 | Int32)) ->
+  | NegOp, Type.(Prim (Int8 | Int16 | Int32 as ty)) ->
     StackRep.of_type t, StackRep.of_type t,
-    Func.share_code1 env "neg32_trap" ("n", I32Type) [I32Type] (fun env get_n ->
+    Func.share_code1 env (prim_fun_name ty "neg_trap") ("n", I32Type) [I32Type] (fun env get_n ->
       get_n ^^
       compile_eq_const 0x80000000l ^^
-      then_arithmetic_overflow env ^^
+      E.then_trap_with env (unop_overflow NegOp t) ^^
       compile_unboxed_zero ^^
       get_n ^^
       G.i (Binary (Wasm.Values.I32 I32Op.Sub))
@@ -7430,9 +7932,9 @@ let compile_unop env t op =
 
 (* Logarithmic helpers for deciding whether we can carry out operations in constant bitwidth *)
 
-(* helper, traps with message *)
-let else_arithmetic_overflow env =
-  E.else_trap_with env "arithmetic overflow"
+(* helper, traps with message naming the type and the operation *)
+let else_arithmetic_overflow env ty op =
+  E.else_trap_with env (Operator.binop_overflow op Type.(Prim ty))
 
 (* helpers to decide if Int64 arithmetic can be carried out on the fast path *)
 let additiveInt64_shortcut fast env get_a get_b slow =
@@ -7474,7 +7976,7 @@ let powInt64_shortcut fast env get_a get_b slow =
             get_a (* {0,1}^(1+n) *)
             begin
               get_b ^^ compile_const_64 64L ^^
-              G.i (Compare (Wasm.Values.I64 I64Op.GeU)) ^^ then_arithmetic_overflow env ^^
+              G.i (Compare (Wasm.Values.I64 I64Op.GeU)) ^^ then_arithmetic_overflow env Type.Int64 Operator.PowOp ^^
               get_a ^^ get_a ^^ compile_shl64_const 1L ^^ G.i (Binary (Wasm.Values.I64 I64Op.Xor)) ^^
               G.i (Unary (Wasm.Values.I64 I64Op.Clz)) ^^ compile_sub64_const 63L ^^
               get_b ^^ G.i (Binary (Wasm.Values.I64 I64Op.Mul)) ^^
@@ -7488,7 +7990,7 @@ let powInt64_shortcut fast env get_a get_b slow =
 
 
 (* kernel for Int64 arithmetic, invokes estimator for fast path *)
-let compile_Int64_kernel env name op shortcut =
+let compile_Int64_kernel env bop name op shortcut =
   Func.share_code2 env (prim_fun_name Type.Int64 name)
     (("a", I64Type), ("b", I64Type)) [I64Type]
     BigNum.(fun env get_a get_b ->
@@ -7503,7 +8005,7 @@ let compile_Int64_kernel env name op shortcut =
         op env ^^
         set_res ^^ get_res ^^
         fits_signed_bits env 64 ^^
-        else_arithmetic_overflow env ^^
+        else_arithmetic_overflow env Type.Int64 bop ^^
         get_res ^^ truncate_to_word64 env
       end)
 
@@ -7537,7 +8039,7 @@ let powNat64_shortcut fast env get_a get_b slow =
       G.if1 I64Type
         get_a (* {0,1}^(1+n) *)
         begin
-          get_b ^^ compile_const_64 64L ^^ G.i (Compare (Wasm.Values.I64 I64Op.GeU)) ^^ then_arithmetic_overflow env ^^
+          get_b ^^ compile_const_64 64L ^^ G.i (Compare (Wasm.Values.I64 I64Op.GeU)) ^^ then_arithmetic_overflow env Type.Nat64 Operator.PowOp ^^
           get_a ^^ G.i (Unary (Wasm.Values.I64 I64Op.Clz)) ^^ compile_sub64_const 64L ^^
           get_b ^^ G.i (Binary (Wasm.Values.I64 I64Op.Mul)) ^^ compile_const_64 (-64L) ^^ G.i (Compare (Wasm.Values.I64 I64Op.GeS)) ^^
           G.if1 I64Type
@@ -7548,7 +8050,7 @@ let powNat64_shortcut fast env get_a get_b slow =
 
 
 (* kernel for Nat64 arithmetic, invokes estimator for fast path *)
-let compile_Nat64_kernel env name op shortcut =
+let compile_Nat64_kernel env bop name op shortcut =
   Func.share_code2 env (prim_fun_name Type.Nat64 name)
     (("a", I64Type), ("b", I64Type)) [I64Type]
     BigNum.(fun env get_a get_b ->
@@ -7563,7 +8065,7 @@ let compile_Nat64_kernel env name op shortcut =
         op env ^^
         set_res ^^ get_res ^^
         fits_unsigned_bits env 64 ^^
-        else_arithmetic_overflow env ^^
+        else_arithmetic_overflow env Type.Nat64 bop ^^
         get_res ^^ truncate_to_word64 env
       end)
 
@@ -7571,18 +8073,18 @@ let compile_Nat64_kernel env name op shortcut =
 (* Compiling Int/Nat32 ops by conversion to/from i64. *)
 
 (* helper, expects i64 on stack *)
-let enforce_32_unsigned_bits env =
+let enforce_32_unsigned_bits env ty op =
   compile_bitand64_const 0xFFFFFFFF00000000L ^^
   G.i (Test (Wasm.Values.I64 I64Op.Eqz)) ^^
-  else_arithmetic_overflow env
+  else_arithmetic_overflow env ty op
 
 (* helper, expects two identical i64s on stack *)
-let enforce_32_signed_bits env =
+let enforce_32_signed_bits env ty op =
   compile_shl64_const 1L ^^
   G.i (Binary (Wasm.Values.I64 I64Op.Xor)) ^^
-  enforce_32_unsigned_bits env
+  enforce_32_unsigned_bits env ty op
 
-let compile_Int32_kernel env name op =
+let compile_Int32_kernel env bop name op =
      Func.share_code2 env (prim_fun_name Type.Int32 name)
        (("a", I32Type), ("b", I32Type)) [I32Type]
        (fun env get_a get_b ->
@@ -7591,10 +8093,10 @@ let compile_Int32_kernel env name op =
          get_b ^^ G.i (Convert (Wasm.Values.I64 I64Op.ExtendSI32)) ^^
          G.i (Binary (Wasm.Values.I64 op)) ^^
          set_res ^^ get_res ^^ get_res ^^
-         enforce_32_signed_bits env ^^
+         enforce_32_signed_bits env Type.Int32 bop ^^
          get_res ^^ G.i (Convert (Wasm.Values.I32 I32Op.WrapI64)))
 
-let compile_Nat32_kernel env name op =
+let compile_Nat32_kernel env bop name op =
      Func.share_code2 env (prim_fun_name Type.Nat32 name)
        (("a", I32Type), ("b", I32Type)) [I32Type]
        (fun env get_a get_b ->
@@ -7603,26 +8105,26 @@ let compile_Nat32_kernel env name op =
          get_b ^^ G.i (Convert (Wasm.Values.I64 I64Op.ExtendUI32)) ^^
          G.i (Binary (Wasm.Values.I64 op)) ^^
          set_res ^^ get_res ^^
-         enforce_32_unsigned_bits env ^^
+         enforce_32_unsigned_bits env Type.Nat32 bop ^^
          get_res ^^ G.i (Convert (Wasm.Values.I32 I32Op.WrapI64)))
 
 (* Customisable kernels for 8/16bit arithmetic via 32 bits. *)
 
 (* helper, expects i32 on stack *)
-let enforce_unsigned_bits env n =
+let enforce_unsigned_bits env n ty op =
   compile_bitand_const Int32.(shift_left minus_one n) ^^
-  then_arithmetic_overflow env
+  then_arithmetic_overflow env ty op
 
 let enforce_16_unsigned_bits env = enforce_unsigned_bits env 16
 
 (* helper, expects two identical i32s on stack *)
-let enforce_signed_bits env n =
+let enforce_signed_bits env n ty op =
   compile_shl_const 1l ^^ G.i (Binary (Wasm.Values.I32 I32Op.Xor)) ^^
-  enforce_unsigned_bits env n
+  enforce_unsigned_bits env n ty op
 
 let enforce_16_signed_bits env = enforce_signed_bits env 16
 
-let compile_smallInt_kernel' env ty name op =
+let compile_smallInt_kernel' env ty bop name op =
   Func.share_code2 env (prim_fun_name ty name)
     (("a", I32Type), ("b", I32Type)) [I32Type]
     (fun env get_a get_b ->
@@ -7631,13 +8133,13 @@ let compile_smallInt_kernel' env ty name op =
       get_b ^^ compile_shrS_const 16l ^^
       op ^^
       set_res ^^ get_res ^^ get_res ^^
-      enforce_16_signed_bits env ^^
+      enforce_16_signed_bits env ty bop ^^
       get_res ^^ compile_shl_const 16l)
 
-let compile_smallInt_kernel env ty name op =
-  compile_smallInt_kernel' env ty name (G.i (Binary (Wasm.Values.I32 op)))
+let compile_smallInt_kernel env ty bop name op =
+  compile_smallInt_kernel' env ty bop name (G.i (Binary (Wasm.Values.I32 op)))
 
-let compile_smallNat_kernel' env ty name op =
+let compile_smallNat_kernel' env ty bop name op =
   Func.share_code2 env (prim_fun_name ty name)
     (("a", I32Type), ("b", I32Type)) [I32Type]
     (fun env get_a get_b ->
@@ -7646,11 +8148,11 @@ let compile_smallNat_kernel' env ty name op =
       get_b ^^ compile_shrU_const 16l ^^
       op ^^
       set_res ^^ get_res ^^
-      enforce_16_unsigned_bits env ^^
+      enforce_16_unsigned_bits env ty bop ^^
       get_res ^^ compile_shl_const 16l)
 
-let compile_smallNat_kernel env ty name op =
-  compile_smallNat_kernel' env ty name (G.i (Binary (Wasm.Values.I32 op)))
+let compile_smallNat_kernel env ty bop name op =
+  compile_smallNat_kernel' env ty bop name (G.i (Binary (Wasm.Values.I32 op)))
 
 (* The first returned StackRep is for the arguments (expected), the second for the results (produced) *)
 let compile_binop env t op : SR.t * SR.t * G.t =
@@ -7661,20 +8163,20 @@ let compile_binop env t op : SR.t * SR.t * G.t =
   | Type.(Prim (Nat | Int)),                  AddOp -> BigNum.compile_add env
   | Type.(Prim (Nat64|Int64)),                WAddOp -> G.i (Binary (Wasm.Values.I64 I64Op.Add))
   | Type.(Prim Int64),                        AddOp ->
-    compile_Int64_kernel env "add" BigNum.compile_add
+    compile_Int64_kernel env AddOp "add" BigNum.compile_add
       (additiveInt64_shortcut (G.i (Binary (Wasm.Values.I64 I64Op.Add))))
   | Type.(Prim Nat64),                        AddOp ->
-    compile_Nat64_kernel env "add" BigNum.compile_add
+    compile_Nat64_kernel env AddOp "add" BigNum.compile_add
       (additiveNat64_shortcut (G.i (Binary (Wasm.Values.I64 I64Op.Add))))
   | Type.(Prim Nat),                          SubOp -> BigNum.compile_unsigned_sub env
   | Type.(Prim Int),                          SubOp -> BigNum.compile_signed_sub env
   | Type.(Prim (Nat | Int)),                  MulOp -> BigNum.compile_mul env
   | Type.(Prim (Nat64|Int64)),                WMulOp -> G.i (Binary (Wasm.Values.I64 I64Op.Mul))
   | Type.(Prim Int64),                        MulOp ->
-    compile_Int64_kernel env "mul" BigNum.compile_mul
+    compile_Int64_kernel env MulOp "mul" BigNum.compile_mul
       (mulInt64_shortcut (G.i (Binary (Wasm.Values.I64 I64Op.Mul))))
   | Type.(Prim Nat64),                        MulOp ->
-    compile_Nat64_kernel env "mul" BigNum.compile_mul
+    compile_Nat64_kernel env MulOp "mul" BigNum.compile_mul
       (mulNat64_shortcut (G.i (Binary (Wasm.Values.I64 I64Op.Mul))))
   | Type.(Prim Nat64),                        DivOp -> G.i (Binary (Wasm.Values.I64 I64Op.DivU))
   | Type.(Prim Nat64) ,                       ModOp -> G.i (Binary (Wasm.Values.I64 I64Op.RemU))
@@ -7684,42 +8186,43 @@ let compile_binop env t op : SR.t * SR.t * G.t =
   | Type.(Prim Nat),                          ModOp -> BigNum.compile_unsigned_rem env
   | Type.(Prim (Nat64|Int64)),                WSubOp -> G.i (Binary (Wasm.Values.I64 I64Op.Sub))
   | Type.(Prim Int64),                        SubOp ->
-    compile_Int64_kernel env "sub" BigNum.compile_signed_sub
+    compile_Int64_kernel env SubOp "sub" BigNum.compile_signed_sub
       (additiveInt64_shortcut (G.i (Binary (Wasm.Values.I64 I64Op.Sub))))
   | Type.(Prim Nat64),                        SubOp ->
-    compile_Nat64_kernel env "sub" BigNum.compile_unsigned_sub
+    compile_Nat64_kernel env SubOp "sub" BigNum.compile_unsigned_sub
       (fun env get_a get_b ->
+        (* check before the slow path, so that it traps with the same message *)
+        get_a ^^ get_b ^^ G.i (Compare (Wasm.Values.I64 I64Op.GeU)) ^^
+        else_arithmetic_overflow env Type.Nat64 SubOp ^^
         additiveNat64_shortcut
-          (G.i (Compare (Wasm.Values.I64 I64Op.GeU)) ^^
-           else_arithmetic_overflow env ^^
-           get_a ^^ get_b ^^ G.i (Binary (Wasm.Values.I64 I64Op.Sub)))
+          (G.i (Binary (Wasm.Values.I64 I64Op.Sub)))
           env get_a get_b)
   | Type.(Prim Int),                          DivOp -> BigNum.compile_signed_div env
   | Type.(Prim Int),                          ModOp -> BigNum.compile_signed_mod env
 
   | Type.Prim Type.(Nat8|Nat16|Nat32|Int8|Int16|Int32),
                                               WAddOp -> G.i (Binary (Wasm.Values.I32 I32Op.Add))
-  | Type.(Prim Int32),                        AddOp -> compile_Int32_kernel env "add" I64Op.Add
-  | Type.Prim Type.(Int8 | Int16 as ty),      AddOp -> compile_smallInt_kernel env ty "add" I32Op.Add
-  | Type.(Prim Nat32),                        AddOp -> compile_Nat32_kernel env "add" I64Op.Add
-  | Type.Prim Type.(Nat8 | Nat16 as ty),      AddOp -> compile_smallNat_kernel env ty "add" I32Op.Add
+  | Type.(Prim Int32),                        AddOp -> compile_Int32_kernel env AddOp "add" I64Op.Add
+  | Type.Prim Type.(Int8 | Int16 as ty),      AddOp -> compile_smallInt_kernel env ty AddOp "add" I32Op.Add
+  | Type.(Prim Nat32),                        AddOp -> compile_Nat32_kernel env AddOp "add" I64Op.Add
+  | Type.Prim Type.(Nat8 | Nat16 as ty),      AddOp -> compile_smallNat_kernel env ty AddOp "add" I32Op.Add
   | Type.(Prim Float),                        AddOp -> G.i (Binary (Wasm.Values.F64 F64Op.Add))
   | Type.Prim Type.(Nat8|Nat16|Nat32|Int8|Int16|Int32),
                                               WSubOp -> G.i (Binary (Wasm.Values.I32 I32Op.Sub))
-  | Type.(Prim Int32),                        SubOp -> compile_Int32_kernel env "sub" I64Op.Sub
-  | Type.(Prim (Int8|Int16 as ty)),           SubOp -> compile_smallInt_kernel env ty "sub" I32Op.Sub
-  | Type.(Prim Nat32),                        SubOp -> compile_Nat32_kernel env "sub" I64Op.Sub
-  | Type.(Prim (Nat8|Nat16 as ty)),           SubOp -> compile_smallNat_kernel env ty "sub" I32Op.Sub
+  | Type.(Prim Int32),                        SubOp -> compile_Int32_kernel env SubOp "sub" I64Op.Sub
+  | Type.(Prim (Int8|Int16 as ty)),           SubOp -> compile_smallInt_kernel env ty SubOp "sub" I32Op.Sub
+  | Type.(Prim Nat32),                        SubOp -> compile_Nat32_kernel env SubOp "sub" I64Op.Sub
+  | Type.(Prim (Nat8|Nat16 as ty)),           SubOp -> compile_smallNat_kernel env ty SubOp "sub" I32Op.Sub
   | Type.(Prim Float),                        SubOp -> G.i (Binary (Wasm.Values.F64 F64Op.Sub))
   | Type.Prim Type.(Nat8|Nat16|Nat32|Int8|Int16|Int32 as ty),
                                               WMulOp -> TaggedSmallWord.compile_word_mul env ty
-  | Type.(Prim Int32),                        MulOp -> compile_Int32_kernel env "mul" I64Op.Mul
-  | Type.(Prim Int16),                        MulOp -> compile_smallInt_kernel env Type.Int16 "mul" I32Op.Mul
-  | Type.(Prim Int8),                         MulOp -> compile_smallInt_kernel' env Type.Int8 "mul"
+  | Type.(Prim Int32),                        MulOp -> compile_Int32_kernel env MulOp "mul" I64Op.Mul
+  | Type.(Prim Int16),                        MulOp -> compile_smallInt_kernel env Type.Int16 MulOp "mul" I32Op.Mul
+  | Type.(Prim Int8),                         MulOp -> compile_smallInt_kernel' env Type.Int8 MulOp "mul"
                                                          (compile_shrS_const 8l ^^ G.i (Binary (Wasm.Values.I32 I32Op.Mul)))
-  | Type.(Prim Nat32),                        MulOp -> compile_Nat32_kernel env "mul" I64Op.Mul
-  | Type.(Prim Nat16),                        MulOp -> compile_smallNat_kernel env Type.Nat16 "mul" I32Op.Mul
-  | Type.(Prim Nat8),                         MulOp -> compile_smallNat_kernel' env Type.Nat8 "mul"
+  | Type.(Prim Nat32),                        MulOp -> compile_Nat32_kernel env MulOp "mul" I64Op.Mul
+  | Type.(Prim Nat16),                        MulOp -> compile_smallNat_kernel env Type.Nat16 MulOp "mul" I32Op.Mul
+  | Type.(Prim Nat8),                         MulOp -> compile_smallNat_kernel' env Type.Nat8 MulOp "mul"
                                                          (compile_shrU_const 8l ^^ G.i (Binary (Wasm.Values.I32 I32Op.Mul)))
   | Type.(Prim Float),                        MulOp -> G.i (Binary (Wasm.Values.F64 F64Op.Mul))
   | Type.(Prim (Nat8|Nat16|Nat32 as ty)),     DivOp -> G.i (Binary (Wasm.Values.I32 I32Op.DivU)) ^^
@@ -7762,11 +8265,11 @@ let compile_binop env t op : SR.t * SR.t * G.t =
                 unsigned_dynamics get_n ^^ compile_sub_const (Int32.of_int bits) ^^
                 get_exp ^^ TaggedSmallWord.lsb_adjust ty ^^ G.i (Binary (Wasm.Values.I32 I32Op.Mul)) ^^
                 compile_unboxed_const (-30l) ^^
-                G.i (Compare (Wasm.Values.I32 I32Op.LtS)) ^^ then_arithmetic_overflow env ^^
+                G.i (Compare (Wasm.Values.I32 I32Op.LtS)) ^^ then_arithmetic_overflow env ty PowOp ^^
                 get_n ^^ TaggedSmallWord.lsb_adjust ty ^^
                 get_exp ^^ TaggedSmallWord.lsb_adjust ty ^^
                 TaggedSmallWord.compile_nat_power env Type.Nat32 ^^ set_res ^^
-                get_res ^^ enforce_unsigned_bits env bits ^^
+                get_res ^^ enforce_unsigned_bits env bits ty PowOp ^^
                 get_res ^^ TaggedSmallWord.msb_adjust ty
               end
               get_n (* n@{0,1} ** (1+exp) == n *)
@@ -7785,15 +8288,15 @@ let compile_binop env t op : SR.t * SR.t * G.t =
             G.if1 I32Type
               begin
                 get_exp ^^ compile_unboxed_const 32l ^^
-                G.i (Compare (Wasm.Values.I32 I32Op.GeU)) ^^ then_arithmetic_overflow env ^^
+                G.i (Compare (Wasm.Values.I32 I32Op.GeU)) ^^ then_arithmetic_overflow env Type.Nat32 PowOp ^^
                 unsigned_dynamics get_n ^^ compile_sub_const 32l ^^
                 get_exp ^^ TaggedSmallWord.lsb_adjust Type.Nat32 ^^ G.i (Binary (Wasm.Values.I32 I32Op.Mul)) ^^
                 compile_unboxed_const (-62l) ^^
-                G.i (Compare (Wasm.Values.I32 I32Op.LtS)) ^^ then_arithmetic_overflow env ^^
+                G.i (Compare (Wasm.Values.I32 I32Op.LtS)) ^^ then_arithmetic_overflow env Type.Nat32 PowOp ^^
                 get_n ^^ G.i (Convert (Wasm.Values.I64 I64Op.ExtendUI32)) ^^
                 get_exp ^^ G.i (Convert (Wasm.Values.I64 I64Op.ExtendUI32)) ^^
                 Word64.compile_unsigned_pow env ^^
-                set_res ^^ get_res ^^ enforce_32_unsigned_bits env ^^
+                set_res ^^ get_res ^^ enforce_32_unsigned_bits env Type.Nat32 PowOp ^^
                 get_res ^^ G.i (Convert (Wasm.Values.I32 I32Op.WrapI64))
               end
               get_n (* n@{0,1} ** (1+exp) == n *)
@@ -7816,11 +8319,11 @@ let compile_binop env t op : SR.t * SR.t * G.t =
                 signed_dynamics get_n ^^ compile_sub_const (Int32.of_int (bits - 1)) ^^
                 get_exp ^^ TaggedSmallWord.lsb_adjust ty ^^ G.i (Binary (Wasm.Values.I32 I32Op.Mul)) ^^
                 compile_unboxed_const (-30l) ^^
-                G.i (Compare (Wasm.Values.I32 I32Op.LtS)) ^^ then_arithmetic_overflow env ^^
+                G.i (Compare (Wasm.Values.I32 I32Op.LtS)) ^^ then_arithmetic_overflow env ty PowOp ^^
                 get_n ^^ TaggedSmallWord.lsb_adjust ty ^^
                 get_exp ^^ TaggedSmallWord.lsb_adjust ty ^^
                 TaggedSmallWord.compile_nat_power env Type.Nat32 ^^
-                set_res ^^ get_res ^^ get_res ^^ enforce_signed_bits env bits ^^
+                set_res ^^ get_res ^^ get_res ^^ enforce_signed_bits env bits ty PowOp ^^
                 get_res ^^ TaggedSmallWord.msb_adjust ty
               end
               get_n (* n@{0,1} ** (1+exp) == n *)
@@ -7855,15 +8358,15 @@ let compile_binop env t op : SR.t * SR.t * G.t =
               end
               begin
                 get_exp ^^ compile_unboxed_const 32l ^^
-                G.i (Compare (Wasm.Values.I32 I32Op.GeU)) ^^ then_arithmetic_overflow env ^^
+                G.i (Compare (Wasm.Values.I32 I32Op.GeU)) ^^ then_arithmetic_overflow env Type.Int32 PowOp ^^
                 signed_dynamics get_n ^^ compile_sub_const 31l ^^
                 get_exp ^^ TaggedSmallWord.lsb_adjust Type.Int32 ^^ G.i (Binary (Wasm.Values.I32 I32Op.Mul)) ^^
                 compile_unboxed_const (-62l) ^^
-                G.i (Compare (Wasm.Values.I32 I32Op.LtS)) ^^ then_arithmetic_overflow env ^^
+                G.i (Compare (Wasm.Values.I32 I32Op.LtS)) ^^ then_arithmetic_overflow env Type.Int32 PowOp ^^
                 get_n ^^ G.i (Convert (Wasm.Values.I64 I64Op.ExtendSI32)) ^^
                 get_exp ^^ G.i (Convert (Wasm.Values.I64 I64Op.ExtendSI32)) ^^
                 Word64.compile_unsigned_pow env ^^
-                set_res ^^ get_res ^^ get_res ^^ enforce_32_signed_bits env ^^
+                set_res ^^ get_res ^^ get_res ^^ enforce_32_signed_bits env Type.Int32 PowOp ^^
                 get_res ^^ G.i (Convert (Wasm.Values.I32 I32Op.WrapI64))
               end
           end
@@ -7879,7 +8382,7 @@ let compile_binop env t op : SR.t * SR.t * G.t =
   | Type.(Prim Nat64),                        WPowOp -> Word64.compile_unsigned_pow env
   | Type.(Prim Int64),                        WPowOp -> Word64.compile_signed_wpow env
   | Type.(Prim Nat64),                        PowOp ->
-    compile_Nat64_kernel env "pow"
+    compile_Nat64_kernel env PowOp "pow"
       BigNum.compile_unsigned_pow
       (powNat64_shortcut (Word64.compile_unsigned_pow env))
   | Type.(Prim Int64),                        PowOp ->
@@ -7890,10 +8393,10 @@ let compile_binop env t op : SR.t * SR.t * G.t =
     E.then_trap_with env "negative power" ^^
     get_exp ^^
     compile_Int64_kernel
-      env "pow" BigNum.compile_unsigned_pow
+      env PowOp "pow" BigNum.compile_unsigned_pow
       (powInt64_shortcut (Word64.compile_unsigned_pow env))
   | Type.(Prim Nat),                          PowOp -> BigNum.compile_unsigned_pow env
-  | Type.(Prim Float),                        PowOp -> E.call_import env "rts" "pow" (* musl *)
+  | Type.(Prim Float),                        PowOp -> E.call_import env "rts" "float_pow"
   | Type.(Prim (Nat64|Int64)),                AndOp -> G.i (Binary (Wasm.Values.I64 I64Op.And))
   | Type.(Prim (Nat8|Nat16|Nat32|Int8|Int16|Int32)),
                                               AndOp -> G.i (Binary (Wasm.Values.I32 I32Op.And))
@@ -7997,13 +8500,13 @@ let rec compile_lexp (env : E.t) ae lexp =
      compile_exp_vanilla env ae e2 ^^ (* idx *)
      Arr.idx_bigint env,
      SR.Vanilla,
-     store_ptr
+     Heap.store_ptr_with_barrier env
   | DotLE (e, n) ->
      compile_exp_vanilla env ae e ^^
      (* Only real objects have mutable fields, no need to branch on the tag *)
      Object.idx env e.note.Note.typ n,
      SR.Vanilla,
-     store_ptr
+     Heap.store_ptr_with_barrier env
 
 and compile_prim_invocation (env : E.t) ae p es at =
   (* for more concise code when all arguments and result use the same sr *)
@@ -8349,17 +8852,60 @@ and compile_prim_invocation (env : E.t) ae p es at =
     compile_exp_vanilla env ae e ^^
     Serialization.deserialize_from_blob false env ts
 
+  | OtherPrim "idl_vec_stream", [e] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "idl_vec_stream"
+
+  | OtherPrim "idl_vec_stream_remaining", [e] ->
+    SR.UnboxedWord32,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "idl_vec_stream_remaining"
+
+  | OtherPrim "idl_vec_stream_next", [e1; e2] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" "idl_vec_stream_next"
+
+  | OtherPrim "idl_lazy", [e] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "idl_lazy"
+
+  | OtherPrim "idl_lazy_is_null", [e1; e2] ->
+    SR.bool,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" "idl_lazy_is_null"
+
+  | OtherPrim "idl_lazy_variant_tag", [e1; e2] ->
+    SR.UnboxedWord32,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" "idl_lazy_variant_tag"
+
+  | OtherPrim ("idl_lazy_some" | "idl_lazy_variant_value" | "idl_lazy_force" as p), [e1; e2] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" p
+
   | ICPerformGC, [] ->
     SR.unit,
     E.collect_garbage env
 
   | ICStableSize t, [e] ->
     SR.UnboxedWord64,
-    let tydesc = Serialization.type_desc env [t] in
+    (* The size of the serialized stable variables, see `Stabilization.stabilize` *)
+    let module Ext = Stabilization.Externalization in
+    let tydesc = Ext.type_desc env [t] in
     let tydesc_len = Int32.of_int (String.length tydesc) in
+    Ext.reset_sharing env ^^
     compile_exp_vanilla env ae e ^^
-    Serialization.buffer_size env t ^^
+    Ext.buffer_size env t ^^
     G.i Drop ^^
+    Ext.reset_sharing env ^^
     compile_add_const tydesc_len  ^^
     G.i (Convert (Wasm.Values.I64 I64Op.ExtendUI32))
 
@@ -8379,6 +8925,29 @@ and compile_prim_invocation (env : E.t) ae p es at =
     SR.bool, compile_exp_vanilla env ae e ^^ Text.iter_done env
   | OtherPrim "text_iter_next", [e] ->
     SR.Vanilla, compile_exp_vanilla env ae e ^^ Text.iter_next env
+  | OtherPrim ("text_to_lower" | "text_to_upper" | "text_nfc" | "text_nfd" as p), [e] ->
+    SR.Vanilla, compile_exp_vanilla env ae e ^^ E.call_import env "rts" p
+  | OtherPrim ("text_split" | "text_join" as p), [e1; e2] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" p
+  | OtherPrim "text_contains", [e1; e2] ->
+    SR.bool,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" "text_contains"
+  | OtherPrim "text_index_of", [e1; e2] ->
+    SR.UnboxedWord32,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" "text_index_of"
+  | OtherPrim "text_replace", [e1; e2; e3] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    compile_exp_vanilla env ae e3 ^^
+    E.call_import env "rts" "text_replace"
 
   | OtherPrim "blob_size", [e] ->
     SR.Vanilla, compile_exp_vanilla env ae e ^^ Blob.len env ^^ BigNum.from_word32 env
@@ -8394,6 +8963,23 @@ and compile_prim_invocation (env : E.t) ae p es at =
     compile_exp_vanilla env ae e ^^
     BigNum.compile_abs env
 
+  (* Bit operations on `Nat` and `Int`, see rts/motoko-rts/src/bigint.rs *)
+  | OtherPrim ("nat_and" | "nat_or" | "nat_xor" as p), [e1; e2] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" p
+  | OtherPrim ("nat_shl" | "nat_shr" as p), [e; shift] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    compile_exp_as env ae SR.UnboxedWord32 shift ^^
+    E.call_import env "rts" p
+  | OtherPrim "nat_popcount", [e] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "nat_popcount" ^^
+    BigNum.from_word32 env
+
   | OtherPrim "fabs", [e] ->
     SR.UnboxedFloat64,
     compile_exp_as env ae SR.UnboxedFloat64 e ^^
@@ -8456,15 +9042,27 @@ and compile_prim_invocation (env : E.t) ae p es at =
     compile_exp_vanilla env ae mode ^^
     E.call_import env "rts" "float_fmt"
 
+  | OtherPrim ("Text->Nat" | "Text->Int" as p), [t; radix] ->
+    let rts_fn = if p = "Text->Nat" then "text_to_nat" else "text_to_int" in
+    let (set_n, get_n) = new_local env "n" in
+    SR.Vanilla,
+    compile_exp_vanilla env ae t ^^
+    compile_exp_as env ae SR.UnboxedWord32 radix ^^
+    E.call_import env "rts" rts_fn ^^
+    set_n ^^ get_n ^^
+    (* INVALID_NUMBER in rts/motoko-rts/src/bigint.rs *)
+    compile_eq_const (-1l) ^^
+    G.if1 I32Type (Opt.null_lit env) (Opt.inject_noop env get_n)
+
   | OtherPrim "fsin", [e] ->
     SR.UnboxedFloat64,
     compile_exp_as env ae SR.UnboxedFloat64 e ^^
-    E.call_import env "rts" "sin" (* musl *)
+    E.call_import env "rts" "float_sin"
 
   | OtherPrim "fcos", [e] ->
     SR.UnboxedFloat64,
     compile_exp_as env ae SR.UnboxedFloat64 e ^^
-    E.call_import env "rts" "cos" (* musl *)
+    E.call_import env "rts" "float_cos"
 
   | OtherPrim "ftan", [e] ->
     SR.UnboxedFloat64,
@@ -8495,12 +9093,12 @@ and compile_prim_invocation (env : E.t) ae p es at =
   | OtherPrim "fexp", [e] ->
     SR.UnboxedFloat64,
     compile_exp_as env ae SR.UnboxedFloat64 e ^^
-    E.call_import env "rts" "exp" (* musl *)
+    E.call_import env "rts" "float_exp"
 
   | OtherPrim "flog", [e] ->
     SR.UnboxedFloat64,
     compile_exp_as env ae SR.UnboxedFloat64 e ^^
-    E.call_import env "rts" "log" (* musl *)
+    E.call_import env "rts" "float_log"
 
   (* Other prims, nullary *)
 
@@ -8508,6 +9106,10 @@ and compile_prim_invocation (env : E.t) ae p es at =
     SR.UnboxedWord64,
     IC.get_system_time env
 
+  | OtherPrim "rts_stable_layout_version", [] ->
+    SR.UnboxedWord32,
+    E.call_import env "rts" "stable_layout_version"
+
   | OtherPrim "rts_version", [] ->
     SR.Vanilla,
     E.call_import env "rts" "version"
@@ -8516,6 +9118,15 @@ and compile_prim_invocation (env : E.t) ae p es at =
     SR.Vanilla,
     Heap.get_heap_size env ^^ Prim.prim_word32toNat env
 
+  | OtherPrim "rts_gc_estimate_reclaimable", [] ->
+    SR.Vanilla,
+    E.call_import env "rts" "gc_estimate_reclaimable" ^^ Prim.prim_word32toNat env
+
+  | OtherPrim "rts_heap_dump", [e] ->
+    SR.UnboxedWord64,
+    compile_exp_as env ae SR.UnboxedWord64 e ^^
+    E.call_import env "rts" "heap_dump_to_stable"
+
   | OtherPrim "rts_memory_size", [] ->
     SR.Vanilla,
     Heap.get_memory_size ^^ BigNum.from_word64 env
@@ -8532,6 +9143,10 @@ and compile_prim_invocation (env : E.t) ae p es at =
     SR.Vanilla,
     Heap.get_max_live_size env ^^ BigNum.from_word32 env
 
+  | OtherPrim "rts_max_heap_size", [] ->
+    SR.Vanilla,
+    Heap.get_max_heap_size env ^^ BigNum.from_word32 env
+
   | OtherPrim "rts_callback_table_count", [] ->
     SR.Vanilla,
     ContinuationTable.count env ^^ Prim.prim_word32toNat env
@@ -8540,11 +9155,187 @@ and compile_prim_invocation (env : E.t) ae p es at =
     SR.Vanilla,
     ContinuationTable.size env ^^ Prim.prim_word32toNat env
 
+  | OtherPrim "rts_alloc_sites", [] ->
+    SR.Vanilla,
+    (if !Flags.alloc_profiling
+     then E.call_import env "rts" "alloc_sites"
+     else compile_unboxed_zero) ^^
+    Prim.prim_word32toNat env
+
+  | OtherPrim ("rts_alloc_site_count" | "rts_alloc_site_bytes" as p), [e] ->
+    let rts_fn = if p = "rts_alloc_site_count" then "alloc_site_count" else "alloc_site_bytes" in
+    SR.Vanilla,
+    compile_exp_as env ae SR.UnboxedWord32 e ^^
+    (if !Flags.alloc_profiling
+     then E.call_import env "rts" rts_fn
+     else G.i Drop ^^ compile_const_64 0L) ^^
+    BigNum.from_word64 env
+
+  | OtherPrim "rts_size_histogram_bucket", [e] ->
+    SR.Vanilla,
+    compile_exp_as env ae SR.UnboxedWord32 e ^^
+    E.call_import env "rts" "size_histogram_bucket" ^^
+    Prim.prim_word32toNat env
+
+  | OtherPrim "rts_reserve_heap", [e] ->
+    SR.unit,
+    compile_exp_as env ae SR.UnboxedWord32 e ^^
+    E.call_import env "rts" "reserve_heap"
+
+  | OtherPrim "rts_register_memory_pressure_handler", [e] ->
+    SR.unit,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "register_memory_pressure_handler"
+
+  | OtherPrim "rts_set_memory_pressure_high_water_mark", [e] ->
+    SR.unit,
+    compile_exp_as env ae SR.UnboxedWord32 e ^^
+    E.call_import env "rts" "set_memory_pressure_high_water_mark"
+
+  | OtherPrim "rts_gc_collect_now", [] ->
+    SR.unit,
+    E.call_import env "rts" "gc_request_collection"
+
+  | OtherPrim "rts_live_history_len", [] ->
+    SR.Vanilla,
+    E.call_import env "rts" "live_history_len" ^^
+    Prim.prim_word32toNat env
+
+  | OtherPrim "rts_live_history_message", [e] ->
+    SR.UnboxedWord64,
+    compile_exp_as env ae SR.UnboxedWord32 e ^^
+    E.call_import env "rts" "live_history_message"
+
+  | OtherPrim "rts_live_history_live", [e] ->
+    SR.Vanilla,
+    compile_exp_as env ae SR.UnboxedWord32 e ^^
+    E.call_import env "rts" "live_history_live" ^^
+    Prim.prim_word32toNat env
+
+  | OtherPrim "rts_gc_pause_percentile", [e] ->
+    SR.UnboxedWord64,
+    compile_exp_as env ae SR.UnboxedFloat64 e ^^
+    E.call_import env "rts" "gc_pause_percentile"
+
+  | OtherPrim "rts_reset_gc_pauses", [] ->
+    SR.unit,
+    E.call_import env "rts" "reset_gc_pauses"
+
+  | OtherPrim "principal_is_controller", [e] ->
+    SR.bool,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "principal_is_controller"
+
+  | OtherPrim "caller_is_controller", [] ->
+    SR.bool,
+    snd (IC.caller env) ^^
+    E.call_import env "rts" "principal_is_controller"
+
+  | OtherPrim "set_controllers", [e] ->
+    SR.unit,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "set_controllers"
+
+  | OtherPrim "onTrap", [e] ->
+    SR.unit,
+    compile_exp_vanilla env ae e ^^
+    OnTrap.register env
+
+  | OtherPrim "rts_self_test", [] ->
+    SR.UnboxedWord32,
+    E.call_import env "rts" "rts_self_test"
+
   | OtherPrim "crc32Hash", [e] ->
     SR.UnboxedWord32,
     compile_exp_vanilla env ae e ^^
     E.call_import env "rts" "compute_crc32"
 
+  | OtherPrim "blobHash", [e] ->
+    SR.UnboxedWord32,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "blob_hash"
+
+  | OtherPrim "blobCompare", [e1; e2] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" "blob_compare" ^^
+    TaggedSmallWord.msb_adjust Type.Int8
+
+  | OtherPrim ("natToNat32Checked" | "natToNat64Checked" as p), [e] ->
+    (* `null` when the number does not fit, instead of trapping like `NumConvTrapPrim` *)
+    let bits, truncate, sr =
+      if p = "natToNat32Checked"
+      then 32, BigNum.truncate_to_word32 env, SR.UnboxedWord32
+      else 64, BigNum.truncate_to_word64 env, SR.UnboxedWord64 in
+    let (set_n, get_n) = new_local env "n" in
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    set_n ^^
+    get_n ^^ BigNum.fits_unsigned_bits env bits ^^
+    G.if1 I32Type
+      (Opt.inject_noop env (get_n ^^ truncate ^^ StackRep.adjust env sr SR.Vanilla))
+      (Opt.null_lit env)
+
+  | OtherPrim "blob_to_hex", [e] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "blob_to_hex"
+
+  | OtherPrim "blob_to_base64", [e1; e2] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_as env ae SR.bool e2 ^^
+    E.call_import env "rts" "blob_to_base64"
+
+  | OtherPrim ("hex_to_blob" | "base64_to_blob" as p), (e :: es) ->
+    (* The RTS returns 0 (a scalar) on malformed input *)
+    let (set_blob, get_blob) = new_local env "blob" in
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    G.concat_map (compile_exp_as env ae SR.bool) es ^^
+    E.call_import env "rts" p ^^
+    set_blob ^^
+    get_blob ^^ G.i (Test (Wasm.Values.I32 I32Op.Eqz)) ^^
+    G.if1 I32Type (Opt.null_lit env) (Opt.inject_noop env get_blob)
+
+  | OtherPrim "textHash", [e] ->
+    SR.UnboxedWord32,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "text_hash"
+
+  | OtherPrim "rngSeed", [e] ->
+    SR.unit,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "rng_seed"
+
+  | OtherPrim "rngNext", [] ->
+    SR.UnboxedWord64,
+    E.call_import env "rts" "rng_next"
+
+  | OtherPrim "rngNextRange", [e] ->
+    SR.UnboxedWord64,
+    compile_exp_as env ae SR.UnboxedWord64 e ^^
+    E.call_import env "rts" "rng_next_range"
+
+  | OtherPrim "rngState", [] ->
+    SR.Vanilla,
+    E.call_import env "rts" "rng_state"
+
+  | OtherPrim "rngSetState", [e] ->
+    SR.unit,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "rng_set_state"
+
+  | OtherPrim "show_cycle_enter", [e] ->
+    SR.bool,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "show_cycle_enter"
+
+  | OtherPrim "show_cycle_leave", [] ->
+    SR.unit,
+    E.call_import env "rts" "show_cycle_leave"
+
   | OtherPrim "idlHash", [e] ->
     SR.Vanilla,
     E.trap_with env "idlHash only implemented in interpreter"
@@ -8610,6 +9401,12 @@ and compile_prim_invocation (env : E.t) ae p es at =
     compile_exp_vanilla env ae e ^^
     IC.trap_text env
 
+  | OtherPrim "trapWithBlob", [e] ->
+    SR.unit,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "trap_with_blob" ^^
+    G.i Unreachable
+
   | OtherPrim ("blobToArray"|"blobToArrayMut"), e ->
     const_sr SR.Vanilla (Arr.ofBlob env)
   | OtherPrim ("arrayToBlob"|"arrayMutToBlob"), e ->
@@ -8718,11 +9515,136 @@ and compile_prim_invocation (env : E.t) ae p es at =
     compile_exp_as env ae SR.UnboxedWord64 e ^^
     StableMem.logical_grow env
 
+  | OtherPrim "ArrayBuffer.new", [] ->
+    SR.Vanilla,
+    E.call_import env "rts" "array_buffer_new"
+
+  | OtherPrim "ArrayBuffer.size", [e] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "array_buffer_len" ^^
+    Prim.prim_word32toNat env
+
+  | OtherPrim "ArrayBuffer.get", [e1; e2] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    Blob.lit env "Array buffer index out of bounds" ^^
+    BigNum.to_word32_with env ^^
+    E.call_import env "rts" "array_buffer_get"
+
+  | OtherPrim "Array.freeze", [e] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "array_freeze"
+
+  | OtherPrim "Array.isFrozen", [e] ->
+    SR.bool,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "array_is_frozen"
+
+  | OtherPrim "ArrayBuffer.toArray", [e] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "array_buffer_to_array"
+
+  | OtherPrim "BlobBuilder.new", [] ->
+    SR.Vanilla,
+    E.call_import env "rts" "blob_builder_new"
+
+  | OtherPrim "BlobBuilder.size", [e] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "blob_builder_len" ^^
+    Prim.prim_word32toNat env
+
+  | OtherPrim "BlobBuilder.finish", [e] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "blob_builder_finish"
+
+  | OtherPrim "BlobMap.new", [] ->
+    SR.Vanilla,
+    E.call_import env "rts" "blob_map_new"
+
+  | OtherPrim "BlobMap.size", [e] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "blob_map_len" ^^
+    Prim.prim_word32toNat env
+
+  | OtherPrim "BlobMap.stableSize", [e] ->
+    SR.UnboxedWord64,
+    compile_exp_vanilla env ae e ^^
+    E.call_import env "rts" "blob_map_stable_size"
+
+  | OtherPrim "BlobMap.load", [e] ->
+    SR.Vanilla,
+    compile_exp_as env ae SR.UnboxedWord64 e ^^
+    E.call_import env "rts" "blob_map_load"
+
   (* Other prims, binary*)
   | OtherPrim "Array.init", [_;_] ->
     const_sr SR.Vanilla (Arr.init env)
   | OtherPrim "Array.tabulate", [_;_] ->
     const_sr SR.Vanilla (Arr.tabulate env)
+  | OtherPrim "Array.sort", [e1; e2] ->
+    SR.unit,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" "array_sort"
+  | OtherPrim "Array.map", [_;_] ->
+    const_sr SR.Vanilla (E.call_import env "rts" "array_map")
+  | OtherPrim "Array.filter", [_;_] ->
+    const_sr SR.Vanilla (E.call_import env "rts" "array_filter")
+  | OtherPrim "Array.dedup", [_;_] ->
+    const_sr SR.Vanilla (E.call_import env "rts" "array_dedup")
+  | OtherPrim "Array.binarySearch", [e1; e2; e3] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    compile_exp_vanilla env ae e3 ^^
+    E.call_import env "rts" "array_binary_search" ^^
+    BigNum.from_signed_word32 env
+  | OtherPrim ("ArrayBuffer.add" | "ArrayBuffer.append" as p), [e1; e2] ->
+    let rts_fn = if p = "ArrayBuffer.add" then "array_buffer_add" else "array_buffer_append" in
+    SR.unit,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" rts_fn
+  | OtherPrim "BlobBuilder.pushByte", [e1; e2] ->
+    SR.unit,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    TaggedSmallWord.lsb_adjust Type.Nat8 ^^
+    E.call_import env "rts" "blob_builder_push_byte"
+  | OtherPrim "BlobBuilder.pushBytes", [e1; e2] ->
+    SR.unit,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" "blob_builder_push_bytes"
+  | OtherPrim ("BlobMap.has" | "BlobMap.remove" as p), [e1; e2] ->
+    let rts_fn = if p = "BlobMap.has" then "blob_map_has" else "blob_map_remove" in
+    SR.bool,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" rts_fn
+  | OtherPrim "BlobMap.get", [e1; e2] ->
+    SR.Vanilla,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    E.call_import env "rts" "blob_map_get"
+  | OtherPrim "BlobMap.put", [e1; e2; e3] ->
+    SR.unit,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_vanilla env ae e2 ^^
+    compile_exp_vanilla env ae e3 ^^
+    E.call_import env "rts" "blob_map_put"
+  | OtherPrim "BlobMap.save", [e1; e2] ->
+    SR.UnboxedWord64,
+    compile_exp_vanilla env ae e1 ^^
+    compile_exp_as env ae SR.UnboxedWord64 e2 ^^
+    E.call_import env "rts" "blob_map_save"
   | OtherPrim "btst8", [_;_] ->
     (* TODO: btstN returns Bool, not a small value *)
     const_sr SR.Vanilla (TaggedSmallWord.btst_kernel env Type.Nat8)
@@ -8765,6 +9687,7 @@ and compile_prim_invocation (env : E.t) ae p es at =
     SR.unit, begin match E.mode env with
     | Flags.ICMode | Flags.RefMode ->
       compile_exp_vanilla env ae e ^^
+      E.call_import env "rts" "reply_splice_begin" ^^
       (* TODO: We can try to avoid the boxing and pass the arguments to
         serialize individually *)
       Serialization.serialize env ts ^^
@@ -8779,6 +9702,9 @@ and compile_prim_invocation (env : E.t) ae p es at =
   | ICCallerPrim, [] ->
     IC.caller env
 
+  | ICMethodNamePrim, [] ->
+    IC.method_name env
+
   | ICCallPrim, [f;e;k;r] ->
     SR.unit, begin
     (* TBR: Can we do better than using the notes? *)
@@ -8837,6 +9763,39 @@ and compile_prim_invocation (env : E.t) ae p es at =
   | SystemCyclesRefundedPrim, [] ->
     SR.Vanilla, Cycles.refunded env
 
+  | OtherPrim "global_timer_set", [e] ->
+    SR.UnboxedWord64,
+    compile_exp_as env ae SR.UnboxedWord64 e ^^
+    IC.set_global_timer env
+
+  | OtherPrim "instructionCount", [] ->
+    SR.UnboxedWord64,
+    E.call_import env "rts" "instruction_count"
+
+  | OtherPrim "instructionBudget", [] ->
+    SR.UnboxedWord64,
+    E.call_import env "rts" "instruction_budget"
+
+  | OtherPrim "setInstructionBudget", [e] ->
+    SR.unit,
+    compile_exp_as env ae SR.UnboxedWord64 e ^^
+    E.call_import env "rts" "set_instruction_budget"
+
+  | OtherPrim "remainingInstructions", [] ->
+    SR.UnboxedWord64,
+    E.call_import env "rts" "remaining_instructions"
+
+  | OtherPrim "setSafepointTrap", [e] ->
+    SR.unit,
+    (* `SafepointAction::Collect` is 0, `SafepointAction::Trap` is 1 *)
+    compile_exp_as env ae SR.bool e ^^
+    E.call_import env "rts" "set_safepoint_action"
+
+  | OtherPrim "rts_safepoint_gc_slices", [] ->
+    SR.Vanilla,
+    E.call_import env "rts" "safepoint_gc_slices" ^^
+    Prim.prim_word32toNat env
+
   | SetCertifiedData, [e1] ->
     SR.unit, compile_exp_vanilla env ae e1 ^^ IC.set_certified_data env
   | GetCertificate, [] ->
@@ -8902,7 +9861,9 @@ and compile_exp (env : E.t) ae exp =
   | LoopE e ->
     SR.Unreachable,
     let ae' = VarEnv.{ ae with lvl = NotTopLvl } in
-    G.loop0 (compile_exp_unit env ae' e ^^ G.i (Br (nr 0l))
+    G.loop0 (compile_exp_unit env ae' e ^^
+      Safepoint.back_edge env ^^
+      G.i (Br (nr 0l))
     )
     ^^
    G.i Unreachable
@@ -9509,6 +10470,24 @@ and main_actor as_opt mod_env ds fs up =
        IC.export_heartbeat env;
     end;
 
+    (* Export global timer (but only when required) *)
+    begin match up.timer.it with
+     | Ir.PrimE (Ir.TupPrim, []) -> ()
+     | _ ->
+       Func.define_built_in env "timer_exp" [] [] (fun env ->
+         compile_exp_as env ae2 SR.unit up.timer);
+       IC.export_timer env;
+    end;
+
+    (* Export inspect (but only when required) *)
+    begin match up.inspect.it with
+     | Ir.LitE (Ir.BoolLit true) -> ()
+     | _ ->
+       Func.define_built_in env "inspect_exp" [] [I32Type] (fun env ->
+         compile_exp_as env ae2 SR.bool up.inspect);
+       IC.export_inspect env;
+    end;
+
     (* Export metadata *)
     env.E.stable_types := metadata "motoko:stable-types" up.meta.sig_;
     env.E.service := metadata "candid:service" up.meta.candid.service;
@@ -9558,6 +10537,12 @@ and conclude_module env start_fi_o =
   let rts_start_fi = E.add_fun env "rts_start" (Func.of_body env [] [] (fun env1 ->
     Bool.lit (!Flags.gc_strategy = Mo_config.Flags.MarkCompact) ^^
     E.call_import env "rts" "init" ^^
+    (* Before the first pointer store, see rts/motoko-rts/src/gc/barrier.rs *)
+    compile_unboxed_const (Heap.write_barrier_mode ()) ^^
+    E.call_import env "rts" "set_write_barrier_mode" ^^
+    (if Heap.write_barrier_mode () = 2l
+     then E.call_import env "rts" "enable_generational_gc"
+     else G.nop) ^^
     match start_fi_o with
     | Some fi ->
       G.i (Call fi)
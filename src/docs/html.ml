@@ -129,7 +129,8 @@ let rec html_of_type : env -> Syntax.typ -> t =
       ++ html_of_type env res
   | Syntax.ArrayT (mut, ty) ->
       string "[" ++ html_of_mut mut ++ html_of_type env ty ++ string "]"
-  | Syntax.AsyncT (_scope, typ) -> keyword "async " ++ html_of_type env typ
+  | Syntax.AsyncT (sort, _scope, typ) ->
+      keyword (Mo_types.Type.string_of_async_sort sort ^ " ") ++ html_of_type env typ
   | Syntax.AndT (typ1, typ2) ->
       html_of_type env typ1 ++ string " and " ++ html_of_type env typ2
   | Syntax.OrT (typ1, typ2) ->
@@ -113,8 +113,8 @@ let rec plain_of_typ : Buffer.t -> render_functions -> Syntax.typ -> unit =
       bprintf buf "(";
       sep_by buf ", " (plain_of_typ_item buf rf) typ_list;
       bprintf buf ")"
-  | Syntax.AsyncT (_scope, typ) ->
-      bprintf buf "async ";
+  | Syntax.AsyncT (sort, _scope, typ) ->
+      bprintf buf "%s " (Mo_types.Type.string_of_async_sort sort);
       plain_of_typ buf rf typ
   | Syntax.AndT (typ1, typ2) ->
       plain_of_typ buf rf typ1;
@@ -146,10 +146,34 @@ let argspec = [
   Arg.Unit (fun () -> Flags.gc_strategy := Mo_config.Flags.Copying),
   " use copying GC (default)";
 
+  "--incremental-gc",
+  Arg.Unit (fun () ->
+    Flags.gc_strategy := Mo_config.Flags.MarkCompact;
+    Flags.gc_mode := Mo_config.Flags.Incremental),
+  " use compacting GC with incremental marking (emits a write barrier for pointer stores)";
+
+  "--generational-gc",
+  Arg.Unit (fun () ->
+    Flags.gc_strategy := Mo_config.Flags.MarkCompact;
+    Flags.gc_mode := Mo_config.Flags.Generational),
+  " use compacting GC with minor collections (emits a write barrier for pointer stores)";
+
   "--force-gc",
   Arg.Unit (fun () -> Flags.force_gc := true),
   " disable GC scheduling, always do GC after an update message (for testing)";
 
+  "--alloc-profiling",
+  Arg.Set Flags.alloc_profiling,
+  " count allocations per allocation site (requires an RTS built with the `alloc-profiling` feature)";
+
+  "--is-controller-api",
+  Arg.Set Flags.is_controller_api,
+  " check controllers with `ic0.is_controller` (requires a replica supporting it)";
+
+  "--no-safepoints",
+  Arg.Clear Flags.safepoints,
+  " do not call the RTS safepoint at loop back-edges";
+
   "--max-stable-pages",
   Arg.Set_int Flags.max_stable_pages,
   "<n>  set maximum number of pages available for library `ExperimentStableMemory.mo` (default " ^ (Int.to_string Flags.max_stable_pages_default) ^ ")"
@@ -222,12 +222,13 @@ let rec check_typ env typ : unit =
        error env no_region "promising function cannot be local:\n  %s" (T.string_of_typ_expand typ);
   | T.Opt typ ->
     check_typ env typ
-  | T.Async (typ1, typ2) ->
+  | T.Async (s, typ1, typ2) ->
     check_typ env typ1;
     check_typ env typ2;
     check env no_region env.flavor.Ir.has_async_typ "async in non-async flavor";
-    let t' = T.promote typ2 in
-    check_shared env no_region t'
+    if s = T.Fut then
+      let t' = T.promote typ2 in
+      check_shared env no_region t'
   | T.Obj (sort, fields) ->
     List.iter (check_typ_field env (Some sort)) fields;
     check_field_hashes env "object" no_region fields;
@@ -348,8 +349,8 @@ let type_lit env lit at : T.prim =
 
 let isAsyncE exp =
   match exp.it with
-  | AsyncE _ (* pre await transformation *)
-  | PrimE (CPSAsync _, [_]) (* post await transformation *)
+  | AsyncE (T.Fut, _, _, _) (* pre await transformation *)
+  | PrimE (CPSAsync (T.Fut, _), [_]) (* post await transformation *)
     -> true
   | _ -> false
 
@@ -507,16 +508,16 @@ let rec check_exp env (exp:Ir.exp) : unit =
       check (env.async <> None) "misplaced throw";
       typ exp1 <: T.throw;
       T.Non <: t (* vacuously true *)
-    | AwaitPrim, [exp1] ->
+    | AwaitPrim s, [exp1] ->
       check env.flavor.has_await "await in non-await flavor";
       let t0 = match env.async with
       | Some c -> T.Con(c, [])
       | None -> error env exp.at "misplaced await" in
       let t1 = T.promote (typ exp1) in
-      let (t2, t3) = try T.as_async_sub t0 t1
+      let (t2, t3) = try T.as_async_sub s t0 t1
              with Invalid_argument _ ->
-               error env exp1.at "expected async type, but expression has type\n  %s"
-                 (T.string_of_typ_expand t1)
+               error env exp1.at "expected %s type, but expression has type\n  %s"
+                 (T.string_of_async_sort s) (T.string_of_typ_expand t1)
       in
       check (T.eq t0 t2) "ill-scoped async";
       t3 <: t
@@ -536,11 +537,11 @@ let rec check_exp env (exp:Ir.exp) : unit =
       check (T.shared (T.seq ots)) "debug_deserialize is not defined for operand type";
       typ exp1 <: T.blob;
       T.seq ots <: t
-    | CPSAwait cont_typ, [a; kr] ->
+    | CPSAwait (s, cont_typ), [a; kr] ->
       check (not (env.flavor.has_await)) "CPSAwait await flavor";
       check (env.flavor.has_async_typ) "CPSAwait in post-async flavor";
       let (_, t1) =
-        try T.as_async_sub T.Non (T.normalize (typ a))
+        try T.as_async_sub s T.Non (T.normalize (typ a))
         with _ -> error env exp.at "CPSAwait expect async arg, found %s" (T.string_of_typ (typ a))
       in
       (match cont_typ with
@@ -554,7 +555,7 @@ let rec check_exp env (exp:Ir.exp) : unit =
            T.seq ts2 <: t;
          end;
        | _ -> error env exp.at "CPSAwait bad cont")
-    | CPSAsync t0, [exp] ->
+    | CPSAsync (s, t0), [exp] ->
       check (not (env.flavor.has_await)) "CPSAsync await flavor";
       check (env.flavor.has_async_typ) "CPSAsync in post-async flavor";
       check_typ env t0;
@@ -564,7 +565,7 @@ let rec check_exp env (exp:Ir.exp) : unit =
             T.Func(T.Local, T.Returns, [], [t_error], []) ],
           []) ->
          T.catch <: t_error;
-         T.Async(t0, Type.open_ [t0] (T.seq ts1)) <: t
+         T.Async (s, t0, Type.open_ [t0] (T.seq ts1)) <: t
        | _ -> error env exp.at "CPSAsync unexpected typ")
       (* TODO: We can check more here, can we *)
     | ICReplyPrim ts, [exp1] ->
@@ -579,6 +580,8 @@ let rec check_exp env (exp:Ir.exp) : unit =
       T.Non <: t
     | ICCallerPrim, [] ->
       T.caller <: t
+    | ICMethodNamePrim, [] ->
+      T.text <: t
     | ICCallPrim, [exp1; exp2; k; r] ->
       let t1 = T.promote (typ exp1) in
       begin match t1 with
@@ -718,7 +721,7 @@ let rec check_exp env (exp:Ir.exp) : unit =
     check_exp (add_lab env id t0) exp1;
     typ exp1 <: t0;
     t0 <: t
-  | AsyncE (tb, exp1, t0) ->
+  | AsyncE (s, tb, exp1, t0) ->
     check env.flavor.has_await "async expression in non-await flavor";
     check_typ env t0;
     let c, tb, ce = check_open_typ_bind env tb in
@@ -729,7 +732,7 @@ let rec check_exp env (exp:Ir.exp) : unit =
     check_exp env' exp1;
     let t1' = T.open_ [t0] (T.close [c] t1)  in
     t1' <: T.Any; (* vacuous *)
-    T.Async (t0, t1') <: t
+    T.Async (s, t0, t1') <: t
   | DeclareE (id, t0, exp1) ->
     check_mut_typ env t0;
     let val_info = { typ = t0; loc_known = false; const = false } in
@@ -785,7 +788,7 @@ let rec check_exp env (exp:Ir.exp) : unit =
     typ exp_f <: T.unit;
     typ exp_k <: T.Func (T.Local, T.Returns, [], ts, []);
     typ exp_r <: T.Func (T.Local, T.Returns, [], [T.error], []);
-  | ActorE (ds, fs, { preupgrade; postupgrade; meta; heartbeat }, t0) ->
+  | ActorE (ds, fs, { preupgrade; postupgrade; meta; heartbeat; timer; inspect }, t0) ->
     (* TODO: check meta *)
     let env' = { env with async = None } in
     let scope1 = gather_block_decs env' ds in
@@ -794,9 +797,13 @@ let rec check_exp env (exp:Ir.exp) : unit =
     check_exp env'' preupgrade;
     check_exp env'' postupgrade;
     check_exp env'' heartbeat;
+    check_exp env'' timer;
+    check_exp env'' inspect;
     typ preupgrade <: T.unit;
     typ postupgrade <: T.unit;
     typ heartbeat <: T.unit;
+    typ timer <: T.unit;
+    typ inspect <: T.bool;
     check (T.is_obj t0) "bad annotation (object type expected)";
     let (s0, tfs0) = T.as_obj t0 in
     let val_tfs0 = List.filter (fun tf -> not (T.is_typ tf.T.typ)) tfs0 in
@@ -1107,7 +1114,7 @@ let check_comp_unit env = function
     let scope = gather_block_decs env ds in
     let env' = adjoin env scope in
     check_decs env' ds
-  | ActorU (as_opt, ds, fs, { preupgrade; postupgrade; meta; heartbeat }, t0) ->
+  | ActorU (as_opt, ds, fs, { preupgrade; postupgrade; meta; heartbeat; timer; inspect }, t0) ->
     let check p = check env no_region p in
     let (<:) t1 t2 = check_sub env no_region t1 t2 in
     let env' = match as_opt with
@@ -1123,6 +1130,8 @@ let check_comp_unit env = function
     check_exp env'' preupgrade;
     check_exp env'' postupgrade;
     check_exp env'' heartbeat;
+    check_exp env'' timer;
+    check_exp env'' inspect;
     typ preupgrade <: T.unit;
     typ postupgrade <: T.unit;
     check (T.is_obj t0) "bad annotation (object type expected)";
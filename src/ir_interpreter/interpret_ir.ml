@@ -317,10 +317,12 @@ and interpret_exp_mut env exp (k : V.value V.cont) =
         last_region := exp.at; (* in case the following throws *)
         f (context env) v2 k
       | UnPrim (ot, op), [v1] ->
-        k (try Operator.unop op ot v1 with Invalid_argument s -> trap exp.at "%s" s)
+        k (try Operator.unop op ot v1 with Invalid_argument _ ->
+          trap exp.at "%s" (Operator.unop_overflow op ot))
       | BinPrim (ot, op), [v1; v2] ->
-        k (try Operator.binop op ot v1 v2 with _ ->
-          trap exp.at "arithmetic overflow")
+        k (try Operator.binop op ot v1 v2 with
+          | Invalid_argument s when s = "negative power" -> trap exp.at "%s" s
+          | _ -> trap exp.at "%s" (Operator.binop_overflow op ot))
       | RelPrim (ot, op), [v1; v2] ->
         k (Operator.relop op ot v1 v2)
       | TupPrim, exps ->
@@ -363,9 +365,12 @@ and interpret_exp_mut env exp (k : V.value V.cont) =
       | BreakPrim id, [v1] -> find id env.labs v1
       | RetPrim, [v1] -> Option.get env.rets v1
       | ThrowPrim, [v1] -> Option.get env.throws v1
-      | AwaitPrim, [v1] ->
+      | AwaitPrim T.Fut, [v1] ->
         assert env.flavor.has_await;
         await env exp.at (V.as_async v1) k (Option.get env.throws)
+      | AwaitPrim T.Cmp, [v1] ->
+        assert env.flavor.has_await;
+        (V.as_comp v1) k (Option.get env.throws)
       | AssertPrim, [v1] ->
         if V.as_bool v1
         then k V.unit
@@ -374,7 +379,7 @@ and interpret_exp_mut env exp (k : V.value V.cont) =
         if Show.can_show ot
         then k (Value.Text (Show.show_val ot v1))
         else raise (Invalid_argument "debug_show")
-      | CPSAsync _, [v1] ->
+      | CPSAsync (s, _), [v1] ->
         assert (not env.flavor.has_await && env.flavor.has_async_typ);
         let (_, f) = V.as_func v1 in
         let typ = (List.hd es).note.Note.typ in
@@ -382,23 +387,26 @@ and interpret_exp_mut env exp (k : V.value V.cont) =
         | T.Func(_, _, _, [f_typ; r_typ], _) ->
           let call_conv_f = CC.call_conv_of_typ f_typ in
           let call_conv_r = CC.call_conv_of_typ r_typ in
-          async env exp.at
-            (fun k' r ->
-              let vk' = Value.Func (call_conv_f, fun c v _ -> k' v) in
-              let vr = Value.Func (call_conv_r, fun c v _ -> r v) in
-              let vc = context env in
-              f vc (V.Tup [vk'; vr]) V.as_unit
-            )
-            k
+          let comp k' r =
+            let vk' = Value.Func (call_conv_f, fun c v _ -> k' v) in
+            let vr = Value.Func (call_conv_r, fun c v _ -> r v) in
+            let vc = context env in
+            f vc (V.Tup [vk'; vr]) V.as_unit
+          in
+          (match s with
+           | T.Fut -> async env exp.at comp k
+           | T.Cmp -> k (V.Comp comp))
         | _ -> assert false
         end
-      | CPSAwait _, [v1; v2] ->
+      | CPSAwait (s, _), [v1; v2] ->
         assert (not env.flavor.has_await && env.flavor.has_async_typ);
         begin match V.as_tup v2 with
          | [vf; vr] ->
            let (_, f) = V.as_func vf in
            let (_, r) = V.as_func vr in
-           await env exp.at (V.as_async v1)
+           (match s with
+            | T.Fut -> await env exp.at (V.as_async v1)
+            | T.Cmp -> V.as_comp v1)
              (fun v -> f (context env) v k)
              (fun e -> r (context env) e k) (* TBR *)
         | _ -> assert false
@@ -449,6 +457,8 @@ and interpret_exp_mut env exp (k : V.value V.cont) =
         f (V.Tup[vc; kv; rv]) v2 k
       | ICCallerPrim, [] ->
         k env.caller
+      | ICMethodNamePrim, [] ->
+        k (V.Text "")
       | ICStableRead t, [] ->
         let (_, tfs) = T.as_obj t in
         let ve = List.fold_left
@@ -495,7 +505,7 @@ and interpret_exp_mut env exp (k : V.value V.cont) =
   | LabelE (id, _typ, exp1) ->
     let env' = {env with labs = V.Env.add id k env.labs} in
     interpret_exp env' exp1 k
-  | AsyncE (_, exp1, _) ->
+  | AsyncE (T.Fut, _, exp1, _) ->
     assert env.flavor.has_await;
     async env
       exp.at
@@ -503,6 +513,11 @@ and interpret_exp_mut env exp (k : V.value V.cont) =
         let env' = { env with labs = V.Env.empty; rets = Some k'; throws = Some r }
         in interpret_exp env' exp1 k')
       k
+  | AsyncE (T.Cmp, _, exp1, _) ->
+    assert env.flavor.has_await;
+    k (V.Comp (fun k' r ->
+      let env' = { env with labs = V.Env.empty; rets = Some k'; throws = Some r }
+      in interpret_exp env' exp1 k'))
   | DeclareE (id, typ, exp1) ->
     let env = adjoin_vals env (declare_id id) in
     interpret_exp env exp1 k
@@ -50,6 +50,10 @@ let t_async as_seq t =
   T.Func (T.Local, T.Returns, [], [fulfillT as_seq t; failT],
      [T.Opt (T.Func(T.Local, T.Returns, [], [], []))])
 
+(* an `async*` computation is just its CPS function, run on demand *)
+let t_async_cmp as_seq t =
+  T.Func (T.Local, T.Returns, [], [fulfillT as_seq t; failT], [])
+
 let new_async_ret as_seq t = [t_async as_seq t; fulfillT as_seq t; failT]
 
 let new_asyncT =
@@ -181,7 +185,8 @@ let transform mode prog =
       Func (s, c', List.map t_bind tbs, List.map t_typ ts1, List.map t_typ ts2)
     | Opt t -> Opt (t_typ t)
     | Variant fs -> Variant (List.map t_field fs)
-    | Async (_, t) -> t_async nary (t_typ t) (* TBR exploit the index _ *)
+    | Async (T.Fut, _, t) -> t_async nary (t_typ t) (* TBR exploit the index _ *)
+    | Async (T.Cmp, _, t) -> t_async_cmp nary (t_typ t)
     | Obj (s, fs) -> Obj (s, List.map t_field fs)
     | Mut t -> Mut (t_typ t)
     | Any -> Any
@@ -235,7 +240,7 @@ let transform mode prog =
     | VarE id -> exp'
     | AssignE (exp1, exp2) ->
       AssignE (t_lexp exp1, t_exp exp2)
-    | PrimE (CPSAwait _, [a; kr]) ->
+    | PrimE (CPSAwait (T.Fut, _), [a; kr]) ->
       (ensureNamed (t_exp kr) (fun vkr ->
          let resume = fresh_var "resume" (T.Func(T.Local, T.Returns, [], [], [])) in
          (switch_optE ((t_exp a) -*- varE vkr)
@@ -245,7 +250,19 @@ let transform mode prog =
               (selfcallE [] (ic_replyE [] (unitE())) (varE resume) (projE (varE vkr) 1))
          T.unit
          ))).it
-    | PrimE (CPSAsync t0, [exp1]) ->
+    | PrimE (CPSAwait (T.Cmp, _), [a; kr]) ->
+      (* run the computation in the current message, no commit point *)
+      ((t_exp a) -*- (t_exp kr)).it
+    | PrimE (CPSAsync (T.Cmp, t0), [exp1]) ->
+      let t0 = t_typ t0 in
+      let ts1 = match typ exp1 with
+        | Func(_,_, [tb], [Func(_, _, [], ts1, []); _], []) ->
+          List.map t_typ (List.map (T.open_ [t0]) ts1)
+        | t -> assert false in
+      let k = fresh_var "k" (contT (T.seq ts1)) in
+      let r = fresh_var "r" err_contT in
+      ([k; r] -->* (callE (t_exp exp1) [t0] (tupE [varE k; varE r]))).it
+    | PrimE (CPSAsync (T.Fut, t0), [exp1]) ->
       let t0 = t_typ t0 in
       let tb, ts1 = match typ exp1 with
         | Func(_,_, [tb], [Func(_, _, [], ts1, []); _], []) ->
@@ -340,7 +357,7 @@ let transform mode prog =
               let args' = t_args args in
               let typbinds' = t_typ_binds typbinds in
               let t0, cps = match exp.it with
-                | PrimE (CPSAsync t0, [cps]) -> t_typ t0, cps
+                | PrimE (CPSAsync (_, t0), [cps]) -> t_typ t0, cps
                 | _ -> assert false in
               let t1, contT = match typ cps with
                 | Func (_,_,
@@ -369,7 +386,7 @@ let transform mode prog =
               let args' = t_args args in
               let typbinds' = t_typ_binds typbinds in
               let t0, cps = match exp.it with
-                | PrimE (CPSAsync t0, [cps]) -> t_typ t0, cps
+                | PrimE (CPSAsync (_, t0), [cps]) -> t_typ t0, cps
                 | _ -> assert false in
               let t1, contT = match typ cps with
                 | Func (_, _,
@@ -391,8 +408,8 @@ let transform mode prog =
             | Replies,_ -> assert false
           end
       end
-    | ActorE (ds, fs, {meta; preupgrade; postupgrade; heartbeat}, typ) ->
-      ActorE (t_decs ds, t_fields fs, {meta; preupgrade = t_exp preupgrade; postupgrade = t_exp postupgrade; heartbeat = t_exp heartbeat}, t_typ typ)
+    | ActorE (ds, fs, {meta; preupgrade; postupgrade; heartbeat; timer; inspect}, typ) ->
+      ActorE (t_decs ds, t_fields fs, {meta; preupgrade = t_exp preupgrade; postupgrade = t_exp postupgrade; heartbeat = t_exp heartbeat; timer = t_exp timer; inspect = t_exp inspect}, t_typ typ)
     | NewObjE (sort, ids, t) ->
       NewObjE (sort, t_fields ids, t_typ t)
     | SelfCallE _ -> assert false
@@ -461,9 +478,9 @@ let transform mode prog =
   and t_comp_unit = function
     | LibU _ -> raise (Invalid_argument "cannot compile library")
     | ProgU ds -> ProgU (t_decs ds)
-    | ActorU (args_opt, ds, fs, {meta; preupgrade; postupgrade; heartbeat}, t) ->
+    | ActorU (args_opt, ds, fs, {meta; preupgrade; postupgrade; heartbeat; timer; inspect}, t) ->
       ActorU (Option.map t_args args_opt, t_decs ds, t_fields fs,
-        { meta; preupgrade = t_exp preupgrade; postupgrade = t_exp postupgrade; heartbeat = t_exp heartbeat }, t_typ t)
+        { meta; preupgrade = t_exp preupgrade; postupgrade = t_exp postupgrade; heartbeat = t_exp heartbeat; timer = t_exp timer; inspect = t_exp inspect }, t_typ t)
 
   and t_prog (cu, flavor) = (t_comp_unit cu, { flavor with has_async_typ = false } )
 in
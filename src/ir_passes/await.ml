@@ -99,7 +99,7 @@ and t_exp' context exp' =
       | Some Label -> (retE (t_exp context exp1)).it
       | None -> assert false
     end
-  | AsyncE (tb, exp1, typ1) ->
+  | AsyncE (s, tb, exp1, typ1) ->
      let exp1 = R.exp R.Renaming.empty exp1 in (* rename all bound vars apart *)
      (* add the implicit return/throw label *)
      let k_ret = fresh_cont (typ exp1) in
@@ -108,7 +108,7 @@ and t_exp' context exp' =
        LabelEnv.add Return (Cont (ContVar k_ret))
          (LabelEnv.add Throw (Cont (ContVar k_fail)) LabelEnv.empty)
      in
-     (cps_asyncE typ1 (typ exp1)
+     (cps_asyncE s typ1 (typ exp1)
         (forall [tb] ([k_ret; k_fail] -->*
                        c_exp context' exp1 (ContVar k_ret)))).it
   | TryE _ -> assert false (* these never have effect T.Triv *)
@@ -119,12 +119,14 @@ and t_exp' context exp' =
   | FuncE (x, s, c, typbinds, pat, typ, exp) ->
     let context' = LabelEnv.add Return Label LabelEnv.empty in
     FuncE (x, s, c, typbinds, pat, typ,t_exp context' exp)
-  | ActorE (ds, ids, { meta; preupgrade; postupgrade; heartbeat }, t) ->
+  | ActorE (ds, ids, { meta; preupgrade; postupgrade; heartbeat; timer; inspect }, t) ->
     ActorE (t_decs context ds, ids,
       { meta;
         preupgrade = t_exp LabelEnv.empty preupgrade;
         postupgrade = t_exp LabelEnv.empty postupgrade;
-        heartbeat = t_exp LabelEnv.empty heartbeat},
+        heartbeat = t_exp LabelEnv.empty heartbeat;
+        timer = t_exp LabelEnv.empty timer;
+        inspect = t_exp LabelEnv.empty inspect},
       t)
   | NewObjE (sort, ids, typ) -> exp'
   | SelfCallE _ -> assert false
@@ -345,7 +347,7 @@ and c_exp' context exp k =
       | Some Label
       | None -> assert false
     end
-  | AsyncE (tb, exp1, typ1) ->
+  | AsyncE (s, tb, exp1, typ1) ->
      (* add the implicit return label *)
     let k_ret = fresh_cont (typ exp1) in
     let k_fail = fresh_err_cont () in
@@ -354,10 +356,10 @@ and c_exp' context exp k =
         (LabelEnv.add Throw (Cont (ContVar k_fail)) LabelEnv.empty)
     in
     k -@-
-      (cps_asyncE typ1 (typ exp1)
+      (cps_asyncE s typ1 (typ exp1)
         (forall [tb] ([k_ret; k_fail] -->*
           (c_exp context' exp1 (ContVar k_ret)))))
-  | PrimE (AwaitPrim, [exp1]) ->
+  | PrimE (AwaitPrim s, [exp1]) ->
     let r = match LabelEnv.find_opt Throw context with
       | Some (Cont r) -> r
       | Some Label
@@ -368,10 +370,10 @@ and c_exp' context exp k =
        let kr = tupE [varE k; varE r] in
        match eff exp1 with
        | T.Triv ->
-          cps_awaitE (typ_of_var k) (t_exp context exp1) kr
+          cps_awaitE s (typ_of_var k) (t_exp context exp1) kr
        | T.Await ->
           c_exp context  exp1
-            (meta (typ exp1) (fun v1 -> (cps_awaitE (typ_of_var k) (varE v1) kr)))
+            (meta (typ exp1) (fun v1 -> (cps_awaitE s (typ_of_var k) (varE v1) kr)))
      ))
   | DeclareE (id, typ, exp1) ->
     unary context k (fun v1 -> e (DeclareE (id, typ, varE v1))) exp1
@@ -526,12 +528,14 @@ and t_comp_unit context = function
           expD (c_block context' ds (tupE []) (meta (T.unit) (fun v1 -> tupE [])))
         ]
     end
-  | ActorU (as_opt, ds, ids, { meta; preupgrade; postupgrade; heartbeat }, t) ->
+  | ActorU (as_opt, ds, ids, { meta; preupgrade; postupgrade; heartbeat; timer; inspect }, t) ->
     ActorU (as_opt, t_decs context ds, ids,
       { meta;
         preupgrade = t_exp LabelEnv.empty preupgrade;
         postupgrade = t_exp LabelEnv.empty postupgrade;
-        heartbeat = t_exp LabelEnv.empty heartbeat},
+        heartbeat = t_exp LabelEnv.empty heartbeat;
+        timer = t_exp LabelEnv.empty timer;
+        inspect = t_exp LabelEnv.empty inspect},
       t)
 
 and t_prog (prog, flavor) =
@@ -137,7 +137,7 @@ let rec exp lvl (env : env) e : Lbool.t =
     | DeclareE (id, _, e1) ->
       exp_ lvl (M.add id no_info env) e1;
       surely_false
-    | LoopE e1 | AsyncE (_, e1, _) ->
+    | LoopE e1 | AsyncE (_, _, e1, _) ->
       exp_ NotTopLvl env e1;
       surely_false
     | AssignE (_, e1) | LabelE (_, _, e1) | DefineE (_, _, e1) ->
@@ -159,12 +159,14 @@ let rec exp lvl (env : env) e : Lbool.t =
       surely_false
     | NewObjE _ -> (* mutable objects *)
       surely_false
-    | ActorE (ds, fs, {meta; preupgrade; postupgrade; heartbeat}, _typ) ->
+    | ActorE (ds, fs, {meta; preupgrade; postupgrade; heartbeat; timer; inspect}, _typ) ->
       (* this may well be “the” top-level actor, so don’t update lvl here *)
       let (env', _) = decs lvl env ds in
       exp_ lvl env' preupgrade;
       exp_ lvl env' postupgrade;
       exp_ lvl env' heartbeat;
+      exp_ lvl env' timer;
+      exp_ lvl env' inspect;
       surely_false
   in
   set_lazy_const e lb;
@@ -218,7 +220,7 @@ and block lvl env (ds, body) =
 and comp_unit = function
   | LibU _ -> raise (Invalid_argument "cannot compile library")
   | ProgU ds -> decs_ TopLvl M.empty ds
-  | ActorU (as_opt, ds, fs, {meta; preupgrade; postupgrade; heartbeat}, typ) ->
+  | ActorU (as_opt, ds, fs, {meta; preupgrade; postupgrade; heartbeat; timer; inspect}, typ) ->
     let env = match as_opt with
       | None -> M.empty
       | Some as_ -> args TopLvl M.empty as_
@@ -226,7 +228,9 @@ and comp_unit = function
     let (env', _) = decs TopLvl env ds in
     exp_ TopLvl env' preupgrade;
     exp_ TopLvl env' postupgrade;
-    exp_ TopLvl env' heartbeat
+    exp_ TopLvl env' heartbeat;
+    exp_ TopLvl env' timer;
+    exp_ TopLvl env' inspect
 
 let analyze ((cu, _flavor) : prog) =
   ignore (comp_unit cu)
@@ -81,6 +81,23 @@ let invoke_text_of_array_mut : T.typ -> Ir.exp -> Ir.exp -> Ir.exp = fun t f e -
     T.Func (T.Local, T.Returns, [{T.var="T";T.sort=T.Type;T.bound=T.Any}], [show_fun_typ_for (T.Var ("T",0)); T.Array (T.Mut (T.Var ("T",0)))], [T.text]) in
   callE (varE (var "@text_of_array_mut" fun_typ)) [t] (tupE [f; e])
 
+let invoke_show_cycle_enter : Ir.exp -> Ir.exp = fun e ->
+  let fun_typ = T.Func (T.Local, T.Returns, [], [T.Any], [T.bool]) in
+  varE (var "@show_cycle_enter" fun_typ) -*- e
+
+let invoke_show_cycle_leave : unit -> Ir.exp = fun () ->
+  let fun_typ = T.Func (T.Local, T.Returns, [], [], []) in
+  varE (var "@show_cycle_leave" fun_typ) -*- unitE ()
+
+(* Shows "#<cycle>" instead of `e` when the argument is already being shown,
+   for values that can be part of a cycle. See `show_cycle` in the RTS. *)
+let guard_cycle : T.typ -> Ir.exp -> Ir.exp = fun t e ->
+  let text = fresh_var "text" T.text in
+  ifE (invoke_show_cycle_enter (argE t))
+    (blockE [letD text e; expD (invoke_show_cycle_leave ())] (varE text))
+    (textE "#<cycle>")
+    T.text
+
 let list_build : 'a -> (unit -> 'a) -> 'a -> 'a list -> 'a list = fun pre sep post xs ->
   let rec go = function
     | [] -> [ post ]
@@ -189,7 +206,8 @@ let show_for : T.typ -> Ir.dec * T.typ list = fun t ->
       [t']
     end
   | T.Obj (T.Object, fs) ->
-    define_show t (
+    let guard = if List.exists (fun f -> T.is_mut f.T.typ) fs then guard_cycle t else Fun.id in
+    define_show t (guard (
       cat_list (list_build
         (textE "{") (fun () -> textE "; ") (textE "}")
         (List.map (fun f ->
@@ -200,7 +218,7 @@ let show_for : T.typ -> Ir.dec * T.typ list = fun t ->
           ) fs
         )
       )
-    ),
+    )),
     List.map (fun f -> T.as_immut (T.normalize (f.Type.typ))) fs
   | T.Variant fs ->
     define_show t (
@@ -283,7 +301,7 @@ and t_exp' env = function
     LoopE (t_exp env exp1)
   | LabelE (id, typ, exp1) ->
     LabelE (id, typ, t_exp env exp1)
-  | AsyncE (tb, e, typ) -> AsyncE (tb, t_exp env e, typ)
+  | AsyncE (s, tb, e, typ) -> AsyncE (s, tb, t_exp env e, typ)
   | DeclareE (id, typ, exp1) ->
     DeclareE (id, typ, t_exp env exp1)
   | DefineE (id, mut ,exp1) ->
@@ -292,7 +310,7 @@ and t_exp' env = function
     NewObjE (sort, ids, t)
   | SelfCallE (ts, e1, e2, e3) ->
     SelfCallE (ts, t_exp env e1, t_exp env e2, t_exp env e3)
-  | ActorE (ds, fields, {meta; preupgrade; postupgrade; heartbeat}, typ) ->
+  | ActorE (ds, fields, {meta; preupgrade; postupgrade; heartbeat; timer; inspect}, typ) ->
     (* Until Actor expressions become their own units,
        we repeat what we do in `comp_unit` below *)
     let env1 = empty_env () in
@@ -300,8 +318,10 @@ and t_exp' env = function
     let preupgrade' = t_exp env1 preupgrade in
     let postupgrade' = t_exp env1 postupgrade in
     let heartbeat' = t_exp env1 heartbeat in
+    let timer' = t_exp env1 timer in
+    let inspect' = t_exp env1 inspect in
     let decls = show_decls !(env1.params) in
-    ActorE (decls @ ds', fields, {meta; preupgrade = preupgrade'; postupgrade = postupgrade'; heartbeat = heartbeat'}, typ)
+    ActorE (decls @ ds', fields, {meta; preupgrade = preupgrade'; postupgrade = postupgrade'; heartbeat = heartbeat'; timer = timer'; inspect = inspect'}, typ)
 
 and t_lexp env (e : Ir.lexp) = { e with it = t_lexp' env e.it }
 and t_lexp' env = function
@@ -329,14 +349,16 @@ and t_comp_unit = function
     let ds' = t_decs env ds in
     let decls = show_decls !(env.params) in
     ProgU (decls @ ds')
-  | ActorU (as_opt, ds, fields, {meta; preupgrade; postupgrade; heartbeat}, typ) ->
+  | ActorU (as_opt, ds, fields, {meta; preupgrade; postupgrade; heartbeat; timer; inspect}, typ) ->
     let env = empty_env () in
     let ds' = t_decs env ds in
     let preupgrade' = t_exp env preupgrade in
     let postupgrade' = t_exp env postupgrade in
     let heartbeat' = t_exp env heartbeat in
+    let timer' = t_exp env timer in
+    let inspect' = t_exp env inspect in
     let decls = show_decls !(env.params) in
-    ActorU (as_opt, decls @ ds', fields, {meta; preupgrade = preupgrade'; postupgrade = postupgrade'; heartbeat = heartbeat'}, typ)
+    ActorU (as_opt, decls @ ds', fields, {meta; preupgrade = preupgrade'; postupgrade = postupgrade'; heartbeat = heartbeat'; timer = timer'; inspect = inspect'}, typ)
 
 (* Entry point for the program transformation *)
 
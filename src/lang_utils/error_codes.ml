@@ -175,4 +175,6 @@ let error_codes : (string * string option) list =
     "M0169", None; (* Stable variable cannot be discarded *)
     "M0170", None; (* Stable variable must subtype *)
     "M0171", None; (* Stable variable changing mutability *)
+    "M0172", None; (* Migration returns a field that is not a stable variable of the right type *)
+    "M0173", None; (* Migration refers to actor fields *)
   ]
@@ -207,12 +207,12 @@ and exp' at note = function
   | S.BreakE (l, e) -> (breakE l.it (exp e)).it
   | S.RetE e -> (retE (exp e)).it
   | S.ThrowE e -> I.PrimE (I.ThrowPrim, [exp e])
-  | S.AsyncE (tb, e) ->
-    I.AsyncE (typ_bind tb, exp e,
+  | S.AsyncE (s, tb, e) ->
+    I.AsyncE (s, typ_bind tb, exp e,
               match note.Note.typ with
-              | T.Async (t, _) -> t
+              | T.Async (_, t, _) -> t
               | _ -> assert false)
-  | S.AwaitE e -> I.PrimE (I.AwaitPrim, [exp e])
+  | S.AwaitE (s, e) -> I.PrimE (I.AwaitPrim s, [exp e])
   | S.AssertE e -> I.PrimE (I.AssertPrim, [exp e])
   | S.AnnotE (e, _) -> assert false
   | S.ImportE (f, ir) -> raise (Invalid_argument (Printf.sprintf "Import expression found in unit body: %s" f))
@@ -315,13 +315,51 @@ and call_system_func_opt name es =
       ->
        Some (
          if name = "heartbeat" then
-           blockE
-             [ expD (callE (varE (var id.it p.note)) [T.Any] (unitE())) ]
+           (* skip this heartbeat if the previous one is still running *)
+           let in_flight = var "@heartbeat_in_flight" (T.Mut T.bool) in
+           let run_heartbeat = var "@run_heartbeat"
+             (T.Func (T.Local, T.Returns, [T.scope_bind],
+               [p.note],
+               [T.Async (T.Fut, T.Var (T.default_scope_var, 0), T.unit)]))
+           in
+           ifE (varE in_flight)
              (unitE ())
+             (blockE
+               [ expD (assignE in_flight (trueE ()));
+                 expD (callE (varE run_heartbeat) [T.Any] (varE (var id.it p.note))) ]
+               (unitE ()))
+             T.unit
+         else if name = "inspect" then
+           (* pass the message's caller and method name *)
+           let caller = fresh_var "caller" T.caller in
+           let method_name = fresh_var "method_name" T.text in
+           let field lab v =
+             { it = { I.name = lab; I.var = id_of_var v }; at = no_region; note = typ_of_var v } in
+           let arg_typ = T.Obj (T.Object, [
+             { T.lab = "caller"; T.typ = T.caller; T.depr = None };
+             { T.lab = "method_name"; T.typ = T.text; T.depr = None }])
+           in
+           blockE
+             [ letD caller (primE I.ICCallerPrim []);
+               letD method_name (primE I.ICMethodNamePrim []) ]
+             (callE (varE (var id.it p.note)) []
+               (newObjE T.Object
+                 [field "caller" caller; field "method_name" method_name]
+                 arg_typ))
          else
            callE (varE (var id.it p.note)) [] (tupE []))
     | _ -> None) es
 
+and call_timer_helper () =
+  (* fire the due timers (see `@timer_helper` in the prelude) *)
+  let timer_helper = var "@timer_helper"
+    (T.Func (T.Local, T.Returns, [T.scope_bind], [],
+      [T.Async (T.Fut, T.Var (T.default_scope_var, 0), T.unit)]))
+  in
+  blockE
+    [ expD (callE (varE timer_helper) [T.Any] (unitE())) ]
+    (unitE ())
+
 and build_candid ts obj_typ =
   let (args, prog) = Mo_idl.Mo_to_idl.of_service_type ts obj_typ in
   I.{
@@ -343,7 +381,7 @@ and export_interface txt =
   let bind2 = typ_arg scope_con2 Scope scope_bound in
   ([ letD (var v typ) (
     funcE v (Shared Query) Promises [bind1] [] [text] (
-      asyncE bind2 (textE txt) (Con (scope_con1, []))
+      asyncE T.Fut bind2 (textE txt) (Con (scope_con1, []))
     )
   )],
   [{ it = { I.name = name; var = v }; at = no_region; note = typ }])
@@ -360,7 +398,7 @@ and export_footprint self_id name expr =
   let bind2 = typ_arg scope_con2 Scope scope_bound in
   ([ letD (var v typ) (
        funcE v (Shared Query) Promises [bind1] [] [nat64] (
-           (asyncE bind2
+           (asyncE T.Fut bind2
               (blockE [expD (assertE (primE (I.RelPrim (caller, Operator.EqOp))
                                         [primE I.ICCallerPrim []; selfRefE caller]))]
                  (primE (I.ICStableSize expr.note.Note.typ) [expr])) (Con (scope_con1, []))))
@@ -371,6 +409,9 @@ and build_actor at ts self_id es obj_typ =
   let candid = build_candid ts obj_typ in
   let fs = build_fields obj_typ in
   let es = List.filter (fun ef -> is_not_typD ef.it.S.dec) es in
+  (* The migration is called before the actor fields are initialised, so it is
+     declared before them (it doesn't refer to them, see `Typing.check_migrate`) *)
+  let migrate_es, es = List.partition is_migrate es in
   let ds = decs (List.map (fun ef -> ef.it.S.dec) es) in
   let stabs = List.map (fun ef -> ef.it.S.stab) es in
   let pairs = List.map2 stabilize stabs ds in
@@ -386,7 +427,12 @@ and build_actor at ts self_id es obj_typ =
   let get_state = fresh_var "getState" (T.Func(T.Local, T.Returns, [], [], [ty])) in
   let ds = List.map (fun mk_d -> mk_d get_state) mk_ds in
   let ds =
-    varD state (optE (primE (I.ICStableRead ty) []))
+    decs (List.map (fun ef -> ef.it.S.dec) migrate_es)
+    @
+    varD state (optE (match migrate_es with
+      | [] -> primE (I.ICStableRead ty) []
+      | [ef] -> migrate_state ef ty
+      | _ -> assert false))
     ::
     nary_funcD get_state []
       (let v = fresh_var "v" ty in
@@ -429,12 +475,77 @@ and build_actor at ts self_id es obj_typ =
        postupgrade = (match call_system_func_opt "postupgrade" es with
                       | Some call -> call
                       | None -> tupE []);
-       heartbeat = match call_system_func_opt "heartbeat" es with
-                   | Some call -> call
-                   | None -> tupE [] },
+       heartbeat = (match call_system_func_opt "heartbeat" es with
+                    | Some call -> call
+                    | None -> tupE []);
+       timer = call_timer_helper ();
+       inspect = (match call_system_func_opt "inspect" es with
+                  | Some call -> call
+                  | None -> trueE ()) },
      obj_typ))
 
 
+and is_migrate ef =
+  match ef.it with
+  | { S.vis = { it = S.System; _ };
+      S.dec = { it = S.LetD ({ it = S.VarP id; _ }, _); _ };
+      _
+    } -> id.it = "migrate"
+  | _ -> false
+
+(* The stable state `ty` of a new version with a migration, see `Stable.md`.
+   The stable variables of the previous version are read at the argument type of
+   `migrate`, with the type table of the stored data. When none of them are
+   stored (e.g. on install) the new version starts with no stable variables
+   set, otherwise all of them must be stored. The stable variables returned by
+   `migrate` are set, the others are initialised by their declarations. *)
+and migrate_state ef ty =
+  let migrate = match ef.it.S.dec.it with
+    | S.LetD ({ it = S.VarP id; note; _ }, _) -> var id.it note
+    | _ -> assert false in
+  let _, _, _, ts1, ts2 = T.as_func (typ_of_var migrate) in
+  let old_typ, new_typ = match ts1, ts2 with
+    | [t1], [t2] -> T.normalize t1, T.normalize t2
+    | _ -> assert false in
+  let _, old_fs = T.as_obj old_typ in
+  let _, new_fs = T.as_obj new_typ in
+  let _, state_fs = T.as_obj ty in
+  let stored_ty = T.Obj (T.Memory,
+    List.map (fun f -> T.{f with typ = Opt f.typ; depr = None}) old_fs) in
+  let stored = fresh_var "stored" stored_ty in
+  let stored_field f = dotE (varE stored) f.T.lab (T.Opt f.T.typ) in
+  let new_state = fresh_var "new_state" new_typ in
+  let obj sort fs es typ =
+    let vs = fresh_vars "v" (List.map (fun f -> f.T.typ) fs) in
+    blockE
+      [letP (seqP (List.map varP vs)) (seqE es)]
+      (newObjE sort
+        (List.map2 (fun f v ->
+          { it = {I.name = f.T.lab; I.var = id_of_var v}; at = no_region; note = f.T.typ }
+        ) fs vs)
+        typ)
+  in
+  let none_stored = conjE (List.map (fun f ->
+    switch_optE (stored_field f) (trueE ()) wildP (falseE ()) T.bool) old_fs) in
+  let old_state = obj T.Object old_fs (List.map (fun f ->
+    let v = fresh_var "v" f.T.typ in
+    switch_optE (stored_field f)
+      { it = I.PrimE (I.OtherPrim "trap",
+          [textE ("migrate: stable variable " ^ f.T.lab ^ " of the previous version is missing")]);
+        at = no_region;
+        note = Note.{ def with typ = T.Non } }
+      (varP v) (varE v)
+      f.T.typ) old_fs) old_typ in
+  blockE [letD stored (primE (I.ICStableRead stored_ty) [])]
+    (ifE none_stored
+      (obj T.Memory state_fs (List.map (fun _ -> nullE ()) state_fs) ty)
+      (blockE [letD new_state (callE (varE migrate) [] old_state)]
+        (obj T.Memory state_fs (List.map (fun f ->
+          match T.lookup_val_field_opt f.T.lab new_fs with
+          | Some t -> optE (dotE (varE new_state) f.T.lab t)
+          | None -> nullE ()) state_fs) ty))
+      ty)
+
 and stabilize stab_opt d =
   let s = match stab_opt with None -> S.Flexible | Some s -> s.it  in
   match s, d.it with
@@ -610,9 +721,9 @@ and dec' at n d = match d with
     let args, wrap, control, _n_res = to_args n.S.note_typ op p in
     let body = if s.it = T.Actor
       then
-        let (_, obj_typ) = T.as_async rng_typ in
+        let (_, _, obj_typ) = T.as_async rng_typ in
         let c = Cons.fresh T.default_scope_var (T.Abs ([], T.scope_bound)) in
-        asyncE (typ_arg c T.Scope T.scope_bound)
+        asyncE T.Fut (typ_arg c T.Scope T.scope_bound)
           (wrap { it = obj_block at s (Some self_id) dfs (T.promote obj_typ);
             at = at;
             note = Note.{def with typ = obj_typ } })
@@ -771,12 +882,12 @@ and to_args typ po p : Ir.arg list * (Ir.exp -> Ir.exp) * T.control * T.typ list
   let wrap_under_async e =
     if T.is_shared_sort sort
     then match control, e.it with
-      | (T.Promises, Ir.AsyncE (tb, e', t)) ->
-        { e with it = Ir.AsyncE (tb, wrap_po e', t) }
+      | (T.Promises, Ir.AsyncE (s, tb, e', t)) ->
+        { e with it = Ir.AsyncE (s, tb, wrap_po e', t) }
       | T.Returns, Ir.BlockE (
-          [{ it = Ir.LetD ({ it = Ir.WildP; _} as pat, ({ it = Ir.AsyncE (tb,e',t); _} as exp)); _ }],
+          [{ it = Ir.LetD ({ it = Ir.WildP; _} as pat, ({ it = Ir.AsyncE (s,tb,e',t); _} as exp)); _ }],
           ({ it = Ir.PrimE (Ir.TupPrim, []); _} as unit)) ->
-        blockE [letP pat {exp with it = Ir.AsyncE (tb,wrap_po e',t)} ] unit
+        blockE [letP pat {exp with it = Ir.AsyncE (s,tb,wrap_po e',t)} ] unit
       | _, Ir.ActorE _ -> wrap_po e
       | _ -> assert false
     else wrap_po e in
@@ -813,19 +924,19 @@ let import_compiled_class (lib : S.comp_unit)  wasm : import_declaration =
   let ts1' = List.map (T.open_ cs) ts1 in
   let ts2' = List.map (T.open_ cs) ts2 in
   let class_typ = match List.map T.normalize ts2' with
-    | [T.Async (_ , class_typ)] -> class_typ
+    | [T.Async (_, _, class_typ)] -> class_typ
     | _ -> assert false
   in
   let vs = fresh_vars "param" ts1' in
   let arg_blob = fresh_var "arg_blob" T.blob in
   let principal = fresh_var "principal" T.principal in
   let t_async = T.codom cntrl (fun () -> assert false) ts2' in
-  let _, t_actor = T.as_async (T.normalize t_async) in
+  let _, _, t_actor = T.as_async (T.normalize t_async) in
   let wasm_blob = blobE wasm in
   let create_actor_helper = var "@create_actor_helper"
     (T.Func (T.Local, T.Returns, [T.scope_bind],
       [T.blob; T.blob],
-      [T.Async(T.Var (T.default_scope_var, 0), T.principal)]))
+      [T.Async (T.Fut, T.Var (T.default_scope_var, 0), T.principal)]))
   in
   let cs' = T.open_binds tbs in
   let c', _ = T.as_con (List.hd cs') in
@@ -833,7 +944,7 @@ let import_compiled_class (lib : S.comp_unit)  wasm : import_declaration =
   let accepted = fresh_var "accepted" T.nat in
   let cycles = var "@cycles" (T.Mut (T.nat)) in
   let body =
-    asyncE
+    asyncE T.Fut
       (typ_arg c' T.Scope T.scope_bound)
       (blockE [
          letD arg_blob (primE (Ir.SerializePrim ts1') [seqE (List.map varE vs)]);
@@ -841,7 +952,7 @@ let import_compiled_class (lib : S.comp_unit)  wasm : import_declaration =
          letD accepted (primE Ir.SystemCyclesAcceptPrim [varE available]);
          expD (assignE cycles (varE accepted));
          letD principal
-           (awaitE (callE (varE create_actor_helper) cs'
+           (awaitE T.Fut (callE (varE create_actor_helper) cs'
              (tupE [wasm_blob;  varE arg_blob])))
          ]
          (primE (Ir.CastPrim (T.principal, t_actor)) [varE principal]))
@@ -910,7 +1021,7 @@ let transform_unit_body (u : S.comp_unit_body) : Ir.comp_unit =
       | T.Func(_s, _c, bds, ts1, [async_rng]) ->
         assert(1 = List.length bds);
         let cs  = T.open_binds bds in
-        let (_, rng) = T.as_async (T.normalize (T.open_ cs async_rng)) in
+        let (_, _, rng) = T.as_async (T.normalize (T.open_ cs async_rng)) in
         List.map (T.open_ cs) ts1,
         T.promote rng
       | _ -> assert false
@@ -966,18 +1077,18 @@ let import_unit (u : S.comp_unit) : import_declaration =
       let cs' = T.open_binds [T.scope_bind] in
       let c', _ = T.as_con (List.hd cs') in
       let body =
-        asyncE
+        asyncE T.Fut
           (typ_arg c' T.Scope T.scope_bound)
           { it = I.ActorE (ds, fs, up, actor_t); at = u.at; note = Note.{ def with typ = actor_t } }
           (List.hd cs)
       in
       let class_typ = match List.map T.normalize ts2 with
-        | [ T.Async(_, t2) ] -> t2
+        | [ T.Async (_, _, t2) ] -> t2
         | _ -> assert false in
       let func = funcE id T.Local T.Returns
         [typ_arg c T.Scope T.scope_bound]
         as_
-        [T.Async (List.hd cs, actor_t)]
+        [T.Async (T.Fut, List.hd cs, actor_t)]
         body
       in
       actor_class_mod_exp id class_typ func
@@ -6,6 +6,9 @@ type compile_mode = WasmMode | ICMode | RefMode | WASIMode
 
 type gc_strategy = MarkCompact | Copying
 
+(* Collections of the compacting GC, the non-stop-the-world modes need a write barrier *)
+type gc_mode = StopTheWorld | Incremental | Generational
+
 let trace = ref false
 let verbose = ref false
 let print_warnings = ref true
@@ -40,3 +43,7 @@ let error_detail = ref 2
 let sanity = ref false
 let gc_strategy = ref Copying
 let force_gc = ref false
+let gc_mode = ref StopTheWorld
+let alloc_profiling = ref false
+let is_controller_api = ref false
+let safepoints = ref true
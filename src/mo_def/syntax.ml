@@ -47,7 +47,7 @@ and typ' =
   | VariantT of typ_tag list                       (* variant *)
   | TupT of typ_item list                          (* tuple *)
   | FuncT of func_sort * typ_bind list * typ * typ (* function *)
-  | AsyncT of scope * typ                          (* future *)
+  | AsyncT of Type.async_sort * scope * typ       (* future / computation *)
   | AndT of typ * typ                              (* intersection *)
   | OrT of typ * typ                               (* union *)
   | ParT of typ                                    (* parentheses, used to control function arity only *)
@@ -178,8 +178,8 @@ and exp' =
   | BreakE of id * exp                         (* break *)
   | RetE of exp                                (* return *)
   | DebugE of exp                              (* debugging *)
-  | AsyncE of typ_bind * exp                   (* async *)
-  | AwaitE of exp                              (* await *)
+  | AsyncE of Type.async_sort * typ_bind * exp (* async/async* *)
+  | AwaitE of Type.async_sort * exp            (* await/await* *)
   | AssertE of exp                             (* assertion *)
   | AnnotE of exp * typ                        (* type annotation *)
   | ImportE of (string * resolved_import ref)  (* import statement *)
@@ -306,23 +306,23 @@ let scopeT at =
 
 (* Expressions *)
 
-let asyncE tbs e =
-  AsyncE (tbs, e) @? e.at
+let asyncE s tbs e =
+  AsyncE (s, tbs, e) @? e.at
 
 let ignore_asyncE tbs e =
   IgnoreE (
-    AnnotE (AsyncE (tbs, e) @? e.at,
-      AsyncT (scopeT e.at, TupT [] @! e.at) @! e.at) @? e.at ) @? e.at
+    AnnotE (AsyncE (Type.Fut, tbs, e) @? e.at,
+      AsyncT (Type.Fut, scopeT e.at, TupT [] @! e.at) @! e.at) @? e.at ) @? e.at
 
 let is_asyncE e =
   match e.it with
-  | AsyncE _ -> true
+  | AsyncE (Type.Fut, _, _) -> true
   | _ -> false
 
 let is_ignore_asyncE e =
   match e.it with
   | IgnoreE
-      {it = AnnotE ({it = AsyncE _; _},
-        {it = AsyncT (_, {it = TupT []; _}); _}); _} ->
+      {it = AnnotE ({it = AsyncE (Type.Fut, _, _); _},
+        {it = AsyncT (Type.Fut, _, {it = TupT []; _}); _}); _} ->
     true
   | _ -> false
@@ -175,7 +175,7 @@ let bi_match_subs scope_opt tbs subs typ_opt =
        | None -> None
       )
       else None
-    | Async (t11, t12), Async (t21, t22) ->
+    | Async (s1, t11, t12), Async (s2, t21, t22) when s1 = s2 ->
       (match bi_equate_typ rel eq inst any t11 t21  with
        | Some inst ->
          bi_match_typ rel eq inst any t12 t22
@@ -120,8 +120,8 @@ let rec exp msgs e : f = match e.it with
   | ForE (p, e1, e2)    -> exp msgs e1 ++ (exp msgs e2 /// pat msgs p)
   | LabelE (i, t, e)    -> exp msgs e
   | DebugE e            -> exp msgs e
-  | AsyncE (_, e)    -> exp msgs e
-  | AwaitE e            -> exp msgs e
+  | AsyncE (_, _, e)    -> exp msgs e
+  | AwaitE (_, e)       -> exp msgs e
   | AssertE e           -> exp msgs e
   | AnnotE (e, t)       -> exp msgs e
   | OptE e              -> exp msgs e
@@ -224,6 +224,13 @@ and group msgs (grp : group) : f =
   M.disjoint_union (map_of_set Eager e) (map_of_set Delayed d) |>
     M.filter (fun v _ -> M.mem v defWhen = false)
 
+(* Free variables of an expression, e.g. to check that it does not refer to
+   the fields of an enclosing actor *)
+let free_vars e =
+  let fv = ref M.empty in
+  ignore (Diag.with_message_store (fun msgs -> fv := exp msgs e; Some ()));
+  M.keys !fv
+
 let check_prog prog =
   Diag.with_message_store (fun msgs ->
     ignore (group msgs (decs msgs prog.it));
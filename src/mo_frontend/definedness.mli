@@ -2,3 +2,4 @@ open Mo_def
 
 val check_prog : Syntax.prog -> unit Diag.result
 val check_lib : Syntax.lib -> unit Diag.result
+val free_vars : Syntax.exp -> string list
@@ -105,7 +105,9 @@ let terminal2token (type a) (symbol : a terminal) : token =
       | T_CASE -> CASE
       | T_BREAK -> BREAK
       | T_BOOL -> BOOL false
+      | T_AWAITSTAR -> AWAITSTAR
       | T_AWAIT -> AWAIT
+      | T_ASYNCSTAR -> ASYNCSTAR
       | T_ASYNC -> ASYNC
       | T_ASSIGN -> ASSIGN
       | T_ASSERT -> ASSERT
@@ -121,7 +121,9 @@ let string_of_symbol symbol : string =
   | X (T T_CASE) -> "case"
   | X (T T_BREAK) -> "break"
   | X (T T_BOOL) -> "<bool>"
+  | X (T T_AWAITSTAR) -> "await*"
   | X (T T_AWAIT) -> "await"
+  | X (T T_ASYNCSTAR) -> "async*"
   | X (T T_ASYNC) -> "async"
   | X (T T_ASSIGN) -> binassign "assign"
   | X (T T_ASSERT) -> "assert"
@@ -18,8 +18,8 @@ let rec over_exp (f : exp -> exp) (exp : exp) : exp = match exp.it with
   | BreakE (x, exp1) -> f { exp with it = BreakE (x, over_exp f exp1) }
   | RetE exp1 -> f { exp with it = RetE (over_exp f exp1) }
   | AnnotE (exp1, x) -> f { exp with it = AnnotE (over_exp f exp1, x) }
-  | AsyncE (tb, exp1) -> f { exp with it = AsyncE (tb, over_exp f exp1) }
-  | AwaitE exp1 -> f { exp with it = AwaitE (over_exp f exp1) }
+  | AsyncE (s, tb, exp1) -> f { exp with it = AsyncE (s, tb, over_exp f exp1) }
+  | AwaitE (s, exp1) -> f { exp with it = AwaitE (s, over_exp f exp1) }
   | ThrowE exp1 -> f { exp with it = ThrowE (over_exp f exp1) }
   | BinE (x, exp1, y, exp2) ->
      f { exp with it = BinE (x, over_exp f exp1, y, over_exp f exp2) }
@@ -205,7 +205,12 @@ let infer_mut mut : T.typ -> T.typ =
 (* System method types *)
 
 let system_funcs = [
-    ("heartbeat", T.Func (T.Local, T.Returns, [T.scope_bind], [], [T.Async (T.Var (T.default_scope_var, 0), T.unit)]));
+    ("heartbeat", T.Func (T.Local, T.Returns, [T.scope_bind], [], [T.Async (T.Fut, T.Var (T.default_scope_var, 0), T.unit)]));
+    ("inspect", T.Func (T.Local, T.Returns, [],
+      [T.Obj (T.Object, [
+        {T.lab = "caller"; T.typ = T.caller; T.depr = None};
+        {T.lab = "method_name"; T.typ = T.text; T.depr = None}])],
+      [T.bool]));
     ("preupgrade", T.Func (T.Local, T.Returns, [], [], []));
     ("postupgrade", T.Func (T.Local, T.Returns, [], [], []))
   ]
@@ -306,7 +311,7 @@ let as_domT t =
 
 let as_codomT sort t =
   match sort, t.Source.it with
-  | T.Shared _,  AsyncT (_, t1) ->
+  | T.Shared _,  AsyncT (T.Fut, _, t1) ->
     T.Promises, as_domT t1
   | _ -> T.Returns, as_domT t
 
@@ -464,14 +469,14 @@ and check_typ' env typ : T.typ =
       (List.map (fun (tag : typ_tag) -> tag.it.tag) tags);
     let fs = List.map (check_typ_tag env) tags in
     T.Variant (List.sort T.compare_field fs)
-  | AsyncT (typ0, typ) ->
+  | AsyncT (s, typ0, typ) ->
     let t0 = check_typ env typ0 in
     let t = check_typ env typ in
-    if not env.pre && not (T.shared t) then
+    if s = T.Fut && not env.pre && not (T.shared t) then
       error_shared env t typ.at
         "M0033" "async has non-shared content type%a"
         display_typ_expand t;
-    T.Async (t0, t)
+    T.Async (s, t0, t)
   | ObjT (sort, fields) ->
     check_ids env "object type" "field"
       (List.map (fun (field : typ_field) -> field.it.id) fields);
@@ -705,7 +710,7 @@ let rec is_explicit_exp e =
   | LitE l -> is_explicit_lit !l
   | UnE (_, _, e1) | OptE e1 | DoOptE e1
   | ProjE (e1, _) | DotE (e1, _) | BangE e1 | IdxE (e1, _) | CallE (e1, _, _)
-  | LabelE (_, _, e1) | AsyncE (_, e1) | AwaitE e1 ->
+  | LabelE (_, _, e1) | AsyncE (_, _, e1) | AwaitE (_, e1) ->
     is_explicit_exp e1
   | BinE (_, e1, _, e2) | IfE (_, e1, e2) ->
     is_explicit_exp e1 || is_explicit_exp e2
@@ -1017,7 +1022,7 @@ and infer_exp'' env exp : T.typ =
       error_in [Flags.WASIMode; Flags.WasmMode] env exp.at "M0068"
         "actors are not supported";
       match context with
-      | (AsyncE _ :: AwaitE _ :: _ :: _ ) ->
+      | (AsyncE (T.Fut, _, _) :: AwaitE (T.Fut, _) :: _ :: _ ) ->
          error_in [Flags.ICMode; Flags.RefMode] env exp.at "M0069"
            "non-toplevel actor; an actor can only be declared at the toplevel of a program"
       | _ -> ()
@@ -1262,7 +1267,7 @@ and infer_exp'' env exp : T.typ =
       check_exp_strong env T.throw exp1
     end;
     T.Non
-  | AsyncE (typ_bind, exp1) ->
+  | AsyncE (s, typ_bind, exp1) ->
     error_in [Flags.WASIMode; Flags.WasmMode] env exp1.at "M0086"
       "async expressions are not supported";
     let t1, next_cap = check_AsyncCap env "async expression" exp.at in
@@ -1276,15 +1281,15 @@ and infer_exp'' env exp : T.typ =
         scopes = T.ConEnv.add c exp.at env.scopes } in
     let t = infer_exp env' exp1 in
     let t' = T.open_ [t1] (T.close [c] t)  in
-    if not (T.shared t') then
+    if s = T.Fut && not (T.shared t') then
       error_shared env t' exp1.at "M0033" "async type has non-shared content type%a"
         display_typ_expand t';
-    T.Async (t1, t')
-  | AwaitE exp1 ->
-    let t0 = check_AwaitCap env "await" exp.at in
+    T.Async (s, t1, t')
+  | AwaitE (s, exp1) ->
+    let t0 = check_AwaitCap env (if s = T.Fut then "await" else "await*") exp.at in
     let t1 = infer_exp_promote env exp1 in
     (try
-       let (t2, t3) = T.as_async_sub t0 t1 in
+       let (t2, t3) = T.as_async_sub s t0 t1 in
        if not (T.eq t0 t2) then begin
           local_error env exp1.at "M0087"
             "ill-scoped await: expected async type from current scope %s, found async type from other scope %s%s%s"
@@ -1298,7 +1303,8 @@ and infer_exp'' env exp : T.typ =
        t3
     with Invalid_argument _ ->
       error env exp1.at "M0088"
-        "expected async type, but expression has type%a"
+        "expected %s type, but expression has type%a"
+        (T.string_of_async_sort s)
         display_typ_expand t1
     )
   | AssertE exp1 ->
@@ -1414,7 +1420,7 @@ and check_exp' env0 t exp : T.typ =
         display_typ_expand (T.Array t');
     List.iter (check_exp env (T.as_immut t')) exps;
     t
-  | AsyncE (tb, exp1), T.Async (t1', t') ->
+  | AsyncE (s, tb, exp1), T.Async (s', t1', t') when s = s' ->
     error_in [Flags.WASIMode; Flags.WasmMode] env exp1.at "M0086"
       "async expressions are not supported";
     let t1, next_cap = check_AsyncCap env "async expression" exp.at in
@@ -2042,6 +2048,10 @@ and check_system_fields env sort scope dec_fields =
            { it = FuncE _; _ }) ->
       begin
         match List.assoc_opt id.it system_funcs with
+        | _ when id.it = "migrate" ->
+          if vis = System then
+            check_migrate env scope dec_fields df
+          else warn env id.at "M0128" "this function has the name of a system method, but is declared without system visibility and will not be called by the system"
         | Some t ->
           (* TBR why does Stable.md require this to be a manifest function, not just any expression of appropriate type?  *)
           if vis = System then
@@ -2056,7 +2066,7 @@ and check_system_fields env sort scope dec_fields =
         | None ->
           if vis = System then
             local_error env id.at "M0129" "unexpected system method named %s, expected %s"
-              id.it (String.concat " or " (List.map fst system_funcs))
+              id.it (String.concat " or " (List.map fst system_funcs @ ["migrate"]))
           else ()
       end
     | _, System, _ ->
@@ -2064,6 +2074,52 @@ and check_system_fields env sort scope dec_fields =
     | _ -> ())
   dec_fields
 
+(* `migrate` has no fixed type: it maps a record of the stable variables of the
+   previous version to a record of (some of) the stable variables of this
+   version. It is called before the actor fields are initialised, so it must
+   not refer to them. *)
+and check_migrate env scope dec_fields df =
+  match df.it.dec.it with
+  | LetD ({ it = VarP id; _ }, e) ->
+    let stable_vars = List.concat_map (fun df ->
+      match df.it.stab, df.it.dec.it with
+      | Some {it = Stable; _}, VarD (id, _) -> [id.it]
+      | Some {it = Stable; _}, LetD (pat, _) when stable_pat pat ->
+        T.Env.keys (gather_pat env T.Env.empty pat)
+      | _ -> []) dec_fields
+    in
+    let t = T.Env.find id.it scope.Scope.val_env in
+    begin match T.normalize t with
+    | T.Func (T.Local, T.Returns, [], [t1], [t2]) when is_stable_record t1 && is_stable_record t2 ->
+      let _, fs2 = T.as_obj (T.normalize t2) in
+      List.iter (fun {T.lab; T.typ = t2'; _} ->
+        if not (List.mem lab stable_vars) then
+          local_error env df.at "M0172"
+            "system function migrate returns field %s, but there is no stable variable %s" lab lab
+        else
+          let t_var = T.as_immut (T.Env.find lab scope.Scope.val_env) in
+          if not (T.sub t2' t_var) then
+            local_error env df.at "M0172"
+              "system function migrate returns field %s of type%a\nbut stable variable %s has type%a"
+              lab display_typ t2' lab display_typ t_var
+      ) fs2
+    | _ ->
+      local_error env df.at "M0127"
+        "system function migrate is declared with type%a\ninstead of expected type\n  {<old stable variables>} -> {<new stable variables>}\nwith records of stable types" display_typ t
+    end;
+    List.iter (fun x ->
+      if x <> id.it && T.Env.mem x scope.Scope.val_env then
+        local_error env df.at "M0173"
+          "system function migrate cannot refer to actor field %s, as it is called before the actor fields are initialised" x
+    ) (Definedness.free_vars e)
+  | _ -> assert false
+
+and is_stable_record t =
+  match T.normalize t with
+  | T.Obj (T.Object, fs) ->
+    List.for_all (fun f -> not (T.is_typ f.T.typ || T.is_mut f.T.typ)) fs && T.stable t
+  | _ -> false
+
 and stable_pat pat =
   match pat.it with
   | VarP _ -> true
@@ -2181,7 +2237,7 @@ and infer_dec env dec : T.typ =
       let t' = infer_obj env''' obj_sort.it dec_fields dec.at in
       match typ_opt, obj_sort.it with
       | None, _ -> ()
-      | Some { it = AsyncT (_, typ); at; _ }, T.Actor
+      | Some { it = AsyncT (T.Fut, _, typ); at; _ }, T.Actor
       | Some ({ at; _ } as typ), (T.Module | T.Object) ->
         if at = Source.no_region then
           warn env dec.at "M0135"
@@ -2273,7 +2329,7 @@ and gather_dec env scope dec : Scope.t =
   | LetD (
       {it = VarP id; _},
       ({it = ObjBlockE (obj_sort, dec_fields); at; _} |
-       {it = AwaitE { it = AsyncE (_, {it = ObjBlockE ({ it = Type.Actor; _} as obj_sort, dec_fields); at; _}) ; _  }; _ })
+       {it = AwaitE (T.Fut, { it = AsyncE (T.Fut, _, {it = ObjBlockE ({ it = Type.Actor; _} as obj_sort, dec_fields); at; _}) ; _  }); _ })
     ) ->
     let decs = List.map (fun df -> df.it.dec) dec_fields in
     let open Scope in
@@ -2355,7 +2411,7 @@ and infer_dec_typdecs env dec : Scope.t =
   | LetD (
       {it = VarP id; _},
       ( {it = ObjBlockE (obj_sort, dec_fields); at; _} |
-        {it = AwaitE { it = AsyncE (_, {it = ObjBlockE ({ it = Type.Actor; _} as obj_sort, dec_fields); at; _}) ; _  }; _ })
+        {it = AwaitE (T.Fut, { it = AsyncE (T.Fut, _, {it = ObjBlockE ({ it = Type.Actor; _} as obj_sort, dec_fields); at; _}) ; _  }); _ })
     ) ->
     let decs = List.map (fun {it = {vis; dec; _}; _} -> dec) dec_fields in
     let scope = T.Env.find id.it env.objs in
@@ -2457,7 +2513,7 @@ and infer_dec_valdecs env dec : Scope.t =
   | LetD (
       {it = VarP id; _} as pat,
       ( {it = ObjBlockE (obj_sort, dec_fields); at; _} |
-        {it = AwaitE { it = AsyncE (_, {it = ObjBlockE ({ it = Type.Actor; _} as obj_sort, dec_fields); at; _}) ; _  }; _ })
+        {it = AwaitE (T.Fut, { it = AsyncE (T.Fut, _, {it = ObjBlockE ({ it = Type.Actor; _} as obj_sort, dec_fields); at; _}) ; _  }); _ })
     ) ->
     let decs = List.map (fun df -> df.it.dec) dec_fields in
     let obj_scope = T.Env.find id.it env.objs in
@@ -2499,7 +2555,7 @@ and infer_dec_valdecs env dec : Scope.t =
     let ts1 = match pat.it with TupP _ -> T.seq_of_tup t1 | _ -> [t1] in
     let t2 =
       if obj_sort.it = T.Actor then
-        T.Async (T.Con (List.hd cs, []),
+        T.Async (T.Fut, T.Con (List.hd cs, []),
           T.Con (c, List.map (fun c -> T.Con (c, [])) (List.tl cs)))
       else
         T.Con (c, List.map (fun c -> T.Con (c, [])) cs)
@@ -2586,7 +2642,7 @@ let check_lib scope lib : Scope.t Diag.result =
                 | T.Func (sort, control, _, ts1, [t2]) ->
                   let t2 = T.normalize (T.open_ cs t2) in
                   (match t2 with
-                   | T.Async (_ , class_typ) -> class_typ
+                   | T.Async (_, _, class_typ) -> class_typ
                    | _ -> assert false)
                 | _ -> assert false
               in
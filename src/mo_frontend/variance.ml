@@ -57,7 +57,7 @@ let variances cons t =
         | Def (_, t) -> go p (open_ ts t)) (* TBR this may fail to terminate *)
       | Array t | Opt t -> go p t
       | Mut t -> go Invariant t
-      | Async (t1, t2) ->
+      | Async (_, t1, t2) ->
         go Invariant t1;
         go p t2
       | Tup ts -> List.iter (go p) ts
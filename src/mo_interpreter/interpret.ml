@@ -399,12 +399,14 @@ and interpret_exp_mut env exp (k : V.value V.cont) =
   | UnE (ot, op, exp1) ->
     interpret_exp env exp1
       (fun v1 ->
-        k (try Operator.unop op !ot v1 with Invalid_argument s -> trap exp.at "%s" s))
+        k (try Operator.unop op !ot v1 with Invalid_argument _ ->
+          trap exp.at "%s" (Operator.unop_overflow op !ot)))
   | BinE (ot, exp1, op, exp2) ->
     interpret_exp env exp1 (fun v1 ->
       interpret_exp env exp2 (fun v2 ->
-        k (try Operator.binop op !ot v1 v2 with _ ->
-          trap exp.at "arithmetic overflow")
+        k (try Operator.binop op !ot v1 v2 with
+          | Invalid_argument s when s = "negative power" -> trap exp.at "%s" s
+          | _ -> trap exp.at "%s" (Operator.binop_overflow op !ot))
       )
     )
   | ShowE (ot, exp1) ->
@@ -592,16 +594,23 @@ and interpret_exp_mut env exp (k : V.value V.cont) =
     interpret_exp env exp1 (Option.get env.rets)
   | ThrowE exp1 ->
     interpret_exp env exp1 (Option.get env.throws)
-  | AsyncE (_, exp1) ->
+  | AsyncE (T.Fut, _, exp1) ->
     async env
       exp.at
       (fun k' r ->
         let env' = {env with labs = V.Env.empty; rets = Some k'; throws = Some r}
         in interpret_exp env' exp1 k')
       k
-  | AwaitE exp1 ->
+  | AsyncE (T.Cmp, _, exp1) ->
+    k (V.Comp (fun k' r ->
+      let env' = {env with labs = V.Env.empty; rets = Some k'; throws = Some r}
+      in interpret_exp env' exp1 k'))
+  | AwaitE (T.Fut, exp1) ->
     interpret_exp env exp1
       (fun v1 -> await env exp.at (V.as_async v1) k)
+  | AwaitE (T.Cmp, exp1) ->
+    interpret_exp env exp1
+      (fun v1 -> (V.as_comp v1) k (Option.get env.throws))
   | AssertE exp1 ->
     interpret_exp env exp1 (fun v ->
       if V.as_bool v
@@ -19,6 +19,10 @@ let func_sort s = match s with
   | Shared Write -> "Shared"
   | Shared Query -> "Shared Query"
 
+let async_sort s = match s with
+  | Fut -> "Fut"
+  | Cmp -> "Cmp"
+
 let prim p = match p with
   | Null -> Atom "Null"
   | Bool -> Atom "Bool"
@@ -53,7 +57,7 @@ let rec typ (t:Type.typ) = match t with
   | Func (s, c, tbs, at, rt) ->
     "Func" $$ [Atom (func_sort s); Atom (control c)] @
       List.map typ_bind tbs @ [ "" $$ (List.map typ at); "" $$ (List.map typ rt)]
-  | Async (t1, t2)         -> "Async" $$ [typ t1; typ t2]
+  | Async (s, t1, t2)      -> "Async" $$ [Atom (async_sort s); typ t1; typ t2]
   | Mut t                  -> "Mut" $$ [typ t]
   | Any                    -> Atom "Any"
   | Non                    -> Atom "Non"
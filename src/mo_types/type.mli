@@ -9,6 +9,7 @@ type shared_sort = Query | Write
 type 'a shared = Local | Shared of 'a
 type func_sort = shared_sort shared
 type eff = Triv | Await
+type async_sort = Fut | Cmp
 
 type prim =
   | Null
@@ -42,7 +43,7 @@ and typ =
   | Opt of typ                                (* option *)
   | Tup of typ list                           (* tuple *)
   | Func of func_sort * control * bind list * typ list * typ list  (* function *)
-  | Async of scope * typ                        (* future *)
+  | Async of async_sort * scope * typ          (* future / computation *)
   | Mut of typ                                (* mutable type *)
   | Any                                       (* top *)
   | Non                                       (* bottom *)
@@ -119,7 +120,7 @@ val as_tup : typ -> typ list
 val as_unit : typ -> unit
 val as_pair : typ -> typ * typ
 val as_func : typ -> func_sort * control * bind list * typ list * typ list
-val as_async : typ -> typ * typ
+val as_async : typ -> async_sort * typ * typ
 val as_mut : typ -> typ
 val as_immut : typ -> typ
 val as_typ : typ -> con
@@ -135,7 +136,7 @@ val as_unit_sub : typ -> unit
 val as_pair_sub : typ -> typ * typ
 val as_func_sub : func_sort -> int -> typ -> func_sort * bind list * typ * typ
 val as_mono_func_sub : typ -> typ * typ
-val as_async_sub : typ -> typ -> typ * typ
+val as_async_sub : async_sort -> typ -> typ -> typ * typ
 
 
 (* Argument/result sequences *)
@@ -242,6 +243,7 @@ val string_of_stab_sig : field list -> string
 val string_of_prim : prim -> string
 val string_of_obj_sort : obj_sort -> string
 val string_of_func_sort : func_sort -> string
+val string_of_async_sort : async_sort -> string
 
 module type Pretty = sig
   val pp_typ : Format.formatter -> typ -> unit
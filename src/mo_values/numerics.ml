@@ -272,7 +272,9 @@ struct
   let max_int = big_int_of_int max_int
 
   let pow x y =
-    if gt y max_int
+    if lt y zero
+    then raise (Invalid_argument "negative power")
+    else if gt y max_int
     then raise (Invalid_argument "Int.pow")
     else power_big_int_positive_int x (int_of_big_int y)
 end
@@ -257,6 +257,26 @@ let relop op t =
   | _ -> raise (Invalid_argument "relop")
 
 
+(* Trap messages for checked arithmetic, naming the type and the operation *)
+
+let overflow_message p what = Printf.sprintf "%s %s" (T.string_of_prim p) what
+
+let unop_overflow op t =
+  match op, t with
+  | NegOp, T.Prim p -> overflow_message p "negation overflow"
+  | _ -> "arithmetic overflow"
+
+let binop_overflow op t =
+  match op, t with
+  | AddOp, T.Prim p -> overflow_message p "addition overflow"
+  | SubOp, T.Prim T.(Nat | Nat8 | Nat16 | Nat32 | Nat64 as p) ->
+    overflow_message p "subtraction underflow"
+  | SubOp, T.Prim p -> overflow_message p "subtraction overflow"
+  | MulOp, T.Prim p -> overflow_message p "multiplication overflow"
+  | PowOp, T.Prim p -> overflow_message p "exponentiation overflow"
+  | _ -> "arithmetic overflow"
+
+
 let has f op t = try ignore (f op t); true with Invalid_argument _ -> false
 let has_unop op t = has unop op t
 let has_binop op t = has binop op t
@@ -45,6 +45,9 @@ val has_unop : unop -> typ -> bool
 val has_binop : binop -> typ -> bool
 val has_relop : relop -> typ -> bool
 
+val unop_overflow : unop -> typ -> string  (* trap message, e.g. "Int8 negation overflow" *)
+val binop_overflow : binop -> typ -> string  (* trap message, e.g. "Nat8 addition overflow" *)
+
 val type_unop : unop -> typ -> typ
 val type_binop : binop -> typ -> typ
 val type_relop : relop -> typ -> typ
@@ -75,6 +75,250 @@ for suitable types t1 and t2
 let num_conv_wrap_prim t1 t2 =
   fun v -> of_big_int_wrap t2 (as_big_int t1 v)
 
+(* 32-bit FNV-1a, as `blob_hash` in the RTS *)
+let fnv1a_hash s =
+  let h = ref 0x811c9dc5 in
+  String.iter (fun c ->
+    h := ((!h lxor Char.code c) * 0x01000193) land 0xffffffff
+  ) s;
+  Nat32.wrapping_of_big_int (Big_int.big_int_of_int !h)
+
+(* Hex and base64 encoding, as `blob_encoding` in the RTS *)
+let hex_of_blob s =
+  String.concat "" (List.map (fun c -> Printf.sprintf "%02x" (Char.code c)) (List.of_seq (String.to_seq s)))
+
+let blob_of_hex t =
+  let digit = function
+    | '0'..'9' as c -> Char.code c - Char.code '0'
+    | 'a'..'f' as c -> Char.code c - Char.code 'a' + 10
+    | 'A'..'F' as c -> Char.code c - Char.code 'A' + 10
+    | _ -> raise Exit in
+  let n = String.length t in
+  if n mod 2 <> 0 then None else
+  try Some (String.init (n / 2) (fun i -> Char.chr (digit t.[2 * i] * 16 + digit t.[2 * i + 1])))
+  with Exit -> None
+
+let base64_chars url_safe =
+  "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789" ^
+  (if url_safe then "-_" else "+/")
+
+let base64_of_blob url_safe s =
+  let chars = base64_chars url_safe in
+  let n = String.length s in
+  let byte i = if i < n then Char.code s.[i] else 0 in
+  let b = Buffer.create ((n + 2) / 3 * 4) in
+  let rec go i =
+    if i < n then begin
+      let k = min 3 (n - i) in
+      let group = (byte i lsl 16) lor (byte (i + 1) lsl 8) lor byte (i + 2) in
+      for j = 0 to 3 do
+        if j <= k then Buffer.add_char b chars.[(group lsr (18 - 6 * j)) land 63]
+        else if not url_safe then Buffer.add_char b '='
+      done;
+      go (i + 3)
+    end in
+  go 0;
+  Buffer.contents b
+
+let blob_of_base64 url_safe t =
+  let chars = base64_chars url_safe in
+  let digit c = match String.index_opt chars c with Some d -> d | None -> raise Exit in
+  let n = String.length t in
+  let n =
+    if n > 0 && n mod 4 = 0 && t.[n - 1] = '=' then
+      if t.[n - 2] = '=' then n - 2 else n - 1
+    else n in
+  if n mod 4 = 1 then None else
+  let b = Buffer.create (n / 4 * 3 + 2) in
+  let rec go i =
+    if i < n then begin
+      let k = min 4 (n - i) in
+      let group = ref 0 in
+      for j = 0 to 3 do
+        group := (!group lsl 6) lor (if j < k then digit t.[i + j] else 0)
+      done;
+      (* the unused bits must be zero *)
+      if !group land ((1 lsl (8 * (4 - k))) - 1) <> 0 then raise Exit;
+      for j = 0 to k - 2 do
+        Buffer.add_char b (Char.chr ((!group lsr (16 - 8 * j)) land 0xff))
+      done;
+      go (i + 4)
+    end in
+  try go 0; Some (Buffer.contents b) with Exit -> None
+
+(* Parts of `t` between the non-overlapping occurrences of `d`, which is not empty *)
+let text_split t d =
+  let n = String.length d and len = String.length t in
+  let rec go start i parts =
+    if i + n > len then List.rev (String.sub t start (len - start) :: parts)
+    else if String.sub t i n = d then go (i + n) (i + n) (String.sub t start (i - start) :: parts)
+    else go start (i + 1) parts in
+  go 0 0 []
+
+(* Byte offset of the first occurrence of `p` in `t` *)
+let text_index_of t p =
+  let n = String.length p and len = String.length t in
+  let rec go i =
+    if i + n > len then None
+    else if String.sub t i n = p then Some i
+    else go (i + 1) in
+  go 0
+
+(* xoshiro256**, as `Rng` in the RTS *)
+let rng : int64 array option ref = ref None
+
+let rng_of_bytes s =
+  let w = Array.make 4 0L in
+  String.iteri (fun i c ->
+    if i < 32 then
+      w.(i / 8) <- Int64.(logor w.(i / 8) (shift_left (of_int (Char.code c)) (8 * (i mod 8))))
+  ) s;
+  if Array.for_all (fun x -> x = 0L) w then begin
+    (* splitmix64 expansion of 0 *)
+    let x = ref 0L in
+    Array.iteri (fun i _ ->
+      x := Int64.add !x 0x9e3779b97f4a7c15L;
+      let z = !x in
+      let z = Int64.(mul (logxor z (shift_right_logical z 30)) 0xbf58476d1ce4e5b9L) in
+      let z = Int64.(mul (logxor z (shift_right_logical z 27)) 0x94d049bb133111ebL) in
+      w.(i) <- Int64.(logxor z (shift_right_logical z 31))
+    ) w
+  end;
+  w
+
+let rng_bytes w =
+  String.init 32 (fun i ->
+    Char.chr Int64.(to_int (logand (shift_right_logical w.(i / 8) (8 * (i mod 8))) 0xffL)))
+
+let rotl x k = Int64.(logor (shift_left x k) (shift_right_logical x (64 - k)))
+
+let rng_next () =
+  match !rng with
+  | None -> raise (Invalid_argument "rng: not seeded")
+  | Some s ->
+    let result = Int64.mul (rotl (Int64.mul s.(1) 5L) 7) 9L in
+    let t = Int64.shift_left s.(1) 17 in
+    s.(2) <- Int64.logxor s.(2) s.(0);
+    s.(3) <- Int64.logxor s.(3) s.(1);
+    s.(1) <- Int64.logxor s.(1) s.(2);
+    s.(0) <- Int64.logxor s.(0) s.(3);
+    s.(2) <- Int64.logxor s.(2) t;
+    s.(3) <- rotl s.(3) 45;
+    result
+
+let rec rng_next_range n threshold =
+  let x = rng_next () in
+  if Int64.unsigned_compare x threshold >= 0
+  then Int64.unsigned_rem x n
+  else rng_next_range n threshold
+
+let nat64_of_int64 x = Nat64.wrapping_of_big_int (Big_int.big_int_of_int64 x)
+let int64_of_nat64 n =
+  let i = Nat64.to_big_int n in
+  let i = if Big_int.(ge_big_int i (power_int_positive_int 2 63))
+    then Big_int.(sub_big_int i (power_int_positive_int 2 64)) else i in
+  Big_int.int64_of_big_int i
+
+(* Parse a number in radix 2 to 36, with `_` between digits, like `text_to_nat` in the RTS *)
+let parse_number radix s =
+  let digit c = match c with
+    | '0'..'9' -> Char.code c - Char.code '0'
+    | 'a'..'z' -> Char.code c - Char.code 'a' + 10
+    | 'A'..'Z' -> Char.code c - Char.code 'A' + 10
+    | _ -> radix in
+  let rec go i n after_digit =
+    if i = String.length s then (if after_digit then Some n else None)
+    else if s.[i] = '_' then (if after_digit then go (i + 1) n false else None)
+    else
+      let d = digit s.[i] in
+      if d >= radix then None
+      else go (i + 1) Big_int.(add_int_big_int d (mult_int_big_int radix n)) true in
+  if radix < 2 || radix > 36 then None else go 0 Big_int.zero_big_int false
+
+let parse_int radix s =
+  let n = String.length s in
+  if n > 0 && s.[0] = '-' then Option.map Big_int.minus_big_int (parse_number radix (String.sub s 1 (n - 1)))
+  else if n > 0 && s.[0] = '+' then parse_number radix (String.sub s 1 (n - 1))
+  else parse_number radix s
+
+(* Bit operations on `Nat` and `Int` in two's complement, like `nat_and` etc. in the RTS.
+   `Big_int`'s bitwise operations only take non-negative numbers, so negative numbers are
+   complemented first, using `lnot i = -i - 1`. *)
+let lnot_big_int i = Big_int.(pred_big_int (minus_big_int i))
+let is_neg_big_int i = Big_int.sign_big_int i < 0
+
+let land_big_int a b =
+  let open Big_int in
+  match is_neg_big_int a, is_neg_big_int b with
+  | false, false -> and_big_int a b
+  | true, true -> lnot_big_int (or_big_int (lnot_big_int a) (lnot_big_int b))
+  | true, false -> sub_big_int b (and_big_int b (lnot_big_int a))
+  | false, true -> sub_big_int a (and_big_int a (lnot_big_int b))
+
+let lor_big_int a b =
+  if not (is_neg_big_int a) && not (is_neg_big_int b)
+  then Big_int.or_big_int a b
+  else lnot_big_int (land_big_int (lnot_big_int a) (lnot_big_int b))
+
+let lxor_big_int a b =
+  let open Big_int in
+  match is_neg_big_int a, is_neg_big_int b with
+  | false, false -> xor_big_int a b
+  | true, true -> xor_big_int (lnot_big_int a) (lnot_big_int b)
+  | true, false -> lnot_big_int (xor_big_int (lnot_big_int a) b)
+  | false, true -> lnot_big_int (xor_big_int a (lnot_big_int b))
+
+(* Number of one bits, or of zero bits in two's complement for negative numbers *)
+let popcount_big_int i =
+  let open Big_int in
+  let rec go n count =
+    if sign_big_int n = 0 then count
+    else go (shift_right_big_int n 1) (count + int_of_big_int (extract_big_int n 0 1)) in
+  go (if is_neg_big_int i then lnot_big_int i else i) 0
+
+(* Array buffers, see `ArrayBuffer_new`: the number of elements and the storage array, which
+   doubles when it is full *)
+let array_buffer v = match Value.as_tup v with
+  | [Mut len; Mut storage] -> len, storage
+  | _ -> assert false
+
+let array_buffer_add b x =
+  let len, storage = array_buffer b in
+  let n = Int.to_int (as_int !len) in
+  let elems = as_array !storage in
+  let elems =
+    if n < Array.length elems then elems else begin
+      let elems' = Array.make (max 8 (2 * n)) Null in
+      Array.blit elems 0 elems' 0 n;
+      storage := Array elems';
+      elems'
+    end in
+  elems.(n) <- x;
+  len := Int (Int.of_int (n + 1))
+
+(* Arrays frozen with `Array_freeze`. Interpreted arrays are not mutated by the RTS, so freezing
+   only needs to be observable with `Array_isFrozen`. A weak set keyed on physical identity, so
+   frozen arrays can still be collected. Arrays are hashed by their length, as their contents can
+   change. *)
+module FrozenArrays = Ephemeron.K1.Make (struct
+  type t = value array
+  let equal = (==)
+  let hash = Array.length
+end)
+
+let frozen_arrays : unit FrozenArrays.t = FrozenArrays.create 16
+
+(* Values being shown by the show translation, see `@show_cycle_enter` *)
+let show_path : value list ref = ref []
+
+(* Soft instruction budget, see `instructionBudget`. The interpreter doesn't count instructions,
+   so all of it remains. *)
+let instruction_budget = ref (Nat64.of_int 5_000_000_000)
+
+(* Controllers registered with `setControllers`, as principal blobs. The interpreter has no
+   `ic0.is_controller` and no message caller, so the caller is never a controller. *)
+let controllers : string list ref = ref []
+
 let prim =
   let via_float f v = Float.(Float (of_float (f (to_float (as_float v))))) in
   let via_float2 f v w = Float.(Float (of_float (f (to_float (as_float v)) (to_float (as_float w))))) in
@@ -108,6 +352,31 @@ let prim =
      | [a; b] -> k (Float (Float.copysign (as_float a) (as_float b)))
      | _ -> assert false)
   | "Float->Text" -> fun _ v k -> k (Text (Float.to_string (as_float v)))
+  | "nat_and" | "nat_or" | "nat_xor" as p -> fun _ v k ->
+    (match Value.as_tup v with
+     | [a; b] ->
+       let op = match p with
+         | "nat_and" -> land_big_int
+         | "nat_or" -> lor_big_int
+         | _ -> lxor_big_int in
+       k (Int (Int.of_big_int (op (Int.to_big_int (as_int a)) (Int.to_big_int (as_int b)))))
+     | _ -> assert false)
+  | "nat_shl" | "nat_shr" as p -> fun _ v k ->
+    (match Value.as_tup v with
+     | [a; amount] ->
+       let shift = if p = "nat_shl" then Big_int.shift_left_big_int else Big_int.shift_right_big_int in
+       k (Int (Int.of_big_int (shift (Int.to_big_int (as_int a)) (Nat32.to_int (as_nat32 amount)))))
+     | _ -> assert false)
+  | "nat_popcount" -> fun _ v k ->
+    k (Int (Int.of_int (popcount_big_int (Int.to_big_int (as_int v)))))
+  | "Text->Nat" | "Text->Int" as p -> fun _ v k ->
+    (match Value.as_tup v with
+     | [t; radix] ->
+       let parse = if p = "Text->Nat" then parse_number else parse_int in
+       (match parse (Nat32.to_int (as_nat32 radix)) (as_text t) with
+        | Some i -> k (Opt (Int (Int.of_big_int i)))
+        | None -> k Null)
+     | _ -> assert false)
   | "fmtFloat->Text" -> fun _ v k ->
     (match Value.as_tup v with
      | [f; prec; mode] ->
@@ -186,17 +455,103 @@ let prim =
   | "print" -> fun _ v k -> Printf.printf "%s\n%!" (as_text v); k unit
   | "trap" -> fun _ v k ->
     raise (Invalid_argument ("explicit trap: "^ (as_text v)))
+  | "trapWithBlob" -> fun _ v k ->
+    raise (Invalid_argument ("explicit trap: " ^ String.escaped (as_blob v)))
+  (* The interpreter does not roll back state on traps, so there is nothing to restore *)
+  | "onTrap" -> fun _ v k -> k unit
+  | "show_cycle_enter" -> fun _ v k ->
+    if List.exists (fun v' -> v' == v) !show_path then k (Bool false)
+    else (show_path := v :: !show_path; k (Bool true))
+  | "show_cycle_leave" -> fun _ v k ->
+    as_unit v;
+    show_path := List.tl !show_path;
+    k unit
   | "rts_version" -> fun _ v k -> as_unit v; k (Text "0.1")
+  | "rts_stable_layout_version" -> fun _ v k -> as_unit v; k (Nat32 (Nat32.of_int 1))
   | "rts_heap_size" -> fun _ v k -> as_unit v; k (Int (Int.of_int 0))
+  | "rts_max_heap_size" -> fun _ v k -> as_unit v; k (Int (Int.of_int 0))
+  | "rts_gc_estimate_reclaimable" -> fun _ v k -> as_unit v; k (Int (Int.of_int 0))
+  | "rts_heap_dump" -> fun _ v k -> ignore (as_nat64 v); k (Nat64 (Nat64.of_int 0))
+  | "rts_alloc_sites" -> fun _ v k -> as_unit v; k (Int (Int.of_int 0))
+  | "rts_alloc_site_count"
+  | "rts_alloc_site_bytes"
+  | "rts_size_histogram_bucket" -> fun _ v k -> ignore (as_nat32 v); k (Int (Int.of_int 0))
   | "rts_total_allocation" -> fun _ v k -> as_unit v; k (Int (Int.of_int 0))
   | "rts_outstanding_callbacks" -> fun _ v k -> as_unit v; k (Int (Int.of_int 0))
+  | "rts_live_history_len" -> fun _ v k -> as_unit v; k (Int (Int.of_int 0))
+  | "rts_live_history_message" -> fun _ v k -> ignore (as_nat32 v); k (Nat64 (Nat64.of_int 0))
+  | "rts_live_history_live" -> fun _ v k -> ignore (as_nat32 v); k (Int (Int.of_int 0))
+  | "rts_gc_pause_percentile" -> fun _ v k -> ignore (as_float v); k (Nat64 (Nat64.of_int 0))
+  | "rts_reset_gc_pauses" -> fun _ v k -> as_unit v; k unit
+  | "rts_reserve_heap" -> fun _ v k -> ignore (as_nat32 v); k unit
+  | "rts_gc_collect_now" -> fun _ v k -> as_unit v; k unit
+  | "rts_register_memory_pressure_handler" -> fun _ v k -> k unit
+  | "rts_set_memory_pressure_high_water_mark" -> fun _ v k -> ignore (as_nat32 v); k unit
+  | "principal_is_controller" -> fun _ v k -> k (Bool (List.mem (as_blob v) !controllers))
+  | "caller_is_controller" -> fun _ v k -> as_unit v; k (Bool false)
+  | "set_controllers" -> fun _ v k ->
+    controllers := List.map as_blob (Array.to_list (as_array v)); k unit
+  | "rts_self_test" -> fun _ v k -> as_unit v; k (Nat32 (Nat32.of_int 0))
   | "time" -> fun _ v k -> as_unit v; k (Value.Nat64 (Numerics.Nat64.of_int 42))
+  | "instructionCount" -> fun _ v k -> as_unit v; k (Value.Nat64 (Numerics.Nat64.of_int 0))
+  | "instructionBudget"
+  | "remainingInstructions" -> fun _ v k -> as_unit v; k (Value.Nat64 !instruction_budget)
+  | "setInstructionBudget" -> fun _ v k -> instruction_budget := as_nat64 v; k unit
+  | "setSafepointTrap" -> fun _ v k -> ignore (as_bool v); k unit
+  | "rts_safepoint_gc_slices" -> fun _ v k -> as_unit v; k (Int (Int.of_int 0))
+  | "global_timer_set" -> fun _ v k -> k (Value.Nat64 (Numerics.Nat64.of_int 0))
   | "idlHash" -> fun _ v k ->
     let s = as_text v in
     k (Nat32 (Nat32.wrapping_of_big_int (Big_int.big_int_of_int32 (Lib.Uint32.to_int32 (Idllib.IdlHash.idl_hash s)))))
   | "crc32Hash" -> fun _ v k -> let s = as_blob v in
     let i = Optint.(to_int32 (Checkseum.Crc32.digest_string s 0 (String.length s) zero)) in
     k (Nat32 (Nat32.wrapping_of_big_int (Big_int.big_int_of_int32 i)))
+  | "blobHash" -> fun _ v k -> k (Nat32 (fnv1a_hash (as_blob v)))
+  | "textHash" -> fun _ v k -> k (Nat32 (fnv1a_hash (as_text v)))
+  | "blobCompare" -> fun _ v k ->
+    (match Value.as_tup v with
+     | [a; b] -> k (Int8 (Int_8.of_int (String.compare (as_blob a) (as_blob b))))
+     | _ -> assert false)
+  | "natToNat64Checked" | "natToNat32Checked" as s -> fun _ v k ->
+    let i = Nat.to_big_int (as_int v) in
+    let bits = if s = "natToNat64Checked" then 64 else 32 in
+    if Big_int.lt_big_int i (Big_int.power_int_positive_int 2 bits)
+    then k (Opt (of_big_int_trap (if bits = 64 then Type.Nat64 else Type.Nat32) i))
+    else k Null
+  | "blob_to_hex" -> fun _ v k -> k (Text (hex_of_blob (as_blob v)))
+  | "hex_to_blob" -> fun _ v k ->
+    (match blob_of_hex (as_text v) with
+     | Some b -> k (Opt (Blob b))
+     | None -> k Null)
+  | "blob_to_base64" -> fun _ v k ->
+    (match Value.as_tup v with
+     | [b; u] -> k (Text (base64_of_blob (as_bool u) (as_blob b)))
+     | _ -> assert false)
+  | "base64_to_blob" -> fun _ v k ->
+    (match Value.as_tup v with
+     | [t; u] ->
+       (match blob_of_base64 (as_bool u) (as_text t) with
+        | Some b -> k (Opt (Blob b))
+        | None -> k Null)
+     | _ -> assert false)
+  | "rngSeed" -> fun _ v k -> rng := Some (rng_of_bytes (as_blob v)); k unit
+  | "rngNext" -> fun _ v k -> as_unit v; k (Nat64 (nat64_of_int64 (rng_next ())))
+  | "rngNextRange" -> fun _ v k ->
+    let n = int64_of_nat64 (as_nat64 v) in
+    if n = 0L then raise (Invalid_argument "rng_next_range: empty range");
+    let threshold = Int64.unsigned_rem (Int64.neg n) n in
+    k (Nat64 (nat64_of_int64 (rng_next_range n threshold)))
+  | "rngState" -> fun _ v k ->
+    as_unit v;
+    (match !rng with
+    | None -> raise (Invalid_argument "rng: not seeded")
+    | Some s -> k (Blob (rng_bytes s)))
+  | "rngSetState" -> fun _ v k ->
+    let s = as_blob v in
+    if String.length s <> 32 || s = String.make 32 '\000'
+    then raise (Invalid_argument "rng_set_state: invalid state");
+    rng := Some (rng_of_bytes s);
+    k unit
   | "array_len" -> fun _ v k ->
     k (Int (Int.of_int (Array.length (Value.as_array v))))
   | "blob_size" -> fun _ v k ->
@@ -220,6 +575,60 @@ let prim =
     let s = Wasm.Utf8.decode (Value.as_text v) in
     let i = Seq.map (fun c -> Char c) (List.to_seq s) in
     k (Iter (ref i))
+  | "text_to_lower" | "text_to_upper" as p -> fun _ v k ->
+    let map = if p = "text_to_lower" then Uucp.Case.Map.to_lower else Uucp.Case.Map.to_upper in
+    let map_char c =
+      match map (Uchar.of_int c) with
+      | `Self -> [c]
+      | `Uchars cs -> List.map Uchar.to_int cs in
+    let s = Wasm.Utf8.decode (Value.as_text v) in
+    k (Text (Wasm.Utf8.encode (List.concat_map map_char s)))
+  | "text_nfc" -> fun _ v k ->
+    k (Text (Uunf_string.normalize_utf_8 `NFC (Value.as_text v)))
+  | "text_nfd" -> fun _ v k ->
+    k (Text (Uunf_string.normalize_utf_8 `NFD (Value.as_text v)))
+  | "text_split" -> fun _ v k ->
+    (match Value.as_tup v with
+    | [t; d] ->
+      let t = Value.as_text t and d = Value.as_text d in
+      let parts =
+        if d = "" then List.map (fun c -> Wasm.Utf8.encode [c]) (Wasm.Utf8.decode t)
+        else text_split t d in
+      k (Array (Array.of_list (List.map (fun p -> Text p) parts)))
+    | _ -> assert false
+    )
+  | "text_contains" -> fun _ v k ->
+    (match Value.as_tup v with
+    | [t; p] -> k (Bool (text_index_of (Value.as_text t) (Value.as_text p) <> None))
+    | _ -> assert false
+    )
+  | "text_index_of" -> fun _ v k ->
+    (match Value.as_tup v with
+    | [t; p] ->
+      let t = Value.as_text t in
+      let i = match text_index_of t (Value.as_text p) with
+        | Some i -> List.length (Wasm.Utf8.decode (String.sub t 0 i))
+        | None -> -1 in
+      k (Int32 (Int_32.of_int i))
+    | _ -> assert false
+    )
+  | "text_replace" -> fun _ v k ->
+    (match Value.as_tup v with
+    | [t; p; r] ->
+      let t = Value.as_text t and p = Value.as_text p and r = Value.as_text r in
+      let parts =
+        if p = "" then "" :: List.map (fun c -> Wasm.Utf8.encode [c]) (Wasm.Utf8.decode t) @ [""]
+        else text_split t p in
+      k (Text (String.concat r parts))
+    | _ -> assert false
+    )
+  | "text_join" -> fun _ v k ->
+    (match Value.as_tup v with
+    | [parts; sep] ->
+      let parts = List.map Value.as_text (Array.to_list (Value.as_array parts)) in
+      k (Text (String.concat (Value.as_text sep) parts))
+    | _ -> assert false
+    )
   | "Array.init" -> fun _ v k ->
     (match Value.as_tup v with
     | [len; x] ->
@@ -238,6 +647,147 @@ let prim =
       in go (fun xs -> xs) k 0
     | _ -> assert false
     )
+  | "Array.sort" -> fun c v k ->
+    (match Value.as_tup v with
+    | [xs; cmp] ->
+      let xs = Value.as_array xs in
+      let (_, cmp') = Value.as_func cmp in
+      let rec merge ys zs k = match ys, zs with
+        | [], _ -> k zs
+        | _, [] -> k ys
+        | y::ys', z::zs' ->
+          cmp' c (Tup [z; y]) (fun o ->
+            if fst (Value.as_variant o) = "less"
+            then merge ys zs' (fun tl -> k (z::tl))
+            else merge ys' zs (fun tl -> k (y::tl)))
+      in
+      let rec sort ys k = match ys with
+        | [] | [_] -> k ys
+        | _ ->
+          let n = List.length ys / 2 in
+          let l = List.filteri (fun i _ -> i < n) ys in
+          let r = List.filteri (fun i _ -> i >= n) ys in
+          sort l (fun l -> sort r (fun r -> merge l r k))
+      in
+      sort (List.map (fun x -> !(Value.as_mut x)) (Array.to_list xs)) (fun ys ->
+        List.iteri (fun i y -> Value.as_mut xs.(i) := y) ys;
+        k unit)
+    | _ -> assert false
+    )
+  | "Array.map" -> fun c v k ->
+    (match Value.as_tup v with
+    | [xs; f] ->
+      let xs = Value.as_array xs in
+      let (_, f') = Value.as_func f in
+      let rec go prefix k i =
+        if i == Array.length xs
+        then k (Array (Array.of_list (prefix [])))
+        else f' c xs.(i) (fun y -> go (fun tl -> prefix (y::tl)) k (i + 1))
+      in go (fun ys -> ys) k 0
+    | _ -> assert false
+    )
+  | "Array.freeze" -> fun _ v k ->
+    let xs = as_array v in
+    FrozenArrays.replace frozen_arrays xs ();
+    k v
+  | "Array.isFrozen" -> fun _ v k ->
+    k (Bool (FrozenArrays.mem frozen_arrays (as_array v)))
+  | "ArrayBuffer.new" -> fun _ v k ->
+    as_unit v; k (Tup [Mut (ref (Int (Int.of_int 0))); Mut (ref (Array [||]))])
+  | "ArrayBuffer.add" -> fun _ v k ->
+    (match Value.as_tup v with
+    | [b; x] -> array_buffer_add b x; k unit
+    | _ -> assert false
+    )
+  | "ArrayBuffer.append" -> fun _ v k ->
+    (match Value.as_tup v with
+    | [b; xs] -> Array.iter (array_buffer_add b) (as_array xs); k unit
+    | _ -> assert false
+    )
+  | "ArrayBuffer.size" -> fun _ v k ->
+    let len, _ = array_buffer v in k !len
+  | "ArrayBuffer.get" -> fun _ v k ->
+    (match Value.as_tup v with
+    | [b; i] ->
+      let len, storage = array_buffer b in
+      if Int.ge (as_int i) (as_int !len)
+      then raise (Invalid_argument "Array buffer index out of bounds")
+      else k (as_array !storage).(Int.to_int (as_int i))
+    | _ -> assert false
+    )
+  | "ArrayBuffer.toArray" -> fun _ v k ->
+    let len, storage = array_buffer v in
+    k (Array (Array.sub (as_array !storage) 0 (Int.to_int (as_int !len))))
+  | "BlobBuilder.new" -> fun _ v k ->
+    as_unit v; k (Mut (ref (Blob "")))
+  | "BlobBuilder.pushByte" -> fun _ v k ->
+    (match Value.as_tup v with
+    | [b; x] ->
+      let bytes = as_mut b in
+      bytes := Blob (as_blob !bytes ^ String.make 1 (Char.chr (Nat8.to_int (as_nat8 x))));
+      k unit
+    | _ -> assert false
+    )
+  | "BlobBuilder.pushBytes" -> fun _ v k ->
+    (match Value.as_tup v with
+    | [b; x] ->
+      let bytes = as_mut b in
+      bytes := Blob (as_blob !bytes ^ as_blob x);
+      k unit
+    | _ -> assert false
+    )
+  | "BlobBuilder.size" -> fun _ v k ->
+    k (Int (Int.of_int (String.length (as_blob !(as_mut v)))))
+  | "BlobBuilder.finish" -> fun _ v k ->
+    k !(as_mut v)
+  | "Array.filter" -> fun c v k ->
+    (match Value.as_tup v with
+    | [xs; pred] ->
+      let xs = Value.as_array xs in
+      let (_, pred') = Value.as_func pred in
+      let rec go prefix k i =
+        if i == Array.length xs
+        then k (Array (Array.of_list (prefix [])))
+        else pred' c xs.(i) (fun b ->
+          if Value.as_bool b
+          then go (fun tl -> prefix (xs.(i)::tl)) k (i + 1)
+          else go prefix k (i + 1))
+      in go (fun ys -> ys) k 0
+    | _ -> assert false
+    )
+  | "Array.binarySearch" -> fun c v k ->
+    (match Value.as_tup v with
+    | [xs; key; cmp] ->
+      let xs = Value.as_array xs in
+      let (_, cmp') = Value.as_func cmp in
+      let rec go lo hi =
+        if lo >= hi then k (Int (Int.of_int (-1 - lo))) else
+        let mid = lo + (hi - lo) / 2 in
+        cmp' c (Tup [xs.(mid); key]) (fun o ->
+          match fst (Value.as_variant o) with
+          | "less" -> go (mid + 1) hi
+          | "greater" -> go lo mid
+          | _ -> k (Int (Int.of_int mid)))
+      in go 0 (Array.length xs)
+    | _ -> assert false
+    )
+  | "Array.dedup" -> fun c v k ->
+    (match Value.as_tup v with
+    | [xs; eq] ->
+      let xs = Value.as_array xs in
+      let (_, eq') = Value.as_func eq in
+      let rec go prefix k i =
+        if i >= Array.length xs
+        then k (Array (Array.of_list (prefix [])))
+        else eq' c (Tup [xs.(i - 1); xs.(i)]) (fun b ->
+          if Value.as_bool b
+          then go prefix k (i + 1)
+          else go (fun tl -> prefix (xs.(i)::tl)) k (i + 1))
+      in
+      if Array.length xs = 0 then k (Array [||])
+      else go (fun tl -> xs.(0)::tl) k 1
+    | _ -> assert false
+    )
   | "blobToArray" -> fun _ v k ->
     k (Array (Array.of_seq (Seq.map (fun c ->
       Nat8 (Nat8.of_int (Char.code c))
@@ -35,6 +35,19 @@ let needs_parens s = s.[0] = '+' || s.[0] = '-' || s.[0] = '?' || s.[0] = '#'
 let parens s = if needs_parens s then "(" ^ s ^ ")" else s
 let sign b s = (if b then "+" else "") ^ s
 
+(* Mutable arrays and objects with mutable fields can be part of a cycle. These
+   are shown with `show_cyclic`, which shows "#<cycle>" instead when the value
+   is already being shown. *)
+let showing : Value.value list ref = ref []
+
+let show_cyclic v f =
+  if List.exists (fun v' -> v' == v) !showing then "#<cycle>" else begin
+    showing := v :: !showing;
+    let s = f () in
+    showing := List.tl !showing;
+    s
+  end
+
 let rec show_val t v =
   let t = T.normalize t in
   match t, v with
@@ -62,18 +75,23 @@ let rec show_val t v =
       (String.concat ", " (List.map2 show_val ts' vs))
       (if List.length vs = 1 then "," else "")
   | T.Array (T.Mut t'), Value.Array a ->
-    if a = [||] then "[var]" else
-    Printf.sprintf "[var %s]"
-      (String.concat ", " (List.map (fun v -> show_val t' !(Value.as_mut v)) (Array.to_list a)))
+    show_cyclic v (fun () ->
+      if a = [||] then "[var]" else
+      Printf.sprintf "[var %s]"
+        (String.concat ", " (List.map (fun v -> show_val t' !(Value.as_mut v)) (Array.to_list a))))
   | T.Array t', Value.Array a ->
     Printf.sprintf "[%s]"
       (String.concat ", " (List.map (show_val t') (Array.to_list a)))
   | T.Obj (_, fts), Value.Obj fs ->
-    Printf.sprintf "{%s}"
-      (String.concat "; "
-         (List.filter_map (fun ft ->
-            if T.is_typ ft.T.typ then None else
-            Some (show_field fs ft)) fts))
+    let show_obj () =
+      Printf.sprintf "{%s}"
+        (String.concat "; "
+           (List.filter_map (fun ft ->
+              if T.is_typ ft.T.typ then None else
+              Some (show_field fs ft)) fts)) in
+    if List.exists (fun ft -> T.is_mut ft.T.typ) fts
+    then show_cyclic v show_obj
+    else show_obj ()
   | T.Variant fs, Value.Variant (l, v) ->
     begin match List.find_opt (fun {T.lab = l'; _} -> l = l') fs with
     | Some {T.typ = T.Tup []; _} -> Printf.sprintf "#%s" l
@@ -51,12 +51,15 @@ and value =
   | Obj of value Env.t
   | Func of Call_conv.t * func
   | Async of async
+  | Comp of comp
   | Mut of value ref
   | Iter of value Seq.t ref (* internal to {b.vals(), t.chars()} iterator *)
 
 and res = Ok of value | Error of value
 and async = {result : res Lib.Promise.t ; mutable waiters : (value cont * value cont) list}
 
+and comp = value cont -> value cont -> unit
+
 and def = value Lib.Promise.t
 and 'a cont = 'a -> unit
 
@@ -100,6 +103,7 @@ let as_pair = function Tup [v1; v2] -> v1, v2 | _ -> invalid "as_pair"
 let as_obj = function Obj ve -> ve | _ -> invalid "as_obj"
 let as_func = function Func (cc, f) -> cc, f | _ -> invalid "as_func"
 let as_async = function Async a -> a | _ -> invalid "as_async"
+let as_comp = function Comp c -> c | _ -> invalid "as_comp"
 let as_mut = function Mut r -> r | _ -> invalid "as_mut"
 
 
@@ -130,6 +134,7 @@ let rec compare x1 x2 =
     )
   | Mut r1, Mut r2 -> compare !r1 !r2
   | Async _, Async _ -> raise (Invalid_argument "Value.compare")
+  | Comp _, Comp _ -> raise (Invalid_argument "Value.compare")
   | _ -> generic_compare x1 x2
 
 let equal x1 x2 = compare x1 x2 = 0
@@ -202,6 +207,7 @@ let rec pp_val_nullary d ppf = function
     fprintf ppf "@[<1>[%a]@]"
       (pp_print_list ~pp_sep:comma (pp_val d)) (Array.to_list a)
   | Func (_, _) -> pr ppf "func"
+  | Comp _ -> pr ppf "async*"
   | v ->
     (* "(" ^ string_of_val d v ^ ")" *)
     fprintf ppf "@[<1>(%a)@]" (pp_val d) v
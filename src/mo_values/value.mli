@@ -43,12 +43,15 @@ and value =
   | Obj of value Env.t
   | Func of Call_conv.t * func
   | Async of async
+  | Comp of comp
   | Mut of value ref
   | Iter of value Seq.t ref (* internal to {b.vals(), t.chars()} iterator *)
 
 and res = Ok of value | Error of value
 and async = {result : res Lib.Promise.t ; mutable waiters : (value cont * value cont) list}
 
+and comp = value cont -> value cont -> unit
+
 and def = value Lib.Promise.t
 and 'a cont = 'a -> unit
 
@@ -96,6 +99,7 @@ val as_obj : value -> value Env.t
 val as_variant : value -> string * value
 val as_func : value -> Call_conv.t * func
 val as_async : value -> async
+val as_comp : value -> comp
 val as_mut : value -> value ref
 
 